@@ -13,10 +13,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Note: This example requires valid model files
     // Set EIDOS_MODEL_PATH and EIDOS_TOKENIZER_PATH environment variables
 
-    let model_path = std::env::var("EIDOS_MODEL_PATH")
-        .unwrap_or_else(|_| "model.onnx".to_string());
-    let tokenizer_path = std::env::var("EIDOS_TOKENIZER_PATH")
-        .unwrap_or_else(|_| "tokenizer.json".to_string());
+    let model_path = std::env::var("EIDOS_MODEL_PATH").unwrap_or_else(|_| "model.onnx".to_string());
+    let tokenizer_path =
+        std::env::var("EIDOS_TOKENIZER_PATH").unwrap_or_else(|_| "tokenizer.json".to_string());
 
     println!("Loading model from: {}", model_path);
     println!("Loading tokenizer from: {}", tokenizer_path);
@@ -1,37 +1,503 @@
+pub mod pipeline;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+
+pub use lib_errors::{Error, ErrorCode};
+pub use pipeline::{Answer, Pipeline};
+#[cfg(feature = "plugins")]
+pub use plugin::Plugin;
+
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Request {
     Chat,
     Core,
     Translate,
 }
 
-/// Handler function that takes input text and returns a Result
-pub type Handler = Box<dyn Fn(&str) -> Result<(), String>>;
+impl Request {
+    /// Lowercase name used in config files and on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Request::Chat => "chat",
+            Request::Core => "core",
+            Request::Translate => "translate",
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "chat" => Ok(Request::Chat),
+            "core" => Ok(Request::Core),
+            "translate" => Ok(Request::Translate),
+            other => Err(format!(
+                "Unknown request type '{}' (expected chat, core or translate)",
+                other
+            )),
+        }
+    }
+}
+
+/// Payload option carrying the request's time budget in milliseconds
+pub const TIMEOUT_OPTION: &str = "timeout_ms";
+
+/// Input handed to a handler: the text plus free-form options
+///
+/// Options carry per-request settings that only some handlers understand
+/// (e.g. `explain` for command generation); unknown keys are ignored.
+/// A time budget set with [`Payload::with_timeout`] travels the same way, so it
+/// survives the daemon socket; handlers turn it into HTTP timeouts and stop
+/// optional inference steps once it has run out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Payload {
+    pub input: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub options: HashMap<String, String>,
+}
+
+impl Payload {
+    pub fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+            options: HashMap::new(),
+        }
+    }
+
+    /// Add an option
+    pub fn with_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.insert(key.into(), value.into());
+        self
+    }
+
+    /// Get an option value
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
+
+    /// Whether a boolean option is set to `true`
+    pub fn flag(&self, key: &str) -> bool {
+        self.option(key) == Some("true")
+    }
+
+    /// Limit how long handling this request may take
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        let millis = timeout.as_millis().to_string();
+        self.with_option(TIMEOUT_OPTION, millis)
+    }
+
+    /// The time budget set by the caller, if any
+    pub fn timeout(&self) -> Option<Duration> {
+        self.option(TIMEOUT_OPTION)?
+            .parse()
+            .ok()
+            .map(Duration::from_millis)
+    }
+
+    /// Deadline for this request, counting the time budget from now
+    ///
+    /// Handlers call this once when they start and pass the result down to
+    /// HTTP clients and model backends.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.timeout().map(|timeout| Instant::now() + timeout)
+    }
+}
+
+impl From<&str> for Payload {
+    fn from(input: &str) -> Self {
+        Self::new(input)
+    }
+}
+
+impl From<String> for Payload {
+    fn from(input: String) -> Self {
+        Self::new(input)
+    }
+}
+
+/// Typed result of a handler
+///
+/// Handlers describe what they produced; the caller (CLI, daemon, tests)
+/// decides how to render it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Response {
+    /// A shell command generated from a natural language prompt
+    CommandGenerated {
+        prompt: String,
+        command: String,
+        explanation: Option<String>,
+    },
+    /// A reply from the chat provider
+    ChatReply {
+        reply: String,
+        /// Notes the reply was grounded in, if any
+        sources: Vec<String>,
+    },
+    /// Result of language detection and translation
+    Translation {
+        source_lang: String,
+        target_lang: String,
+        original: String,
+        translated: String,
+        was_translated: bool,
+        /// Confidence in the detected source language (0.0 to 1.0); `None` when
+        /// the source language was given
+        #[cfg_attr(feature = "serde", serde(default))]
+        confidence: Option<f64>,
+    },
+    /// Free-form text for handlers without a dedicated variant
+    Text(String),
+}
+
+/// Handler function that takes a payload and returns a typed response
+pub type Handler = Box<dyn Fn(&Payload) -> Result<Response, Error>>;
+
+/// Boxed future returned by async handlers
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+/// Async handler: takes ownership of the payload so the future can be `'static`
+pub type AsyncHandler = Box<dyn Fn(Payload) -> HandlerFuture + Send + Sync>;
+
+/// Future returned by middleware; it may borrow the bridge through [`Next`]
+pub type MiddlewareFuture<'a> = Pin<Box<dyn Future<Output = Result<Response, Error>> + 'a>>;
+
+/// Middleware: sees every routed request and decides how to pass it on
+pub type Middleware = Box<dyn for<'a> Fn(Request, Payload, Next<'a>) -> MiddlewareFuture<'a>>;
+
+/// The rest of the chain behind a middleware: later middleware, then the handler
+pub struct Next<'a> {
+    bridge: &'a Bridge,
+    request: Request,
+    index: usize,
+    blocking: bool,
+}
+
+impl<'a> Next<'a> {
+    /// Hand `payload` to the rest of the chain
+    pub fn run(self, payload: Payload) -> MiddlewareFuture<'a> {
+        let Next {
+            bridge,
+            request,
+            index,
+            blocking,
+        } = self;
+        match bridge.middleware.get(index) {
+            Some(middleware) => {
+                let next = Next {
+                    bridge,
+                    request,
+                    index: index + 1,
+                    blocking,
+                };
+                middleware(request, payload, next)
+            }
+            None if blocking => {
+                let result = bridge.dispatch(request, &payload);
+                Box::pin(async move { result })
+            }
+            None => Box::pin(bridge.dispatch_async(request, payload)),
+        }
+    }
+}
 
 pub struct Bridge {
     router: HashMap<Request, Handler>,
+    async_router: HashMap<Request, AsyncHandler>,
+    middleware: Vec<Middleware>,
+    #[cfg(feature = "plugins")]
+    plugins: HashMap<String, Plugin>,
+    default: Option<Request>,
 }
 
 impl Bridge {
     pub fn new() -> Self {
         Self {
             router: HashMap::new(),
+            async_router: HashMap::new(),
+            middleware: Vec::new(),
+            #[cfg(feature = "plugins")]
+            plugins: HashMap::new(),
+            default: None,
         }
     }
 
+    /// Set the request type used when the caller does not name one explicitly
+    pub fn set_default(&mut self, request: Request) {
+        self.default = Some(request);
+    }
+
+    /// Get the configured default request type, if any
+    pub fn default_request(&self) -> Option<Request> {
+        self.default
+    }
+
     /// Register a handler for a specific request type
     pub fn register(&mut self, request: Request, handler: Handler) {
         self.router.insert(request, handler);
     }
 
+    /// Register an async handler for a specific request type
+    ///
+    /// Async handlers are only reached through [`Bridge::route_async`]; the bridge
+    /// does not pick an executor, the caller's runtime drives the returned future.
+    ///
+    /// # Example
+    /// ```
+    /// use lib_bridge::{Bridge, Payload, Request, Response};
+    ///
+    /// let mut bridge = Bridge::new();
+    /// bridge.register_async(Request::Chat, |payload: Payload| async move {
+    ///     Ok(Response::Text(payload.input.to_uppercase()))
+    /// });
+    /// assert!(bridge.is_registered(Request::Chat));
+    /// ```
+    pub fn register_async<F, Fut>(&mut self, request: Request, handler: F)
+    where
+        F: Fn(Payload) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+    {
+        self.async_router
+            .insert(request, Box::new(move |payload| Box::pin(handler(payload))));
+    }
+
+    /// Wrap every routed request in `middleware`
+    ///
+    /// Middleware runs in registration order, the first added outermost, for both
+    /// [`Bridge::route`] and [`Bridge::route_async`]. It may rewrite the payload,
+    /// answer without calling `next`, or inspect the handler's result.
+    ///
+    /// # Example
+    /// ```
+    /// use lib_bridge::{Bridge, Payload, Request, Response};
+    ///
+    /// let mut bridge = Bridge::new();
+    /// bridge.register(
+    ///     Request::Chat,
+    ///     Box::new(|payload: &Payload| Ok(Response::Text(payload.input.clone()))),
+    /// );
+    /// bridge.use_middleware(|_request, payload, next| {
+    ///     Box::pin(async move {
+    ///         let started = std::time::Instant::now();
+    ///         let result = next.run(payload).await;
+    ///         eprintln!("handled in {:?}", started.elapsed());
+    ///         result
+    ///     })
+    /// });
+    /// assert_eq!(bridge.route(Request::Chat, "hi"), Ok(Response::Text("hi".to_string())));
+    /// ```
+    pub fn use_middleware<F>(&mut self, middleware: F)
+    where
+        F: for<'a> Fn(Request, Payload, Next<'a>) -> MiddlewareFuture<'a> + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Run `hook` on every payload before it reaches the handler
+    ///
+    /// The hook may rewrite the payload; an error rejects the request without
+    /// calling the handler.
+    pub fn use_before<F>(&mut self, hook: F)
+    where
+        F: Fn(Request, &mut Payload) -> Result<(), Error> + 'static,
+    {
+        self.use_middleware(
+            move |request, mut payload, next| match hook(request, &mut payload) {
+                Ok(()) => next.run(payload),
+                Err(e) => Box::pin(async move { Err(e) }),
+            },
+        );
+    }
+
+    /// Run `hook` on every handler result before it is returned
+    pub fn use_after<F>(&mut self, hook: F)
+    where
+        F: Fn(Request, Result<Response, Error>) -> Result<Response, Error> + 'static,
+    {
+        let hook = Rc::new(hook);
+        self.use_middleware(move |request, payload, next| {
+            let hook = Rc::clone(&hook);
+            Box::pin(async move { hook(request, next.run(payload).await) })
+        });
+    }
+
+    /// Number of registered middleware
+    pub fn middleware_count(&self) -> usize {
+        self.middleware.len()
+    }
+
+    /// Whether a sync or async handler is registered for `request`
+    pub fn is_registered(&self, request: Request) -> bool {
+        self.router.contains_key(&request) || self.async_router.contains_key(&request)
+    }
+
+    /// Number of request types with a registered handler
+    pub fn handler_count(&self) -> usize {
+        let async_only = self
+            .async_router
+            .keys()
+            .filter(|request| !self.router.contains_key(request))
+            .count();
+        self.router.len() + async_only
+    }
+
     /// Route a request to its registered handler with input
-    pub fn route(&self, request: Request, input: &str) -> Result<(), String> {
+    ///
+    /// Accepts plain text (`&str`, `String`) or a [`Payload`] with options.
+    pub fn route(&self, request: Request, payload: impl Into<Payload>) -> Result<Response, Error> {
+        if self.middleware.is_empty() {
+            return self.dispatch(request, &payload.into());
+        }
+        block_on(self.chain(request, true).run(payload.into()))
+    }
+
+    /// Route a request to its handler from async code
+    ///
+    /// Prefers an async handler and falls back to a sync one, which runs inline.
+    pub async fn route_async(
+        &self,
+        request: Request,
+        payload: impl Into<Payload>,
+    ) -> Result<Response, Error> {
+        if self.middleware.is_empty() {
+            return self.dispatch_async(request, payload.into()).await;
+        }
+        self.chain(request, false).run(payload.into()).await
+    }
+
+    /// Start of the middleware chain for `request`
+    fn chain(&self, request: Request, blocking: bool) -> Next<'_> {
+        Next {
+            bridge: self,
+            request,
+            index: 0,
+            blocking,
+        }
+    }
+
+    /// Call the sync handler for `request`, bypassing middleware
+    fn dispatch(&self, request: Request, payload: &Payload) -> Result<Response, Error> {
         if let Some(handler) = self.router.get(&request) {
-            handler(input)
+            handler(payload)
+        } else if self.async_router.contains_key(&request) {
+            Err(Error::new(
+                ErrorCode::Internal,
+                format!(
+                    "Handler for request {:?} is async; use route_async",
+                    request
+                ),
+            ))
+        } else {
+            Err(Error::new(
+                ErrorCode::Unsupported,
+                format!("No handler registered for request: {:?}", request),
+            ))
+        }
+    }
+
+    /// Call the handler for `request` from async code, bypassing middleware
+    async fn dispatch_async(&self, request: Request, payload: Payload) -> Result<Response, Error> {
+        if let Some(handler) = self.async_router.get(&request) {
+            handler(payload).await
+        } else if let Some(handler) = self.router.get(&request) {
+            handler(&payload)
         } else {
-            Err(format!("No handler registered for request: {:?}", request))
+            Err(Error::new(
+                ErrorCode::Unsupported,
+                format!("No handler registered for request: {:?}", request),
+            ))
+        }
+    }
+
+    /// Route input to the handler of the default request type
+    pub fn route_default(&self, payload: impl Into<Payload>) -> Result<Response, Error> {
+        match self.default {
+            Some(request) => self.route(request, payload),
+            None => Err(Error::new(
+                ErrorCode::NotConfigured,
+                "No default request configured",
+            )),
+        }
+    }
+
+    /// Register an external plugin under its verb
+    ///
+    /// Fails when the verb names a built-in request type, which always takes
+    /// precedence; a later plugin with the same verb replaces an earlier one.
+    #[cfg(feature = "plugins")]
+    pub fn register_plugin(&mut self, plugin: Plugin) -> Result<(), String> {
+        if plugin.name().parse::<Request>().is_ok() {
+            return Err(format!(
+                "Plugin '{}' shadows a built-in request type",
+                plugin.name()
+            ));
+        }
+        self.plugins.insert(plugin.name().to_string(), plugin);
+        Ok(())
+    }
+
+    /// The plugin registered for `verb`, if any
+    #[cfg(feature = "plugins")]
+    pub fn plugin(&self, verb: &str) -> Option<&Plugin> {
+        self.plugins.get(verb)
+    }
+
+    /// Verbs with a registered plugin, sorted
+    #[cfg(feature = "plugins")]
+    pub fn plugin_verbs(&self) -> Vec<&str> {
+        let mut verbs: Vec<&str> = self.plugins.keys().map(String::as_str).collect();
+        verbs.sort_unstable();
+        verbs
+    }
+
+    /// Route input to the plugin registered for `verb`
+    ///
+    /// Runs the plugin to completion on the calling thread.
+    #[cfg(feature = "plugins")]
+    pub fn route_plugin(&self, verb: &str, payload: impl Into<Payload>) -> Result<Response, Error> {
+        match self.plugins.get(verb) {
+            Some(plugin) => plugin.call(&payload.into()),
+            None => Err(Error::new(
+                ErrorCode::Unsupported,
+                format!("No plugin registered for '{}'", verb),
+            )),
+        }
+    }
+
+    /// Async counterpart of [`Bridge::route_default`]
+    pub async fn route_default_async(
+        &self,
+        payload: impl Into<Payload>,
+    ) -> Result<Response, Error> {
+        match self.default {
+            Some(request) => self.route_async(request, payload).await,
+            None => Err(Error::new(
+                ErrorCode::NotConfigured,
+                "No default request configured",
+            )),
         }
     }
 }
@@ -42,10 +508,42 @@ impl Default for Bridge {
     }
 }
 
+/// Wakes the thread blocked in [`block_on`]
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drive the middleware chain of a sync [`Bridge::route`] to completion
+///
+/// The handler at the end of the chain is sync, so the future only waits on
+/// whatever the middleware itself awaits.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
     #[test]
     fn test_bridge_new() {
         let bridge = Bridge::new();
@@ -58,11 +556,27 @@ mod tests {
         assert_eq!(bridge.router.len(), 0);
     }
 
+    #[test]
+    fn test_is_registered() {
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Translate,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
+
+        assert!(bridge.is_registered(Request::Translate));
+        assert!(!bridge.is_registered(Request::Core));
+        assert_eq!(bridge.handler_count(), 1);
+    }
+
     #[test]
     fn test_register_handler() {
         let mut bridge = Bridge::new();
 
-        bridge.register(Request::Chat, Box::new(|_text: &str| Ok(())));
+        bridge.register(
+            Request::Chat,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
 
         assert_eq!(bridge.router.len(), 1);
     }
@@ -74,18 +588,18 @@ mod tests {
         // Create a handler that captures input
         bridge.register(
             Request::Chat,
-            Box::new(|text: &str| {
-                if text == "test" {
-                    Ok(())
+            Box::new(|payload: &Payload| {
+                if payload.input == "test" {
+                    Ok(Response::Text(payload.input.clone()))
                 } else {
-                    Err("Unexpected input".to_string())
+                    Err("Unexpected input".into())
                 }
             }),
         );
 
         // Test successful routing
         let result = bridge.route(Request::Chat, "test");
-        assert!(result.is_ok());
+        assert_eq!(result, Ok(Response::Text("test".to_string())));
     }
 
     #[test]
@@ -94,32 +608,41 @@ mod tests {
 
         bridge.register(
             Request::Chat,
-            Box::new(|_text: &str| Err("Handler error".to_string())),
+            Box::new(|_: &Payload| Err("Handler error".into())),
         );
 
         let result = bridge.route(Request::Chat, "test");
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Handler error");
+        assert_eq!(result.unwrap_err().message(), "Handler error");
     }
 
     #[test]
     fn test_route_no_handler() {
         let bridge = Bridge::new();
 
-        let result = bridge.route(Request::Chat, "test");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("No handler registered"));
+        let err = bridge.route(Request::Chat, "test").unwrap_err();
+        assert!(err.message().contains("No handler registered"));
+        assert_eq!(err.code(), ErrorCode::Unsupported);
     }
 
     #[test]
     fn test_multiple_handlers() {
         let mut bridge = Bridge::new();
 
-        bridge.register(Request::Chat, Box::new(|_: &str| Ok(())));
+        bridge.register(
+            Request::Chat,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
 
-        bridge.register(Request::Core, Box::new(|_: &str| Ok(())));
+        bridge.register(
+            Request::Core,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
 
-        bridge.register(Request::Translate, Box::new(|_: &str| Ok(())));
+        bridge.register(
+            Request::Translate,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
 
         assert_eq!(bridge.router.len(), 3);
 
@@ -135,10 +658,10 @@ mod tests {
 
         bridge.register(
             Request::Chat,
-            Box::new(|text: &str| {
+            Box::new(|payload: &Payload| {
                 // Verify the handler receives the correct input
-                assert_eq!(text, "hello world");
-                Ok(())
+                assert_eq!(payload.input, "hello world");
+                Ok(Response::Text(String::new()))
             }),
         );
 
@@ -158,6 +681,47 @@ mod tests {
         assert_ne!(core, translate);
     }
 
+    #[test]
+    fn test_payload_timeout() {
+        let payload = Payload::new("hi");
+        assert_eq!(payload.timeout(), None);
+        assert_eq!(payload.deadline(), None);
+
+        let payload = payload.with_timeout(Duration::from_millis(1500));
+        assert_eq!(payload.option(TIMEOUT_OPTION), Some("1500"));
+        assert_eq!(payload.timeout(), Some(Duration::from_millis(1500)));
+        assert!(payload.deadline().unwrap() > Instant::now());
+
+        // Garbage from the wire is ignored rather than failing the request
+        let payload = Payload::new("hi").with_option(TIMEOUT_OPTION, "soon");
+        assert_eq!(payload.timeout(), None);
+    }
+
+    #[test]
+    fn test_request_from_str() {
+        assert_eq!("core".parse::<Request>().unwrap(), Request::Core);
+        assert_eq!(" Chat ".parse::<Request>().unwrap(), Request::Chat);
+        assert_eq!("translate".parse::<Request>().unwrap(), Request::Translate);
+        assert!("unknown".parse::<Request>().is_err());
+        assert_eq!(Request::Core.to_string(), "core");
+    }
+
+    #[test]
+    fn test_route_default() {
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Core,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
+
+        // No default configured yet
+        assert!(bridge.route_default("list files").is_err());
+
+        bridge.set_default(Request::Core);
+        assert_eq!(bridge.default_request(), Some(Request::Core));
+        assert!(bridge.route_default("list files").is_ok());
+    }
+
     #[test]
     fn test_overwrite_handler() {
         let mut bridge = Bridge::new();
@@ -165,14 +729,197 @@ mod tests {
         // Register first handler
         bridge.register(
             Request::Chat,
-            Box::new(|_: &str| Err("First handler".to_string())),
+            Box::new(|_: &Payload| Err("First handler".into())),
         );
 
         // Overwrite with second handler
-        bridge.register(Request::Chat, Box::new(|_: &str| Ok(())));
+        bridge.register(
+            Request::Chat,
+            Box::new(|_: &Payload| Ok(Response::Text(String::new()))),
+        );
 
         // Should use the second handler
         let result = bridge.route(Request::Chat, "test");
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_payload_options_reach_handler() {
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Core,
+            Box::new(|payload: &Payload| {
+                Ok(Response::CommandGenerated {
+                    prompt: payload.input.clone(),
+                    command: "ls -la".to_string(),
+                    explanation: payload
+                        .flag("explain")
+                        .then(|| "Lists all files".to_string()),
+                })
+            }),
+        );
+
+        let response = bridge
+            .route(
+                Request::Core,
+                Payload::new("list files").with_option("explain", "true"),
+            )
+            .unwrap();
+        assert_eq!(
+            response,
+            Response::CommandGenerated {
+                prompt: "list files".to_string(),
+                command: "ls -la".to_string(),
+                explanation: Some("Lists all files".to_string()),
+            }
+        );
+
+        // Plain text input has no options
+        match bridge.route(Request::Core, "list files").unwrap() {
+            Response::CommandGenerated { explanation, .. } => assert!(explanation.is_none()),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_route_async_handler() {
+        let mut bridge = Bridge::new();
+        bridge.register_async(Request::Translate, |payload: Payload| async move {
+            Ok(Response::Text(format!("translated: {}", payload.input)))
+        });
+
+        let response = block_on(bridge.route_async(Request::Translate, "bonjour"));
+        assert_eq!(
+            response,
+            Ok(Response::Text("translated: bonjour".to_string()))
+        );
+
+        // Async handlers cannot be driven from the sync entry point
+        let err = bridge.route(Request::Translate, "bonjour").unwrap_err();
+        assert!(err.message().contains("route_async"));
+    }
+
+    #[test]
+    fn test_route_async_falls_back_to_sync_handler() {
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Core,
+            Box::new(|payload: &Payload| Ok(Response::Text(payload.input.clone()))),
+        );
+        bridge.register_async(Request::Chat, |_: Payload| async {
+            Err(Error::new(ErrorCode::Network, "provider down"))
+        });
+        bridge.set_default(Request::Core);
+
+        assert_eq!(bridge.handler_count(), 2);
+        assert_eq!(
+            block_on(bridge.route_default_async("list files")),
+            Ok(Response::Text("list files".to_string()))
+        );
+        assert_eq!(
+            block_on(bridge.route_async(Request::Chat, "hi")),
+            Err(Error::new(ErrorCode::Network, "provider down"))
+        );
+        assert!(block_on(bridge.route_async(Request::Translate, "hi"))
+            .unwrap_err()
+            .message()
+            .contains("No handler registered"));
+    }
+
+    #[test]
+    fn test_middleware_wraps_sync_and_async_routes() {
+        use std::cell::RefCell;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Core,
+            Box::new(|payload: &Payload| Ok(Response::Text(payload.input.clone()))),
+        );
+        bridge.register_async(Request::Chat, |payload: Payload| async move {
+            Ok(Response::Text(payload.input))
+        });
+        for name in ["outer", "inner"] {
+            let log = Rc::clone(&log);
+            bridge.use_middleware(move |request, payload, next| {
+                let log = Rc::clone(&log);
+                Box::pin(async move {
+                    log.borrow_mut().push(format!("{} {}", name, request));
+                    let result = next.run(payload).await;
+                    log.borrow_mut().push(format!("{} done", name));
+                    result
+                })
+            });
+        }
+
+        assert_eq!(bridge.middleware_count(), 2);
+        assert_eq!(
+            bridge.route(Request::Core, "ls"),
+            Ok(Response::Text("ls".to_string()))
+        );
+        assert_eq!(
+            *log.borrow(),
+            ["outer core", "inner core", "inner done", "outer done"]
+        );
+        assert_eq!(
+            block_on(bridge.route_async(Request::Chat, "hi")),
+            Ok(Response::Text("hi".to_string()))
+        );
+        assert_eq!(log.borrow().len(), 8);
+        // Sync routing still cannot reach an async handler
+        assert!(bridge
+            .route(Request::Chat, "hi")
+            .unwrap_err()
+            .message()
+            .contains("route_async"));
+    }
+
+    #[test]
+    fn test_before_and_after_hooks() {
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Translate,
+            Box::new(|payload: &Payload| Ok(Response::Text(payload.input.clone()))),
+        );
+        bridge.use_before(|_, payload| {
+            if payload.input.len() > 10 {
+                return Err(Error::new(ErrorCode::InvalidInput, "Input too long"));
+            }
+            payload.input = payload.input.trim().to_string();
+            Ok(())
+        });
+        bridge.use_after(|request, result| {
+            result.map(|response| match response {
+                Response::Text(text) => Response::Text(format!("{}: {}", request, text)),
+                other => other,
+            })
+        });
+
+        assert_eq!(
+            bridge.route(Request::Translate, " hallo "),
+            Ok(Response::Text("translate: hallo".to_string()))
+        );
+        assert_eq!(
+            block_on(bridge.route_async(Request::Translate, "guten morgen welt")),
+            Err(Error::new(ErrorCode::InvalidInput, "Input too long"))
+        );
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn test_plugins_cannot_shadow_builtin_requests() {
+        let mut bridge = Bridge::new();
+        let chat = Plugin::new("/usr/local/bin/chat").unwrap();
+        assert!(bridge.register_plugin(chat).is_err());
+
+        let summarize = Plugin::new("/opt/plugins/summarize.py").unwrap();
+        bridge.register_plugin(summarize).unwrap();
+        assert_eq!(bridge.plugin_verbs(), vec!["summarize"]);
+        assert!(bridge.plugin("chat").is_none());
+        assert!(bridge
+            .route_plugin("lint", "x")
+            .unwrap_err()
+            .message()
+            .contains("No plugin registered"));
+    }
 }
@@ -0,0 +1,387 @@
+// Translate -> generate -> translate back, over the handlers of a `Bridge`
+//
+// Command generation works best on English prompts. `Pipeline::ask` sends the
+// prompt to the translate handler (which detects its language and translates it to
+// English when needed), generates a command from the English prompt, and
+// translates the explanation back into the prompt's language.
+//
+// Most prompts are already English, so by default generation starts on the
+// original prompt while translation runs. If the prompt turns out to be English
+// that result is used as is, hiding the detection latency; otherwise the request
+// is dropped unfinished and generation runs again on the translation. Core handlers
+// should stop working when their future is dropped, so the abandoned generation
+// does not hold the model. Speculative requests carry `SPECULATIVE_OPTION` so
+// handlers keep quiet about failures nobody may see.
+// Generation from a translated prompt carries `REPLY_LANG_OPTION`, so the core
+// handler can report a rejected command in the language the user wrote in.
+
+use crate::{Bridge, Error, Payload, Request, Response};
+use futures_util::future::{select, Either};
+use std::pin::pin;
+use std::time::Instant;
+
+/// Payload flag marking a request whose response may be discarded
+pub const SPECULATIVE_OPTION: &str = "speculative";
+
+/// Translate payload option: language of the input (detected when absent)
+pub const SOURCE_LANG_OPTION: &str = "source_lang";
+
+/// Translate payload option: language to translate into (English when absent)
+pub const TARGET_LANG_OPTION: &str = "target_lang";
+
+/// Core payload option: language for handlers to report failures in, that of a
+/// translated prompt or the user's display language (English when absent)
+pub const REPLY_LANG_OPTION: &str = "reply_lang";
+
+/// Command generated for a prompt in any language
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer {
+    /// Language detected in the prompt
+    pub source_lang: String,
+    /// Prompt the command was generated from (the original when it was English)
+    pub english_prompt: String,
+    pub command: String,
+    /// Explanation in the prompt's language
+    pub explanation: Option<String>,
+}
+
+impl Answer {
+    /// Whether the prompt had to be translated
+    pub fn was_translated(&self) -> bool {
+        self.source_lang != "en"
+    }
+}
+
+/// Language detection and translation of a prompt
+struct Detected {
+    source_lang: String,
+    translated: Option<String>,
+}
+
+/// Runs a prompt through the translate and core handlers of a bridge
+pub struct Pipeline<'a> {
+    bridge: &'a Bridge,
+    speculate: bool,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(bridge: &'a Bridge) -> Self {
+        Self {
+            bridge,
+            speculate: true,
+        }
+    }
+
+    /// Whether to start generating from the original prompt while its language is
+    /// detected (on by default)
+    pub fn speculate(mut self, speculate: bool) -> Self {
+        self.speculate = speculate;
+        self
+    }
+
+    /// Generate a command for `payload`, whatever its language
+    ///
+    /// Options on the payload (`explain`, the time budget, ...) reach the core
+    /// handler; the time budget covers the whole pipeline. An explanation that
+    /// cannot be translated back is returned in English.
+    pub async fn ask(&self, payload: impl Into<Payload>) -> Result<Answer, Error> {
+        let payload = payload.into();
+        let deadline = payload.deadline();
+        let budgeted = |payload: Payload| match deadline {
+            Some(deadline) => {
+                payload.with_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => payload,
+        };
+
+        let detect = self.detect(budgeted(payload.clone()));
+        let (detected, speculated) = if self.speculate {
+            let speculative = budgeted(payload.clone()).with_option(SPECULATIVE_OPTION, "true");
+            let generate = self.bridge.route_async(Request::Core, speculative);
+            match select(pin!(detect), pin!(generate)).await {
+                Either::Left((detected, generate)) => {
+                    let detected = detected?;
+                    // Not English: the speculative generation is dropped, cancelling it
+                    let speculated = match detected.translated {
+                        None => generate.await.ok(),
+                        Some(_) => None,
+                    };
+                    (detected, speculated)
+                }
+                Either::Right((generated, detect)) => (detect.await?, generated.ok()),
+            }
+        } else {
+            (detect.await?, None)
+        };
+
+        // A failed speculative generation runs again normally, so the handler
+        // reports the failure
+        let response = match speculated {
+            Some(response) if detected.translated.is_none() => response,
+            _ => {
+                let english = detected.translated.as_deref().unwrap_or(&payload.input);
+                let mut core = budgeted(payload.clone());
+                if detected.translated.is_some() {
+                    core = core.with_option(REPLY_LANG_OPTION, &detected.source_lang);
+                }
+                self.generate(core, english).await?
+            }
+        };
+
+        let (command, explanation) = match response {
+            Response::CommandGenerated {
+                command,
+                explanation,
+                ..
+            } => (command, explanation),
+            other => {
+                return Err(Error::from(format!(
+                    "Unexpected response from core: {:?}",
+                    other
+                )))
+            }
+        };
+        let explanation = match explanation {
+            Some(explanation) if detected.translated.is_some() => Some(
+                self.translate_back(
+                    budgeted(Payload::new(explanation.as_str())),
+                    &detected.source_lang,
+                )
+                .await
+                .unwrap_or(explanation),
+            ),
+            explanation => explanation,
+        };
+
+        Ok(Answer {
+            english_prompt: detected.translated.unwrap_or(payload.input),
+            source_lang: detected.source_lang,
+            command,
+            explanation,
+        })
+    }
+
+    async fn detect(&self, payload: Payload) -> Result<Detected, Error> {
+        match self.bridge.route_async(Request::Translate, payload).await? {
+            Response::Translation {
+                source_lang,
+                translated,
+                was_translated,
+                ..
+            } => Ok(Detected {
+                source_lang,
+                translated: was_translated.then_some(translated),
+            }),
+            other => Err(Error::from(format!(
+                "Unexpected response from translate: {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Route `prompt` to the core handler with the options of `payload`
+    async fn generate(&self, payload: Payload, prompt: &str) -> Result<Response, Error> {
+        let payload = Payload {
+            input: prompt.to_string(),
+            options: payload.options,
+        };
+        self.bridge.route_async(Request::Core, payload).await
+    }
+
+    async fn translate_back(&self, payload: Payload, target_lang: &str) -> Result<String, Error> {
+        let payload = payload
+            .with_option(SOURCE_LANG_OPTION, "en")
+            .with_option(TARGET_LANG_OPTION, target_lang);
+        match self.bridge.route_async(Request::Translate, payload).await? {
+            Response::Translation { translated, .. } => Ok(translated),
+            other => Err(Error::from(format!(
+                "Unexpected response from translate: {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// Bridge whose translator treats prompts starting with "¿" as Spanish, and
+    /// whose core handler counts its calls
+    fn bridge(core_calls: Arc<AtomicUsize>) -> Bridge {
+        let mut bridge = Bridge::new();
+        bridge.register_async(Request::Translate, |payload: Payload| async move {
+            let (source_lang, target_lang, translated) = match payload.option(TARGET_LANG_OPTION) {
+                Some(target) => ("en", target, format!("[{}] {}", target, payload.input)),
+                None if payload.input.starts_with('¿') => ("es", "en", "list files".to_string()),
+                None => ("en", "en", payload.input.clone()),
+            };
+            Ok(Response::Translation {
+                was_translated: source_lang != target_lang,
+                source_lang: source_lang.to_string(),
+                target_lang: target_lang.to_string(),
+                original: payload.input.clone(),
+                translated,
+
+                confidence: None,
+            })
+        });
+        bridge.register_async(Request::Core, move |payload: Payload| {
+            let calls = core_calls.clone();
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                if payload.flag(SPECULATIVE_OPTION) && payload.input.starts_with('¿') {
+                    return Err("not a command".into());
+                }
+                if payload.input == "delete everything" {
+                    let lang = payload.option(REPLY_LANG_OPTION).unwrap_or("en");
+                    return Err(format!("rejected ({})", lang).into());
+                }
+                Ok(Response::CommandGenerated {
+                    command: format!("cmd for {}", payload.input),
+                    explanation: payload.flag("explain").then(|| "Lists files".to_string()),
+                    prompt: payload.input,
+                })
+            }
+        });
+        bridge
+    }
+
+    #[test]
+    fn test_english_prompt_uses_speculative_generation() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let bridge = bridge(calls.clone());
+        let answer = block_on(Pipeline::new(&bridge).ask("list files")).unwrap();
+
+        assert_eq!(answer.command, "cmd for list files");
+        assert!(!answer.was_translated());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_foreign_prompt_is_translated_both_ways() {
+        for speculate in [true, false] {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let bridge = bridge(calls.clone());
+            let payload = Payload::new("¿listar archivos?").with_option("explain", "true");
+            let answer =
+                block_on(Pipeline::new(&bridge).speculate(speculate).ask(payload)).unwrap();
+
+            assert_eq!(answer.source_lang, "es");
+            assert_eq!(answer.english_prompt, "list files");
+            assert_eq!(answer.command, "cmd for list files");
+            assert_eq!(answer.explanation.as_deref(), Some("[es] Lists files"));
+            // Detection finished first, so the speculative generation never ran
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn test_reply_language_reaches_core_for_translated_prompts() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut bridge = bridge(calls);
+        bridge.register_async(Request::Translate, |payload: Payload| async move {
+            let source_lang = if payload.input.starts_with('¿') {
+                "es"
+            } else {
+                "en"
+            };
+            Ok(Response::Translation {
+                was_translated: source_lang != "en",
+                source_lang: source_lang.to_string(),
+                target_lang: "en".to_string(),
+                original: payload.input.clone(),
+                translated: "delete everything".to_string(),
+
+                confidence: None,
+            })
+        });
+
+        let err = block_on(Pipeline::new(&bridge).ask("¿borrar todo?")).unwrap_err();
+        assert_eq!(err.message(), "rejected (es)");
+        let err = block_on(Pipeline::new(&bridge).ask("delete everything")).unwrap_err();
+        assert_eq!(err.message(), "rejected (en)");
+    }
+
+    #[test]
+    fn test_foreign_prompt_drops_speculative_generation() {
+        /// Records that the core handler's future was dropped
+        struct Dropped(Arc<AtomicBool>);
+        impl Drop for Dropped {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let mut bridge = bridge(Arc::new(AtomicUsize::new(0)));
+        bridge.register_async(Request::Translate, |payload: Payload| async move {
+            // Let the speculative generation start before detection answers
+            tokio::task::yield_now().await;
+            Ok(Response::Translation {
+                was_translated: true,
+                source_lang: "es".to_string(),
+                target_lang: "en".to_string(),
+                original: payload.input.clone(),
+                translated: "list files".to_string(),
+                confidence: None,
+            })
+        });
+        let flag = dropped.clone();
+        bridge.register_async(Request::Core, move |payload: Payload| {
+            let dropped = flag.clone();
+            async move {
+                if payload.flag(SPECULATIVE_OPTION) {
+                    let _guard = Dropped(dropped);
+                    std::future::pending::<()>().await;
+                }
+                Ok(Response::CommandGenerated {
+                    command: format!("cmd for {}", payload.input),
+                    explanation: None,
+                    prompt: payload.input,
+                })
+            }
+        });
+
+        let answer = block_on(Pipeline::new(&bridge).ask("¿listar archivos?")).unwrap();
+        assert_eq!(answer.command, "cmd for list files");
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_failed_speculation_runs_again() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut bridge = bridge(calls.clone());
+        let counter = calls.clone();
+        bridge.register_async(Request::Core, move |payload: Payload| {
+            let calls = counter.clone();
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                match payload.flag(SPECULATIVE_OPTION) {
+                    true => Err("quiet failure".into()),
+                    false => Err("reported failure".into()),
+                }
+            }
+        });
+
+        let err = block_on(Pipeline::new(&bridge).ask("list files")).unwrap_err();
+        assert_eq!(err.message(), "reported failure");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_missing_handler_fails() {
+        let bridge = Bridge::new();
+        let err = block_on(Pipeline::new(&bridge).ask("list files")).unwrap_err();
+        assert!(err.message().contains("No handler"));
+    }
+}
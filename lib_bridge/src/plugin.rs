@@ -0,0 +1,295 @@
+// External executables that add verbs to the bridge
+//
+// A plugin is any executable file in the plugins directory; its file name (minus
+// an extension) is the verb it handles, so `plugins/summarize.py` answers
+// `eidos summarize ...`. The bridge runs it once per request: the payload goes to
+// stdin as a JSON object
+//
+//     {"verb": "summarize", "input": "...", "options": {"timeout_ms": "30000"}}
+//
+// and stdout is read back as a `Response` in the daemon's wire format
+// (`{"text": "..."}`, `{"command_generated": {...}}`, ...). Output that is not a
+// JSON response is taken as plain text, so a shell script can simply `echo`.
+// A non-zero exit status fails the request with the plugin's stderr.
+
+use crate::{Error, ErrorCode, Payload, Response};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a plugin with a time budget is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Request written to a plugin's stdin
+#[derive(serde::Serialize)]
+struct PluginRequest<'a> {
+    verb: &'a str,
+    input: &'a str,
+    options: &'a HashMap<String, String>,
+}
+
+/// An executable that handles one custom verb
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plugin {
+    name: String,
+    path: PathBuf,
+}
+
+impl Plugin {
+    /// Plugin for the executable at `path`, named after its file stem
+    pub fn new(path: impl Into<PathBuf>) -> Option<Self> {
+        let path = path.into();
+        let name = path.file_stem()?.to_str()?.to_string();
+        valid_name(&name).then_some(Self { name, path })
+    }
+
+    /// Verb this plugin handles
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Executable run for each request
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run the plugin on `payload` and parse what it printed
+    ///
+    /// Blocks until the plugin exits. A time budget on the payload (see
+    /// [`Payload::with_timeout`]) kills a plugin that takes longer.
+    pub fn call(&self, payload: &Payload) -> Result<Response, Error> {
+        let request = PluginRequest {
+            verb: &self.name,
+            input: &payload.input,
+            options: &payload.options,
+        };
+        let request = serde_json::to_vec(&request)
+            .map_err(|e| format!("Failed to encode request for plugin '{}': {}", self.name, e))?;
+
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                Error::new(
+                    ErrorCode::Io,
+                    format!("Failed to start plugin '{}': {}", self.name, e),
+                )
+            })?;
+
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+        if let Some(mut stdin) = child.stdin.take() {
+            // A plugin that ignores its input may exit before reading it
+            match stdin.write_all(&request) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                    let _ = child.kill();
+                    return Err(Error::new(
+                        ErrorCode::Io,
+                        format!("Failed to write to plugin '{}': {}", self.name, e),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        let status = wait(&mut child, payload.deadline()).map_err(|e| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Plugin '{}' failed: {}", self.name, e),
+            )
+        })?;
+        // After a timeout its own children may still hold the pipes open, so the
+        // output is abandoned rather than waited for
+        let Some(status) = status else {
+            return Err(Error::new(
+                ErrorCode::Timeout,
+                format!(
+                    "Plugin '{}' timed out after {}ms",
+                    self.name,
+                    payload.timeout().unwrap_or_default().as_millis()
+                ),
+            ));
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            let reason = stderr.trim();
+            return Err(Error::from(if reason.is_empty() {
+                format!("Plugin '{}' exited with {}", self.name, status)
+            } else {
+                format!("Plugin '{}' failed: {}", self.name, reason)
+            }));
+        }
+        Ok(parse_response(&stdout))
+    }
+}
+
+/// Every plugin in `dir`, sorted by name
+///
+/// A missing directory means no plugins. Files that are not executable, hidden
+/// files and names that are not valid verbs are skipped; when two files share a
+/// stem (`lint.sh`, `lint.py`), the first in file name order wins.
+pub fn discover(dir: &Path) -> Vec<Plugin> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_executable(path))
+        .collect();
+    paths.sort();
+
+    let mut plugins: Vec<Plugin> = Vec::new();
+    for plugin in paths.into_iter().filter_map(Plugin::new) {
+        if !plugins.iter().any(|p| p.name == plugin.name) {
+            plugins.push(plugin);
+        }
+    }
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+/// Verbs are lowercase words so they read like the built-in subcommands
+fn valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Read a pipe to the end on its own thread so a chatty plugin never blocks
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Wait for the plugin to exit; `None` when it was killed at the deadline
+fn wait(
+    child: &mut Child,
+    deadline: Option<Instant>,
+) -> io::Result<Option<std::process::ExitStatus>> {
+    let Some(deadline) = deadline else {
+        return child.wait().map(Some);
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// A JSON response if the plugin printed one, otherwise its output as text
+fn parse_response(stdout: &[u8]) -> Response {
+    serde_json::from_slice(stdout).unwrap_or_else(|_| {
+        let text = String::from_utf8_lossy(stdout);
+        Response::Text(text.trim_end().to_string())
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_discover_skips_non_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "summarize.sh", "echo one");
+        script(dir.path(), "summarize.py", "echo two");
+        script(dir.path(), "Shout", "echo loud");
+        script(dir.path(), ".hidden", "echo hidden");
+        fs::write(dir.path().join("notes"), "not executable").unwrap();
+
+        let plugins = discover(dir.path());
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].name(), "summarize");
+        assert!(plugins[0].path().ends_with("summarize.py"));
+        assert!(discover(&dir.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_call_reads_text_and_json_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        let echo = Plugin::new(script(dir.path(), "echo", "cat")).unwrap();
+        match echo
+            .call(&Payload::new("hi").with_option("k", "v"))
+            .unwrap()
+        {
+            Response::Text(text) => {
+                let request: serde_json::Value = serde_json::from_str(&text).unwrap();
+                assert_eq!(request["verb"], "echo");
+                assert_eq!(request["input"], "hi");
+                assert_eq!(request["options"]["k"], "v");
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let json = Plugin::new(script(
+            dir.path(),
+            "json",
+            r#"echo '{"chat_reply": {"reply": "hello", "sources": []}}'"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            json.call(&Payload::new("")).unwrap(),
+            Response::ChatReply {
+                reply: "hello".to_string(),
+                sources: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_call_reports_failures_and_timeouts() {
+        let dir = tempfile::tempdir().unwrap();
+        let failing =
+            Plugin::new(script(dir.path(), "fail", "echo 'no notes' >&2; exit 3")).unwrap();
+        let err = failing.call(&Payload::new("")).unwrap_err();
+        assert!(err.message().contains("no notes"), "{}", err);
+
+        let slow = Plugin::new(script(dir.path(), "slow", "sleep 5")).unwrap();
+        let payload = Payload::new("").with_timeout(Duration::from_millis(50));
+        let err = slow.call(&payload).unwrap_err();
+        assert!(err.message().contains("timed out"), "{}", err);
+        assert!(err.is_retryable());
+    }
+}
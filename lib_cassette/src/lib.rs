@@ -0,0 +1,451 @@
+//! Record the HTTP requests of the Eidos clients and replay them offline
+//!
+//! A cassette is a JSON file of request/response pairs. `lib_chat` and
+//! `lib_translate` send every request through the cassette named by the
+//! environment, if any:
+//!
+//! - `EIDOS_RECORD=cassette.json` sends requests as usual and appends each one,
+//!   with the response it got, to the file
+//! - `EIDOS_REPLAY=cassette.json` answers requests from the file and never
+//!   touches the network; a request that was not recorded fails
+//!
+//! Replay matches the method, URL and body of a request against the recorded
+//! ones and serves each recorded response once, in order, so a conversation
+//! that sends the same request twice gets both replies back. Headers are never
+//! written to the file, which keeps API keys sent as headers out of it, and the
+//! `api_key` and `key` fields of JSON bodies and query strings are replaced by
+//! `<redacted>` before requests are saved or matched. Streamed responses are
+//! recorded whole and replayed in one piece.
+//!
+//! Bodies that are JSON objects or arrays are stored as JSON, anything else as
+//! a string, so a cassette can be written by hand to mock a provider:
+//!
+//! ```json
+//! {
+//!   "interactions": [
+//!     {
+//!       "request": {
+//!         "method": "POST",
+//!         "url": "http://localhost:5000/translate",
+//!         "body": {"q": "hola", "source": "es", "target": "en", "format": "text"}
+//!       },
+//!       "response": {
+//!         "status": 200,
+//!         "content_type": "application/json",
+//!         "body": {"translatedText": "hello"}
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+
+use once_cell::sync::Lazy;
+use reqwest::{RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Environment variable naming a cassette to record requests into
+pub const RECORD_VAR: &str = "EIDOS_RECORD";
+
+/// Environment variable naming a cassette to replay requests from
+pub const REPLAY_VAR: &str = "EIDOS_REPLAY";
+
+/// Body and query fields that hold credentials
+const SECRET_FIELDS: [&str; 2] = ["api_key", "key"];
+
+/// What secret fields are replaced with
+const REDACTED: &str = "<redacted>";
+
+#[derive(Error, Debug)]
+pub enum CassetteError {
+    /// Sending a request while recording failed
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+
+    #[error("No recorded response for {method} {url} in {path}")]
+    NotRecorded {
+        method: String,
+        url: String,
+        path: String,
+    },
+
+    #[error("Cannot read cassette {path}: {reason}")]
+    Read { path: String, reason: String },
+
+    #[error("Cannot write cassette {path}: {reason}")]
+    Write { path: String, reason: String },
+
+    #[error("Set only one of {RECORD_VAR} and {REPLAY_VAR}")]
+    Conflict,
+}
+
+pub type Result<T> = std::result::Result<T, CassetteError>;
+
+/// Whether a cassette is being written or played back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Record,
+    Replay,
+}
+
+/// A request and the response it got
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub body: Value,
+}
+
+/// The file format
+#[derive(Default, Serialize, Deserialize)]
+struct Tape {
+    interactions: Vec<Interaction>,
+}
+
+/// Recorded interactions, and which of them replay has served
+struct State {
+    interactions: Vec<Interaction>,
+    served: Vec<bool>,
+}
+
+/// A cassette file being recorded or replayed
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    state: Mutex<State>,
+}
+
+impl Cassette {
+    /// Record into `path`, after the interactions it already holds
+    pub fn record(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let tape = if path.exists() {
+            load(&path)?
+        } else {
+            Tape::default()
+        };
+        Ok(Self::open(path, Mode::Record, tape))
+    }
+
+    /// Replay the interactions recorded in `path`
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let tape = load(&path)?;
+        Ok(Self::open(path, Mode::Replay, tape))
+    }
+
+    /// The cassette named by `EIDOS_RECORD` or `EIDOS_REPLAY`, if either is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+        match (var(RECORD_VAR), var(REPLAY_VAR)) {
+            (Some(_), Some(_)) => Err(CassetteError::Conflict),
+            (Some(path), None) => Self::record(path).map(Some),
+            (None, Some(path)) => Self::replay(path).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn open(path: PathBuf, mode: Mode, tape: Tape) -> Self {
+        let served = vec![false; tape.interactions.len()];
+        Self {
+            path,
+            mode,
+            state: Mutex::new(State {
+                interactions: tape.interactions,
+                served,
+            }),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Every interaction in the cassette, recorded before or during this run
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.lock().interactions.clone()
+    }
+
+    /// Send `request`, or answer it from the cassette when replaying
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let recorded = RecordedRequest {
+            method: request.method().to_string(),
+            url: redact_url(request.url()),
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|bytes| redact(body_value(bytes)))
+                .unwrap_or(Value::Null),
+        };
+
+        match self.mode {
+            Mode::Replay => {
+                let response = self.take(&recorded)?;
+                self.to_response(&response)
+            }
+            Mode::Record => {
+                let response = client.execute(request).await?;
+                let recorded_response = RecordedResponse {
+                    status: response.status().as_u16(),
+                    content_type: response
+                        .headers()
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string),
+                    body: body_value(&response.bytes().await?),
+                };
+                let built = self.to_response(&recorded_response)?;
+                self.append(Interaction {
+                    request: recorded,
+                    response: recorded_response,
+                })?;
+                Ok(built)
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The first recorded response to `request` that has not been served yet
+    fn take(&self, request: &RecordedRequest) -> Result<RecordedResponse> {
+        let mut state = self.lock();
+        let State {
+            interactions,
+            served,
+        } = &mut *state;
+        let found = interactions
+            .iter()
+            .zip(served.iter_mut())
+            .find(|(interaction, served)| !**served && interaction.request == *request);
+        match found {
+            Some((interaction, served)) => {
+                *served = true;
+                Ok(interaction.response.clone())
+            }
+            None => Err(CassetteError::NotRecorded {
+                method: request.method.clone(),
+                url: request.url.clone(),
+                path: self.path.display().to_string(),
+            }),
+        }
+    }
+
+    /// Add `interaction` and rewrite the file, so an interrupted run keeps what
+    /// it recorded so far
+    fn append(&self, interaction: Interaction) -> Result<()> {
+        let mut state = self.lock();
+        state.interactions.push(interaction);
+        state.served.push(true);
+        let tape = Tape {
+            interactions: state.interactions.clone(),
+        };
+        let write_error = |reason: String| CassetteError::Write {
+            path: self.path.display().to_string(),
+            reason,
+        };
+        let json = serde_json::to_string_pretty(&tape).map_err(|e| write_error(e.to_string()))?;
+        lib_util::write_atomic(&self.path, json + "\n").map_err(|e| write_error(e.to_string()))
+    }
+
+    fn to_response(&self, recorded: &RecordedResponse) -> Result<Response> {
+        let mut builder = http::Response::builder().status(recorded.status);
+        if let Some(content_type) = &recorded.content_type {
+            builder = builder.header(http::header::CONTENT_TYPE, content_type.as_str());
+        }
+        let body = match &recorded.body {
+            Value::Null => Vec::new(),
+            Value::String(text) => text.clone().into_bytes(),
+            json => json.to_string().into_bytes(),
+        };
+        builder
+            .body(body)
+            .map(Response::from)
+            .map_err(|e| CassetteError::Read {
+                path: self.path.display().to_string(),
+                reason: e.to_string(),
+            })
+    }
+}
+
+/// The cassette named by the environment, opened on first use and shared by
+/// every client in the process
+pub fn global() -> std::result::Result<Option<Arc<Cassette>>, String> {
+    static GLOBAL: Lazy<std::result::Result<Option<Arc<Cassette>>, String>> = Lazy::new(|| {
+        Cassette::from_env()
+            .map(|cassette| cassette.map(Arc::new))
+            .map_err(|e| e.to_string())
+    });
+    GLOBAL.clone()
+}
+
+/// Send `request` through `cassette`, or straight to the network without one
+pub async fn send(cassette: Option<&Cassette>, request: RequestBuilder) -> Result<Response> {
+    match cassette {
+        Some(cassette) => cassette.send(request).await,
+        None => Ok(request.send().await?),
+    }
+}
+
+fn load(path: &Path) -> Result<Tape> {
+    let read_error = |reason: String| CassetteError::Read {
+        path: path.display().to_string(),
+        reason,
+    };
+    let json = fs::read_to_string(path).map_err(|e| read_error(e.to_string()))?;
+    serde_json::from_str(&json).map_err(|e| read_error(e.to_string()))
+}
+
+/// `bytes` as JSON when they hold an object or array, otherwise as text
+fn body_value(bytes: &[u8]) -> Value {
+    match serde_json::from_slice::<Value>(bytes) {
+        Ok(json @ (Value::Object(_) | Value::Array(_))) => json,
+        _ => Value::String(String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
+
+fn is_secret(field: &str) -> bool {
+    SECRET_FIELDS.contains(&field)
+}
+
+/// `body` with the values of secret fields replaced, at any depth
+fn redact(body: Value) -> Value {
+    match body {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(field, value)| match value {
+                    Value::String(_) if is_secret(&field) => (field, REDACTED.into()),
+                    value => (field, redact(value)),
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
+
+/// `url` with the values of secret query parameters replaced
+fn redact_url(url: &Url) -> String {
+    if !url.query_pairs().any(|(name, _)| is_secret(&name)) {
+        return url.to_string();
+    }
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if is_secret(&name) {
+                REDACTED.to_string()
+            } else {
+                value.into_owned()
+            };
+            (name.into_owned(), value)
+        })
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// A server answering one request with `body`, on a `http://` URL
+    fn serve_once(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay_without_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        let url = format!("{}/translate", serve_once(r#"{"translatedText":"hello"}"#));
+        let client = reqwest::Client::new();
+        let request = || {
+            client
+                .post(&url)
+                .header("Authorization", "Bearer sk-secret")
+                .json(&json!({"q": "hola", "api_key": "sk-secret"}))
+        };
+
+        let recorder = Cassette::record(&path).unwrap();
+        let response = recorder.send(request()).await.unwrap();
+        assert_eq!(
+            response.text().await.unwrap(),
+            r#"{"translatedText":"hello"}"#
+        );
+
+        // Credentials stay out of the file
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("sk-secret"), "{}", saved);
+        assert_eq!(
+            recorder.interactions()[0].request.body,
+            json!({"q": "hola", "api_key": "<redacted>"})
+        );
+
+        // The server is gone; replay answers from the file, once per recording
+        let player = Cassette::replay(&path).unwrap();
+        let response = player.send(request()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let reply: Value = response.json().await.unwrap();
+        assert_eq!(reply, json!({"translatedText": "hello"}));
+        let err = player.send(request()).await.unwrap_err();
+        assert!(matches!(err, CassetteError::NotRecorded { .. }), "{}", err);
+
+        let other = client.post(&url).json(&json!({"q": "adios"}));
+        assert!(player.send(other).await.is_err());
+    }
+
+    #[test]
+    fn test_redact_url_query_keys() {
+        let url = Url::parse("https://example.com/v1?key=secret&alt=sse").unwrap();
+        assert_eq!(
+            redact_url(&url),
+            "https://example.com/v1?key=%3Credacted%3E&alt=sse"
+        );
+        assert!(Cassette::replay("/nonexistent/cassette.json").is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// lib_chat/src/anthropic.rs
+// Wire format of the Anthropic Messages API
+//
+// Unlike the OpenAI format, the system prompt is a top-level `system` field rather
+// than a message, `max_tokens` is mandatory, and the reply is a list of content
+// blocks. Streams are Server-Sent Events whose `data:` payload names its `type`:
+// text arrives in `content_block_delta` events and `message_stop` ends the reply.
+
+use crate::error::{ChatError, Result};
+use crate::history::{Message, Role};
+#[cfg(feature = "vision")]
+use crate::image::Image;
+use crate::stream::StreamEvent;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const API_URL: &str = "https://api.anthropic.com/v1/messages";
+pub(crate) const API_VERSION: &str = "2023-06-01";
+
+/// Used when the options leave `max_tokens` unset, since the API requires it
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage {
+    role: &'static str,
+    content: AnthropicContent,
+}
+
+/// Plain text, or content blocks when the message carries images
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum AnthropicContent {
+    Text(String),
+    #[cfg(feature = "vision")]
+    Blocks(Vec<ContentPart>),
+}
+
+/// An [`Image`] serializes as the `source` of an image block
+#[cfg(feature = "vision")]
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Image { source: Image },
+    Text { text: String },
+}
+
+impl AnthropicContent {
+    /// Content of `message`, its images first as the API recommends
+    fn of(message: &Message) -> Self {
+        #[cfg(feature = "vision")]
+        if !message.images.is_empty() {
+            let images = message.images.iter().map(|image| ContentPart::Image {
+                source: image.clone(),
+            });
+            let text = ContentPart::Text {
+                text: message.content.clone(),
+            };
+            return AnthropicContent::Blocks(images.chain(std::iter::once(text)).collect());
+        }
+        AnthropicContent::Text(message.content.clone())
+    }
+}
+
+impl AnthropicRequest {
+    /// Request for `messages`, with every system message joined into `system`
+    pub(crate) fn new(
+        model: &str,
+        messages: &[Message],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+        stream: bool,
+    ) -> Self {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect();
+        let messages = messages
+            .iter()
+            .filter_map(|m| {
+                let role = match m.role {
+                    Role::System => return None,
+                    Role::User => "user",
+                    Role::Assistant => "assistant",
+                };
+                Some(AnthropicMessage {
+                    role,
+                    content: AnthropicContent::of(m),
+                })
+            })
+            .collect();
+
+        Self {
+            model: model.to_string(),
+            max_tokens: max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages,
+            temperature,
+            stream,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AnthropicResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+impl AnthropicResponse {
+    /// The text blocks of the reply, concatenated
+    pub(crate) fn text(self) -> Result<String> {
+        let text: String = self
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        if text.is_empty() {
+            return Err(ChatError::InvalidResponse(
+                "No text content in response".to_string(),
+            ));
+        }
+        Ok(text)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    delta: Option<StreamDelta>,
+    #[serde(default)]
+    error: Option<StreamError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+/// Parse one Server-Sent Events line from a Messages API stream
+///
+/// `event:` lines are ignored; the `data:` payload repeats the event type.
+pub fn parse_stream_line(line: &str) -> Option<Result<StreamEvent>> {
+    let data = line.trim().strip_prefix("data:")?.trim();
+
+    match serde_json::from_str::<StreamChunk>(data) {
+        Ok(chunk) => match chunk.kind.as_str() {
+            "content_block_delta" => chunk
+                .delta
+                .and_then(|delta| delta.text)
+                .filter(|text| !text.is_empty())
+                .map(|text| Ok(StreamEvent::Token(text))),
+            "message_stop" => Some(Ok(StreamEvent::Done)),
+            "error" => Some(Err(ChatError::ApiError(
+                chunk
+                    .error
+                    .map(|e| e.message)
+                    .unwrap_or_else(|| "stream error".to_string()),
+            ))),
+            _ => None,
+        },
+        Err(e) => Some(Err(ChatError::InvalidResponse(format!(
+            "Malformed stream event: {}",
+            e
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_messages_move_to_system_field() {
+        let messages = vec![
+            Message::system("Be brief."),
+            Message::system("Notes: ..."),
+            Message::user("hi"),
+            Message::assistant("hello"),
+        ];
+        let request = AnthropicRequest::new("claude", &messages, Some(0.2), None, false);
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["system"], "Be brief.\n\nNotes: ...");
+        assert_eq!(json["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(json["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(json["messages"][1]["role"], "assistant");
+        assert!(json.get("stream").is_none());
+    }
+
+    #[cfg(feature = "vision")]
+    #[test]
+    fn test_images_become_content_blocks() {
+        let screenshot = Image::from_bytes(b"\x89PNG\r\n\x1a\n").unwrap();
+        let messages = vec![
+            Message::user("What does this error mean?").with_images(vec![screenshot]),
+            Message::assistant("A missing file."),
+        ];
+        let request = AnthropicRequest::new("claude", &messages, None, None, false);
+        let json = serde_json::to_value(&request).unwrap();
+
+        let blocks = &json["messages"][0]["content"];
+        assert_eq!(blocks[0]["type"], "image");
+        assert_eq!(blocks[0]["source"]["type"], "base64");
+        assert_eq!(blocks[0]["source"]["media_type"], "image/png");
+        assert_eq!(blocks[1]["type"], "text");
+        assert_eq!(blocks[1]["text"], "What does this error mean?");
+        assert_eq!(json["messages"][1]["content"], "A missing file.");
+    }
+
+    #[test]
+    fn test_response_text() {
+        let response: AnthropicResponse = serde_json::from_str(
+            r#"{"content":[{"type":"text","text":"ls "},{"type":"text","text":"-la"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.text().unwrap(), "ls -la");
+
+        let empty: AnthropicResponse = serde_json::from_str(r#"{"content":[]}"#).unwrap();
+        assert!(empty.text().is_err());
+    }
+
+    #[test]
+    fn test_parse_stream_line() {
+        let delta = r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#;
+        assert_eq!(
+            parse_stream_line(delta).unwrap().unwrap(),
+            StreamEvent::Token("Hi".to_string())
+        );
+        assert_eq!(
+            parse_stream_line(r#"data: {"type":"message_stop"}"#)
+                .unwrap()
+                .unwrap(),
+            StreamEvent::Done
+        );
+        assert!(parse_stream_line("event: content_block_delta").is_none());
+        assert!(parse_stream_line(r#"data: {"type":"ping"}"#).is_none());
+        assert!(parse_stream_line(
+            r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+        )
+        .unwrap()
+        .is_err());
+    }
+}
@@ -1,21 +1,92 @@
 // lib_chat/src/api.rs
+use crate::anthropic::{self, AnthropicRequest, AnthropicResponse};
 use crate::error::{ChatError, Result};
-use crate::history::Message;
-use reqwest::Client;
+use crate::gemini::{self, GeminiRequest, GeminiResponse};
+use crate::history::{Message, Role};
+#[cfg(feature = "vision")]
+use crate::image::Image;
+use crate::stream::{token_stream, StreamFormat, TokenStream};
+use lib_cassette::Cassette;
+use reqwest::{Client, IntoUrl, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use std::env;
-use std::time::Duration;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-// Default timeouts (can be overridden via environment variables)
+// Default timeouts, used unless the caller passes its own `HttpTimeouts`
 const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
 const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
 
+/// Providers in the order [`ApiProvider::from_env`] tries them
+pub const PROVIDER_NAMES: [&str; 5] = ["openai", "anthropic", "gemini", "ollama", "custom"];
+
+/// Timeouts of the HTTP client talking to the provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpTimeouts {
+    /// Limit for a whole request, including reading the reply
+    pub request: Duration,
+    /// Limit for establishing the connection
+    pub connect: Duration,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self {
+            request: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            connect: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+        }
+    }
+}
+
+/// Request option that not every provider can honour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Token-by-token replies (`send_message_stream`)
+    Streaming,
+    /// Embeddings API, used to build and query notes indexes
+    Embeddings,
+    /// Replies constrained to a single JSON object (`ChatOptions::json_mode`)
+    JsonMode,
+    /// Images given by URL rather than sent along (`Image::Url`)
+    #[cfg(feature = "vision")]
+    ImageUrls,
+}
+
+impl Feature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Streaming => "streaming",
+            Feature::Embeddings => "embeddings",
+            Feature::JsonMode => "JSON mode",
+            #[cfg(feature = "vision")]
+            Feature::ImageUrls => "image URLs (attach the file instead)",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ApiProvider {
     OpenAI {
         api_key: String,
         model: String,
     },
+    /// Anthropic Messages API
+    Anthropic {
+        api_key: String,
+        model: String,
+    },
+    /// Google Gemini API
+    Gemini {
+        api_key: String,
+        model: String,
+    },
     Ollama {
         base_url: String,
         model: String,
@@ -29,54 +100,250 @@ pub enum ApiProvider {
 
 impl ApiProvider {
     /// Load provider from environment variables
-    /// Priority: OPENAI_API_KEY > OLLAMA_HOST > Custom
+    /// Priority: OPENAI_API_KEY > ANTHROPIC_API_KEY > GEMINI_API_KEY > OLLAMA_HOST > Custom
     pub fn from_env() -> Result<Self> {
-        // Try OpenAI first
-        if let Ok(api_key) = env::var("OPENAI_API_KEY") {
-            let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-3.5-turbo".to_string());
-            return Ok(ApiProvider::OpenAI { api_key, model });
+        for name in PROVIDER_NAMES {
+            match Self::from_env_named(name) {
+                Err(ChatError::NoProviderError) => continue,
+                result => return result,
+            }
         }
+        Err(ChatError::NoProviderError)
+    }
 
-        // Try Ollama
-        if let Ok(host) = env::var("OLLAMA_HOST") {
-            let model = env::var("OLLAMA_MODEL").unwrap_or_else(|_| "llama2".to_string());
-            return Ok(ApiProvider::Ollama {
-                base_url: host,
-                model,
-            });
+    /// Load the provider called `name` (see [`PROVIDER_NAMES`]), with its API key
+    /// or endpoint from the environment
+    ///
+    /// Fails with [`ChatError::NoProviderError`] when the provider's key or
+    /// endpoint variable is not set.
+    pub fn from_env_named(name: &str) -> Result<Self> {
+        let var = |key: &str| env::var(key).map_err(|_| ChatError::NoProviderError);
+        let model =
+            |var: &str, default: &str| env::var(var).unwrap_or_else(|_| default.to_string());
+
+        match name.trim().to_lowercase().as_str() {
+            "openai" => Ok(ApiProvider::OpenAI {
+                api_key: var("OPENAI_API_KEY")?,
+                model: model("OPENAI_MODEL", "gpt-3.5-turbo"),
+            }),
+            "anthropic" => Ok(ApiProvider::Anthropic {
+                api_key: var("ANTHROPIC_API_KEY")?,
+                model: model("ANTHROPIC_MODEL", "claude-3-5-haiku-latest"),
+            }),
+            "gemini" => Ok(ApiProvider::Gemini {
+                api_key: var("GEMINI_API_KEY")?,
+                model: model("GEMINI_MODEL", "gemini-1.5-flash"),
+            }),
+            "ollama" => Self::at_url(name, &var("OLLAMA_HOST")?),
+            "custom" => Self::at_url(name, &var("LLM_API_URL")?),
+            other => Err(ChatError::InvalidInput(format!(
+                "Unknown chat provider '{}' (expected {})",
+                other,
+                PROVIDER_NAMES.join(", ")
+            ))),
         }
+    }
 
-        // Try custom provider
-        if let Ok(base_url) = env::var("LLM_API_URL") {
-            let api_key = env::var("LLM_API_KEY").ok();
-            let model = env::var("LLM_MODEL").unwrap_or_else(|_| "default".to_string());
-            return Ok(ApiProvider::Custom {
-                base_url,
-                api_key,
-                model,
-            });
+    /// The `ollama` or `custom` provider served at `url`, with its model (and
+    /// API key) from the environment
+    pub fn at_url(name: &str, url: &str) -> Result<Self> {
+        let model =
+            |var: &str, default: &str| env::var(var).unwrap_or_else(|_| default.to_string());
+
+        match name.trim().to_lowercase().as_str() {
+            "ollama" => Ok(ApiProvider::Ollama {
+                base_url: url.to_string(),
+                model: model("OLLAMA_MODEL", "llama2"),
+            }),
+            "custom" => Ok(ApiProvider::Custom {
+                base_url: url.to_string(),
+                api_key: env::var("LLM_API_KEY").ok(),
+                model: model("LLM_MODEL", "default"),
+            }),
+            other => Err(ChatError::InvalidInput(format!(
+                "Chat provider '{}' does not take a URL (only ollama and custom do)",
+                other
+            ))),
         }
+    }
 
-        Err(ChatError::NoProviderError)
+    /// The same provider, asking for `model`
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        match &mut self {
+            ApiProvider::OpenAI { model: m, .. }
+            | ApiProvider::Anthropic { model: m, .. }
+            | ApiProvider::Gemini { model: m, .. }
+            | ApiProvider::Ollama { model: m, .. }
+            | ApiProvider::Custom { model: m, .. } => *m = model.into(),
+        }
+        self
+    }
+
+    /// Short provider identifier ("openai", "anthropic", "gemini", "ollama", "custom")
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiProvider::OpenAI { .. } => "openai",
+            ApiProvider::Anthropic { .. } => "anthropic",
+            ApiProvider::Gemini { .. } => "gemini",
+            ApiProvider::Ollama { .. } => "ollama",
+            ApiProvider::Custom { .. } => "custom",
+        }
+    }
+
+    /// Whether requests stay on this machine: an ollama or custom endpoint on
+    /// `localhost` or a loopback address
+    pub fn is_local(&self) -> bool {
+        let (ApiProvider::Ollama { base_url, .. } | ApiProvider::Custom { base_url, .. }) = self
+        else {
+            return false;
+        };
+        let Ok(url) = reqwest::Url::parse(base_url) else {
+            return false;
+        };
+        match url.host_str() {
+            Some("localhost") => true,
+            Some(host) => host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback()),
+            None => false,
+        }
     }
 
     pub fn model_name(&self) -> &str {
         match self {
             ApiProvider::OpenAI { model, .. } => model,
+            ApiProvider::Anthropic { model, .. } => model,
+            ApiProvider::Gemini { model, .. } => model,
             ApiProvider::Ollama { model, .. } => model,
             ApiProvider::Custom { model, .. } => model,
         }
     }
+
+    /// Whether requests using `feature` can be sent to this provider
+    ///
+    /// Custom endpoints only promise the basic OpenAI-compatible chat,
+    /// streaming and embeddings routes; `response_format` is not part of that.
+    /// Anthropic has neither an embeddings API nor a JSON mode.
+    pub fn supports(&self, feature: Feature) -> bool {
+        match (self, feature) {
+            #[cfg(feature = "vision")]
+            (ApiProvider::Gemini { .. } | ApiProvider::Ollama { .. }, Feature::ImageUrls) => false,
+            #[cfg(feature = "vision")]
+            (ApiProvider::Anthropic { .. }, Feature::ImageUrls) => true,
+            (
+                ApiProvider::OpenAI { .. }
+                | ApiProvider::Gemini { .. }
+                | ApiProvider::Ollama { .. },
+                _,
+            ) => true,
+            (ApiProvider::Anthropic { .. }, Feature::Streaming) => true,
+            (ApiProvider::Anthropic { .. }, _) => false,
+            (ApiProvider::Custom { .. }, Feature::JsonMode) => false,
+            (ApiProvider::Custom { .. }, _) => true,
+        }
+    }
+
+    /// Fail with [`ChatError::UnsupportedFeature`] unless `feature` is supported
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(ChatError::UnsupportedFeature {
+                provider: self.name(),
+                feature,
+            })
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 struct OpenAIRequest {
     model: String,
-    messages: Vec<Message>,
+    messages: Vec<OpenAIMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+/// A message in the OpenAI format, whose content is a list of parts when it
+/// carries images
+#[derive(Debug, Serialize)]
+struct OpenAIMessage {
+    role: Role,
+    content: OpenAIContent,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    #[cfg(feature = "vision")]
+    Parts(Vec<OpenAIPart>),
+}
+
+#[cfg(feature = "vision")]
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+/// Inline images are sent as `data:` URLs
+#[cfg(feature = "vision")]
+#[derive(Debug, Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+/// `messages` in the OpenAI format, also used by custom providers
+fn openai_messages(messages: &[Message]) -> Vec<OpenAIMessage> {
+    messages
+        .iter()
+        .map(|m| {
+            #[cfg(feature = "vision")]
+            if !m.images.is_empty() {
+                let text = OpenAIPart::Text {
+                    text: m.content.clone(),
+                };
+                let images = m.images.iter().map(|image| OpenAIPart::ImageUrl {
+                    image_url: ImageUrl {
+                        url: image.to_url(),
+                    },
+                });
+                return OpenAIMessage {
+                    role: m.role.clone(),
+                    content: OpenAIContent::Parts(std::iter::once(text).chain(images).collect()),
+                };
+            }
+            OpenAIMessage {
+                role: m.role.clone(),
+                content: OpenAIContent::Text(m.content.clone()),
+            }
+        })
+        .collect()
+}
+
+/// OpenAI `response_format`; only `json_object` is used
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+impl ResponseFormat {
+    fn json(json_mode: bool) -> Option<Self> {
+        json_mode.then_some(Self {
+            kind: "json_object",
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -97,8 +364,66 @@ struct ResponseMessage {
 #[derive(Debug, Serialize)]
 struct OllamaRequest {
     model: String,
-    messages: Vec<Message>,
+    messages: Vec<OllamaMessage>,
     stream: bool,
+    /// `"json"` constrains the reply to valid JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    #[serde(skip_serializing_if = "OllamaOptions::is_empty")]
+    options: OllamaOptions,
+}
+
+/// A message in the Ollama format, with its images as base64 strings
+#[derive(Debug, Serialize)]
+struct OllamaMessage {
+    role: Role,
+    content: String,
+    #[cfg(feature = "vision")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+/// `messages` in the Ollama format; images given by URL are rejected beforehand
+/// (see [`Feature::ImageUrls`])
+fn ollama_messages(messages: &[Message]) -> Vec<OllamaMessage> {
+    messages
+        .iter()
+        .map(|m| OllamaMessage {
+            role: m.role.clone(),
+            content: m.content.clone(),
+            #[cfg(feature = "vision")]
+            images: m
+                .images
+                .iter()
+                .filter_map(|image| match image {
+                    Image::Base64 { data, .. } => Some(data.clone()),
+                    Image::Url { .. } => None,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Ollama takes sampling settings in a nested `options` object
+#[derive(Debug, Default, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+impl OllamaOptions {
+    fn new(temperature: Option<f32>, max_tokens: Option<u32>) -> Self {
+        Self {
+            temperature,
+            num_predict: max_tokens,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none() && self.num_predict.is_none()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -106,32 +431,123 @@ struct OllamaResponse {
     message: ResponseMessage,
 }
 
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingResponse {
+    data: Vec<OpenAIEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbedding {
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbeddingResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Clone)]
 pub struct ApiClient {
     provider: ApiProvider,
     client: Client,
+    request_timeout: Duration,
+    deadline: Option<Instant>,
+    json_mode: bool,
+    cassette: Option<Arc<Cassette>>,
 }
 
 impl ApiClient {
     pub fn new(provider: ApiProvider) -> Result<Self> {
-        // Get timeout values from environment variables or use defaults
-        let request_timeout = env::var("HTTP_REQUEST_TIMEOUT_SECS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
-
-        let connect_timeout = env::var("HTTP_CONNECT_TIMEOUT_SECS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
-
-        // Create HTTP client with configurable timeouts to prevent hanging requests
+        Self::with_timeouts(provider, HttpTimeouts::default())
+    }
+
+    /// Client for `provider` whose requests give up after `timeouts`
+    pub fn with_timeouts(provider: ApiProvider, timeouts: HttpTimeouts) -> Result<Self> {
+        // Timeouts keep a provider that stops answering from hanging the caller
         let client = Client::builder()
-            .timeout(Duration::from_secs(request_timeout))
-            .connect_timeout(Duration::from_secs(connect_timeout))
+            .timeout(timeouts.request)
+            .connect_timeout(timeouts.connect)
             .build()
             .map_err(|e| ChatError::ApiError(format!("Failed to build HTTP client: {}", e)))?;
+        let cassette = lib_cassette::global().map_err(ChatError::ApiError)?;
+
+        Ok(Self {
+            provider,
+            client,
+            request_timeout: timeouts.request,
+            deadline: None,
+            json_mode: false,
+            cassette,
+        })
+    }
+
+    /// Record requests into, or replay them from, `cassette` instead of the one
+    /// named by `EIDOS_RECORD` / `EIDOS_REPLAY`
+    pub fn set_cassette(&mut self, cassette: Option<Arc<Cassette>>) {
+        self.cassette = cassette;
+    }
+
+    /// Finish every request by `deadline`, even when the configured HTTP
+    /// timeout would allow longer
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Ask for replies that are a single JSON object
+    ///
+    /// Requests fail with [`ChatError::UnsupportedFeature`] before anything is
+    /// sent when the provider cannot do this.
+    pub fn set_json_mode(&mut self, json_mode: bool) {
+        self.json_mode = json_mode;
+    }
 
-        Ok(Self { provider, client })
+    /// Check the provider supports every feature the next chat request uses
+    fn check_features(&self, messages: &[Message]) -> Result<()> {
+        if self.json_mode {
+            self.provider.require(Feature::JsonMode)?;
+        }
+        #[cfg(feature = "vision")]
+        if messages
+            .iter()
+            .flat_map(|m| &m.images)
+            .any(|image| matches!(image, Image::Url { .. }))
+        {
+            self.provider.require(Feature::ImageUrls)?;
+        }
+        #[cfg(not(feature = "vision"))]
+        let _ = messages;
+        Ok(())
+    }
+
+    /// Start a POST request whose timeout is the smaller of the HTTP timeout
+    /// and the time left until the deadline
+    fn post(&self, url: impl IntoUrl) -> Result<RequestBuilder> {
+        let timeout = match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(ChatError::DeadlineExceeded);
+                }
+                remaining.min(self.request_timeout)
+            }
+            None => self.request_timeout,
+        };
+        Ok(self.client.post(url).timeout(timeout))
+    }
+
+    /// Send `request`, through the cassette when one is set
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        Ok(lib_cassette::send(self.cassette.as_deref(), request).await?)
     }
 
     pub fn from_env() -> Result<Self> {
@@ -139,19 +555,42 @@ impl ApiClient {
         Self::new(provider)
     }
 
+    /// The provider this client talks to
+    pub fn provider(&self) -> &ApiProvider {
+        &self.provider
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "http",
+            skip_all,
+            fields(provider = self.provider.name(), op = "chat")
+        )
+    )]
     pub async fn send_message(
         &self,
         messages: &[Message],
         temperature: Option<f32>,
         max_tokens: Option<u32>,
     ) -> Result<String> {
+        self.check_features(messages)?;
         match &self.provider {
             ApiProvider::OpenAI { api_key, model } => {
                 self.send_openai_request(api_key, model, messages, temperature, max_tokens)
                     .await
             }
+            ApiProvider::Anthropic { api_key, model } => {
+                self.send_anthropic_request(api_key, model, messages, temperature, max_tokens)
+                    .await
+            }
+            ApiProvider::Gemini { api_key, model } => {
+                self.send_gemini_request(api_key, model, messages, temperature, max_tokens)
+                    .await
+            }
             ApiProvider::Ollama { base_url, model } => {
-                self.send_ollama_request(base_url, model, messages).await
+                self.send_ollama_request(base_url, model, messages, temperature, max_tokens)
+                    .await
             }
             ApiProvider::Custom {
                 base_url,
@@ -171,6 +610,211 @@ impl ApiClient {
         }
     }
 
+    /// Send messages and receive the reply incrementally
+    ///
+    /// OpenAI-compatible providers stream Server-Sent Events, Ollama streams
+    /// newline-delimited JSON. The returned stream yields text fragments in order;
+    /// concatenating them gives the full reply.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "http",
+            skip_all,
+            fields(provider = self.provider.name(), op = "chat_stream")
+        )
+    )]
+    pub async fn send_message_stream(
+        &self,
+        messages: &[Message],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Result<TokenStream> {
+        self.provider.require(Feature::Streaming)?;
+        self.check_features(messages)?;
+        let (request, format) = match &self.provider {
+            ApiProvider::OpenAI { api_key, model } => {
+                let body = OpenAIRequest {
+                    model: model.to_string(),
+                    messages: openai_messages(messages),
+                    temperature,
+                    max_tokens,
+                    stream: Some(true),
+                    response_format: ResponseFormat::json(self.json_mode),
+                };
+                let request = self
+                    .post("https://api.openai.com/v1/chat/completions")?
+                    .header("Authorization", format!("Bearer {}", api_key))
+                    .json(&body);
+                (request, StreamFormat::Sse)
+            }
+            ApiProvider::Anthropic { api_key, model } => {
+                let body = AnthropicRequest::new(model, messages, temperature, max_tokens, true);
+                let request = self.anthropic_post(api_key)?.json(&body);
+                (request, StreamFormat::Anthropic)
+            }
+            ApiProvider::Gemini { api_key, model } => {
+                let body = GeminiRequest::new(messages, temperature, max_tokens, self.json_mode);
+                let request = self
+                    .post(gemini::generate_url(model, true))?
+                    .header("x-goog-api-key", api_key)
+                    .json(&body);
+                (request, StreamFormat::Gemini)
+            }
+            ApiProvider::Ollama { base_url, model } => {
+                let body = OllamaRequest {
+                    model: model.to_string(),
+                    messages: ollama_messages(messages),
+                    stream: true,
+                    format: self.json_mode.then_some("json"),
+                    options: OllamaOptions::new(temperature, max_tokens),
+                };
+                let request = self.post(format!("{}/api/chat", base_url))?.json(&body);
+                (request, StreamFormat::Ndjson)
+            }
+            ApiProvider::Custom {
+                base_url,
+                api_key,
+                model,
+            } => {
+                let body = OpenAIRequest {
+                    model: model.to_string(),
+                    messages: openai_messages(messages),
+                    temperature,
+                    max_tokens,
+                    stream: Some(true),
+                    response_format: ResponseFormat::json(self.json_mode),
+                };
+                let mut request = self
+                    .post(format!("{}/chat/completions", base_url))?
+                    .json(&body);
+                if let Some(key) = api_key {
+                    request = request.header("Authorization", format!("Bearer {}", key));
+                }
+                (request, StreamFormat::Sse)
+            }
+        };
+
+        self.open_stream(request, format).await
+    }
+
+    async fn open_stream(
+        &self,
+        request: RequestBuilder,
+        format: StreamFormat,
+    ) -> Result<TokenStream> {
+        let response = self
+            .send(request.header("Content-Type", "application/json"))
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ChatError::ApiError(format!(
+                "Streaming request failed with status {}: {}",
+                status, error_text
+            )));
+        }
+
+        Ok(token_stream(response.bytes_stream(), format))
+    }
+
+    /// Default embedding model for the configured provider
+    ///
+    /// Overridable with OPENAI_EMBEDDING_MODEL, OLLAMA_EMBEDDING_MODEL or LLM_EMBEDDING_MODEL.
+    pub fn default_embedding_model(&self) -> String {
+        let (var, default) = match &self.provider {
+            ApiProvider::OpenAI { .. } => ("OPENAI_EMBEDDING_MODEL", "text-embedding-3-small"),
+            ApiProvider::Gemini { .. } => ("GEMINI_EMBEDDING_MODEL", "text-embedding-004"),
+            // No embeddings API; `embed` fails with `UnsupportedFeature`
+            ApiProvider::Anthropic { .. } => ("ANTHROPIC_EMBEDDING_MODEL", "none"),
+            ApiProvider::Ollama { .. } => ("OLLAMA_EMBEDDING_MODEL", "nomic-embed-text"),
+            ApiProvider::Custom { .. } => ("LLM_EMBEDDING_MODEL", "default"),
+        };
+        env::var(var).unwrap_or_else(|_| default.to_string())
+    }
+
+    /// Compute embeddings for a batch of texts, in input order
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "http",
+            skip_all,
+            fields(provider = self.provider.name(), op = "embed")
+        )
+    )]
+    pub async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.provider.require(Feature::Embeddings)?;
+        let body = EmbeddingRequest {
+            model,
+            input: inputs,
+        };
+
+        let request = match &self.provider {
+            ApiProvider::OpenAI { api_key, .. } => self
+                .post("https://api.openai.com/v1/embeddings")?
+                .header("Authorization", format!("Bearer {}", api_key)),
+            ApiProvider::Ollama { base_url, .. } => self.post(format!("{}/api/embed", base_url))?,
+            ApiProvider::Gemini { api_key, .. } => {
+                return self.embed_gemini(api_key, model, inputs).await;
+            }
+            ApiProvider::Anthropic { .. } => {
+                return Err(ChatError::UnsupportedFeature {
+                    provider: self.provider.name(),
+                    feature: Feature::Embeddings,
+                })
+            }
+            ApiProvider::Custom {
+                base_url, api_key, ..
+            } => {
+                let request = self.post(format!("{}/embeddings", base_url))?;
+                match api_key {
+                    Some(key) => request.header("Authorization", format!("Bearer {}", key)),
+                    None => request,
+                }
+            }
+        };
+
+        let response = self
+            .send(
+                request
+                    .header("Content-Type", "application/json")
+                    .json(&body),
+            )
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ChatError::ApiError(format!(
+                "Embedding request failed with status {}: {}",
+                status, error_text
+            )));
+        }
+
+        let embeddings = match &self.provider {
+            ApiProvider::Ollama { .. } => {
+                response.json::<OllamaEmbeddingResponse>().await?.embeddings
+            }
+            _ => response
+                .json::<OpenAIEmbeddingResponse>()
+                .await?
+                .data
+                .into_iter()
+                .map(|e| e.embedding)
+                .collect(),
+        };
+
+        if embeddings.len() != inputs.len() {
+            return Err(ChatError::InvalidResponse(format!(
+                "Expected {} embeddings, got {}",
+                inputs.len(),
+                embeddings.len()
+            )));
+        }
+
+        Ok(embeddings)
+    }
+
     async fn send_openai_request(
         &self,
         api_key: &str,
@@ -183,18 +827,20 @@ impl ApiClient {
 
         let request_body = OpenAIRequest {
             model: model.to_string(),
-            messages: messages.to_vec(),
+            messages: openai_messages(messages),
             temperature,
             max_tokens,
+            stream: None,
+            response_format: ResponseFormat::json(self.json_mode),
         };
 
         let response = self
-            .client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
+            .send(
+                self.post(url)?
+                    .header("Authorization", format!("Bearer {}", api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request_body),
+            )
             .await?;
 
         if !response.status().is_success() {
@@ -215,26 +861,115 @@ impl ApiClient {
             .ok_or_else(|| ChatError::InvalidResponse("No choices in response".to_string()))
     }
 
+    /// POST to the Messages API with Anthropic's authentication headers
+    fn anthropic_post(&self, api_key: &str) -> Result<RequestBuilder> {
+        Ok(self
+            .post(anthropic::API_URL)?
+            .header("x-api-key", api_key)
+            .header("anthropic-version", anthropic::API_VERSION))
+    }
+
+    async fn send_anthropic_request(
+        &self,
+        api_key: &str,
+        model: &str,
+        messages: &[Message],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Result<String> {
+        let request_body = AnthropicRequest::new(model, messages, temperature, max_tokens, false);
+
+        let response = self
+            .send(
+                self.anthropic_post(api_key)?
+                    .header("Content-Type", "application/json")
+                    .json(&request_body),
+            )
+            .await?;
+        let response = check_status("Anthropic", response).await?;
+
+        let response_data: AnthropicResponse = response.json().await?;
+        response_data.text()
+    }
+
+    async fn send_gemini_request(
+        &self,
+        api_key: &str,
+        model: &str,
+        messages: &[Message],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Result<String> {
+        let request_body = GeminiRequest::new(messages, temperature, max_tokens, self.json_mode);
+
+        let response = self
+            .send(
+                self.post(gemini::generate_url(model, false))?
+                    .header("x-goog-api-key", api_key)
+                    .header("Content-Type", "application/json")
+                    .json(&request_body),
+            )
+            .await?;
+        let response = check_status("Gemini", response).await?;
+
+        let response_data: GeminiResponse = response.json().await?;
+        response_data.text()
+    }
+
+    async fn embed_gemini(
+        &self,
+        api_key: &str,
+        model: &str,
+        inputs: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        let response = self
+            .send(
+                self.post(gemini::embed_url(model))?
+                    .header("x-goog-api-key", api_key)
+                    .header("Content-Type", "application/json")
+                    .json(&gemini::EmbedRequest::new(model, inputs)),
+            )
+            .await?;
+        let response = check_status("Gemini", response).await?;
+
+        let embeddings = response
+            .json::<gemini::EmbedResponse>()
+            .await?
+            .into_vectors();
+        if embeddings.len() != inputs.len() {
+            return Err(ChatError::InvalidResponse(format!(
+                "Expected {} embeddings, got {}",
+                inputs.len(),
+                embeddings.len()
+            )));
+        }
+        Ok(embeddings)
+    }
+
     async fn send_ollama_request(
         &self,
         base_url: &str,
         model: &str,
         messages: &[Message],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
     ) -> Result<String> {
         let url = format!("{}/api/chat", base_url);
 
         let request_body = OllamaRequest {
             model: model.to_string(),
-            messages: messages.to_vec(),
+            messages: ollama_messages(messages),
             stream: false,
+            format: self.json_mode.then_some("json"),
+            options: OllamaOptions::new(temperature, max_tokens),
         };
 
         let response = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
+            .send(
+                self.post(&url)?
+                    .header("Content-Type", "application/json")
+                    .json(&request_body),
+            )
             .await?;
 
         if !response.status().is_success() {
@@ -263,21 +998,20 @@ impl ApiClient {
 
         let request_body = OpenAIRequest {
             model: model.to_string(),
-            messages: messages.to_vec(),
+            messages: openai_messages(messages),
             temperature,
             max_tokens,
+            stream: None,
+            response_format: ResponseFormat::json(self.json_mode),
         };
 
-        let mut request = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json");
+        let mut request = self.post(&url)?.header("Content-Type", "application/json");
 
         if let Some(key) = api_key {
             request = request.header("Authorization", format!("Bearer {}", key));
         }
 
-        let response = request.json(&request_body).send().await?;
+        let response = self.send(request.json(&request_body)).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -297,3 +1031,257 @@ impl ApiClient {
             .ok_or_else(|| ChatError::InvalidResponse("No choices in response".to_string()))
     }
 }
+
+/// Pass successful responses through and turn failures into a `ChatError`
+async fn check_status(provider: &str, response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(status_error(provider, status, &body))
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    message: String,
+}
+
+/// Error for a failed request, using the provider's `{"error": {"message"}}` text
+///
+/// Rejected keys and rate limits get their own variants. Gemini reports an
+/// invalid key as a 400, so its message is checked too.
+fn status_error(provider: &str, status: StatusCode, body: &str) -> ChatError {
+    let message = serde_json::from_str::<ErrorBody>(body)
+        .map(|e| e.error.message)
+        .unwrap_or_else(|_| body.to_string());
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ChatError::AuthenticationError,
+        StatusCode::TOO_MANY_REQUESTS => ChatError::RateLimitError,
+        StatusCode::BAD_REQUEST if message.contains("API key not valid") => {
+            ChatError::AuthenticationError
+        }
+        _ => ChatError::ApiError(format!(
+            "{} API request failed with status {}: {}",
+            provider, status, message
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_expired_deadline_fails_before_sending() {
+        let mut client = ApiClient::new(ApiProvider::Ollama {
+            // Nothing listens here; an attempted request would fail differently
+            base_url: "http://127.0.0.1:9".to_string(),
+            model: "llama2".to_string(),
+        })
+        .unwrap();
+        client.set_deadline(Some(Instant::now()));
+
+        let messages = vec![Message::user("hi")];
+        assert!(matches!(
+            client.send_message(&messages, None, None).await,
+            Err(ChatError::DeadlineExceeded)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_feature_fails_before_sending() {
+        let mut client = ApiClient::new(ApiProvider::Custom {
+            base_url: "http://127.0.0.1:9".to_string(),
+            api_key: None,
+            model: "default".to_string(),
+        })
+        .unwrap();
+        client.set_json_mode(true);
+
+        let messages = vec![Message::user("hi")];
+        let err = client
+            .send_message(&messages, None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ChatError::UnsupportedFeature {
+                provider: "custom",
+                feature: Feature::JsonMode
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "The custom provider does not support JSON mode"
+        );
+        assert!(client
+            .send_message_stream(&messages, None, None)
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "vision")]
+    #[tokio::test]
+    async fn test_image_messages_per_provider() {
+        let screenshot = Image::from_bytes(b"\x89PNG\r\n\x1a\n").unwrap();
+        let messages = vec![
+            Message::system("Be brief."),
+            Message::user("What does this error mean?").with_images(vec![screenshot]),
+        ];
+
+        let json = serde_json::to_value(openai_messages(&messages)).unwrap();
+        assert_eq!(json[0]["content"], "Be brief.");
+        assert_eq!(json[1]["content"][0]["type"], "text");
+        assert_eq!(json[1]["content"][1]["type"], "image_url");
+        assert_eq!(
+            json[1]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+
+        let json = serde_json::to_value(ollama_messages(&messages)).unwrap();
+        assert!(json[0].get("images").is_none());
+        assert_eq!(json[1]["content"], "What does this error mean?");
+        assert_eq!(json[1]["images"][0], "iVBORw0KGgo=");
+
+        // Ollama cannot fetch images itself
+        let client = ApiClient::new(ApiProvider::Ollama {
+            base_url: "http://127.0.0.1:9".to_string(),
+            model: "llava".to_string(),
+        })
+        .unwrap();
+        let linked = vec![Message::user("And this one?")
+            .with_images(vec![Image::load("https://example.com/error.png").unwrap()])];
+        let err = client.send_message(&linked, None, None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            ChatError::UnsupportedFeature {
+                provider: "ollama",
+                feature: Feature::ImageUrls
+            }
+        ));
+    }
+
+    #[test]
+    fn test_request_messages_leave_out_metadata() {
+        let messages = vec![Message::assistant("ls -la")
+            .stamped()
+            .with_tokens(3)
+            .with_model("ollama", "llama3")
+            .with_latency(std::time::Duration::from_millis(900))];
+        let expected = r#"[{"role":"assistant","content":"ls -la"}]"#;
+        assert_eq!(
+            serde_json::to_string(&openai_messages(&messages)).unwrap(),
+            expected
+        );
+        assert_eq!(
+            serde_json::to_string(&ollama_messages(&messages)).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_status_error_mapping() {
+        assert!(matches!(
+            status_error("Anthropic", StatusCode::UNAUTHORIZED, ""),
+            ChatError::AuthenticationError
+        ));
+        assert!(matches!(
+            status_error(
+                "Gemini",
+                StatusCode::BAD_REQUEST,
+                r#"{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT"}}"#
+            ),
+            ChatError::AuthenticationError
+        ));
+        assert!(matches!(
+            status_error("Anthropic", StatusCode::TOO_MANY_REQUESTS, ""),
+            ChatError::RateLimitError
+        ));
+        let err = status_error(
+            "Anthropic",
+            StatusCode::BAD_REQUEST,
+            r#"{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens: required"}}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            "API error: Anthropic API request failed with status 400 Bad Request: max_tokens: required"
+        );
+    }
+
+    #[test]
+    fn test_provider_features() {
+        let ollama = ApiProvider::Ollama {
+            base_url: "http://localhost:11434".to_string(),
+            model: "llama2".to_string(),
+        };
+        assert!(ollama.supports(Feature::JsonMode));
+        assert!(ollama.require(Feature::Embeddings).is_ok());
+
+        let anthropic = ApiProvider::Anthropic {
+            api_key: "key".to_string(),
+            model: "claude".to_string(),
+        };
+        assert!(anthropic.supports(Feature::Streaming));
+        assert!(!anthropic.supports(Feature::Embeddings));
+        assert!(!anthropic.supports(Feature::JsonMode));
+    }
+
+    #[test]
+    fn test_named_provider_and_model() {
+        let ollama = ApiProvider::Ollama {
+            base_url: "http://localhost:11434".to_string(),
+            model: "llama2".to_string(),
+        }
+        .with_model("mistral");
+        assert_eq!(ollama.model_name(), "mistral");
+
+        let err = ApiProvider::from_env_named("bard").unwrap_err();
+        assert!(err.to_string().contains("Unknown chat provider 'bard'"));
+
+        let custom = ApiProvider::at_url("Custom", "http://localhost:8080/v1").unwrap();
+        assert!(matches!(
+            custom,
+            ApiProvider::Custom { ref base_url, .. } if base_url == "http://localhost:8080/v1"
+        ));
+        let err = ApiProvider::at_url("openai", "http://localhost:8080").unwrap_err();
+        assert!(err.to_string().contains("does not take a URL"));
+    }
+
+    #[test]
+    fn test_local_providers() {
+        for url in [
+            "http://localhost:11434",
+            "http://127.0.0.1:8080/v1",
+            "http://[::1]:8080",
+        ] {
+            assert!(
+                ApiProvider::at_url("custom", url).unwrap().is_local(),
+                "{}",
+                url
+            );
+        }
+        for url in [
+            "https://llm.example.com/v1",
+            "http://192.168.1.5:11434",
+            "not a url",
+        ] {
+            assert!(
+                !ApiProvider::at_url("ollama", url).unwrap().is_local(),
+                "{}",
+                url
+            );
+        }
+        let openai = ApiProvider::OpenAI {
+            api_key: "key".to_string(),
+            model: "gpt-4o".to_string(),
+        };
+        assert!(!openai.is_local());
+    }
+}
@@ -1,4 +1,6 @@
 // lib_chat/src/error.rs
+use crate::api::Feature;
+use lib_errors::{EidosError, ErrorCode};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -29,6 +31,55 @@ pub enum ChatError {
 
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    #[error("Request deadline exceeded")]
+    DeadlineExceeded,
+
+    #[error("The {provider} provider does not support {feature}")]
+    UnsupportedFeature {
+        provider: &'static str,
+        feature: Feature,
+    },
+
+    #[cfg(feature = "translate")]
+    #[error("Translation failed: {0}")]
+    Translation(#[from] lib_translate::TranslateError),
+}
+
+impl EidosError for ChatError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ChatError::RequestError(e) if e.is_timeout() => ErrorCode::Timeout,
+            ChatError::RequestError(_) | ChatError::ApiError(_) => ErrorCode::Network,
+            ChatError::JsonError(_) | ChatError::InvalidResponse(_) => ErrorCode::Network,
+            ChatError::AuthenticationError => ErrorCode::Auth,
+            ChatError::RateLimitError => ErrorCode::RateLimit,
+            ChatError::NoProviderError => ErrorCode::NotConfigured,
+            ChatError::EnvError(_) => ErrorCode::Config,
+            ChatError::InvalidInput(_) => ErrorCode::InvalidInput,
+            ChatError::DeadlineExceeded => ErrorCode::Timeout,
+            ChatError::UnsupportedFeature { .. } => ErrorCode::Unsupported,
+            #[cfg(feature = "translate")]
+            ChatError::Translation(e) => e.code(),
+        }
+    }
+}
+
+impl ChatError {
+    /// Whether the provider could not be reached at all (connection refused,
+    /// connect timeout), as opposed to answering with an error
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, ChatError::RequestError(e) if e.is_connect())
+    }
+}
+
+impl From<lib_cassette::CassetteError> for ChatError {
+    fn from(e: lib_cassette::CassetteError) -> Self {
+        match e {
+            lib_cassette::CassetteError::Request(e) => ChatError::RequestError(e),
+            e => ChatError::ApiError(e.to_string()),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ChatError>;
@@ -0,0 +1,289 @@
+// lib_chat/src/export.rs
+// Saved sessions rendered for reading or sharing
+//
+// An export is the whole conversation in one document: a header with the title,
+// when the session was created, the model and where it was imported from, then
+// every message with its role and, when known, when it was written, the model
+// that wrote it and how long the reply took. Markdown
+// keeps message contents as written, since replies are Markdown already; HTML
+// escapes them and keeps their line breaks; JSON is the session as stored, for
+// other tools. Times are shown in UTC so an export reads the same everywhere.
+
+use crate::history::{Message, Role};
+use crate::session::Session;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Document format of a transcript export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    #[default]
+    Markdown,
+    Json,
+    Html,
+}
+
+impl FromStr for TranscriptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Ok(TranscriptFormat::Markdown),
+            "json" => Ok(TranscriptFormat::Json),
+            "html" => Ok(TranscriptFormat::Html),
+            other => Err(format!(
+                "Unknown export format '{}' (expected markdown, json or html)",
+                other
+            )),
+        }
+    }
+}
+
+/// `session` as a document in `format`
+pub fn export(session: &Session, format: TranscriptFormat) -> String {
+    match format {
+        TranscriptFormat::Markdown => markdown(session),
+        TranscriptFormat::Json => {
+            serde_json::to_string_pretty(session).expect("sessions serialize to JSON")
+        }
+        TranscriptFormat::Html => html(session),
+    }
+}
+
+fn markdown(session: &Session) -> String {
+    let mut doc = format!("# {}\n\n", session.title);
+    for (label, value) in metadata(session) {
+        let _ = writeln!(doc, "- {}: {}", label, value);
+    }
+    for message in &session.messages {
+        let _ = write!(doc, "\n## {}", role_name(&message.role));
+        if let Some(time) = message.timestamp {
+            let _ = write!(doc, " · {}", format_time(time));
+        }
+        for detail in details(message) {
+            let _ = write!(doc, " · {}", detail);
+        }
+        let _ = write!(doc, "\n\n{}\n", message.content.trim_end());
+        for url in image_urls(message) {
+            let _ = write!(doc, "\n![image]({})\n", url);
+        }
+    }
+    doc
+}
+
+fn html(session: &Session) -> String {
+    let title = escape_html(&session.title);
+    let mut doc = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; max-width: 48em; margin: 2em auto; }}\n\
+         .message {{ border-top: 1px solid #ccc; }}\n\
+         .content {{ white-space: pre-wrap; }}\n\
+         time, .meta {{ color: #666; font-size: 0.8em; margin-left: 0.5em; }}\n\
+         img {{ max-width: 100%; }}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n<ul>\n",
+        title, title
+    );
+    for (label, value) in metadata(session) {
+        let _ = writeln!(doc, "<li>{}: {}</li>", label, escape_html(&value));
+    }
+    doc.push_str("</ul>\n");
+    for message in &session.messages {
+        let role = role_name(&message.role);
+        let _ = write!(
+            doc,
+            "<section class=\"message {}\">\n<h2>{}",
+            role.to_lowercase(),
+            role
+        );
+        if let Some(time) = message.timestamp {
+            let _ = write!(
+                doc,
+                "<time datetime=\"{}\">{}</time>",
+                format_iso(time),
+                format_time(time)
+            );
+        }
+        let details = details(message);
+        if !details.is_empty() {
+            let _ = write!(
+                doc,
+                "<span class=\"meta\">{}</span>",
+                escape_html(&details.join(" · "))
+            );
+        }
+        let _ = writeln!(
+            doc,
+            "</h2>\n<div class=\"content\">{}</div>",
+            escape_html(message.content.trim_end())
+        );
+        for url in image_urls(message) {
+            let _ = writeln!(doc, "<img src=\"{}\" alt=\"image\">", escape_html(&url));
+        }
+        doc.push_str("</section>\n");
+    }
+    doc.push_str("</body>\n</html>\n");
+    doc
+}
+
+/// Header lines shared by Markdown and HTML
+fn metadata(session: &Session) -> Vec<(&'static str, String)> {
+    let mut lines = vec![("Session", session.name.clone())];
+    if session.created > 0 {
+        lines.push(("Created", format_time(session.created)));
+    }
+    if let Some(model) = &session.model {
+        lines.push(("Model", model.clone()));
+    }
+    if let Some(origin) = &session.origin {
+        lines.push(("Imported from", origin.clone()));
+    }
+    lines.push(("Messages", session.messages.len().to_string()));
+    lines
+}
+
+/// What is known about how a message was written, besides its time
+fn details(message: &Message) -> Vec<String> {
+    let mut details = Vec::new();
+    match (&message.model, &message.provider) {
+        (Some(model), Some(provider)) => details.push(format!("{} via {}", model, provider)),
+        (Some(name), None) | (None, Some(name)) => details.push(name.clone()),
+        (None, None) => {}
+    }
+    if let Some(latency) = message.latency_ms {
+        details.push(format!("{:.1} s", latency as f64 / 1000.0));
+    }
+    if let Some(tokens) = message.tokens {
+        details.push(format!("{} tokens", tokens));
+    }
+    details
+}
+
+fn role_name(role: &Role) -> &'static str {
+    match role {
+        Role::System => "System",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+    }
+}
+
+#[cfg(feature = "vision")]
+fn image_urls(message: &Message) -> Vec<String> {
+    message.images.iter().map(|image| image.to_url()).collect()
+}
+
+#[cfg(not(feature = "vision"))]
+fn image_urls(_message: &Message) -> Vec<String> {
+    Vec::new()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `secs` since the Unix epoch as `2023-11-14 22:13 UTC`
+fn format_time(secs: u64) -> String {
+    let (year, month, day, hour, minute, _) = civil_time(secs);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year, month, day, hour, minute
+    )
+}
+
+/// `secs` since the Unix epoch as `2023-11-14T22:13:20Z`
+fn format_iso(secs: u64) -> String {
+    let (year, month, day, hour, minute, second) = civil_time(secs);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Date and time in UTC of `secs` since the Unix epoch, by Howard Hinnant's
+/// `civil_from_days`
+fn civil_time(secs: u64) -> (u64, u64, u64, u64, u64, u64) {
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let mut session = Session::new("Disk <usage>", 1_700_000_000);
+        session.model = Some("gpt-4o".to_string());
+        session.messages = vec![
+            Message::user("how full is my disk?").with_timestamp(1_700_000_060),
+            Message::assistant("Run `df -h` & check the **Use%** column.\n")
+                .with_model("openai", "gpt-4o")
+                .with_latency(std::time::Duration::from_millis(1250)),
+        ];
+        session
+    }
+
+    #[test]
+    fn test_export_markdown() {
+        assert_eq!(
+            export(&session(), TranscriptFormat::Markdown),
+            "# Disk <usage>\n\n\
+             - Session: disk-usage\n\
+             - Created: 2023-11-14 22:13 UTC\n\
+             - Model: gpt-4o\n\
+             - Messages: 2\n\
+             \n## User · 2023-11-14 22:14 UTC\n\nhow full is my disk?\n\
+             \n## Assistant · gpt-4o via openai · 1.2 s\n\nRun `df -h` & check the **Use%** column.\n"
+        );
+    }
+
+    #[test]
+    fn test_export_html_escapes_content() {
+        let html = export(&session(), TranscriptFormat::Html);
+        assert!(html.contains("<title>Disk &lt;usage&gt;</title>"));
+        assert!(html.contains(
+            "<h2>User<time datetime=\"2023-11-14T22:14:20Z\">2023-11-14 22:14 UTC</time></h2>"
+        ));
+        assert!(html
+            .contains("<h2>Assistant<span class=\"meta\">gpt-4o via openai · 1.2 s</span></h2>"));
+        assert!(html.contains("Run `df -h` &amp; check the **Use%** column.</div>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn test_export_json_keeps_metadata() {
+        let json: serde_json::Value =
+            serde_json::from_str(&export(&session(), TranscriptFormat::Json)).unwrap();
+        assert_eq!(json["model"], "gpt-4o");
+        assert_eq!(json["messages"][0]["timestamp"], 1_700_000_060);
+        assert!(json["messages"][1].get("timestamp").is_none());
+        assert_eq!(json["messages"][1]["latency_ms"], 1250);
+
+        assert_eq!("HTML".parse(), Ok(TranscriptFormat::Html));
+        assert!("pdf".parse::<TranscriptFormat>().is_err());
+    }
+
+    #[test]
+    fn test_civil_time() {
+        assert_eq!(civil_time(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(civil_time(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(format_iso(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+}
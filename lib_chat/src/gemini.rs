@@ -0,0 +1,349 @@
+// lib_chat/src/gemini.rs
+// Wire format of the Gemini API (generativelanguage.googleapis.com)
+//
+// Conversations are `contents` with `user` and `model` roles, each a list of
+// parts; the system prompt goes in `systemInstruction` and sampling settings in
+// `generationConfig`. Streaming uses `streamGenerateContent?alt=sse`, where every
+// event is a complete response object holding the next piece of text and the
+// stream simply ends after the last one.
+
+use crate::error::{ChatError, Result};
+use crate::history::{Message, Role};
+#[cfg(feature = "vision")]
+use crate::image::Image;
+use crate::stream::StreamEvent;
+use serde::{Deserialize, Serialize};
+
+const API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Endpoint generating a reply from `model`, streamed or in one response
+pub(crate) fn generate_url(model: &str, stream: bool) -> String {
+    let method = if stream {
+        "streamGenerateContent?alt=sse"
+    } else {
+        "generateContent"
+    };
+    format!("{}/models/{}:{}", API_BASE, model, method)
+}
+
+/// Endpoint embedding a batch of texts with `model`
+pub(crate) fn embed_url(model: &str) -> String {
+    format!("{}/models/{}:batchEmbedContents", API_BASE, model)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiRequest {
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    #[serde(skip_serializing_if = "GenerationConfig::is_empty")]
+    generation_config: GenerationConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Content {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+impl Content {
+    fn text(role: Option<&str>, text: impl Into<String>) -> Self {
+        Self {
+            role: role.map(str::to_string),
+            parts: vec![Part::text(text)],
+        }
+    }
+
+    /// Content of `message`: its text, then a part for each image, which are all
+    /// inline since image URLs are rejected beforehand
+    fn of(role: &str, message: &Message) -> Self {
+        #[allow(unused_mut)]
+        let mut content = Self::text(Some(role), message.content.clone());
+        #[cfg(feature = "vision")]
+        content
+            .parts
+            .extend(message.images.iter().filter_map(|image| match image {
+                Image::Base64 { media_type, data } => Some(Part {
+                    text: String::new(),
+                    inline_data: Some(InlineData {
+                        mime_type: media_type.clone(),
+                        data: data.clone(),
+                    }),
+                }),
+                Image::Url { .. } => None,
+            }));
+        content
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    text: String,
+    #[cfg(feature = "vision")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline_data: Option<InlineData>,
+}
+
+impl Part {
+    fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            #[cfg(feature = "vision")]
+            inline_data: None,
+        }
+    }
+}
+
+/// An image sent with the request, base64-encoded
+#[cfg(feature = "vision")]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InlineData {
+    mime_type: String,
+    data: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    /// `application/json` for JSON mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+}
+
+impl GenerationConfig {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.max_output_tokens.is_none()
+            && self.response_mime_type.is_none()
+    }
+}
+
+impl GeminiRequest {
+    /// Request for `messages`, with every system message joined into the
+    /// system instruction
+    pub(crate) fn new(
+        messages: &[Message],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+        json_mode: bool,
+    ) -> Self {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect();
+        let contents = messages
+            .iter()
+            .filter_map(|m| {
+                let role = match m.role {
+                    Role::System => return None,
+                    Role::User => "user",
+                    Role::Assistant => "model",
+                };
+                Some(Content::of(role, m))
+            })
+            .collect();
+
+        Self {
+            contents,
+            system_instruction: (!system.is_empty())
+                .then(|| Content::text(None, system.join("\n\n"))),
+            generation_config: GenerationConfig {
+                temperature,
+                max_output_tokens: max_tokens,
+                response_mime_type: json_mode.then_some("application/json"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+impl GeminiResponse {
+    /// Text of the first candidate, empty when this piece carries none
+    fn candidate_text(&self) -> Option<String> {
+        let candidate = self.candidates.first()?;
+        let parts = &candidate.content.as_ref()?.parts;
+        Some(parts.iter().map(|p| p.text.as_str()).collect())
+    }
+
+    /// Text of the first candidate
+    ///
+    /// A response without candidates, or one stopped for safety reasons, is
+    /// reported as an invalid response with the finish reason.
+    pub(crate) fn text(self) -> Result<String> {
+        match self.candidate_text() {
+            Some(text) if !text.is_empty() => Ok(text),
+            _ => {
+                let reason = self
+                    .candidates
+                    .first()
+                    .and_then(|c| c.finish_reason.as_deref())
+                    .unwrap_or("no candidates");
+                Err(ChatError::InvalidResponse(format!(
+                    "No text in response ({})",
+                    reason
+                )))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EmbedRequest {
+    requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedContentRequest {
+    model: String,
+    content: Content,
+}
+
+impl EmbedRequest {
+    pub(crate) fn new(model: &str, inputs: &[String]) -> Self {
+        Self {
+            requests: inputs
+                .iter()
+                .map(|text| EmbedContentRequest {
+                    model: format!("models/{}", model),
+                    content: Content::text(None, text.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbedResponse {
+    #[serde(default)]
+    embeddings: Vec<Embedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Embedding {
+    values: Vec<f32>,
+}
+
+impl EmbedResponse {
+    pub(crate) fn into_vectors(self) -> Vec<Vec<f32>> {
+        self.embeddings.into_iter().map(|e| e.values).collect()
+    }
+}
+
+/// Parse one Server-Sent Events line from a `streamGenerateContent` stream
+pub fn parse_stream_line(line: &str) -> Option<Result<StreamEvent>> {
+    let data = line.trim().strip_prefix("data:")?.trim();
+
+    match serde_json::from_str::<GeminiResponse>(data) {
+        Ok(response) => response
+            .candidate_text()
+            .filter(|text| !text.is_empty())
+            .map(|text| Ok(StreamEvent::Token(text))),
+        Err(e) => Some(Err(ChatError::InvalidResponse(format!(
+            "Malformed stream event: {}",
+            e
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_layout() {
+        let messages = vec![
+            Message::system("Be brief."),
+            Message::user("hi"),
+            Message::assistant("hello"),
+        ];
+        let request = GeminiRequest::new(&messages, Some(0.5), Some(100), true);
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["systemInstruction"]["parts"][0]["text"], "Be brief.");
+        assert!(json["systemInstruction"].get("role").is_none());
+        assert_eq!(json["contents"][1]["role"], "model");
+        assert_eq!(json["generationConfig"]["maxOutputTokens"], 100);
+        assert_eq!(
+            json["generationConfig"]["responseMimeType"],
+            "application/json"
+        );
+
+        let bare = GeminiRequest::new(&[Message::user("hi")], None, None, false);
+        let json = serde_json::to_value(&bare).unwrap();
+        assert!(json.get("generationConfig").is_none());
+        assert!(json.get("systemInstruction").is_none());
+    }
+
+    #[cfg(feature = "vision")]
+    #[test]
+    fn test_images_become_inline_parts() {
+        let screenshot = Image::from_bytes(b"\x89PNG\r\n\x1a\n").unwrap();
+        let messages = vec![Message::user("What is this?").with_images(vec![screenshot])];
+        let request = GeminiRequest::new(&messages, None, None, false);
+        let json = serde_json::to_value(&request).unwrap();
+
+        let parts = &json["contents"][0]["parts"];
+        assert_eq!(parts[0]["text"], "What is this?");
+        assert!(parts[1].get("text").is_none());
+        assert_eq!(parts[1]["inlineData"]["mimeType"], "image/png");
+        assert_eq!(parts[1]["inlineData"]["data"], "iVBORw0KGgo=");
+    }
+
+    #[test]
+    fn test_response_text() {
+        let response: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"df -h"}]},"finishReason":"STOP"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.text().unwrap(), "df -h");
+
+        let blocked: GeminiResponse =
+            serde_json::from_str(r#"{"candidates":[{"finishReason":"SAFETY"}]}"#).unwrap();
+        assert!(blocked.text().unwrap_err().to_string().contains("SAFETY"));
+    }
+
+    #[test]
+    fn test_parse_stream_line() {
+        let line =
+            r#"data: {"candidates":[{"content":{"parts":[{"text":"Hel"}],"role":"model"}}]}"#;
+        assert_eq!(
+            parse_stream_line(line).unwrap().unwrap(),
+            StreamEvent::Token("Hel".to_string())
+        );
+        assert!(parse_stream_line("").is_none());
+        assert!(parse_stream_line("data: {oops").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_embed_request() {
+        let request = EmbedRequest::new("text-embedding-004", &["a".to_string()]);
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["requests"][0]["model"], "models/text-embedding-004");
+        assert_eq!(json["requests"][0]["content"]["parts"][0]["text"], "a");
+    }
+}
@@ -1,5 +1,10 @@
 // lib_chat/src/history.rs
+#[cfg(feature = "vision")]
+use crate::image::{Image, IMAGE_TOKENS};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Role {
@@ -11,10 +16,36 @@ pub enum Role {
     Assistant,
 }
 
+/// A message of a conversation
+///
+/// Besides the role and content sent to providers, a message can carry what is
+/// known about how it was written. The metadata is kept in saved sessions and
+/// exports but never sent to a provider: each provider builds its own request
+/// messages from the role, content and images.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     pub content: String,
+    /// When the message was written, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Tokens of `content` as the model counts them, e.g. from the provider's
+    /// usage report; trimming uses it instead of the history's counter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<usize>,
+    /// Provider that wrote a reply (`openai`, `ollama`, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Model that wrote a reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// How long the reply took, from sending the request to its last token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Images shown to the model along with `content`
+    #[cfg(feature = "vision")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Image>,
 }
 
 impl Message {
@@ -22,9 +53,53 @@ impl Message {
         Self {
             role,
             content: content.into(),
+            timestamp: None,
+            tokens: None,
+            provider: None,
+            model: None,
+            latency_ms: None,
+            #[cfg(feature = "vision")]
+            images: Vec::new(),
         }
     }
 
+    /// Record when the message was written, in seconds since the Unix epoch
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Record that the message was written now
+    pub fn stamped(self) -> Self {
+        self.with_timestamp(lib_util::unix_now())
+    }
+
+    /// Record the tokens of `content` as the model counts them
+    pub fn with_tokens(mut self, tokens: usize) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    /// Record the provider and model that wrote the message
+    pub fn with_model(mut self, provider: impl Into<String>, model: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Record how long the reply took
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = Some(u64::try_from(latency.as_millis()).unwrap_or(u64::MAX));
+        self
+    }
+
+    /// Attach `images` to the message
+    #[cfg(feature = "vision")]
+    pub fn with_images(mut self, images: Vec<Image>) -> Self {
+        self.images = images;
+        self
+    }
+
     pub fn system(content: impl Into<String>) -> Self {
         Self::new(Role::System, content)
     }
@@ -38,20 +113,88 @@ impl Message {
     }
 }
 
+/// Counts the tokens a model sees for a piece of text
+///
+/// Implemented for closures, so a real tokenizer can be plugged in with
+/// `|text: &str| tokenizer.encode(text, false).map_or(0, |e| e.len())`.
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+impl<F: Fn(&str) -> usize + Send + Sync> TokenCounter for F {
+    fn count(&self, text: &str) -> usize {
+        self(text)
+    }
+}
+
+/// Estimate of about four characters per token, which holds for English text with
+/// the BPE vocabularies of the common providers; code and non-Latin scripts take
+/// more tokens, so leave headroom below the model's context
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicCounter;
+
+impl TokenCounter for HeuristicCounter {
+    fn count(&self, text: &str) -> usize {
+        (text.chars().count() + 3) / 4
+    }
+}
+
+/// Tokens each message costs beyond its content (role and delimiters)
+pub const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// The counter a history measures its messages with
+#[derive(Clone)]
+struct Counter(Arc<dyn TokenCounter>);
+
+impl fmt::Debug for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenCounter")
+    }
+}
+
+/// What happens to the oldest turns when the history outgrows its token budget
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryCompaction {
+    /// Drop them
+    #[default]
+    Drop,
+    /// Fold them into one system message summarizing them; [`crate::Chat`] asks its
+    /// provider for the summary, other callers use [`ConversationHistory::compact_with`]
+    Summarize,
+}
+
+/// Start of the system message older turns are folded into
+pub const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:";
+
+/// Share of the token budget freed by a summarization pass, so that the summary
+/// fits and the next few turns do not trigger another pass straight away
+const SUMMARY_HEADROOM_DIVISOR: usize = 4;
+
+/// Messages of a conversation, trimmed oldest first to stay within its limits
+///
+/// System prompts are never trimmed: when a limit is exceeded the oldest user or
+/// assistant message (or earlier summary) goes, and the newest message is always
+/// kept.
 #[derive(Debug, Clone)]
 pub struct ConversationHistory {
     messages: Vec<Message>,
     max_messages: usize,
-    max_bytes_total: usize,      // Max total memory for all messages
+    max_bytes_total: usize,       // Max total memory for all messages
     max_bytes_per_message: usize, // Max size for a single message
+    max_tokens: Option<usize>,
+    token_counter: Counter,
+    compaction: HistoryCompaction,
+    /// Whether the first message was set with
+    /// [`ConversationHistory::set_head_system_message`]
+    head: bool,
 }
 
 impl ConversationHistory {
     pub fn new(max_messages: usize) -> Self {
         Self::new_with_limits(
             max_messages,
-            10 * 1024 * 1024,  // 10MB total by default
-            1 * 1024 * 1024,   // 1MB per message by default
+            10 * 1024 * 1024, // 10MB total by default
+            1024 * 1024,      // 1MB per message by default
         )
     }
 
@@ -65,15 +208,152 @@ impl ConversationHistory {
             max_messages,
             max_bytes_total,
             max_bytes_per_message,
+            max_tokens: None,
+            token_counter: Counter(Arc::new(HeuristicCounter)),
+            compaction: HistoryCompaction::Drop,
+            head: false,
+        }
+    }
+
+    /// Also keep the history within `max_tokens`, counted with [`HeuristicCounter`]
+    /// unless [`ConversationHistory::with_token_counter`] sets another counter
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_chat::history::ConversationHistory;
+    ///
+    /// let mut history = ConversationHistory::default().with_token_limit(40);
+    /// history.add_system_message("You are a shell assistant").unwrap();
+    /// for _ in 0..5 {
+    ///     history.add_user_message("How do I list files?").unwrap();
+    /// }
+    /// assert!(history.total_tokens() <= 40);
+    /// assert_eq!(history.messages()[0].content, "You are a shell assistant");
+    /// ```
+    pub fn with_token_limit(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self.trim();
+        self
+    }
+
+    /// Count tokens with `counter`, e.g. the tokenizer of the model in use
+    pub fn with_token_counter(mut self, counter: impl TokenCounter + 'static) -> Self {
+        self.token_counter = Counter(Arc::new(counter));
+        self.trim();
+        self
+    }
+
+    /// How to get back within the token budget; with [`HistoryCompaction::Summarize`]
+    /// the history stays over budget until it is compacted
+    pub fn with_compaction(mut self, compaction: HistoryCompaction) -> Self {
+        self.compaction = compaction;
+        self.trim();
+        self
+    }
+
+    pub fn compaction(&self) -> HistoryCompaction {
+        self.compaction
+    }
+
+    /// The token budget set with [`ConversationHistory::with_token_limit`]
+    pub fn token_limit(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    /// Tokens of all messages, including [`MESSAGE_OVERHEAD_TOKENS`] per message
+    /// and an estimate for each attached image
+    pub fn total_tokens(&self) -> usize {
+        self.messages.iter().map(|m| self.message_tokens(m)).sum()
+    }
+
+    fn message_tokens(&self, message: &Message) -> usize {
+        #[cfg(feature = "vision")]
+        let images = message.images.len() * IMAGE_TOKENS;
+        #[cfg(not(feature = "vision"))]
+        let images = 0;
+        let content = message
+            .tokens
+            .unwrap_or_else(|| self.token_counter.0.count(&message.content));
+        content + MESSAGE_OVERHEAD_TOKENS + images
+    }
+
+    /// Whether the history is over its token budget and waits for a summarization
+    pub fn needs_compaction(&self) -> bool {
+        self.compaction == HistoryCompaction::Summarize
+            && self
+                .max_tokens
+                .is_some_and(|max_tokens| self.total_tokens() > max_tokens)
+    }
+
+    /// The oldest turns to fold into a summary, starting with the previous summary;
+    /// empty when the history is within its budget
+    pub fn turns_to_summarize(&self) -> Vec<Message> {
+        let Some(max_tokens) = self.max_tokens.filter(|_| self.needs_compaction()) else {
+            return Vec::new();
+        };
+        let target = max_tokens - max_tokens / SUMMARY_HEADROOM_DIVISOR;
+        let mut tokens = self.total_tokens();
+        let newest = self.messages.len().saturating_sub(1);
+        let mut turns = Vec::new();
+        for message in self.messages[..newest].iter().filter(|m| is_foldable(m)) {
+            if tokens <= target {
+                break;
+            }
+            tokens -= self.message_tokens(message);
+            turns.push(message.clone());
         }
+        turns
     }
 
-    /// Calculate total byte size of all messages
+    /// Replace the first `count` of [`ConversationHistory::turns_to_summarize`] with
+    /// a system message holding `summary`
+    pub fn fold_into_summary(&mut self, count: usize, summary: &str) {
+        let newest = self.messages.len().saturating_sub(1);
+        let folded: Vec<usize> = (0..newest)
+            .filter(|&i| is_foldable(&self.messages[i]))
+            .take(count)
+            .collect();
+        let Some(&first) = folded.first() else {
+            return;
+        };
+        for &index in folded.iter().rev() {
+            self.messages.remove(index);
+        }
+        self.messages.insert(
+            first,
+            Message::system(format!("{} {}", SUMMARY_PREFIX, summary.trim())),
+        );
+        // A summary longer than what it replaced still has to fit
+        self.drop_to_token_limit();
+    }
+
+    /// Fold the oldest turns into a summary written by `summarize`, e.g. a local
+    /// model; on error the history is left as it was
+    pub fn compact_with<E>(
+        &mut self,
+        summarize: impl FnOnce(&[Message]) -> Result<String, E>,
+    ) -> Result<(), E> {
+        let turns = self.turns_to_summarize();
+        if !turns.is_empty() {
+            let summary = summarize(&turns)?;
+            self.fold_into_summary(turns.len(), &summary);
+        }
+        Ok(())
+    }
+
+    /// Drop the oldest turns until the history fits its token budget, whatever the
+    /// compaction strategy; the fallback when no summary can be had
+    pub fn drop_to_token_limit(&mut self) {
+        if let Some(max_tokens) = self.max_tokens {
+            while self.total_tokens() > max_tokens && self.drop_oldest() {}
+        }
+    }
+
+    /// Calculate total byte size of all messages; attached images do not count,
+    /// their files are limited in size when loaded
     fn total_bytes(&self) -> usize {
-        self.messages
-            .iter()
-            .map(|m| m.content.len())
-            .sum()
+        self.messages.iter().map(|m| m.content.len()).sum()
     }
 
     pub fn add_message(&mut self, message: Message) -> Result<(), String> {
@@ -87,20 +367,55 @@ impl ConversationHistory {
         }
 
         self.messages.push(message);
+        self.trim();
+
+        Ok(())
+    }
 
+    /// Drop the oldest messages until the history is within all of its limits
+    fn trim(&mut self) {
         // Keep only the most recent messages by count
-        if self.messages.len() > self.max_messages {
-            let start = self.messages.len() - self.max_messages;
-            self.messages.drain(0..start);
-        }
+        while self.messages.len() > self.max_messages && self.drop_oldest() {}
 
         // Keep only the most recent messages by total size
-        while self.total_bytes() > self.max_bytes_total && self.messages.len() > 1 {
-            // Remove oldest message
-            self.messages.remove(0);
+        while self.total_bytes() > self.max_bytes_total && self.drop_oldest() {}
+
+        // Keep only the most recent messages that fit the model's context, unless
+        // they are waiting to be summarized
+        if self.compaction == HistoryCompaction::Drop {
+            self.drop_to_token_limit();
         }
+    }
 
-        Ok(())
+    /// Remove the oldest message that is neither a system prompt nor the newest
+    /// one; false when there is none
+    fn drop_oldest(&mut self) -> bool {
+        let newest = self.messages.len().saturating_sub(1);
+        match self.messages[..newest].iter().position(is_foldable) {
+            Some(index) => {
+                self.messages.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make `content` the system message at the head of the history, replacing the
+    /// one set before; other system messages stay where they are
+    pub fn set_head_system_message(&mut self, content: impl Into<String>) {
+        let message = Message::system(content);
+        if self.head {
+            self.messages[0] = message;
+        } else {
+            self.messages.insert(0, message);
+            self.head = true;
+        }
+        self.trim();
+    }
+
+    /// The system message set with [`ConversationHistory::set_head_system_message`]
+    pub fn head_system_message(&self) -> Option<&str> {
+        self.head.then(|| self.messages[0].content.as_str())
     }
 
     pub fn add_user_message(&mut self, content: impl Into<String>) -> Result<(), String> {
@@ -119,8 +434,15 @@ impl ConversationHistory {
         &self.messages
     }
 
+    /// Messages to rewrite in place, e.g. translated
+    #[cfg(feature = "translate")]
+    pub(crate) fn messages_mut(&mut self) -> &mut [Message] {
+        &mut self.messages
+    }
+
     pub fn clear(&mut self) {
         self.messages.clear();
+        self.head = false;
     }
 
     pub fn is_empty(&self) -> bool {
@@ -132,6 +454,11 @@ impl ConversationHistory {
     }
 }
 
+/// A turn or an earlier summary, which a summarization pass may fold
+fn is_foldable(message: &Message) -> bool {
+    message.role != Role::System || message.content.starts_with(SUMMARY_PREFIX)
+}
+
 impl Default for ConversationHistory {
     fn default() -> Self {
         Self::new(50) // Default to keeping last 50 messages
@@ -204,4 +531,169 @@ mod tests {
         assert!(history.total_bytes() <= 200);
         assert!(history.len() < 3);
     }
+
+    #[test]
+    fn test_message_metadata() {
+        let reply = Message::assistant("Run df -h")
+            .with_timestamp(1_700_000_000)
+            .with_model("ollama", "llama3")
+            .with_latency(Duration::from_millis(1250))
+            .with_tokens(40);
+        let json = serde_json::to_value(&reply).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "role": "assistant",
+                "content": "Run df -h",
+                "timestamp": 1_700_000_000,
+                "tokens": 40,
+                "provider": "ollama",
+                "model": "llama3",
+                "latency_ms": 1250
+            })
+        );
+        // Sessions saved before the metadata existed still load
+        let plain: Message = serde_json::from_str(r#"{"role": "user", "content": "hi"}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&plain).unwrap(),
+            r#"{"role":"user","content":"hi"}"#
+        );
+        assert!(Message::user("hi").stamped().timestamp.unwrap() > 1_700_000_000);
+
+        // A reported token count replaces the estimate
+        let mut history = ConversationHistory::new(10).with_token_counter(|_: &str| 3);
+        history.add_message(reply).unwrap();
+        history.add_user_message("thanks").unwrap();
+        assert_eq!(history.total_tokens(), 40 + 3 + 2 * MESSAGE_OVERHEAD_TOKENS);
+    }
+
+    #[cfg(feature = "vision")]
+    #[test]
+    fn test_images_count_towards_tokens_and_persist() {
+        let screenshot = Image::from_bytes(b"\x89PNG\r\n\x1a\n").unwrap();
+        let message = Message::user("what is this").with_images(vec![screenshot]);
+        let mut history = ConversationHistory::new(50).with_token_counter(|_: &str| 3);
+        history.add_message(message.clone()).unwrap();
+        assert_eq!(
+            history.total_tokens(),
+            3 + MESSAGE_OVERHEAD_TOKENS + IMAGE_TOKENS
+        );
+
+        let json = serde_json::to_string(&message).unwrap();
+        let loaded: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.images, message.images);
+        // Messages without images keep their old layout
+        let plain = serde_json::to_string(&Message::user("hi")).unwrap();
+        assert_eq!(plain, r#"{"role":"user","content":"hi"}"#);
+    }
+
+    #[test]
+    fn test_token_limit_keeps_system_prompt() {
+        // One token per word, plus the per-message overhead
+        let words = |text: &str| text.split_whitespace().count();
+        let mut history = ConversationHistory::new(50)
+            .with_token_limit(30)
+            .with_token_counter(words);
+        assert_eq!(history.token_limit(), Some(30));
+
+        history.add_system_message("answer with commands").unwrap(); // 7
+        history.add_user_message("list all files here").unwrap(); // 8
+        history.add_assistant_message("ls -a").unwrap(); // 6
+        history.add_user_message("now only the large ones").unwrap(); // 9
+        assert_eq!(history.total_tokens(), 30);
+
+        history.add_assistant_message("find . -size +100M").unwrap(); // 8
+        let contents: Vec<&str> = history.messages().iter().map(|m| &*m.content).collect();
+        assert_eq!(
+            contents,
+            [
+                "answer with commands",
+                "ls -a",
+                "now only the large ones",
+                "find . -size +100M"
+            ]
+        );
+
+        // The newest message stays even when it alone exceeds the budget
+        history.add_user_message("word ".repeat(40)).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.messages()[0].role, Role::System);
+    }
+
+    #[test]
+    fn test_summarize_folds_oldest_turns() {
+        let words = |text: &str| text.split_whitespace().count();
+        let mut history = ConversationHistory::new(50)
+            .with_token_limit(40)
+            .with_token_counter(words)
+            .with_compaction(HistoryCompaction::Summarize);
+
+        history.add_system_message("answer with commands").unwrap(); // 7
+        history.add_user_message("list all files here").unwrap(); // 8
+        history.add_assistant_message("ls -a").unwrap(); // 6
+        history.add_user_message("now only the large ones").unwrap(); // 9
+        assert!(!history.needs_compaction());
+        history.add_assistant_message("find . -size +100M").unwrap(); // 8
+        history.add_user_message("and sort them").unwrap(); // 7
+                                                            // Kept over budget until summarized
+        assert_eq!(history.total_tokens(), 45);
+        assert!(history.needs_compaction());
+
+        let turns = history.turns_to_summarize();
+        assert_eq!(turns.len(), 3);
+        history
+            .compact_with(|turns| Ok::<_, String>(format!("{} turns about files", turns.len())))
+            .unwrap();
+        assert_eq!(history.messages()[0].content, "answer with commands");
+        assert_eq!(
+            history.messages()[1].content,
+            "Summary of the earlier conversation: 3 turns about files"
+        );
+        assert_eq!(history.len(), 4);
+        assert!(!history.needs_compaction());
+
+        // The next pass folds the previous summary in first
+        history
+            .add_assistant_message("find . -size +100M | sort -h")
+            .unwrap(); // 10
+        history
+            .add_user_message("thanks a lot for all of that")
+            .unwrap(); // 10
+        let turns = history.turns_to_summarize();
+        assert!(turns[0].content.starts_with(SUMMARY_PREFIX));
+        assert!(history.compact_with(|_| Err("offline")).is_err());
+        // Without a summary the oldest turns go, the earlier summary first
+        history.drop_to_token_limit();
+        assert!(history.total_tokens() <= 40);
+        assert_eq!(history.messages()[0].content, "answer with commands");
+        assert!(!history.messages()[1].content.starts_with(SUMMARY_PREFIX));
+    }
+
+    #[test]
+    fn test_head_system_message_is_replaced() {
+        let mut history = ConversationHistory::new(10);
+        history.add_system_message("notes context").unwrap();
+        history.add_user_message("hi").unwrap();
+        history.set_head_system_message("You are a sysadmin");
+        history.set_head_system_message("You are a teacher");
+
+        let contents: Vec<&str> = history.messages().iter().map(|m| &*m.content).collect();
+        assert_eq!(contents, ["You are a teacher", "notes context", "hi"]);
+        assert_eq!(history.head_system_message(), Some("You are a teacher"));
+
+        history.clear();
+        assert_eq!(history.head_system_message(), None);
+    }
+
+    #[test]
+    fn test_count_limit_keeps_system_prompt() {
+        let mut history = ConversationHistory::new(2);
+        history.add_system_message("be brief").unwrap();
+        history.add_user_message("one").unwrap();
+        history.add_user_message("two").unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.messages()[0].content, "be brief");
+        assert_eq!(history.messages()[1].content, "two");
+    }
 }
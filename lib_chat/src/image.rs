@@ -0,0 +1,139 @@
+// lib_chat/src/image.rs
+// Images attached to chat messages (the `vision` feature)
+//
+// An image is either a URL the provider fetches itself or the file's bytes sent
+// with the request, base64-encoded. Each provider wraps them in its own content
+// parts (see `api`, `anthropic` and `gemini`); Gemini and Ollama only take inline
+// images. Files are recognised by their first bytes, so a screenshot saved without
+// an extension still works, and only the formats every provider accepts are
+// allowed: PNG, JPEG, GIF and WebP.
+
+use crate::error::{ChatError, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Largest image file accepted, the limit of the strictest provider
+pub const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Tokens an image is counted as in a history's token budget; providers charge
+/// between a few hundred and about 1600 depending on its size
+pub const IMAGE_TOKENS: usize = 1000;
+
+/// An image attached to a message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Image {
+    /// Fetched by the provider
+    Url { url: String },
+    /// Sent with the request
+    Base64 { media_type: String, data: String },
+}
+
+impl Image {
+    /// `arg` as given on the command line: an `http(s)://` URL, otherwise a file
+    pub fn load(arg: &str) -> Result<Self> {
+        if arg.starts_with("https://") || arg.starts_with("http://") {
+            Ok(Image::Url {
+                url: arg.to_string(),
+            })
+        } else {
+            Self::from_file(Path::new(arg))
+        }
+    }
+
+    /// The image in the file at `path`
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let read_error = |e: std::io::Error| {
+            ChatError::InvalidInput(format!("Cannot read {}: {}", path.display(), e))
+        };
+        let size = fs::metadata(path).map_err(read_error)?.len();
+        if size > MAX_IMAGE_BYTES {
+            return Err(ChatError::InvalidInput(format!(
+                "{} is too large to attach: {} MB (max {} MB)",
+                path.display(),
+                size / (1024 * 1024),
+                MAX_IMAGE_BYTES / (1024 * 1024)
+            )));
+        }
+        let bytes = fs::read(path).map_err(read_error)?;
+        Self::from_bytes(&bytes).ok_or_else(|| {
+            ChatError::InvalidInput(format!(
+                "{} is not a PNG, JPEG, GIF or WebP image",
+                path.display()
+            ))
+        })
+    }
+
+    /// The image encoded in `bytes`; `None` unless it is a PNG, JPEG, GIF or WebP
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Image::Base64 {
+            media_type: media_type(bytes)?.to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        })
+    }
+
+    /// The image as a URL: its own, or a `data:` URL holding it
+    pub fn to_url(&self) -> String {
+        match self {
+            Image::Url { url } => url.clone(),
+            Image::Base64 { media_type, data } => format!("data:{};base64,{}", media_type, data),
+        }
+    }
+}
+
+/// MIME type of the image format `bytes` start with
+fn media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_from_bytes_detects_format() {
+        let png = Image::from_bytes(b"\x89PNG\r\n\x1a\nrest").unwrap();
+        assert_eq!(
+            png,
+            Image::Base64 {
+                media_type: "image/png".to_string(),
+                data: "iVBORw0KGgpyZXN0".to_string(),
+            }
+        );
+        assert_eq!(png.to_url(), "data:image/png;base64,iVBORw0KGgpyZXN0");
+        assert!(Image::from_bytes(b"RIFF\0\0\0\0WEBPVP8 ").is_some());
+        assert!(Image::from_bytes(b"%PDF-1.7").is_none());
+    }
+
+    #[test]
+    fn test_image_load_url_or_file() {
+        let url = Image::load("https://example.com/error.png").unwrap();
+        assert_eq!(url.to_url(), "https://example.com/error.png");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "not an image").unwrap();
+        let err = Image::load(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("not a PNG, JPEG, GIF or WebP"));
+        assert!(Image::load("/nonexistent/screenshot.png").is_err());
+
+        // Sessions keep the attached images
+        let json = serde_json::to_string(&url).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"url","url":"https://example.com/error.png"}"#
+        );
+    }
+}
@@ -0,0 +1,299 @@
+// lib_chat/src/import.rs
+// Conversations exported from other chat apps
+//
+// A ChatGPT data export ("Settings > Data controls > Export data") is a zip whose
+// conversations.json holds every conversation as a tree of message nodes: editing
+// a message or regenerating a reply starts a new branch. The conversation as the
+// user last saw it is the path from `current_node` back to the root, which is what
+// gets imported. Hidden system messages, tool calls and non-text content (images,
+// code interpreter output) are left out.
+
+use crate::error::{ChatError, Result};
+use crate::history::{Message, Role};
+use crate::session::Session;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Name of the conversations file inside a ChatGPT export
+const CHATGPT_CONVERSATIONS: &str = "conversations.json";
+
+#[derive(Debug, Deserialize)]
+struct ChatGptConversation {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    mapping: HashMap<String, ChatGptNode>,
+    #[serde(default)]
+    current_node: Option<String>,
+    #[serde(default)]
+    default_model_slug: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptNode {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    #[serde(default)]
+    content: Option<ChatGptContent>,
+    #[serde(default)]
+    create_time: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptContent {
+    #[serde(default)]
+    content_type: String,
+    /// Strings for text; objects for images and other attachments
+    #[serde(default)]
+    parts: Vec<serde_json::Value>,
+}
+
+impl ChatGptMessage {
+    fn to_message(&self) -> Option<Message> {
+        let role = match self.author.role.as_str() {
+            "system" => Role::System,
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            _ => return None,
+        };
+        let content = self.content.as_ref()?;
+        if content.content_type != "text" {
+            return None;
+        }
+        let text: Vec<&str> = content
+            .parts
+            .iter()
+            .filter_map(|part| part.as_str())
+            .filter(|part| !part.trim().is_empty())
+            .collect();
+        if text.is_empty() {
+            return None;
+        }
+        let message = Message::new(role, text.join("\n"));
+        Some(match self.create_time {
+            Some(time) if time > 0.0 => message.with_timestamp(time as u64),
+            _ => message,
+        })
+    }
+}
+
+impl ChatGptConversation {
+    /// The branch shown last, root first
+    fn visible_messages(&self) -> Vec<Message> {
+        let Some(mut current) = self.current_node.clone().or_else(|| self.latest_node()) else {
+            return Vec::new();
+        };
+        let mut messages = Vec::new();
+        // Bounded by the node count in case the tree has a cycle
+        for _ in 0..self.mapping.len() {
+            let Some(node) = self.mapping.get(&current) else {
+                break;
+            };
+            messages.extend(node.message.as_ref().and_then(ChatGptMessage::to_message));
+            match &node.parent {
+                Some(parent) => current = parent.clone(),
+                None => break,
+            }
+        }
+        messages.reverse();
+        messages
+    }
+
+    /// Node with the most recent message, for exports without `current_node`
+    fn latest_node(&self) -> Option<String> {
+        self.mapping
+            .iter()
+            .filter_map(|(id, node)| Some((id, node.message.as_ref()?.create_time?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id.clone())
+    }
+
+    fn into_session(self) -> Option<Session> {
+        let messages = self.visible_messages();
+        if !messages.iter().any(|m| m.role != Role::System) {
+            return None;
+        }
+        let title = self
+            .title
+            .clone()
+            .filter(|t| !t.trim().is_empty())
+            .unwrap_or_else(|| "Untitled conversation".to_string());
+        let mut session = Session::new(title, self.create_time.unwrap_or(0.0) as u64);
+        session.origin = self
+            .conversation_id
+            .or(self.id)
+            .map(|id| format!("chatgpt:{}", id));
+        session.model = self.default_model_slug;
+        session.messages = messages;
+        Some(session)
+    }
+}
+
+/// Parse the contents of a ChatGPT `conversations.json`
+///
+/// Conversations without any text messages are skipped.
+pub fn parse_chatgpt_conversations(json: &str) -> Result<Vec<Session>> {
+    let conversations: Vec<ChatGptConversation> = serde_json::from_str(json)
+        .map_err(|e| ChatError::InvalidInput(format!("Not a ChatGPT conversations.json: {}", e)))?;
+    Ok(conversations
+        .into_iter()
+        .filter_map(ChatGptConversation::into_session)
+        .collect())
+}
+
+/// Read the conversations from a ChatGPT export, given either the zip archive or
+/// the `conversations.json` extracted from it
+pub fn read_chatgpt_export(path: &Path) -> Result<Vec<Session>> {
+    let read_error = |e: std::io::Error| {
+        ChatError::InvalidInput(format!("Cannot read {}: {}", path.display(), e))
+    };
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut magic = [0u8; 4];
+    let is_zip = file.read_exact(&mut magic).is_ok() && magic.starts_with(b"PK");
+    drop(file);
+
+    let json = if is_zip {
+        read_from_zip(path)?
+    } else {
+        std::fs::read_to_string(path).map_err(read_error)?
+    };
+    parse_chatgpt_conversations(&json)
+}
+
+fn read_from_zip(path: &Path) -> Result<String> {
+    let zip_error = |e: zip::result::ZipError| {
+        ChatError::InvalidInput(format!("Cannot read archive {}: {}", path.display(), e))
+    };
+    let file = File::open(path)
+        .map_err(|e| ChatError::InvalidInput(format!("Cannot read {}: {}", path.display(), e)))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+
+    // At the root of the archive, but accept it one directory down as well
+    let name = archive
+        .file_names()
+        .filter(|name| {
+            name.rsplit('/').next() == Some(CHATGPT_CONVERSATIONS) && name.matches('/').count() <= 1
+        })
+        .min_by_key(|name| name.len())
+        .map(str::to_string)
+        .ok_or_else(|| {
+            ChatError::InvalidInput(format!(
+                "{} has no {}; is it a ChatGPT data export?",
+                path.display(),
+                CHATGPT_CONVERSATIONS
+            ))
+        })?;
+
+    let mut json = String::new();
+    archive
+        .by_name(&name)
+        .map_err(zip_error)?
+        .read_to_string(&mut json)
+        .map_err(|e| ChatError::InvalidInput(format!("Cannot read {}: {}", name, e)))?;
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Two branches after the first question: the user edited it, and the
+    /// current node is on the edited branch
+    const EXPORT: &str = r#"[
+      {
+        "id": "c1",
+        "title": "Disk usage",
+        "create_time": 1700000000.5,
+        "current_node": "a2",
+        "default_model_slug": "gpt-4o",
+        "mapping": {
+          "root": {"id": "root", "message": null, "parent": null, "children": ["sys"]},
+          "sys": {"message": {"author": {"role": "system"}, "content": {"content_type": "text", "parts": [""]}}, "parent": "root"},
+          "q1": {"message": {"author": {"role": "user"}, "content": {"content_type": "text", "parts": ["how full is my disk"]}}, "parent": "sys"},
+          "q2": {"message": {"author": {"role": "user"}, "content": {"content_type": "text", "parts": ["how full are my disks"]}, "create_time": 1700000060.2}, "parent": "sys"},
+          "tool": {"message": {"author": {"role": "tool"}, "content": {"content_type": "text", "parts": ["..."]}}, "parent": "q2"},
+          "a2": {"message": {"author": {"role": "assistant"}, "content": {"content_type": "text", "parts": ["Run df -h", {"asset": "img"}]}}, "parent": "tool"}
+        }
+      },
+      {"id": "c2", "title": "", "mapping": {}}
+    ]"#;
+
+    #[test]
+    fn test_parse_follows_current_branch() {
+        let sessions = parse_chatgpt_conversations(EXPORT).unwrap();
+        assert_eq!(sessions.len(), 1);
+
+        let session = &sessions[0];
+        assert_eq!(session.name, "disk-usage");
+        assert_eq!(session.created, 1_700_000_000);
+        assert_eq!(session.origin.as_deref(), Some("chatgpt:c1"));
+        assert_eq!(session.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(session.messages[0].timestamp, Some(1_700_000_060));
+        assert_eq!(session.messages[1].timestamp, None);
+        let messages: Vec<(&Role, &str)> = session
+            .messages
+            .iter()
+            .map(|m| (&m.role, m.content.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (&Role::User, "how full are my disks"),
+                (&Role::Assistant, "Run df -h")
+            ]
+        );
+
+        assert!(parse_chatgpt_conversations("{}").is_err());
+    }
+
+    #[test]
+    fn test_read_zip_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("chat.html", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file(
+            CHATGPT_CONVERSATIONS,
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(EXPORT.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        assert_eq!(read_chatgpt_export(&path).unwrap().len(), 1);
+
+        let json = dir.path().join("conversations.json");
+        std::fs::write(&json, EXPORT).unwrap();
+        assert_eq!(read_chatgpt_export(&json).unwrap().len(), 1);
+
+        let empty = dir.path().join("empty.zip");
+        zip::ZipWriter::new(File::create(&empty).unwrap())
+            .finish()
+            .unwrap();
+        let err = read_chatgpt_export(&empty).unwrap_err();
+        assert!(err.to_string().contains("ChatGPT data export"));
+    }
+}
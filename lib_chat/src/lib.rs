@@ -1,11 +1,32 @@
+pub mod anthropic;
 pub mod api;
 pub mod error;
+pub mod export;
+pub mod gemini;
 pub mod history;
+#[cfg(feature = "vision")]
+pub mod image;
+pub mod import;
+pub mod options;
+pub mod persona;
+pub mod retrieval;
+pub mod session;
+mod storage;
+pub mod stream;
+pub mod summarize;
 
 use crate::api::{ApiClient, ApiProvider};
 use crate::error::Result;
+#[cfg(feature = "translate")]
+use crate::history::Role;
 use crate::history::{ConversationHistory, Message};
+#[cfg(feature = "vision")]
+use crate::image::Image;
+use crate::persona::{Persona, PersonaRegistry};
+use crate::retrieval::{Embedder, Index};
+use futures_util::StreamExt;
 use once_cell::sync::Lazy;
+use std::time::Instant;
 use tokio::runtime::Runtime;
 
 /// Global shared tokio runtime for synchronous chat operations
@@ -27,31 +48,195 @@ static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
 pub struct Chat {
     client: Option<ApiClient>,
     history: ConversationHistory,
+    options: ChatOptions,
+    personas: PersonaRegistry,
+    persona: Option<Persona>,
+    #[cfg(feature = "translate")]
+    translate: Option<lib_translate::Translate>,
+    /// Attached to the next user message
+    #[cfg(feature = "vision")]
+    images: Vec<Image>,
 }
 
 impl Chat {
     /// Create a new Chat instance with API client from environment
+    ///
+    /// Use [`Chat::is_configured`] to check whether a provider was found;
+    /// the library never prints to the terminal itself.
     pub fn new() -> Self {
-        let client = ApiClient::from_env().ok();
-        if client.is_none() {
-            eprintln!("Warning: No API provider configured. Set OPENAI_API_KEY, OLLAMA_HOST, or LLM_API_URL");
-        }
+        Self::from_client(ApiClient::from_env().ok())
+    }
+
+    /// Create a Chat instance with a specific provider
+    pub fn with_provider(provider: ApiProvider) -> Result<Self> {
+        Ok(Self::from_client(Some(ApiClient::new(provider)?)))
+    }
+
+    /// Create a Chat instance sending requests through `client`; `None` gives an
+    /// unconfigured instance, as [`Chat::new`] does without a provider
+    pub fn from_client(client: Option<ApiClient>) -> Self {
         Self {
             client,
             history: ConversationHistory::default(),
+            options: ChatOptions::default(),
+            personas: PersonaRegistry::builtin(),
+            persona: None,
+            #[cfg(feature = "translate")]
+            translate: None,
+            #[cfg(feature = "vision")]
+            images: Vec::new(),
         }
     }
 
-    /// Create a Chat instance with a specific provider
-    pub fn with_provider(provider: ApiProvider) -> Result<Self> {
-        Ok(Self {
-            client: Some(ApiClient::new(provider)?),
-            history: ConversationHistory::default(),
-        })
+    /// Keep the conversation in `history`, e.g. one built with
+    /// [`ConversationHistory::with_token_limit`] to fit the model's context window
+    pub fn with_history(mut self, history: ConversationHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Personas [`Chat::with_persona`] chooses from (the built-in ones by default)
+    pub fn with_personas(mut self, personas: PersonaRegistry) -> Self {
+        self.personas = personas;
+        self
+    }
+
+    /// Frame the conversation with the persona `name`, see [`Chat::set_persona`]
+    pub fn with_persona(mut self, name: &str) -> Result<Self> {
+        self.set_persona(name)?;
+        Ok(self)
+    }
+
+    /// Make the system prompt of the persona `name` the one system message at the
+    /// head of the history, replacing the previous persona
+    pub fn set_persona(&mut self, name: &str) -> Result<()> {
+        let persona = self.personas.get(name).ok_or_else(|| {
+            error::ChatError::InvalidInput(format!(
+                "Unknown persona '{}' (available: {})",
+                name,
+                self.personas.names().join(", ")
+            ))
+        })?;
+        self.history
+            .set_head_system_message(persona.system_prompt.as_str());
+        self.persona = Some(persona);
+        Ok(())
+    }
+
+    /// The persona set with [`Chat::set_persona`]
+    pub fn persona(&self) -> Option<&Persona> {
+        self.persona.as_ref()
+    }
+
+    /// Translate with `translate` in [`Chat::switch_language`] instead of the
+    /// service from the environment
+    #[cfg(feature = "translate")]
+    pub fn with_translate(mut self, translate: lib_translate::Translate) -> Self {
+        self.translate = Some(translate);
+        self
+    }
+
+    /// Translate the user and assistant messages of the conversation into
+    /// `target_lang`, e.g. to hand it to someone who reads another language
+    ///
+    /// All messages are translated in one batch, one request per language found
+    /// (see [`lib_translate::Translate::translate_batch_async`]); system prompts
+    /// and messages already in `target_lang` stay as they are. Returns the number
+    /// of messages translated.
+    #[cfg(feature = "translate")]
+    pub async fn switch_language_async(&mut self, target_lang: &str) -> Result<usize> {
+        let visible: Vec<usize> = self
+            .history
+            .messages()
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| message.role != Role::System)
+            .map(|(i, _)| i)
+            .collect();
+        let texts: Vec<String> = visible
+            .iter()
+            .map(|&i| self.history.messages()[i].content.clone())
+            .collect();
+        let results = match &self.translate {
+            Some(translate) => translate.translate_batch_async(&texts, target_lang).await?,
+            None => {
+                lib_translate::Translate::new()
+                    .translate_batch_async(&texts, target_lang)
+                    .await?
+            }
+        };
+
+        let messages = self.history.messages_mut();
+        let mut translated = 0;
+        for (i, result) in visible.into_iter().zip(results) {
+            if result.was_translated {
+                messages[i].content = result.translated;
+                // Counted for the original text
+                messages[i].tokens = None;
+                translated += 1;
+            }
+        }
+        Ok(translated)
+    }
+
+    /// Synchronous wrapper around [`Chat::switch_language_async`]
+    #[cfg(feature = "translate")]
+    pub fn switch_language(&mut self, target_lang: &str) -> Result<usize> {
+        RUNTIME.block_on(self.switch_language_async(target_lang))
+    }
+
+    /// Use `options` for subsequent requests (see [`options::Verbosity`] for presets)
+    pub fn with_options(mut self, options: ChatOptions) -> Self {
+        self.set_options(options);
+        self
+    }
+
+    pub fn set_options(&mut self, options: ChatOptions) {
+        if let Some(client) = &mut self.client {
+            client.set_deadline(options.deadline);
+            client.set_json_mode(options.json_mode);
+        }
+        self.options = options;
+    }
+
+    pub fn options(&self) -> &ChatOptions {
+        &self.options
+    }
+
+    /// Show `images` to the model along with the next message sent
+    ///
+    /// They stay in the history, so later turns can refer to them. Providers that
+    /// only take inline images fail with [`error::ChatError::UnsupportedFeature`]
+    /// for an [`Image::Url`].
+    #[cfg(feature = "vision")]
+    pub fn set_images(&mut self, images: Vec<Image>) {
+        self.images = images;
+    }
+
+    /// `text` as a user message written now, carrying the images set for it
+    fn user_message(&mut self, text: &str) -> Message {
+        #[allow(unused_mut)]
+        let mut message = Message::user(text).stamped();
+        #[cfg(feature = "vision")]
+        {
+            message.images = std::mem::take(&mut self.images);
+        }
+        message
+    }
+
+    /// Messages for the next request: the options' system prompt, then the history
+    fn request_messages(&self) -> Vec<Message> {
+        self.options
+            .system_prompt
+            .iter()
+            .map(Message::system)
+            .chain(self.history.messages().iter().cloned())
+            .collect()
     }
 
     /// Send a message and get a response (async)
     pub async fn send_async(&mut self, message: &str) -> Result<String> {
+        let message = self.user_message(message);
         let client = self
             .client
             .as_ref()
@@ -59,22 +244,79 @@ impl Chat {
 
         // Add user message to history
         self.history
-            .add_user_message(message)
-            .map_err(|e| error::ChatError::InvalidInput(e))?;
+            .add_message(message)
+            .map_err(error::ChatError::InvalidInput)?;
+        compact_history(&mut self.history, client).await;
 
         // Send to API with full conversation history
+        let started = Instant::now();
         let response = client
-            .send_message(self.history.messages(), Some(0.7), Some(1000))
+            .send_message(
+                &self.request_messages(),
+                self.options.temperature,
+                self.options.max_tokens,
+            )
             .await?;
 
         // Add assistant response to history
         self.history
-            .add_assistant_message(&response)
-            .map_err(|e| error::ChatError::InvalidInput(e))?;
+            .add_message(reply_message(client, &response, started))
+            .map_err(error::ChatError::InvalidInput)?;
 
         Ok(response)
     }
 
+    /// Send a message and receive the reply incrementally (async)
+    ///
+    /// `on_token` is called with each text fragment as it arrives. The full
+    /// reply is returned and recorded in the history once the stream ends.
+    pub async fn send_stream_async(
+        &mut self,
+        message: &str,
+        mut on_token: impl FnMut(&str),
+    ) -> Result<String> {
+        let message = self.user_message(message);
+        let client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| error::ChatError::NoProviderError)?;
+
+        self.history
+            .add_message(message)
+            .map_err(error::ChatError::InvalidInput)?;
+        compact_history(&mut self.history, client).await;
+
+        let started = Instant::now();
+        let mut stream = client
+            .send_message_stream(
+                &self.request_messages(),
+                self.options.temperature,
+                self.options.max_tokens,
+            )
+            .await?;
+
+        let mut response = String::new();
+        while let Some(token) = stream.next().await {
+            let token = token?;
+            on_token(&token);
+            response.push_str(&token);
+        }
+
+        self.history
+            .add_message(reply_message(client, &response, started))
+            .map_err(error::ChatError::InvalidInput)?;
+
+        Ok(response)
+    }
+
+    /// Synchronous wrapper around [`Chat::send_stream_async`]
+    ///
+    /// Blocks on the shared runtime while invoking `on_token` for every fragment,
+    /// so callers can print the reply as it is generated.
+    pub fn run_streaming(&mut self, text: &str, on_token: impl FnMut(&str)) -> Result<String> {
+        RUNTIME.block_on(self.send_stream_async(text, on_token))
+    }
+
     /// Synchronous wrapper that blocks on async send
     /// This is the method called from main.rs
     ///
@@ -85,16 +327,78 @@ impl Chat {
         Ok(response)
     }
 
+    /// Ground the conversation in the most relevant chunks of a notes index (async)
+    ///
+    /// Embeds `question`, retrieves the top `k` chunks and adds them as a system
+    /// message. Returns the sources used, best match first.
+    pub async fn attach_index_async(
+        &mut self,
+        index: &Index,
+        question: &str,
+        k: usize,
+    ) -> Result<Vec<String>> {
+        let embedder =
+            Embedder::from_client(self.client.clone()).with_deadline(self.options.deadline);
+        let chunks = index.retrieve(question, k, &embedder).await?;
+        let sources = chunks.iter().map(|c| c.chunk.source.clone()).collect();
+        self.set_system_prompt(&retrieval::format_context(&chunks))?;
+        Ok(sources)
+    }
+
+    /// Synchronous wrapper around [`Chat::attach_index_async`]
+    pub fn attach_index(&mut self, index: &Index, question: &str, k: usize) -> Result<Vec<String>> {
+        RUNTIME.block_on(self.attach_index_async(index, question, k))
+    }
+
+    /// Summarize a document (async)
+    ///
+    /// Runs outside the conversation history. Inputs too long for one request
+    /// are chunked, summarized part by part and then combined.
+    pub async fn summarize_async(&self, text: &str, length: SummaryLength) -> Result<String> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or(error::ChatError::NoProviderError)?;
+        summarize::summarize(client, text, length).await
+    }
+
+    /// Synchronous wrapper around [`Chat::summarize_async`]
+    pub fn summarize(&self, text: &str, length: SummaryLength) -> Result<String> {
+        RUNTIME.block_on(self.summarize_async(text, length))
+    }
+
+    /// Answer `question` from the output of `command`, which was run to answer it (async)
+    ///
+    /// Runs outside the conversation history; long output is condensed the same
+    /// way as in [`Chat::summarize_async`].
+    pub async fn answer_from_output_async(
+        &self,
+        question: &str,
+        command: &str,
+        output: &str,
+        length: SummaryLength,
+    ) -> Result<String> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or(error::ChatError::NoProviderError)?;
+        summarize::answer_from_output(client, question, command, output, length).await
+    }
+
     /// Add a system message to guide the conversation
     pub fn set_system_prompt(&mut self, prompt: &str) -> Result<()> {
         self.history
             .add_system_message(prompt)
-            .map_err(|e| error::ChatError::InvalidInput(e))
+            .map_err(error::ChatError::InvalidInput)
     }
 
-    /// Clear conversation history
+    /// Clear conversation history; the persona stays
     pub fn clear_history(&mut self) {
         self.history.clear();
+        if let Some(persona) = &self.persona {
+            self.history
+                .set_head_system_message(persona.system_prompt.as_str());
+        }
     }
 
     /// Get conversation history
@@ -106,6 +410,42 @@ impl Chat {
     pub fn is_configured(&self) -> bool {
         self.client.is_some()
     }
+
+    /// The provider requests go to, if one is configured
+    pub fn provider(&self) -> Option<&ApiProvider> {
+        self.client.as_ref().map(ApiClient::provider)
+    }
+}
+
+/// `response` as an assistant message recording when, by which model and how fast
+/// it was written
+fn reply_message(client: &ApiClient, response: &str, started: Instant) -> Message {
+    let provider = client.provider();
+    Message::assistant(response)
+        .stamped()
+        .with_model(provider.name(), provider.model_name())
+        .with_latency(started.elapsed())
+}
+
+/// Fold the oldest turns of `history` into a summary written by the provider when it
+/// has outgrown its token budget (see [`history::HistoryCompaction::Summarize`])
+async fn compact_history(history: &mut ConversationHistory, client: &ApiClient) {
+    let turns = history.turns_to_summarize();
+    if turns.is_empty() {
+        return;
+    }
+    match summarize::summarize_conversation(client, &turns).await {
+        Ok(summary) => history.fold_into_summary(turns.len(), &summary),
+        // A shorter memory is better than failing the request
+        Err(_e) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                "Could not summarize the conversation, dropping old turns: {}",
+                _e
+            );
+            history.drop_to_token_limit();
+        }
+    }
 }
 
 impl Default for Chat {
@@ -114,5 +454,55 @@ impl Default for Chat {
     }
 }
 
+/// Build an embedding index over the notes in `root` (async)
+///
+/// Uses the configured provider's embeddings API, or the offline embedder
+/// when no provider is configured.
+pub async fn build_index_async(name: &str, root: &std::path::Path) -> Result<Index> {
+    let embedder = Embedder::from_env();
+    Index::build(name, root, &embedder).await
+}
+
+/// Synchronous wrapper around [`build_index_async`]
+pub fn build_index(name: &str, root: &std::path::Path) -> Result<Index> {
+    RUNTIME.block_on(build_index_async(name, root))
+}
+
 // Re-export commonly used types for convenience
 pub use error::ChatError;
+pub use options::{ChatOptions, Verbosity};
+pub use summarize::SummaryLength;
+
+#[cfg(all(test, feature = "translate"))]
+mod tests {
+    use super::*;
+    use lib_translate::translator::TranslatorProvider;
+
+    #[tokio::test]
+    async fn test_switch_language_translates_visible_messages() {
+        let translate = lib_translate::Translate::with_provider(TranslatorProvider::Mock).unwrap();
+        let mut chat = Chat::from_client(None)
+            .with_persona("teacher")
+            .unwrap()
+            .with_translate(translate);
+        chat.history
+            .add_user_message("Wie finde ich die größten Dateien in meinem Ordner?")
+            .unwrap();
+        chat.history
+            .add_assistant_message("Use du with sort to list the largest files first.")
+            .unwrap();
+
+        assert_eq!(chat.switch_language_async("en").await.unwrap(), 1);
+        let history = chat.history();
+        assert_eq!(history[0].role, Role::System);
+        assert!(!history[0].content.starts_with("[Translated"));
+        assert_eq!(
+            history[1].content,
+            "[Translated from de to en] Wie finde ich die größten Dateien in meinem Ordner?"
+        );
+        assert_eq!(
+            history[2].content,
+            "Use du with sort to list the largest files first."
+        );
+    }
+}
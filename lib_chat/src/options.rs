@@ -0,0 +1,167 @@
+// lib_chat/src/options.rs
+// Generation settings for chat requests
+//
+// `ChatOptions` holds the knobs sent with every request. `Verbosity` is a set of
+// presets over them: terminal users usually want much shorter answers than the
+// provider defaults, so `concise` trims both the instructions and the token budget.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Sampling and framing settings applied to each chat request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatOptions {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Instructions sent ahead of the conversation; not stored in the history
+    pub system_prompt: Option<String>,
+    /// Point by which every HTTP request of a call must have finished
+    pub deadline: Option<Instant>,
+    /// Ask the provider for a single JSON object as the reply
+    pub json_mode: bool,
+}
+
+impl Default for ChatOptions {
+    fn default() -> Self {
+        Self {
+            temperature: Some(0.7),
+            max_tokens: Some(1000),
+            system_prompt: None,
+            deadline: None,
+            json_mode: false,
+        }
+    }
+}
+
+impl ChatOptions {
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Deadline `timeout` from now
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Request JSON replies; providers without a JSON mode reject the request
+    /// with `ChatError::UnsupportedFeature` before it is sent
+    pub fn with_json_mode(mut self) -> Self {
+        self.json_mode = true;
+        self
+    }
+}
+
+const CONCISE_PROMPT: &str = "Answer as briefly as possible: one or two sentences, or just \
+     the command or code snippet when that answers the question. Skip preambles, restating \
+     the question and caveats.";
+
+const DETAILED_PROMPT: &str = "Give a thorough answer: explain the reasoning, cover the \
+     relevant options and caveats, and include examples where they help.";
+
+/// How long chat answers should be
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// A sentence or two, or just the command
+    Concise,
+    /// Provider defaults
+    #[default]
+    Normal,
+    /// Thorough answers with reasoning and examples
+    Detailed,
+}
+
+impl Verbosity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::Concise => "concise",
+            Verbosity::Normal => "normal",
+            Verbosity::Detailed => "detailed",
+        }
+    }
+
+    /// Request settings for this preset
+    pub fn options(&self) -> ChatOptions {
+        match self {
+            Verbosity::Concise => ChatOptions::default()
+                .with_max_tokens(300)
+                .with_system_prompt(CONCISE_PROMPT),
+            Verbosity::Normal => ChatOptions::default(),
+            Verbosity::Detailed => ChatOptions::default()
+                .with_max_tokens(2000)
+                .with_system_prompt(DETAILED_PROMPT),
+        }
+    }
+}
+
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "concise" => Ok(Verbosity::Concise),
+            "normal" => Ok(Verbosity::Normal),
+            "detailed" => Ok(Verbosity::Detailed),
+            other => Err(format!(
+                "Unknown verbosity '{}' (expected concise, normal or detailed)",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_presets() {
+        assert_eq!(Verbosity::Normal.options(), ChatOptions::default());
+
+        let concise = Verbosity::Concise.options();
+        let detailed = Verbosity::Detailed.options();
+        assert!(concise.max_tokens < ChatOptions::default().max_tokens);
+        assert!(detailed.max_tokens > ChatOptions::default().max_tokens);
+        assert!(concise.system_prompt.is_some());
+        assert_eq!(concise.temperature, ChatOptions::default().temperature);
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        let before = Instant::now();
+        let options = ChatOptions::default().with_timeout(Duration::from_secs(10));
+        let deadline = options.deadline.unwrap();
+        assert!(deadline >= before + Duration::from_secs(10));
+        assert!(deadline <= Instant::now() + Duration::from_secs(10));
+        assert_eq!(ChatOptions::default().deadline, None);
+    }
+
+    #[test]
+    fn test_verbosity_from_str() {
+        assert_eq!(" Concise ".parse::<Verbosity>(), Ok(Verbosity::Concise));
+        assert_eq!("detailed".parse::<Verbosity>(), Ok(Verbosity::Detailed));
+        assert!("terse".parse::<Verbosity>().is_err());
+    }
+}
@@ -0,0 +1,77 @@
+// lib_chat/src/persona.rs
+// Named system prompts for chat conversations
+//
+// A persona frames a whole conversation: `sysadmin` answers like an operator on call,
+// `teacher` explains step by step. `Chat::with_persona` puts its prompt at the head of
+// the history as the one system message there, replacing an earlier persona, so
+// switching personas never stacks prompts. The built-in personas can be replaced and
+// new ones added; the CLI reads them from `[personas.NAME]` tables in its config.
+
+use std::collections::BTreeMap;
+
+/// A named system prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Persona {
+    pub name: String,
+    pub system_prompt: String,
+}
+
+const SYSADMIN_PROMPT: &str = "You are an experienced Linux system administrator. Answer \
+     with the commands and configuration to run, note what needs root or may disrupt \
+     running services, and prefer standard tools over ad-hoc scripts.";
+
+const TEACHER_PROMPT: &str = "You are a patient teacher of the Linux command line. Explain \
+     concepts step by step, say what each part of a command does, and end with a small \
+     exercise the user can try.";
+
+/// Personas available by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PersonaRegistry {
+    personas: BTreeMap<String, String>,
+}
+
+impl PersonaRegistry {
+    /// The personas shipped with eidos: `sysadmin` and `teacher`
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.insert("sysadmin", SYSADMIN_PROMPT);
+        registry.insert("teacher", TEACHER_PROMPT);
+        registry
+    }
+
+    /// Add a persona, replacing one of the same name
+    pub fn insert(&mut self, name: impl Into<String>, system_prompt: impl Into<String>) {
+        self.personas.insert(name.into(), system_prompt.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<Persona> {
+        self.personas.get(name).map(|prompt| Persona {
+            name: name.to_string(),
+            system_prompt: prompt.clone(),
+        })
+    }
+
+    /// Names of all personas, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.personas.keys().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_personas_extend_the_builtins() {
+        let mut registry = PersonaRegistry::builtin();
+        registry.insert("teacher", "Explain like I'm five.");
+        registry.insert("reviewer", "Review shell scripts.");
+
+        assert_eq!(registry.names(), ["reviewer", "sysadmin", "teacher"]);
+        assert_eq!(
+            registry.get("teacher").unwrap().system_prompt,
+            "Explain like I'm five."
+        );
+        assert!(registry.get("pirate").is_none());
+    }
+}
@@ -0,0 +1,513 @@
+// lib_chat/src/retrieval.rs
+// Retrieval over local notes (RAG)
+//
+// Text and markdown files are split into overlapping chunks, embedded, and stored
+// in a zstd-compressed JSON index. At chat time the question is embedded with the same embedder
+// and the top-k most similar chunks are injected into the prompt, numbered so the
+// model can cite them ("[2]") and the reply can be checked against the notes.
+
+use crate::api::{ApiClient, Feature};
+use crate::error::{ChatError, Result};
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// File extensions picked up when indexing a directory
+const INDEXED_EXTENSIONS: &[&str] = &["txt", "md", "markdown", "rst", "org"];
+
+/// Files larger than this are skipped (not notes)
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Target chunk size in characters
+const CHUNK_CHARS: usize = 1200;
+
+/// Characters shared between consecutive chunks
+const CHUNK_OVERLAP: usize = 200;
+
+/// Number of texts sent per embedding request
+const EMBED_BATCH: usize = 64;
+
+/// Dimension of the local hashing embedder
+const LOCAL_DIMENSIONS: usize = 512;
+
+/// Identifier stored in indexes built with the local embedder
+const LOCAL_EMBEDDER_ID: &str = "local:hashed-bow-512";
+
+/// Produces vector embeddings for text
+pub enum Embedder {
+    /// Embeddings endpoint of the configured chat provider
+    Api { client: ApiClient, model: String },
+    /// Offline hashed bag-of-words vectors; no network, lower quality
+    Local,
+}
+
+impl Embedder {
+    /// Use the configured provider's embeddings API, falling back to the local
+    /// embedder when there is no provider or it has no embeddings API
+    pub fn from_env() -> Self {
+        Self::from_client(ApiClient::from_env().ok())
+    }
+
+    /// Use the embeddings API behind `client`, falling back to the local embedder
+    /// like [`Embedder::from_env`]
+    pub fn from_client(client: Option<ApiClient>) -> Self {
+        match client {
+            Some(client) if client.provider().supports(Feature::Embeddings) => {
+                let model = client.default_embedding_model();
+                Embedder::Api { client, model }
+            }
+            _ => Embedder::Local,
+        }
+    }
+
+    /// Bound embedding requests by `deadline` (see [`ApiClient::set_deadline`])
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        if let Embedder::Api { client, .. } = &mut self {
+            client.set_deadline(deadline);
+        }
+        self
+    }
+
+    /// Stable identifier recorded in the index, e.g. "openai:text-embedding-3-small"
+    pub fn id(&self) -> String {
+        match self {
+            Embedder::Api { client, model } => format!("{}:{}", client.provider().name(), model),
+            Embedder::Local => LOCAL_EMBEDDER_ID.to_string(),
+        }
+    }
+
+    /// Embed a batch of texts
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        match self {
+            Embedder::Api { client, model } => {
+                let mut embeddings = Vec::with_capacity(texts.len());
+                for batch in texts.chunks(EMBED_BATCH) {
+                    embeddings.extend(client.embed(model, batch).await?);
+                }
+                Ok(embeddings)
+            }
+            Embedder::Local => Ok(texts.iter().map(|t| local_embedding(t)).collect()),
+        }
+    }
+}
+
+/// A piece of a source document together with its embedding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Path of the file the chunk was taken from
+    pub source: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// A chunk returned by a search, with its similarity score
+#[derive(Debug, Clone)]
+pub struct ScoredChunk<'a> {
+    pub chunk: &'a Chunk,
+    pub score: f32,
+}
+
+/// Embedding index over a directory of notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    /// Directory the index was built from
+    pub root: PathBuf,
+    /// Identifier of the embedder used (queries must use the same one)
+    pub embedder: String,
+    pub chunks: Vec<Chunk>,
+}
+
+impl Index {
+    /// Index all supported files below `root`
+    pub async fn build(name: &str, root: &Path, embedder: &Embedder) -> Result<Self> {
+        let mut files = Vec::new();
+        collect_files(root, &mut files)?;
+        files.sort();
+
+        let mut pieces: Vec<(String, String)> = Vec::new();
+        for file in &files {
+            let Ok(text) = fs::read_to_string(file) else {
+                continue; // Not UTF-8 text
+            };
+            let source = file.display().to_string();
+            for chunk in chunk_text(&text, CHUNK_CHARS, CHUNK_OVERLAP) {
+                pieces.push((source.clone(), chunk));
+            }
+        }
+
+        let texts: Vec<String> = pieces.iter().map(|(_, text)| text.clone()).collect();
+        let embeddings = embedder.embed(&texts).await?;
+
+        let chunks = pieces
+            .into_iter()
+            .zip(embeddings)
+            .map(|((source, text), embedding)| Chunk {
+                source,
+                text,
+                embedding,
+            })
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            root: root.to_path_buf(),
+            embedder: embedder.id(),
+            chunks,
+        })
+    }
+
+    /// Number of distinct source files in the index
+    pub fn source_count(&self) -> usize {
+        let mut sources: Vec<&str> = self.chunks.iter().map(|c| c.source.as_str()).collect();
+        sources.sort_unstable();
+        sources.dedup();
+        sources.len()
+    }
+
+    /// Return the `k` chunks most similar to the query embedding
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<ScoredChunk<'_>> {
+        let mut scored: Vec<ScoredChunk<'_>> = self
+            .chunks
+            .iter()
+            .map(|chunk| ScoredChunk {
+                chunk,
+                score: cosine_similarity(query, &chunk.embedding),
+            })
+            .collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored.truncate(k);
+        scored
+    }
+
+    /// Embed `question` and return the `k` most relevant chunks
+    pub async fn retrieve(
+        &self,
+        question: &str,
+        k: usize,
+        embedder: &Embedder,
+    ) -> Result<Vec<ScoredChunk<'_>>> {
+        if embedder.id() != self.embedder {
+            return Err(ChatError::InvalidInput(format!(
+                "Index '{}' was built with embedder '{}', but '{}' is configured",
+                self.name,
+                self.embedder,
+                embedder.id()
+            )));
+        }
+        let query = embedder
+            .embed(&[question.to_string()])
+            .await?
+            .pop()
+            .unwrap_or_default();
+        Ok(self.search(&query, k))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_json(path, self, "index")
+    }
+
+    /// Load an index, compressed or (as written by older versions) plain JSON
+    pub fn load(path: &Path) -> Result<Self> {
+        storage::read_json(path, "index")
+    }
+
+    /// Whether `bytes` are an index in the compressed format [`Index::save`] writes
+    pub fn is_compressed(bytes: &[u8]) -> bool {
+        storage::is_compressed(bytes)
+    }
+}
+
+/// Build a system prompt that grounds the answer in retrieved chunks
+pub fn format_context(chunks: &[ScoredChunk<'_>]) -> String {
+    let mut prompt = String::from(
+        "Answer using the following excerpts from the user's notes when they are relevant. \
+         If they do not contain the answer, say so. Cite each excerpt you use by its \
+         number in square brackets, e.g. [2].\n",
+    );
+    for (i, scored) in chunks.iter().enumerate() {
+        prompt.push_str(&format!(
+            "\n[{}] (from {})\n{}\n",
+            i + 1,
+            scored.chunk.source,
+            scored.chunk.text.trim()
+        ));
+    }
+    prompt
+}
+
+/// An excerpt the reply refers to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    /// Number of the excerpt in the prompt, starting at 1
+    pub number: usize,
+    /// File the excerpt was taken from
+    pub source: String,
+}
+
+/// Citations (`[1]`, `[2, 3]`) in `reply`, in order of first mention
+///
+/// `sources` are the sources of the excerpts given to [`format_context`], in the
+/// same order. Numbers without a matching excerpt are ignored.
+pub fn parse_citations(reply: &str, sources: &[String]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    let mut rest = reply;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+        let numbers: Option<Vec<usize>> = rest[..close]
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect();
+        for number in numbers.into_iter().flatten() {
+            let Some(source) = number.checked_sub(1).and_then(|i| sources.get(i)) else {
+                continue;
+            };
+            if !citations.iter().any(|c| c.number == number) {
+                citations.push(Citation {
+                    number,
+                    source: source.clone(),
+                });
+            }
+        }
+    }
+    citations
+}
+
+/// Split text into chunks of roughly `max_chars`, preferring paragraph boundaries
+///
+/// Consecutive chunks share up to `overlap` characters so that facts spanning
+/// a boundary are still retrievable.
+pub fn chunk_text(text: &str, max_chars: usize, overlap: usize) -> Vec<String> {
+    let overlap = overlap.min(max_chars / 2);
+    let unit_size = (max_chars - overlap).max(1);
+
+    // Paragraphs longer than a unit are hard-split on character boundaries
+    let units: Vec<String> = text
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .flat_map(|p| {
+            let chars: Vec<char> = p.chars().collect();
+            chars
+                .chunks(unit_size)
+                .map(|c| c.iter().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut has_new_text = false;
+
+    for unit in units {
+        if has_new_text && current.chars().count() + 2 + unit.chars().count() > max_chars {
+            let tail = last_chars(&current, overlap);
+            chunks.push(std::mem::replace(&mut current, tail));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(&unit);
+        has_new_text = true;
+    }
+
+    if has_new_text {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn last_chars(text: &str, n: usize) -> String {
+    let count = text.chars().count();
+    text.chars().skip(count.saturating_sub(n)).collect()
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        ChatError::InvalidInput(format!("Cannot read directory {}: {}", dir.display(), e))
+    })?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() && !hidden {
+            collect_files(&path, files)?;
+        } else if file_type.is_file() && !hidden {
+            let supported = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| INDEXED_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+                .unwrap_or(false);
+            let small_enough = entry
+                .metadata()
+                .map(|m| m.len() <= MAX_FILE_BYTES)
+                .unwrap_or(false);
+            if supported && small_enough {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Hashed bag-of-words embedding (lowercased alphanumeric terms, L2-normalized)
+fn local_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; LOCAL_DIMENSIONS];
+    for term in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.len() > 1)
+    {
+        let term = term.to_lowercase();
+        // FNV-1a: stable across runs and platforms, unlike std's RandomState
+        let hash = term.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        vector[(hash % LOCAL_DIMENSIONS as u64) as usize] += 1.0;
+    }
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_chunk_text_respects_size() {
+        let text = (0..20)
+            .map(|i| format!("Paragraph {} {}", i, "word ".repeat(30)))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let chunks = chunk_text(&text, 400, 50);
+        assert!(chunks.len() > 1);
+        // The paragraph separator may push a chunk just over the target size
+        assert!(chunks.iter().all(|c| c.chars().count() <= 400 + 2));
+        assert!(chunks[0].starts_with("Paragraph 0"));
+    }
+
+    #[test]
+    fn test_chunk_text_short_input() {
+        assert_eq!(chunk_text("just one line", 400, 50), vec!["just one line"]);
+        assert!(chunk_text("   \n\n  ", 400, 50).is_empty());
+    }
+
+    #[test]
+    fn test_local_embedding_similarity() {
+        let a = local_embedding("restart the nginx service with systemctl");
+        let b = local_embedding("how do I restart nginx");
+        let c = local_embedding("banana bread recipe with walnuts");
+        assert!(cosine_similarity(&a, &b) > cosine_similarity(&a, &c));
+    }
+
+    #[test]
+    fn test_build_and_search_local_index() {
+        let dir = std::env::temp_dir().join(format!("eidos-rag-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(
+            dir.join("nginx.md"),
+            "Restart nginx with systemctl restart nginx.",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("sub/bread.txt"),
+            "Banana bread needs ripe bananas.",
+        )
+        .unwrap();
+        fs::write(dir.join("image.png"), "not indexed").unwrap();
+
+        let embedder = Embedder::Local;
+        let index = block_on(Index::build("notes", &dir, &embedder)).unwrap();
+        assert_eq!(index.source_count(), 2);
+        assert_eq!(index.embedder, LOCAL_EMBEDDER_ID);
+
+        let results = block_on(index.retrieve("how to restart nginx", 1, &embedder)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].chunk.source.ends_with("nginx.md"));
+
+        let path = dir.join("index.json");
+        index.save(&path).unwrap();
+        assert!(Index::is_compressed(&fs::read(&path).unwrap()));
+        let loaded = Index::load(&path).unwrap();
+        assert_eq!(loaded.chunks.len(), index.chunks.len());
+
+        // Indexes written before compression still load
+        fs::write(&path, serde_json::to_vec(&index).unwrap()).unwrap();
+        assert_eq!(Index::load(&path).unwrap().chunks.len(), index.chunks.len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_context_numbers_sources() {
+        let chunk = Chunk {
+            source: "notes/a.md".to_string(),
+            text: "Some fact".to_string(),
+            embedding: vec![],
+        };
+        let scored = vec![ScoredChunk {
+            chunk: &chunk,
+            score: 1.0,
+        }];
+        let prompt = format_context(&scored);
+        assert!(prompt.contains("[1] (from notes/a.md)"));
+        assert!(prompt.contains("Some fact"));
+        assert!(prompt.contains("square brackets"));
+    }
+
+    #[test]
+    fn test_parse_citations() {
+        let sources = vec!["nginx.md".to_string(), "bread.txt".to_string()];
+        let citations = parse_citations(
+            "Run `systemctl reload nginx` [2, 1]. See [1] and [the docs](x) or [ -f a ]; [7] [",
+            &sources,
+        );
+        assert_eq!(
+            citations,
+            vec![
+                Citation {
+                    number: 2,
+                    source: "bread.txt".to_string()
+                },
+                Citation {
+                    number: 1,
+                    source: "nginx.md".to_string()
+                },
+            ]
+        );
+        assert!(parse_citations("[0] [3]", &sources).is_empty());
+    }
+}
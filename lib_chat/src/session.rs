@@ -0,0 +1,236 @@
+// lib_chat/src/session.rs
+// Saved conversations
+//
+// A session is a named conversation kept as one zstd-compressed JSON file in a
+// sessions directory chosen by the caller (the CLI uses
+// ~/.local/share/eidos/sessions). Sessions imported from elsewhere remember where
+// they came from, so importing the same export twice does not duplicate them.
+
+use crate::error::{ChatError, Result};
+use crate::history::Message;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Longest session name derived from a title
+const MAX_NAME_CHARS: usize = 48;
+
+/// A saved conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// File name (without extension) and handle on the command line
+    pub name: String,
+    pub title: String,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    /// Where an imported conversation came from, e.g. `chatgpt:<conversation id>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Model that wrote the replies, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub messages: Vec<Message>,
+}
+
+impl Session {
+    /// Session named after `title` (see [`slugify`])
+    pub fn new(title: impl Into<String>, created: u64) -> Self {
+        let title = title.into();
+        Self {
+            name: slugify(&title),
+            title,
+            created,
+            origin: None,
+            model: None,
+            messages: Vec::new(),
+        }
+    }
+}
+
+/// Sessions imported by [`SessionStore::import`]
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// Names of the sessions written
+    pub imported: Vec<String>,
+    /// Conversations already imported earlier
+    pub skipped: usize,
+}
+
+/// Directory of saved sessions
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// File holding the session called `name`
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    pub fn load(&self, name: &str) -> Result<Session> {
+        if !is_valid_name(name) {
+            return Err(ChatError::InvalidInput(format!(
+                "Invalid session name: {}",
+                name
+            )));
+        }
+        storage::read_json(&self.path(name), "session")
+    }
+
+    pub fn save(&self, session: &Session) -> Result<()> {
+        if !is_valid_name(&session.name) {
+            return Err(ChatError::InvalidInput(format!(
+                "Invalid session name: {}",
+                session.name
+            )));
+        }
+        storage::write_json(&self.path(&session.name), session, "session")
+    }
+
+    /// All sessions, oldest first; a missing directory holds none
+    pub fn list(&self) -> Result<Vec<Session>> {
+        let mut sessions = self
+            .files()?
+            .iter()
+            .map(|path| storage::read_json::<Session>(path, "session"))
+            .collect::<Result<Vec<_>>>()?;
+        sessions.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+        Ok(sessions)
+    }
+
+    /// Save imported sessions under names not yet taken, skipping those whose
+    /// origin is already in the store
+    ///
+    /// Imports running at the same time take turns, so neither reuses a name or an
+    /// origin the other has just saved.
+    pub fn import(&self, sessions: Vec<Session>) -> Result<ImportReport> {
+        storage::with_dir_lock(&self.dir, || self.import_locked(sessions))
+    }
+
+    fn import_locked(&self, sessions: Vec<Session>) -> Result<ImportReport> {
+        let existing = self.list()?;
+        let mut origins: HashSet<String> =
+            existing.iter().filter_map(|s| s.origin.clone()).collect();
+        let mut names: HashSet<String> = existing.into_iter().map(|s| s.name).collect();
+        let mut report = ImportReport::default();
+
+        for mut session in sessions {
+            if let Some(origin) = &session.origin {
+                if !origins.insert(origin.clone()) {
+                    report.skipped += 1;
+                    continue;
+                }
+            }
+            session.name = unique_name(&session.name, &names);
+            names.insert(session.name.clone());
+            self.save(&session)?;
+            report.imported.push(session.name);
+        }
+        Ok(report)
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(ChatError::InvalidInput(format!(
+                    "Cannot read {}: {}",
+                    self.dir.display(),
+                    e
+                )))
+            }
+        };
+        Ok(entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect())
+    }
+}
+
+/// Lowercase, dash-separated name for `title`, e.g. "Fix nginx 502s" -> "fix-nginx-502s"
+pub fn slugify(title: &str) -> String {
+    let mut name = String::new();
+    for word in title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let word = word.to_lowercase();
+        if name.chars().count() + word.chars().count() >= MAX_NAME_CHARS {
+            break;
+        }
+        if !name.is_empty() {
+            name.push('-');
+        }
+        name.push_str(&word);
+    }
+    if name.is_empty() {
+        "session".to_string()
+    } else {
+        name
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// `base`, or `base-2`, `base-3`, ... if it is taken
+fn unique_name(base: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|name| !taken.contains(name))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Fix nginx 502s!"), "fix-nginx-502s");
+        assert_eq!(slugify("  Résumé: tips  "), "résumé-tips");
+        assert_eq!(slugify("???"), "session");
+        assert!(slugify(&"word ".repeat(40)).len() < MAX_NAME_CHARS);
+    }
+
+    #[test]
+    fn test_save_list_and_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions"));
+        assert!(store.list().unwrap().is_empty());
+
+        let mut session = Session::new("Disk usage", 10);
+        session.messages.push(Message::user("how full is my disk"));
+        store.save(&session).unwrap();
+        assert_eq!(store.load("disk-usage").unwrap().messages.len(), 1);
+
+        let imported = |id: &str| {
+            let mut session = Session::new("Disk usage", 20);
+            session.origin = Some(format!("chatgpt:{}", id));
+            session
+        };
+        let report = store.import(vec![imported("a"), imported("b")]).unwrap();
+        assert_eq!(report.imported, ["disk-usage-2", "disk-usage-3"]);
+
+        // Importing the same conversations again changes nothing
+        let report = store.import(vec![imported("a")]).unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(report.skipped, 1);
+
+        let names: Vec<String> = store.list().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["disk-usage", "disk-usage-2", "disk-usage-3"]);
+        assert!(store.load("../etc").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+// lib_chat/src/storage.rs
+// zstd-compressed JSON files (notes indexes, sessions)
+//
+// Files are written compressed; reading recognises the zstd magic number, so plain
+// JSON files written by older versions still load. Files are replaced in one step
+// (written beside the target, then renamed over it), so another invocation reading
+// a session or index while it is saved sees either the old or the new version.
+
+use crate::error::{ChatError, Result};
+use fd_lock::RwLock;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// zstd level for saved files
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// First bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Whether `bytes` start a zstd frame
+pub(crate) fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ZSTD_MAGIC)
+}
+
+/// Serialize `value` to `path` as compressed JSON, creating parent directories
+///
+/// `what` names the file in error messages ("index", "session").
+pub(crate) fn write_json(path: &Path, value: &impl Serialize, what: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            ChatError::InvalidInput(format!("Cannot create {}: {}", parent.display(), e))
+        })?;
+    }
+    let write_error = |e: std::io::Error| {
+        ChatError::InvalidInput(format!("Cannot write {} {}: {}", what, path.display(), e))
+    };
+    let json = serde_json::to_vec(value)?;
+    let compressed = zstd::encode_all(json.as_slice(), COMPRESSION_LEVEL).map_err(write_error)?;
+    lib_util::write_atomic(path, compressed).map_err(write_error)
+}
+
+/// Run `update` holding an exclusive advisory lock on `dir/.lock`, so concurrent
+/// invocations updating the same directory take turns
+pub(crate) fn with_dir_lock<T>(dir: &Path, update: impl FnOnce() -> Result<T>) -> Result<T> {
+    let lock_path: PathBuf = dir.join(".lock");
+    let lock_error = |e: std::io::Error| {
+        ChatError::InvalidInput(format!("Cannot lock {}: {}", lock_path.display(), e))
+    };
+    fs::create_dir_all(dir).map_err(lock_error)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(lock_error)?;
+    let mut lock = RwLock::new(file);
+    let _guard = lock.write().map_err(lock_error)?;
+    update()
+}
+
+/// Deserialize a file written by [`write_json`], or a plain JSON file
+pub(crate) fn read_json<T: DeserializeOwned>(path: &Path, what: &str) -> Result<T> {
+    let read_error = |e: std::io::Error| {
+        ChatError::InvalidInput(format!("Cannot read {} {}: {}", what, path.display(), e))
+    };
+    let mut bytes = fs::read(path).map_err(read_error)?;
+    if is_compressed(&bytes) {
+        bytes = zstd::decode_all(bytes.as_slice()).map_err(read_error)?;
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
@@ -0,0 +1,298 @@
+// lib_chat/src/stream.rs
+// Incremental parsing of streamed chat completions
+//
+// OpenAI-compatible endpoints stream Server-Sent Events (`data: {...}` lines ending
+// with `data: [DONE]`), Ollama streams newline-delimited JSON objects ending with
+// `"done": true`, and Anthropic and Gemini send Server-Sent Events with their own
+// payloads. All are line oriented, so a single line splitter feeds a
+// provider-specific line parser.
+
+use crate::error::{ChatError, Result};
+use crate::{anthropic, gemini};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+/// Stream of text fragments as they arrive from the provider
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// Outcome of parsing a single streamed line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// A text fragment to append to the response
+    Token(String),
+    /// The provider signalled the end of the response
+    Done,
+}
+
+/// Line format used by a provider's streaming endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// OpenAI-style Server-Sent Events
+    Sse,
+    /// Ollama-style newline-delimited JSON
+    Ndjson,
+    /// Anthropic Messages API events
+    Anthropic,
+    /// Gemini `streamGenerateContent` events
+    Gemini,
+}
+
+impl StreamFormat {
+    fn parse_line(self, line: &str) -> Option<Result<StreamEvent>> {
+        match self {
+            StreamFormat::Sse => parse_sse_line(line),
+            StreamFormat::Ndjson => parse_ndjson_line(line),
+            StreamFormat::Anthropic => anthropic::parse_stream_line(line),
+            StreamFormat::Gemini => gemini::parse_stream_line(line),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SseChunk {
+    choices: Vec<SseChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SseChoice {
+    #[serde(default)]
+    delta: SseDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SseDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NdjsonChunk {
+    #[serde(default)]
+    message: Option<NdjsonMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NdjsonMessage {
+    #[serde(default)]
+    content: String,
+}
+
+/// Parse one Server-Sent Events line from an OpenAI-compatible stream
+///
+/// Returns `None` for lines that carry no content (comments, keep-alives,
+/// empty deltas such as the initial role-only chunk).
+pub fn parse_sse_line(line: &str) -> Option<Result<StreamEvent>> {
+    let data = line.trim().strip_prefix("data:")?.trim();
+
+    if data == "[DONE]" {
+        return Some(Ok(StreamEvent::Done));
+    }
+
+    match serde_json::from_str::<SseChunk>(data) {
+        Ok(chunk) => chunk
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.delta.content)
+            .filter(|content| !content.is_empty())
+            .map(|content| Ok(StreamEvent::Token(content))),
+        Err(e) => Some(Err(ChatError::InvalidResponse(format!(
+            "Malformed stream event: {}",
+            e
+        )))),
+    }
+}
+
+/// Parse one newline-delimited JSON line from an Ollama stream
+pub fn parse_ndjson_line(line: &str) -> Option<Result<StreamEvent>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    match serde_json::from_str::<NdjsonChunk>(line) {
+        Ok(chunk) => {
+            if let Some(error) = chunk.error {
+                return Some(Err(ChatError::ApiError(error)));
+            }
+            if chunk.done {
+                return Some(Ok(StreamEvent::Done));
+            }
+            chunk
+                .message
+                .map(|m| m.content)
+                .filter(|content| !content.is_empty())
+                .map(|content| Ok(StreamEvent::Token(content)))
+        }
+        Err(e) => Some(Err(ChatError::InvalidResponse(format!(
+            "Malformed stream event: {}",
+            e
+        )))),
+    }
+}
+
+struct LineState<S> {
+    bytes: S,
+    buffer: Vec<u8>,
+    pending: VecDeque<Result<String>>,
+    format: StreamFormat,
+    finished: bool,
+}
+
+impl<S> LineState<S> {
+    /// Parse every complete line in the buffer into pending tokens
+    fn drain_lines(&mut self, flush: bool) {
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            self.push_line(&line);
+        }
+        if flush && !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            self.push_line(&line);
+        }
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        if self.finished {
+            return;
+        }
+        let line = String::from_utf8_lossy(line);
+        match self.format.parse_line(&line) {
+            Some(Ok(StreamEvent::Token(token))) => self.pending.push_back(Ok(token)),
+            Some(Ok(StreamEvent::Done)) => self.finished = true,
+            Some(Err(e)) => {
+                self.pending.push_back(Err(e));
+                self.finished = true;
+            }
+            None => {}
+        }
+    }
+}
+
+/// Turn a raw byte stream into a stream of text fragments
+pub fn token_stream<S, B, E>(bytes: S, format: StreamFormat) -> TokenStream
+where
+    S: Stream<Item = std::result::Result<B, E>> + Send + Unpin + 'static,
+    B: AsRef<[u8]>,
+    E: Into<ChatError>,
+{
+    let state = LineState {
+        bytes,
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        format,
+        finished: false,
+    };
+
+    let stream = stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+            if state.finished {
+                return None;
+            }
+            match state.bytes.next().await {
+                Some(Ok(chunk)) => {
+                    state.buffer.extend_from_slice(chunk.as_ref());
+                    state.drain_lines(false);
+                }
+                Some(Err(e)) => {
+                    state.finished = true;
+                    return Some((Err(e.into()), state));
+                }
+                None => {
+                    state.drain_lines(true);
+                    state.finished = true;
+                }
+            }
+        }
+    });
+
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(chunks: Vec<&'static str>, format: StreamFormat) -> Vec<Result<String>> {
+        let bytes = stream::iter(
+            chunks
+                .into_iter()
+                .map(|c| Ok::<_, ChatError>(c.as_bytes().to_vec())),
+        );
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(token_stream(bytes, format).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_sse_line() {
+        let line = r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#;
+        assert_eq!(
+            parse_sse_line(line).unwrap().unwrap(),
+            StreamEvent::Token("Hel".to_string())
+        );
+        assert_eq!(
+            parse_sse_line("data: [DONE]").unwrap().unwrap(),
+            StreamEvent::Done
+        );
+        // Role-only first chunk and keep-alive comments carry no text
+        assert!(parse_sse_line(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#).is_none());
+        assert!(parse_sse_line(": keep-alive").is_none());
+        assert!(parse_sse_line("data: {not json").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_ndjson_line() {
+        let line = r#"{"message":{"role":"assistant","content":"Hi"},"done":false}"#;
+        assert_eq!(
+            parse_ndjson_line(line).unwrap().unwrap(),
+            StreamEvent::Token("Hi".to_string())
+        );
+        assert_eq!(
+            parse_ndjson_line(r#"{"done":true}"#).unwrap().unwrap(),
+            StreamEvent::Done
+        );
+        assert!(parse_ndjson_line(r#"{"error":"model not found"}"#)
+            .unwrap()
+            .is_err());
+        assert!(parse_ndjson_line("").is_none());
+    }
+
+    #[test]
+    fn test_sse_stream_split_across_chunks() {
+        let tokens = collect(
+            vec![
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\ndata: {\"choi",
+                "ces\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+                "data: [DONE]\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"ignored\"}}]}\n",
+            ],
+            StreamFormat::Sse,
+        );
+        let tokens: Vec<String> = tokens.into_iter().map(|t| t.unwrap()).collect();
+        assert_eq!(tokens, vec!["Hel", "lo"]);
+    }
+
+    #[test]
+    fn test_ndjson_stream_without_trailing_newline() {
+        let tokens = collect(
+            vec![
+                "{\"message\":{\"content\":\"ls\"},\"done\":false}\n",
+                "{\"message\":{\"content\":\" -la\"},\"done\":false}",
+            ],
+            StreamFormat::Ndjson,
+        );
+        let tokens: Vec<String> = tokens.into_iter().map(|t| t.unwrap()).collect();
+        assert_eq!(tokens, vec!["ls", " -la"]);
+    }
+}
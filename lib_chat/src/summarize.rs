@@ -0,0 +1,253 @@
+// lib_chat/src/summarize.rs
+// Summaries of files and piped text through the chat provider
+//
+// Inputs that fit in one request are summarized directly. Longer inputs are split
+// with the same chunker used for notes indexes, each chunk is summarized on its
+// own, and the partial summaries are then combined into the final summary. The
+// output of a command run by `eidos core --run-safe --summarize-output` goes
+// through the same reduction, but the final request answers the question the
+// command was generated for instead of summarizing. Conversations that outgrow
+// their token budget fold their oldest turns into a summary the same way.
+
+use crate::api::ApiClient;
+use crate::error::{ChatError, Result};
+use crate::history::{Message, Role, SUMMARY_PREFIX};
+use crate::retrieval::chunk_text;
+use std::fmt;
+use std::str::FromStr;
+
+/// Largest piece of text sent to the provider in a single request
+pub const SUMMARY_CHUNK_CHARS: usize = 6_000;
+
+/// Characters shared between consecutive chunks
+const SUMMARY_CHUNK_OVERLAP: usize = 200;
+
+/// Upper bound on combine passes, so a provider that returns long partials
+/// cannot keep the reduction going forever
+const MAX_COMBINE_ROUNDS: usize = 4;
+
+/// How detailed the summary should be
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryLength {
+    #[default]
+    Short,
+    Long,
+}
+
+impl SummaryLength {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SummaryLength::Short => "short",
+            SummaryLength::Long => "long",
+        }
+    }
+
+    fn instructions(&self) -> &'static str {
+        match self {
+            SummaryLength::Short => "Write the summary in 2-3 sentences.",
+            SummaryLength::Long => {
+                "Write a one-paragraph overview followed by bullet points covering \
+                 the key details, decisions and figures."
+            }
+        }
+    }
+
+    fn max_tokens(&self) -> u32 {
+        match self {
+            SummaryLength::Short => 200,
+            SummaryLength::Long => 800,
+        }
+    }
+}
+
+impl fmt::Display for SummaryLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SummaryLength {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "short" => Ok(SummaryLength::Short),
+            "long" => Ok(SummaryLength::Long),
+            other => Err(format!(
+                "Unknown summary length '{}' (expected short or long)",
+                other
+            )),
+        }
+    }
+}
+
+/// System prompt for the final summary
+pub fn summary_prompt(length: SummaryLength) -> String {
+    format!(
+        "You summarize documents. Capture the main points faithfully and do not add \
+         information that is not in the text. {} Reply with the summary only.",
+        length.instructions()
+    )
+}
+
+/// System prompt for answering `question` from the output of `command`
+pub fn output_answer_prompt(question: &str, command: &str, length: SummaryLength) -> String {
+    format!(
+        "The user asked: \"{}\". To find out, the command `{}` was run; its output \
+         follows. Answer the question from the output only, without describing the \
+         command or repeating the output. If the output does not answer it, say so. {} \
+         Reply with the answer only.",
+        question,
+        command,
+        length.instructions()
+    )
+}
+
+/// System prompt for folding the oldest turns of a conversation into a summary
+const CONVERSATION_PROMPT: &str = "You condense the earlier part of a conversation between \
+     a user and an assistant so it can continue without it. Keep the facts, decisions, \
+     commands and open questions the rest of the conversation may refer to; drop \
+     pleasantries. Reply with the summary only, in a few sentences or bullet points.";
+
+/// The turns of a conversation as a transcript, one `Role: text` block per turn
+pub fn transcript(messages: &[Message]) -> String {
+    let blocks: Vec<String> = messages
+        .iter()
+        .map(|m| match m.role {
+            Role::User => format!("User: {}", m.content),
+            Role::Assistant => format!("Assistant: {}", m.content),
+            Role::System => match m.content.strip_prefix(SUMMARY_PREFIX) {
+                Some(summary) => format!("Earlier summary: {}", summary.trim()),
+                None => format!("System: {}", m.content),
+            },
+        })
+        .collect();
+    blocks.join("\n\n")
+}
+
+/// Summarize the oldest turns of a conversation, see
+/// [`crate::history::HistoryCompaction::Summarize`]
+pub async fn summarize_conversation(client: &ApiClient, messages: &[Message]) -> Result<String> {
+    let input = reduce(client, &transcript(messages)).await?;
+    complete(client, CONVERSATION_PROMPT, &input, 400).await
+}
+
+/// System prompt for summarizing one part of a longer document
+fn partial_prompt(part: usize, total: usize) -> String {
+    format!(
+        "You summarize part {} of {} of a longer document. List the key points of this \
+         part as concise bullet points so they can be merged with the other parts later. \
+         Reply with the bullet points only.",
+        part, total
+    )
+}
+
+/// Split text into request-sized pieces
+pub fn split_for_summary(text: &str) -> Vec<String> {
+    chunk_text(text, SUMMARY_CHUNK_CHARS, SUMMARY_CHUNK_OVERLAP)
+}
+
+/// Summarize `text`, chunking it when it does not fit in a single request
+pub async fn summarize(client: &ApiClient, text: &str, length: SummaryLength) -> Result<String> {
+    let input = reduce(client, text).await?;
+    complete(client, &summary_prompt(length), &input, length.max_tokens()).await
+}
+
+/// Answer `question` from what `command` printed, condensing long output first
+pub async fn answer_from_output(
+    client: &ApiClient,
+    question: &str,
+    command: &str,
+    output: &str,
+    length: SummaryLength,
+) -> Result<String> {
+    let input = reduce(client, output).await?;
+    let system = output_answer_prompt(question, command, length);
+    complete(client, &system, &input, length.max_tokens()).await
+}
+
+/// Condense `text` by summarizing its chunks until it fits in one request
+async fn reduce(client: &ApiClient, text: &str) -> Result<String> {
+    let mut chunks = split_for_summary(text);
+    if chunks.is_empty() {
+        return Err(ChatError::InvalidInput("Nothing to summarize".to_string()));
+    }
+
+    for _ in 0..MAX_COMBINE_ROUNDS {
+        if chunks.len() == 1 {
+            break;
+        }
+
+        let total = chunks.len();
+        let mut partials = Vec::with_capacity(total);
+        for (i, chunk) in chunks.iter().enumerate() {
+            let partial = complete(client, &partial_prompt(i + 1, total), chunk, 400).await?;
+            partials.push(partial);
+        }
+        chunks = split_for_summary(&partials.join("\n\n"));
+    }
+
+    // Whatever remains is combined in one final request
+    Ok(chunks.join("\n\n"))
+}
+
+async fn complete(client: &ApiClient, system: &str, text: &str, max_tokens: u32) -> Result<String> {
+    let messages = [Message::system(system), Message::user(text)];
+    let reply = client
+        .send_message(&messages, Some(0.3), Some(max_tokens))
+        .await?;
+    Ok(reply.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_length_from_str() {
+        assert_eq!("short".parse::<SummaryLength>(), Ok(SummaryLength::Short));
+        assert_eq!(" LONG ".parse::<SummaryLength>(), Ok(SummaryLength::Long));
+        assert!("medium".parse::<SummaryLength>().is_err());
+    }
+
+    #[test]
+    fn test_output_answer_prompt() {
+        let prompt = output_answer_prompt(
+            "which process uses the most memory?",
+            "ps aux --sort=-%mem",
+            SummaryLength::Short,
+        );
+        assert!(prompt.contains("\"which process uses the most memory?\""));
+        assert!(prompt.contains("`ps aux --sort=-%mem`"));
+        assert!(prompt.contains(SummaryLength::Short.instructions()));
+    }
+
+    #[test]
+    fn test_transcript() {
+        let messages = [
+            Message::system(format!("{} the user wants large files", SUMMARY_PREFIX)),
+            Message::user("only under /var"),
+            Message::assistant("find /var -size +100M"),
+        ];
+        assert_eq!(
+            transcript(&messages),
+            "Earlier summary: the user wants large files\n\n\
+             User: only under /var\n\n\
+             Assistant: find /var -size +100M"
+        );
+    }
+
+    #[test]
+    fn test_split_for_summary() {
+        assert_eq!(split_for_summary("A short note.").len(), 1);
+        assert!(split_for_summary("  \n\n ").is_empty());
+
+        let paragraph = "word ".repeat(300);
+        let long = [paragraph.trim(); 10].join("\n\n");
+        let chunks = split_for_summary(&long);
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|c| c.chars().count() <= SUMMARY_CHUNK_CHARS));
+    }
+}
@@ -1,8 +1,24 @@
 // Alternative command generation strategies
+//
+// Backends that sample token by token (GGUF) draw alternatives from the model
+// itself: the same request decoded at a higher temperature with a different seed
+// per attempt. Single-pass backends (ONNX) cannot vary their output that way and
+// fall back to rephrasing the request. Either way duplicates are dropped and the
+// list may come back shorter than asked for, never padded with repeats.
+//
+// Candidates are generated in rounds of up to `InferenceBackend::parallelism` on
+// scoped threads: an ONNX model runs a pass per core, a pool of GGUF instances a
+// sample per instance. A round never holds more candidates than are still
+// missing, and they are kept in the order they were asked for, so the result is
+// the same as generating one after another, only sooner.
 
+use crate::backend::InferenceBackend;
+use crate::generation::GenerationConfig;
+#[cfg(feature = "onnx")]
 use crate::Core;
-use tract_onnx::prelude::TractResult;
+use anyhow::{anyhow, Result};
 
+#[cfg(feature = "onnx")]
 impl Core {
     /// Generate multiple alternative commands for the same prompt
     ///
@@ -12,56 +28,207 @@ impl Core {
     /// - Approach (different tools for same task)
     /// - Output format
     ///
+    /// The model decodes in a single pass, so alternatives come from rephrasings
+    /// of the request; fewer than `count` are returned when those run out.
+    ///
     /// # Example
     /// ```ignore
     /// let alternatives = core.generate_alternatives("list files", 3)?;
     /// // Might return: ["ls", "ls -a", "ls -la"]
     /// ```
-    pub fn generate_alternatives(&self, input: &str, count: usize) -> TractResult<Vec<String>> {
-        if count == 0 {
-            return Ok(vec![]);
-        }
+    pub fn generate_alternatives(&self, input: &str, count: usize) -> Result<Vec<String>> {
+        self.generate_alternatives_with_context(input, count, None)
+    }
 
-        if count == 1 {
-            return Ok(vec![self.generate_command(input)?]);
-        }
+    /// Generate alternatives with per-request context added to the system prompt
+    pub fn generate_alternatives_with_context(
+        &self,
+        input: &str,
+        count: usize,
+        context: Option<&str>,
+    ) -> Result<Vec<String>> {
+        generate_alternatives(self, input, count, context)
+    }
+}
 
-        let mut alternatives = Vec::with_capacity(count);
+/// Temperature sampled alternatives use at least, enough to leave the single most
+/// likely command without wandering off the request
+pub const ALTERNATIVE_TEMPERATURE: f64 = 0.8;
 
-        // Generate base command
-        let base_command = self.generate_command(input)?;
-        alternatives.push(base_command.clone());
+/// Tokens sampled alternatives choose from when the model sets no top-k
+pub const ALTERNATIVE_TOP_K: usize = 40;
 
-        // Generate variations with modified prompts
-        let variations = vec![
-            format!("{} with details", input),
-            format!("{} verbose", input),
-            format!("{} concise", input),
-            format!("{} with all options", input),
-            format!("{} simple", input),
-        ];
+/// Samples drawn per requested alternative before settling for fewer
+const ATTEMPTS_PER_ALTERNATIVE: usize = 3;
 
-        for variation in variations.iter().take(count - 1) {
-            match self.generate_command(variation) {
-                Ok(cmd) => {
-                    // Only add if different from base and not already in list
-                    if cmd != base_command && !alternatives.contains(&cmd) {
-                        alternatives.push(cmd);
-                    }
-                }
-                Err(_) => continue, // Skip variations that fail
-            }
+/// Alternatives for backends that decode in a single pass: the plain request
+/// first, then rephrasings of it, keeping only commands not seen yet
+///
+/// Returns fewer than `count` commands when the rephrasings run out; a command is
+/// never repeated to make up the number.
+pub(crate) fn generate_alternatives<B: InferenceBackend + ?Sized>(
+    backend: &B,
+    input: &str,
+    count: usize,
+    context: Option<&str>,
+) -> Result<Vec<String>> {
+    let variations = [
+        format!("{} with details", input),
+        format!("{} verbose", input),
+        format!("{} concise", input),
+        format!("{} with all options", input),
+        format!("{} simple", input),
+    ];
+    collect_distinct(backend, count, 1 + variations.len(), |job| match job {
+        0 => backend.generate(input, context),
+        n => backend.generate(&variations[n - 1], context),
+    })
+}
 
-            if alternatives.len() >= count {
-                break;
-            }
+/// Alternatives for backends that sample token by token: the command of the
+/// configured settings first, then samples at [`ALTERNATIVE_TEMPERATURE`] with a
+/// different seed each, keeping only commands not seen yet
+///
+/// Seeds are derived from `config.seed`, so the same request yields the same
+/// alternatives. Returns fewer than `count` commands when the model keeps
+/// producing ones already found.
+pub(crate) fn sample_alternatives<B: InferenceBackend + ?Sized>(
+    backend: &B,
+    input: &str,
+    count: usize,
+    context: Option<&str>,
+    config: &GenerationConfig,
+) -> Result<Vec<String>> {
+    let mut sampling = config
+        .clone()
+        .with_temperature(config.temperature.max(ALTERNATIVE_TEMPERATURE));
+    sampling.top_k = config.top_k.or(Some(ALTERNATIVE_TOP_K));
+    let attempts = count.saturating_sub(1) * ATTEMPTS_PER_ALTERNATIVE;
+    collect_distinct(backend, count, 1 + attempts, |job| match job {
+        0 => backend.generate(input, context),
+        attempt => {
+            let mut sampling = sampling.clone();
+            sampling.seed = config.seed.wrapping_add(attempt as u64);
+            backend.generate_with_config(input, context, &sampling)
         }
+    })
+}
 
-        // If we didn't get enough unique alternatives, pad with the base command
-        while alternatives.len() < count {
-            alternatives.push(base_command.clone());
+/// Up to `count` distinct commands from `jobs` generations, run in order in
+/// rounds of up to `backend.parallelism()` at once
+///
+/// Job 0 is the plain request: its command always comes first and its failure
+/// fails the whole list. Later jobs that fail are skipped.
+fn collect_distinct<B: InferenceBackend + ?Sized>(
+    backend: &B,
+    count: usize,
+    jobs: usize,
+    generate: impl Fn(usize) -> Result<String> + Sync,
+) -> Result<Vec<String>> {
+    let workers = backend.parallelism().max(1);
+    let mut alternatives = Vec::new();
+    let mut next = 0;
+    while alternatives.len() < count && next < jobs {
+        let round = workers.min(count - alternatives.len()).min(jobs - next);
+        for (job, result) in (next..).zip(run_round(next..next + round, &generate)) {
+            match result {
+                Ok(cmd) if job == 0 => alternatives.push(cmd),
+                Ok(cmd) => push_distinct(&mut alternatives, cmd),
+                Err(e) if job == 0 => return Err(e),
+                // Skip generations that fail
+                Err(_) => {}
+            }
         }
+        next += round;
+    }
+    Ok(alternatives)
+}
 
-        Ok(alternatives)
+/// Results of `jobs`, each on its own scoped thread when there are several
+fn run_round(
+    jobs: std::ops::Range<usize>,
+    generate: &(impl Fn(usize) -> Result<String> + Sync),
+) -> Vec<Result<String>> {
+    if jobs.len() <= 1 {
+        return jobs.map(generate).collect();
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = jobs.map(|job| scope.spawn(move || generate(job))).collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("Generation worker panicked")))
+            })
+            .collect()
+    })
+}
+
+/// Add `cmd` unless it is empty or differs from a command already listed only
+/// in whitespace
+fn push_distinct(alternatives: &mut Vec<String>, cmd: String) {
+    let words = |cmd: &str| cmd.split_whitespace().collect::<Vec<_>>().join(" ");
+    let key = words(&cmd);
+    if !key.is_empty() && !alternatives.iter().any(|seen| words(seen) == key) {
+        alternatives.push(cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+    use std::time::Duration;
+
+    /// Greedy generation gives `ls`; samples are picked by seed
+    fn sampler() -> MockBackend {
+        MockBackend::new()
+            .with_command("list files", "ls")
+            .with_samples(["ls", "ls  -la", "ls -la", "", "ls -lh"])
+            .with_latency(Duration::from_millis(10))
+    }
+
+    #[test]
+    fn test_sampled_alternatives_are_distinct() {
+        let config = GenerationConfig::default().with_seed(0);
+        let backend = sampler();
+        assert_eq!(
+            sample_alternatives(&backend, "list files", 3, None, &config).unwrap(),
+            vec!["ls", "ls  -la", "ls -lh"]
+        );
+        // Samples are drawn hotter than the configured settings
+        assert!(backend
+            .requests()
+            .iter()
+            .filter_map(|r| r.config.as_ref())
+            .all(
+                |c| c.temperature == ALTERNATIVE_TEMPERATURE && c.top_k == Some(ALTERNATIVE_TOP_K)
+            ));
+        // Out of samples: fewer commands, never a repeat
+        assert_eq!(
+            sample_alternatives(&sampler(), "list files", 5, None, &config).unwrap(),
+            vec!["ls", "ls  -la", "ls -lh"]
+        );
+        assert!(
+            sample_alternatives(&sampler(), "list files", 0, None, &config)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parallel_alternatives_match_serial_ones() {
+        let config = GenerationConfig::default().with_seed(0);
+        for count in [3, 5] {
+            let serial =
+                sample_alternatives(&sampler(), "list files", count, None, &config).unwrap();
+            let backend = sampler().with_parallelism(4);
+            assert_eq!(
+                sample_alternatives(&backend, "list files", count, None, &config).unwrap(),
+                serial
+            );
+            assert!(backend.peak_concurrency() > 1);
+        }
     }
 }
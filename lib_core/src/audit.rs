@@ -0,0 +1,303 @@
+// Safety audit of shell scripts
+//
+// `eidos audit-script` runs every command of a script through the same risk
+// classifier and safety policy as generated commands. Scripts legitimately call
+// programs outside the whitelist and use quoting, variables and pipes, so only the
+// rules that point at real harm are reported: dangerous programs, entries from the
+// user's deny list, paths into /dev, /proc and /sys, flags like `find -exec`,
+// encoded characters and IFS tampering.
+//
+// The splitter understands enough shell to find the commands: comments, line
+// continuations, here-documents, `;` / `&&` / `||` lists and the keywords of
+// `if`, `while`, `for` and `case`. It is not a full parser; pipelines and
+// substitutions stay part of the command they appear in.
+
+use crate::risk::{classify_command, Risk};
+use crate::validation::{SafetyPolicy, SafetyRule, Violation};
+
+/// Rules reported by an audit; the others flag normal script syntax
+pub const AUDIT_RULES: [SafetyRule; 6] = [
+    SafetyRule::DeniedByPolicy,
+    SafetyRule::DangerousCommand,
+    SafetyRule::PathTraversal,
+    SafetyRule::DisallowedFlag,
+    SafetyRule::EncodedCharacters,
+    SafetyRule::IfsManipulation,
+];
+
+/// Words that open or close a compound command rather than run a program
+const KEYWORDS: [&str; 10] = [
+    "if", "then", "else", "elif", "fi", "while", "until", "do", "done", "esac",
+];
+
+/// One command of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCommand {
+    /// Line the command starts on, from 1
+    pub line: usize,
+    pub command: String,
+}
+
+/// A script command breaking at least one of the [`AUDIT_RULES`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AuditFinding {
+    pub line: usize,
+    pub command: String,
+    pub risk: Risk,
+    pub violations: Vec<Violation>,
+}
+
+impl AuditFinding {
+    /// Why the command deserves a second look
+    pub fn explanation(&self) -> String {
+        let reasons: Vec<String> = self
+            .violations
+            .iter()
+            .map(|v| format!("{}: {}", v, advice(v.rule)))
+            .collect();
+        reasons.join("; ")
+    }
+}
+
+/// What a reviewer should check for a broken rule
+pub fn advice(rule: SafetyRule) -> &'static str {
+    match rule {
+        SafetyRule::DeniedByPolicy => "listed in the [safety] deny entries",
+        SafetyRule::DangerousCommand => {
+            "deletes data, changes permissions or users, needs privileges or uses the network"
+        }
+        SafetyRule::PathTraversal => "reaches outside the working tree or into device files",
+        SafetyRule::DisallowedFlag => {
+            "makes a read-only command run programs, delete files or change settings"
+        }
+        SafetyRule::EncodedCharacters => "escape sequences can hide what actually runs",
+        SafetyRule::IfsManipulation => "changing IFS alters how every later command is split",
+        _ => rule.description(),
+    }
+}
+
+/// Audit every command in `script` against `policy`
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::audit::audit_script;
+/// use lib_core::{Risk, SafetyPolicy};
+///
+/// let script = "#!/bin/sh\nset -e\nmake build && rm -rf \"$OUT\"\necho done\n";
+/// let findings = audit_script(script, &SafetyPolicy::default());
+/// assert_eq!(findings.len(), 1);
+/// assert_eq!(findings[0].line, 3);
+/// assert_eq!(findings[0].command, "rm -rf \"$OUT\"");
+/// assert_eq!(findings[0].risk, Risk::Destructive);
+/// ```
+pub fn audit_script(script: &str, policy: &SafetyPolicy) -> Vec<AuditFinding> {
+    let commands = script_commands(script);
+    let texts: Vec<&str> = commands.iter().map(|c| c.command.as_str()).collect();
+    let reports = policy.validate_all(&texts);
+    commands
+        .into_iter()
+        .zip(reports)
+        .filter_map(|(c, report)| {
+            let violations: Vec<Violation> = report
+                .violations
+                .into_iter()
+                .filter(|v| AUDIT_RULES.contains(&v.rule))
+                .collect();
+            (!violations.is_empty()).then(|| AuditFinding {
+                line: c.line,
+                risk: classify_command(&c.command),
+                command: c.command,
+                violations,
+            })
+        })
+        .collect()
+}
+
+/// The commands of `script`, in order
+pub fn script_commands(script: &str) -> Vec<ScriptCommand> {
+    let mut commands = Vec::new();
+    let mut heredoc: Option<String> = None;
+    let mut pending: Option<(usize, String)> = None;
+
+    for (i, line) in script.lines().enumerate() {
+        if let Some(terminator) = &heredoc {
+            if line.trim() == terminator {
+                heredoc = None;
+            }
+            continue;
+        }
+
+        let (start, text) = match pending.take() {
+            Some((start, joined)) => (start, joined + strip_comment(line).trim_start()),
+            None => (i + 1, strip_comment(line).to_string()),
+        };
+        if let Some(joined) = text.strip_suffix('\\') {
+            pending = Some((start, format!("{} ", joined.trim_end())));
+            continue;
+        }
+
+        heredoc = heredoc_terminator(&text);
+        for part in split_list(&text) {
+            if let Some(command) = strip_keywords(part) {
+                commands.push(ScriptCommand {
+                    line: start,
+                    command: command.to_string(),
+                });
+            }
+        }
+    }
+    commands
+}
+
+/// `line` without a trailing `# comment`; `#` inside quotes or words is kept
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '#') if prev.is_whitespace() || i == 0 => return line[..i].trim_end(),
+            _ => {}
+        }
+        prev = c;
+    }
+    line.trim_end()
+}
+
+/// The word ending a here-document opened on `line` (`<<EOF`, `<<-'EOF'`)
+fn heredoc_terminator(line: &str) -> Option<String> {
+    let (_, rest) = line.split_once("<<")?;
+    if rest.starts_with('<') {
+        // A here-string, not a here-document
+        return None;
+    }
+    let rest = rest.strip_prefix('-').unwrap_or(rest).trim_start();
+    let word: String = rest
+        .chars()
+        .take_while(|c| !c.is_whitespace() && !matches!(c, ';' | '&' | '|' | ')'))
+        .filter(|c| !matches!(c, '\'' | '"'))
+        .collect();
+    (!word.is_empty()).then_some(word)
+}
+
+/// Split a command list on `;`, `&&` and `||` outside quotes
+fn split_list(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == b'\'' || c == b'"' => quote = Some(c),
+            None if c == b';' => {
+                parts.push(&text[start..i]);
+                // `;;` ends a case branch
+                if bytes.get(i + 1) == Some(&b';') {
+                    i += 1;
+                }
+                start = i + 1;
+            }
+            None if (c == b'&' || c == b'|') && bytes.get(i + 1) == Some(&c) => {
+                parts.push(&text[start..i]);
+                i += 1;
+                start = i + 1;
+            }
+            None => {}
+        }
+        i += 1;
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// The program part of a list element, without leading keywords; `None` for
+/// elements that run nothing (`fi`, `done`, `case x in`, `foo() {`, ...)
+fn strip_keywords(part: &str) -> Option<&str> {
+    let mut rest = part.trim();
+    loop {
+        let word = rest.split_whitespace().next()?;
+        // `clean)` opens a case branch
+        let is_case_pattern = word.ends_with(')') && !word.contains('(');
+        if KEYWORDS.contains(&word) || is_case_pattern || matches!(word, "!" | "{" | "}" | "(") {
+            rest = rest[word.len()..].trim_start();
+        } else {
+            break;
+        }
+    }
+
+    let first = rest.split_whitespace().next()?;
+    let is_function = first.ends_with("()") || rest.starts_with("function ");
+    let is_header = matches!(first, "case" | "for" | "select");
+    if is_function || is_header {
+        return None;
+    }
+    Some(rest.trim_end_matches(['{', '}']).trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::SafetyLevel;
+
+    #[test]
+    fn test_script_commands() {
+        let script = "#!/bin/bash\n\
+            # cleanup script\n\
+            set -euo pipefail\n\
+            if [ -d build ]; then\n\
+            \x20 rm -rf build # stale output\n\
+            fi\n\
+            cat <<EOF > notes.txt\n\
+            rm everything in here is text\n\
+            EOF\n\
+            tar -czf out.tar.gz \\\n\
+            \x20 src docs\n\
+            case \"$1\" in\n\
+            \x20 clean) make clean ;;\n\
+            esac\n\
+            deploy() {\n\
+            \x20 scp out.tar.gz host:/srv || echo 'failed; retry'\n\
+            }\n";
+        let commands: Vec<(usize, String)> = script_commands(script)
+            .into_iter()
+            .map(|c| (c.line, c.command))
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                (3, "set -euo pipefail".to_string()),
+                (4, "[ -d build ]".to_string()),
+                (5, "rm -rf build".to_string()),
+                (7, "cat <<EOF > notes.txt".to_string()),
+                (10, "tar -czf out.tar.gz src docs".to_string()),
+                (13, "make clean".to_string()),
+                (16, "scp out.tar.gz host:/srv".to_string()),
+                (16, "echo 'failed; retry'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_audit_reports_only_harmful_rules() {
+        let script = "ls \"$HOME\" | grep -v '*.tmp'\nsudo systemctl restart app\nIFS=,\n";
+        let policy = SafetyPolicy::new(SafetyLevel::Standard).with_deny(["sudo systemctl"]);
+        let findings = audit_script(script, &policy);
+
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].line, 2);
+        let rules: Vec<SafetyRule> = findings[0].violations.iter().map(|v| v.rule).collect();
+        assert_eq!(
+            rules,
+            vec![SafetyRule::DeniedByPolicy, SafetyRule::DangerousCommand]
+        );
+        assert!(findings[0].explanation().contains("needs privileges"));
+        assert_eq!(findings[1].violations[0].rule, SafetyRule::IfsManipulation);
+    }
+}
@@ -0,0 +1,195 @@
+// Common interface over the command generation backends
+//
+// `Core` runs seq2seq ONNX models through tract in a single decoding pass;
+// `QuantizedLlm` runs chat-tuned GGUF models through candle token by token. Callers
+// that only need "request in, command out" (the CLI, the daemon) hold a
+// `dyn InferenceBackend` and pick the implementation from configuration.
+
+use crate::alternatives;
+use crate::generation::GenerationConfig;
+use crate::prompt::PromptTemplate;
+use anyhow::Result;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Which inference engine runs a model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BackendKind {
+    /// ONNX models through tract (`onnx` feature)
+    #[default]
+    Onnx,
+    /// Quantized GGUF models through candle (`gguf` feature)
+    Gguf,
+}
+
+impl BackendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::Onnx => "onnx",
+            BackendKind::Gguf => "gguf",
+        }
+    }
+
+    /// Backend implied by the model file extension, defaulting to ONNX
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_core::BackendKind;
+    ///
+    /// assert_eq!(BackendKind::from_path("models/mistral.Q4_K_M.gguf"), BackendKind::Gguf);
+    /// assert_eq!(BackendKind::from_path("model.onnx"), BackendKind::Onnx);
+    /// ```
+    pub fn from_path(model_path: impl AsRef<Path>) -> Self {
+        match model_path.as_ref().extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("gguf") => BackendKind::Gguf,
+            _ => BackendKind::Onnx,
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "onnx" => Ok(BackendKind::Onnx),
+            "gguf" => Ok(BackendKind::Gguf),
+            other => Err(format!(
+                "Unknown backend '{}' (expected onnx or gguf)",
+                other
+            )),
+        }
+    }
+}
+
+/// Description of a loaded model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    pub backend: BackendKind,
+    pub model_path: PathBuf,
+    pub template: PromptTemplate,
+    pub vocab_size: usize,
+    /// Name of the generation preset applied, if any
+    pub preset: Option<String>,
+    /// Device the model runs on, e.g. `cpu` or `cuda:0`
+    pub device: String,
+}
+
+/// A model that turns natural language requests into shell commands
+///
+/// Implemented by [`crate::Core`] and [`crate::QuantizedLlm`], and by
+/// [`crate::MockBackend`] for tests without model files. Safety validation
+/// is not part of the backend; run the output through a
+/// [`crate::SafetyPolicy`] before showing it.
+pub trait InferenceBackend: Send + Sync {
+    /// Generate a command with the backend's configured settings
+    ///
+    /// `context` (e.g. a snapshot of the user's environment) is added to the
+    /// system prompt.
+    fn generate(&self, request: &str, context: Option<&str>) -> Result<String>;
+
+    /// Generate a command with explicit sampling settings
+    ///
+    /// Backends that decode in a single pass only honour the stop sequences.
+    fn generate_with_config(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> Result<String>;
+
+    /// Describe what `command` does
+    fn explain(&self, command: &str) -> Result<String>;
+
+    /// Explain several commands; results are in the order of `commands`
+    fn explain_all(&self, commands: &[String]) -> Vec<Result<String>> {
+        commands.iter().map(|cmd| self.explain(cmd)).collect()
+    }
+
+    /// Generations that can run at once without waiting for each other
+    ///
+    /// Alternatives are generated this many at a time. Backends that decode one
+    /// request at a time keep the default of 1.
+    fn parallelism(&self) -> usize {
+        1
+    }
+
+    /// Sampling settings of a backend that samples token by token
+    ///
+    /// Such a backend draws alternatives from samples of the request; the others
+    /// get them from rephrasings of it.
+    fn sampling(&self) -> Option<GenerationConfig> {
+        None
+    }
+
+    /// Up to `count` different commands for the same request, best first
+    fn alternatives(
+        &self,
+        request: &str,
+        count: usize,
+        context: Option<&str>,
+    ) -> Result<Vec<String>> {
+        match self.sampling() {
+            Some(config) => {
+                alternatives::sample_alternatives(self, request, count, context, &config)
+            }
+            None => alternatives::generate_alternatives(self, request, count, context),
+        }
+    }
+
+    fn model_info(&self) -> ModelInfo;
+}
+
+/// Command generation, under the name embedders and tests program against
+///
+/// Every [`InferenceBackend`] is a `CommandGenerator`, so `Core`, `QuantizedLlm`
+/// and [`crate::MockGenerator`] can be passed wherever one is expected.
+pub trait CommandGenerator: InferenceBackend {}
+
+impl<T: InferenceBackend + ?Sized> CommandGenerator for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_kind() {
+        assert_eq!(" GGUF ".parse::<BackendKind>(), Ok(BackendKind::Gguf));
+        assert!("pytorch".parse::<BackendKind>().is_err());
+        assert_eq!(BackendKind::from_path("MODEL.GGUF"), BackendKind::Gguf);
+        assert_eq!(BackendKind::from_path("model"), BackendKind::Onnx);
+    }
+
+    #[test]
+    fn test_default_methods() {
+        let backend: &dyn InferenceBackend = &crate::MockBackend::new()
+            .with_command("list files verbose", "ls -la")
+            .with_fallback("ls");
+        assert_eq!(
+            backend.alternatives("list files", 3, None).unwrap(),
+            vec!["ls", "ls -la"]
+        );
+        let explained = backend.explain_all(&["ls".to_string(), "pwd".to_string()]);
+        assert_eq!(explained[1].as_ref().unwrap(), "Runs `pwd`");
+    }
+
+    #[test]
+    fn test_backends_are_command_generators() {
+        fn first_word(generator: &dyn CommandGenerator, request: &str) -> String {
+            let command = generator.generate(request, None).unwrap();
+            command.split_whitespace().next().unwrap().to_string()
+        }
+
+        let mock = crate::MockGenerator::new().with_command("list files", "ls -la");
+        assert_eq!(first_word(&mock, "list files"), "ls");
+        assert_eq!(mock.calls(), 1);
+    }
+}
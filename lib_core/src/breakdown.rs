@@ -0,0 +1,607 @@
+// Flag-by-flag breakdown of shell commands
+//
+// `eidos explain` pairs the model's prose explanation with a structured listing
+// of what each program and option in the command is. The listing does not need a
+// model: the command is split into the programs of its pipeline, clustered short
+// options are expanded (`-xzvf` is `-x -z -v -f`), and options of common programs
+// are looked up in a small built-in table. Options missing from the table are
+// still listed, just without a description.
+
+/// An option of a [`ProgramSpec`]: name, whether it takes a value, description
+type OptionSpec = (&'static str, bool, &'static str);
+
+/// Options of a program eidos can describe
+struct ProgramSpec {
+    program: &'static str,
+    /// Long options are written with a single dash (`find -name`)
+    single_dash_long: bool,
+    /// Options may be written without a leading dash as the first argument (`tar xzf`)
+    bare_first_word: bool,
+    options: &'static [OptionSpec],
+}
+
+const PROGRAMS: &[ProgramSpec] = &[
+    ProgramSpec {
+        program: "tar",
+        single_dash_long: false,
+        bare_first_word: true,
+        options: &[
+            ("-c", false, "create a new archive"),
+            ("-x", false, "extract files from an archive"),
+            ("-t", false, "list the contents of an archive"),
+            ("-r", false, "append files to an archive"),
+            ("-z", false, "filter the archive through gzip"),
+            ("-j", false, "filter the archive through bzip2"),
+            ("-J", false, "filter the archive through xz"),
+            ("-v", false, "list files as they are processed"),
+            ("-f", true, "use the given archive file"),
+            ("-C", true, "change to the given directory first"),
+            ("-p", false, "preserve file permissions"),
+            ("--exclude", true, "skip files matching the pattern"),
+        ],
+    },
+    ProgramSpec {
+        program: "ls",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-a", false, "include hidden entries starting with ."),
+            ("-A", false, "include hidden entries except . and .."),
+            ("-l", false, "use the long listing format"),
+            ("-h", false, "print sizes in human-readable units"),
+            ("-t", false, "sort by modification time, newest first"),
+            ("-S", false, "sort by file size, largest first"),
+            ("-r", false, "reverse the sort order"),
+            ("-R", false, "list subdirectories recursively"),
+            (
+                "-d",
+                false,
+                "list directories themselves, not their contents",
+            ),
+            ("-1", false, "list one entry per line"),
+        ],
+    },
+    ProgramSpec {
+        program: "grep",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-i", false, "ignore case"),
+            ("-r", false, "search directories recursively"),
+            (
+                "-R",
+                false,
+                "search directories recursively, following symlinks",
+            ),
+            ("-n", false, "prefix matches with their line number"),
+            ("-v", false, "select non-matching lines"),
+            ("-l", false, "print only the names of matching files"),
+            ("-c", false, "print only a count of matching lines"),
+            ("-w", false, "match whole words only"),
+            ("-E", false, "use extended regular expressions"),
+            ("-F", false, "treat the pattern as a fixed string"),
+            ("-o", false, "print only the matching part of each line"),
+            ("-e", true, "use the given pattern"),
+            ("-A", true, "print lines of context after each match"),
+            ("-B", true, "print lines of context before each match"),
+            ("-C", true, "print lines of context around each match"),
+            ("--include", true, "search only files matching the glob"),
+            ("--exclude", true, "skip files matching the glob"),
+        ],
+    },
+    ProgramSpec {
+        program: "find",
+        single_dash_long: true,
+        bare_first_word: false,
+        options: &[
+            ("-name", true, "match file names against the pattern"),
+            (
+                "-iname",
+                true,
+                "match file names against the pattern, ignoring case",
+            ),
+            ("-path", true, "match paths against the pattern"),
+            (
+                "-type",
+                true,
+                "match files of the given type (f file, d directory, l link)",
+            ),
+            ("-size", true, "match files of the given size"),
+            (
+                "-mtime",
+                true,
+                "match files modified the given number of days ago",
+            ),
+            (
+                "-mmin",
+                true,
+                "match files modified the given number of minutes ago",
+            ),
+            (
+                "-newer",
+                true,
+                "match files modified more recently than the given file",
+            ),
+            ("-user", true, "match files owned by the given user"),
+            ("-perm", true, "match files with the given permission bits"),
+            (
+                "-maxdepth",
+                true,
+                "descend at most the given number of levels",
+            ),
+            ("-mindepth", true, "skip matches above the given depth"),
+            ("-empty", false, "match empty files and directories"),
+            ("-print", false, "print the path of each match"),
+            ("-print0", false, "print each path followed by a NUL byte"),
+            ("-delete", false, "delete each match"),
+            ("-exec", true, "run the given command on each match"),
+        ],
+    },
+    ProgramSpec {
+        program: "rm",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            (
+                "-r",
+                false,
+                "remove directories and their contents recursively",
+            ),
+            (
+                "-R",
+                false,
+                "remove directories and their contents recursively",
+            ),
+            ("-f", false, "ignore missing files and never prompt"),
+            ("-i", false, "prompt before every removal"),
+            ("-d", false, "remove empty directories"),
+            ("-v", false, "print each file as it is removed"),
+        ],
+    },
+    ProgramSpec {
+        program: "cp",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-r", false, "copy directories recursively"),
+            ("-R", false, "copy directories recursively"),
+            (
+                "-a",
+                false,
+                "archive mode: copy recursively, preserving attributes",
+            ),
+            ("-p", false, "preserve mode, ownership and timestamps"),
+            ("-i", false, "prompt before overwriting"),
+            ("-n", false, "never overwrite existing files"),
+            ("-u", false, "copy only when the source is newer"),
+            ("-v", false, "print each file as it is copied"),
+        ],
+    },
+    ProgramSpec {
+        program: "mv",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-i", false, "prompt before overwriting"),
+            ("-n", false, "never overwrite existing files"),
+            ("-f", false, "never prompt before overwriting"),
+            ("-u", false, "move only when the source is newer"),
+            ("-v", false, "print each file as it is moved"),
+        ],
+    },
+    ProgramSpec {
+        program: "du",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-s", false, "print only a total for each argument"),
+            ("-h", false, "print sizes in human-readable units"),
+            ("-a", false, "include files, not just directories"),
+            ("-c", false, "print a grand total"),
+            ("-d", true, "descend at most the given number of levels"),
+            (
+                "--max-depth",
+                true,
+                "descend at most the given number of levels",
+            ),
+        ],
+    },
+    ProgramSpec {
+        program: "df",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-h", false, "print sizes in human-readable units"),
+            ("-T", false, "print the filesystem type"),
+            ("-i", false, "show inode usage instead of block usage"),
+            ("-t", true, "only show filesystems of the given type"),
+        ],
+    },
+    ProgramSpec {
+        program: "ps",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-a", false, "show processes of all users"),
+            ("-e", false, "show every process"),
+            ("-f", false, "use the full listing format"),
+            ("-u", true, "show processes of the given user"),
+            ("-x", false, "include processes without a terminal"),
+        ],
+    },
+    ProgramSpec {
+        program: "chmod",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-R", false, "change files and directories recursively"),
+            ("-v", false, "print each file as it is processed"),
+        ],
+    },
+    ProgramSpec {
+        program: "chown",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-R", false, "change files and directories recursively"),
+            ("-v", false, "print each file as it is processed"),
+        ],
+    },
+    ProgramSpec {
+        program: "mkdir",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-p", false, "create parent directories as needed"),
+            ("-m", true, "set the permission mode"),
+            ("-v", false, "print each directory as it is created"),
+        ],
+    },
+    ProgramSpec {
+        program: "head",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-n", true, "print the given number of lines"),
+            ("-c", true, "print the given number of bytes"),
+        ],
+    },
+    ProgramSpec {
+        program: "tail",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-n", true, "print the given number of lines from the end"),
+            ("-c", true, "print the given number of bytes from the end"),
+            ("-f", false, "keep printing data as the file grows"),
+            (
+                "-F",
+                false,
+                "keep following the file, even if it is replaced",
+            ),
+        ],
+    },
+    ProgramSpec {
+        program: "sort",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-n", false, "compare numerically"),
+            ("-h", false, "compare human-readable sizes (2K, 1G)"),
+            ("-r", false, "reverse the sort order"),
+            ("-u", false, "drop duplicate lines"),
+            ("-k", true, "sort by the given key field"),
+            ("-t", true, "use the given field separator"),
+        ],
+    },
+    ProgramSpec {
+        program: "wc",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-l", false, "count lines"),
+            ("-w", false, "count words"),
+            ("-c", false, "count bytes"),
+            ("-m", false, "count characters"),
+        ],
+    },
+    ProgramSpec {
+        program: "curl",
+        single_dash_long: false,
+        bare_first_word: false,
+        options: &[
+            ("-L", false, "follow redirects"),
+            ("-O", false, "save to a file named like the remote file"),
+            ("-o", true, "save to the given file"),
+            ("-s", false, "silent: no progress or errors"),
+            ("-S", false, "show errors even when silent"),
+            ("-f", false, "fail on HTTP errors without printing the body"),
+            ("-I", false, "fetch only the response headers"),
+            ("-X", true, "use the given request method"),
+            ("-H", true, "add the given request header"),
+            ("-d", true, "send the given data in the request body"),
+        ],
+    },
+];
+
+/// An option in a command, with its value and description when known
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandFlag {
+    /// The option as written on its own, e.g. `-x` or `--max-depth`
+    pub flag: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub value: Option<String>,
+    /// What the option does, for options of the programs eidos knows
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub description: Option<String>,
+}
+
+/// One program of a pipeline or command list, with its options and operands
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandPart {
+    pub program: String,
+    /// The program is run through `sudo`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub sudo: bool,
+    pub flags: Vec<CommandFlag>,
+    /// Remaining arguments: files, patterns, subcommands
+    pub operands: Vec<String>,
+}
+
+/// Break `command` into its programs and list the options given to each
+///
+/// Pipelines and command lists (`|`, `&&`, `||`, `;`) yield one part per
+/// program. Quotes group words but are otherwise kept as written.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::breakdown::breakdown_command;
+///
+/// let parts = breakdown_command("tar -xzvf file.tar.gz");
+/// let flags: Vec<&str> = parts[0].flags.iter().map(|f| f.flag.as_str()).collect();
+/// assert_eq!(flags, ["-x", "-z", "-v", "-f"]);
+/// assert_eq!(parts[0].flags[3].value.as_deref(), Some("file.tar.gz"));
+/// assert!(parts[0].operands.is_empty());
+/// ```
+pub fn breakdown_command(command: &str) -> Vec<CommandPart> {
+    split_segments(command)
+        .iter()
+        .filter_map(|words| part(words))
+        .collect()
+}
+
+fn part(words: &[String]) -> Option<CommandPart> {
+    let (sudo, words) = match words.split_first() {
+        Some((first, rest)) if first == "sudo" => (true, rest),
+        _ => (false, words),
+    };
+    let (program, args) = words.split_first()?;
+    let spec = PROGRAMS.iter().find(|spec| spec.program == program);
+
+    let mut flags = Vec::new();
+    let mut operands = Vec::new();
+    let mut args = args.iter().enumerate().peekable();
+    while let Some((i, arg)) = args.next() {
+        let bare_cluster = i == 0
+            && spec.is_some_and(|s| s.bare_first_word)
+            && !arg.starts_with('-')
+            && arg.chars().all(|c| c.is_ascii_alphabetic());
+
+        if arg == "--" {
+            operands.extend(args.by_ref().map(|(_, a)| a.clone()));
+        } else if let Some(long) = arg.strip_prefix("--").filter(|l| !l.is_empty()) {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (format!("--{}", name), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let known = lookup(spec, &name);
+            let value = match value {
+                Some(value) => Some(value),
+                None if known.is_some_and(|(takes_value, _)| takes_value) => {
+                    args.next().map(|(_, a)| a.clone())
+                }
+                None => None,
+            };
+            flags.push(flag(name, value, known));
+        } else if spec.is_some_and(|s| s.single_dash_long) && is_option(arg) {
+            let known = lookup(spec, arg);
+            let value = if arg == "-exec" {
+                // The command runs up to the terminating `;` or `+`
+                let rest: Vec<String> = args
+                    .by_ref()
+                    .map(|(_, a)| a.clone())
+                    .take_while(|a| a != ";" && a != "\\;" && a != "+")
+                    .collect();
+                Some(rest.join(" "))
+            } else if known.is_some_and(|(takes_value, _)| takes_value) {
+                args.next().map(|(_, a)| a.clone())
+            } else {
+                None
+            };
+            flags.push(flag(arg.clone(), value, known));
+        } else if is_option(arg) || bare_cluster {
+            let cluster = arg.strip_prefix('-').unwrap_or(arg);
+            for (pos, c) in cluster.char_indices() {
+                let name = format!("-{}", c);
+                let known = lookup(spec, &name);
+                if known.is_some_and(|(takes_value, _)| takes_value) {
+                    // `-n20` carries its value; `-xzvf file` takes the next word
+                    let rest = &cluster[pos + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next().map(|(_, a)| a.clone())
+                    } else {
+                        Some(rest.to_string())
+                    };
+                    flags.push(flag(name, value, known));
+                    break;
+                }
+                flags.push(flag(name, None, known));
+            }
+        } else {
+            operands.push(arg.clone());
+        }
+    }
+
+    Some(CommandPart {
+        program: program.clone(),
+        sudo,
+        flags,
+        operands,
+    })
+}
+
+fn flag(flag: String, value: Option<String>, known: Option<(bool, &str)>) -> CommandFlag {
+    CommandFlag {
+        flag,
+        value,
+        description: known.map(|(_, description)| description.to_string()),
+    }
+}
+
+/// Whether `option` takes a value, and its description
+fn lookup(spec: Option<&ProgramSpec>, option: &str) -> Option<(bool, &'static str)> {
+    spec?
+        .options
+        .iter()
+        .find(|(name, _, _)| *name == option)
+        .map(|&(_, takes_value, description)| (takes_value, description))
+}
+
+/// `-x` style options; `-` alone (stdin) and negative numbers are operands
+fn is_option(arg: &str) -> bool {
+    arg.len() > 1
+        && arg.starts_with('-')
+        && !arg[1..].chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+/// Words of each program in `command`, split at `|`, `&&`, `||` and `;`
+fn split_segments(command: &str) -> Vec<Vec<String>> {
+    let mut segments = vec![Vec::new()];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = command.chars().peekable();
+
+    let end_word = |word: &mut String, segments: &mut Vec<Vec<String>>| {
+        if !word.is_empty() {
+            segments
+                .last_mut()
+                .expect("segments is never empty")
+                .push(std::mem::take(word));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                word.push(c);
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (None, '\\') => {
+                word.push(c);
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            (None, '|' | ';' | '&') => {
+                // `2>&1` and a trailing `&` stay part of the current program
+                if c == '&' && (word.ends_with('>') || chars.peek() != Some(&'&')) {
+                    word.push(c);
+                    continue;
+                }
+                end_word(&mut word, &mut segments);
+                if matches!(chars.peek(), Some('|' | '&')) {
+                    chars.next();
+                }
+                segments.push(Vec::new());
+            }
+            (None, c) if c.is_whitespace() => end_word(&mut word, &mut segments),
+            (None, c) => word.push(c),
+        }
+    }
+    end_word(&mut word, &mut segments);
+    segments.retain(|words| !words.is_empty());
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(part: &CommandPart) -> Vec<(&str, Option<&str>)> {
+        part.flags
+            .iter()
+            .map(|f| (f.flag.as_str(), f.value.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_clustered_and_valued_options() {
+        let parts = breakdown_command("tar xzf backup.tar.gz -C /tmp");
+        assert_eq!(
+            flags(&parts[0]),
+            [
+                ("-x", None),
+                ("-z", None),
+                ("-f", Some("backup.tar.gz")),
+                ("-C", Some("/tmp"))
+            ]
+        );
+        assert_eq!(
+            parts[0].flags[1].description.as_deref(),
+            Some("filter the archive through gzip")
+        );
+
+        let parts = breakdown_command("tail -n20 --lines=5 app.log -");
+        assert_eq!(
+            flags(&parts[0]),
+            [("-n", Some("20")), ("--lines", Some("5"))]
+        );
+        assert_eq!(parts[0].operands, ["app.log", "-"]);
+    }
+
+    #[test]
+    fn test_unknown_options_are_listed_without_description() {
+        let parts = breakdown_command("sudo rsync -avz --delete src/ dest/");
+        assert!(parts[0].sudo);
+        assert_eq!(parts[0].program, "rsync");
+        assert_eq!(
+            flags(&parts[0]),
+            [("-a", None), ("-v", None), ("-z", None), ("--delete", None)]
+        );
+        assert!(parts[0].flags.iter().all(|f| f.description.is_none()));
+        assert_eq!(parts[0].operands, ["src/", "dest/"]);
+    }
+
+    #[test]
+    fn test_single_dash_long_options() {
+        let parts = breakdown_command("find . -type f -name '*.log' -exec rm {} \\;");
+        assert_eq!(
+            flags(&parts[0]),
+            [
+                ("-type", Some("f")),
+                ("-name", Some("'*.log'")),
+                ("-exec", Some("rm {}"))
+            ]
+        );
+        assert_eq!(parts[0].operands, ["."]);
+    }
+
+    #[test]
+    fn test_pipelines_and_lists() {
+        let parts = breakdown_command("du -sh * 2>&1 | sort -rh && echo 'a | b'; ls");
+        let programs: Vec<&str> = parts.iter().map(|p| p.program.as_str()).collect();
+        assert_eq!(programs, ["du", "sort", "echo", "ls"]);
+        assert_eq!(parts[0].operands, ["*", "2>&1"]);
+        assert_eq!(flags(&parts[1]), [("-r", None), ("-h", None)]);
+        assert_eq!(parts[2].operands, ["'a | b'"]);
+        assert!(breakdown_command("  ").is_empty());
+    }
+}
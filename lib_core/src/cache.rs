@@ -0,0 +1,314 @@
+// Cache of generated commands
+//
+// Generating a command takes seconds with a large model, and the same prompts come
+// back again and again ("show disk usage" from the shell widget every morning).
+// `CommandCache` remembers the command generated for a request, keyed by a SHA-256
+// of everything that decides it: the model (an identity string the caller derives
+// from its settings and the files on disk), the request and its context. A cached
+// command is returned without loading the model at all; explanations are cached
+// the same way, keyed by the command they explain.
+//
+// Each entry is a small file in a directory chosen by the caller (the CLI uses
+// ~/.cache/eidos/commands). The first line holds the creation time and the number
+// of hits; the rest is the cached text. Entries expire after a time to live, and
+// the oldest are dropped when there are more than `max_entries`. Files are
+// replaced by renaming, so concurrent invocations never read half an entry.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long an entry is used by default
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Entries kept by default before the oldest are dropped
+pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Extension of entry files
+const EXTENSION: &str = "entry";
+
+/// Size and use of a cache directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    /// Times entries were used instead of generating
+    pub hits: u64,
+    /// Age of the oldest entry
+    pub oldest: Option<Duration>,
+}
+
+/// Generated commands and explanations for one model
+#[derive(Debug, Clone)]
+pub struct CommandCache {
+    dir: PathBuf,
+    model: String,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl CommandCache {
+    /// Cache in `dir` for the model identified by `model`
+    ///
+    /// `model` must change whenever the model would answer differently: its
+    /// files, prompt template, sampling settings and so on.
+    pub fn new(dir: impl Into<PathBuf>, model: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            model: model.into(),
+            ttl: DEFAULT_TTL,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+
+    /// Use entries for `ttl` after they were stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Keep at most `max_entries`, dropping the oldest
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// The command cached for `request` with `context`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_core::cache::CommandCache;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("eidos-doc-cache-{}", std::process::id()));
+    /// let cache = CommandCache::new(&dir, "codellama-7b.Q4_K_M.gguf");
+    /// assert_eq!(cache.command("show disk usage", None), None);
+    ///
+    /// cache.put_command("show disk usage", None, "df -h").unwrap();
+    /// assert_eq!(cache.command("show disk usage", None).as_deref(), Some("df -h"));
+    /// // Another model has its own entries
+    /// assert_eq!(CommandCache::new(&dir, "mistral").command("show disk usage", None), None);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn command(&self, request: &str, context: Option<&str>) -> Option<String> {
+        self.get(&self.key("command", request, context))
+    }
+
+    pub fn put_command(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        command: &str,
+    ) -> io::Result<()> {
+        self.put(&self.key("command", request, context), command)
+    }
+
+    /// The explanation cached for `command`
+    pub fn explanation(&self, command: &str) -> Option<String> {
+        self.get(&self.key("explanation", command, None))
+    }
+
+    pub fn put_explanation(&self, command: &str, explanation: &str) -> io::Result<()> {
+        self.put(&self.key("explanation", command, None), explanation)
+    }
+
+    /// Entry file for a request, named after the hash of its parts
+    fn key(&self, kind: &str, text: &str, context: Option<&str>) -> PathBuf {
+        let mut hasher = Sha256::new();
+        // Length-prefixed, so no two different requests hash the same bytes
+        for part in [self.model.as_str(), kind, text, context.unwrap_or("")] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.update([context.is_some() as u8]);
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.dir.join(format!("{}.{}", hex, EXTENSION))
+    }
+
+    /// The cached text in `path`, counting the hit; missing, expired or
+    /// unreadable entries are misses
+    fn get(&self, path: &Path) -> Option<String> {
+        let contents = fs::read_to_string(path).ok()?;
+        let (header, text) = contents.split_once('\n')?;
+        let (created, hits) = parse_header(header)?;
+        if lib_util::unix_now().saturating_sub(created) >= self.ttl.as_secs() {
+            return None;
+        }
+        // Counting is best effort; a lost update only skews `stats`
+        let _ = write_entry(path, created, hits + 1, text);
+        Some(text.to_string())
+    }
+
+    fn put(&self, path: &Path, text: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_entry(path, lib_util::unix_now(), 0, text)?;
+        prune(&self.dir, self.ttl, self.max_entries)
+    }
+}
+
+/// Remove every entry in `dir`, returning how many there were
+pub fn clear(dir: &Path) -> io::Result<usize> {
+    let entries = entries(dir)?;
+    for (path, _) in &entries {
+        remove_if_present(path)?;
+    }
+    Ok(entries.len())
+}
+
+/// Entries in `dir`, their total size and hits
+pub fn stats(dir: &Path) -> io::Result<CacheStats> {
+    let mut stats = CacheStats::default();
+    let now = lib_util::unix_now();
+    for (path, metadata) in entries(dir)? {
+        let Some((created, hits)) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| parse_header(contents.lines().next()?))
+        else {
+            continue;
+        };
+        stats.entries += 1;
+        stats.bytes += metadata.len();
+        stats.hits += hits;
+        let age = Duration::from_secs(now.saturating_sub(created));
+        stats.oldest = stats.oldest.max(Some(age));
+    }
+    Ok(stats)
+}
+
+/// Drop expired entries, then the oldest beyond `max_entries`
+fn prune(dir: &Path, ttl: Duration, max_entries: usize) -> io::Result<()> {
+    let mut entries: Vec<(PathBuf, SystemTime)> = entries(dir)?
+        .into_iter()
+        .map(|(path, metadata)| (path, metadata.modified().unwrap_or(UNIX_EPOCH)))
+        .collect();
+    // Newest first; hits rewrite an entry, so often used ones stay
+    entries.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    let expired = SystemTime::now().checked_sub(ttl).unwrap_or(UNIX_EPOCH);
+    for (i, (path, modified)) in entries.iter().enumerate() {
+        if i >= max_entries || *modified < expired {
+            remove_if_present(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn entries(dir: &Path) -> io::Result<Vec<(PathBuf, fs::Metadata)>> {
+    let read_dir = match fs::read_dir(dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        result => result?,
+    };
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            entries.push((path, entry.metadata()?));
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_header(header: &str) -> Option<(u64, u64)> {
+    let (created, hits) = header.split_once(' ')?;
+    Some((created.parse().ok()?, hits.parse().ok()?))
+}
+
+/// Replace the entry at `path` in one step
+fn write_entry(path: &Path, created: u64, hits: u64, text: &str) -> io::Result<()> {
+    lib_util::write_atomic(path, format!("{} {}\n{}", created, hits, text))
+}
+
+fn remove_if_present(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_are_keyed_by_model_request_and_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CommandCache::new(dir.path(), "model-a");
+        cache
+            .put_command(
+                "list my branches",
+                Some("Inside a git repository: yes"),
+                "git branch",
+            )
+            .unwrap();
+        cache
+            .put_explanation("git branch", "Lists local branches.")
+            .unwrap();
+
+        assert_eq!(
+            cache
+                .command("list my branches", Some("Inside a git repository: yes"))
+                .as_deref(),
+            Some("git branch")
+        );
+        assert_eq!(cache.command("list my branches", None), None);
+        assert_eq!(cache.command("list my branches", Some("")), None);
+        assert_eq!(
+            CommandCache::new(dir.path(), "model-b")
+                .command("list my branches", Some("Inside a git repository: yes")),
+            None
+        );
+        assert_eq!(
+            cache.explanation("git branch").as_deref(),
+            Some("Lists local branches.")
+        );
+
+        let found = stats(dir.path()).unwrap();
+        assert_eq!((found.entries, found.hits), (2, 2));
+        assert!(found.bytes > 0);
+        assert_eq!(clear(dir.path()).unwrap(), 2);
+        assert_eq!(stats(dir.path()).unwrap(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_expired_and_excess_entries_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CommandCache::new(dir.path(), "model").with_ttl(Duration::ZERO);
+        cache.put_command("show disk usage", None, "df -h").unwrap();
+        assert_eq!(cache.command("show disk usage", None), None);
+
+        let cache = CommandCache::new(dir.path(), "model").with_max_entries(2);
+        for request in ["one", "two", "three"] {
+            cache.put_command(request, None, "true").unwrap();
+            // Distinct modification times
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(stats(dir.path()).unwrap().entries, 2);
+        assert_eq!(cache.command("one", None), None);
+        assert_eq!(cache.command("three", None).as_deref(), Some("true"));
+    }
+
+    #[test]
+    fn test_multiline_text_and_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CommandCache::new(dir.path().join("nested"), "model");
+        assert_eq!(
+            stats(&dir.path().join("nested")).unwrap(),
+            CacheStats::default()
+        );
+
+        let script = "for f in *.log; do\n  gzip \"$f\"\ndone";
+        cache
+            .put_command("compress the logs", None, script)
+            .unwrap();
+        assert_eq!(
+            cache.command("compress the logs", None).as_deref(),
+            Some(script)
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Clarifying questions for vague prompts
+//
+// "delete old log files" leaves the model to guess where the files are and how old
+// is old; it usually picks the current directory and an arbitrary age. A cheap
+// keyword classifier spots the most common gaps (no directory for a bulk file
+// operation, no size or age for "large" or "old", no port number) so an interactive
+// caller can ask one question before generating and add the answer to the prompt.
+// Only the first gap found is asked about: one question is cheap, an interview is not.
+
+/// A question about something the prompt leaves open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clarification {
+    pub question: &'static str,
+    /// What the answer supplies, e.g. `directory`
+    pub label: &'static str,
+}
+
+impl Clarification {
+    /// `prompt` with `answer` spelled out for the model
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_core::clarify::clarifying_question;
+    ///
+    /// let clarification = clarifying_question("delete old log files").unwrap();
+    /// assert_eq!(clarification.question, "Which directory?");
+    /// assert_eq!(
+    ///     clarification.apply("delete old log files", "/var/log"),
+    ///     "delete old log files (directory: /var/log)"
+    /// );
+    /// ```
+    pub fn apply(&self, prompt: &str, answer: &str) -> String {
+        format!("{} ({}: {})", prompt.trim(), self.label, answer.trim())
+    }
+}
+
+const LOCATION: Clarification = Clarification {
+    question: "Which directory?",
+    label: "directory",
+};
+
+const SIZE: Clarification = Clarification {
+    question: "How large, e.g. 100M?",
+    label: "size",
+};
+
+const AGE: Clarification = Clarification {
+    question: "How old, e.g. 7 days?",
+    label: "age",
+};
+
+const PORT: Clarification = Clarification {
+    question: "Which port?",
+    label: "port",
+};
+
+/// Verbs that act on many files at once, where the directory matters
+const BULK_VERBS: &[&str] = &[
+    "archive", "backup", "chmod", "chown", "clean", "compress", "copy", "count", "delete", "find",
+    "move", "remove", "search", "zip",
+];
+
+const FILE_NOUNS: &[&str] = &[
+    "directories",
+    "files",
+    "folders",
+    "images",
+    "logs",
+    "photos",
+    "pictures",
+    "videos",
+];
+
+/// Words that already name a directory
+const HERE: &[&str] = &["here", "cwd", "current", "this", "home"];
+
+const SIZE_WORDS: &[&str] = &[
+    "large", "big", "bigger", "huge", "larger", "small", "smaller",
+];
+
+const AGE_WORDS: &[&str] = &[
+    "old", "older", "recent", "recently", "new", "newer", "stale",
+];
+
+/// The question to ask before generating a command for `prompt`, if it leaves a
+/// directory, size, age or port open
+pub fn clarifying_question(prompt: &str) -> Option<Clarification> {
+    let lower = prompt.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let has = |list: &[&str]| words.iter().any(|w| list.contains(w));
+    let has_number = lower.chars().any(|c| c.is_ascii_digit());
+
+    if has(BULK_VERBS) && has(FILE_NOUNS) && !has(HERE) && !names_path(prompt) {
+        return Some(LOCATION);
+    }
+    if has(SIZE_WORDS) && has(FILE_NOUNS) && !has_number {
+        return Some(SIZE);
+    }
+    if has(AGE_WORDS) && has(FILE_NOUNS) && !has_number {
+        return Some(AGE);
+    }
+    if words.contains(&"port") && !has_number {
+        return Some(PORT);
+    }
+    None
+}
+
+/// Whether a word of `prompt` looks like a path (`/var/log`, `~/src`, `./build`)
+fn names_path(prompt: &str) -> bool {
+    prompt
+        .split_whitespace()
+        .any(|word| word.contains('/') || word.starts_with('~') || word.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vague_prompts_get_one_question() {
+        assert_eq!(clarifying_question("delete old log files"), Some(LOCATION));
+        assert_eq!(clarifying_question("find large files in /srv"), Some(SIZE));
+        assert_eq!(clarifying_question("compress old logs here"), Some(AGE));
+        assert_eq!(
+            clarifying_question("what is listening on the port"),
+            Some(PORT)
+        );
+    }
+
+    #[test]
+    fn test_specific_prompts_are_left_alone() {
+        assert_eq!(clarifying_question("list files"), None);
+        assert_eq!(
+            clarifying_question("delete files older than 7 days in ~/tmp"),
+            None
+        );
+        assert_eq!(
+            clarifying_question("find files larger than 100M in the current directory"),
+            None
+        );
+        assert_eq!(clarifying_question("what is listening on port 8080"), None);
+        assert_eq!(clarifying_question("show disk usage"), None);
+    }
+}
@@ -0,0 +1,140 @@
+// Model/tokenizer compatibility checks
+//
+// A tokenizer from a different model family produces ids the model was never trained
+// on. Depending on the backend that shows up as garbage output or an opaque
+// tensor-shape error deep inside inference, so both loaders compare the tokenizer's
+// vocabulary against the model's embedding table up front.
+
+#[cfg(feature = "gguf")]
+use candle_core::quantized::gguf_file;
+use lib_errors::{EidosError, ErrorCode};
+use thiserror::Error;
+#[cfg(feature = "onnx")]
+use tract_core::ops::array::Gather;
+#[cfg(feature = "onnx")]
+use tract_onnx::prelude::*;
+
+/// Extra embedding rows a model may carry beyond the tokenizer's vocabulary.
+/// Many checkpoints pad the table to a multiple of 64/128 (e.g. T5: 32100 → 32128).
+pub const VOCAB_PADDING_TOLERANCE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "tokenizer does not match model: tokenizer has {tokenizer_vocab} tokens but the model \
+     embedding table has {model_vocab} rows. Use the tokenizer.json shipped with this model"
+)]
+pub struct VocabMismatch {
+    pub tokenizer_vocab: usize,
+    pub model_vocab: usize,
+}
+
+impl EidosError for VocabMismatch {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::Model
+    }
+}
+
+/// Fail when the tokenizer can emit ids the model has no embedding for, or when the
+/// model's vocabulary is far larger than padding alone would explain
+pub fn check_vocab(tokenizer_vocab: usize, model_vocab: usize) -> Result<(), VocabMismatch> {
+    let too_small = tokenizer_vocab > model_vocab;
+    let too_large = model_vocab - tokenizer_vocab.min(model_vocab) > VOCAB_PADDING_TOLERANCE;
+
+    if too_small || too_large {
+        Err(VocabMismatch {
+            tokenizer_vocab,
+            model_vocab,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Number of rows in the token embedding table of an ONNX model
+///
+/// Looks for axis-0 `Gather` ops over constant 2-D tables and takes the largest
+/// one, which skips smaller lookups such as position embeddings. Returns `None`
+/// when the graph has no recognisable embedding lookup.
+#[cfg(feature = "onnx")]
+pub fn onnx_embedding_rows(model: &TypedModel) -> Option<usize> {
+    model
+        .nodes()
+        .iter()
+        .filter(|node| node.op_as::<Gather>().is_some_and(|g| g.axis == 0))
+        .filter_map(|node| {
+            let table = model
+                .outlet_fact(*node.inputs.first()?)
+                .ok()?
+                .konst
+                .clone()?;
+            (table.rank() == 2).then(|| table.shape()[0])
+        })
+        .max()
+}
+
+/// Vocabulary size of a GGUF model, from its embedding tensor or metadata
+#[cfg(feature = "gguf")]
+pub fn gguf_vocab_size(content: &gguf_file::Content) -> Option<usize> {
+    if let Some(info) = content.tensor_infos.get("token_embd.weight") {
+        return info.shape.dims().first().copied();
+    }
+
+    let arch = content
+        .metadata
+        .get("general.architecture")
+        .and_then(|v| v.to_string().ok())?;
+    content
+        .metadata
+        .get(&format!("{}.vocab_size", arch))
+        .and_then(|v| v.to_u32().ok())
+        .map(|n| n as usize)
+}
+
+/// Number of tokens a GGUF model attends to (`{arch}.context_length`)
+#[cfg(feature = "gguf")]
+pub fn gguf_context_length(content: &gguf_file::Content) -> Option<usize> {
+    let arch = content
+        .metadata
+        .get("general.architecture")
+        .and_then(|v| v.to_string().ok())?;
+    content
+        .metadata
+        .get(&format!("{}.context_length", arch))
+        .and_then(|v| v.to_u32().ok())
+        .map(|n| n as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_vocab() {
+        assert!(check_vocab(32000, 32000).is_ok());
+        // Padded embedding tables are fine
+        assert!(check_vocab(32100, 32128).is_ok());
+
+        let err = check_vocab(50257, 32000).unwrap_err();
+        assert_eq!(err.tokenizer_vocab, 50257);
+        assert!(err.to_string().contains("tokenizer does not match model"));
+
+        assert!(check_vocab(32000, 128256).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "onnx")]
+    fn test_onnx_embedding_rows() -> TractResult<()> {
+        let mut model = TypedModel::default();
+        let ids = model.add_source("input_ids", i64::fact([4]))?;
+        let positions = model.add_source("position_ids", i64::fact([4]))?;
+        let vocab = model.add_const("embed_tokens", Tensor::zero::<f32>(&[300, 8])?)?;
+        let pos = model.add_const("embed_positions", Tensor::zero::<f32>(&[16, 8])?)?;
+        let tokens = model.wire_node("tokens", Gather { axis: 0 }, &[vocab, ids])?;
+        let positions = model.wire_node("positions", Gather { axis: 0 }, &[pos, positions])?;
+        model.set_output_outlets(&[tokens[0], positions[0]])?;
+
+        assert_eq!(onnx_embedding_rows(&model), Some(300));
+        assert_eq!(onnx_embedding_rows(&TypedModel::default()), None);
+        Ok(())
+    }
+}
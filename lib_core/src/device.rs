@@ -0,0 +1,203 @@
+// Compute device selection for the GGUF backend
+//
+// `QuantizedLlm` runs on the CPU unless another device is requested (the `device`
+// config key or EIDOS_DEVICE). CUDA and Metal need the `cuda` / `metal` features,
+// which build candle's kernels for them. Asking for an accelerator that is not
+// compiled in or not present falls back to the CPU, together with the reason so the
+// caller can tell the user.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Device requested for inference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DeviceSpec {
+    #[default]
+    Cpu,
+    /// The first usable accelerator, else the CPU
+    Auto,
+    /// CUDA GPU with the given ordinal (`cuda:0`)
+    Cuda(usize),
+    /// Metal GPU with the given ordinal (`metal:0`)
+    Metal(usize),
+}
+
+impl fmt::Display for DeviceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSpec::Cpu => f.write_str("cpu"),
+            DeviceSpec::Auto => f.write_str("auto"),
+            DeviceSpec::Cuda(ordinal) => write!(f, "cuda:{}", ordinal),
+            DeviceSpec::Metal(ordinal) => write!(f, "metal:{}", ordinal),
+        }
+    }
+}
+
+impl FromStr for DeviceSpec {
+    type Err = String;
+
+    /// Parse `cpu`, `auto`, `cuda`, `cuda:N`, `metal` or `metal:N`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_core::DeviceSpec;
+    ///
+    /// assert_eq!("cuda:1".parse(), Ok(DeviceSpec::Cuda(1)));
+    /// assert_eq!("metal".parse(), Ok(DeviceSpec::Metal(0)));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let invalid = || {
+            format!(
+                "Unknown device '{}' (expected cpu, auto, cuda[:N] or metal[:N])",
+                s
+            )
+        };
+        let ordinal = |n: &str| n.parse::<usize>().map_err(|_| invalid());
+
+        match s.split_once(':') {
+            None => match s.as_str() {
+                "cpu" => Ok(DeviceSpec::Cpu),
+                "auto" => Ok(DeviceSpec::Auto),
+                "cuda" => Ok(DeviceSpec::Cuda(0)),
+                "metal" => Ok(DeviceSpec::Metal(0)),
+                _ => Err(invalid()),
+            },
+            Some(("cuda", n)) => ordinal(n).map(DeviceSpec::Cuda),
+            Some(("metal", n)) => ordinal(n).map(DeviceSpec::Metal),
+            Some(_) => Err(invalid()),
+        }
+    }
+}
+
+/// Support for one accelerator in this build and on this machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Accelerator {
+    /// `cuda` or `metal`
+    pub name: &'static str,
+    /// Whether eidos was built with the matching feature
+    pub compiled: bool,
+    /// Whether device 0 could be opened
+    pub available: bool,
+    /// Why it is unavailable, when it was compiled in
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub error: Option<String>,
+}
+
+#[cfg(feature = "gguf")]
+mod candle_device {
+    use super::{Accelerator, DeviceSpec};
+    use candle_core::Device;
+
+    /// Open the device for `spec`
+    ///
+    /// Falls back to the CPU when the accelerator cannot be used; the second
+    /// value then says why.
+    pub fn select_device(spec: DeviceSpec) -> (Device, Option<String>) {
+        match spec {
+            DeviceSpec::Cpu => (Device::Cpu, None),
+            DeviceSpec::Auto => {
+                let device = try_open(DeviceSpec::Cuda(0))
+                    .or_else(|_| try_open(DeviceSpec::Metal(0)))
+                    .unwrap_or(Device::Cpu);
+                (device, None)
+            }
+            spec => match try_open(spec) {
+                Ok(device) => (device, None),
+                Err(e) => (
+                    Device::Cpu,
+                    Some(format!(
+                        "{} is not usable ({}); running on the CPU",
+                        spec, e
+                    )),
+                ),
+            },
+        }
+    }
+
+    /// CUDA and Metal support, probing device 0 of each compiled-in backend
+    pub fn accelerators() -> Vec<Accelerator> {
+        [
+            ("cuda", DeviceSpec::Cuda(0)),
+            ("metal", DeviceSpec::Metal(0)),
+        ]
+        .into_iter()
+        .map(|(name, spec)| {
+            let compiled = match spec {
+                DeviceSpec::Cuda(_) => candle_core::utils::cuda_is_available(),
+                _ => candle_core::utils::metal_is_available(),
+            };
+            let error = if compiled { try_open(spec).err() } else { None };
+            Accelerator {
+                name,
+                compiled,
+                available: compiled && error.is_none(),
+                error,
+            }
+        })
+        .collect()
+    }
+
+    /// Human-readable name of `device`, e.g. `cuda:0`
+    pub fn device_name(device: &Device) -> String {
+        match device.location() {
+            candle_core::DeviceLocation::Cpu => "cpu".to_string(),
+            candle_core::DeviceLocation::Cuda { gpu_id } => format!("cuda:{}", gpu_id),
+            candle_core::DeviceLocation::Metal { gpu_id } => format!("metal:{}", gpu_id),
+        }
+    }
+
+    fn try_open(spec: DeviceSpec) -> Result<Device, String> {
+        let opened = match spec {
+            DeviceSpec::Cuda(ordinal) if candle_core::utils::cuda_is_available() => {
+                Device::new_cuda(ordinal)
+            }
+            DeviceSpec::Metal(ordinal) if candle_core::utils::metal_is_available() => {
+                Device::new_metal(ordinal)
+            }
+            DeviceSpec::Cuda(_) => return Err("built without the `cuda` feature".to_string()),
+            DeviceSpec::Metal(_) => return Err("built without the `metal` feature".to_string()),
+            DeviceSpec::Cpu | DeviceSpec::Auto => Ok(Device::Cpu),
+        };
+        opened.map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "gguf")]
+pub use candle_device::{accelerators, device_name, select_device};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device() {
+        assert_eq!(" CPU ".parse(), Ok(DeviceSpec::Cpu));
+        assert_eq!("auto".parse(), Ok(DeviceSpec::Auto));
+        assert_eq!("cuda".parse(), Ok(DeviceSpec::Cuda(0)));
+        assert_eq!(
+            "cuda:2".parse::<DeviceSpec>().unwrap().to_string(),
+            "cuda:2"
+        );
+        assert!("cuda:x".parse::<DeviceSpec>().is_err());
+        assert!("cpu:1".parse::<DeviceSpec>().is_err());
+        assert!("tpu".parse::<DeviceSpec>().is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "gguf", not(feature = "cuda")))]
+    fn test_missing_accelerator_falls_back_to_cpu() {
+        let (device, reason) = select_device(DeviceSpec::Cuda(0));
+        assert!(device.is_cpu());
+        assert!(reason.unwrap().contains("`cuda` feature"));
+        assert_eq!(device_name(&device), "cpu");
+
+        let cuda = &accelerators()[0];
+        assert_eq!(
+            (cuda.name, cuda.compiled, cuda.available),
+            ("cuda", false, false)
+        );
+    }
+}
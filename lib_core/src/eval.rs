@@ -0,0 +1,383 @@
+// Evaluation of a model against expected commands
+//
+// `eidos eval` runs the prompts of a dataset of `{"prompt", "command"}` pairs (the
+// JSONL format the training scripts read) through a backend and compares each
+// generated command with the expected one three ways:
+//
+// - exact: the same string, ignoring surrounding whitespace
+// - normalized: the same shell words, so `find . -name "*.py"` matches
+//   `find  .  -name '*.py'`
+// - BLEU: a smoothed BLEU-4 score over those words, which gives partial credit
+//   for `ls -l` against `ls -la`
+//
+// Every generated command also goes through the safety policy. A fine-tune that
+// starts producing commands the policy rejects shows up in the rejection rate even
+// when its matches still look good.
+
+use crate::backend::InferenceBackend;
+use crate::shell::{tokenize, Token};
+use crate::validation::{SafetyPolicy, Violation};
+use std::collections::HashMap;
+use std::time::Instant;
+use thiserror::Error;
+
+/// Longest n-grams counted by [`bleu`]
+const MAX_NGRAM: usize = 4;
+
+/// A prompt and the command the model should generate for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvalCase {
+    pub prompt: String,
+    /// The expected command
+    pub command: String,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EvalError {
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("the dataset has no cases")]
+    Empty,
+}
+
+/// Read a JSONL dataset, one `{"prompt": ..., "command": ...}` object per line
+///
+/// Blank lines are skipped; other fields on a line are ignored.
+#[cfg(feature = "serde")]
+pub fn parse_dataset(text: &str) -> Result<Vec<EvalCase>, EvalError> {
+    let mut cases = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let case = serde_json::from_str(line).map_err(|e| EvalError::Parse {
+            line: i + 1,
+            message: e.to_string(),
+        })?;
+        cases.push(case);
+    }
+    if cases.is_empty() {
+        return Err(EvalError::Empty);
+    }
+    Ok(cases)
+}
+
+/// How close a generated command is to the expected one
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Scores {
+    pub exact: bool,
+    pub normalized: bool,
+    /// Smoothed BLEU-4 over shell words, from 0.0 to 1.0
+    pub bleu: f64,
+}
+
+/// Compare `generated` with `expected`
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::eval::score;
+///
+/// let scores = score("find . -name '*.py'", r#"find  . -name "*.py""#);
+/// assert!(!scores.exact && scores.normalized);
+/// assert_eq!(scores.bleu, 1.0);
+///
+/// let partial = score("ls -la", "ls -l");
+/// assert!(!partial.normalized && partial.bleu > 0.0 && partial.bleu < 1.0);
+/// ```
+pub fn score(expected: &str, generated: &str) -> Scores {
+    let reference = normalize(expected);
+    let candidate = normalize(generated);
+    Scores {
+        exact: expected.trim() == generated.trim(),
+        normalized: reference == candidate,
+        bleu: bleu(&reference, &candidate),
+    }
+}
+
+/// The shell words and operators of `command`, without quoting
+pub fn normalize(command: &str) -> Vec<String> {
+    tokenize(command)
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) | Token::Operator(word) => word,
+        })
+        .collect()
+}
+
+/// Sentence BLEU of `candidate` against one `reference`
+///
+/// Uses n-grams up to 4 (fewer for shorter references) with add-one smoothing
+/// above unigrams, since commands are too short for plain BLEU to be anything but
+/// zero when a single 4-gram differs.
+pub fn bleu(reference: &[String], candidate: &[String]) -> f64 {
+    if reference.is_empty() || candidate.is_empty() {
+        return if reference == candidate { 1.0 } else { 0.0 };
+    }
+    let max_n = MAX_NGRAM.min(reference.len());
+    let mut log_precision = 0.0;
+    for n in 1..=max_n {
+        let reference_counts = ngram_counts(reference, n);
+        let mut matches = 0;
+        for (ngram, count) in ngram_counts(candidate, n) {
+            matches += count.min(reference_counts.get(ngram).copied().unwrap_or(0));
+        }
+        let total = (candidate.len() + 1).saturating_sub(n);
+        let precision = if n == 1 {
+            if matches == 0 {
+                return 0.0;
+            }
+            matches as f64 / total as f64
+        } else {
+            (matches + 1) as f64 / (total + 1) as f64
+        };
+        log_precision += precision.ln();
+    }
+
+    let brevity = if candidate.len() >= reference.len() {
+        1.0
+    } else {
+        (1.0 - reference.len() as f64 / candidate.len() as f64).exp()
+    };
+    brevity * (log_precision / max_n as f64).exp()
+}
+
+fn ngram_counts(words: &[String], n: usize) -> HashMap<&[String], usize> {
+    let mut counts = HashMap::new();
+    for ngram in words.windows(n) {
+        *counts.entry(ngram).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Outcome of one case
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EvalRecord {
+    pub prompt: String,
+    pub expected: String,
+    /// What the model generated; `None` when generation failed
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub generated: Option<String>,
+    /// Why generation failed
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub error: Option<String>,
+    /// The main reason the safety policy rejected the generated command
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub rejected: Option<Violation>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub scores: Scores,
+    pub latency_ms: u64,
+}
+
+/// Rates over all cases of a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EvalSummary {
+    pub cases: usize,
+    /// Share of cases, from 0.0 to 1.0, with an exact match
+    pub exact: f64,
+    /// Share of cases whose shell words match
+    pub normalized: f64,
+    /// Mean BLEU score; failed generations count as 0
+    pub bleu: f64,
+    /// Share of generated commands the safety policy rejected
+    pub rejected: f64,
+    /// Cases where generation failed
+    pub errors: usize,
+    pub mean_latency_ms: u64,
+}
+
+/// Every case of a run and the summary over them
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EvalReport {
+    pub summary: EvalSummary,
+    pub records: Vec<EvalRecord>,
+}
+
+impl EvalReport {
+    pub fn new(records: Vec<EvalRecord>) -> Self {
+        let cases = records.len();
+        let share = |count: usize| {
+            if cases == 0 {
+                0.0
+            } else {
+                count as f64 / cases as f64
+            }
+        };
+        let generated = records.iter().filter(|r| r.generated.is_some()).count();
+        let rejected = records.iter().filter(|r| r.rejected.is_some()).count();
+        let latency: u64 = records.iter().map(|r| r.latency_ms).sum();
+        let summary = EvalSummary {
+            cases,
+            exact: share(records.iter().filter(|r| r.scores.exact).count()),
+            normalized: share(records.iter().filter(|r| r.scores.normalized).count()),
+            bleu: if cases == 0 {
+                0.0
+            } else {
+                records.iter().map(|r| r.scores.bleu).sum::<f64>() / cases as f64
+            },
+            rejected: if generated == 0 {
+                0.0
+            } else {
+                rejected as f64 / generated as f64
+            },
+            errors: cases - generated,
+            mean_latency_ms: latency.checked_div(cases as u64).unwrap_or(0),
+        };
+        Self { summary, records }
+    }
+
+    /// Cases whose command did not match, worst BLEU first
+    pub fn misses(&self) -> Vec<&EvalRecord> {
+        let mut misses: Vec<&EvalRecord> = self
+            .records
+            .iter()
+            .filter(|r| !r.scores.normalized)
+            .collect();
+        misses.sort_by(|a, b| a.scores.bleu.total_cmp(&b.scores.bleu));
+        misses
+    }
+}
+
+/// Generate a command for `case` and score it
+///
+/// No context is added to the prompt, so results do not depend on the machine the
+/// evaluation runs on.
+pub fn evaluate_case(
+    backend: &dyn InferenceBackend,
+    policy: &SafetyPolicy,
+    case: &EvalCase,
+) -> EvalRecord {
+    let started = Instant::now();
+    let generated = backend.generate(&case.prompt, None);
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let (generated, error) = match generated {
+        Ok(command) => (Some(command), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let scores = match &generated {
+        Some(command) => score(&case.command, command),
+        None => Scores {
+            exact: false,
+            normalized: false,
+            bleu: 0.0,
+        },
+    };
+    let rejected = generated
+        .as_deref()
+        .and_then(|command| policy.validate(command).primary().cloned());
+    EvalRecord {
+        prompt: case.prompt.clone(),
+        expected: case.command.clone(),
+        generated,
+        error,
+        rejected,
+        scores,
+        latency_ms,
+    }
+}
+
+/// Run every case through `backend`, calling `progress` after each one
+pub fn evaluate(
+    backend: &dyn InferenceBackend,
+    policy: &SafetyPolicy,
+    cases: &[EvalCase],
+    mut progress: impl FnMut(usize, &EvalRecord),
+) -> EvalReport {
+    let records = cases
+        .iter()
+        .enumerate()
+        .map(|(i, case)| {
+            let record = evaluate_case(backend, policy, case);
+            progress(i + 1, &record);
+            record
+        })
+        .collect();
+    EvalReport::new(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+
+    fn words(command: &str) -> Vec<String> {
+        normalize(command)
+    }
+
+    #[test]
+    fn test_bleu_bounds() {
+        assert_eq!(bleu(&words("ls -la"), &words("ls -la")), 1.0);
+        assert_eq!(bleu(&words("ls -la"), &words("pwd")), 0.0);
+        assert_eq!(bleu(&[], &[]), 1.0);
+        assert_eq!(bleu(&words("ls"), &[]), 0.0);
+
+        let close = bleu(
+            &words("find . -name '*.py' -type f"),
+            &words("find . -name '*.py'"),
+        );
+        let far = bleu(&words("find . -name '*.py' -type f"), &words("ls *.py"));
+        assert!(close > far, "{} <= {}", close, far);
+        assert!(close < 1.0);
+    }
+
+    #[test]
+    fn test_normalized_match_ignores_quoting_and_spacing() {
+        assert!(score("grep -r 'TODO' .", "grep  -r TODO .").normalized);
+        assert!(!score("grep -r TODO .", "grep -r todo .").normalized);
+        assert!(score(" ls ", "ls").exact);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_parse_dataset() {
+        let text = "{\"prompt\": \"list files\", \"command\": \"ls\"}\n\n{\"prompt\": \"where am i\", \"command\": \"pwd\", \"tags\": []}\n";
+        let cases = parse_dataset(text).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[1].command, "pwd");
+
+        assert_eq!(parse_dataset("\n"), Err(EvalError::Empty));
+        let err = parse_dataset("{\"prompt\": \"x\"}").unwrap_err();
+        assert!(matches!(err, EvalError::Parse { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_evaluate_reports_rates() {
+        let backend = MockBackend::new()
+            .with_command("list files", "ls -la")
+            .with_command("where am i", "pwd")
+            .with_command("free space", "rm -rf /")
+            .with_fallback("echo unknown");
+        let cases = [
+            ("list files", "ls -la"),
+            ("where am i", "pwd -P"),
+            ("free space", "df -h"),
+            ("show date", "date"),
+        ]
+        .map(|(prompt, command)| EvalCase {
+            prompt: prompt.to_string(),
+            command: command.to_string(),
+        });
+
+        let mut seen = 0;
+        let report = evaluate(&backend, &SafetyPolicy::default(), &cases, |done, _| {
+            seen = done
+        });
+        assert_eq!(seen, 4);
+        let summary = report.summary;
+        assert_eq!(summary.cases, 4);
+        assert_eq!(summary.exact, 0.25);
+        assert_eq!(summary.rejected, 0.25);
+        assert_eq!(summary.errors, 0);
+        assert!(summary.bleu > 0.25 && summary.bleu < 1.0);
+        assert!(report.records[2].rejected.is_some());
+
+        let misses = report.misses();
+        assert_eq!(misses.len(), 3);
+        assert_eq!(misses.last().unwrap().prompt, "where am i");
+    }
+}
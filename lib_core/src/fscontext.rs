@@ -0,0 +1,249 @@
+// Read-only facts about a directory for the generation context
+//
+// "build this project" means `cargo build` in one directory and `npm run build` in
+// another. These helpers look at a directory without changing anything: the kind
+// of project its manifest files announce and its top-level entries. Both are
+// bounded: projects are found by checking a fixed list of file names, and at most
+// `MAX_SCANNED` entries are read for a listing, so a home directory with thousands
+// of files costs no more than a fresh checkout.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Directory entries read for a listing before the rest are ignored
+pub const MAX_SCANNED: usize = 1000;
+
+/// A kind of project, recognised by its manifest file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    Rust,
+    Node,
+    Python,
+    Go,
+    Maven,
+    Gradle,
+    CMake,
+    Meson,
+    Make,
+    Ruby,
+    Php,
+}
+
+impl ProjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "Rust",
+            ProjectKind::Node => "Node.js",
+            ProjectKind::Python => "Python",
+            ProjectKind::Go => "Go",
+            ProjectKind::Maven => "Maven",
+            ProjectKind::Gradle => "Gradle",
+            ProjectKind::CMake => "CMake",
+            ProjectKind::Meson => "Meson",
+            ProjectKind::Make => "Make",
+            ProjectKind::Ruby => "Ruby",
+            ProjectKind::Php => "PHP",
+        }
+    }
+}
+
+impl fmt::Display for ProjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Manifest files and the build command they imply, in the order projects are
+/// reported. The first manifest found for a kind wins.
+const MANIFESTS: &[(&str, ProjectKind, &str)] = &[
+    ("Cargo.toml", ProjectKind::Rust, "cargo build"),
+    ("package.json", ProjectKind::Node, "npm run build"),
+    ("pyproject.toml", ProjectKind::Python, "python -m build"),
+    ("setup.py", ProjectKind::Python, "python -m build"),
+    (
+        "requirements.txt",
+        ProjectKind::Python,
+        "pip install -r requirements.txt",
+    ),
+    ("go.mod", ProjectKind::Go, "go build ./..."),
+    ("pom.xml", ProjectKind::Maven, "mvn package"),
+    ("build.gradle.kts", ProjectKind::Gradle, "gradle build"),
+    ("build.gradle", ProjectKind::Gradle, "gradle build"),
+    (
+        "CMakeLists.txt",
+        ProjectKind::CMake,
+        "cmake -B build && cmake --build build",
+    ),
+    (
+        "meson.build",
+        ProjectKind::Meson,
+        "meson setup build && meson compile -C build",
+    ),
+    ("GNUmakefile", ProjectKind::Make, "make"),
+    ("Makefile", ProjectKind::Make, "make"),
+    ("makefile", ProjectKind::Make, "make"),
+    ("Gemfile", ProjectKind::Ruby, "bundle exec rake"),
+    ("composer.json", ProjectKind::Php, "composer install"),
+];
+
+/// Node.js lock files naming a package manager other than npm
+const NODE_LOCKFILES: &[(&str, &str)] = &[
+    ("pnpm-lock.yaml", "pnpm run build"),
+    ("yarn.lock", "yarn build"),
+    ("bun.lockb", "bun run build"),
+    ("bun.lock", "bun run build"),
+];
+
+/// A project found in a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub kind: ProjectKind,
+    /// The file it was recognised by, e.g. `Cargo.toml`
+    pub manifest: &'static str,
+    /// The usual way to build it, e.g. `cargo build`
+    pub build_command: &'static str,
+}
+
+impl fmt::Display for Project {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, built with `{}`)",
+            self.kind, self.manifest, self.build_command
+        )
+    }
+}
+
+/// Projects whose manifest files are in `dir`, e.g. Rust for `Cargo.toml`
+///
+/// A directory can hold several (a Rust crate with a `Makefile`); each kind is
+/// reported once. Node.js projects build with the package manager their lock file
+/// names.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::fscontext::{detect_projects, ProjectKind};
+///
+/// let projects = detect_projects(env!("CARGO_MANIFEST_DIR").as_ref());
+/// assert_eq!(projects[0].kind, ProjectKind::Rust);
+/// assert_eq!(projects[0].build_command, "cargo build");
+/// ```
+pub fn detect_projects(dir: &Path) -> Vec<Project> {
+    let mut projects: Vec<Project> = Vec::new();
+    for &(manifest, kind, build_command) in MANIFESTS {
+        if projects.iter().any(|p| p.kind == kind) || !dir.join(manifest).is_file() {
+            continue;
+        }
+        let build_command = match kind {
+            ProjectKind::Node => NODE_LOCKFILES
+                .iter()
+                .find(|(lockfile, _)| dir.join(lockfile).is_file())
+                .map_or(build_command, |&(_, command)| command),
+            _ => build_command,
+        };
+        projects.push(Project {
+            kind,
+            manifest,
+            build_command,
+        });
+    }
+    projects
+}
+
+/// The first names of a directory's top-level entries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listing {
+    /// Entry names in sorted order, directories with a trailing `/`
+    pub entries: Vec<String>,
+    /// Whether entries were left out
+    pub truncated: bool,
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.entries.join(", "))?;
+        if self.truncated {
+            f.write_str(", ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Up to `limit` of the top-level entries of `dir`
+///
+/// Hidden entries are skipped. Only the first [`MAX_SCANNED`] entries the system
+/// returns are considered, so in very large directories the listing is a sample
+/// rather than the alphabetical start.
+pub fn list_entries(dir: &Path, limit: usize) -> io::Result<Listing> {
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    for (scanned, entry) in fs::read_dir(dir)?.enumerate() {
+        if scanned == MAX_SCANNED {
+            truncated = true;
+            break;
+        }
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        // `file_type` does not follow symlinks; a link to a directory is listed
+        // as a file, which is what `ls` without `-L` shows too
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        entries.push(if is_dir { format!("{}/", name) } else { name });
+    }
+    entries.sort();
+    if entries.len() > limit {
+        entries.truncate(limit);
+        truncated = true;
+    }
+    Ok(Listing { entries, truncated })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(detect_projects(dir.path()).is_empty());
+
+        for file in ["package.json", "yarn.lock", "Makefile", "makefile"] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        // A directory named like a manifest is not one
+        fs::create_dir(dir.path().join("Cargo.toml")).unwrap();
+        let projects: Vec<String> = detect_projects(dir.path())
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            projects,
+            [
+                "Node.js (package.json, built with `yarn build`)",
+                "Make (Makefile, built with `make`)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["README.md", "Cargo.toml", ".env"] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        fs::create_dir(dir.path().join("src")).unwrap();
+
+        let listing = list_entries(dir.path(), 10).unwrap();
+        assert_eq!(listing.to_string(), "Cargo.toml, README.md, src/");
+        assert!(!listing.truncated);
+
+        let listing = list_entries(dir.path(), 2).unwrap();
+        assert_eq!(listing.to_string(), "Cargo.toml, README.md, ...");
+        assert!(list_entries(&dir.path().join("missing"), 10).is_err());
+    }
+}
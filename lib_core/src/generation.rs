@@ -0,0 +1,319 @@
+// Sampling settings for token-by-token generation backends
+//
+// The defaults reproduce the previous hard-coded behaviour: greedy decoding with a
+// fixed seed, so the same request always yields the same command. Raising the
+// temperature (optionally with top-k / top-p) trades determinism for variety.
+//
+// Small command models also tend to loop ("ls ls ls ls ..."), burning the whole
+// token budget. Generation stops once the output ends in the same run of tokens
+// repeated `loop_repeats` times, keeping the text up to the first repetition.
+//
+// A generation whose result is no longer wanted (e.g. speculative generation for
+// a prompt that turned out to need translation) is stopped through a `CancelFlag`,
+// checked before every token, so it does not hold the model until `max_tokens`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Seed used when none is given, kept from the original hard-coded sampler
+pub const DEFAULT_SEED: u64 = 299_792_458;
+
+/// Longest run of tokens checked for repetition
+pub const MAX_LOOP_NGRAM: usize = 16;
+
+/// Fewest repeated tokens that count as a loop, so a handful of identical tokens
+/// (`../../`, a row of dashes) is not cut off
+pub const MIN_LOOP_TOKENS: usize = 8;
+
+/// How the next token is picked during generation
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::generation::GenerationConfig;
+///
+/// let config = GenerationConfig::default()
+///     .with_temperature(0.7)
+///     .with_top_p(0.9)
+///     .with_stop("\n");
+/// assert!(!config.is_greedy());
+/// assert_eq!(config.truncate_at_stop("ls -la\nls"), Some("ls -la"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationConfig {
+    /// Softmax temperature; `0.0` always picks the most likely token
+    pub temperature: f64,
+    /// Sample only from the smallest set of tokens whose probability reaches `p`
+    pub top_p: Option<f64>,
+    /// Sample only from the `k` most likely tokens
+    pub top_k: Option<usize>,
+    /// Penalty applied to tokens already generated; `1.0` disables it
+    pub repeat_penalty: f32,
+    /// How many of the most recent tokens the repeat penalty looks at
+    pub repeat_last_n: usize,
+    /// Random seed, so sampled output is reproducible
+    pub seed: u64,
+    /// Generation stops as soon as the output contains one of these
+    pub stop: Vec<String>,
+    /// Generation stops when the output ends in a run of tokens repeated this many
+    /// times in a row; `0` disables loop detection
+    pub loop_repeats: usize,
+    /// Generation stops with an error once this flag is cancelled
+    pub cancel: Option<CancelFlag>,
+}
+
+/// Flag shared with a running generation to stop it at the next token
+///
+/// Clones share the flag. Equal only to its own clones.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Guard that cancels the flag when dropped, e.g. together with the future
+    /// waiting for the generation
+    pub fn cancel_on_drop(&self) -> CancelGuard {
+        CancelGuard(self.clone())
+    }
+}
+
+impl PartialEq for CancelFlag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Cancels its [`CancelFlag`] when dropped
+#[derive(Debug)]
+pub struct CancelGuard(CancelFlag);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 0.0,
+            top_p: None,
+            top_k: None,
+            repeat_penalty: 1.0,
+            repeat_last_n: 64,
+            seed: DEFAULT_SEED,
+            stop: Vec::new(),
+            loop_repeats: 4,
+            cancel: None,
+        }
+    }
+}
+
+impl GenerationConfig {
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_repeat_penalty(mut self, penalty: f32) -> Self {
+        self.repeat_penalty = penalty;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    pub fn with_loop_repeats(mut self, repeats: usize) -> Self {
+        self.loop_repeats = repeats;
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancelFlag) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Whether the generation was cancelled through [`Self::cancel`]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelFlag::is_cancelled)
+    }
+
+    /// Whether sampling always picks the most likely token
+    pub fn is_greedy(&self) -> bool {
+        self.temperature <= 0.0
+    }
+
+    /// Check that every setting is in range
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.temperature.is_finite() || self.temperature < 0.0 {
+            return Err(format!(
+                "temperature must be 0 or greater, got {}",
+                self.temperature
+            ));
+        }
+        if let Some(p) = self.top_p {
+            if !(p > 0.0 && p <= 1.0) {
+                return Err(format!("top_p must be in (0, 1], got {}", p));
+            }
+        }
+        if self.top_k == Some(0) {
+            return Err("top_k must be at least 1".to_string());
+        }
+        if !self.repeat_penalty.is_finite() || self.repeat_penalty <= 0.0 {
+            return Err(format!(
+                "repeat_penalty must be greater than 0, got {}",
+                self.repeat_penalty
+            ));
+        }
+        if self.stop.iter().any(|s| s.is_empty()) {
+            return Err("stop sequences must not be empty".to_string());
+        }
+        if self.loop_repeats == 1 {
+            return Err("loop_repeats must be 0 (off) or at least 2".to_string());
+        }
+        Ok(())
+    }
+
+    /// `text` cut before the first stop sequence, or `None` if it contains none
+    pub fn truncate_at_stop<'a>(&self, text: &'a str) -> Option<&'a str> {
+        self.stop
+            .iter()
+            .filter_map(|stop| text.find(stop.as_str()))
+            .min()
+            .map(|end| &text[..end])
+    }
+
+    /// Number of `tokens` to keep when they end in a loop: everything up to the
+    /// end of the first repetition, or `None` if they do not loop
+    ///
+    /// ```
+    /// use lib_core::generation::GenerationConfig;
+    ///
+    /// let config = GenerationConfig::default();
+    /// // "ls" "-la" "ls" "-la" ... after the first "ls -la"
+    /// let tokens = [9, 5, 6, 5, 6, 5, 6, 5, 6];
+    /// assert_eq!(config.loop_prefix_len(&tokens), Some(3));
+    /// assert_eq!(config.loop_prefix_len(&tokens[..7]), None);
+    /// ```
+    pub fn loop_prefix_len(&self, tokens: &[u32]) -> Option<usize> {
+        if self.loop_repeats < 2 {
+            return None;
+        }
+        (1..=MAX_LOOP_NGRAM)
+            .filter_map(|n| {
+                let repeats = self.loop_repeats.max((MIN_LOOP_TOKENS + n - 1) / n);
+                let span = n * repeats;
+                let mut start = tokens.len().checked_sub(span)?;
+                let ngram = &tokens[start..start + n];
+                if !tokens[start..].chunks(n).all(|chunk| chunk == ngram) {
+                    return None;
+                }
+                // Back to the first occurrence of the repeated run
+                while start >= n && &tokens[start - n..start] == ngram {
+                    start -= n;
+                }
+                Some(start + n)
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_deterministic() {
+        let config = GenerationConfig::default();
+        assert!(config.is_greedy());
+        assert_eq!(config.seed, DEFAULT_SEED);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_ranges() {
+        let base = GenerationConfig::default;
+        assert!(base().with_temperature(-0.1).validate().is_err());
+        assert!(base().with_top_p(0.0).validate().is_err());
+        assert!(base().with_top_p(1.5).validate().is_err());
+        assert!(base().with_top_k(0).validate().is_err());
+        assert!(base().with_repeat_penalty(0.0).validate().is_err());
+        assert!(base().with_stop("").validate().is_err());
+        assert!(base()
+            .with_temperature(0.8)
+            .with_top_k(40)
+            .with_top_p(0.95)
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        let config = GenerationConfig::default();
+        // A single repeated token needs MIN_LOOP_TOKENS copies
+        let dashes = [3; MIN_LOOP_TOKENS];
+        assert_eq!(config.loop_prefix_len(&dashes), Some(1));
+        assert_eq!(config.loop_prefix_len(&dashes[1..]), None);
+
+        // The shortest repeating unit wins: "a b a b" is also "(a b a b) x 2"
+        let tokens = [7, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2];
+        assert_eq!(config.loop_prefix_len(&tokens), Some(3));
+        assert_eq!(config.loop_prefix_len(&[7, 1, 2, 3, 4]), None);
+
+        let off = GenerationConfig::default().with_loop_repeats(0);
+        assert_eq!(off.loop_prefix_len(&dashes), None);
+        assert!(GenerationConfig::default()
+            .with_loop_repeats(1)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_truncate_at_earliest_stop() {
+        let config = GenerationConfig::default()
+            .with_stop("</s>")
+            .with_stop("\n");
+        assert_eq!(config.truncate_at_stop("df -h\n</s>"), Some("df -h"));
+        assert_eq!(config.truncate_at_stop("df -h"), None);
+    }
+
+    #[test]
+    fn test_cancel_flag_is_shared_by_clones() {
+        let flag = CancelFlag::new();
+        let config = GenerationConfig::default().with_cancel(flag.clone());
+        assert!(!config.is_cancelled());
+        assert_ne!(flag, CancelFlag::new());
+
+        drop(flag.cancel_on_drop());
+        assert!(config.is_cancelled());
+        assert!(!GenerationConfig::default().is_cancelled());
+    }
+}
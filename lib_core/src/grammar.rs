@@ -0,0 +1,179 @@
+// Grammar for constrained command generation
+//
+// The safety policy rejects a generated command after the fact: an unknown first
+// word, a pipe, a quote. A `CommandGrammar` states the same rules as a test on
+// unfinished text, so a decoder can refuse tokens that would break them while it
+// generates: the first words must be able to become an allowed command, and no
+// blocked metacharacter may appear. Whatever the decoder finishes then passes the
+// policy's whitelist and metacharacter checks by construction.
+//
+// The grammar knows nothing about tokens; `QuantizedLlm::with_grammar` asks it
+// about the text each candidate token would produce.
+
+/// Commands a generated text may start with and substrings it may not contain
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::grammar::CommandGrammar;
+///
+/// let grammar = CommandGrammar::new(["ls", "git status"], ["|", ";"]);
+/// assert!(grammar.allows_prefix(" l"));
+/// assert!(grammar.allows_prefix("git sta"));
+/// assert!(!grammar.allows_prefix("git push"));
+/// assert!(!grammar.allows_prefix("ls | "));
+///
+/// assert!(grammar.accepts("ls -la"));
+/// assert!(!grammar.accepts("git"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandGrammar {
+    /// Allowed commands as lowercase words, e.g. `["git", "status"]`
+    commands: Vec<Vec<String>>,
+    /// Commands that are never allowed, matched the same way
+    denied: Vec<Vec<String>>,
+    blocked: Vec<String>,
+}
+
+impl CommandGrammar {
+    /// Texts starting with one of `commands` (its leading words, e.g. `git status`)
+    /// and containing none of `blocked`
+    pub fn new<C, B>(commands: C, blocked: B) -> Self
+    where
+        C: IntoIterator,
+        C::Item: AsRef<str>,
+        B: IntoIterator,
+        B::Item: Into<String>,
+    {
+        Self {
+            commands: words(commands),
+            denied: Vec::new(),
+            blocked: blocked.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Also reject texts starting with one of `commands`
+    pub fn with_denied<D>(mut self, commands: D) -> Self
+    where
+        D: IntoIterator,
+        D::Item: AsRef<str>,
+    {
+        self.denied.extend(words(commands));
+        self
+    }
+
+    /// Whether `text` can still grow into a text the grammar accepts
+    ///
+    /// Leading whitespace is ignored, and commands are matched case-insensitively
+    /// like the safety policy does.
+    pub fn allows_prefix(&self, text: &str) -> bool {
+        if self.blocked.iter().any(|b| text.contains(b.as_str())) {
+            return false;
+        }
+        let text = text.trim_start().to_lowercase();
+        let mut typed: Vec<&str> = text.split_whitespace().collect();
+        // The last word is still being typed unless whitespace follows it
+        let partial = if text.ends_with(char::is_whitespace) {
+            None
+        } else {
+            typed.pop()
+        };
+        if self.denied.iter().any(|d| starts_with_words(&typed, d)) {
+            return false;
+        }
+        self.commands.iter().any(|command| {
+            command
+                .iter()
+                .enumerate()
+                .all(|(i, word)| match typed.get(i) {
+                    Some(typed) => typed == word,
+                    None if i == typed.len() => partial.map_or(true, |p| word.starts_with(p)),
+                    None => true,
+                })
+        })
+    }
+
+    /// Whether `text` is complete: allowed and starting with a whole command
+    pub fn accepts(&self, text: &str) -> bool {
+        if !self.allows_prefix(text) {
+            return false;
+        }
+        let text = text.to_lowercase();
+        let typed: Vec<&str> = text.split_whitespace().collect();
+        !self.denied.iter().any(|d| starts_with_words(&typed, d))
+            && self.commands.iter().any(|c| starts_with_words(&typed, c))
+    }
+}
+
+fn words<I>(commands: I) -> Vec<Vec<String>>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    commands
+        .into_iter()
+        .map(|c| {
+            c.as_ref()
+                .split_whitespace()
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+        })
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+fn starts_with_words(typed: &[&str], command: &[String]) -> bool {
+    typed.len() >= command.len() && command.iter().zip(typed).all(|(word, typed)| word == typed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::{SafetyLevel, SafetyPolicy};
+
+    #[test]
+    fn test_prefixes_of_multi_word_commands() {
+        let grammar = CommandGrammar::new(["ls", "git status", "git log"], ["|"]);
+        for prefix in [
+            "",
+            "  ",
+            "G",
+            "git ",
+            "git  s",
+            "git log --oneline",
+            "LS -la",
+        ] {
+            assert!(grammar.allows_prefix(prefix), "{:?}", prefix);
+        }
+        for prefix in ["x", "lsblk", "git stash", "git status | wc", "gitk"] {
+            assert!(!grammar.allows_prefix(prefix), "{:?}", prefix);
+        }
+        assert!(!grammar.accepts(""));
+        assert!(!grammar.accepts("git stat"));
+        assert!(grammar.accepts("git status -s\n"));
+    }
+
+    #[test]
+    fn test_policy_grammar_matches_the_policy() {
+        let policy = SafetyPolicy::new(SafetyLevel::Standard)
+            .with_allow(["git status"])
+            .with_deny(["cat"]);
+        let grammar = policy.grammar();
+        for command in ["ls -la", "git status -s", "du -sh .", "cat notes.txt"] {
+            assert_eq!(
+                grammar.accepts(command),
+                policy.is_safe(command),
+                "{}",
+                command
+            );
+        }
+        for prefix in ["ls && rm", "echo $(id)", "ls ../", "head 'x"] {
+            assert!(!grammar.allows_prefix(prefix), "{}", prefix);
+        }
+        // Quotes are fine at the permissive level
+        let permissive = SafetyPolicy::new(SafetyLevel::Permissive).grammar();
+        assert!(permissive.accepts("grep 'TODO' src"));
+        assert!(permissive.accepts("docker ps -a"));
+        assert!(!permissive.allows_prefix("docker run"));
+    }
+}
@@ -1,9 +1,64 @@
 pub mod alternatives;
+pub mod audit;
+pub mod backend;
+pub mod breakdown;
+pub mod cache;
+pub mod clarify;
+pub mod compat;
+pub mod device;
+pub mod eval;
+pub mod fscontext;
+pub mod generation;
+pub mod grammar;
+pub mod mock;
+pub mod pool;
+pub mod presets;
+pub mod preview;
+pub mod prompt;
+#[cfg(feature = "gguf")]
 pub mod quantized_llm;
+pub mod refine;
+pub mod registry;
+pub mod risk;
+pub mod shell;
+pub mod special_tokens;
+pub mod target;
+pub mod template;
+#[cfg(any(feature = "onnx", feature = "gguf"))]
+pub mod tokenizer;
+#[cfg(feature = "onnx")]
 pub mod tract_llm;
+pub mod undo;
 pub mod validation;
 
 // Re-export commonly used types
+pub use audit::{audit_script, AuditFinding};
+pub use backend::{BackendKind, CommandGenerator, InferenceBackend, ModelInfo};
+pub use breakdown::{breakdown_command, CommandFlag, CommandPart};
+pub use cache::CommandCache;
+pub use device::{Accelerator, DeviceSpec};
+pub use eval::{evaluate, EvalCase, EvalReport};
+pub use generation::{CancelFlag, GenerationConfig};
+pub use grammar::CommandGrammar;
+pub use mock::{MockBackend, MockGenerator, MockRequest};
+pub use pool::GeneratorPool;
+pub use presets::{ModelPreset, PresetRegistry};
+pub use preview::{preview_command, CommandPreview};
+pub use prompt::{HistoryContext, PromptBuilder, PromptTemplate, RefinementContext};
+#[cfg(feature = "gguf")]
 pub use quantized_llm::{QuantizedLlm, QuantizedLlmError};
+pub use refine::RefinementSession;
+pub use registry::ModelRegistry;
+pub use risk::{classify_command, Risk};
+pub use special_tokens::SpecialTokens;
+pub use target::TargetShell;
+pub use template::{TemplateError, TemplateVars};
+#[cfg(any(feature = "onnx", feature = "gguf"))]
+pub use tokenizer::{TokenCounter, TokenizerWarning};
+#[cfg(feature = "onnx")]
 pub use tract_llm::Core;
-pub use validation::is_safe_command;
+pub use undo::undo_command;
+pub use validation::{
+    is_safe_command, validate_command, CommandSchema, SafetyLevel, SafetyPolicy, SafetyReport,
+    SafetyRule, Severity,
+};
@@ -0,0 +1,267 @@
+// Scripted command generation backend
+//
+// `Core` and `QuantizedLlm` need model files, so code built on `InferenceBackend`
+// (bridge handlers, an embedder's own pipeline) cannot be tested with them in CI.
+// `MockBackend` answers from a table of canned commands instead and records what
+// it was asked. It can also stand in for a sampling backend (one answer per seed)
+// and for slow or parallel ones, so alternatives and the generator pool are tested
+// with it too.
+
+use crate::backend::{BackendKind, InferenceBackend, ModelInfo};
+use crate::generation::GenerationConfig;
+use crate::prompt::PromptTemplate;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// [`MockBackend`] under the name used with [`crate::CommandGenerator`]
+pub type MockGenerator = MockBackend;
+
+/// Backend returning canned commands, for tests and examples
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::{InferenceBackend, MockBackend};
+///
+/// let backend = MockBackend::new()
+///     .with_command("list files", "ls -la")
+///     .with_explanation("ls -la", "Lists all files with details");
+/// assert_eq!(backend.generate("list files", None).unwrap(), "ls -la");
+/// assert_eq!(backend.explain("ls -la").unwrap(), "Lists all files with details");
+/// assert!(backend.generate("reboot", None).is_err());
+/// assert_eq!(backend.calls(), 2);
+/// ```
+///
+/// Clones share what they record, so a test can hand clones to a pool and inspect
+/// the original.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    commands: HashMap<String, String>,
+    fallback: Option<String>,
+    explanations: HashMap<String, String>,
+    samples: Vec<String>,
+    parallelism: Option<usize>,
+    latency: Duration,
+    record: Arc<Record>,
+}
+
+/// What a [`MockBackend`] and its clones were asked
+#[derive(Debug, Default)]
+struct Record {
+    requests: Mutex<Vec<MockRequest>>,
+    running: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+/// A generation request received by a [`MockBackend`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub request: String,
+    pub context: Option<String>,
+    /// Settings of `generate_with_config`; `None` for `generate`
+    pub config: Option<GenerationConfig>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `request` with `command`
+    pub fn with_command(mut self, request: impl Into<String>, command: impl Into<String>) -> Self {
+        self.commands.insert(request.into(), command.into());
+        self
+    }
+
+    /// Answer requests without a canned command with `command` instead of failing
+    pub fn with_fallback(mut self, command: impl Into<String>) -> Self {
+        self.fallback = Some(command.into());
+        self
+    }
+
+    /// Explain `command` with `explanation`; other commands get a generic one
+    pub fn with_explanation(
+        mut self,
+        command: impl Into<String>,
+        explanation: impl Into<String>,
+    ) -> Self {
+        self.explanations.insert(command.into(), explanation.into());
+        self
+    }
+
+    /// Act as a backend sampling token by token: `generate_with_config` answers
+    /// with `samples[seed % samples.len()]`
+    pub fn with_samples<I, S>(mut self, samples: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.samples = samples.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Report `parallelism` generations at once (default 1)
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = Some(parallelism);
+        self
+    }
+
+    /// Take `latency` to answer each request
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Number of generation requests so far
+    pub fn calls(&self) -> usize {
+        self.lock_requests().len()
+    }
+
+    /// Generation requests so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock_requests().clone()
+    }
+
+    /// Most generation requests that were running at the same time
+    pub fn peak_concurrency(&self) -> usize {
+        self.record.peak.load(Ordering::SeqCst)
+    }
+
+    fn lock_requests(&self) -> std::sync::MutexGuard<'_, Vec<MockRequest>> {
+        self.record
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn answer(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String> {
+        self.lock_requests().push(MockRequest {
+            request: request.to_string(),
+            context: context.map(str::to_string),
+            config: config.cloned(),
+        });
+        let running = self.record.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.record.peak.fetch_max(running, Ordering::SeqCst);
+        thread::sleep(self.latency);
+        self.record.running.fetch_sub(1, Ordering::SeqCst);
+
+        match config {
+            Some(config) if !self.samples.is_empty() => {
+                Ok(self.samples[config.seed as usize % self.samples.len()].clone())
+            }
+            _ => self
+                .commands
+                .get(request.trim())
+                .or(self.fallback.as_ref())
+                .cloned()
+                .ok_or_else(|| anyhow!("MockBackend has no command for '{}'", request)),
+        }
+    }
+}
+
+impl InferenceBackend for MockBackend {
+    fn generate(&self, request: &str, context: Option<&str>) -> Result<String> {
+        self.answer(request, context, None)
+    }
+
+    fn generate_with_config(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        let command = self.answer(request, context, Some(config))?;
+        Ok(match config.truncate_at_stop(&command) {
+            Some(command) => command.to_string(),
+            None => command,
+        })
+    }
+
+    fn explain(&self, command: &str) -> Result<String> {
+        Ok(self
+            .explanations
+            .get(command)
+            .cloned()
+            .unwrap_or_else(|| format!("Runs `{}`", command)))
+    }
+
+    fn parallelism(&self) -> usize {
+        self.parallelism.unwrap_or(1)
+    }
+
+    /// Sampling settings when [`MockBackend::with_samples`] is used
+    fn sampling(&self) -> Option<GenerationConfig> {
+        (!self.samples.is_empty()).then(GenerationConfig::default)
+    }
+
+    /// Reported as an ONNX model at the path `mock`
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            backend: BackendKind::Onnx,
+            model_path: PathBuf::from("mock"),
+            template: PromptTemplate::Plain,
+            vocab_size: 0,
+            preset: None,
+            device: "cpu".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_and_stop_sequences() {
+        let backend = MockBackend::new()
+            .with_command("show disk usage", "df -h\nls")
+            .with_fallback("pwd");
+
+        assert_eq!(
+            backend.generate("  show disk usage ", None).unwrap(),
+            "df -h\nls"
+        );
+        assert_eq!(backend.generate("anything", None).unwrap(), "pwd");
+        let config = GenerationConfig::default().with_stop("\n");
+        assert_eq!(
+            backend
+                .generate_with_config("show disk usage", None, &config)
+                .unwrap(),
+            "df -h"
+        );
+        assert_eq!(backend.explain("pwd").unwrap(), "Runs `pwd`");
+        assert_eq!(backend.calls(), 3);
+        assert_eq!(backend.requests()[2].config, Some(config));
+    }
+
+    #[test]
+    fn test_samples_by_seed_and_shared_record() {
+        let backend = MockBackend::new()
+            .with_command("list files", "ls")
+            .with_samples(["ls -a", "ls -l"]);
+        let clone = backend.clone();
+
+        assert_eq!(clone.generate("list files", Some("cwd")).unwrap(), "ls");
+        let config = GenerationConfig::default().with_seed(3);
+        assert_eq!(
+            clone
+                .generate_with_config("list files", None, &config)
+                .unwrap(),
+            "ls -l"
+        );
+        assert!(backend.sampling().is_some());
+        assert_eq!(backend.calls(), 2);
+        assert_eq!(backend.requests()[0].context.as_deref(), Some("cwd"));
+        assert_eq!(backend.peak_concurrency(), 1);
+    }
+}
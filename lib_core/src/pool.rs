@@ -0,0 +1,264 @@
+// Several instances of one model for concurrent requests
+//
+// `QuantizedLlm` generates through `&self`, but the weights carry the KV cache of
+// the request being decoded, so one instance runs one generation at a time and
+// concurrent callers queue on its lock. A `GeneratorPool` holds several instances
+// of the same model and hands each request the next free one, so up to that many
+// requests decode in parallel, at the cost of memory for every copy. (`Core` keeps
+// no state between passes and needs no pool.)
+//
+// Requests wait in a first-come, first-served queue: a request arriving while all
+// instances are busy is served after every request that was already waiting, so a
+// steady stream of callers cannot starve an earlier one the way a free-for-all on
+// a lock can.
+
+use crate::backend::{InferenceBackend, ModelInfo};
+use crate::generation::GenerationConfig;
+use anyhow::Result;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+/// Instances in use and requests waiting for one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub size: usize,
+    pub busy: usize,
+    pub waiting: usize,
+}
+
+#[derive(Debug)]
+struct Queue {
+    /// Indices of the instances not in use
+    free: Vec<usize>,
+    /// Ticket handed to the next request that arrives
+    next_ticket: u64,
+    /// Ticket of the request served next
+    next_served: u64,
+}
+
+/// A model loaded several times, serving one request per instance
+///
+/// It is an [`InferenceBackend`] itself, so it can stand in for a single instance
+/// anywhere, including a [`crate::ModelRegistry`].
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::{GeneratorPool, InferenceBackend, MockBackend};
+/// use std::sync::Arc;
+///
+/// let pool = GeneratorPool::load(2, || {
+///     Ok::<_, String>(Arc::new(MockBackend::new().with_fallback("ls")) as _)
+/// })
+/// .unwrap();
+/// assert_eq!(pool.generate("list files", None).unwrap(), "ls");
+/// assert_eq!(pool.stats().size, 2);
+/// ```
+pub struct GeneratorPool {
+    instances: Vec<Arc<dyn InferenceBackend>>,
+    queue: Mutex<Queue>,
+    released: Condvar,
+}
+
+/// An instance lent to one request, returned to the pool when dropped
+struct Lease<'a> {
+    pool: &'a GeneratorPool,
+    index: usize,
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        self.pool.lock().free.push(self.index);
+        self.pool.released.notify_all();
+    }
+}
+
+impl GeneratorPool {
+    /// Pool of `instances`, which should all be the same model
+    ///
+    /// # Panics
+    ///
+    /// If `instances` is empty.
+    pub fn new(instances: Vec<Arc<dyn InferenceBackend>>) -> Self {
+        assert!(!instances.is_empty(), "a pool needs at least one instance");
+        Self {
+            queue: Mutex::new(Queue {
+                free: (0..instances.len()).rev().collect(),
+                next_ticket: 0,
+                next_served: 0,
+            }),
+            instances,
+            released: Condvar::new(),
+        }
+    }
+
+    /// Pool of `size` instances (at least one), each made by calling `load`
+    pub fn load<E>(
+        size: usize,
+        mut load: impl FnMut() -> Result<Arc<dyn InferenceBackend>, E>,
+    ) -> Result<Self, E> {
+        let instances = (0..size.max(1))
+            .map(|_| load())
+            .collect::<Result<Vec<_>, E>>()?;
+        Ok(Self::new(instances))
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let queue = self.lock();
+        PoolStats {
+            size: self.instances.len(),
+            busy: self.instances.len() - queue.free.len(),
+            waiting: (queue.next_ticket - queue.next_served) as usize,
+        }
+    }
+
+    /// Run `f` on the next free instance, waiting in line for one if needed
+    pub fn with_instance<T>(&self, f: impl FnOnce(&dyn InferenceBackend) -> T) -> T {
+        let lease = self.acquire();
+        f(self.instances[lease.index].as_ref())
+    }
+
+    fn acquire(&self) -> Lease<'_> {
+        let mut queue = self.lock();
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        while queue.next_served != ticket || queue.free.is_empty() {
+            queue = self
+                .released
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        queue.next_served += 1;
+        let index = queue.free.pop().expect("checked above");
+        // The next in line may find another free instance
+        self.released.notify_all();
+        Lease { pool: self, index }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // The queue is consistent between statements, so a panic elsewhere
+        // cannot leave it half updated
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl InferenceBackend for GeneratorPool {
+    fn generate(&self, request: &str, context: Option<&str>) -> Result<String> {
+        self.with_instance(|backend| backend.generate(request, context))
+    }
+
+    fn generate_with_config(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        self.with_instance(|backend| backend.generate_with_config(request, context, config))
+    }
+
+    fn explain(&self, command: &str) -> Result<String> {
+        self.with_instance(|backend| backend.explain(command))
+    }
+
+    fn explain_all(&self, commands: &[String]) -> Vec<Result<String>> {
+        self.with_instance(|backend| backend.explain_all(commands))
+    }
+
+    /// One generation per instance; alternatives are drawn from every instance
+    /// at once rather than all from the first one free
+    fn parallelism(&self) -> usize {
+        self.instances.len()
+    }
+
+    fn sampling(&self) -> Option<GenerationConfig> {
+        self.instances[0].sampling()
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        self.instances[0].model_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Instances answering `ls` slowly enough for requests to overlap; clones
+    /// share the record of how many ran at once
+    fn slow_backend() -> MockBackend {
+        MockBackend::new()
+            .with_fallback("ls")
+            .with_latency(Duration::from_millis(30))
+    }
+
+    #[test]
+    fn test_requests_run_in_parallel_one_per_instance() {
+        let backend = slow_backend();
+        let pool = Arc::new(
+            GeneratorPool::load(2, || Ok::<_, ()>(Arc::new(backend.clone()) as _)).unwrap(),
+        );
+
+        let handles: Vec<_> = (0..6)
+            .map(|_| {
+                let pool = pool.clone();
+                thread::spawn(move || pool.generate("list files", None).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "ls");
+        }
+        assert_eq!(backend.peak_concurrency(), 2);
+        assert_eq!(backend.calls(), 6);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                size: 2,
+                busy: 0,
+                waiting: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_alternatives_use_every_instance() {
+        let backend = slow_backend();
+        let pool = GeneratorPool::load(2, || Ok::<_, ()>(Arc::new(backend.clone()) as _)).unwrap();
+
+        assert_eq!(pool.parallelism(), 2);
+        // Every rephrasing yields `ls`, so only one distinct command is found
+        assert_eq!(
+            pool.alternatives("list files", 3, None).unwrap(),
+            vec!["ls"]
+        );
+        assert_eq!(backend.peak_concurrency(), 2);
+    }
+
+    #[test]
+    fn test_waiting_requests_are_served_in_arrival_order() {
+        let pool = Arc::new(GeneratorPool::new(vec![Arc::new(MockBackend::new())]));
+        let served = Arc::new(Mutex::new(Vec::new()));
+
+        let held = pool.acquire();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let (waiter, served) = (pool.clone(), served.clone());
+                let handle = thread::spawn(move || {
+                    waiter.with_instance(|_| served.lock().unwrap().push(i));
+                });
+                // Wait until this request is in line before the next arrives
+                while pool.stats().waiting <= i {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+        assert_eq!(pool.stats().busy, 1);
+        drop(held);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*served.lock().unwrap(), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,191 @@
+// Per-model generation presets
+//
+// Fine-tunes differ in the prompt format they were trained on and in the sampling
+// settings that keep them on task. A preset bundles both and is picked by matching
+// its name against the model file name, so `codellama-7b-instruct.Q4_K_M.gguf`
+// gets the CodeLlama settings without the user having to know them. The built-in
+// table covers common models; `[[presets]]` entries in eidos.toml add or override.
+
+use crate::generation::GenerationConfig;
+use crate::prompt::PromptTemplate;
+use std::path::Path;
+
+/// Recommended settings for one family of models
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelPreset {
+    /// Matched against the model file name, ignoring case and punctuation
+    pub name: String,
+    pub template: PromptTemplate,
+    pub generation: GenerationConfig,
+    /// End-of-sequence tokens besides the ones the model file declares
+    pub eos_tokens: Vec<String>,
+    /// Whether prompts start with the BOS token, when the model file does not say
+    /// or says wrong
+    pub add_bos: Option<bool>,
+}
+
+impl ModelPreset {
+    /// Preset with the default template and sampling settings
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            template: PromptTemplate::default(),
+            generation: GenerationConfig::default(),
+            eos_tokens: Vec::new(),
+            add_bos: None,
+        }
+    }
+
+    pub fn with_template(mut self, template: PromptTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    pub fn with_generation(mut self, generation: GenerationConfig) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    pub fn with_eos_token(mut self, token: impl Into<String>) -> Self {
+        self.eos_tokens.push(token.into());
+        self
+    }
+
+    pub fn with_add_bos(mut self, add_bos: bool) -> Self {
+        self.add_bos = Some(add_bos);
+        self
+    }
+
+    fn matches(&self, file_name: &str) -> bool {
+        let key = normalize(&self.name);
+        !key.is_empty() && file_name.contains(&key)
+    }
+}
+
+/// Presets looked up by model file name
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::presets::PresetRegistry;
+/// use lib_core::PromptTemplate;
+///
+/// let registry = PresetRegistry::builtin();
+/// let preset = registry.find("models/codellama-7b-instruct.Q4_K_M.gguf").unwrap();
+/// assert_eq!(preset.name, "codellama");
+/// assert_eq!(preset.template, PromptTemplate::Llama2);
+/// assert!(registry.find("model.onnx").is_none());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PresetRegistry {
+    presets: Vec<ModelPreset>,
+}
+
+impl PresetRegistry {
+    /// The presets shipped with eidos
+    pub fn builtin() -> Self {
+        let chat_model = |name: &str, temperature: f64, top_p: f64| {
+            ModelPreset::new(name)
+                .with_template(PromptTemplate::Llama2)
+                .with_generation(
+                    GenerationConfig::default()
+                        .with_temperature(temperature)
+                        .with_top_p(top_p)
+                        .with_repeat_penalty(1.1)
+                        // The model starting a new turn means the answer is over
+                        .with_stop("[INST]"),
+                )
+        };
+
+        Self {
+            presets: vec![
+                chat_model("codellama", 0.2, 0.95),
+                chat_model("llama2", 0.2, 0.9),
+                chat_model("mistral", 0.3, 0.9),
+            ],
+        }
+    }
+
+    /// Add `preset`, replacing any preset with the same name
+    pub fn insert(&mut self, preset: ModelPreset) {
+        match self.presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ModelPreset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+
+    pub fn presets(&self) -> &[ModelPreset] {
+        &self.presets
+    }
+
+    /// Preset for the model at `model_path`
+    ///
+    /// When several names match, the longest one wins, so `codellama` is
+    /// preferred over a generic `llama` entry.
+    pub fn find(&self, model_path: impl AsRef<Path>) -> Option<&ModelPreset> {
+        let file_name = model_path.as_ref().file_name()?.to_string_lossy();
+        let file_name = normalize(&file_name);
+        self.presets
+            .iter()
+            .filter(|p| p.matches(&file_name))
+            .max_by_key(|p| normalize(&p.name).len())
+    }
+}
+
+/// Lowercase alphanumerics only, so `Llama-2` and `llama2` compare equal
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_ignores_punctuation_and_case() {
+        let registry = PresetRegistry::builtin();
+        let preset = registry.find("/models/Llama-2-7b-Chat.Q4_0.gguf").unwrap();
+        assert_eq!(preset.name, "llama2");
+        assert_eq!(preset.generation.temperature, 0.2);
+        assert_eq!(
+            registry.find("mistral-7b-instruct-v0.2.gguf").unwrap().name,
+            "mistral"
+        );
+        // Only the file name is matched, not the directories above it
+        assert!(registry.find("/opt/mistral/model.gguf").is_none());
+    }
+
+    #[test]
+    fn test_longest_match_wins() {
+        let mut registry = PresetRegistry::builtin();
+        registry.insert(ModelPreset::new("llama"));
+        assert_eq!(
+            registry.find("codellama-13b.gguf").unwrap().name,
+            "codellama"
+        );
+        assert_eq!(registry.find("llama-3-8b.gguf").unwrap().name, "llama");
+    }
+
+    #[test]
+    fn test_insert_overrides_builtin() {
+        let mut registry = PresetRegistry::builtin();
+        let count = registry.presets().len();
+        registry.insert(
+            ModelPreset::new("mistral")
+                .with_generation(GenerationConfig::default().with_temperature(0.7)),
+        );
+        assert_eq!(registry.presets().len(), count);
+        assert_eq!(registry.get("mistral").unwrap().generation.temperature, 0.7);
+        assert_eq!(
+            registry.get("mistral").unwrap().template,
+            PromptTemplate::Plain
+        );
+    }
+}
@@ -0,0 +1,478 @@
+// What a command would touch, without running it
+//
+// `eidos core --preview` shows, beneath a generated command, the paths it refers
+// to, whether it descends into directories, and roughly how much it would print.
+// The command is taken apart with `breakdown_command`, so flags and operands are
+// known per program, and the filesystem is then probed in its place: paths are
+// looked up and the directories the command would walk are walked here, read-only,
+// without following symlinks and with `find -maxdepth` and `tree -L` limits
+// respected. This is the dry run: the command itself never executes.
+//
+// Walking stops after `MAX_PROBED_ENTRIES`, so a preview of `find /` stays quick;
+// figures gathered after that point are reported as lower bounds.
+
+use crate::breakdown::{breakdown_command, CommandPart};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory entries probed per preview before giving up on exact figures
+pub const MAX_PROBED_ENTRIES: u64 = 10_000;
+
+/// Programs whose operands are files or directories
+const PATH_PROGRAMS: [&str; 11] = [
+    "ls", "cat", "head", "tail", "wc", "du", "stat", "file", "find", "tree", "grep",
+];
+
+/// Programs that list the working directory when given no path
+const DEFAULT_DOT: [&str; 4] = ["ls", "du", "find", "tree"];
+
+/// What a path operand is on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PathKind {
+    File,
+    Directory,
+    /// A symlink, device, socket or the like
+    Other,
+    Missing,
+    /// A glob or variable the shell would expand, not probed
+    Pattern,
+}
+
+/// A path the command refers to, as found on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PathAccess {
+    /// The operand as written
+    pub path: String,
+    pub kind: PathKind,
+    /// Entries below a directory the command walks (or lists)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub entries: Option<u64>,
+    /// Size of a file, or of the files below a directory the command walks
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub bytes: Option<u64>,
+}
+
+/// How an [`OutputEstimate`] relates to the real output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Bound {
+    About,
+    AtLeast,
+    AtMost,
+}
+
+/// Expected size of what the command prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OutputEstimate {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub lines: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub bytes: Option<u64>,
+    pub bound: Bound,
+}
+
+/// Static analysis and probe of one command
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandPreview {
+    /// Programs of the command, in pipeline order
+    pub programs: Vec<String>,
+    pub paths: Vec<PathAccess>,
+    /// Whether the command descends into directories
+    pub recursive: bool,
+    /// Output of the last program, when it can be estimated
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub output: Option<OutputEstimate>,
+    /// Probing stopped after [`MAX_PROBED_ENTRIES`] entries
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub truncated: bool,
+}
+
+/// Preview `command` as if run in `dir`
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::preview::{preview_command, PathKind};
+///
+/// let dir = std::env::temp_dir();
+/// let preview = preview_command("ls -la no-such-file", &dir);
+/// assert_eq!(preview.programs, ["ls"]);
+/// assert_eq!(preview.paths[0].kind, PathKind::Missing);
+/// assert!(!preview.recursive);
+/// ```
+pub fn preview_command(command: &str, dir: &Path) -> CommandPreview {
+    let mut preview = CommandPreview {
+        programs: Vec::new(),
+        paths: Vec::new(),
+        recursive: false,
+        output: None,
+        truncated: false,
+    };
+    let mut budget = MAX_PROBED_ENTRIES;
+    for part in breakdown_command(command) {
+        preview.programs.push(part.program.clone());
+        let depth = walk_depth(&part);
+        preview.recursive |= depth.map_or(true, |d| d > 1);
+
+        let mut probed = Vec::new();
+        for operand in path_operands(&part) {
+            let access = probe(&operand, dir, depth, &mut budget);
+            preview.truncated |= budget == 0;
+            probed.push(access);
+        }
+        preview.output = estimate(&part, &probed, budget == 0);
+        preview.paths.extend(probed);
+    }
+    preview
+}
+
+/// Levels the program descends below its operands: `Some(0)` for the operand
+/// alone, `Some(1)` for a directory's entries, `None` for no limit
+fn walk_depth(part: &CommandPart) -> Option<u64> {
+    let has = |names: &[&str]| part.flags.iter().any(|f| names.contains(&f.flag.as_str()));
+    let value = |names: &[&str]| {
+        part.flags
+            .iter()
+            .find(|f| names.contains(&f.flag.as_str()))
+            .and_then(|f| f.value.as_deref()?.parse().ok())
+    };
+    match part.program.as_str() {
+        "ls" if has(&["-R", "--recursive"]) => None,
+        "ls" if has(&["-d", "--directory"]) => Some(0),
+        "ls" => Some(1),
+        "grep" if has(&["-r", "-R", "--recursive", "--dereference-recursive"]) => None,
+        "find" => value(&["-maxdepth"]),
+        // du sizes the whole tree whatever depth it prints
+        "du" => None,
+        "tree" => value(&["-L"]),
+        _ => Some(0),
+    }
+}
+
+/// Operands naming files or directories, with the implied `.` of `ls` and friends
+fn path_operands(part: &CommandPart) -> Vec<String> {
+    if !PATH_PROGRAMS.contains(&part.program.as_str()) {
+        return Vec::new();
+    }
+    let mut operands: Vec<String> = part.operands.iter().map(|o| unquote(o)).collect();
+    // Without -e, grep's first operand is the pattern
+    if part.program == "grep" && !part.flags.iter().any(|f| f.flag == "-e") && !operands.is_empty()
+    {
+        operands.remove(0);
+    }
+    let recursive_grep = part.program == "grep" && walk_depth(part).is_none();
+    if operands.is_empty() && (DEFAULT_DOT.contains(&part.program.as_str()) || recursive_grep) {
+        operands.push(".".to_string());
+    }
+    operands
+}
+
+fn unquote(operand: &str) -> String {
+    let quoted = |q: char| operand.len() >= 2 && operand.starts_with(q) && operand.ends_with(q);
+    if quoted('\'') || quoted('"') {
+        operand[1..operand.len() - 1].to_string()
+    } else {
+        operand.to_string()
+    }
+}
+
+fn probe(operand: &str, dir: &Path, depth: Option<u64>, budget: &mut u64) -> PathAccess {
+    let mut access = PathAccess {
+        path: operand.to_string(),
+        kind: PathKind::Pattern,
+        entries: None,
+        bytes: None,
+    };
+    if operand.contains(['*', '?', '[', '$']) {
+        return access;
+    }
+    let path = resolve(operand, dir);
+    let Ok(metadata) = fs::symlink_metadata(&path) else {
+        access.kind = PathKind::Missing;
+        return access;
+    };
+    if metadata.is_file() {
+        access.kind = PathKind::File;
+        access.bytes = Some(metadata.len());
+    } else if metadata.is_dir() {
+        access.kind = PathKind::Directory;
+        if depth != Some(0) {
+            let (entries, bytes) = walk(&path, depth, budget);
+            access.entries = Some(entries);
+            access.bytes = Some(bytes);
+        }
+    } else {
+        access.kind = PathKind::Other;
+    }
+    access
+}
+
+/// `operand` relative to `dir`, with a leading `~` expanded
+fn resolve(operand: &str, dir: &Path) -> PathBuf {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match (operand.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => dir.join(operand),
+    }
+}
+
+/// Entries and file bytes below `dir`, down to `depth` levels
+fn walk(dir: &Path, depth: Option<u64>, budget: &mut u64) -> (u64, u64) {
+    let (mut entries, mut bytes) = (0, 0);
+    let mut pending = vec![(dir.to_path_buf(), 1)];
+    while let Some((dir, level)) = pending.pop() {
+        let Ok(children) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for child in children.flatten() {
+            if *budget == 0 {
+                return (entries, bytes);
+            }
+            *budget -= 1;
+            entries += 1;
+            let Ok(metadata) = child.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                bytes += metadata.len();
+            } else if metadata.is_dir() && depth.map_or(true, |d| level < d) {
+                pending.push((child.path(), level + 1));
+            }
+        }
+    }
+    (entries, bytes)
+}
+
+/// Output of `part` given its probed paths
+fn estimate(part: &CommandPart, paths: &[PathAccess], truncated: bool) -> Option<OutputEstimate> {
+    let bound = if truncated {
+        Bound::AtLeast
+    } else {
+        Bound::About
+    };
+    let count = |kind: PathKind| paths.iter().filter(|p| p.kind == kind).count() as u64;
+    let entries: u64 = paths
+        .iter()
+        .map(|p| match p.kind {
+            PathKind::Directory => p.entries.unwrap_or(1),
+            _ => 1,
+        })
+        .sum();
+    let file_bytes: u64 = paths.iter().filter_map(|p| p.bytes).sum();
+    let n_value = |names: &[&str]| {
+        part.flags
+            .iter()
+            .find(|f| names.contains(&f.flag.as_str()))
+            .and_then(|f| f.value.as_deref()?.trim_start_matches('+').parse().ok())
+    };
+    let lines = |lines: u64, bound: Bound| OutputEstimate {
+        lines: Some(lines),
+        bytes: None,
+        bound,
+    };
+
+    if paths.iter().any(|p| p.kind == PathKind::Pattern) {
+        return None;
+    }
+    match part.program.as_str() {
+        "ls" | "tree" => Some(lines(entries, bound)),
+        // Filters only narrow what find prints
+        "find" => {
+            let filters = part
+                .flags
+                .iter()
+                .any(|f| !matches!(f.flag.as_str(), "-maxdepth" | "-mindepth" | "-print"));
+            Some(lines(
+                // find prints its starting points too
+                entries + count(PathKind::Directory),
+                if filters { Bound::AtMost } else { bound },
+            ))
+        }
+        "du" if part.flags.iter().any(|f| f.flag == "-s") => Some(lines(paths.len() as u64, bound)),
+        "cat" => Some(OutputEstimate {
+            lines: None,
+            bytes: Some(file_bytes),
+            bound,
+        }),
+        "head" | "tail" if paths.is_empty() => None,
+        "head" | "tail" => match n_value(&["-c"]) {
+            Some(bytes) => Some(OutputEstimate {
+                lines: None,
+                bytes: Some(bytes * paths.len() as u64),
+                bound: Bound::AtMost,
+            }),
+            None => Some(lines(
+                n_value(&["-n"]).unwrap_or(10) * paths.len() as u64,
+                Bound::AtMost,
+            )),
+        },
+        "grep" if !paths.is_empty() => Some(OutputEstimate {
+            lines: None,
+            bytes: Some(file_bytes),
+            bound: Bound::AtMost,
+        }),
+        "wc" | "file" if !paths.is_empty() => {
+            let total = u64::from(part.program == "wc" && paths.len() > 1);
+            Some(lines(paths.len() as u64 + total, Bound::About))
+        }
+        _ => None,
+    }
+}
+
+impl fmt::Display for CommandPreview {
+    /// A few lines describing what the command would touch
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.recursive {
+            "Descends into directories"
+        } else if self.paths.is_empty() {
+            "Names no files or directories"
+        } else {
+            "Does not descend into directories"
+        })?;
+        for path in &self.paths {
+            write!(f, "\n  {} ({})", path.path, describe(path))?;
+        }
+        if let Some(output) = &self.output {
+            write!(f, "\nOutput: {}", output)?;
+        }
+        if self.truncated {
+            write!(
+                f,
+                "\nStopped probing after {} entries; figures are lower bounds",
+                MAX_PROBED_ENTRIES
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(path: &PathAccess) -> String {
+    let kind = match path.kind {
+        PathKind::File => "file",
+        PathKind::Directory => "directory",
+        PathKind::Other => "special file",
+        PathKind::Missing => "does not exist",
+        PathKind::Pattern => "expanded by the shell, not probed",
+    };
+    let mut parts = vec![kind.to_string()];
+    if let Some(entries) = path.entries {
+        parts.push(format!("{} entries", entries));
+    }
+    if let Some(bytes) = path.bytes {
+        parts.push(human_bytes(bytes));
+    }
+    parts.join(", ")
+}
+
+impl fmt::Display for OutputEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bound = match self.bound {
+            Bound::About => "about",
+            Bound::AtLeast => "at least",
+            Bound::AtMost => "at most",
+        };
+        match (self.lines, self.bytes) {
+            (Some(lines), _) => write!(f, "{} {} lines", bound, lines),
+            (None, Some(bytes)) => write!(f, "{} {}", bound, human_bytes(bytes)),
+            (None, None) => write!(f, "unknown"),
+        }
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        fs::write(dir.path().join("README.md"), "hello\n").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("src/nested/lib.rs"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_listing_and_walking_directories() {
+        let dir = tree();
+        let preview = preview_command("ls -la src", dir.path());
+        assert!(!preview.recursive);
+        assert_eq!(preview.paths[0].kind, PathKind::Directory);
+        assert_eq!(preview.paths[0].entries, Some(2));
+        assert_eq!(preview.output.unwrap().lines, Some(2));
+
+        let preview = preview_command("find . -name '*.rs'", dir.path());
+        assert!(preview.recursive);
+        assert_eq!(preview.paths[0].path, ".");
+        assert_eq!(preview.paths[0].entries, Some(5));
+        assert_eq!(preview.paths[0].bytes, Some(19));
+        let output = preview.output.unwrap();
+        assert_eq!((output.lines, output.bound), (Some(6), Bound::AtMost));
+
+        let preview = preview_command("find src -maxdepth 1", dir.path());
+        assert!(!preview.recursive);
+        assert_eq!(preview.paths[0].entries, Some(2));
+        assert_eq!(preview.output.unwrap().bound, Bound::About);
+
+        let preview = preview_command("grep -rn TODO", dir.path());
+        assert!(preview.recursive);
+        assert_eq!(preview.paths[0].path, ".");
+    }
+
+    #[test]
+    fn test_files_patterns_and_pipelines() {
+        let dir = tree();
+        let preview = preview_command("cat README.md missing.txt", dir.path());
+        let kinds: Vec<PathKind> = preview.paths.iter().map(|p| p.kind).collect();
+        assert_eq!(kinds, [PathKind::File, PathKind::Missing]);
+        assert_eq!(preview.output.unwrap().bytes, Some(6));
+        assert_eq!(
+            preview.to_string(),
+            "Does not descend into directories\n  README.md (file, 6 bytes)\n  \
+             missing.txt (does not exist)\nOutput: about 6 bytes"
+        );
+
+        let preview = preview_command("head -n 5 README.md src/main.rs", dir.path());
+        assert_eq!(preview.output.unwrap().to_string(), "at most 10 lines");
+
+        let preview = preview_command("ls *.md", dir.path());
+        assert_eq!(preview.paths[0].kind, PathKind::Pattern);
+        assert_eq!(preview.output, None);
+
+        let preview = preview_command("ps aux | grep nginx", dir.path());
+        assert_eq!(preview.programs, ["ps", "grep"]);
+        assert!(preview.paths.is_empty());
+        assert!(!preview.recursive);
+    }
+
+    #[test]
+    fn test_walking_stops_at_the_budget() {
+        let dir = tree();
+        let mut budget = 2;
+        let (entries, _) = walk(dir.path(), None, &mut budget);
+        assert_eq!((entries, budget), (2, 0));
+    }
+}
@@ -0,0 +1,431 @@
+// Prompt templates for the command generation backends
+//
+// Each backend expects its input in a different shape: the ONNX seq2seq model takes
+// the raw request, chat-tuned GGUF models expect their instruction format. The user's
+// system prompt (`[core] system_prompt`) is injected by whichever template applies.
+// In a refinement dialogue the earlier requests and commands travel as context, so
+// a follow-up like "only the hidden ones" edits the last command.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Prompt format expected by a generation backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PromptTemplate {
+    /// Seq2seq models (ONNX): the system prompt is prepended to the request
+    #[default]
+    Plain,
+    /// Llama-2 chat instruction format (GGUF models)
+    Llama2,
+}
+
+impl PromptTemplate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptTemplate::Plain => "plain",
+            PromptTemplate::Llama2 => "llama2",
+        }
+    }
+}
+
+impl fmt::Display for PromptTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PromptTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "plain" => Ok(PromptTemplate::Plain),
+            "llama2" | "llama-2" => Ok(PromptTemplate::Llama2),
+            other => Err(format!(
+                "Unknown prompt template '{}' (expected plain or llama2)",
+                other
+            )),
+        }
+    }
+}
+
+/// Builds the final model input from a request and an optional system prompt
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::prompt::{PromptBuilder, PromptTemplate};
+///
+/// let prompt = PromptBuilder::new(PromptTemplate::Plain)
+///     .system("Target distro: Debian. Prefer long flags.")
+///     .build("list files");
+/// assert_eq!(prompt, "Target distro: Debian. Prefer long flags.\n\nlist files");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptBuilder {
+    template: PromptTemplate,
+    system: Option<String>,
+}
+
+impl PromptBuilder {
+    pub fn new(template: PromptTemplate) -> Self {
+        Self {
+            template,
+            system: None,
+        }
+    }
+
+    /// Set the system prompt; blank prompts are ignored
+    pub fn system(mut self, prompt: impl Into<String>) -> Self {
+        self.set_system(prompt);
+        self
+    }
+
+    /// Replace the system prompt in place; blank prompts clear it
+    pub fn set_system(&mut self, prompt: impl Into<String>) {
+        let prompt = prompt.into();
+        let prompt = prompt.trim();
+        self.system = (!prompt.is_empty()).then(|| prompt.to_string());
+    }
+
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system.as_deref()
+    }
+
+    pub fn template(&self) -> PromptTemplate {
+        self.template
+    }
+
+    /// Switch to another prompt format, keeping the system prompt
+    pub fn set_template(&mut self, template: PromptTemplate) {
+        self.template = template;
+    }
+
+    /// Render the model input for `request`
+    pub fn build(&self, request: &str) -> String {
+        self.build_with_context(request, None)
+    }
+
+    /// Render the model input for `request` with per-request context (e.g. a
+    /// snapshot of the user's environment) placed after the system prompt
+    pub fn build_with_context(&self, request: &str, context: Option<&str>) -> String {
+        let context = context.map(str::trim).filter(|c| !c.is_empty());
+        let system = match (self.system.as_deref(), context) {
+            (Some(system), Some(context)) => Some(format!("{}\n\n{}", system, context)),
+            (system, context) => system.or(context).map(str::to_string),
+        };
+
+        match (self.template, system.as_deref()) {
+            (PromptTemplate::Plain, None) => request.to_string(),
+            (PromptTemplate::Plain, Some(system)) => format!("{}\n\n{}", system, request),
+            (PromptTemplate::Llama2, None) => format!("[INST] {} [/INST]", request),
+            (PromptTemplate::Llama2, Some(system)) => format!(
+                "[INST] <<SYS>>\n{}\n<</SYS>>\n\n{} [/INST]",
+                system, request
+            ),
+        }
+    }
+}
+
+/// Earlier turns kept by [`RefinementContext`]; older ones are dropped
+pub const MAX_REFINEMENT_TURNS: usize = 4;
+
+/// The requests and commands of a refinement dialogue so far
+///
+/// Rendered as context for the next request, so follow-ups such as "sort by size
+/// instead" refine the previous command rather than starting fresh.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::prompt::RefinementContext;
+///
+/// let mut refinement = RefinementContext::new();
+/// refinement.push("list files", "ls -la");
+/// let context = refinement.render().unwrap();
+/// assert!(context.contains("Request: list files\nCommand: ls -la"));
+/// assert_eq!(refinement.last_command(), Some("ls -la"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefinementContext {
+    turns: Vec<(String, String)>,
+}
+
+impl RefinementContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `request` produced `command`
+    pub fn push(&mut self, request: impl Into<String>, command: impl Into<String>) {
+        if self.turns.len() == MAX_REFINEMENT_TURNS {
+            self.turns.remove(0);
+        }
+        self.turns.push((request.into(), command.into()));
+    }
+
+    /// Remove and return the last turn
+    pub fn pop(&mut self) -> Option<(String, String)> {
+        self.turns.pop()
+    }
+
+    /// `(request, command)` pairs, oldest first
+    pub fn turns(&self) -> &[(String, String)] {
+        &self.turns
+    }
+
+    pub fn last_command(&self) -> Option<&str> {
+        self.turns.last().map(|(_, command)| command.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Forget the dialogue, so the next request starts fresh
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+
+    /// Context block for the next request, or `None` before the first command
+    pub fn render(&self) -> Option<String> {
+        if self.turns.is_empty() {
+            return None;
+        }
+        let turns: Vec<String> = self
+            .turns
+            .iter()
+            .map(|(request, command)| format!("Request: {}\nCommand: {}", request, command))
+            .collect();
+        Some(format!(
+            "Earlier in this session:\n{}\nThe next request refines the last command; \
+             change it as asked instead of starting over.",
+            turns.join("\n")
+        ))
+    }
+
+    /// `context` (e.g. the environment) followed by the dialogue, for
+    /// [`PromptBuilder::build_with_context`]
+    pub fn with_context(&self, context: Option<&str>) -> Option<String> {
+        let context = context.map(str::trim).filter(|c| !c.is_empty());
+        match (self.render(), context) {
+            (Some(turns), Some(context)) => Some(format!("{}\n\n{}", context, turns)),
+            (turns, context) => turns.or(context.map(str::to_string)),
+        }
+    }
+}
+
+/// Most earlier commands [`HistoryContext`] keeps; older ones are dropped
+pub const MAX_HISTORY_TURNS: usize = 20;
+
+/// Commands generated by earlier runs, oldest first
+///
+/// Rendered as context for a new request, so prompts such as "same as before but
+/// sorted by size" can build on a command from the history without repeating it.
+/// Unlike [`RefinementContext`], the model is not told to change the last
+/// command: the new request may just as well be unrelated.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::prompt::HistoryContext;
+///
+/// let history = HistoryContext::from_turns([("find large files", "du -ah . | sort -rh")], 5);
+/// let context = history.with_context(Some("Environment:\nSHELL=/bin/bash")).unwrap();
+/// assert!(context.contains("Request: find large files\nCommand: du -ah . | sort -rh"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryContext {
+    turns: Vec<(String, String)>,
+}
+
+impl HistoryContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The last `limit` of `turns` (at most [`MAX_HISTORY_TURNS`]), given
+    /// oldest first as `(request, command)` pairs
+    pub fn from_turns<R, C>(turns: impl IntoIterator<Item = (R, C)>, limit: usize) -> Self
+    where
+        R: Into<String>,
+        C: Into<String>,
+    {
+        let mut turns: Vec<(String, String)> = turns
+            .into_iter()
+            .map(|(request, command)| (request.into(), command.into()))
+            .collect();
+        let keep = limit.min(MAX_HISTORY_TURNS);
+        turns.drain(..turns.len().saturating_sub(keep));
+        Self { turns }
+    }
+
+    /// `(request, command)` pairs, oldest first
+    pub fn turns(&self) -> &[(String, String)] {
+        &self.turns
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Context block listing the earlier commands, or `None` without any
+    pub fn render(&self) -> Option<String> {
+        if self.turns.is_empty() {
+            return None;
+        }
+        let turns: Vec<String> = self
+            .turns
+            .iter()
+            .map(|(request, command)| format!("Request: {}\nCommand: {}", request, command))
+            .collect();
+        Some(format!(
+            "Recently generated commands, oldest first:\n{}\nThe next request may refer to \
+             these (\"the same\", \"that command\"); build on the one it means, otherwise \
+             ignore them.",
+            turns.join("\n")
+        ))
+    }
+
+    /// `context` (e.g. the environment) followed by the earlier commands, for
+    /// [`PromptBuilder::build_with_context`]
+    pub fn with_context(&self, context: Option<&str>) -> Option<String> {
+        let context = context.map(str::trim).filter(|c| !c.is_empty());
+        match (self.render(), context) {
+            (Some(turns), Some(context)) => Some(format!("{}\n\n{}", context, turns)),
+            (turns, context) => turns.or(context.map(str::to_string)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_template() {
+        let builder = PromptBuilder::new(PromptTemplate::Plain);
+        assert_eq!(builder.build("list files"), "list files");
+
+        let builder = builder.system("Prefer long flags.");
+        assert_eq!(
+            builder.build("list files"),
+            "Prefer long flags.\n\nlist files"
+        );
+    }
+
+    #[test]
+    fn test_llama2_template() {
+        let builder = PromptBuilder::new(PromptTemplate::Llama2).system("Target: Arch Linux");
+        assert_eq!(
+            builder.build("update packages"),
+            "[INST] <<SYS>>\nTarget: Arch Linux\n<</SYS>>\n\nupdate packages [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_build_with_context() {
+        let context = Some("Environment:\nSHELL=/bin/zsh");
+        let builder = PromptBuilder::new(PromptTemplate::Plain);
+        assert_eq!(
+            builder.build_with_context("list files", context),
+            "Environment:\nSHELL=/bin/zsh\n\nlist files"
+        );
+        assert_eq!(
+            builder.build_with_context("list files", Some("  ")),
+            "list files"
+        );
+
+        let builder = PromptBuilder::new(PromptTemplate::Llama2).system("Target: Arch Linux");
+        assert_eq!(
+            builder.build_with_context("update packages", context),
+            "[INST] <<SYS>>\nTarget: Arch Linux\n\nEnvironment:\nSHELL=/bin/zsh\n<</SYS>>\n\nupdate packages [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_blank_system_prompt_ignored() {
+        let mut builder = PromptBuilder::new(PromptTemplate::Plain).system("   ");
+        assert_eq!(builder.system_prompt(), None);
+
+        builder.set_system("Use GNU coreutils");
+        assert_eq!(builder.system_prompt(), Some("Use GNU coreutils"));
+        builder.set_system("");
+        assert_eq!(builder.system_prompt(), None);
+    }
+
+    #[test]
+    fn test_refinement_context() {
+        let mut refinement = RefinementContext::new();
+        assert_eq!(refinement.render(), None);
+        assert_eq!(
+            refinement
+                .with_context(Some("Environment:\nSHELL=/bin/zsh"))
+                .as_deref(),
+            Some("Environment:\nSHELL=/bin/zsh")
+        );
+
+        refinement.push("list files", "ls -la");
+        refinement.push("only the hidden ones", "ls -d .*");
+        assert_eq!(
+            refinement.render().as_deref(),
+            Some(
+                "Earlier in this session:\nRequest: list files\nCommand: ls -la\n\
+                 Request: only the hidden ones\nCommand: ls -d .*\n\
+                 The next request refines the last command; change it as asked instead of \
+                 starting over."
+            )
+        );
+        let combined = refinement
+            .with_context(Some("Environment:\nSHELL=/bin/zsh"))
+            .unwrap();
+        assert!(combined.starts_with("Environment:\nSHELL=/bin/zsh\n\nEarlier in this session:"));
+
+        for i in 0..MAX_REFINEMENT_TURNS {
+            refinement.push(format!("request {}", i), format!("cmd{}", i));
+        }
+        assert_eq!(refinement.turns().len(), MAX_REFINEMENT_TURNS);
+        assert_eq!(refinement.turns()[0].0, "request 0");
+        assert_eq!(refinement.last_command(), Some("cmd3"));
+
+        refinement.clear();
+        assert!(refinement.is_empty());
+    }
+
+    #[test]
+    fn test_history_context() {
+        let history = HistoryContext::from_turns(Vec::<(String, String)>::new(), 3);
+        assert!(history.is_empty());
+        assert_eq!(history.with_context(None), None);
+        assert_eq!(
+            history.with_context(Some("Environment:")).as_deref(),
+            Some("Environment:")
+        );
+
+        let turns = [
+            ("list files", "ls -la"),
+            ("show disk usage", "df -h"),
+            ("find large files", "du -ah . | sort -rh | head"),
+        ];
+        let history = HistoryContext::from_turns(turns, 2);
+        assert_eq!(history.turns().len(), 2);
+        assert_eq!(
+            history.render().as_deref(),
+            Some(
+                "Recently generated commands, oldest first:\nRequest: show disk usage\n\
+                 Command: df -h\nRequest: find large files\nCommand: du -ah . | sort -rh | head\n\
+                 The next request may refer to these (\"the same\", \"that command\"); build on \
+                 the one it means, otherwise ignore them."
+            )
+        );
+        let combined = history
+            .with_context(Some("Environment:\nSHELL=/bin/zsh"))
+            .unwrap();
+        assert!(combined.starts_with("Environment:\nSHELL=/bin/zsh\n\nRecently generated"));
+
+        let many = (0..50).map(|i| (format!("request {}", i), format!("cmd{}", i)));
+        let history = HistoryContext::from_turns(many, 100);
+        assert_eq!(history.turns().len(), MAX_HISTORY_TURNS);
+        assert_eq!(history.turns()[0].1, "cmd30");
+    }
+}
@@ -1,29 +1,85 @@
+use crate::backend::{BackendKind, InferenceBackend, ModelInfo};
+use crate::compat;
+use crate::device;
+use crate::generation::GenerationConfig;
+use crate::grammar::CommandGrammar;
+use crate::presets::{ModelPreset, PresetRegistry};
+use crate::prompt::{PromptBuilder, PromptTemplate};
+use crate::special_tokens::{self, SpecialTokens, TokenMetadata};
+use crate::tokenizer::{self, TokenizerWarning, DEFAULT_CONTEXT_LENGTH};
 use anyhow::{Error as E, Result};
 use candle_core::quantized::gguf_file;
-use candle_core::{Device, Tensor};
-use candle_transformers::generation::LogitsProcessor;
+use candle_core::{DType, Device, Tensor};
+use candle_transformers::generation::{LogitsProcessor, Sampling};
 use candle_transformers::models::quantized_llama::ModelWeights;
+use lib_errors::{EidosError, ErrorCode};
 use std::fs::File;
-use tokenizers::Tokenizer;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokenizers::{Tokenizer, TruncationDirection};
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum QuantizedLlmError {
+    #[error("Failed to load model: {0}")]
     ModelLoad(E),
+    #[error("Failed to load tokenizer: {0}")]
     TokenizerLoad(E),
+    #[error("Inference failed: {0}")]
     Inference(E),
 }
 
+impl EidosError for QuantizedLlmError {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::Model
+    }
+}
+
 pub struct QuantizedLlm {
-    model: ModelWeights,
+    /// Locked per generation: the weights carry the KV cache of the running request
+    model: Mutex<ModelWeights>,
     device: Device,
     tokenizer: Tokenizer,
-    logits_processor: LogitsProcessor,
+    /// Tokenizer components that were missing or unusable at load
+    tokenizer_warnings: Vec<TokenizerWarning>,
+    /// Special tokens declared by the model file
+    token_metadata: TokenMetadata,
+    special_tokens: SpecialTokens,
+    generation: GenerationConfig,
+    prompt: PromptBuilder,
+    deadline: Option<Instant>,
+    /// Rules generated commands are held to while decoding
+    grammar: Option<CommandGrammar>,
+    /// Text each token adds, by id; computed when a grammar is set
+    pieces: Vec<String>,
+    /// Token budget for commands generated through [`InferenceBackend`]
+    max_tokens: usize,
+    model_path: PathBuf,
+    preset: Option<String>,
 }
 
+/// Instruction always given to chat-tuned models before any user framing
+const COMMAND_INSTRUCTION: &str =
+    "Translate the request into a single Linux shell command. Reply with the command only.";
+
+/// Instruction for [`QuantizedLlm::explain_command`]
+const EXPLAIN_INSTRUCTION: &str =
+    "Explain in one or two sentences what the following Linux shell command does.";
+
+/// Token budgets used through [`InferenceBackend`], which has no length parameter;
+/// the command budget can be changed with [`QuantizedLlm::with_max_tokens`]
+const COMMAND_MAX_TOKENS: usize = 96;
+const EXPLAIN_MAX_TOKENS: usize = 160;
+
 impl QuantizedLlm {
+    /// Load a GGUF model on the CPU, applying the built-in preset matching its file name
     pub fn new(model_path: &str, tokenizer_path: &str) -> Result<Self> {
-        let device = Device::Cpu;
+        Self::new_with_device(model_path, tokenizer_path, Device::Cpu)
+    }
 
+    /// Load a GGUF model on `device` (see [`crate::device::select_device`])
+    pub fn new_with_device(model_path: &str, tokenizer_path: &str, device: Device) -> Result<Self> {
         // Load the quantized model from GGUF file
         let mut file = File::open(model_path)
             .map_err(|e| E::msg(format!("Failed to open model file: {}", e)))?;
@@ -32,57 +88,500 @@ impl QuantizedLlm {
         let content = gguf_file::Content::read(&mut file)
             .map_err(|e| E::msg(format!("Failed to read GGUF file: {}", e)))?;
 
-        let model_weights = ModelWeights::from_gguf(content, &mut file, &device)?;
+        // Load tokenizer; over-long prompts keep their end, where the request is
+        let context_length =
+            compat::gguf_context_length(&content).unwrap_or(DEFAULT_CONTEXT_LENGTH);
+        let (tokenizer, tokenizer_warnings) =
+            tokenizer::load_tokenizer(tokenizer_path, context_length, TruncationDirection::Left)
+                .map_err(E::msg)?;
 
-        // Load tokenizer
-        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(E::msg)?;
+        // Fail fast on a mismatched tokenizer instead of producing garbage later
+        if let Some(vocab) = compat::gguf_vocab_size(&content) {
+            compat::check_vocab(tokenizer.get_vocab_size(true), vocab)?;
+        }
 
-        let logits_processor = LogitsProcessor::new(299792458, Some(0.0), None);
+        let token_metadata = special_tokens::gguf_token_metadata(&content);
+        let model_weights = ModelWeights::from_gguf(content, &mut file, &device)?;
 
-        Ok(Self {
-            model: model_weights,
+        let mut llm = Self {
+            model: Mutex::new(model_weights),
             device,
             tokenizer,
-            logits_processor,
+            tokenizer_warnings,
+            token_metadata,
+            special_tokens: SpecialTokens::default(),
+            generation: GenerationConfig::default(),
+            prompt: PromptBuilder::new(PromptTemplate::Llama2).system(COMMAND_INSTRUCTION),
+            deadline: None,
+            grammar: None,
+            pieces: Vec::new(),
+            max_tokens: COMMAND_MAX_TOKENS,
+            model_path: PathBuf::from(model_path),
+            preset: None,
+        };
+        llm.special_tokens = llm.resolve_special_tokens(None);
+
+        // Known model families get their recommended template and sampling
+        Ok(match PresetRegistry::builtin().find(model_path) {
+            Some(preset) => llm.with_preset(preset),
+            None => llm,
+        })
+    }
+
+    /// Use the prompt template and sampling settings of `preset`
+    pub fn with_preset(mut self, preset: &ModelPreset) -> Self {
+        self.prompt.set_template(preset.template);
+        self.generation = preset.generation.clone();
+        self.special_tokens = self.resolve_special_tokens(Some(preset));
+        self.preset = Some(preset.name.clone());
+        self
+    }
+
+    /// BOS and EOS tokens used for generation
+    pub fn special_tokens(&self) -> &SpecialTokens {
+        &self.special_tokens
+    }
+
+    /// Tokenizer components replaced with defaults when loading
+    pub fn tokenizer_warnings(&self) -> &[TokenizerWarning] {
+        &self.tokenizer_warnings
+    }
+
+    fn resolve_special_tokens(&self, preset: Option<&ModelPreset>) -> SpecialTokens {
+        SpecialTokens::resolve(
+            &self.token_metadata,
+            preset.map_or(&[], |p| &p.eos_tokens),
+            preset.and_then(|p| p.add_bos),
+            |token| self.tokenizer.token_to_id(token),
+        )
+    }
+
+    /// Add a system prompt that frames every command generation request
+    ///
+    /// The framing is appended to the built-in instruction, so the model is
+    /// still asked for a bare command.
+    pub fn with_system_prompt(mut self, prompt: &str) -> Self {
+        let prompt = prompt.trim();
+        if prompt.is_empty() {
+            self.prompt.set_system(COMMAND_INSTRUCTION);
+        } else {
+            self.prompt
+                .set_system(format!("{}\n{}", COMMAND_INSTRUCTION, prompt));
+        }
+        self
+    }
+
+    /// Sampling settings used by [`QuantizedLlm::generate_command`]
+    ///
+    /// The default is greedy decoding, which makes output deterministic.
+    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
+        self.generation = config;
+        self
+    }
+
+    /// Stop commands generated through [`InferenceBackend`] after `max_tokens` tokens
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Only generate commands `grammar` allows (see [`crate::SafetyPolicy::grammar`])
+    ///
+    /// A sampled token that would take the command outside the grammar is refused
+    /// and the token sampled again from those that keep it inside, so the model
+    /// cannot write a pipe or start with a binary the policy does not allow.
+    /// Explanations and [`QuantizedLlm::generate`] are not constrained.
+    pub fn with_grammar(mut self, grammar: CommandGrammar) -> Self {
+        if self.pieces.is_empty() {
+            self.pieces = token_pieces(&self.tokenizer);
+        }
+        self.grammar = Some(grammar);
+        self
+    }
+
+    pub fn generation_config(&self) -> &GenerationConfig {
+        &self.generation
+    }
+
+    /// Abandon generation once `deadline` passes
+    ///
+    /// Checked before every token, so a request stops within one forward pass
+    /// of its deadline instead of running to `max_tokens`.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Generate a shell command for a natural language request
+    pub fn generate_command(&self, request: &str, max_tokens: usize) -> Result<String> {
+        self.generate_command_with_context(request, max_tokens, None)
+    }
+
+    /// Generate a shell command with per-request context added to the system prompt
+    pub fn generate_command_with_context(
+        &self,
+        request: &str,
+        max_tokens: usize,
+        context: Option<&str>,
+    ) -> Result<String> {
+        let prompt = self.prompt.build_with_context(request, context);
+        Ok(self
+            .run(
+                &prompt,
+                max_tokens,
+                &self.generation,
+                self.grammar.as_ref(),
+                |_| ControlFlow::Continue(()),
+            )?
+            .trim()
+            .to_string())
+    }
+
+    /// Describe what `command` does in a sentence or two
+    pub fn explain_command(&self, command: &str) -> Result<String> {
+        let prompt = PromptBuilder::new(self.prompt.template())
+            .system(EXPLAIN_INSTRUCTION)
+            .build(command);
+        Ok(self
+            .generate(&prompt, EXPLAIN_MAX_TOKENS, &self.generation)?
+            .trim()
+            .to_string())
+    }
+
+    /// Generate up to `max_tokens` tokens continuing `prompt`
+    ///
+    /// Stops early at an end-of-sequence token (see [`SpecialTokens`]), when the
+    /// output contains one of `config.stop`, which is cut off, or when it starts
+    /// repeating itself (see [`GenerationConfig::loop_prefix_len`]).
+    pub fn generate(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        self.run(prompt, max_tokens, config, None, |_| {
+            ControlFlow::Continue(())
         })
     }
 
-    pub fn generate(&mut self, prompt: &str, max_tokens: usize) -> Result<String> {
+    /// Like [`QuantizedLlm::generate`] with the configured sampling settings,
+    /// handing each new piece of text to `on_token` as soon as it is decoded
+    ///
+    /// Returning `ControlFlow::Break` from `on_token` stops generation; the text
+    /// produced so far is returned. Text that could be the start of a stop
+    /// sequence is held back until it is clear it is not one.
+    ///
+    /// ```ignore
+    /// let text = llm.generate_stream(&prompt, 100, |piece| {
+    ///     print!("{}", piece);
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// ```
+    pub fn generate_stream(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        on_token: impl FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<String> {
+        self.run(prompt, max_tokens, &self.generation, None, on_token)
+    }
+
+    fn run(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        config: &GenerationConfig,
+        grammar: Option<&CommandGrammar>,
+        mut on_token: impl FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<String> {
+        config
+            .validate()
+            .map_err(|e| E::msg(format!("Invalid generation config: {}", e)))?;
+        let mut logits_processor = LogitsProcessor::from_sampling(config.seed, sampling(config));
+        let mut model = self
+            .model
+            .lock()
+            .map_err(|_| E::msg("Model lock poisoned by an earlier failure"))?;
+
         // Fix tokenizer encoding - handle boxed error
         let encoding = self
             .tokenizer
             .encode(prompt, true)
             .map_err(|e| E::msg(format!("Tokenizer encoding failed: {}", e)))?;
-        let tokens = encoding.get_ids().to_vec();
+        let mut token_ids = self
+            .special_tokens
+            .prepare_prompt(encoding.get_ids().to_vec());
         let mut generated_tokens = Vec::new();
-        let mut token_ids = tokens;
+        let mut text = String::new();
+        // Bytes of `text` already handed to `on_token`
+        let mut emitted = 0;
 
-        for _ in 0..max_tokens {
-            let context_size = token_ids.len();
-            let context = &token_ids[..];
-            let input = Tensor::new(context, &self.device)?.unsqueeze(0)?;
+        for step in 0..max_tokens {
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(E::msg("Generation deadline exceeded"));
+            }
+            if config.is_cancelled() {
+                return Err(E::msg("Generation cancelled"));
+            }
 
-            // Quantized models manage their own internal state, no external cache needed
-            let logits = self.model.forward(&input, context_size - 1)?;
-            let logits = logits.squeeze(0)?;
-            let next_token = self.logits_processor.sample(&logits)?;
+            // The model keeps a KV cache: the first pass feeds the whole prompt at
+            // position 0 (which also clears the previous request), later passes
+            // only the newest token at its position
+            let (input, index_pos) = if step == 0 {
+                (&token_ids[..], 0)
+            } else {
+                (&token_ids[token_ids.len() - 1..], token_ids.len() - 1)
+            };
+            let input = Tensor::new(input, &self.device)?.unsqueeze(0)?;
+            let logits = model.forward(&input, index_pos)?;
+            let mut logits = logits.squeeze(0)?;
+            if config.repeat_penalty != 1.0 {
+                let start = token_ids.len().saturating_sub(config.repeat_last_n);
+                logits = candle_transformers::utils::apply_repeat_penalty(
+                    &logits,
+                    config.repeat_penalty,
+                    &token_ids[start..],
+                )?;
+            }
+            let next_token = match grammar {
+                Some(grammar) => {
+                    match self.sample_allowed(
+                        &mut logits_processor,
+                        &logits,
+                        &text,
+                        grammar,
+                        config,
+                    )? {
+                        Some(token) => token,
+                        // Nothing can follow within the grammar
+                        None => break,
+                    }
+                }
+                None => logits_processor.sample(&logits)?,
+            };
 
+            if self.special_tokens.is_eos(next_token) {
+                break;
+            }
             token_ids.push(next_token);
             generated_tokens.push(next_token);
+            // The model is looping: keep the text up to the first repetition
+            let looping = config.loop_prefix_len(&generated_tokens);
+            if let Some(keep) = looping {
+                generated_tokens.truncate(keep);
+            }
+
+            // Fix tokenizer decoding - handle boxed error
+            text = self
+                .tokenizer
+                .decode(&generated_tokens, true)
+                .map_err(|e| E::msg(format!("Tokenizer decoding failed: {}", e)))?;
+            if looping.is_some() {
+                break;
+            }
+            if let Some(end) = config.truncate_at_stop(&text).map(str::len) {
+                text.truncate(end);
+                break;
+            }
 
-            // Check for EOS token (empty string or actual EOS)
-            if let Some(eos_token) = self.tokenizer.token_to_id("</s>") {
-                if next_token == eos_token {
-                    break;
+            // A trailing replacement character is a multi-byte character still
+            // being assembled from several tokens
+            if text.ends_with('\u{fffd}') {
+                continue;
+            }
+            let ready = text.len() - pending_stop_len(&text, &config.stop);
+            if let Some(piece) = text.get(emitted..ready).filter(|p| !p.is_empty()) {
+                emitted = ready;
+                if on_token(piece).is_break() {
+                    return Ok(text);
                 }
             }
         }
 
-        // Fix tokenizer decoding - handle boxed error
-        let output = self
-            .tokenizer
-            .decode(&generated_tokens, true)
-            .map_err(|e| E::msg(format!("Tokenizer decoding failed: {}", e)))?;
-        Ok(output)
+        if let Some(rest) = text.get(emitted..).filter(|r| !r.is_empty()) {
+            let _ = on_token(rest);
+        }
+        Ok(text)
+    }
+
+    /// Sample a token that keeps `text` within `grammar`; `None` if no token does
+    ///
+    /// The model's choice is usually allowed, so it is tried first; only when it
+    /// is refused are all tokens checked and the refused ones masked out.
+    fn sample_allowed(
+        &self,
+        logits_processor: &mut LogitsProcessor,
+        logits: &Tensor,
+        text: &str,
+        grammar: &CommandGrammar,
+        config: &GenerationConfig,
+    ) -> Result<Option<u32>> {
+        let allowed = |token: u32| {
+            // Ending is allowed once the text is a whole command
+            if self.special_tokens.is_eos(token) {
+                return grammar.accepts(text);
+            }
+            let Some(piece) = self.pieces.get(token as usize) else {
+                return false;
+            };
+            let candidate = format!("{}{}", text, piece);
+            match config.truncate_at_stop(&candidate) {
+                Some(command) => grammar.accepts(command),
+                None => grammar.allows_prefix(&candidate),
+            }
+        };
+
+        let token = logits_processor.sample(logits)?;
+        if allowed(token) {
+            return Ok(Some(token));
+        }
+        let mut values: Vec<f32> = logits.to_dtype(DType::F32)?.to_vec1()?;
+        let mut any = false;
+        for (token, value) in values.iter_mut().enumerate() {
+            if allowed(token as u32) {
+                any = true;
+            } else {
+                *value = f32::NEG_INFINITY;
+            }
+        }
+        if !any {
+            return Ok(None);
+        }
+        let masked = Tensor::new(values, logits.device())?;
+        Ok(Some(logits_processor.sample(&masked)?))
+    }
+}
+
+impl InferenceBackend for QuantizedLlm {
+    fn generate(&self, request: &str, context: Option<&str>) -> Result<String> {
+        self.generate_command_with_context(request, self.max_tokens, context)
+    }
+
+    fn generate_with_config(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        let prompt = self.prompt.build_with_context(request, context);
+        Ok(self
+            .run(
+                &prompt,
+                self.max_tokens,
+                config,
+                self.grammar.as_ref(),
+                |_| ControlFlow::Continue(()),
+            )?
+            .trim()
+            .to_string())
+    }
+
+    fn explain(&self, command: &str) -> Result<String> {
+        self.explain_command(command)
+    }
+
+    fn sampling(&self) -> Option<GenerationConfig> {
+        Some(self.generation.clone())
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            backend: BackendKind::Gguf,
+            model_path: self.model_path.clone(),
+            template: self.prompt.template(),
+            vocab_size: self.tokenizer.get_vocab_size(true),
+            preset: self.preset.clone(),
+            device: device::device_name(&self.device),
+        }
+    }
+}
+
+/// The text each token adds to the output, by id
+///
+/// Decoding a token on its own drops the space many tokens start with (`▁ls`
+/// decodes as `ls`), so each is decoded after a fixed first token and that
+/// token's text removed. Special tokens add nothing.
+fn token_pieces(tokenizer: &Tokenizer) -> Vec<String> {
+    let anchor = tokenizer
+        .encode("a", false)
+        .ok()
+        .and_then(|encoding| encoding.get_ids().first().copied());
+    let anchored =
+        anchor.and_then(|anchor| Some((anchor, tokenizer.decode(&[anchor], true).ok()?)));
+    (0..tokenizer.get_vocab_size(true) as u32)
+        .map(|id| {
+            anchored
+                .as_ref()
+                .and_then(|(anchor, prefix)| {
+                    let text = tokenizer.decode(&[*anchor, id], true).ok()?;
+                    text.strip_prefix(prefix.as_str()).map(str::to_string)
+                })
+                .or_else(|| tokenizer.decode(&[id], true).ok())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Length of the longest suffix of `text` that is the start of a stop sequence
+fn pending_stop_len(text: &str, stops: &[String]) -> usize {
+    stops
+        .iter()
+        .flat_map(|stop| {
+            (1..stop.len())
+                .filter(|&n| stop.is_char_boundary(n) && text.ends_with(&stop[..n]))
+                .max()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Candle sampling strategy for `config`
+fn sampling(config: &GenerationConfig) -> Sampling {
+    if config.is_greedy() {
+        return Sampling::ArgMax;
+    }
+    let temperature = config.temperature;
+    match (config.top_k, config.top_p) {
+        (None, None) => Sampling::All { temperature },
+        (Some(k), None) => Sampling::TopK { k, temperature },
+        (None, Some(p)) => Sampling::TopP { p, temperature },
+        (Some(k), Some(p)) => Sampling::TopKThenTopP { k, p, temperature },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_pieces_keep_leading_spaces() {
+        let tokenizer: Tokenizer = r#"{
+            "version": "1.0",
+            "added_tokens": [{"id": 4, "content": "</s>", "single_word": false, "lstrip": false,
+                              "rstrip": false, "normalized": false, "special": true}],
+            "normalizer": null,
+            "pre_tokenizer": {"type": "Metaspace", "replacement": "▁", "prepend_scheme": "always", "split": true},
+            "post_processor": null,
+            "decoder": {"type": "Metaspace", "replacement": "▁", "prepend_scheme": "always", "split": true},
+            "model": {
+                "type": "WordLevel",
+                "vocab": {"▁a": 0, "▁ls": 1, "-la": 2, "▁|": 3, "</s>": 4},
+                "unk_token": "▁a"
+            }
+        }"#
+        .parse()
+        .unwrap();
+        assert_eq!(token_pieces(&tokenizer), vec![" a", " ls", "-la", " |", ""]);
+    }
+
+    #[test]
+    fn test_pending_stop_len() {
+        let stops = vec!["[INST]".to_string(), "\n\n".to_string()];
+        assert_eq!(pending_stop_len("ls -la [IN", &stops), 3);
+        assert_eq!(pending_stop_len("ls -la\n", &stops), 1);
+        assert_eq!(pending_stop_len("ls -la", &stops), 0);
+        assert_eq!(pending_stop_len("ls -la", &[]), 0);
     }
 }
@@ -0,0 +1,115 @@
+// Multi-turn refinement of a generated command
+//
+// A first request produces a command; follow-up instructions ("only files modified
+// today", "sort by size") are sent with the earlier requests and commands as
+// context, so the model edits the last command instead of starting over. The CLI
+// drives a session from `eidos core --refine`; programs can drive one directly.
+
+use crate::backend::InferenceBackend;
+use crate::prompt::RefinementContext;
+use anyhow::Result;
+
+/// A refinement dialogue over one backend
+///
+/// Commands are not validated here; run each one through a
+/// [`crate::SafetyPolicy`] and call [`RefinementSession::reject_last`] for those
+/// that fail, so they are not refined further.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::{MockBackend, RefinementSession};
+///
+/// let backend = MockBackend::new()
+///     .with_command("list files", "ls")
+///     .with_command("sort by size", "ls -S");
+/// let mut session = RefinementSession::new(&backend);
+/// assert_eq!(session.generate("list files").unwrap(), "ls");
+/// assert_eq!(session.generate("sort by size").unwrap(), "ls -S");
+/// assert_eq!(session.command(), Some("ls -S"));
+/// assert_eq!(session.turns().len(), 2);
+/// ```
+pub struct RefinementSession<'a> {
+    backend: &'a dyn InferenceBackend,
+    context: Option<String>,
+    refinement: RefinementContext,
+}
+
+impl<'a> RefinementSession<'a> {
+    pub fn new(backend: &'a dyn InferenceBackend) -> Self {
+        Self {
+            backend,
+            context: None,
+            refinement: RefinementContext::new(),
+        }
+    }
+
+    /// Context sent with every request, e.g. a snapshot of the environment
+    pub fn with_context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Generate a command for `request`, refining the previous one if any
+    pub fn generate(&mut self, request: &str) -> Result<String> {
+        let context = self.refinement.with_context(self.context.as_deref());
+        let command = self.backend.generate(request, context.as_deref())?;
+        self.refinement.push(request.trim(), command.as_str());
+        Ok(command)
+    }
+
+    /// Drop the last command, e.g. because it failed safety validation; the
+    /// next request refines the one before it
+    pub fn reject_last(&mut self) {
+        self.refinement.pop();
+    }
+
+    /// The current command, `None` before the first request
+    pub fn command(&self) -> Option<&str> {
+        self.refinement.last_command()
+    }
+
+    /// `(request, command)` pairs so far, oldest first
+    pub fn turns(&self) -> &[(String, String)] {
+        self.refinement.turns()
+    }
+
+    /// Start over; the next request is not a refinement
+    pub fn reset(&mut self) {
+        self.refinement.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+
+    #[test]
+    fn test_follow_ups_carry_earlier_turns() {
+        let backend = MockBackend::new()
+            .with_command("list files", "ls")
+            .with_command("only hidden", "ls -a")
+            .with_command("sort by size", "ls -S");
+        let mut session = RefinementSession::new(&backend)
+            .with_context(Some("Environment:\nSHELL=/bin/zsh".into()));
+
+        session.generate("list files").unwrap();
+        session.generate("only hidden").unwrap();
+        session.reject_last();
+        assert_eq!(session.command(), Some("ls"));
+        session.generate("sort by size").unwrap();
+
+        let requests = backend.requests();
+        assert_eq!(
+            requests[0].context.as_deref(),
+            Some("Environment:\nSHELL=/bin/zsh")
+        );
+        let last = requests[2].context.as_deref().unwrap();
+        assert!(last.contains("Request: list files\nCommand: ls"));
+        assert!(!last.contains("only hidden"));
+
+        session.reset();
+        assert_eq!(session.command(), None);
+    }
+}
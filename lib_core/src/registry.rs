@@ -0,0 +1,385 @@
+// Loaded models kept between requests
+//
+// Loading a model reads hundreds of megabytes and takes seconds, so long-running
+// callers (the daemon, the HTTP server, an interactive session) keep their loaded
+// models in a `ModelRegistry`. Entries are keyed by whatever decides how a model was
+// loaded: its path by default, the full model settings in the CLI. Several models
+// can be cached at once, so requests alternating between two models load each once.
+//
+// A cached model is only handed out while its files are unchanged: every access
+// compares their size and modification time with those at load time, so a model
+// replaced by a new fine-tune is loaded again without a restart. Once the cached
+// models' files add up to more than the memory budget, the least recently used are
+// dropped. File size is a rough measure of memory use, but it is known before
+// loading and needs nothing from the backends.
+
+use crate::backend::InferenceBackend;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Instant, SystemTime};
+
+/// Total size of cached model files before the least recently used are dropped
+pub const DEFAULT_MEMORY_BUDGET: u64 = 8 * 1024 * 1024 * 1024;
+
+/// Size and modification time of a model's files; `None` for a file that cannot
+/// be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStamp(Vec<Option<(u64, SystemTime)>>);
+
+impl FileStamp {
+    /// The files as they are now on disk
+    ///
+    /// Two `stat` calls per access are cheap next to inference, and unlike a
+    /// watcher they need no background thread.
+    pub fn of(files: &[PathBuf]) -> Self {
+        FileStamp(
+            files
+                .iter()
+                .map(|path| {
+                    let metadata = fs::metadata(path).ok()?;
+                    Some((metadata.len(), metadata.modified().ok()?))
+                })
+                .collect(),
+        )
+    }
+
+    /// Total size of the files that could be read
+    pub fn bytes(&self) -> u64 {
+        self.0.iter().flatten().map(|(len, _)| len).sum()
+    }
+}
+
+/// How [`ModelRegistry::get_or_load`] came by the model it returned
+#[derive(Debug)]
+pub enum Loaded<E> {
+    /// Cached, with its files unchanged
+    Cached,
+    /// Loaded for the first time
+    Loaded,
+    /// Loaded again because its files changed
+    Reloaded,
+    /// Its files changed but loading them failed (a file still being copied into
+    /// place, say); the model loaded before is returned and the load is retried
+    /// on the next access
+    Stale(E),
+}
+
+/// A cached model, as listed by [`ModelRegistry::models`]
+#[derive(Debug, Clone)]
+pub struct CachedModel<K> {
+    pub key: K,
+    /// Size of its files when it was loaded
+    pub bytes: u64,
+    pub loaded_at: Instant,
+    pub last_used: Instant,
+    /// Whether its files changed since it was loaded
+    pub changed: bool,
+}
+
+struct Entry<K> {
+    key: K,
+    backend: Arc<dyn InferenceBackend>,
+    files: Vec<PathBuf>,
+    stamp: FileStamp,
+    loaded_at: Instant,
+    last_used: Instant,
+}
+
+impl<K> Entry<K> {
+    fn changed(&self) -> bool {
+        FileStamp::of(&self.files) != self.stamp
+    }
+}
+
+/// Loaded models by key, dropped least recently used first
+///
+/// Loads run while the registry is locked, so two callers never load the same
+/// model twice; a caller wanting another model waits for the load in progress.
+/// Dropping an entry does not unload a model still in use: callers hold an `Arc`.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::registry::{Loaded, ModelRegistry};
+/// use lib_core::{InferenceBackend, MockBackend};
+/// use std::path::PathBuf;
+/// use std::sync::Arc;
+///
+/// let registry = ModelRegistry::new();
+/// let path = PathBuf::from("codellama-7b.Q4_K_M.gguf");
+/// let load = || -> Result<Arc<dyn InferenceBackend>, String> {
+///     Ok(Arc::new(MockBackend::new().with_fallback("ls")))
+/// };
+///
+/// let (_, how) = registry.get_or_load(&path, &[path.clone()], load).unwrap();
+/// assert!(matches!(how, Loaded::Loaded));
+/// let (_, how) = registry.get_or_load(&path, &[path.clone()], load).unwrap();
+/// assert!(matches!(how, Loaded::Cached));
+/// assert_eq!(registry.models().len(), 1);
+/// ```
+pub struct ModelRegistry<K = PathBuf> {
+    /// Most recently used last
+    entries: Mutex<Vec<Entry<K>>>,
+    memory_budget: AtomicU64,
+}
+
+impl<K: PartialEq + Clone> Default for ModelRegistry<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PartialEq + Clone> ModelRegistry<K> {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+            memory_budget: AtomicU64::new(DEFAULT_MEMORY_BUDGET),
+        }
+    }
+
+    /// Drop the least recently used models once the cached files exceed `bytes`
+    pub fn with_memory_budget(self, bytes: u64) -> Self {
+        self.set_memory_budget(bytes);
+        self
+    }
+
+    /// Change the memory budget, dropping models beyond it right away
+    ///
+    /// The model used last is always kept, even when it alone exceeds the budget;
+    /// a budget of 0 keeps just that one.
+    pub fn set_memory_budget(&self, bytes: u64) {
+        self.memory_budget.store(bytes, Ordering::Relaxed);
+        self.evict(&mut self.lock());
+    }
+
+    /// The model cached for `key`, if its files are unchanged
+    pub fn get(&self, key: &K) -> Option<Arc<dyn InferenceBackend>> {
+        let mut entries = self.lock();
+        let i = entries.iter().position(|e| e.key == *key)?;
+        if entries[i].changed() {
+            return None;
+        }
+        Some(Self::touch(&mut entries, i))
+    }
+
+    /// The model cached for `key`, loading it with `load` when it is not cached or
+    /// `files` changed since it was
+    ///
+    /// `files` are the paths `load` reads (model and tokenizer). When reloading
+    /// changed files fails, the model loaded before is returned as
+    /// [`Loaded::Stale`]; only a first load's error is returned as `Err`.
+    pub fn get_or_load<E>(
+        &self,
+        key: &K,
+        files: &[PathBuf],
+        load: impl FnOnce() -> Result<Arc<dyn InferenceBackend>, E>,
+    ) -> Result<(Arc<dyn InferenceBackend>, Loaded<E>), E> {
+        let mut entries = self.lock();
+        let cached = entries.iter().position(|e| e.key == *key);
+        if let Some(i) = cached {
+            if !entries[i].changed() {
+                return Ok((Self::touch(&mut entries, i), Loaded::Cached));
+            }
+        }
+        match self.load(&mut entries, key, files, load) {
+            Ok(backend) if cached.is_some() => Ok((backend, Loaded::Reloaded)),
+            Ok(backend) => Ok((backend, Loaded::Loaded)),
+            Err(e) => match cached {
+                Some(i) => Ok((Self::touch(&mut entries, i), Loaded::Stale(e))),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Load the model for `key` again even though its files are unchanged, e.g.
+    /// after a change the file stamps cannot see
+    ///
+    /// On failure the model loaded before stays cached.
+    pub fn reload<E>(
+        &self,
+        key: &K,
+        files: &[PathBuf],
+        load: impl FnOnce() -> Result<Arc<dyn InferenceBackend>, E>,
+    ) -> Result<Arc<dyn InferenceBackend>, E> {
+        self.load(&mut self.lock(), key, files, load)
+    }
+
+    /// Drop the model cached for `key`; returns whether there was one
+    pub fn remove(&self, key: &K) -> bool {
+        let mut entries = self.lock();
+        let before = entries.len();
+        entries.retain(|e| e.key != *key);
+        entries.len() < before
+    }
+
+    /// Drop every cached model
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// The cached models, most recently used first
+    pub fn models(&self) -> Vec<CachedModel<K>> {
+        self.lock()
+            .iter()
+            .rev()
+            .map(|e| CachedModel {
+                key: e.key.clone(),
+                bytes: e.stamp.bytes(),
+                loaded_at: e.loaded_at,
+                last_used: e.last_used,
+                changed: e.changed(),
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Entry<K>>> {
+        // A panicking load leaves the entries as they were
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Mark entry `i` as used last and return its model
+    fn touch(entries: &mut Vec<Entry<K>>, i: usize) -> Arc<dyn InferenceBackend> {
+        let mut entry = entries.remove(i);
+        entry.last_used = Instant::now();
+        let backend = Arc::clone(&entry.backend);
+        entries.push(entry);
+        backend
+    }
+
+    fn load<E>(
+        &self,
+        entries: &mut Vec<Entry<K>>,
+        key: &K,
+        files: &[PathBuf],
+        load: impl FnOnce() -> Result<Arc<dyn InferenceBackend>, E>,
+    ) -> Result<Arc<dyn InferenceBackend>, E> {
+        // Stamped before loading: a file replaced while it loads is loaded again
+        // on the next access
+        let stamp = FileStamp::of(files);
+        let backend = load()?;
+        entries.retain(|e| e.key != *key);
+        let now = Instant::now();
+        entries.push(Entry {
+            key: key.clone(),
+            backend: Arc::clone(&backend),
+            files: files.to_vec(),
+            stamp,
+            loaded_at: now,
+            last_used: now,
+        });
+        self.evict(entries);
+        Ok(backend)
+    }
+
+    /// Drop the least recently used models until the rest fit the budget
+    fn evict(&self, entries: &mut Vec<Entry<K>>) {
+        let budget = self.memory_budget.load(Ordering::Relaxed);
+        let mut total: u64 = entries.iter().map(|e| e.stamp.bytes()).sum();
+        while total > budget && entries.len() > 1 {
+            total -= entries.remove(0).stamp.bytes();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+    use std::slice;
+
+    fn mock(command: &str) -> Result<Arc<dyn InferenceBackend>, String> {
+        Ok(Arc::new(MockBackend::new().with_fallback(command)))
+    }
+
+    fn model_file(dir: &std::path::Path, name: &str, bytes: usize) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, vec![0u8; bytes]).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_models_are_cached_by_key_until_files_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = model_file(dir.path(), "a.gguf", 10);
+        let b = model_file(dir.path(), "b.gguf", 20);
+        let registry = ModelRegistry::new();
+
+        for (path, command) in [(&a, "ls"), (&b, "pwd")] {
+            let (backend, how) = registry
+                .get_or_load(path, slice::from_ref(path), || mock(command))
+                .unwrap();
+            assert!(matches!(how, Loaded::Loaded));
+            assert_eq!(backend.generate("x", None).unwrap(), command);
+        }
+        let (backend, how) = registry
+            .get_or_load(&a, slice::from_ref(&a), || mock("unused"))
+            .unwrap();
+        assert!(matches!(how, Loaded::Cached));
+        assert_eq!(backend.generate("x", None).unwrap(), "ls");
+        let keys: Vec<PathBuf> = registry.models().into_iter().map(|m| m.key).collect();
+        assert_eq!(keys, vec![a.clone(), b.clone()]);
+
+        // A different size is a different file
+        fs::write(&a, "new fine-tune").unwrap();
+        assert!(registry.get(&a).is_none());
+        assert!(registry.models()[0].changed);
+        let (_, how) = registry
+            .get_or_load(&a, slice::from_ref(&a), || Err("still copying"))
+            .unwrap();
+        assert!(matches!(how, Loaded::Stale("still copying")));
+        let (backend, how) = registry
+            .get_or_load(&a, slice::from_ref(&a), || mock("df -h"))
+            .unwrap();
+        assert!(matches!(how, Loaded::Reloaded));
+        assert_eq!(backend.generate("x", None).unwrap(), "df -h");
+
+        assert_eq!(
+            registry
+                .reload(&b, slice::from_ref(&b), || mock("whoami"))
+                .unwrap()
+                .generate("x", None)
+                .unwrap(),
+            "whoami"
+        );
+        assert!(registry
+            .reload(&b, slice::from_ref(&b), || Err(()))
+            .is_err());
+        assert_eq!(
+            registry.get(&b).unwrap().generate("x", None).unwrap(),
+            "whoami"
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used_models_beyond_budget_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["a", "b", "c"]
+            .iter()
+            .map(|name| model_file(dir.path(), name, 100))
+            .collect();
+        let registry = ModelRegistry::new().with_memory_budget(250);
+
+        for path in &files[..2] {
+            registry
+                .get_or_load(path, slice::from_ref(path), || mock("ls"))
+                .unwrap();
+        }
+        // Using `a` makes `b` the least recently used
+        assert!(registry.get(&files[0]).is_some());
+        registry
+            .get_or_load(&files[2], slice::from_ref(&files[2]), || mock("ls"))
+            .unwrap();
+        let keys: Vec<PathBuf> = registry.models().into_iter().map(|m| m.key).collect();
+        assert_eq!(keys, vec![files[2].clone(), files[0].clone()]);
+
+        // The model used last stays even over budget
+        registry.set_memory_budget(0);
+        assert_eq!(registry.models().len(), 1);
+        assert!(registry.get(&files[2]).is_some());
+        assert!(registry.remove(&files[2]));
+        assert!(!registry.remove(&files[2]));
+        assert!(registry.models().is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+// Risk classification for generated commands
+//
+// Validation answers "may this command be shown?"; the risk level answers "what
+// could running it do?". Only commands that provably just read state are
+// `ReadOnly`: a base command allowed at the strict safety level, with no shell
+// metacharacters or paths into /dev, /proc and /sys, not one that keeps running
+// until interrupted (`tail -f`, `free -s 1`). The built-in flag schemas already
+// keep out operands that change the system (`hostname NAME`, `date MMDDhhmm`).
+// Those are the only commands eidos is willing to run on the user's behalf.
+//
+// Long options are matched by prefix too, as getopt accepts any unambiguous
+// abbreviation (`tail --fol` is `tail --follow`).
+
+use crate::validation::{SafetyLevel, SafetyPolicy, SafetyRule};
+use std::fmt;
+
+/// Programs that read state but can be told to keep running, with the options
+/// that do so
+const FOLLOW_OPTIONS: [(&str, &[&str]); 2] = [
+    ("tail", &["-f", "-F", "--follow"]),
+    ("free", &["-s", "--seconds", "-c", "--count"]),
+];
+
+/// What running a command could do, from least to most harmful
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Risk {
+    /// Only reads state and terminates on its own; safe to run unattended
+    ReadOnly,
+    /// May change files or processes, or cannot be shown to be read-only
+    Modifying,
+    /// Destructive, privileged or network command
+    Destructive,
+}
+
+impl Risk {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Risk::ReadOnly => "read-only",
+            Risk::Modifying => "modifying",
+            Risk::Destructive => "destructive",
+        }
+    }
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Classify what running `command` could do
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::risk::{classify_command, Risk};
+///
+/// assert_eq!(classify_command("df -h"), Risk::ReadOnly);
+/// assert_eq!(classify_command("tail -f app.log"), Risk::Modifying);
+/// assert_eq!(classify_command("mv a b"), Risk::Modifying);
+/// assert_eq!(classify_command("rm -rf build"), Risk::Destructive);
+/// ```
+pub fn classify_command(command: &str) -> Risk {
+    let report = SafetyPolicy::new(SafetyLevel::Strict).validate(command);
+    if report
+        .violations
+        .iter()
+        .any(|v| v.rule == SafetyRule::DangerousCommand)
+    {
+        return Risk::Destructive;
+    }
+    if report.is_safe() && !runs_until_interrupted(command) {
+        Risk::ReadOnly
+    } else {
+        Risk::Modifying
+    }
+}
+
+/// Whether `command` is read-only, see [`classify_command`]
+pub fn is_read_only(command: &str) -> bool {
+    classify_command(command) == Risk::ReadOnly
+}
+
+fn runs_until_interrupted(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let Some((_, options)) = FOLLOW_OPTIONS.iter().find(|(p, _)| *p == program) else {
+        return false;
+    };
+
+    words.any(|word| {
+        let name = word.split('=').next().unwrap_or(word);
+        matches_option(name, options)
+            // Clustered short options: `tail -fn 20`
+            || (word.starts_with('-')
+                && !word.starts_with("--")
+                && options
+                    .iter()
+                    .filter_map(|o| o.strip_prefix('-').filter(|o| o.len() == 1))
+                    .any(|o| word[1..].contains(o)))
+    })
+}
+
+/// Whether `word` is one of `options` or, for long options, a getopt
+/// abbreviation of one
+fn matches_option(word: &str, options: &[&str]) -> bool {
+    options.iter().any(|option| {
+        *option == word || (word.len() > 2 && word.starts_with("--") && option.starts_with(word))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_commands() {
+        for cmd in ["df -h", "ls -la", "free -m", "tail -n 20 app.log", "whoami"] {
+            assert_eq!(classify_command(cmd), Risk::ReadOnly, "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn test_anything_beyond_reading_is_not_read_only() {
+        let modifying = [
+            // Not in the strict whitelist
+            "mv a b",
+            "git status",
+            "find . -delete",
+            "top",
+            // Chaining and redirection
+            "ls > files.txt",
+            "ls | grep x",
+            "cat /proc/meminfo",
+            // Never terminates
+            "tail -f app.log",
+            "tail -fn 20 app.log",
+            "tail --follow=name app.log",
+            "free -s 1",
+            // Abbreviated long options
+            "tail --f app.log",
+            "tail --foll=name app.log",
+            "free --sec 1",
+            // Operands that change the system
+            "hostname build-01",
+            "hostname -v build-01",
+            "date 010112002025",
+            "date -u 0101",
+            "date -d now 0101",
+            "date -- 0101",
+            "",
+        ];
+        for cmd in modifying {
+            assert_eq!(classify_command(cmd), Risk::Modifying, "{}", cmd);
+        }
+        assert_eq!(classify_command("sudo ls"), Risk::Destructive);
+    }
+
+    #[test]
+    fn test_option_arguments_are_not_operands() {
+        for cmd in [
+            "hostname",
+            "hostname -f",
+            "date",
+            "date +%F",
+            "date -u +%s",
+            "date -d yesterday",
+            "date -ud yesterday +%F",
+            "date --date yesterday",
+            "date --da yesterday",
+            "date -r notes.txt",
+            "date --date=tomorrow",
+            "tail --lines 5 app.log",
+        ] {
+            assert_eq!(classify_command(cmd), Risk::ReadOnly, "{}", cmd);
+        }
+        assert!(Risk::ReadOnly < Risk::Modifying);
+    }
+}
@@ -0,0 +1,499 @@
+// Shell words of plain pipelines
+//
+// The standard safety level rejects every quote, glob and pipe because it only
+// looks at characters. The review level instead parses the command the way a
+// POSIX shell would split it: quotes are removed from the words they group, `|`
+// separates the stages of a pipeline, and the validator then checks each stage on
+// its own words. A quoted `|` or `;` is just text in an argument.
+//
+// Only plain pipelines parse. Anything that makes the shell run more than the
+// words it was given is refused rather than interpreted: command lists (`;`,
+// `&&`, `||`, `&`), redirections, substitutions and expansions (`$`, backticks,
+// also inside double quotes), subshells, brace expansion, escapes, comments and
+// history expansion. Globs are allowed and reported, since they expand to file
+// names the validator cannot see.
+//
+// `command_names` answers a different question for any command, however far from
+// a plain pipeline: which programs would it run? It lexes the command into words
+// and operators like the shell does and takes the first word of every command,
+// substitution and subshell, plus the programs that wrappers such as `sudo`,
+// `xargs`, `find -exec` and `sh -c` are given. Validation checks those names
+// against the dangerous-command list, so `rm` in a file name is not mistaken for
+// the program.
+
+use thiserror::Error;
+
+/// Characters the shell treats specially outside quotes, which a plain pipeline
+/// does not use
+const UNSUPPORTED: [char; 15] = [
+    ';', '&', '<', '>', '(', ')', '$', '`', '\\', '!', '{', '}', '^', '\n', '\r',
+];
+
+/// Characters the shell treats specially inside double quotes
+const UNSUPPORTED_IN_DOUBLE_QUOTES: [char; 6] = ['$', '`', '\\', '!', '\n', '\r'];
+
+/// Glob characters, expanded to matching file names outside quotes
+const GLOB: [char; 4] = ['*', '?', '[', ']'];
+
+/// Operators outside quotes, longest first so `&&` is not read as two `&`
+const OPERATORS: [&str; 22] = [
+    "&>>", "<<<", "&&", "||", "|&", ";;", "&>", ">>", "<<", ">|", ">&", "<&", "<>", ";", "&", "|",
+    "(", ")", "<", ">", "\n", "\r",
+];
+
+/// Operators whose next word is a file or here-document delimiter, not a command
+const REDIRECTIONS: [&str; 11] = [
+    "&>>", "<<<", "&>", ">>", "<<", ">|", ">&", "<&", "<>", "<", ">",
+];
+
+/// Words that open, continue or close a compound command rather than run a program
+const KEYWORDS: [&str; 15] = [
+    "if", "then", "else", "elif", "fi", "while", "until", "do", "done", "esac", "{", "}", "!",
+    "time", "coproc",
+];
+
+/// Programs running the program named in their arguments
+const WRAPPERS: [&str; 15] = [
+    "sudo", "doas", "env", "exec", "nohup", "nice", "ionice", "timeout", "xargs", "command",
+    "builtin", "watch", "stdbuf", "strace", "busybox",
+];
+
+/// Programs running their arguments as shell code
+const SHELLS: [&str; 7] = ["sh", "bash", "zsh", "dash", "ksh", "fish", "eval"];
+
+/// `find` options running the command that follows
+const FIND_EXEC: [&str; 4] = ["-exec", "-execdir", "-ok", "-okdir"];
+
+/// Why a command is not a plain pipeline
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ShellSyntaxError {
+    #[error("unclosed {0} quote")]
+    UnclosedQuote(char),
+    #[error("'{0}' is not part of a plain pipeline")]
+    Unsupported(String),
+    #[error("empty pipeline stage")]
+    EmptyStage,
+}
+
+/// A word or operator of a command, as the shell would lex it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A word with its quotes and backslashes removed
+    Word(String),
+    /// A control or redirection operator, or `$(`, `<(`, `>(`, a backtick or the
+    /// `)` closing a substitution
+    Operator(String),
+}
+
+/// A command parsed into the words of each pipeline stage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    /// Words of each stage, quotes removed
+    pub stages: Vec<Vec<String>>,
+    /// Some word was (partly) quoted
+    pub quoted: bool,
+    /// Some word has an unquoted glob character
+    pub globbed: bool,
+}
+
+impl Pipeline {
+    /// Stages as plain text, words joined by single spaces
+    pub fn stage_texts(&self) -> Vec<String> {
+        self.stages.iter().map(|words| words.join(" ")).collect()
+    }
+}
+
+/// Parse `command` as a plain pipeline
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::shell::{parse_pipeline, ShellSyntaxError};
+///
+/// let pipeline = parse_pipeline("ps aux | grep 'nginx: master'").unwrap();
+/// assert_eq!(pipeline.stages, [vec!["ps", "aux"], vec!["grep", "nginx: master"]]);
+/// assert!(pipeline.quoted);
+///
+/// assert_eq!(
+///     parse_pipeline("ls; id"),
+///     Err(ShellSyntaxError::Unsupported(";".to_string()))
+/// );
+/// assert!(parse_pipeline("echo \"$(id)\"").is_err());
+/// ```
+pub fn parse_pipeline(command: &str) -> Result<Pipeline, ShellSyntaxError> {
+    let mut pipeline = Pipeline {
+        stages: vec![Vec::new()],
+        ..Pipeline::default()
+    };
+    // `Some` while a word is open, so `''` is an (empty) word
+    let mut word: Option<String> = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                pipeline.quoted = true;
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(u) if c == '"' && UNSUPPORTED_IN_DOUBLE_QUOTES.contains(&u) => {
+                            return Err(ShellSyntaxError::Unsupported(u.to_string()));
+                        }
+                        Some(u @ ('\n' | '\r')) => {
+                            return Err(ShellSyntaxError::Unsupported(u.to_string()))
+                        }
+                        Some(text) => word.push(text),
+                        None => return Err(ShellSyntaxError::UnclosedQuote(c)),
+                    }
+                }
+            }
+            '|' => {
+                if let Some(next @ ('|' | '&')) = chars.peek() {
+                    return Err(ShellSyntaxError::Unsupported(format!("|{}", next)));
+                }
+                end_word(&mut word, &mut pipeline);
+                if pipeline.stages.last().is_some_and(Vec::is_empty) {
+                    return Err(ShellSyntaxError::EmptyStage);
+                }
+                pipeline.stages.push(Vec::new());
+            }
+            '#' if word.is_none() => {
+                return Err(ShellSyntaxError::Unsupported(c.to_string()));
+            }
+            c if UNSUPPORTED.contains(&c) => {
+                return Err(ShellSyntaxError::Unsupported(c.to_string()));
+            }
+            c if c.is_whitespace() => end_word(&mut word, &mut pipeline),
+            c => {
+                pipeline.globbed |= GLOB.contains(&c);
+                word.get_or_insert_with(String::new).push(c);
+            }
+        }
+    }
+    end_word(&mut word, &mut pipeline);
+    if pipeline.stages.iter().any(Vec::is_empty) {
+        return Err(ShellSyntaxError::EmptyStage);
+    }
+    Ok(pipeline)
+}
+
+fn end_word(word: &mut Option<String>, pipeline: &mut Pipeline) {
+    if let Some(word) = word.take() {
+        pipeline
+            .stages
+            .last_mut()
+            .expect("stages is never empty")
+            .push(word);
+    }
+}
+
+/// Where the lexer is: which quote or substitution it is inside
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Plain,
+    DoubleQuotes,
+    Substitution,
+    Backticks,
+}
+
+/// Split `command` into words and operators
+///
+/// Unlike [`parse_pipeline`] this accepts any command: substitutions, also inside
+/// double quotes, are lexed as operators around the words they run, and an
+/// unclosed quote runs to the end of the command.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::shell::{tokenize, Token};
+///
+/// let word = |w: &str| Token::Word(w.to_string());
+/// let operator = |o: &str| Token::Operator(o.to_string());
+/// assert_eq!(
+///     tokenize("ls 'my file'&&echo \"$(id)\""),
+///     [
+///         word("ls"),
+///         word("my file"),
+///         operator("&&"),
+///         word("echo"),
+///         operator("$("),
+///         word("id"),
+///         operator(")"),
+///     ]
+/// );
+/// ```
+pub fn tokenize(command: &str) -> Vec<Token> {
+    let chars: Vec<char> = command.chars().collect();
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut modes = vec![Mode::Plain];
+    let mut i = 0;
+
+    let end_word = |word: &mut Option<String>, tokens: &mut Vec<Token>| {
+        if let Some(word) = word.take() {
+            tokens.push(Token::Word(word));
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let mode = *modes.last().expect("the plain mode is never left");
+        i += 1;
+
+        if mode == Mode::DoubleQuotes {
+            match (c, next) {
+                ('"', _) => {
+                    modes.pop();
+                }
+                ('\\', Some(escaped @ ('$' | '`' | '"' | '\\'))) => {
+                    word.get_or_insert_with(String::new).push(escaped);
+                    i += 1;
+                }
+                ('$', Some('(')) | ('`', _) => {
+                    // `"$(id)"` is the substitution alone, not an empty word too
+                    if word.as_deref() == Some("") {
+                        word = None;
+                    }
+                    end_word(&mut word, &mut tokens);
+                    let (operator, mode) = if c == '$' {
+                        i += 1;
+                        ("$(", Mode::Substitution)
+                    } else {
+                        ("`", Mode::Backticks)
+                    };
+                    tokens.push(Token::Operator(operator.to_string()));
+                    modes.push(mode);
+                }
+                (c, _) => word.get_or_insert_with(String::new).push(c),
+            }
+            continue;
+        }
+
+        match (c, next) {
+            ('\'', _) => {
+                let word = word.get_or_insert_with(String::new);
+                while i < chars.len() && chars[i] != '\'' {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+                i += 1;
+            }
+            ('"', _) => {
+                word.get_or_insert_with(String::new);
+                modes.push(Mode::DoubleQuotes);
+            }
+            ('\\', Some('\n')) => i += 1,
+            ('\\', Some(escaped)) => {
+                word.get_or_insert_with(String::new).push(escaped);
+                i += 1;
+            }
+            (')', _) if mode == Mode::Substitution => {
+                end_word(&mut word, &mut tokens);
+                tokens.push(Token::Operator(")".to_string()));
+                modes.pop();
+            }
+            ('`', _) => {
+                end_word(&mut word, &mut tokens);
+                tokens.push(Token::Operator("`".to_string()));
+                if mode == Mode::Backticks {
+                    modes.pop();
+                } else {
+                    modes.push(Mode::Backticks);
+                }
+            }
+            ('$' | '<' | '>', Some('(')) => {
+                end_word(&mut word, &mut tokens);
+                tokens.push(Token::Operator(format!("{}(", c)));
+                modes.push(Mode::Substitution);
+                i += 1;
+            }
+            ('#', _) if word.is_none() => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            (c, _) if c.is_whitespace() && c != '\n' && c != '\r' => {
+                end_word(&mut word, &mut tokens)
+            }
+            _ => {
+                let rest: String = chars[i - 1..].iter().take(3).collect();
+                match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    Some(op) => {
+                        end_word(&mut word, &mut tokens);
+                        tokens.push(Token::Operator(op.to_string()));
+                        i += op.chars().count() - 1;
+                    }
+                    None => word.get_or_insert_with(String::new).push(c),
+                }
+            }
+        }
+    }
+    end_word(&mut word, &mut tokens);
+    tokens
+}
+
+/// Programs `command` would run, lowercased and without their directory
+///
+/// Options of wrappers may take values (`sudo -u root rm`), so every word after a
+/// wrapper up to the next operator is taken as a program that may run.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::shell::command_names;
+///
+/// assert_eq!(command_names("ls -l my-rmdir-notes.txt"), ["ls"]);
+/// assert_eq!(
+///     command_names("cd /tmp && sudo -u root /bin/RM -rf x"),
+///     ["cd", "sudo", "root", "rm", "x"]
+/// );
+/// assert_eq!(command_names("echo \"$(bash -c 'kill 1')\""), ["echo", "bash", "kill"]);
+/// ```
+pub fn command_names(command: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    // Every word until the next operator names a program (after a wrapper) or is
+    // shell code (after a shell)
+    let (mut at_start, mut wrapped, mut shell) = (true, false, false);
+    let (mut redirected, mut find_exec) = (false, false);
+
+    for token in tokenize(command) {
+        let word = match token {
+            Token::Operator(op) => {
+                if REDIRECTIONS.contains(&op.as_str()) {
+                    redirected = true;
+                } else {
+                    (at_start, wrapped, shell, find_exec) = (true, false, false, false);
+                }
+                continue;
+            }
+            Token::Word(word) => word,
+        };
+        if std::mem::take(&mut redirected) {
+            continue;
+        }
+        if shell {
+            if !word.starts_with('-') {
+                names.extend(command_names(&word));
+            }
+            continue;
+        }
+        if at_start && (is_assignment(&word) || KEYWORDS.contains(&word.as_str())) {
+            continue;
+        }
+        if !(at_start || wrapped || find_exec) {
+            find_exec = FIND_EXEC.contains(&word.as_str());
+            continue;
+        }
+        if wrapped && word.starts_with('-') {
+            continue;
+        }
+        let name = program_name(&word);
+        if name.is_empty() {
+            continue;
+        }
+        at_start = false;
+        find_exec = false;
+        wrapped |= WRAPPERS.contains(&name.as_str());
+        shell = SHELLS.contains(&name.as_str());
+        names.push(name);
+    }
+    names
+}
+
+/// `NAME=value`, which sets a variable for the command that follows
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn program_name(word: &str) -> String {
+    word.rsplit('/').next().unwrap_or(word).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotes_group_words_and_hide_operators() {
+        let pipeline = parse_pipeline("grep -r \"a | b; c\" src|wc -l").unwrap();
+        assert_eq!(
+            pipeline.stages,
+            [vec!["grep", "-r", "a | b; c", "src"], vec!["wc", "-l"]]
+        );
+        assert!(pipeline.quoted && !pipeline.globbed);
+
+        let pipeline = parse_pipeline("ls *.log it' is' ''").unwrap();
+        assert_eq!(pipeline.stages, [vec!["ls", "*.log", "it is", ""]]);
+        assert!(pipeline.globbed);
+        assert_eq!(pipeline.stage_texts(), ["ls *.log it is "]);
+
+        assert!(parse_pipeline("echo issue#12").is_ok());
+    }
+
+    #[test]
+    fn test_anything_beyond_a_pipeline_is_refused() {
+        for command in [
+            "ls && id",
+            "ls || id",
+            "ls |& cat",
+            "ls &",
+            "ls > out",
+            "cat < in",
+            "echo $HOME",
+            "echo \"${HOME}\"",
+            "echo `id`",
+            "(ls)",
+            "echo {a,b}",
+            "echo a\\ b",
+            "ls # comment",
+            "echo 'a\nb'",
+            "ls\nid",
+        ] {
+            assert!(parse_pipeline(command).is_err(), "{:?}", command);
+        }
+        assert_eq!(
+            parse_pipeline("grep 'x"),
+            Err(ShellSyntaxError::UnclosedQuote('\''))
+        );
+        for command in ["", "| ls", "ls |", "ls | | wc"] {
+            assert_eq!(
+                parse_pipeline(command),
+                Err(ShellSyntaxError::EmptyStage),
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_command_names_follow_operators_and_wrappers() {
+        let cases: [(&str, &[&str]); 11] = [
+            ("ls -l my-rmdir-notes.txt", &["ls"]),
+            ("grep firmware /var/log/syslog", &["grep"]),
+            ("ls;rm x", &["ls", "rm"]),
+            ("ls|rm x", &["ls", "rm"]),
+            ("LANG=C sort < rm > dd", &["sort"]),
+            ("echo `whoami` $(id)", &["echo", "whoami", "id"]),
+            ("if true; then reboot; fi", &["true", "reboot"]),
+            ("find . -name '*.log' -exec rm {} ;", &["find", "rm"]),
+            ("xargs -0 rm -f", &["xargs", "rm"]),
+            ("r''m x; \\rm y", &["rm", "rm"]),
+            ("sh -c \"ls; shutdown now\"", &["sh", "ls", "shutdown"]),
+        ];
+        for (command, names) in cases {
+            assert_eq!(command_names(command), names, "{:?}", command);
+        }
+        // Comments and unclosed quotes do not hide or invent programs
+        assert_eq!(command_names("ls # rm -rf /"), ["ls"]);
+        assert_eq!(command_names("echo 'rm -rf /"), ["echo"]);
+        assert_eq!(command_names("cat <(curl x)"), ["cat", "curl"]);
+    }
+}
@@ -0,0 +1,196 @@
+// Beginning- and end-of-sequence tokens for token-by-token generation
+//
+// Model families end their answers with different tokens: `</s>` for Llama 2 and
+// Mistral, `<|im_end|>` for ChatML fine-tunes, `<|eot_id|>` for Llama 3. A missed
+// end token lets the model run on to the token budget, appending junk to the
+// command. The ids come from the GGUF metadata, from tokens named in the model's
+// preset (`[[presets]] eos`), and from well-known end tokens in the vocabulary.
+// Whether the prompt starts with a BOS token follows the metadata (or the preset)
+// instead of whatever the tokenizer's post-processor happens to do.
+
+#[cfg(feature = "gguf")]
+use candle_core::quantized::gguf_file;
+
+/// End-of-sequence tokens of common model families, used when present in the vocabulary
+pub const KNOWN_EOS_TOKENS: &[&str] = &[
+    "</s>",
+    "<|im_end|>",
+    "<|eot_id|>",
+    "<|end_of_text|>",
+    "<|endoftext|>",
+    "<end_of_turn>",
+    "<|end|>",
+];
+
+/// Beginning-of-sequence tokens of common model families
+pub const KNOWN_BOS_TOKENS: &[&str] = &["<s>", "<|begin_of_text|>", "<bos>"];
+
+/// Special token ids and settings stored in a model file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub bos: Option<u32>,
+    /// End-of-sequence, end-of-turn and end-of-message ids
+    pub eos: Vec<u32>,
+    /// Whether prompts must start with the BOS token
+    pub add_bos: Option<bool>,
+}
+
+/// Special token metadata of a GGUF model (`tokenizer.ggml.*`)
+#[cfg(feature = "gguf")]
+pub fn gguf_token_metadata(content: &gguf_file::Content) -> TokenMetadata {
+    let id = |key: &str| {
+        content
+            .metadata
+            .get(&format!("tokenizer.ggml.{}", key))
+            .and_then(|v| v.to_u32().ok())
+    };
+    TokenMetadata {
+        bos: id("bos_token_id"),
+        eos: ["eos_token_id", "eot_token_id", "eom_token_id"]
+            .into_iter()
+            .filter_map(id)
+            .collect(),
+        add_bos: content
+            .metadata
+            .get("tokenizer.ggml.add_bos_token")
+            .and_then(|v| v.to_bool().ok()),
+    }
+}
+
+/// BOS and EOS handling for one model and tokenizer
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::special_tokens::{SpecialTokens, TokenMetadata};
+///
+/// let vocab = |token: &str| match token {
+///     "<s>" => Some(1),
+///     "</s>" => Some(2),
+///     "<|im_end|>" => Some(7),
+///     _ => None,
+/// };
+/// let tokens = SpecialTokens::resolve(&TokenMetadata::default(), &[], None, vocab);
+/// assert!(tokens.is_eos(2) && tokens.is_eos(7));
+/// assert_eq!(tokens.prepare_prompt(vec![1, 1, 5]), vec![1, 5]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecialTokens {
+    pub bos: Option<u32>,
+    /// `Some(true)` makes prompts start with `bos`, `Some(false)` removes it;
+    /// `None` keeps what the tokenizer produced
+    pub add_bos: Option<bool>,
+    /// Generation stops at the first of these
+    pub eos: Vec<u32>,
+}
+
+impl SpecialTokens {
+    /// Combine the model's metadata with the EOS tokens named in its preset and
+    /// the well-known end tokens found by `token_to_id`
+    ///
+    /// Named tokens missing from the vocabulary are ignored. `add_bos` (from the
+    /// preset) takes precedence over the metadata.
+    pub fn resolve(
+        metadata: &TokenMetadata,
+        eos_names: &[String],
+        add_bos: Option<bool>,
+        token_to_id: impl Fn(&str) -> Option<u32>,
+    ) -> Self {
+        let mut eos = metadata.eos.clone();
+        let named = eos_names.iter().map(String::as_str);
+        for id in named
+            .chain(KNOWN_EOS_TOKENS.iter().copied())
+            .filter_map(&token_to_id)
+        {
+            if !eos.contains(&id) {
+                eos.push(id);
+            }
+        }
+
+        let bos = metadata
+            .bos
+            .or_else(|| KNOWN_BOS_TOKENS.iter().find_map(|t| token_to_id(t)));
+        Self {
+            bos,
+            add_bos: add_bos.or(metadata.add_bos),
+            eos,
+        }
+    }
+
+    pub fn is_eos(&self, token: u32) -> bool {
+        self.eos.contains(&token)
+    }
+
+    /// Prompt ids starting with a single BOS token when the model expects one,
+    /// and without one when it does not
+    pub fn prepare_prompt(&self, mut ids: Vec<u32>) -> Vec<u32> {
+        let Some(bos) = self.bos else {
+            return ids;
+        };
+        let leading = ids.iter().take_while(|&&id| id == bos).count();
+        let keep = match self.add_bos {
+            Some(true) => 1,
+            Some(false) => 0,
+            None => leading.min(1),
+        };
+        if leading != keep {
+            ids.splice(..leading, std::iter::repeat(bos).take(keep));
+        }
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab(token: &str) -> Option<u32> {
+        match token {
+            "<|begin_of_text|>" => Some(128000),
+            "<|end_of_text|>" => Some(128001),
+            "<|eot_id|>" => Some(128009),
+            "<|im_end|>" => Some(32001),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_merges_metadata_preset_and_known_tokens() {
+        let metadata = TokenMetadata {
+            bos: Some(128000),
+            eos: vec![128001],
+            add_bos: Some(true),
+        };
+        let names = vec!["<|im_end|>".to_string(), "<|missing|>".to_string()];
+        let tokens = SpecialTokens::resolve(&metadata, &names, None, vocab);
+
+        assert_eq!(tokens.eos, vec![128001, 32001, 128009]);
+        assert_eq!(tokens.bos, Some(128000));
+        assert_eq!(tokens.add_bos, Some(true));
+        assert!(!tokens.is_eos(128000));
+
+        // Without metadata the vocabulary still provides the ids
+        let tokens = SpecialTokens::resolve(&TokenMetadata::default(), &[], Some(false), vocab);
+        assert_eq!(tokens.bos, Some(128000));
+        assert_eq!(tokens.add_bos, Some(false));
+        assert!(tokens.is_eos(128009));
+    }
+
+    #[test]
+    fn test_prepare_prompt() {
+        let tokens = |add_bos| SpecialTokens {
+            bos: Some(1),
+            add_bos,
+            eos: vec![2],
+        };
+        assert_eq!(tokens(Some(true)).prepare_prompt(vec![5, 6]), vec![1, 5, 6]);
+        assert_eq!(tokens(Some(true)).prepare_prompt(vec![1, 1, 5]), vec![1, 5]);
+        assert_eq!(tokens(Some(false)).prepare_prompt(vec![1, 5]), vec![5]);
+        assert_eq!(tokens(None).prepare_prompt(vec![5]), vec![5]);
+        assert_eq!(tokens(None).prepare_prompt(vec![1, 1, 5]), vec![1, 5]);
+        assert_eq!(
+            SpecialTokens::default().prepare_prompt(vec![1, 5]),
+            vec![1, 5]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+// Shells commands are generated for
+//
+// The whitelist, the dangerous-program list and the metacharacter rules of the
+// validator were written for POSIX shells on Linux. A `TargetShell` swaps in the
+// tables for another shell: PowerShell and cmd.exe have their own read-only
+// commands (`Get-ChildItem`, `dir`), their own destructive ones (`Remove-Item`,
+// `del`, `format`), variables written differently (`$env:PATH`, `%PATH%`) and
+// backslashes as path separators rather than escapes. It also tells the model
+// which shell to write for.
+//
+// The POSIX dangerous programs stay blocked for every target, since PowerShell
+// aliases `rm`, `curl` and friends and WSL runs the real ones. Windows names are
+// compared case-insensitively, as both Windows shells do.
+
+use crate::validation::{BASE_COMMANDS, PATH_TRAVERSAL_PATTERNS, SHELL_INJECTION_PATTERNS};
+use std::fmt;
+use std::str::FromStr;
+
+/// Read-only cmdlets and aliases allowed at every level on PowerShell
+const POWERSHELL_COMMANDS: [&str; 30] = [
+    "get-childitem",
+    "gci",
+    "dir",
+    "ls",
+    "get-location",
+    "gl",
+    "pwd",
+    "get-content",
+    "gc",
+    "cat",
+    "type",
+    "select-string",
+    "sls",
+    "get-item",
+    "gi",
+    "get-itemproperty",
+    "gp",
+    "get-process",
+    "gps",
+    "ps",
+    "get-date",
+    "get-command",
+    "gcm",
+    "get-psdrive",
+    "gdr",
+    "get-computerinfo",
+    "measure-object",
+    "write-output",
+    "whoami",
+    "hostname",
+];
+
+/// Read-only commands allowed at every level on cmd.exe
+const CMD_COMMANDS: [&str; 13] = [
+    "dir",
+    "type",
+    "echo",
+    "cd",
+    "where",
+    "whoami",
+    "hostname",
+    "ver",
+    "findstr",
+    "find",
+    "tree",
+    "tasklist",
+    "systeminfo",
+];
+
+/// PowerShell cmdlets and aliases that delete, overwrite, stop, download or run
+/// other code, blocked like the POSIX dangerous programs
+const POWERSHELL_DANGEROUS: [&str; 38] = [
+    "remove-item",
+    "ri",
+    "del",
+    "erase",
+    "rd",
+    "clear-content",
+    "clc",
+    "set-content",
+    "sc",
+    "add-content",
+    "ac",
+    "out-file",
+    "move-item",
+    "mi",
+    "move",
+    "rename-item",
+    "rni",
+    "stop-process",
+    "spps",
+    "stop-computer",
+    "restart-computer",
+    "format-volume",
+    "clear-disk",
+    "set-executionpolicy",
+    "invoke-expression",
+    "iex",
+    "invoke-command",
+    "icm",
+    "invoke-webrequest",
+    "iwr",
+    "invoke-restmethod",
+    "irm",
+    "start-process",
+    "saps",
+    "new-localuser",
+    "remove-localuser",
+    "set-acl",
+    "set-itemproperty",
+];
+
+/// cmd.exe commands and Windows programs that delete, overwrite, stop, download
+/// or reconfigure the system
+const CMD_DANGEROUS: [&str; 29] = [
+    "del",
+    "erase",
+    "rd",
+    "rmdir",
+    "format",
+    "diskpart",
+    "shutdown",
+    "taskkill",
+    "reg",
+    "regedit",
+    "icacls",
+    "cacls",
+    "takeown",
+    "attrib",
+    "net",
+    "netsh",
+    "bcdedit",
+    "sc",
+    "schtasks",
+    "wmic",
+    "powershell",
+    "pwsh",
+    "certutil",
+    "bitsadmin",
+    "move",
+    "ren",
+    "copy",
+    "xcopy",
+    "robocopy",
+];
+
+/// Path traversal, UNC network shares and `\\.\` device paths on Windows
+const WINDOWS_TRAVERSAL: [&str; 3] = ["../", "..\\", "\\\\"];
+
+/// Shell a generated command is meant for
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::{SafetyPolicy, TargetShell};
+///
+/// let shell: TargetShell = "powershell".parse().unwrap();
+/// let policy = SafetyPolicy::default().with_target(shell);
+/// assert!(policy.is_safe("Get-ChildItem -Force C:\\Users"));
+/// assert!(!policy.is_safe("Remove-Item -Recurse C:\\Users"));
+/// assert!(!policy.is_safe("ls; rm -rf /"));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TargetShell {
+    /// bash on Linux (the historical target)
+    #[default]
+    Bash,
+    /// zsh, the macOS default
+    Zsh,
+    /// Windows PowerShell or PowerShell 7
+    PowerShell,
+    /// cmd.exe
+    Cmd,
+}
+
+impl TargetShell {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TargetShell::Bash => "bash",
+            TargetShell::Zsh => "zsh",
+            TargetShell::PowerShell => "powershell",
+            TargetShell::Cmd => "cmd",
+        }
+    }
+
+    /// Whether this is a Windows shell, with backslash paths and
+    /// case-insensitive command names
+    pub fn is_windows(&self) -> bool {
+        matches!(self, TargetShell::PowerShell | TargetShell::Cmd)
+    }
+
+    /// Instruction telling the model which shell to write for; `None` for bash,
+    /// which the models were trained on
+    pub fn instruction(&self) -> Option<&'static str> {
+        match self {
+            TargetShell::Bash => None,
+            TargetShell::Zsh => Some(
+                "Write the command for zsh on macOS: BSD versions of ls, find, stat, du \
+                 and date, not GNU options.",
+            ),
+            TargetShell::PowerShell => Some(
+                "Write the command for PowerShell on Windows: cmdlets such as \
+                 Get-ChildItem, Get-Content and Select-String, and Windows paths.",
+            ),
+            TargetShell::Cmd => Some(
+                "Write the command for cmd.exe on Windows: built-ins such as dir, type \
+                 and findstr, /-style options and Windows paths.",
+            ),
+        }
+    }
+
+    /// `system_prompt` preceded by [`TargetShell::instruction`], for backends'
+    /// `with_system_prompt`
+    pub fn system_prompt(&self, system_prompt: Option<&str>) -> Option<String> {
+        let system_prompt = system_prompt.map(str::trim).filter(|p| !p.is_empty());
+        match (self.instruction(), system_prompt) {
+            (Some(instruction), Some(prompt)) => Some(format!("{}\n{}", instruction, prompt)),
+            (instruction, prompt) => instruction.or(prompt).map(str::to_string),
+        }
+    }
+
+    /// Read-only commands allowed at every safety level, lowercase
+    pub fn base_commands(&self) -> &'static [&'static str] {
+        match self {
+            TargetShell::Bash | TargetShell::Zsh => &BASE_COMMANDS,
+            TargetShell::PowerShell => &POWERSHELL_COMMANDS,
+            TargetShell::Cmd => &CMD_COMMANDS,
+        }
+    }
+
+    /// Programs blocked on top of the POSIX dangerous ones, lowercase
+    pub fn dangerous_commands(&self) -> &'static [&'static str] {
+        match self {
+            TargetShell::Bash | TargetShell::Zsh => &[],
+            TargetShell::PowerShell => &POWERSHELL_DANGEROUS,
+            TargetShell::Cmd => &CMD_DANGEROUS,
+        }
+    }
+
+    /// Metacharacters that chain, substitute, redirect or expand
+    ///
+    /// On Windows the backslash is a path separator, not an escape; PowerShell
+    /// variables and subexpressions start with `$` or `@(`, cmd.exe ones with `%`.
+    pub fn injection_patterns(&self) -> Vec<&'static str> {
+        let windows = SHELL_INJECTION_PATTERNS
+            .iter()
+            .copied()
+            .filter(|&p| p != "\\");
+        match self {
+            TargetShell::Bash | TargetShell::Zsh => SHELL_INJECTION_PATTERNS.to_vec(),
+            TargetShell::PowerShell => windows.chain(["$", "@("]).collect(),
+            TargetShell::Cmd => windows.chain(["%"]).collect(),
+        }
+    }
+
+    /// Paths reaching outside the working tree or into devices
+    pub fn traversal_patterns(&self) -> &'static [&'static str] {
+        if self.is_windows() {
+            &WINDOWS_TRAVERSAL
+        } else {
+            &PATH_TRAVERSAL_PATTERNS
+        }
+    }
+}
+
+impl fmt::Display for TargetShell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TargetShell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bash" | "sh" => Ok(TargetShell::Bash),
+            "zsh" => Ok(TargetShell::Zsh),
+            "powershell" | "pwsh" => Ok(TargetShell::PowerShell),
+            "cmd" => Ok(TargetShell::Cmd),
+            other => Err(format!(
+                "Unknown target shell '{}' (expected bash, zsh, powershell or cmd)",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_shell_parsing() {
+        assert_eq!("PowerShell".parse(), Ok(TargetShell::PowerShell));
+        assert_eq!("pwsh".parse(), Ok(TargetShell::PowerShell));
+        assert_eq!(" zsh ".parse(), Ok(TargetShell::Zsh));
+        assert!("fish".parse::<TargetShell>().is_err());
+        assert_eq!(TargetShell::default().to_string(), "bash");
+        assert_eq!(TargetShell::Bash.instruction(), None);
+        assert!(TargetShell::Cmd.instruction().unwrap().contains("cmd.exe"));
+
+        assert_eq!(
+            TargetShell::Bash.system_prompt(Some("Prefer long flags.")),
+            Some("Prefer long flags.".to_string())
+        );
+        let prompt = TargetShell::PowerShell
+            .system_prompt(Some("Prefer long flags."))
+            .unwrap();
+        assert!(prompt.starts_with("Write the command for PowerShell"));
+        assert!(prompt.ends_with("\nPrefer long flags."));
+        assert_eq!(TargetShell::Bash.system_prompt(Some(" ")), None);
+    }
+
+    #[test]
+    fn test_tables_are_lowercase() {
+        for shell in [
+            TargetShell::Bash,
+            TargetShell::Zsh,
+            TargetShell::PowerShell,
+            TargetShell::Cmd,
+        ] {
+            for name in shell
+                .base_commands()
+                .iter()
+                .chain(shell.dangerous_commands())
+            {
+                assert_eq!(*name, name.to_lowercase(), "{}", shell);
+                assert!(
+                    !shell.dangerous_commands().contains(name)
+                        || !shell.base_commands().contains(name),
+                    "{} is both allowed and dangerous on {}",
+                    name,
+                    shell
+                );
+            }
+        }
+        assert!(!TargetShell::Cmd.injection_patterns().contains(&"\\"));
+        assert!(TargetShell::Zsh.injection_patterns().contains(&"\\"));
+    }
+}
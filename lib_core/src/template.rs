@@ -0,0 +1,229 @@
+// Templated prompts with user-supplied placeholders
+//
+// A prompt such as "find files larger than {size} in {dir}" names its parameters
+// instead of spelling out values. The model is asked to keep the placeholders in the
+// command it writes, which makes the command a reusable template; the values given
+// with `--var size=100M --var dir=/var/log` are then filled in, shell-quoted, and the
+// filled command is what safety validation sees.
+
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TemplateError {
+    #[error("'{0}' is not a NAME=VALUE variable")]
+    InvalidVar(String),
+    #[error("No value for {}; pass it with --var NAME=VALUE", braced(.0))]
+    MissingValues(Vec<String>),
+    #[error("--var {0} does not match a placeholder in the prompt")]
+    UnknownVar(String),
+}
+
+fn braced(names: &[String]) -> String {
+    let names: Vec<String> = names.iter().map(|n| format!("{{{}}}", n)).collect();
+    names.join(", ")
+}
+
+/// Parse a `NAME=VALUE` variable
+pub fn parse_var(var: &str) -> Result<(String, String), TemplateError> {
+    match var.split_once('=') {
+        Some((name, value)) if is_name(name) => Ok((name.to_string(), value.to_string())),
+        _ => Err(TemplateError::InvalidVar(var.to_string())),
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `{name}` placeholders of `text` in order of first use
+///
+/// Shell parameter expansions (`${HOME}`) and `find -exec` braces (`{}`) are not
+/// placeholders.
+pub fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    for (start, end) in placeholder_spans(text) {
+        let name = &text[start + 1..end - 1];
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Byte ranges of the `{name}` placeholders in `text`, braces included
+fn placeholder_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut rest = 0;
+    while let Some(open) = text[rest..].find('{').map(|i| rest + i) {
+        rest = open + 1;
+        let Some(close) = text[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        if is_name(&text[open + 1..close]) && !text[..open].ends_with('$') {
+            spans.push((open, close + 1));
+            rest = close + 1;
+        }
+    }
+    spans
+}
+
+/// `value` as one shell word: unchanged when it only holds safe characters, else in
+/// single quotes
+pub fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// Values for the placeholders of a templated prompt
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateVars {
+    values: BTreeMap<String, String>,
+}
+
+impl TemplateVars {
+    /// Values for the placeholders of `prompt`; `None` when the prompt has no
+    /// placeholders and no values were given
+    ///
+    /// Every placeholder needs a value, and every value a placeholder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_core::template::TemplateVars;
+    ///
+    /// let vars = vec![
+    ///     ("size".to_string(), "100M".to_string()),
+    ///     ("dir".to_string(), "/var/log".to_string()),
+    /// ];
+    /// let vars = TemplateVars::for_prompt("find files larger than {size} in {dir}", vars)
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     vars.fill("find {dir} -type f -size +{size}"),
+    ///     "find /var/log -type f -size +100M"
+    /// );
+    /// ```
+    pub fn for_prompt(
+        prompt: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Option<Self>, TemplateError> {
+        let names = placeholders(prompt);
+        let values: BTreeMap<String, String> = vars.into_iter().collect();
+        if let Some(unknown) = values.keys().find(|name| !names.contains(&name.as_str())) {
+            return Err(TemplateError::UnknownVar(unknown.clone()));
+        }
+        let missing: Vec<String> = names
+            .iter()
+            .filter(|name| !values.contains_key(**name))
+            .map(|name| name.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(TemplateError::MissingValues(missing));
+        }
+        Ok((!values.is_empty()).then_some(Self { values }))
+    }
+
+    /// Generation context asking the model to keep the placeholders
+    pub fn instruction(&self) -> String {
+        let names: Vec<String> = self.values.keys().cloned().collect();
+        format!(
+            "The request names its parameters as placeholders. Write {} literally in the \
+             command where those values go; they are filled in afterwards.",
+            braced(&names)
+        )
+    }
+
+    /// `template` with each placeholder replaced by its shell-quoted value
+    pub fn fill(&self, template: &str) -> String {
+        let mut filled = String::with_capacity(template.len());
+        let mut rest = 0;
+        for (start, end) in placeholder_spans(template) {
+            if let Some(value) = self.values.get(&template[start + 1..end - 1]) {
+                filled.push_str(&template[rest..start]);
+                filled.push_str(&shell_quote(value));
+                rest = end;
+            }
+        }
+        filled.push_str(&template[rest..]);
+        filled
+    }
+
+    /// Placeholders the model left out of `template`, having written a value of its
+    /// own or dropped the parameter
+    pub fn missing_in(&self, template: &str) -> Vec<&str> {
+        let used = placeholders(template);
+        self.values
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !used.contains(name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_placeholders_skip_shell_syntax() {
+        assert_eq!(
+            placeholders("copy {src} to {dest}, then {src} again"),
+            ["src", "dest"]
+        );
+        assert!(placeholders("find . -exec rm {} \\; in ${HOME} {not a name}").is_empty());
+        assert_eq!(parse_var("dir=/var/log=old").unwrap().1, "/var/log=old");
+        assert!(parse_var("9lives=x").is_err());
+        assert!(parse_var("novalue").is_err());
+    }
+
+    #[test]
+    fn test_values_are_checked_against_the_prompt() {
+        let prompt = "archive {dir} as {name}";
+        assert_eq!(TemplateVars::for_prompt("list files", vec![]), Ok(None));
+        assert_eq!(
+            TemplateVars::for_prompt(prompt, vars(&[("dir", "/tmp")])),
+            Err(TemplateError::MissingValues(vec!["name".to_string()]))
+        );
+        assert_eq!(
+            TemplateVars::for_prompt("list files", vars(&[("dir", "/tmp")])),
+            Err(TemplateError::UnknownVar("dir".to_string()))
+        );
+        let err = TemplateVars::for_prompt(prompt, vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No value for {dir}, {name}; pass it with --var NAME=VALUE"
+        );
+    }
+
+    #[test]
+    fn test_fill_quotes_values() {
+        let template = TemplateVars::for_prompt(
+            "archive {dir} as {name}",
+            vars(&[("dir", "My Documents"), ("name", "it's.tar; rm -rf ~")]),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            template.fill("tar -czf {name} {dir} && echo ${dir} {}"),
+            r#"tar -czf 'it'\''s.tar; rm -rf ~' 'My Documents' && echo ${dir} {}"#
+        );
+        assert_eq!(template.missing_in("tar -czf out.tar {dir}"), ["name"]);
+        assert!(template.instruction().contains("{dir}, {name}"));
+    }
+}
@@ -0,0 +1,224 @@
+// Tokenizer loading with defaults for incomplete tokenizer.json files
+//
+// tokenizer.json files exported by different tools vary in what they spell out.
+// Most leave truncation unset, some carry a truncation length longer than the model
+// can attend to or a stride that leaves no room for the special tokens (which
+// `tokenizers` only trips over during encoding, deep inside inference), and some name
+// a padding token that is not in the vocabulary. `load_tokenizer` inspects these
+// components once at load time, replaces what is missing or unusable with defaults
+// derived from the model's context length, and reports each change as a
+// `TokenizerWarning` so the caller can tell the user.
+
+use std::path::Path;
+use thiserror::Error;
+use tokenizers::{PostProcessor, Tokenizer, TruncationDirection, TruncationParams};
+
+/// Context length assumed when the model does not declare one
+pub const DEFAULT_CONTEXT_LENGTH: usize = 512;
+
+/// A tokenizer component that was missing or unusable, and what was done instead
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TokenizerWarning {
+    #[error("tokenizer has no truncation settings; truncating input to {max_length} tokens")]
+    MissingTruncation { max_length: usize },
+    #[error(
+        "tokenizer truncates at {configured} tokens, beyond the model context; \
+         truncating input to {max_length} tokens"
+    )]
+    TruncationBeyondContext {
+        configured: usize,
+        max_length: usize,
+    },
+    #[error(
+        "tokenizer truncation stride {stride} leaves no room within {max_length} tokens; \
+         using no stride"
+    )]
+    InvalidStride { stride: usize, max_length: usize },
+    #[error("tokenizer pads with '{token}', which is not in its vocabulary; padding disabled")]
+    UnknownPadToken { token: String },
+    #[error("tokenizer has no decoder; output may contain raw subword markers")]
+    MissingDecoder,
+}
+
+impl TokenizerWarning {
+    /// Whether the tokenizer was merely terse rather than wrong: most tokenizer.json
+    /// files leave truncation unset, so this is expected and not worth a warning
+    pub fn is_routine(&self) -> bool {
+        matches!(self, TokenizerWarning::MissingTruncation { .. })
+    }
+}
+
+/// Load the tokenizer at `path` for a model attending to `context_length` tokens
+///
+/// Input longer than the context is cut from `direction`: causal models keep the end
+/// of the prompt (`Left`), seq2seq models the beginning (`Right`).
+pub fn load_tokenizer(
+    path: impl AsRef<Path>,
+    context_length: usize,
+    direction: TruncationDirection,
+) -> tokenizers::Result<(Tokenizer, Vec<TokenizerWarning>)> {
+    let mut tokenizer = Tokenizer::from_file(path)?;
+    let warnings = apply_defaults(&mut tokenizer, context_length, direction);
+    Ok((tokenizer, warnings))
+}
+
+/// Counts the tokens a model's tokenizer makes of some text, e.g. for throughput
+/// figures of a backend that only returns text
+pub struct TokenCounter(Tokenizer);
+
+impl TokenCounter {
+    pub fn from_file(path: impl AsRef<Path>) -> tokenizers::Result<Self> {
+        Tokenizer::from_file(path).map(Self)
+    }
+
+    /// Tokens in `text`, without special tokens; 0 if it cannot be encoded
+    pub fn count(&self, text: &str) -> usize {
+        self.0
+            .encode(text, false)
+            .map(|encoding| encoding.len())
+            .unwrap_or(0)
+    }
+}
+
+/// Replace missing or unusable truncation and padding settings of `tokenizer`
+pub fn apply_defaults(
+    tokenizer: &mut Tokenizer,
+    context_length: usize,
+    direction: TruncationDirection,
+) -> Vec<TokenizerWarning> {
+    let mut warnings = Vec::new();
+    let added = tokenizer
+        .get_post_processor()
+        .map_or(0, |processor| processor.added_tokens(false));
+    // Room for at least one token of input besides the special tokens
+    let context_length = context_length.max(added + 1);
+
+    let truncation = match tokenizer.get_truncation().cloned() {
+        None => {
+            warnings.push(TokenizerWarning::MissingTruncation {
+                max_length: context_length,
+            });
+            TruncationParams {
+                max_length: context_length,
+                direction,
+                ..TruncationParams::default()
+            }
+        }
+        Some(mut params) => {
+            if params.max_length > context_length || params.max_length <= added {
+                warnings.push(TokenizerWarning::TruncationBeyondContext {
+                    configured: params.max_length,
+                    max_length: context_length,
+                });
+                params.max_length = context_length;
+            }
+            if params.stride > 0 && params.stride >= params.max_length - added {
+                warnings.push(TokenizerWarning::InvalidStride {
+                    stride: params.stride,
+                    max_length: params.max_length,
+                });
+                params.stride = 0;
+            }
+            params
+        }
+    };
+    // Cannot fail: the stride is below the room left after the special tokens
+    let _ = tokenizer.with_truncation(Some(truncation));
+
+    let unknown_pad = tokenizer
+        .get_padding()
+        .filter(|padding| tokenizer.token_to_id(&padding.pad_token).is_none())
+        .map(|padding| padding.pad_token.clone());
+    if let Some(token) = unknown_pad {
+        warnings.push(TokenizerWarning::UnknownPadToken { token });
+        tokenizer.with_padding(None);
+    }
+
+    if tokenizer.get_decoder().is_none() {
+        warnings.push(TokenizerWarning::MissingDecoder);
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A whitespace word-level tokenizer, with `extra` spliced into its JSON
+    fn tokenizer(extra: &str) -> Tokenizer {
+        let json = format!(
+            r###"{{
+                "version": "1.0",
+                "added_tokens": [],
+                "normalizer": null,
+                "pre_tokenizer": {{"type": "Whitespace"}},
+                "post_processor": null,
+                "decoder": {{"type": "WordPiece", "prefix": "##", "cleanup": true}},
+                "model": {{
+                    "type": "WordLevel",
+                    "vocab": {{"[UNK]": 0, "[PAD]": 1, "list": 2, "files": 3}},
+                    "unk_token": "[UNK]"
+                }},
+                {}
+            }}"###,
+            extra
+        );
+        Tokenizer::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_token_counter_counts_words() {
+        let counter = TokenCounter(tokenizer(r#""truncation": null, "padding": null"#));
+        assert_eq!(counter.count("list files"), 2);
+        assert_eq!(counter.count(""), 0);
+    }
+
+    #[test]
+    fn test_missing_truncation_defaults_to_context() {
+        let mut tok = tokenizer(r#""truncation": null, "padding": null"#);
+        let warnings = apply_defaults(&mut tok, 2, TruncationDirection::Left);
+        assert_eq!(
+            warnings,
+            vec![TokenizerWarning::MissingTruncation { max_length: 2 }]
+        );
+        assert!(warnings[0].is_routine());
+
+        let encoding = tok.encode("list files list files", false).unwrap();
+        // Causal models keep the end of the prompt
+        assert_eq!(encoding.get_ids(), &[2, 3]);
+    }
+
+    #[test]
+    fn test_unusable_settings_are_replaced() {
+        let mut tok = tokenizer(
+            r#""truncation": {"direction": "Right", "max_length": 4096, "strategy": "LongestFirst", "stride": 600},
+               "padding": {"strategy": "BatchLongest", "direction": "Right", "pad_to_multiple_of": null,
+                           "pad_id": 9, "pad_type_id": 0, "pad_token": "<pad>"}"#,
+        );
+        let warnings = apply_defaults(&mut tok, 512, TruncationDirection::Left);
+        assert_eq!(
+            warnings,
+            vec![
+                TokenizerWarning::TruncationBeyondContext {
+                    configured: 4096,
+                    max_length: 512
+                },
+                TokenizerWarning::InvalidStride {
+                    stride: 600,
+                    max_length: 512
+                },
+                TokenizerWarning::UnknownPadToken {
+                    token: "<pad>".to_string()
+                },
+            ]
+        );
+        assert!(warnings.iter().all(|w| !w.is_routine()));
+        let truncation = tok.get_truncation().unwrap();
+        assert_eq!((truncation.max_length, truncation.stride), (512, 0));
+        // The configured direction is kept
+        assert_eq!(truncation.direction, TruncationDirection::Right);
+        assert!(tok.get_padding().is_none());
+    }
+}
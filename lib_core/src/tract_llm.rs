@@ -1,28 +1,114 @@
+use crate::backend::{BackendKind, InferenceBackend, ModelInfo};
+use crate::compat;
+use crate::generation::GenerationConfig;
+use crate::presets::ModelPreset;
+use crate::prompt::{PromptBuilder, PromptTemplate};
+use crate::tokenizer::{self, TokenizerWarning, DEFAULT_CONTEXT_LENGTH};
 use crate::validation::is_safe_command;
 use anyhow::anyhow;
 use ndarray::arr1;
-use std::path::Path;
-use tokenizers::Tokenizer;
+use std::path::{Path, PathBuf};
+use tokenizers::{Tokenizer, TruncationDirection};
 use tract_onnx::prelude::*;
 
 pub struct Core {
     model: TypedRunnableModel<TypedModel>,
     tokenizer: Tokenizer,
+    /// Tokenizer components that were missing or unusable at load
+    tokenizer_warnings: Vec<TokenizerWarning>,
+    prompt: PromptBuilder,
+    /// Only the stop sequences apply: the model runs a single decoding pass
+    generation: GenerationConfig,
+    model_path: PathBuf,
+    preset: Option<String>,
 }
 
 impl Core {
     pub fn new<P: AsRef<Path>>(model_path: P, tokenizer_path: P) -> TractResult<Self> {
+        let model_path = model_path.as_ref().to_path_buf();
         let model = tract_onnx::onnx()
-            .model_for_path(model_path)?
-            .into_optimized()?
-            .into_runnable()?;
+            .model_for_path(&model_path)?
+            .into_typed()?;
+        // ONNX graphs do not declare a context length; seq2seq models keep the
+        // beginning of over-long input
+        let (tokenizer, tokenizer_warnings) = tokenizer::load_tokenizer(
+            tokenizer_path,
+            DEFAULT_CONTEXT_LENGTH,
+            TruncationDirection::Right,
+        )
+        .map_err(|e| anyhow!(e))?;
+
+        // Fail fast on a mismatched tokenizer instead of producing garbage later
+        if let Some(rows) = compat::onnx_embedding_rows(&model) {
+            compat::check_vocab(tokenizer.get_vocab_size(true), rows)?;
+        }
+
+        let model = model.into_optimized()?.into_runnable()?;
+
+        Ok(Self {
+            model,
+            tokenizer,
+            tokenizer_warnings,
+            prompt: PromptBuilder::new(PromptTemplate::Plain),
+            generation: GenerationConfig::default(),
+            model_path,
+            preset: None,
+        })
+    }
 
-        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| anyhow!(e))?;
+    /// Set the system prompt that frames every command generation request
+    /// (target distro, preferred tools, flag style, ...)
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt.set_system(prompt);
+        self
+    }
+
+    /// Use the prompt template and stop sequences of `preset`
+    pub fn with_preset(mut self, preset: &ModelPreset) -> Self {
+        self.prompt.set_template(preset.template);
+        self.generation = preset.generation.clone();
+        self.preset = Some(preset.name.clone());
+        self
+    }
+
+    /// The configured system prompt, if any
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.prompt.system_prompt()
+    }
 
-        Ok(Self { model, tokenizer })
+    /// Tokenizer components replaced with defaults when loading
+    pub fn tokenizer_warnings(&self) -> &[TokenizerWarning] {
+        &self.tokenizer_warnings
     }
 
     pub fn generate_command(&self, input: &str) -> TractResult<String> {
+        self.generate_command_with_context(input, None)
+    }
+
+    /// Generate a command with per-request context added to the system prompt
+    pub fn generate_command_with_context(
+        &self,
+        input: &str,
+        context: Option<&str>,
+    ) -> TractResult<String> {
+        self.generate_with_stops(input, context, &self.generation)
+    }
+
+    fn generate_with_stops(
+        &self,
+        input: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> TractResult<String> {
+        let output = self.run_model(&self.prompt.build_with_context(input, context))?;
+        Ok(match config.truncate_at_stop(&output) {
+            Some(output) => output.to_string(),
+            None => output,
+        })
+    }
+
+    /// Run a single encode → infer → decode pass over the model
+    fn run_model(&self, input: &str) -> TractResult<String> {
         let encoding = self.tokenizer.encode(input, true).map_err(|e| anyhow!(e))?;
         let input_ids: Vec<i64> = encoding.get_ids().iter().map(|&id| id as i64).collect();
         let input_tensor = arr1(&input_ids).into_dyn().into_tensor();
@@ -32,12 +118,12 @@ impl Core {
         let output_tensor = result[0].to_array_view::<i64>()?;
         let output_ids: Vec<u32> = output_tensor.iter().map(|&id| id as u32).collect();
 
-        let command = self
+        let output = self
             .tokenizer
             .decode(&output_ids, true)
             .map_err(|e| anyhow!(e))?;
 
-        Ok(command)
+        Ok(output)
     }
 
     /// Validates if a command is safe to display to users
@@ -58,24 +144,100 @@ impl Core {
     /// // Returns: "Lists all files in long format, including hidden files"
     /// ```
     pub fn explain_command(&self, command: &str) -> TractResult<String> {
-        let prompt = format!("Explain what this command does: {}", command);
+        self.run_model(&explain_prompt(command))
+    }
 
-        let encoding = self.tokenizer.encode(prompt.as_str(), true).map_err(|e| anyhow!(e))?;
-        let input_ids: Vec<i64> = encoding.get_ids().iter().map(|&id| id as i64).collect();
-        let input_tensor = arr1(&input_ids).into_dyn().into_tensor();
+    /// Generates explanations for several commands at once
+    ///
+    /// Inference runs on a bounded set of scoped threads instead of one command
+    /// after another, so `--alternatives 5 --explain` costs roughly one extra
+    /// inference of wall-clock time rather than five. Results are returned in
+    /// the same order as `commands`; a failure only affects its own entry.
+    pub fn explain_commands(&self, commands: &[String]) -> Vec<TractResult<String>> {
+        if commands.len() <= 1 {
+            return commands
+                .iter()
+                .map(|cmd| self.explain_command(cmd))
+                .collect();
+        }
 
-        let result = self.model.run(tvec!(input_tensor.into()))?;
+        let workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(commands.len());
+        let chunk_size = (commands.len() + workers - 1) / workers;
 
-        let output_tensor = result[0].to_array_view::<i64>()?;
-        let output_ids: Vec<u32> = output_tensor.iter().map(|&id| id as u32).collect();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = commands
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let handle = scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|cmd| self.explain_command(cmd))
+                            .collect::<Vec<_>>()
+                    });
+                    (chunk.len(), handle)
+                })
+                .collect();
 
-        let explanation = self
-            .tokenizer
-            .decode(&output_ids, true)
-            .map_err(|e| anyhow!(e))?;
+            handles
+                .into_iter()
+                .flat_map(|(len, handle)| {
+                    handle.join().unwrap_or_else(|_| {
+                        (0..len)
+                            .map(|_| Err(anyhow!("Explanation worker panicked")))
+                            .collect()
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+impl InferenceBackend for Core {
+    fn generate(&self, request: &str, context: Option<&str>) -> anyhow::Result<String> {
+        self.generate_command_with_context(request, context)
+    }
 
-        Ok(explanation)
+    fn generate_with_config(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> anyhow::Result<String> {
+        self.generate_with_stops(request, context, config)
     }
+
+    fn explain(&self, command: &str) -> anyhow::Result<String> {
+        self.explain_command(command)
+    }
+
+    fn explain_all(&self, commands: &[String]) -> Vec<anyhow::Result<String>> {
+        self.explain_commands(commands)
+    }
+
+    /// Passes share no state, so as many run at once as there are cores
+    fn parallelism(&self) -> usize {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            backend: BackendKind::Onnx,
+            model_path: self.model_path.clone(),
+            template: self.prompt.template(),
+            vocab_size: self.tokenizer.get_vocab_size(true),
+            preset: self.preset.clone(),
+            // tract only runs on the CPU
+            device: "cpu".to_string(),
+        }
+    }
+}
+
+/// Prompt used to ask the model for a command explanation
+fn explain_prompt(command: &str) -> String {
+    format!("Explain what this command does: {}", command)
 }
 
 impl Default for Core {
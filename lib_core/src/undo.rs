@@ -0,0 +1,259 @@
+// Undo suggestions for state-changing commands
+//
+// Relaxed safety levels let through commands that change state (`mv`, `git commit`,
+// `systemctl stop`, ...). For the common ones the inverse is mechanical, so it is
+// derived from the command itself rather than asked of the model. Commands whose
+// effect cannot be reversed (`rm`, `cp` over an existing file) get no suggestion,
+// and neither does anything with pipes, redirects, quoting or substitutions,
+// since a whitespace split no longer describes what the shell will run.
+
+/// Characters that make a command too complex to invert reliably
+const UNSUPPORTED_CHARS: &[char] = &['|', ';', '&', '>', '<', '`', '$', '"', '\'', '\\', '*', '?'];
+
+/// Suggest a command that reverses the effect of `command`, if one is known
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::undo::undo_command;
+///
+/// assert_eq!(undo_command("mv a.txt b.txt").as_deref(), Some("mv b.txt a.txt"));
+/// assert_eq!(undo_command("git stash").as_deref(), Some("git stash pop"));
+/// assert_eq!(undo_command("ls -la"), None);
+/// ```
+pub fn undo_command(command: &str) -> Option<String> {
+    if command.contains(UNSUPPORTED_CHARS) {
+        return None;
+    }
+
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let (sudo, words) = match words.split_first() {
+        Some((&"sudo", rest)) => ("sudo ", rest),
+        _ => ("", &words[..]),
+    };
+
+    inverse(words).map(|undo| format!("{}{}", sudo, undo))
+}
+
+fn inverse(words: &[&str]) -> Option<String> {
+    let (&program, args) = words.split_first()?;
+    let operands = operands(args);
+
+    match program {
+        "mv" if operands.len() == 2 && operands.len() == args.len() => {
+            let (from, to) = (operands[0], operands[1]);
+            // `mv file dir/` leaves the file inside the directory
+            match to.strip_suffix('/') {
+                Some(dir) => Some(format!("mv {}/{} {}", dir, basename(from), from)),
+                None => Some(format!("mv {} {}", to, from)),
+            }
+        }
+        "mkdir" if !operands.is_empty() && args.iter().all(|a| !a.starts_with('-')) => {
+            Some(format!("rmdir {}", operands.join(" ")))
+        }
+        "ln" if operands.len() == 2
+            && args
+                .iter()
+                .all(|a| matches!(*a, "-s" | "-sf" | "-f") || !a.starts_with('-')) =>
+        {
+            Some(format!("unlink {}", operands[1]))
+        }
+        "chmod" if args.len() == 2 => {
+            let mode = invert_symbolic_mode(args[0])?;
+            Some(format!("chmod {} {}", mode, args[1]))
+        }
+        "gzip" if operands.len() == 1 && args.len() == 1 => Some(format!("gunzip {}.gz", args[0])),
+        "gunzip" if operands.len() == 1 && args.len() == 1 => {
+            Some(format!("gzip {}", args[0].strip_suffix(".gz")?))
+        }
+        "cd" if args.len() == 1 && args[0] != "-" => Some("cd -".to_string()),
+        "pushd" if args.len() == 1 => Some("popd".to_string()),
+        "export" if args.len() == 1 => {
+            let (name, _) = args[0].split_once('=')?;
+            Some(format!("unset {}", name))
+        }
+        "alias" if args.len() == 1 => {
+            let (name, _) = args[0].split_once('=')?;
+            Some(format!("unalias {}", name))
+        }
+        "git" => git_inverse(args),
+        "systemctl" => swap_verb(
+            program,
+            args,
+            &[("start", "stop"), ("enable", "disable"), ("mask", "unmask")],
+        ),
+        "docker" | "podman" => swap_verb(program, args, &[("start", "stop"), ("pause", "unpause")]),
+        "apt" | "apt-get" | "dnf" | "yum" => swap_verb(program, args, &[("install", "remove")]),
+        "pip" | "pip3" | "npm" | "brew" | "cargo" => {
+            swap_verb(program, args, &[("install", "uninstall")])
+        }
+        "pacman" => swap_verb(program, args, &[("-S", "-R")]),
+        _ => None,
+    }
+}
+
+fn git_inverse(args: &[&str]) -> Option<String> {
+    let (&verb, rest) = args.split_first()?;
+    let operands = operands(rest);
+
+    match (verb, rest) {
+        ("stash", []) | ("stash", ["push"]) => Some("git stash pop".to_string()),
+        ("stash", ["pop"]) => Some("git stash".to_string()),
+        ("add", ["-A"]) | ("add", ["--all"]) => Some("git reset".to_string()),
+        ("add", _) if !operands.is_empty() && operands.len() == rest.len() => {
+            Some(format!("git restore --staged {}", operands.join(" ")))
+        }
+        ("commit", _) => Some("git reset --soft HEAD~1".to_string()),
+        ("mv", [from, to]) if !from.starts_with('-') && !to.starts_with('-') => {
+            Some(format!("git mv {} {}", to, from))
+        }
+        ("branch", [name]) if !name.starts_with('-') => Some(format!("git branch -d {}", name)),
+        ("tag", [name]) if !name.starts_with('-') => Some(format!("git tag -d {}", name)),
+        ("checkout", ["-b", name]) | ("switch", ["-c", name]) => {
+            Some(format!("git switch - && git branch -D {}", name))
+        }
+        ("switch", [name]) if !name.starts_with('-') => Some("git switch -".to_string()),
+        _ => None,
+    }
+}
+
+/// `program verb args...` with `verb` replaced by its counterpart
+fn swap_verb(program: &str, args: &[&str], pairs: &[(&str, &str)]) -> Option<String> {
+    let (&verb, rest) = args.split_first()?;
+    if operands(rest).is_empty() {
+        return None;
+    }
+
+    let other = pairs.iter().find_map(|&(a, b)| {
+        if verb == a {
+            Some(b)
+        } else if verb == b {
+            Some(a)
+        } else {
+            None
+        }
+    })?;
+
+    let mut words = vec![program, other];
+    words.extend_from_slice(rest);
+    Some(words.join(" "))
+}
+
+/// Invert a symbolic chmod mode such as `+x` or `go-w`; numeric modes have
+/// no inverse without knowing the previous permissions
+fn invert_symbolic_mode(mode: &str) -> Option<String> {
+    let pos = mode.find(['+', '-'])?;
+    let (who, rest) = mode.split_at(pos);
+    let perms = &rest[1..];
+    if perms.is_empty()
+        || perms.contains(['+', '-', '=', ','])
+        || !who.chars().all(|c| "ugoa".contains(c))
+    {
+        return None;
+    }
+
+    let op = if rest.starts_with('+') { '-' } else { '+' };
+    Some(format!("{}{}{}", who, op, perms))
+}
+
+fn operands<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    args.iter()
+        .copied()
+        .filter(|a| !a.starts_with('-'))
+        .collect()
+}
+
+fn basename(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::undo_command as undo;
+
+    #[test]
+    fn test_file_operations() {
+        assert_eq!(undo("mv a b").as_deref(), Some("mv b a"));
+        assert_eq!(
+            undo("mv notes.txt archive/").as_deref(),
+            Some("mv archive/notes.txt notes.txt")
+        );
+        assert_eq!(
+            undo("mkdir build dist").as_deref(),
+            Some("rmdir build dist")
+        );
+        assert_eq!(
+            undo("ln -s /opt/app current").as_deref(),
+            Some("unlink current")
+        );
+        assert_eq!(undo("chmod +x run.sh").as_deref(), Some("chmod -x run.sh"));
+        assert_eq!(undo("chmod go-w file").as_deref(), Some("chmod go+w file"));
+        assert_eq!(undo("gzip log.txt").as_deref(), Some("gunzip log.txt.gz"));
+
+        // Irreversible or ambiguous
+        assert_eq!(undo("chmod 755 run.sh"), None);
+        assert_eq!(undo("mv -f a b"), None);
+        assert_eq!(undo("mkdir -p a/b/c"), None);
+        assert_eq!(undo("rm a"), None);
+        assert_eq!(undo("cp a b"), None);
+    }
+
+    #[test]
+    fn test_git() {
+        assert_eq!(undo("git stash").as_deref(), Some("git stash pop"));
+        assert_eq!(undo("git stash pop").as_deref(), Some("git stash"));
+        assert_eq!(
+            undo("git add src/main.rs").as_deref(),
+            Some("git restore --staged src/main.rs")
+        );
+        assert_eq!(undo("git add -A").as_deref(), Some("git reset"));
+        assert_eq!(
+            undo("git commit -m wip").as_deref(),
+            Some("git reset --soft HEAD~1")
+        );
+        assert_eq!(
+            undo("git checkout -b feature").as_deref(),
+            Some("git switch - && git branch -D feature")
+        );
+        assert_eq!(undo("git push"), None);
+    }
+
+    #[test]
+    fn test_services_and_packages() {
+        assert_eq!(
+            undo("sudo systemctl stop nginx").as_deref(),
+            Some("sudo systemctl start nginx")
+        );
+        assert_eq!(
+            undo("docker pause web").as_deref(),
+            Some("docker unpause web")
+        );
+        assert_eq!(
+            undo("sudo apt install -y htop").as_deref(),
+            Some("sudo apt remove -y htop")
+        );
+        assert_eq!(
+            undo("pip install requests").as_deref(),
+            Some("pip uninstall requests")
+        );
+        assert_eq!(undo("systemctl status nginx"), None);
+        assert_eq!(undo("apt install"), None);
+    }
+
+    #[test]
+    fn test_shell_state() {
+        assert_eq!(undo("cd /tmp").as_deref(), Some("cd -"));
+        assert_eq!(undo("export EDITOR=vim").as_deref(), Some("unset EDITOR"));
+        assert_eq!(undo("export PATH=$PATH:/opt/bin"), None);
+    }
+
+    #[test]
+    fn test_complex_commands_skipped() {
+        assert_eq!(undo("mv a b && mv c d"), None);
+        assert_eq!(undo("mv 'my file' b"), None);
+        assert_eq!(undo("mv *.txt docs/"), None);
+        assert_eq!(undo(""), None);
+        assert_eq!(undo("ls -la"), None);
+    }
+}
@@ -1,6 +1,12 @@
 // Command validation module
 // Provides security validation for generated shell commands
 
+use crate::grammar::CommandGrammar;
+use crate::shell::{command_names, parse_pipeline, tokenize, Token};
+use crate::target::TargetShell;
+use std::fmt;
+use std::str::FromStr;
+
 /// Validates if a command is safe to display to users.
 ///
 /// This is the **primary security gate** for Eidos. It prevents generating commands
@@ -31,110 +37,978 @@
 /// assert!(!is_safe_command("ls && rm file"));
 /// ```
 ///
+/// This is the [`SafetyPolicy`] at [`SafetyLevel::Standard`] with no user
+/// allow/deny entries; use a policy directly to honour the `[safety]` config.
+///
 /// # See Also
 ///
 /// - `docs/SAFETY.md` for full security rationale
 /// - `tests/` for comprehensive security test suite
 pub fn is_safe_command(command: &str) -> bool {
-    // Whitelist of safe base commands that are read-only and don't modify system state.
-    // DO NOT add write commands (including touch/mkdir). See SAFETY.md for rationale.
-    // Even "safe" write operations are excluded to maintain strict read-only policy.
-    let allowed_commands = [
-        "ls", "pwd", "echo", "cat", "head", "tail", "grep", "find", "wc", "date", "whoami",
-        "hostname", "uname", "df", "du", "free", "top", "ps", "which", "whereis", "file", "stat",
-    ];
-
-    // Dangerous patterns that should never be allowed
-    let dangerous_patterns = [
-        "rm",
-        "rmdir",
-        "dd",
-        "mkfs",
-        "fdisk",
-        "shutdown",
-        "reboot",
-        "halt",
-        "poweroff",
-        "init",
-        "kill",
-        "killall",
-        "pkill",
-        "chown",
-        "chmod",
-        "chgrp",
-        "useradd",
-        "userdel",
-        "groupadd",
-        "groupdel",
-        "passwd",
-        "su",
-        "sudo",
-        "doas",
-        "curl",
-        "wget",
-        "nc",
-        "netcat",
-        "telnet",
-        "ssh",
-        "scp",
-        "sftp",
-        "rsync",
-        "mount",
-        "umount",
-        "mkswap",
-        "swapon",
-        "swapoff",
-        "iptables",
-        "ip6tables",
-        "nft",
-    ];
-
-    // Shell metacharacters and injection patterns
-    let shell_injection_patterns = [
-        "`", "$(", "${", "$((", ">>", "<<<", "&>", "|&", "&&", "||", "|", ";", "\n", "\r", "\\",
-        "'", "\"", "*", "?", "[", "]", "{", "}", "!", "~", "^", "<(", ">(", "../", "/dev/",
-        "/proc/", "/sys/", ">", "&",
-    ];
-
-    let cmd_lower = command.to_lowercase();
-    let cmd_trimmed = command.trim();
-
-    // Check for dangerous patterns
-    if dangerous_patterns.iter().any(|&p| {
-        cmd_lower.contains(p)
-            || cmd_trimmed.starts_with(p)
-            || cmd_lower.contains(&format!("/{}", p))
-    }) {
-        return false;
-    }
-
-    // Check for shell injection attempts
-    if shell_injection_patterns
+    SafetyPolicy::default().is_safe(command)
+}
+
+/// Validates a command with the default policy and explains any rejection
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::validation::{validate_command, SafetyRule, Severity};
+///
+/// let report = validate_command("ls; rm -rf /");
+/// assert!(!report.is_safe());
+/// assert_eq!(report.severity(), Some(Severity::Critical));
+/// assert_eq!(report.primary().unwrap().rule, SafetyRule::DangerousCommand);
+/// ```
+pub fn validate_command(command: &str) -> SafetyReport {
+    SafetyPolicy::default().validate(command)
+}
+
+/// Read-only base commands allowed at every level on bash and zsh; see
+/// [`TargetShell::base_commands`] for the other shells.
+// DO NOT add write commands (including touch/mkdir). See SAFETY.md for rationale.
+// Even "safe" write operations are excluded to maintain strict read-only policy.
+pub(crate) const BASE_COMMANDS: [&str; 22] = [
+    "ls", "pwd", "echo", "cat", "head", "tail", "grep", "find", "wc", "date", "whoami", "hostname",
+    "uname", "df", "du", "free", "top", "ps", "which", "whereis", "file", "stat",
+];
+
+/// Base commands withheld at the strict level (`find` can `-delete`, `top` is interactive)
+const STRICT_EXCLUDED: [&str; 2] = ["find", "top"];
+
+/// Additional read-only commands allowed at the permissive level
+const PERMISSIVE_COMMANDS: [&str; 12] = [
+    "git status",
+    "git log",
+    "git diff",
+    "git show",
+    "docker ps",
+    "docker images",
+    "kubectl get",
+    "tree",
+    "cut",
+    "id",
+    "uptime",
+    "lsblk",
+];
+
+/// Programs that are never allowed to run, whatever the policy says
+///
+/// They are matched against every program a command would run (see
+/// [`command_names`]), not against its text, so `ls rm-notes.txt` is fine.
+/// `mkfs` also covers `mkfs.ext4` and the like.
+const DANGEROUS_COMMANDS: [&str; 41] = [
+    "rm",
+    "rmdir",
+    "dd",
+    "mkfs",
+    "fdisk",
+    "shutdown",
+    "reboot",
+    "halt",
+    "poweroff",
+    "init",
+    "kill",
+    "killall",
+    "pkill",
+    "chown",
+    "chmod",
+    "chgrp",
+    "useradd",
+    "userdel",
+    "groupadd",
+    "groupdel",
+    "passwd",
+    "su",
+    "sudo",
+    "doas",
+    "curl",
+    "wget",
+    "nc",
+    "netcat",
+    "telnet",
+    "ssh",
+    "scp",
+    "sftp",
+    "rsync",
+    "mount",
+    "umount",
+    "mkswap",
+    "swapon",
+    "swapoff",
+    "iptables",
+    "ip6tables",
+    "nft",
+];
+
+/// Shell metacharacters and injection patterns
+pub(crate) const SHELL_INJECTION_PATTERNS: [&str; 30] = [
+    "`", "$(", "${", "$((", ">>", "<<<", "&>", "|&", "&&", "||", "|", ";", "\n", "\r", "\\", "'",
+    "\"", "*", "?", "[", "]", "{", "}", "!", "~", "^", "<(", ">(", ">", "&",
+];
+
+/// Path traversal and device/kernel filesystem access
+pub(crate) const PATH_TRAVERSAL_PATTERNS: [&str; 4] = ["../", "/dev/", "/proc/", "/sys/"];
+
+/// Quoting and globbing characters tolerated at the permissive level
+const PERMISSIVE_TOLERATED: [&str; 6] = ["'", "\"", "*", "?", "[", "]"];
+
+/// Flags whitelisted commands may not take, by command
+///
+/// Each entry becomes a [`CommandSchema`] of every [`SafetyPolicy`]; `find` takes
+/// its options as whole words (`-name`), the others group letters like getopt.
+const DENIED_FLAGS: [(&str, &[&str]); 5] = [
+    // Run programs, delete files or write output files
+    (
+        "find",
+        &[
+            "-exec", "-execdir", "-ok", "-okdir", "-delete", "-fprint", "-fprint0", "-fprintf",
+            "-fls",
+        ],
+    ),
+    // Selecting by parent process is how injected commands hide their children
+    ("ps", &["--ppid"]),
+    // Set the system clock
+    ("date", &["-s", "--set"]),
+    // Set the host name
+    ("hostname", &["-F", "--file", "-b", "--boot"]),
+    // Compile a magic file, writing `NAME.mgc`
+    ("file", &["-C", "--compile"]),
+];
+
+/// Commands in [`DENIED_FLAGS`] whose options are whole words rather than letters
+const SINGLE_DASH_LONG: [&str; 1] = ["find"];
+
+/// Commands in [`DENIED_FLAGS`] that change the system when given an operand, with
+/// the flags taking the next word as their value and the operand prefixes that
+/// change nothing
+const DENIED_OPERANDS: [(&str, &[&str], &[&str]); 2] = [
+    // `date MMDDhhmm` sets the clock; `date +%F` only formats
+    (
+        "date",
+        &[
+            "-d",
+            "--date",
+            "-f",
+            "--file",
+            "-r",
+            "--reference",
+            "-s",
+            "--set",
+        ],
+        &["+"],
+    ),
+    // `hostname NAME` sets the host name
+    ("hostname", &["-F", "--file"], &[]),
+];
+
+/// Commands [`SafetyPolicy::validate_all`] checks per thread; a check takes
+/// microseconds, so smaller batches are not worth a thread
+const VALIDATION_CHUNK: usize = 256;
+
+/// How serious a safety violation is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Severity {
+    /// Nothing harmful, just nothing to show (e.g. empty output)
+    Low,
+    /// Unknown command; not known to be harmful but not vetted either
+    Medium,
+    /// Could change what the command does (chaining, substitution, encoding)
+    High,
+    /// Destructive, privileged or network command
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Validation rule a command can break
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SafetyRule {
+    /// Blank command
+    EmptyCommand,
+    /// Matches a `deny` entry from the safety config
+    DeniedByPolicy,
+    /// Contains a destructive, privileged or network binary
+    DangerousCommand,
+    /// Contains a shell metacharacter (chaining, substitution, redirection, globbing)
+    ShellMetacharacter,
+    /// Reaches outside the working tree or into /dev, /proc, /sys
+    PathTraversal,
+    /// First word is not an allowed command at this safety level
+    NotWhitelisted,
+    /// Contains hex/octal escapes
+    EncodedCharacters,
+    /// Tampers with the shell field separator
+    IfsManipulation,
+    /// Passes a flag or operand the command's [`CommandSchema`] does not allow
+    DisallowedFlag,
+}
+
+impl SafetyRule {
+    pub fn severity(&self) -> Severity {
+        match self {
+            SafetyRule::EmptyCommand => Severity::Low,
+            SafetyRule::NotWhitelisted => Severity::Medium,
+            SafetyRule::DeniedByPolicy
+            | SafetyRule::ShellMetacharacter
+            | SafetyRule::PathTraversal
+            | SafetyRule::EncodedCharacters
+            | SafetyRule::IfsManipulation
+            | SafetyRule::DisallowedFlag => Severity::High,
+            SafetyRule::DangerousCommand => Severity::Critical,
+        }
+    }
+
+    /// Human-readable description of the rule
+    pub fn description(&self) -> &'static str {
+        match self {
+            SafetyRule::EmptyCommand => "empty command",
+            SafetyRule::DeniedByPolicy => "denied by safety config",
+            SafetyRule::DangerousCommand => "dangerous command",
+            SafetyRule::ShellMetacharacter => "shell metacharacter",
+            SafetyRule::PathTraversal => "path traversal",
+            SafetyRule::NotWhitelisted => "command not in whitelist",
+            SafetyRule::EncodedCharacters => "encoded characters",
+            SafetyRule::IfsManipulation => "IFS manipulation",
+            SafetyRule::DisallowedFlag => "flag or operand not allowed for this command",
+        }
+    }
+}
+
+impl fmt::Display for SafetyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// A single broken rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Violation {
+    pub rule: SafetyRule,
+    /// The offending substring
+    pub matched: String,
+    pub severity: Severity,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.matched.is_empty() {
+            write!(f, "{} [{}]", self.rule, self.severity)
+        } else {
+            write!(f, "{} '{}' [{}]", self.rule, self.matched, self.severity)
+        }
+    }
+}
+
+/// Outcome of validating a command: every rule it breaks, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SafetyReport {
+    pub command: String,
+    pub violations: Vec<Violation>,
+    /// The command passed only after being parsed into pipeline stages (quotes,
+    /// globs or `|`), so a person should read it before running it
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub needs_review: bool,
+}
+
+impl SafetyReport {
+    fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            violations: Vec::new(),
+            needs_review: false,
+        }
+    }
+
+    fn push(&mut self, rule: SafetyRule, matched: &str) {
+        self.violations.push(Violation {
+            rule,
+            matched: matched.to_string(),
+            severity: rule.severity(),
+        });
+    }
+
+    pub fn is_safe(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Highest severity among the violations
+    pub fn severity(&self) -> Option<Severity> {
+        self.violations.iter().map(|v| v.severity).max()
+    }
+
+    /// The most severe violation (the first one on ties)
+    pub fn primary(&self) -> Option<&Violation> {
+        self.violations.iter().rev().max_by_key(|v| v.severity)
+    }
+}
+
+/// How strict command validation should be
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SafetyLevel {
+    /// Smallest built-in whitelist; user allow entries are ignored
+    Strict,
+    /// Built-in whitelist plus user allow entries (the historical behaviour)
+    #[default]
+    Standard,
+    /// Standard, but commands with quotes, globs or pipes are parsed into pipeline
+    /// stages and allowed when every stage is, flagged as needing review
+    Review,
+    /// Also allows common read-only developer commands (`git status`, `docker ps`, ...)
+    /// and tolerates quotes and glob characters
+    Permissive,
+}
+
+impl SafetyLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SafetyLevel::Strict => "strict",
+            SafetyLevel::Standard => "standard",
+            SafetyLevel::Review => "review",
+            SafetyLevel::Permissive => "permissive",
+        }
+    }
+}
+
+impl fmt::Display for SafetyLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SafetyLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "strict" => Ok(SafetyLevel::Strict),
+            "standard" => Ok(SafetyLevel::Standard),
+            "review" => Ok(SafetyLevel::Review),
+            "permissive" => Ok(SafetyLevel::Permissive),
+            other => Err(format!(
+                "Unknown safety level '{}' (expected strict, standard, review or permissive)",
+                other
+            )),
+        }
+    }
+}
+
+/// Which flags a [`CommandSchema`] accepts
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FlagRule {
+    Any,
+    Only(Vec<String>),
+    Except(Vec<String>),
+}
+
+/// The flags and operands one command may take
+///
+/// A schema applies to commands starting with its words, like a `[safety]` entry,
+/// and checks the words as the shell passes them, quotes removed, so `'-delete'`
+/// is the flag `-delete`. Every argument that starts with `-` up to a `--` is a
+/// flag; `--name=value` is checked as `--name`. Unless the command takes
+/// whole-word options like `find`, `-la` is checked as `-l` and `-a`, and a denied
+/// long flag also rejects the abbreviations getopt would expand to it (`--se` for
+/// `--set`). The other arguments are operands, apart from the values of
+/// [`CommandSchema::value_flags`].
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::validation::{CommandSchema, SafetyPolicy, SafetyRule};
+///
+/// let policy = SafetyPolicy::default().with_schema(CommandSchema::new("grep").allow_flags([
+///     "-i", "-n", "-r", "--color",
+/// ]));
+/// assert!(policy.is_safe("grep -rn TODO src"));
+/// assert!(policy.is_safe("grep --color=auto TODO notes.txt"));
+///
+/// let report = policy.validate("grep -f patterns.txt notes.txt");
+/// assert_eq!(report.primary().unwrap().rule, SafetyRule::DisallowedFlag);
+/// assert_eq!(report.primary().unwrap().matched, "-f");
+///
+/// // Built-in schemas can be replaced, here lifting the `find` restrictions
+/// assert!(!policy.is_safe("find . -name x -delete"));
+/// let policy = policy.with_schema(CommandSchema::new("find").single_dash_long());
+/// assert!(policy.is_safe("find . -name x -delete"));
+///
+/// // Operands can be denied too, keeping `hostname` from renaming the host
+/// assert!(policy.is_safe("hostname -f"));
+/// assert!(!policy.is_safe("hostname build-01"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSchema {
+    /// Lowercase leading words, e.g. `git log`
+    command: String,
+    flags: FlagRule,
+    /// Whether `-abc` is one option, as with `find`, rather than `-a -b -c`
+    single_dash_long: bool,
+    /// Flags taking the next word as their value
+    value_flags: Vec<String>,
+    /// Prefixes of the only operands accepted, when operands are denied
+    operands: Option<Vec<String>>,
+}
+
+/// An argument of a command, as a [`CommandSchema`] reads it
+enum Argument {
+    Flag(String),
+    Operand(String),
+}
+
+impl CommandSchema {
+    /// Schema for `command` accepting any flag
+    pub fn new(command: impl AsRef<str>) -> Self {
+        Self {
+            command: normalize_entries([command]).next().unwrap_or_default(),
+            flags: FlagRule::Any,
+            single_dash_long: false,
+            value_flags: Vec::new(),
+            operands: None,
+        }
+    }
+
+    /// Accept only `flags`
+    pub fn allow_flags<I, S>(mut self, flags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.flags = FlagRule::Only(flags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Accept every flag but `flags`
+    pub fn deny_flags<I, S>(mut self, flags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.flags = FlagRule::Except(flags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Treat `-name` as one option instead of the letters `-n -a -m -e`
+    pub fn single_dash_long(mut self) -> Self {
+        self.single_dash_long = true;
+        self
+    }
+
+    /// Flags whose value is the next word (`-d yesterday`), which is then neither
+    /// a flag nor an operand
+    pub fn value_flags<I, S>(mut self, flags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.value_flags = flags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Reject operands, except those starting with one of `allowed_prefixes`
+    pub fn deny_operands<I, S>(mut self, allowed_prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.operands = Some(allowed_prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// The first flag or operand of `command` this schema rejects, if it applies
+    /// to any of the commands `command` runs
+    pub fn disallowed_flag(&self, command: &str) -> Option<String> {
+        simple_commands(command)
+            .iter()
+            .find_map(|words| self.disallowed_in(words))
+    }
+
+    /// The first word of one command, quotes removed, this schema rejects
+    fn disallowed_in(&self, words: &[String]) -> Option<String> {
+        if !matches_entry(&words.join(" ").to_lowercase(), &self.command) {
+            return None;
+        }
+        let arguments = &words[self.command.split_whitespace().count()..];
+        self.arguments_of(arguments)
+            .into_iter()
+            .find_map(|argument| match argument {
+                Argument::Flag(flag) if !self.accepts(&flag) => Some(flag),
+                Argument::Operand(operand) if !self.accepts_operand(&operand) => Some(operand),
+                _ => None,
+            })
+    }
+
+    fn arguments_of(&self, arguments: &[String]) -> Vec<Argument> {
+        let mut parsed = Vec::new();
+        let mut words = arguments.iter();
+        while let Some(argument) = words.next() {
+            if argument == "--" {
+                parsed.extend(words.map(|word| Argument::Operand(word.clone())));
+                break;
+            }
+            if let Some(long) = argument.strip_prefix("--") {
+                let name = format!("--{}", long.split('=').next().unwrap_or(long));
+                if !long.contains('=') && self.takes_value(&name) {
+                    words.next();
+                }
+                parsed.push(Argument::Flag(name));
+            } else if let Some(short) = argument.strip_prefix('-').filter(|s| !s.is_empty()) {
+                if self.single_dash_long {
+                    if self.takes_value(argument) {
+                        words.next();
+                    }
+                    parsed.push(Argument::Flag(argument.clone()));
+                    continue;
+                }
+                // Letters after one taking a value are that value: `-dnow`
+                for (i, c) in short.char_indices() {
+                    let flag = format!("-{}", c);
+                    let takes_value = self.takes_value(&flag);
+                    parsed.push(Argument::Flag(flag));
+                    if takes_value {
+                        if i + c.len_utf8() == short.len() {
+                            words.next();
+                        }
+                        break;
+                    }
+                }
+            } else {
+                // Includes `-`, which is stdin
+                parsed.push(Argument::Operand(argument.clone()));
+            }
+        }
+        parsed
+    }
+
+    fn takes_value(&self, flag: &str) -> bool {
+        self.value_flags
+            .iter()
+            .any(|v| v == flag || self.abbreviates(flag, v))
+    }
+
+    fn accepts(&self, flag: &str) -> bool {
+        match &self.flags {
+            FlagRule::Any => true,
+            FlagRule::Only(allowed) => allowed.iter().any(|a| a == flag),
+            FlagRule::Except(denied) => !denied
+                .iter()
+                .any(|d| d == flag || self.abbreviates(flag, d)),
+        }
+    }
+
+    fn accepts_operand(&self, operand: &str) -> bool {
+        match &self.operands {
+            None => true,
+            Some(prefixes) => prefixes.iter().any(|p| operand.starts_with(p.as_str())),
+        }
+    }
+
+    /// Whether getopt would expand `flag` to the long flag `long`
+    fn abbreviates(&self, flag: &str, long: &str) -> bool {
+        !self.single_dash_long && flag.len() > 2 && flag.starts_with("--") && long.starts_with(flag)
+    }
+}
+
+/// The words of each simple command `command` runs, quotes removed: those of
+/// every pipeline stage, list element and substitution
+fn simple_commands(command: &str) -> Vec<Vec<String>> {
+    let mut commands = vec![Vec::new()];
+    for token in tokenize(command) {
+        match token {
+            Token::Word(word) => commands.last_mut().expect("never empty").push(word),
+            Token::Operator(_) => commands.push(Vec::new()),
+        }
+    }
+    commands.retain(|words| !words.is_empty());
+    commands
+}
+
+/// The built-in [`DENIED_FLAGS`] table as schemas
+fn builtin_schemas() -> Vec<CommandSchema> {
+    DENIED_FLAGS
         .iter()
-        .any(|&p| command.contains(p))
+        .map(|(command, flags)| {
+            let mut schema = CommandSchema::new(command).deny_flags(flags.iter().copied());
+            if SINGLE_DASH_LONG.contains(command) {
+                schema = schema.single_dash_long();
+            }
+            if let Some((_, values, allowed)) = DENIED_OPERANDS.iter().find(|(c, ..)| c == command)
+            {
+                schema = schema
+                    .value_flags(values.iter().copied())
+                    .deny_operands(allowed.iter().copied());
+            }
+            schema
+        })
+        .collect()
+}
+
+/// Command safety policy: a level plus user-supplied allow/deny entries.
+///
+/// Entries are matched against the leading words of a command, so `"git status"`
+/// allows `git status -s` but not `git push`, and `"tree"` allows any `tree`
+/// invocation. Deny entries always win over allow entries, and the built-in
+/// dangerous-command, injection and encoding checks apply at every level.
+///
+/// Allowed commands are also held to the flags of their [`CommandSchema`]s: the
+/// built-in ones keep `find` from running programs or deleting files, `date` from
+/// setting the clock and so on, and [`SafetyPolicy::with_schema`] adds more.
+///
+/// # Examples
+///
+/// ```
+/// use lib_core::validation::{SafetyLevel, SafetyPolicy};
+///
+/// let policy = SafetyPolicy::new(SafetyLevel::Standard)
+///     .with_allow(["git status"])
+///     .with_deny(["cat"]);
+///
+/// assert!(policy.is_safe("git status -s"));
+/// assert!(!policy.is_safe("git push"));
+/// assert!(!policy.is_safe("cat file.txt"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyPolicy {
+    level: SafetyLevel,
+    target: TargetShell,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    schemas: Vec<CommandSchema>,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self::new(SafetyLevel::default())
+    }
+}
+
+impl SafetyPolicy {
+    pub fn new(level: SafetyLevel) -> Self {
+        Self {
+            level,
+            target: TargetShell::default(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            schemas: builtin_schemas(),
+        }
+    }
+
+    /// Add user allow entries (ignored at the strict level)
+    pub fn with_allow<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
     {
-        return false;
+        self.allow.extend(normalize_entries(entries));
+        self
+    }
+
+    /// Add user deny entries
+    pub fn with_deny<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.deny.extend(normalize_entries(entries));
+        self
+    }
+
+    /// Check the flags of `schema`'s command, replacing any schema for the same
+    /// command, built-in ones included
+    pub fn with_schema(mut self, schema: CommandSchema) -> Self {
+        self.schemas.retain(|s| s.command != schema.command);
+        self.schemas.push(schema);
+        self
+    }
+
+    /// Validate commands for `target`'s whitelist, dangerous programs and
+    /// metacharacters instead of bash's
+    pub fn with_target(mut self, target: TargetShell) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn level(&self) -> SafetyLevel {
+        self.level
+    }
+
+    pub fn target(&self) -> TargetShell {
+        self.target
+    }
+
+    pub fn schemas(&self) -> &[CommandSchema] {
+        &self.schemas
+    }
+
+    /// Validates a command against this policy
+    pub fn is_safe(&self, command: &str) -> bool {
+        self.validate(command).is_safe()
+    }
+
+    /// Validates a command and reports every rule it breaks
+    ///
+    /// Each rule is reported at most once, with the first offending substring.
+    pub fn validate(&self, command: &str) -> SafetyReport {
+        let mut report = SafetyReport::new(command);
+        let cmd_trimmed = command.trim();
+
+        if cmd_trimmed.is_empty() {
+            report.push(SafetyRule::EmptyCommand, "");
+            return report;
+        }
+
+        // Check for shell injection attempts
+        let metacharacter = self.target.injection_patterns().into_iter().find(|&p| {
+            command.contains(p)
+                && !(self.level == SafetyLevel::Permissive && PERMISSIVE_TOLERATED.contains(&p))
+        });
+        // From the review level up, a plain pipeline is checked stage by stage
+        // on its unquoted words instead
+        let stages = if metacharacter.is_some() && self.reviews_pipelines() {
+            parse_pipeline(cmd_trimmed)
+                .ok()
+                .map(|pipeline| pipeline.stage_texts())
+        } else {
+            None
+        };
+        report.needs_review = stages.is_some();
+        let stages = stages.unwrap_or_else(|| vec![cmd_trimmed.to_string()]);
+        let stages_lower: Vec<String> = stages.iter().map(|s| s.to_lowercase()).collect();
+
+        // User deny entries take precedence over everything else
+        if let Some(entry) = self
+            .deny
+            .iter()
+            .find(|entry| stages_lower.iter().any(|stage| matches_entry(stage, entry)))
+        {
+            report.push(SafetyRule::DeniedByPolicy, entry);
+        }
+
+        // Check every program the command would run, in substitutions and behind
+        // wrappers like `sudo` or `xargs` too
+        if let Some(p) = command_names(command)
+            .iter()
+            .find_map(|name| self.dangerous_command(name))
+        {
+            report.push(SafetyRule::DangerousCommand, p);
+        }
+
+        if let Some(p) = metacharacter.filter(|_| !report.needs_review) {
+            report.push(SafetyRule::ShellMetacharacter, p);
+        }
+
+        // Check for path traversal and special filesystems
+        if let Some(p) = self
+            .target
+            .traversal_patterns()
+            .iter()
+            .find(|&&p| command.contains(p))
+        {
+            report.push(SafetyRule::PathTraversal, p);
+        }
+
+        // Check if every stage starts with an allowed command (case-insensitive)
+        if let Some(stage) = stages_lower.iter().find(|stage| !self.is_allowed(stage)) {
+            let first_word = stage.split_whitespace().next().unwrap_or("");
+            report.push(SafetyRule::NotWhitelisted, first_word);
+        }
+
+        // Check the unquoted words of every command against its schemas
+        let commands = simple_commands(command);
+        if let Some(flag) = commands.iter().find_map(|words| {
+            self.schemas
+                .iter()
+                .find_map(|schema| schema.disallowed_in(words))
+        }) {
+            report.push(SafetyRule::DisallowedFlag, &flag);
+        }
+
+        // Additional checks for suspicious patterns
+        // Check for hex/octal encoded characters
+        if let Some(p) = ["\\x", "\\0"].iter().find(|&&p| command.contains(p)) {
+            report.push(SafetyRule::EncodedCharacters, p);
+        }
+
+        // Check for IFS manipulation
+        if mentions_ifs(command) {
+            report.push(SafetyRule::IfsManipulation, "IFS");
+        }
+
+        report
     }
 
-    // Check if command starts with an allowed command (case-insensitive)
-    let first_word = cmd_lower.split_whitespace().next().unwrap_or("");
-    if !allowed_commands.contains(&first_word) {
-        return false;
+    /// Validates several commands, e.g. the alternatives of one request or the
+    /// lines of a script; reports are in the order of `commands`
+    ///
+    /// Batches larger than a few hundred commands are split across scoped
+    /// threads.
+    pub fn validate_all<S: AsRef<str> + Sync>(&self, commands: &[S]) -> Vec<SafetyReport> {
+        if commands.len() <= VALIDATION_CHUNK {
+            return commands
+                .iter()
+                .map(|cmd| self.validate(cmd.as_ref()))
+                .collect();
+        }
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = commands
+                .chunks(VALIDATION_CHUNK)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|cmd| self.validate(cmd.as_ref()))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("validation does not panic"))
+                .collect()
+        })
     }
 
-    // Additional checks for suspicious patterns
-    // Check for hex/octal encoded characters
-    if command.contains("\\x") || command.contains("\\0") {
-        return false;
+    /// The whitelist and metacharacter rules of this policy as a grammar for
+    /// constrained generation
+    ///
+    /// Texts the grammar accepts pass every check but the dangerous-command,
+    /// encoding and `IFS` ones, which look inside words and cannot be enforced
+    /// token by token without rejecting harmless text.
+    pub fn grammar(&self) -> CommandGrammar {
+        let base = self
+            .target
+            .base_commands()
+            .iter()
+            .filter(|c| self.level != SafetyLevel::Strict || !STRICT_EXCLUDED.contains(c))
+            .map(|c| c.to_string());
+        let commands: Vec<String> = match self.level {
+            SafetyLevel::Strict => base.collect(),
+            SafetyLevel::Standard | SafetyLevel::Review => {
+                base.chain(self.allow.iter().cloned()).collect()
+            }
+            SafetyLevel::Permissive => base
+                .chain(PERMISSIVE_COMMANDS.iter().map(|c| c.to_string()))
+                .chain(self.allow.iter().cloned())
+                .collect(),
+        };
+        let blocked = self
+            .target
+            .injection_patterns()
+            .into_iter()
+            .filter(|p| {
+                !(self.level == SafetyLevel::Permissive && PERMISSIVE_TOLERATED.contains(p))
+            })
+            .chain(self.target.traversal_patterns().iter().copied())
+            .map(|p| p.to_string());
+        CommandGrammar::new(commands, blocked).with_denied(&self.deny)
     }
 
-    // Check for IFS manipulation
-    if command.to_uppercase().contains("IFS") {
-        return false;
+    fn is_allowed(&self, cmd_lower: &str) -> bool {
+        let first_word = cmd_lower.split_whitespace().next().unwrap_or("");
+        if first_word.is_empty() {
+            return false;
+        }
+
+        let base = self.target.base_commands().contains(&first_word);
+        match self.level {
+            SafetyLevel::Strict => base && !STRICT_EXCLUDED.contains(&first_word),
+            SafetyLevel::Standard | SafetyLevel::Review => base || self.allow_matches(cmd_lower),
+            SafetyLevel::Permissive => {
+                base || PERMISSIVE_COMMANDS
+                    .iter()
+                    .any(|entry| matches_entry(cmd_lower, entry))
+                    || self.allow_matches(cmd_lower)
+            }
+        }
+    }
+
+    /// The dangerous program `name` is, for any target or this one; Windows
+    /// shells ignore case
+    fn dangerous_command(&self, name: &str) -> Option<&'static str> {
+        if !self.target.is_windows() {
+            return dangerous_command(&DANGEROUS_COMMANDS, name);
+        }
+        let name = name.to_lowercase();
+        dangerous_command(&DANGEROUS_COMMANDS, &name)
+            .or_else(|| dangerous_command(self.target.dangerous_commands(), &name))
     }
 
-    // Command seems safe
-    true
+    /// Whether commands with metacharacters get a second look as pipelines
+    fn reviews_pipelines(&self) -> bool {
+        matches!(self.level, SafetyLevel::Review | SafetyLevel::Permissive)
+    }
+
+    fn allow_matches(&self, cmd_lower: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|entry| matches_entry(cmd_lower, entry))
+    }
+}
+
+/// The entry of `table` (e.g. [`DANGEROUS_COMMANDS`]) a program name is, if any
+fn dangerous_command(table: &[&'static str], name: &str) -> Option<&'static str> {
+    table.iter().copied().find(|&p| {
+        name.strip_prefix(p)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// Whether `command` names the `IFS` variable: `IFS=`, `$IFS`, `${IFS}`, but not
+/// `gifs.txt`
+fn mentions_ifs(command: &str) -> bool {
+    let is_name = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+    command.match_indices("IFS").any(|(i, _)| {
+        !is_name(command[..i].chars().next_back()) && !is_name(command[i + 3..].chars().next())
+    })
+}
+
+/// Lowercase entries and drop empty ones
+fn normalize_entries<I, S>(entries: I) -> impl Iterator<Item = String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    entries
+        .into_iter()
+        .map(|e| e.as_ref().trim().to_lowercase())
+        .filter(|e| !e.is_empty())
+}
+
+/// Whether the leading words of `cmd_lower` are exactly the words of `entry`
+fn matches_entry(cmd_lower: &str, entry: &str) -> bool {
+    let mut cmd_words = cmd_lower.split_whitespace();
+    let mut matched = false;
+    for word in entry.split_whitespace() {
+        if cmd_words.next() != Some(word) {
+            return false;
+        }
+        matched = true;
+    }
+    matched
 }
 
 #[cfg(test)]
@@ -275,4 +1149,358 @@ mod tests {
         assert!(!is_safe_command("\t"));
         assert!(!is_safe_command("\n"));
     }
+
+    #[test]
+    fn test_safety_level_from_str() {
+        assert_eq!("strict".parse::<SafetyLevel>(), Ok(SafetyLevel::Strict));
+        assert_eq!(
+            " Permissive ".parse::<SafetyLevel>(),
+            Ok(SafetyLevel::Permissive)
+        );
+        assert!("lax".parse::<SafetyLevel>().is_err());
+        assert_eq!(SafetyLevel::default(), SafetyLevel::Standard);
+    }
+
+    #[test]
+    fn test_policy_levels() {
+        let strict = SafetyPolicy::new(SafetyLevel::Strict);
+        let standard = SafetyPolicy::new(SafetyLevel::Standard);
+        let permissive = SafetyPolicy::new(SafetyLevel::Permissive);
+
+        assert!(strict.is_safe("ls -la"));
+        assert!(!strict.is_safe("find . -name test"));
+        assert!(standard.is_safe("find . -name test"));
+
+        assert!(!standard.is_safe("git status"));
+        assert!(permissive.is_safe("git status"));
+        assert!(permissive.is_safe("docker ps -a"));
+        assert!(!permissive.is_safe("git push"));
+
+        // Quotes and globs are only tolerated at the permissive level
+        assert!(!standard.is_safe("ls *.txt"));
+        assert!(permissive.is_safe("ls *.txt"));
+        assert!(permissive.is_safe("grep 'todo' notes.md"));
+
+        // Hard blocks hold at every level
+        for policy in [&strict, &standard, &permissive] {
+            assert!(!policy.is_safe("rm -rf /"));
+            assert!(!policy.is_safe("ls && whoami"));
+            assert!(!policy.is_safe("cat $(whoami)"));
+        }
+    }
+
+    #[test]
+    fn test_policy_allow_and_deny_lists() {
+        let policy = SafetyPolicy::new(SafetyLevel::Standard)
+            .with_allow(["Git Status", "tree", ""])
+            .with_deny(["cat", "tree -f"]);
+
+        assert!(policy.is_safe("git status -s"));
+        assert!(policy.is_safe("tree -L 2"));
+        assert!(!policy.is_safe("git commit"));
+        assert!(!policy.is_safe("cat file.txt"));
+        // Deny wins over allow
+        assert!(!policy.is_safe("tree -f"));
+        // The built-in dangerous list cannot be allowed away
+        assert!(!SafetyPolicy::default()
+            .with_allow(["rm"])
+            .is_safe("rm file"));
+        // Strict ignores the allow list
+        assert!(!SafetyPolicy::new(SafetyLevel::Strict)
+            .with_allow(["tree"])
+            .is_safe("tree"));
+    }
+
+    #[test]
+    fn test_safety_report_rules() {
+        let report = validate_command("ls -la");
+        assert!(report.is_safe());
+        assert_eq!(report.severity(), None);
+
+        let cases = [
+            ("", SafetyRule::EmptyCommand, ""),
+            ("rm file.txt", SafetyRule::DangerousCommand, "rm"),
+            ("ls | wc -l", SafetyRule::ShellMetacharacter, "|"),
+            ("cat ../secret", SafetyRule::PathTraversal, "../"),
+            ("python script.py", SafetyRule::NotWhitelisted, "python"),
+            ("IFS=x ls", SafetyRule::IfsManipulation, "IFS"),
+        ];
+        for (cmd, rule, matched) in cases {
+            let report = validate_command(cmd);
+            let violation = report
+                .violations
+                .iter()
+                .find(|v| v.rule == rule)
+                .unwrap_or_else(|| panic!("Expected {:?} for '{}'", rule, cmd));
+            assert_eq!(violation.matched, matched);
+            assert_eq!(violation.severity, rule.severity());
+        }
+    }
+
+    #[test]
+    fn test_safety_report_primary_violation() {
+        let report = validate_command("python x.py && rm -rf /");
+        assert_eq!(report.severity(), Some(Severity::Critical));
+        assert_eq!(report.primary().unwrap().rule, SafetyRule::DangerousCommand);
+
+        let report = SafetyPolicy::default()
+            .with_deny(["cat"])
+            .validate("cat a.txt");
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].rule, SafetyRule::DeniedByPolicy);
+        assert_eq!(
+            report.violations[0].to_string(),
+            "denied by safety config 'cat' [high]"
+        );
+    }
+
+    #[test]
+    fn test_builtin_flag_schemas() {
+        for command in [
+            "find . -name x -delete",
+            "find . -type f -exec cat {} +",
+            "find . -fprint out.txt",
+            "ps --ppid 1",
+            "ps --ppid=1",
+            "date -s 2020-01-01",
+            "date -us 2020-01-01",
+            "date --set=2020-01-01",
+            "date --se 2020-01-01",
+            "hostname -F name.txt",
+            "file -C -m magic",
+            "file --comp -m magic",
+            "find . -name x '-delete'",
+            "find . -de\"\"lete",
+            "ls | find . -exec rm {} ;",
+            "hostname build-01",
+            "hostname 'build-01'",
+            "hostname -- build-01",
+            "date 0101000020",
+            "date -u 0101000020",
+            "date -dyesterday 0101000020",
+        ] {
+            let report = validate_command(command);
+            assert!(
+                report
+                    .violations
+                    .iter()
+                    .any(|v| v.rule == SafetyRule::DisallowedFlag),
+                "{}",
+                command
+            );
+        }
+        for command in [
+            "find . -name notes -type f",
+            "find . -newer stamp",
+            "ps aux",
+            "ps -ef",
+            "date -u",
+            "date --date=yesterday",
+            "hostname -f",
+            "file -b notes.txt",
+            "cat -- -s",
+            "date +%F",
+            "date -d yesterday +%F",
+            "date -dyesterday",
+            "date --da yesterday",
+            "date -r notes.txt",
+            "echo hostname build-01",
+        ] {
+            assert!(is_safe_command(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_custom_flag_schemas() {
+        let policy = SafetyPolicy::new(SafetyLevel::Standard)
+            .with_allow(["git log"])
+            .with_schema(CommandSchema::new("git log").deny_flags(["--output", "-p"]))
+            .with_schema(CommandSchema::new("ls").allow_flags(["-l", "-a", "-h"]));
+        assert!(policy.is_safe("git log --oneline -n 5"));
+        assert!(!policy.is_safe("git log --output=log.txt"));
+        assert!(policy.is_safe("ls -lah"));
+        assert!(policy.is_safe("ls - notes"));
+
+        let report = policy.validate("ls -laR");
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(
+            report.violations[0].to_string(),
+            "flag or operand not allowed for this command '-R' [high]"
+        );
+
+        // A schema for the same command replaces the built-in one
+        let policy = policy.with_schema(
+            CommandSchema::new("FIND")
+                .deny_flags(["-newer"])
+                .single_dash_long(),
+        );
+        assert!(policy.is_safe("find . -delete"));
+        assert!(!policy.is_safe("find . -newer stamp"));
+        assert_eq!(
+            policy
+                .schemas()
+                .iter()
+                .filter(|s| s.command() == "find")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_review_level_checks_each_pipeline_stage() {
+        let review = SafetyPolicy::new(SafetyLevel::Review).with_deny(["cat"]);
+        for command in [
+            "ps aux | grep nginx",
+            "grep -rn 'TODO: fix' src | wc -l",
+            "ls *.log",
+            "echo \"a | b; c\"",
+        ] {
+            let report = review.validate(command);
+            assert!(report.is_safe(), "{}: {:?}", command, report.violations);
+            assert!(report.needs_review, "{}", command);
+        }
+        assert!(!review.validate("ls -la").needs_review);
+
+        // Every stage is held to the whitelist, deny entries and flag schemas
+        let rules = |command: &str| -> Vec<SafetyRule> {
+            let report = review.validate(command);
+            assert!(!report.needs_review || !report.is_safe(), "{}", command);
+            report.violations.iter().map(|v| v.rule).collect()
+        };
+        assert_eq!(rules("ps aux | sort -k3"), [SafetyRule::NotWhitelisted]);
+        assert_eq!(rules("ls | cat"), [SafetyRule::DeniedByPolicy]);
+        assert_eq!(
+            rules("find . -name '*.tmp' | '-delete'"),
+            [SafetyRule::NotWhitelisted]
+        );
+        assert_eq!(
+            rules("find . -name '*.tmp' '-delete'"),
+            [SafetyRule::DisallowedFlag]
+        );
+        // Commands that are more than a plain pipeline are still rejected
+        for command in ["ls && id", "echo \"$(id)\"", "ls > out.txt", "grep 'x"] {
+            let report = review.validate(command);
+            assert!(!report.is_safe() && !report.needs_review, "{}", command);
+            assert!(report
+                .violations
+                .iter()
+                .any(|v| v.rule == SafetyRule::ShellMetacharacter));
+        }
+
+        // Standard and strict never parse pipelines
+        assert!(!is_safe_command("ps aux | grep nginx"));
+        let permissive = SafetyPolicy::new(SafetyLevel::Permissive).validate("git log | head");
+        assert!(permissive.is_safe() && permissive.needs_review);
+        let quoted = SafetyPolicy::new(SafetyLevel::Permissive).validate("grep 'x' notes");
+        assert!(quoted.is_safe() && !quoted.needs_review);
+    }
+
+    #[test]
+    fn test_program_names_in_arguments_are_not_dangerous() {
+        // Substring matching used to reject all of these
+        for command in [
+            "ls -l my-rmdir-notes.txt",
+            "grep firmware notes.txt",
+            "cat address.txt",
+            "ls format",
+            "wc -l summary.md",
+            "stat /etc/sudoers",
+            "file initrd.img",
+            "cat gifs.txt",
+            "ps -o comm,pid",
+            "grep -c kill notes.txt",
+        ] {
+            let report = validate_command(command);
+            assert!(report.is_safe(), "{}: {:?}", command, report.violations);
+        }
+
+        // The programs themselves are caught wherever they would run
+        for command in [
+            "RM -rf x",
+            "/bin/rm x",
+            "ls;rm x",
+            "echo $(reboot)",
+            "echo \"`sudo id`\"",
+            "find . -exec rm {} +",
+            "ls | xargs -0 chmod 777",
+            "env X=1 curl example.com",
+            "sh -c 'halt'",
+            "mkfs.ext4 disk.img",
+            "LANG=C kill 1",
+        ] {
+            let report = validate_command(command);
+            assert!(
+                report
+                    .violations
+                    .iter()
+                    .any(|v| v.rule == SafetyRule::DangerousCommand),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_target_shells() {
+        let powershell = SafetyPolicy::default().with_target(TargetShell::PowerShell);
+        assert!(powershell.is_safe("Get-ChildItem -Recurse C:\\Users\\me\\Documents"));
+        assert!(powershell.is_safe("get-content notes.txt"));
+        assert!(!powershell.is_safe("uname -a"));
+        for (command, rule) in [
+            (
+                "Remove-Item -Recurse C:\\Temp",
+                SafetyRule::DangerousCommand,
+            ),
+            (
+                "Invoke-WebRequest example.com",
+                SafetyRule::DangerousCommand,
+            ),
+            ("rm notes.txt", SafetyRule::DangerousCommand),
+            (
+                "Get-Content $env:USERPROFILE",
+                SafetyRule::ShellMetacharacter,
+            ),
+            ("Get-ChildItem ..\\..\\Windows", SafetyRule::PathTraversal),
+            ("Get-ChildItem \\\\server\\share", SafetyRule::PathTraversal),
+        ] {
+            let report = powershell.validate(command);
+            assert!(
+                report.violations.iter().any(|v| v.rule == rule),
+                "{}: {:?}",
+                command,
+                report.violations
+            );
+        }
+
+        let cmd = SafetyPolicy::default().with_target(TargetShell::Cmd);
+        assert!(cmd.is_safe("dir /s /b C:\\Projects"));
+        assert!(!cmd.is_safe("DEL /q C:\\Temp"));
+        assert!(!cmd.is_safe("echo %PATH%"));
+        assert!(!cmd.is_safe("dir & format c:"));
+        assert!(cmd.grammar().allows_prefix("tasklist"));
+
+        // zsh keeps the POSIX rules; bash is still the default
+        let zsh = SafetyPolicy::default().with_target(TargetShell::Zsh);
+        assert!(zsh.is_safe("ls -la"));
+        assert!(!zsh.is_safe("dir C:\\Projects"));
+        assert_eq!(SafetyPolicy::default().target(), TargetShell::Bash);
+    }
+
+    #[test]
+    fn test_validate_all_keeps_order() {
+        let policy = SafetyPolicy::default();
+        let commands: Vec<String> = (0..VALIDATION_CHUNK * 2 + 1)
+            .map(|i| match i % 3 {
+                0 => format!("ls dir{}", i),
+                1 => format!("rm -rf dir{}", i),
+                _ => format!("cat ../dir{}", i),
+            })
+            .collect();
+        let reports = policy.validate_all(&commands);
+        assert_eq!(reports.len(), commands.len());
+        for (command, report) in commands.iter().zip(&reports) {
+            assert_eq!(*report, policy.validate(command));
+        }
+        assert!(policy.validate_all::<&str>(&[]).is_empty());
+    }
 }
@@ -0,0 +1,347 @@
+//! Error codes shared by the Eidos crates
+//!
+//! Each crate keeps its own error enum (`ChatError`, `TranslateError`, ...) and
+//! implements [`EidosError`] for it, mapping every variant to an [`ErrorCode`]. The
+//! CLI turns the code into its exit status and, with `--output json`, reports it
+//! next to the message, so scripts can tell a missing API key from a rejected
+//! command without parsing English text.
+//!
+//! Where errors of several crates meet, such as the bridge between the CLI and
+//! its handlers, they travel as an [`Error`]: every `EidosError` converts into it
+//! with `?`, keeping its code and whether retrying may help.
+//!
+//! # Examples
+//!
+//! ```
+//! use lib_errors::{EidosError, ErrorCode, ErrorReport};
+//!
+//! #[derive(Debug)]
+//! struct MissingKey;
+//!
+//! impl std::fmt::Display for MissingKey {
+//!     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+//!         f.write_str("OPENAI_API_KEY is not set")
+//!     }
+//! }
+//!
+//! impl std::error::Error for MissingKey {}
+//!
+//! impl EidosError for MissingKey {
+//!     fn code(&self) -> ErrorCode {
+//!         ErrorCode::NotConfigured
+//!     }
+//! }
+//!
+//! let report = MissingKey.report();
+//! assert_eq!(report.code, ErrorCode::NotConfigured);
+//! assert_eq!(report.exit_code, 69);
+//! assert_eq!(report.to_string(), "OPENAI_API_KEY is not set");
+//!
+//! let routed: lib_errors::Error = MissingKey.into();
+//! assert_eq!(routed.code(), ErrorCode::NotConfigured);
+//! assert!(!routed.is_retryable());
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// What kind of failure an error is, independent of the crate it comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request itself is malformed: empty, too long, unparseable
+    InvalidInput,
+    /// eidos.toml or an environment variable holds an invalid value
+    Config,
+    /// A subsystem has nothing to run on: no model, API key or translator
+    NotConfigured,
+    /// The model or tokenizer failed to load or run
+    Model,
+    /// The generated command was rejected by the safety policy
+    Unsafe,
+    /// A remote service could not be reached or answered with an error
+    Network,
+    /// A remote service rejected the credentials
+    Auth,
+    /// A remote service is throttling requests
+    RateLimit,
+    /// The time budget (`--timeout`) ran out
+    Timeout,
+    /// The provider or build does not support the operation
+    Unsupported,
+    /// Reading or writing a local file failed
+    Io,
+    /// Anything else; a bug if it is not one of the above
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::Config => "config",
+            ErrorCode::NotConfigured => "not_configured",
+            ErrorCode::Model => "model",
+            ErrorCode::Unsafe => "unsafe",
+            ErrorCode::Network => "network",
+            ErrorCode::Auth => "auth",
+            ErrorCode::RateLimit => "rate_limit",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Unsupported => "unsupported",
+            ErrorCode::Io => "io",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// Process exit status for the code, following BSD `sysexits.h` where one fits
+    ///
+    /// A rejected command exits with 3, so scripts can tell it apart from every
+    /// other failure (1 is reserved for internal errors, 2 for usage errors).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorCode::Unsafe => 3,
+            ErrorCode::InvalidInput => 65, // EX_DATAERR
+            ErrorCode::NotConfigured | ErrorCode::Unsupported => 69, // EX_UNAVAILABLE
+            ErrorCode::Model => 70,        // EX_SOFTWARE
+            ErrorCode::Io => 74,           // EX_IOERR
+            ErrorCode::Network | ErrorCode::RateLimit | ErrorCode::Timeout => 75, // EX_TEMPFAIL
+            ErrorCode::Auth => 77,         // EX_NOPERM
+            ErrorCode::Config => 78,       // EX_CONFIG
+            ErrorCode::Internal => 1,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later: the remote
+    /// service was unreachable, throttling or slow
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Network | ErrorCode::RateLimit | ErrorCode::Timeout
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error that knows its [`ErrorCode`]
+pub trait EidosError: std::error::Error {
+    fn code(&self) -> ErrorCode;
+
+    /// Whether the same request may succeed if sent again later
+    fn is_retryable(&self) -> bool {
+        self.code().is_retryable()
+    }
+
+    /// Message, code and exit status, ready to print or serialize
+    fn report(&self) -> ErrorReport {
+        ErrorReport {
+            retryable: self.is_retryable(),
+            ..ErrorReport::new(self.code(), self.to_string())
+        }
+    }
+}
+
+/// An error from any crate, as it crosses a boundary shared by several
+///
+/// Any [`EidosError`] converts into it, keeping the original as its
+/// [`source`](Error::source); a plain message becomes [`ErrorCode::Internal`].
+/// It does not implement `std::error::Error` itself, which is what lets that
+/// conversion cover every error type.
+///
+/// In JSON it is `{"error": ..., "code": ..., "retryable": ...}`, the shape of an
+/// [`ErrorReport`]; a bare `{"error": ...}` reads as an internal error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Error {
+    #[serde(rename = "error")]
+    message: String,
+    #[serde(default = "internal")]
+    code: ErrorCode,
+    #[serde(default)]
+    retryable: bool,
+    #[serde(skip)]
+    source: Option<Arc<dyn std::error::Error + Send + Sync>>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code,
+            retryable: code.is_retryable(),
+            source: None,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Whether the same request may succeed if sent again later
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
+    /// The error this one was converted from; `None` for plain messages and
+    /// errors that came over the wire
+    pub fn source(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        self.source.as_deref()
+    }
+
+    /// Message, code and exit status, ready to print or serialize
+    pub fn report(&self) -> ErrorReport {
+        ErrorReport {
+            retryable: self.retryable,
+            ..ErrorReport::new(self.code, self.message.clone())
+        }
+    }
+}
+
+fn internal() -> ErrorCode {
+    ErrorCode::Internal
+}
+
+impl<E: EidosError + Send + Sync + 'static> From<E> for Error {
+    fn from(e: E) -> Self {
+        Self {
+            message: e.to_string(),
+            code: e.code(),
+            retryable: e.is_retryable(),
+            source: Some(Arc::new(e)),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Errors are equal when they report the same thing, wherever they came from
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+            && self.code == other.code
+            && self.retryable == other.retryable
+    }
+}
+
+impl Eq for Error {}
+
+/// A failure as reported to the user: `{"error": ..., "code": ..., "exit_code": ...}`
+/// in JSON, the message alone as text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    #[serde(rename = "error")]
+    pub message: String,
+    pub code: ErrorCode,
+    pub exit_code: i32,
+    pub retryable: bool,
+}
+
+impl ErrorReport {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code,
+            exit_code: code.exit_code(),
+            retryable: code.is_retryable(),
+        }
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct_for_scripts() {
+        assert_eq!(ErrorCode::Unsafe.exit_code(), 3);
+        assert_eq!(ErrorCode::Config.exit_code(), 78);
+        assert_ne!(ErrorCode::Auth.exit_code(), ErrorCode::Network.exit_code());
+        assert_eq!(
+            ErrorCode::Timeout.exit_code(),
+            ErrorCode::RateLimit.exit_code()
+        );
+    }
+
+    #[test]
+    fn test_report_serializes_code_as_snake_case() {
+        let report = ErrorReport::new(ErrorCode::RateLimit, "Rate limit exceeded");
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["error"], "Rate limit exceeded");
+        assert_eq!(json["code"], "rate_limit");
+        assert_eq!(json["exit_code"], 75);
+        assert_eq!(json["retryable"], true);
+    }
+
+    #[derive(Debug)]
+    struct Throttled;
+
+    impl fmt::Display for Throttled {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Rate limit exceeded")
+        }
+    }
+
+    impl std::error::Error for Throttled {}
+
+    impl EidosError for Throttled {
+        fn code(&self) -> ErrorCode {
+            ErrorCode::RateLimit
+        }
+    }
+
+    #[test]
+    fn test_error_keeps_code_retryability_and_source() {
+        let e = Error::from(Throttled);
+        assert_eq!(e.code(), ErrorCode::RateLimit);
+        assert!(e.is_retryable());
+        assert_eq!(e.to_string(), "Rate limit exceeded");
+        assert!(e.source().unwrap().is::<Throttled>());
+        assert_eq!(e.report(), Throttled.report());
+
+        let plain = Error::from("No handler registered");
+        assert_eq!(plain.code(), ErrorCode::Internal);
+        assert!(!plain.is_retryable());
+
+        // The daemon sends errors as JSON; the source stays behind
+        let json = serde_json::to_string(&e).unwrap();
+        assert_eq!(
+            json,
+            r#"{"error":"Rate limit exceeded","code":"rate_limit","retryable":true}"#
+        );
+        let received: Error = serde_json::from_str(&json).unwrap();
+        assert_eq!(received, e);
+        assert!(received.source().is_none());
+        let bare: Error = serde_json::from_str(r#"{"error":"Daemon stopped"}"#).unwrap();
+        assert_eq!(bare, Error::from("Daemon stopped"));
+    }
+}
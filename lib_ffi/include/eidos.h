@@ -0,0 +1,38 @@
+/*
+ * eidos.h - C interface to the Eidos request bridge
+ *
+ * Link against libeidos_ffi (built with `cargo build --profile release-ffi -p lib_ffi`).
+ * Every string returned by the library is a NUL-terminated UTF-8 JSON object,
+ * {"ok":{...}} or {"error":"...","code":"...","retryable":false}, and must be
+ * released with eidos_free_string().
+ * All functions may be called from any thread, except one already running a
+ * tokio runtime (a Rust async task), which gets an "unsupported" error.
+ */
+
+#ifndef EIDOS_H
+#define EIDOS_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Route `input` to the handler for `request` ("core", "chat" or "translate").
+ * Never returns NULL.
+ */
+char *eidos_route(const char *request, const char *input);
+
+/*
+ * eidos_route() with payload options given as a JSON object of strings,
+ * e.g. {"explain":"true","timeout_ms":"5000"}. `options` may be NULL.
+ */
+char *eidos_route_with_options(const char *request, const char *input, const char *options);
+
+/* Release a string returned by this library. NULL is ignored. */
+void eidos_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EIDOS_H */
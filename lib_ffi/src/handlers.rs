@@ -0,0 +1,239 @@
+// lib_ffi/src/handlers.rs
+// Bridge handlers for embedders
+//
+// The CLI's handlers print diagnostics and read eidos.toml; these return every
+// failure in the reply instead and read only the environment.
+
+#[allow(unused_imports)]
+use lib_bridge::{Bridge, Error, ErrorCode, Payload, Request, Response};
+
+/// Bridge with a handler for every subsystem compiled into the library
+pub(crate) fn bridge() -> Bridge {
+    #[allow(unused_mut)]
+    let mut bridge = Bridge::new();
+
+    #[cfg(feature = "core")]
+    bridge.register_async(Request::Core, |payload: Payload| async move {
+        tokio::task::spawn_blocking(move || core::generate(&payload))
+            .await
+            .map_err(|e| format!("Command generation panicked: {}", e))?
+    });
+
+    #[cfg(feature = "chat")]
+    bridge.register_async(Request::Chat, |payload: Payload| async move {
+        let mut options = lib_chat::ChatOptions::default();
+        if let Some(deadline) = payload.deadline() {
+            options = options.with_deadline(deadline);
+        }
+        let mut chat = lib_chat::Chat::new().with_options(options);
+        if !chat.is_configured() {
+            return Err(Error::new(
+                ErrorCode::NotConfigured,
+                "No chat provider configured (set OPENAI_API_KEY, ANTHROPIC_API_KEY, \
+                 GEMINI_API_KEY, OLLAMA_HOST or LLM_API_URL)",
+            ));
+        }
+        let reply = chat.send_async(&payload.input).await?;
+        Ok(Response::ChatReply {
+            reply,
+            sources: Vec::new(),
+        })
+    });
+
+    #[cfg(feature = "translate")]
+    bridge.register_async(Request::Translate, |payload: Payload| async move {
+        use lib_bridge::pipeline::{SOURCE_LANG_OPTION, TARGET_LANG_OPTION};
+
+        let mut translate = lib_translate::Translate::new();
+        if let Some(deadline) = payload.deadline() {
+            translate = translate.with_deadline(deadline);
+        }
+        if payload.flag("localize") {
+            translate = translate.with_localizer(lib_translate::localize::Localizer::new());
+        }
+        let text = payload.input.as_str();
+        let target = payload.option(TARGET_LANG_OPTION).unwrap_or("en");
+        let result = match payload.option(SOURCE_LANG_OPTION) {
+            Some(source) => translate.translate_async(text, source, target).await,
+            None => translate.detect_and_translate_async(text, target).await,
+        }?;
+        Ok(Response::Translation {
+            source_lang: result.source_lang,
+            target_lang: result.target_lang,
+            original: result.original,
+            translated: result.translated,
+            was_translated: result.was_translated,
+            confidence: result.confidence,
+        })
+    });
+
+    bridge
+}
+
+#[cfg(feature = "core")]
+mod core {
+    use lib_bridge::{Error, ErrorCode, Payload, Response};
+    use lib_core::{
+        BackendKind, DeviceSpec, InferenceBackend, PresetRegistry, SafetyPolicy, TargetShell,
+    };
+    use once_cell::sync::OnceCell;
+    use std::env;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    /// Loaded on the first request; a failed load is retried by the next one
+    static MODEL: OnceCell<Arc<dyn InferenceBackend>> = OnceCell::new();
+
+    /// Generate a command for `payload` and validate it
+    pub(super) fn generate(payload: &Payload) -> Result<Response, Error> {
+        let policy = match env::var("EIDOS_SAFETY_LEVEL") {
+            Ok(level) => SafetyPolicy::new(level.parse().map_err(config_error)?),
+            Err(_) => SafetyPolicy::default(),
+        };
+        let policy = policy.with_target(target_shell()?);
+        let backend = MODEL.get_or_try_init(load_model)?;
+        generate_with(backend.as_ref(), &policy, payload)
+    }
+
+    /// Generate a command for `payload` with `backend` and validate it with `policy`
+    fn generate_with(
+        backend: &dyn InferenceBackend,
+        policy: &SafetyPolicy,
+        payload: &Payload,
+    ) -> Result<Response, Error> {
+        let command = backend
+            .generate(&payload.input, None)
+            .map_err(|e| Error::new(ErrorCode::Model, e.to_string()))?;
+        let report = policy.validate(&command);
+        if !report.is_safe() {
+            let reasons: Vec<String> = report.violations.iter().map(|v| v.to_string()).collect();
+            return Err(Error::new(
+                ErrorCode::Unsafe,
+                format!(
+                    "Generated command failed safety validation: {}",
+                    reasons.join("; ")
+                ),
+            ));
+        }
+
+        let out_of_time = payload.deadline().is_some_and(|d| Instant::now() >= d);
+        let explanation = if payload.flag("explain") && !out_of_time {
+            backend.explain(&command).ok()
+        } else {
+            None
+        };
+        Ok(Response::CommandGenerated {
+            prompt: payload.input.clone(),
+            command,
+            explanation,
+        })
+    }
+
+    fn load_model() -> Result<Arc<dyn InferenceBackend>, Error> {
+        let var = |name: &str| {
+            env::var(name)
+                .map_err(|_| Error::new(ErrorCode::NotConfigured, format!("{} is not set", name)))
+        };
+        let model_path = var("EIDOS_MODEL_PATH")?;
+        let tokenizer_path = var("EIDOS_TOKENIZER_PATH")?;
+        let backend = match env::var("EIDOS_BACKEND") {
+            Ok(backend) => backend.parse().map_err(config_error)?,
+            Err(_) => BackendKind::from_path(&model_path),
+        };
+        let device: DeviceSpec = match env::var("EIDOS_DEVICE") {
+            Ok(device) => device.parse().map_err(config_error)?,
+            Err(_) => DeviceSpec::default(),
+        };
+        let system_prompt =
+            target_shell()?.system_prompt(env::var("EIDOS_SYSTEM_PROMPT").ok().as_deref());
+        let registry = PresetRegistry::builtin();
+        let preset = registry.find(&model_path);
+
+        match backend {
+            #[cfg(feature = "onnx")]
+            BackendKind::Onnx => {
+                let _ = device;
+                let mut core = lib_core::Core::new(&model_path, &tokenizer_path).map_err(|e| {
+                    Error::new(ErrorCode::Model, format!("Failed to load model: {}", e))
+                })?;
+                if let Some(prompt) = &system_prompt {
+                    core = core.with_system_prompt(prompt);
+                }
+                if let Some(preset) = preset {
+                    core = core.with_preset(preset);
+                }
+                Ok(Arc::new(core))
+            }
+            #[cfg(feature = "gguf")]
+            BackendKind::Gguf => {
+                let (device, _) = lib_core::device::select_device(device);
+                let mut llm =
+                    lib_core::QuantizedLlm::new_with_device(&model_path, &tokenizer_path, device)
+                        .map_err(|e| {
+                        Error::new(ErrorCode::Model, format!("Failed to load model: {}", e))
+                    })?;
+                if let Some(prompt) = &system_prompt {
+                    llm = llm.with_system_prompt(prompt);
+                }
+                if let Some(preset) = preset {
+                    llm = llm.with_preset(preset);
+                }
+                Ok(Arc::new(llm))
+            }
+            #[allow(unreachable_patterns)]
+            kind => {
+                let _ = (device, system_prompt, preset, tokenizer_path);
+                Err(Error::new(
+                    ErrorCode::Unsupported,
+                    format!(
+                        "The {} backend is not available: the library was built without the `{}` feature",
+                        kind, kind
+                    ),
+                ))
+            }
+        }
+    }
+
+    /// Shell named by `EIDOS_TARGET_SHELL`, bash by default
+    fn target_shell() -> Result<TargetShell, Error> {
+        match env::var("EIDOS_TARGET_SHELL") {
+            Ok(shell) => shell.parse().map_err(config_error),
+            Err(_) => Ok(TargetShell::default()),
+        }
+    }
+
+    /// An environment variable holding an invalid value
+    fn config_error(e: impl ToString) -> Error {
+        Error::new(ErrorCode::Config, e.to_string())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use lib_core::MockBackend;
+
+        #[test]
+        fn test_generate_with_mock_backend() {
+            let backend = MockBackend::new()
+                .with_command("list files", "ls -la")
+                .with_command("wipe the disk", "rm -rf /");
+            let policy = SafetyPolicy::default();
+
+            let payload = Payload::new("list files").with_option("explain", "true");
+            assert_eq!(
+                generate_with(&backend, &policy, &payload),
+                Ok(Response::CommandGenerated {
+                    prompt: "list files".to_string(),
+                    command: "ls -la".to_string(),
+                    explanation: Some("Runs `ls -la`".to_string()),
+                })
+            );
+
+            let err = generate_with(&backend, &policy, &Payload::new("wipe the disk")).unwrap_err();
+            assert!(err
+                .message()
+                .starts_with("Generated command failed safety validation"));
+            assert_eq!(err.code(), ErrorCode::Unsafe);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! C ABI over the Eidos request bridge
+//!
+//! Lets GUI shells, Python scripts (ctypes/cffi) and other languages route requests
+//! through Eidos in-process instead of spawning the CLI for every call. The model is
+//! loaded on the first command generation request and kept for the life of the
+//! process.
+//!
+//! [`eidos_route`] takes the request type (`core`, `chat` or `translate`) and the
+//! input as NUL-terminated UTF-8 and returns one JSON object, in the format of the
+//! daemon's replies:
+//!
+//! ```text
+//! {"ok":{"command_generated":{"prompt":"list files","command":"ls -la","explanation":null}}}
+//! {"ok":{"translation":{"source_lang":"fr","target_lang":"en",...}}}
+//! {"error":"Generated command failed safety validation: ...","code":"unsafe","retryable":false}
+//! ```
+//!
+//! `code` is one of the error codes of the CLI's JSON output (`not_configured`,
+//! `network`, `unsafe`, ...), and `retryable` says whether the same request may
+//! succeed later.
+//!
+//! Every string returned must be released with [`eidos_free_string`]. Functions may
+//! be called from any thread but one driving a tokio runtime, where blocking on the
+//! shared runtime would panic; such calls get an `unsupported` error.
+//!
+//! Panics are caught and returned as `internal` errors, which needs the unwinding
+//! `release-ffi` profile: the workspace `release` profile aborts on panic.
+//!
+//! Handlers are configured through the environment variables the CLI reads when
+//! there is no eidos.toml: `EIDOS_MODEL_PATH`, `EIDOS_TOKENIZER_PATH`,
+//! `EIDOS_BACKEND`, `EIDOS_DEVICE`, `EIDOS_SYSTEM_PROMPT`, `EIDOS_SAFETY_LEVEL` and
+//! `EIDOS_TARGET_SHELL` for command generation, the chat provider keys for chat and `LIBRETRANSLATE_URL`
+//! for translation (without it, text is translated offline).
+
+mod handlers;
+
+use lib_bridge::{Error, ErrorCode, Payload, Request, Response};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use tokio::runtime::Runtime;
+
+/// Runtime shared by every call; handlers run on its worker threads
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    Runtime::new().expect(
+        "FATAL: Failed to create tokio runtime. \
+         This likely indicates system resource exhaustion.",
+    )
+});
+
+thread_local! {
+    /// Handlers are not `Sync`, so each calling thread gets its own bridge; the
+    /// loaded model is shared between them
+    static BRIDGE: lib_bridge::Bridge = handlers::bridge();
+}
+
+/// Reply returned to C callers (the daemon's `DaemonReply` wire format)
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Reply {
+    Ok { ok: Response },
+    Error(Error),
+}
+
+/// Route `input` to the handler for `request` and return the reply as JSON
+///
+/// # Safety
+///
+/// `request` and `input` must be NULL or valid NUL-terminated strings. The result
+/// must be released with [`eidos_free_string`].
+#[no_mangle]
+pub unsafe extern "C" fn eidos_route(request: *const c_char, input: *const c_char) -> *mut c_char {
+    eidos_route_with_options(request, input, std::ptr::null())
+}
+
+/// [`eidos_route`] with payload options, given as a JSON object of strings such as
+/// `{"explain":"true","timeout_ms":"5000"}`; `options` may be NULL
+///
+/// # Safety
+///
+/// As for [`eidos_route`]; `options` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn eidos_route_with_options(
+    request: *const c_char,
+    input: *const c_char,
+    options: *const c_char,
+) -> *mut c_char {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| route(request, input, options)))
+        .unwrap_or_else(|_| Err("Eidos panicked while handling the request".into()));
+    let reply = match result {
+        Ok(response) => Reply::Ok { ok: response },
+        Err(e) => Reply::Error(e),
+    };
+    // serde_json escapes NUL, so the JSON never contains one
+    let json = serde_json::to_string(&reply).expect("replies always serialize");
+    CString::new(json)
+        .expect("JSON has no interior NUL")
+        .into_raw()
+}
+
+/// Release a string returned by this library; NULL is ignored
+///
+/// # Safety
+///
+/// `s` must come from [`eidos_route`] or [`eidos_route_with_options`] and must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn eidos_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn route(
+    request: *const c_char,
+    input: *const c_char,
+    options: *const c_char,
+) -> Result<Response, Error> {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Err(Error::new(
+            ErrorCode::Unsupported,
+            "eidos_route cannot be called from within a tokio runtime",
+        ));
+    }
+    let invalid = |e: String| Error::new(ErrorCode::InvalidInput, e);
+    let request: Request = str_arg(request, "request")?.parse().map_err(invalid)?;
+    let mut payload = Payload::new(str_arg(input, "input")?);
+    if !options.is_null() {
+        payload.options = serde_json::from_str(str_arg(options, "options")?)
+            .map_err(|e| invalid(format!("options must be a JSON object of strings: {}", e)))?;
+    }
+    BRIDGE.with(|bridge| RUNTIME.block_on(bridge.route_async(request, payload)))
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Error> {
+    let invalid = |e: String| Error::new(ErrorCode::InvalidInput, e);
+    if ptr.is_null() {
+        return Err(invalid(format!("{} is NULL", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| invalid(format!("{} is not valid UTF-8", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn call(request: &str, input: &str, options: Option<&str>) -> Value {
+        let request = CString::new(request).unwrap();
+        let input = CString::new(input).unwrap();
+        let options = options.map(|o| CString::new(o).unwrap());
+        unsafe {
+            let reply = eidos_route_with_options(
+                request.as_ptr(),
+                input.as_ptr(),
+                options.as_ref().map_or(std::ptr::null(), |o| o.as_ptr()),
+            );
+            let json = CStr::from_ptr(reply).to_str().unwrap().to_string();
+            eidos_free_string(reply);
+            serde_json::from_str(&json).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_errors() {
+        let reply = call("search", "hello", None);
+        assert!(reply["error"]
+            .as_str()
+            .unwrap()
+            .contains("Unknown request type"));
+
+        let reply = call("core", "list files", Some("[1, 2]"));
+        assert!(reply["error"].as_str().unwrap().contains("JSON object"));
+
+        let reply = unsafe {
+            let request = CString::new("core").unwrap();
+            let reply = eidos_route(request.as_ptr(), std::ptr::null());
+            let json: Value =
+                serde_json::from_str(CStr::from_ptr(reply).to_str().unwrap()).unwrap();
+            eidos_free_string(reply);
+            json
+        };
+        assert_eq!(
+            reply,
+            json!({"error": "input is NULL", "code": "invalid_input", "retryable": false})
+        );
+        unsafe { eidos_free_string(std::ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_calls_from_a_runtime_are_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let reply = runtime.block_on(async { call("translate", "Bonjour", None) });
+        assert_eq!(reply["code"], "unsupported");
+        assert!(reply["error"].as_str().unwrap().contains("tokio runtime"));
+    }
+
+    #[test]
+    #[cfg(feature = "translate")]
+    fn test_route_translation() {
+        // English input needs no translation service
+        let reply = call(
+            "translate",
+            "This is English text that is long enough to be detected properly.",
+            None,
+        );
+        let translation = &reply["ok"]["translation"];
+        assert_eq!(translation["source_lang"], "en");
+        assert_eq!(translation["was_translated"], false);
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_core_requires_model() {
+        if std::env::var_os("EIDOS_MODEL_PATH").is_some() {
+            return;
+        }
+        let reply = call("core", "list files", None);
+        assert!(reply["error"]
+            .as_str()
+            .unwrap()
+            .contains("EIDOS_MODEL_PATH"));
+        assert_eq!(reply["code"], "not_configured");
+    }
+}
@@ -14,6 +14,12 @@ fn get_detector() -> &'static LanguageDetector {
     })
 }
 
+/// Lowest confidence in the detected language that is acted on by default
+///
+/// Short phrases like "Bonjour le monde" score around 0.4; a single word often
+/// scores below 0.25 and is as likely as not to be guessed wrong.
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.3;
+
 /// Detect the language of the given text
 pub fn detect_language(text: &str) -> Result<Language> {
     let detector = get_detector();
@@ -29,6 +35,34 @@ pub fn detect_language_code(text: &str) -> Result<String> {
     Ok(language.iso_code_639_1().to_string().to_lowercase())
 }
 
+/// Detect the language code of `text` together with the detector's confidence in
+/// it (0.0 to 1.0)
+///
+/// Unlike [`detect_language_code`], `min_confidence` alone decides whether the
+/// most likely language is good enough; below it this fails with
+/// [`TranslateError::LowConfidence`] naming the best guess.
+pub fn detect_language_code_with_confidence(
+    text: &str,
+    min_confidence: f64,
+) -> Result<(String, f64)> {
+    let (language, confidence) = get_detector()
+        .compute_language_confidence_values(text)
+        .into_iter()
+        .next()
+        // Text without letters scores zero for every language
+        .filter(|(_, confidence)| *confidence > 0.0)
+        .ok_or_else(|| TranslateError::DetectionError("Could not detect language".to_string()))?;
+    let lang = language.iso_code_639_1().to_string().to_lowercase();
+    if confidence < min_confidence {
+        return Err(TranslateError::LowConfidence {
+            lang,
+            confidence,
+            min_confidence,
+        });
+    }
+    Ok((lang, confidence))
+}
+
 /// Check if text is in English
 pub fn is_english(text: &str) -> bool {
     detect_language(text)
@@ -83,6 +117,26 @@ mod tests {
         assert_eq!(code, "es");
     }
 
+    #[test]
+    fn test_detect_with_minimum_confidence() {
+        let (code, confidence) =
+            detect_language_code_with_confidence("zeige die größten Dateien", 0.3).unwrap();
+        assert_eq!(code, "de");
+        assert!(confidence > 0.9);
+
+        // Spanish, but only just ahead of the alternatives
+        assert!(detect_language_code_with_confidence("borra archivos", 0.3).is_ok());
+        match detect_language_code_with_confidence("borra archivos", 0.9) {
+            Err(TranslateError::LowConfidence { lang, .. }) => assert_eq!(lang, "es"),
+            other => panic!("expected LowConfidence, got {:?}", other),
+        }
+        // Too short for any language to stand out
+        assert!(matches!(
+            detect_language_code_with_confidence("ciao", 0.3),
+            Err(TranslateError::LowConfidence { .. })
+        ));
+    }
+
     #[test]
     fn test_is_english() {
         assert!(is_english(
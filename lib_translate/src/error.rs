@@ -1,4 +1,5 @@
 // lib_translate/src/error.rs
+use lib_errors::{EidosError, ErrorCode};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,6 +13,17 @@ pub enum TranslateError {
     #[error("Language detection failed: {0}")]
     DetectionError(String),
 
+    #[error(
+        "Not sure which language this is (best guess {lang} at {:.0}% confidence, below {:.0}%)",
+        confidence * 100.0,
+        min_confidence * 100.0
+    )]
+    LowConfidence {
+        lang: String,
+        confidence: f64,
+        min_confidence: f64,
+    },
+
     #[error("Translation failed: {0}")]
     TranslationFailed(String),
 
@@ -26,6 +38,45 @@ pub enum TranslateError {
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
+
+    #[error("Request deadline exceeded")]
+    DeadlineExceeded,
+}
+
+impl EidosError for TranslateError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            TranslateError::RequestError(e) if e.is_timeout() => ErrorCode::Timeout,
+            TranslateError::RequestError(_) | TranslateError::ApiError(_) => ErrorCode::Network,
+            TranslateError::JsonError(_) | TranslateError::TranslationFailed(_) => {
+                ErrorCode::Network
+            }
+            TranslateError::DetectionError(_) | TranslateError::LowConfidence { .. } => {
+                ErrorCode::InvalidInput
+            }
+            TranslateError::UnsupportedLanguage(_) => ErrorCode::Unsupported,
+            TranslateError::NoTranslatorError => ErrorCode::NotConfigured,
+            TranslateError::ConfigError(_) => ErrorCode::Config,
+            TranslateError::DeadlineExceeded => ErrorCode::Timeout,
+        }
+    }
+}
+
+impl TranslateError {
+    /// Whether the service could not be reached at all (connection refused,
+    /// connect timeout), as opposed to answering with an error
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, TranslateError::RequestError(e) if e.is_connect())
+    }
+}
+
+impl From<lib_cassette::CassetteError> for TranslateError {
+    fn from(e: lib_cassette::CassetteError) -> Self {
+        match e {
+            lib_cassette::CassetteError::Request(e) => TranslateError::RequestError(e),
+            e => TranslateError::ApiError(e.to_string()),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, TranslateError>;
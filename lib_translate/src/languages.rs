@@ -0,0 +1,126 @@
+// Display names for language codes
+//
+// Detection and translation work with ISO 639-1 codes; people read names. The
+// table below is a small subset of the CLDR language display names: each
+// language's English name and its name in the language itself ("Turkish" /
+// "Türkçe"), for the languages the detector and the translation services
+// commonly handle. Region subtags (`pt-BR`) are looked up by their base language.
+
+/// English and native names of a language
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LanguageName {
+    /// ISO 639-1 code
+    pub code: &'static str,
+    pub english: &'static str,
+    /// The name in the language itself
+    pub native: &'static str,
+}
+
+impl LanguageName {
+    /// `Turkish / Türkçe`, or just `English` when both names agree
+    pub fn display(&self) -> String {
+        if self.english == self.native {
+            self.english.to_string()
+        } else {
+            format!("{} / {}", self.english, self.native)
+        }
+    }
+}
+
+const fn name(code: &'static str, english: &'static str, native: &'static str) -> LanguageName {
+    LanguageName {
+        code,
+        english,
+        native,
+    }
+}
+
+/// CLDR display names, sorted by code
+const LANGUAGES: [LanguageName; 40] = [
+    name("ar", "Arabic", "العربية"),
+    name("bg", "Bulgarian", "български"),
+    name("bn", "Bangla", "বাংলা"),
+    name("ca", "Catalan", "català"),
+    name("cs", "Czech", "čeština"),
+    name("da", "Danish", "dansk"),
+    name("de", "German", "Deutsch"),
+    name("el", "Greek", "Ελληνικά"),
+    name("en", "English", "English"),
+    name("es", "Spanish", "español"),
+    name("et", "Estonian", "eesti"),
+    name("fa", "Persian", "فارسی"),
+    name("fi", "Finnish", "suomi"),
+    name("fr", "French", "français"),
+    name("ga", "Irish", "Gaeilge"),
+    name("he", "Hebrew", "עברית"),
+    name("hi", "Hindi", "हिन्दी"),
+    name("hr", "Croatian", "hrvatski"),
+    name("hu", "Hungarian", "magyar"),
+    name("id", "Indonesian", "Indonesia"),
+    name("it", "Italian", "italiano"),
+    name("ja", "Japanese", "日本語"),
+    name("ko", "Korean", "한국어"),
+    name("lt", "Lithuanian", "lietuvių"),
+    name("lv", "Latvian", "latviešu"),
+    name("ms", "Malay", "Melayu"),
+    name("nb", "Norwegian Bokmål", "norsk bokmål"),
+    name("nl", "Dutch", "Nederlands"),
+    name("pl", "Polish", "polski"),
+    name("pt", "Portuguese", "português"),
+    name("ro", "Romanian", "română"),
+    name("ru", "Russian", "русский"),
+    name("sk", "Slovak", "slovenčina"),
+    name("sl", "Slovenian", "slovenščina"),
+    name("sv", "Swedish", "svenska"),
+    name("th", "Thai", "ไทย"),
+    name("tr", "Turkish", "Türkçe"),
+    name("uk", "Ukrainian", "українська"),
+    name("vi", "Vietnamese", "Tiếng Việt"),
+    name("zh", "Chinese", "中文"),
+];
+
+/// Names of the language `code` (`tr`, `pt-BR`, `ZH_hans`), if it is in the table
+pub fn language_name(code: &str) -> Option<LanguageName> {
+    let base = code.split(['-', '_']).next().unwrap_or(code).to_lowercase();
+    // Norwegian is detected as `nb`, but services and users also say `no`
+    let base = if base == "no" { "nb".to_string() } else { base };
+    LANGUAGES
+        .binary_search_by(|l| l.code.cmp(base.as_str()))
+        .ok()
+        .map(|i| LANGUAGES[i])
+}
+
+/// Display name for `code`: `Turkish / Türkçe`, or the code itself when unknown
+///
+/// # Examples
+///
+/// ```
+/// use lib_translate::languages::display_name;
+///
+/// assert_eq!(display_name("tr"), "Turkish / Türkçe");
+/// assert_eq!(display_name("en"), "English");
+/// assert_eq!(display_name("xx"), "xx");
+/// ```
+pub fn display_name(code: &str) -> String {
+    language_name(code)
+        .map(|l| l.display())
+        .unwrap_or_else(|| code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_is_sorted_for_lookup() {
+        assert!(LANGUAGES.windows(2).all(|w| w[0].code < w[1].code));
+    }
+
+    #[test]
+    fn test_region_subtags_and_aliases() {
+        assert_eq!(language_name("pt-BR").unwrap().english, "Portuguese");
+        assert_eq!(language_name("ZH_hans").unwrap().native, "中文");
+        assert_eq!(display_name("no"), "Norwegian Bokmål / norsk bokmål");
+        assert_eq!(language_name(""), None);
+    }
+}
@@ -1,11 +1,22 @@
 pub mod detector;
 pub mod error;
+pub mod languages;
+pub mod localize;
+pub mod phrasebook;
+pub mod protect;
 pub mod translator;
 
-use crate::detector::{detect_language_code, is_english};
+use crate::detector::{
+    detect_language_code, detect_language_code_with_confidence, is_english, DEFAULT_MIN_CONFIDENCE,
+};
 use crate::error::Result;
+use crate::localize::Localizer;
+use crate::protect::protect;
 use crate::translator::{Translator, TranslatorProvider};
+use futures_util::{StreamExt, TryStreamExt};
 use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 
 /// Global shared tokio runtime for synchronous translation operations
@@ -24,31 +35,105 @@ static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     )
 });
 
+/// Default for [`Translate::with_max_concurrent_requests`]
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
 pub struct Translate {
     translator: Option<Translator>,
+    localizer: Option<Localizer>,
+    min_confidence: f64,
+    max_concurrent_requests: usize,
 }
 
 impl Translate {
     /// Create a new Translate instance with translator from environment
+    ///
+    /// Falls back to offline translation with the bundled phrasebook when no
+    /// service is configured; check [`Translate::is_offline`] to tell the user.
     pub fn new() -> Self {
         let translator = Translator::from_env().ok();
         if translator.is_none() {
-            eprintln!(
-                "Warning: Using mock translator. Set LIBRETRANSLATE_URL for real translation"
-            );
-            // Use mock translator as fallback
             return Self {
-                translator: Translator::new(TranslatorProvider::Mock).ok(),
+                translator: Translator::new(TranslatorProvider::Offline).ok(),
+                localizer: None,
+                min_confidence: DEFAULT_MIN_CONFIDENCE,
+                max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
             };
         }
-        Self { translator }
+        Self {
+            translator,
+            localizer: None,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+        }
+    }
+
+    /// Whether translations are served by the mock fallback provider
+    pub fn is_mock(&self) -> bool {
+        self.translator
+            .as_ref()
+            .map(|t| matches!(t.provider(), TranslatorProvider::Mock))
+            .unwrap_or(false)
+    }
+
+    /// Whether translations are made on this machine (see [`phrasebook`])
+    pub fn is_offline(&self) -> bool {
+        self.translator
+            .as_ref()
+            .map(|t| matches!(t.provider(), TranslatorProvider::Offline))
+            .unwrap_or(false)
     }
 
     /// Create a Translate instance with a specific provider
     pub fn with_provider(provider: TranslatorProvider) -> Result<Self> {
-        Ok(Self {
-            translator: Some(Translator::new(provider)?),
-        })
+        Ok(Self::from_translator(Translator::new(provider)?))
+    }
+
+    /// Create a Translate instance sending requests through `translator`
+    pub fn from_translator(translator: Translator) -> Self {
+        Self {
+            translator: Some(translator),
+            localizer: None,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+        }
+    }
+
+    /// Rewrite numbers and dates in translations for the target language (see
+    /// [`localize`])
+    pub fn with_localizer(mut self, localizer: Localizer) -> Self {
+        self.localizer = Some(localizer);
+        self
+    }
+
+    /// Refuse to translate text whose detected language scores below
+    /// `min_confidence` (0.0 to 1.0, default
+    /// [`DEFAULT_MIN_CONFIDENCE`](detector::DEFAULT_MIN_CONFIDENCE)); name the source
+    /// language with [`Translate::translate_async`] instead
+    pub fn with_min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// Send at most `max` requests at once from
+    /// [`Translate::translate_batch_async`] (default
+    /// [`DEFAULT_MAX_CONCURRENT_REQUESTS`]; at least 1)
+    pub fn with_max_concurrent_requests(mut self, max: usize) -> Self {
+        self.max_concurrent_requests = max.max(1);
+        self
+    }
+
+    /// Give up on translation requests still running at `deadline`
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        if let Some(translator) = &mut self.translator {
+            translator.set_deadline(Some(deadline));
+        }
+        self
+    }
+
+    /// Deadline `timeout` from now
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
     }
 
     /// Detect language and translate if needed
@@ -57,8 +142,17 @@ impl Translate {
         text: &str,
         target_lang: &str,
     ) -> Result<TranslationResult> {
-        // Detect source language
-        let source_lang = detect_language_code(text)?;
+        // Detect source language from the prose around any code
+        let (source_lang, confidence) =
+            match detect_language_code_with_confidence(protect(text).text(), self.min_confidence) {
+                Ok(detected) => detected,
+                // Probably already in the target language: passing it through
+                // untranslated cannot make it worse
+                Err(error::TranslateError::LowConfidence {
+                    lang, confidence, ..
+                }) if lang == target_lang => (lang, confidence),
+                Err(e) => return Err(e),
+            };
 
         // If already in target language, no translation needed
         if source_lang == target_lang {
@@ -68,46 +162,180 @@ impl Translate {
                 source_lang: source_lang.clone(),
                 target_lang: target_lang.to_string(),
                 was_translated: false,
+                confidence: Some(confidence),
             });
         }
 
-        // Translate
-        let translator = self
-            .translator
-            .as_ref()
-            .ok_or_else(|| error::TranslateError::NoTranslatorError)?;
-
-        let translated = translator
-            .translate(text, &source_lang, target_lang)
-            .await?;
+        let translated = self
+            .translate_texts(&[text.to_string()], &source_lang, target_lang)
+            .await?
+            .remove(0);
 
         Ok(TranslationResult {
+            was_translated: translated != text,
             original: text.to_string(),
             translated,
             source_lang,
             target_lang: target_lang.to_string(),
-            was_translated: true,
+            confidence: Some(confidence),
         })
     }
 
-    /// Synchronous wrapper for the main run method
-    /// Returns a TranslationResult if translation was performed, or the original text if it was already in English
-    pub fn run(&self, text: &str) -> Result<TranslationResult> {
-        let lang_code = detect_language_code(text)?;
+    /// Translate `text` between two known languages, skipping detection
+    pub async fn translate_async(
+        &self,
+        text: &str,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<TranslationResult> {
+        let translated = if source_lang == target_lang {
+            text.to_string()
+        } else {
+            self.translate_texts(&[text.to_string()], source_lang, target_lang)
+                .await?
+                .remove(0)
+        };
 
-        if is_english(text) {
-            // Text is already in English, no translation needed
-            Ok(TranslationResult {
-                original: text.to_string(),
-                translated: text.to_string(),
-                source_lang: lang_code,
-                target_lang: "en".to_string(),
+        Ok(TranslationResult {
+            was_translated: translated != text,
+            original: text.to_string(),
+            translated,
+            source_lang: source_lang.to_string(),
+            target_lang: target_lang.to_string(),
+            confidence: None,
+        })
+    }
+
+    /// Translate several lines in one request, keeping them apart
+    ///
+    /// The lines come back untranslated when the service does not return one line
+    /// for each line sent.
+    pub async fn translate_lines_async(
+        &self,
+        lines: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<String>> {
+        let result = self
+            .translate_async(&lines.join("\n"), source_lang, target_lang)
+            .await?;
+        let translated: Vec<String> = result.translated.lines().map(str::to_string).collect();
+        Ok(match translated.len() == lines.len() {
+            true => translated,
+            false => lines.to_vec(),
+        })
+    }
+
+    /// Translate each of `texts` into `target_lang` from the language detected in it
+    ///
+    /// Texts in the same language go out in one batched request, and the requests
+    /// for different languages run concurrently (see
+    /// [`Translate::with_max_concurrent_requests`]). Texts already in the target
+    /// language, or whose language is below the minimum confidence, come back
+    /// unchanged with `was_translated` false.
+    pub async fn translate_batch_async(
+        &self,
+        texts: &[String],
+        target_lang: &str,
+    ) -> Result<Vec<TranslationResult>> {
+        let mut results = Vec::with_capacity(texts.len());
+        // Indexes of the texts to translate, by source language
+        let mut batches: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, text) in texts.iter().enumerate() {
+            let (source_lang, confidence) = match detect_language_code_with_confidence(
+                protect(text).text(),
+                self.min_confidence,
+            ) {
+                Ok((lang, confidence)) => {
+                    if lang != target_lang {
+                        batches.entry(lang.clone()).or_default().push(i);
+                    }
+                    (lang, Some(confidence))
+                }
+                Err(error::TranslateError::LowConfidence {
+                    lang, confidence, ..
+                }) => (lang, Some(confidence)),
+                // Nothing to detect, e.g. only digits
+                Err(_) => (target_lang.to_string(), None),
+            };
+            results.push(TranslationResult {
+                original: text.clone(),
+                translated: text.clone(),
+                source_lang,
+                target_lang: target_lang.to_string(),
                 was_translated: false,
+                confidence,
+            });
+        }
+        if batches.is_empty() {
+            return Ok(results);
+        }
+
+        let requests = batches.into_iter().map(|(source_lang, indexes)| {
+            let batch: Vec<String> = indexes.iter().map(|&i| texts[i].clone()).collect();
+            async move {
+                let translated = self
+                    .translate_texts(&batch, &source_lang, target_lang)
+                    .await?;
+                Ok::<_, error::TranslateError>((indexes, translated))
+            }
+        });
+        let translated: Vec<_> = futures_util::stream::iter(requests)
+            .buffer_unordered(self.max_concurrent_requests)
+            .try_collect()
+            .await?;
+        for (indexes, batch) in translated {
+            for (i, text) in indexes.into_iter().zip(batch) {
+                results[i].was_translated = text != results[i].original;
+                results[i].translated = text;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Translate `texts` in one request, keeping code, paths and other literals
+    /// verbatim (see [`protect`]) and localizing numbers and dates in the rest
+    async fn translate_texts(
+        &self,
+        texts: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<String>> {
+        let translator = self
+            .translator
+            .as_ref()
+            .ok_or(error::TranslateError::NoTranslatorError)?;
+        let protected: Vec<_> = texts.iter().map(|text| protect(text)).collect();
+        let masked: Vec<String> = protected.iter().map(|p| p.text().to_string()).collect();
+        let translated = translator
+            .translate_batch(&masked, source_lang, target_lang)
+            .await?;
+        Ok(protected
+            .iter()
+            .zip(translated)
+            .map(|(protected, text)| {
+                protected.restore(&self.localize(text, source_lang, target_lang))
             })
-        } else {
-            // Use shared runtime for async translation (avoids ~10-50ms overhead)
-            let result = RUNTIME.block_on(self.detect_and_translate_async(text, "en"))?;
-            Ok(result)
+            .collect())
+    }
+
+    /// Translate `text` to English unless it already is (async)
+    /// Returns a TranslationResult if translation was performed, or the original text if it was already in English
+    pub async fn run_async(&self, text: &str) -> Result<TranslationResult> {
+        self.detect_and_translate_async(text, "en").await
+    }
+
+    /// Synchronous wrapper around [`Translate::run_async`]
+    ///
+    /// Uses the shared runtime (avoids ~10-50ms overhead per call).
+    pub fn run(&self, text: &str) -> Result<TranslationResult> {
+        RUNTIME.block_on(self.run_async(text))
+    }
+
+    fn localize(&self, translated: String, source_lang: &str, target_lang: &str) -> String {
+        match &self.localizer {
+            Some(localizer) => localizer.localize(&translated, source_lang, target_lang),
+            None => translated,
         }
     }
 
@@ -135,8 +363,146 @@ pub struct TranslationResult {
     pub translated: String,
     pub source_lang: String,
     pub target_lang: String,
+    /// Whether translation changed the text; false when it was in the target
+    /// language already or the offline phrasebook knew too few of its words
     pub was_translated: bool,
+    /// Confidence in the detected source language (0.0 to 1.0); `None` when the
+    /// caller named it
+    pub confidence: Option<f64>,
 }
 
 // Re-export commonly used types
 pub use error::TranslateError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_translate_batch_groups_by_language() {
+        let translate = Translate::with_provider(TranslatorProvider::Mock).unwrap();
+        let texts: Vec<String> = [
+            "zeige die größten Dateien im Ordner",
+            "This sentence is already written in plain English.",
+            "lösche alle temporären Dateien",
+            "¿dónde están mis archivos?",
+            "",
+        ]
+        .map(String::from)
+        .to_vec();
+        let results = translate.translate_batch_async(&texts, "en").await.unwrap();
+
+        let summary: Vec<(&str, bool)> = results
+            .iter()
+            .map(|r| (r.source_lang.as_str(), r.was_translated))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("de", true),
+                ("en", false),
+                ("de", true),
+                ("es", true),
+                ("en", false)
+            ]
+        );
+        assert_eq!(
+            results[2].translated,
+            "[Translated from de to en] lösche alle temporären Dateien"
+        );
+        assert_eq!(results[1].translated, texts[1]);
+        assert_eq!(results[4].confidence, None);
+    }
+
+    #[tokio::test]
+    async fn test_offline_reports_unknown_text_untranslated() {
+        let translate = Translate::with_provider(TranslatorProvider::Offline).unwrap();
+        let result = translate
+            .translate_async("borra los archivos viejos", "es", "en")
+            .await
+            .unwrap();
+        assert!(result.was_translated);
+        assert_eq!(result.translated, "delete files old");
+
+        let text = "¿Qué tiempo hará mañana en Sevilla?";
+        let result = translate.translate_async(text, "es", "en").await.unwrap();
+        assert!(!result.was_translated);
+        assert_eq!(result.translated, text);
+    }
+
+    #[tokio::test]
+    async fn test_min_confidence() {
+        let translate = Translate::with_provider(TranslatorProvider::Mock).unwrap();
+        let result = translate
+            .detect_and_translate_async("zeige die größten Dateien", "en")
+            .await
+            .unwrap();
+        assert_eq!(result.source_lang, "de");
+        assert!(result.confidence.unwrap() > 0.9);
+
+        let translate = translate.with_min_confidence(0.9);
+        let err = translate
+            .detect_and_translate_async("borra archivos", "en")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TranslateError::LowConfidence { .. }), "{err}");
+        // Naming the language skips detection
+        let result = translate
+            .translate_async("borra archivos", "es", "en")
+            .await
+            .unwrap();
+        assert!(result.was_translated);
+        assert_eq!(result.confidence, None);
+        // A marginal guess of the target language is passed through
+        let result = translate
+            .detect_and_translate_async("borra archivos", "es")
+            .await
+            .unwrap();
+        assert!(!result.was_translated);
+        assert!(result.confidence.unwrap() < 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_code_and_paths_are_not_translated() {
+        let translate = Translate::with_provider(TranslatorProvider::Mock).unwrap();
+        let text = "verschiebe alle Dateien aus ~/Downloads nach /srv/archiv mit `mv -n`";
+        let result = translate
+            .detect_and_translate_async(text, "en")
+            .await
+            .unwrap();
+        assert_eq!(result.source_lang, "de");
+        assert_eq!(
+            result.translated,
+            "[Translated from de to en] verschiebe alle Dateien aus ~/Downloads nach /srv/archiv mit `mv -n`"
+        );
+
+        let results = translate
+            .translate_batch_async(&[text.to_string()], "en")
+            .await
+            .unwrap();
+        assert_eq!(results[0].translated, result.translated);
+    }
+
+    #[tokio::test]
+    async fn test_translate_lines_keeps_line_structure() {
+        let translate = Translate::with_provider(TranslatorProvider::Mock).unwrap();
+        let lines = vec![
+            "Safety Error".to_string(),
+            "Rejected because of:".to_string(),
+        ];
+
+        let translated = translate
+            .translate_lines_async(&lines, "en", "es")
+            .await
+            .unwrap();
+        assert_eq!(translated.len(), 2);
+        assert!(translated[0].contains("to es"));
+        assert_eq!(translated[1], "Rejected because of:");
+
+        let same = translate
+            .translate_lines_async(&lines, "en", "en")
+            .await
+            .unwrap();
+        assert_eq!(same, lines);
+    }
+}
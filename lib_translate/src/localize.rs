@@ -0,0 +1,324 @@
+// Number and date formats in translated text
+//
+// Machine translation copies numbers and dates verbatim, so a German "1.234,5 MB
+// am 05.03.2024" comes out in English as "1.234,5 MB on 05.03.2024". `Localizer`
+// rewrites them from the source language's conventions into the target's: decimal
+// and digit grouping separators, and the order of day, month and year.
+//
+// Only tokens that parse unambiguously in the source format are touched. Plain
+// integers (years, ports, counts), ISO dates, versions and IP addresses, times, and
+// numbers inside paths or identifiers are left alone.
+
+use std::collections::HashMap;
+
+/// Order of the fields in a numeric date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    /// 05.03.2024
+    Dmy,
+    /// 03/05/2024
+    Mdy,
+    /// 2024/03/05
+    Ymd,
+}
+
+impl std::str::FromStr for DateOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "dmy" => Ok(DateOrder::Dmy),
+            "mdy" => Ok(DateOrder::Mdy),
+            "ymd" => Ok(DateOrder::Ymd),
+            other => Err(format!(
+                "Unknown date order '{}' (expected dmy, mdy or ymd)",
+                other
+            )),
+        }
+    }
+}
+
+/// How a language writes numbers and dates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocaleFormat {
+    pub decimal: char,
+    /// Separator between groups of three digits
+    pub group: char,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+}
+
+impl LocaleFormat {
+    pub const fn new(
+        decimal: char,
+        group: char,
+        date_order: DateOrder,
+        date_separator: char,
+    ) -> Self {
+        Self {
+            decimal,
+            group,
+            date_order,
+            date_separator,
+        }
+    }
+
+    /// Built-in format for a language code (`de`, `pt-BR`, ...)
+    pub fn for_language(lang: &str) -> Option<Self> {
+        use DateOrder::*;
+        let format = match base_language(lang).as_str() {
+            "en" => Self::new('.', ',', Mdy, '/'),
+            "de" => Self::new(',', '.', Dmy, '.'),
+            "fr" => Self::new(',', '\u{202f}', Dmy, '/'),
+            "es" | "it" | "pt" => Self::new(',', '.', Dmy, '/'),
+            "nl" => Self::new(',', '.', Dmy, '-'),
+            "tr" => Self::new(',', '.', Dmy, '.'),
+            "ru" | "pl" | "uk" | "cs" => Self::new(',', '\u{a0}', Dmy, '.'),
+            "ja" | "zh" | "ko" => Self::new('.', ',', Ymd, '/'),
+            _ => return None,
+        };
+        Some(format)
+    }
+
+    fn is_token_char(&self, c: char) -> bool {
+        c.is_ascii_digit() || c == self.decimal || c == self.group || c == self.date_separator
+    }
+
+    /// `token` rewritten in the `to` format, if it is a date or a separated
+    /// number in this format
+    fn reformat(&self, token: &str, to: &LocaleFormat) -> Option<String> {
+        self.reformat_date(token, to)
+            .or_else(|| self.reformat_number(token, to))
+    }
+
+    fn reformat_date(&self, token: &str, to: &LocaleFormat) -> Option<String> {
+        let parts: Vec<&str> = token.split(self.date_separator).collect();
+        let [a, b, c] = parts[..] else {
+            return None;
+        };
+        if parts
+            .iter()
+            .any(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return None;
+        }
+        let (year, month, day) = match self.date_order {
+            DateOrder::Dmy => (c, b, a),
+            DateOrder::Mdy => (c, a, b),
+            DateOrder::Ymd => (a, b, c),
+        };
+        let in_range = |s: &str, max: u32| {
+            s.len() <= 2 && s.parse::<u32>().is_ok_and(|n| (1..=max).contains(&n))
+        };
+        if year.len() != 4 || !in_range(month, 12) || !in_range(day, 31) {
+            return None;
+        }
+
+        let fields = match to.date_order {
+            DateOrder::Dmy => [day, month, year],
+            DateOrder::Mdy => [month, day, year],
+            DateOrder::Ymd => [year, month, day],
+        };
+        Some(fields.join(&to.date_separator.to_string()))
+    }
+
+    fn reformat_number(&self, token: &str, to: &LocaleFormat) -> Option<String> {
+        if self.date_separator != self.decimal
+            && self.date_separator != self.group
+            && token.contains(self.date_separator)
+        {
+            return None;
+        }
+        let (int, frac) = match token.split_once(self.decimal) {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (token, None),
+        };
+        let groups: Vec<&str> = int.split(self.group).collect();
+        let grouped = groups.len() > 1;
+
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let valid_groups = groups[0].len() <= 3 && groups[1..].iter().all(|g| g.len() == 3);
+        if !groups.iter().all(|g| digits(g))
+            || (grouped && !valid_groups)
+            || frac.is_some_and(|f| !digits(f))
+            // A plain integer looks the same everywhere
+            || (!grouped && frac.is_none())
+        {
+            return None;
+        }
+
+        let mut out = groups.join(&if grouped {
+            to.group.to_string()
+        } else {
+            String::new()
+        });
+        if let Some(frac) = frac {
+            out.push(to.decimal);
+            out.push_str(frac);
+        }
+        Some(out)
+    }
+}
+
+/// Rewrites numbers and dates in translated text for the target language
+///
+/// Built-in formats cover common languages; [`Localizer::with_format`] adds or
+/// overrides one.
+#[derive(Debug, Clone, Default)]
+pub struct Localizer {
+    overrides: HashMap<String, LocaleFormat>,
+}
+
+impl Localizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `format` for `lang`
+    pub fn with_format(mut self, lang: &str, format: LocaleFormat) -> Self {
+        self.overrides.insert(lang.to_lowercase(), format);
+        self
+    }
+
+    /// Format used for `lang`, preferring an override for the exact code
+    /// (`pt-br`), then one for the base language (`pt`), then the built-in one
+    pub fn format(&self, lang: &str) -> Option<LocaleFormat> {
+        self.overrides
+            .get(&lang.to_lowercase())
+            .or_else(|| self.overrides.get(&base_language(lang)))
+            .copied()
+            .or_else(|| LocaleFormat::for_language(lang))
+    }
+
+    /// Rewrite the numbers and dates of `text` from `from_lang`'s conventions into
+    /// `to_lang`'s; text is returned unchanged when either language is unknown
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_translate::localize::Localizer;
+    ///
+    /// let localizer = Localizer::new();
+    /// assert_eq!(
+    ///     localizer.localize("1.234,5 MB free on 05.03.2024", "de", "en"),
+    ///     "1,234.5 MB free on 03/05/2024"
+    /// );
+    /// ```
+    pub fn localize(&self, text: &str, from_lang: &str, to_lang: &str) -> String {
+        match (self.format(from_lang), self.format(to_lang)) {
+            (Some(from), Some(to)) if from != to => localize(text, &from, &to),
+            _ => text.to_string(),
+        }
+    }
+}
+
+/// Rewrite the numbers and dates of `text` from `from` into `to`
+pub fn localize(text: &str, from: &LocaleFormat, to: &LocaleFormat) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let starts_token = chars[i].is_ascii_digit() && (i == 0 || !is_attached(chars[i - 1]));
+        if !starts_token {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let mut end = i;
+        while end < chars.len() && from.is_token_char(chars[end]) {
+            end += 1;
+        }
+        // Trailing separators are punctuation ("costs 1,5.")
+        while !chars[end - 1].is_ascii_digit() {
+            end -= 1;
+        }
+
+        let token: String = chars[i..end].iter().collect();
+        let next = chars.get(end).copied();
+        let next_after = chars.get(end + 1).copied();
+        // Part of a path, time, version or identifier
+        let embedded = matches!(next, Some('/' | '\\' | ':' | '_'))
+            || (next.is_some_and(|c| c == '.' || c == ',' || c == '-')
+                && next_after.is_some_and(|c| c.is_alphanumeric()));
+        match from.reformat(&token, to).filter(|_| !embedded) {
+            Some(localized) => out.push_str(&localized),
+            None => out.push_str(&token),
+        }
+        i = end;
+    }
+    out
+}
+
+/// Whether a number after `c` belongs to a word, path or dotted sequence
+fn is_attached(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '/' | '\\' | '.' | ',' | ':' | '-')
+}
+
+fn base_language(lang: &str) -> String {
+    lang.split(['-', '_']).next().unwrap_or(lang).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localize(text: &str, from: &str, to: &str) -> String {
+        Localizer::new().localize(text, from, to)
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(localize("1.234,56 and 0,5", "de", "en"), "1,234.56 and 0.5");
+        assert_eq!(
+            localize("3.5 GB, 1,000,000 rows", "en", "de"),
+            "3,5 GB, 1.000.000 rows"
+        );
+        assert_eq!(localize("12,000.5", "en", "fr"), "12\u{202f}000,5");
+        // Plain integers and years are left as they are
+        assert_eq!(
+            localize("port 8080 since 2024", "en", "de"),
+            "port 8080 since 2024"
+        );
+        // Trailing punctuation is not part of the number
+        assert_eq!(localize("Es kostet 2,5.", "de", "en"), "Es kostet 2.5.");
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(localize("am 05.03.2024", "de", "en"), "am 03/05/2024");
+        assert_eq!(localize("on 12/31/2023", "en", "fr"), "on 31/12/2023");
+        assert_eq!(localize("on 3/5/2024", "en", "ja"), "on 2024/3/5");
+        // Not a valid month, so not a date
+        assert_eq!(localize("13/31/2024", "en", "de"), "13/31/2024");
+        // ISO dates are universal
+        assert_eq!(localize("2024-03-05", "en", "de"), "2024-03-05");
+    }
+
+    #[test]
+    fn test_technical_tokens_untouched() {
+        for text in [
+            "ping 192.168.1.1",
+            "version 1.2.3",
+            "at 10:30",
+            "/var/log/app.1.5",
+            "file_1.5.txt",
+            "python3.11",
+            "v2.5",
+        ] {
+            assert_eq!(localize(text, "en", "de"), text);
+        }
+    }
+
+    #[test]
+    fn test_unknown_and_overridden_languages() {
+        assert_eq!(localize("1.5", "en", "xx"), "1.5");
+        assert_eq!(localize("1.5", "en", "en-GB"), "1.5");
+
+        let swiss = LocaleFormat::new('.', '\'', DateOrder::Dmy, '.');
+        let localizer = Localizer::new().with_format("de-CH", swiss);
+        assert_eq!(localizer.localize("1,234.5", "en", "de-ch"), "1'234.5");
+        assert_eq!(localizer.localize("1,234.5", "en", "de"), "1.234,5");
+    }
+}
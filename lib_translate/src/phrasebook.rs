@@ -0,0 +1,760 @@
+// Bundled phrasebook for offline translation
+//
+// Without a translation service or model, prompts can still be made legible to an
+// English-only command model: the vocabulary of command requests is small ("find",
+// "files", "larger than", "days", ...). The phrasebook maps those words and short
+// phrases of ten common languages to English, word by word, and drops articles.
+// Anything not in it is kept as written, so paths, names and numbers pass through.
+// The result is rough ("find files larger than 100M in /var/log") but is what the
+// command model needs; full sentences deserve LibreTranslate. Text in which the
+// phrasebook knows fewer than half of the words is returned as written rather than
+// as a mix of two languages, and callers report it untranslated.
+
+use crate::error::{Result, TranslateError};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+/// Languages the phrasebook translates to and from English
+pub const LANGUAGES: [&str; 10] = ["de", "es", "fr", "it", "nl", "pl", "pt", "ru", "sv", "tr"];
+
+/// Longest phrase in the tables, in words
+const MAX_PHRASE_WORDS: usize = 3;
+
+/// Share of the words of a text that must be in the phrasebook for it to be
+/// translated; numbers, paths and placeholders do not count as words
+const MIN_COVERAGE: f64 = 0.5;
+
+// Each table maps lowercase words or phrases to English; "" drops the word
+const DE: &[(&str, &str)] = &[
+    ("liste", "list"),
+    ("listen", "list"),
+    ("zeige", "show"),
+    ("zeigen", "show"),
+    ("finde", "find"),
+    ("finden", "find"),
+    ("suche", "search"),
+    ("lösche", "delete"),
+    ("löschen", "delete"),
+    ("kopiere", "copy"),
+    ("verschiebe", "move"),
+    ("erstelle", "create"),
+    ("datei", "file"),
+    ("dateien", "files"),
+    ("verzeichnis", "directory"),
+    ("ordner", "folder"),
+    ("große", "large"),
+    ("großen", "large"),
+    ("kleine", "small"),
+    ("alte", "old"),
+    ("alten", "old"),
+    ("neue", "new"),
+    ("alle", "all"),
+    ("versteckte", "hidden"),
+    ("größe", "size"),
+    ("speicherplatz", "disk space"),
+    ("speicher", "memory"),
+    ("prozesse", "processes"),
+    ("laufenden", "running"),
+    ("beende", "kill"),
+    ("komprimiere", "compress"),
+    ("entpacke", "extract"),
+    ("zähle", "count"),
+    ("zeilen", "lines"),
+    ("benutzer", "user"),
+    ("sortiere", "sort"),
+    ("nach", "by"),
+    ("im", "in"),
+    ("größer als", "larger than"),
+    ("als", "than"),
+    ("geändert", "modified"),
+    ("heute", "today"),
+    ("tagen", "days"),
+    ("tage", "days"),
+    ("aktuellen", "current"),
+    ("und", "and"),
+    ("mit", "with"),
+    ("von", "of"),
+    ("der", ""),
+    ("die", ""),
+    ("das", ""),
+    ("den", ""),
+    ("dem", ""),
+    ("ein", ""),
+    ("eine", ""),
+];
+
+const ES: &[(&str, &str)] = &[
+    ("lista", "list"),
+    ("listar", "list"),
+    ("muestra", "show"),
+    ("mostrar", "show"),
+    ("busca", "find"),
+    ("buscar", "find"),
+    ("encuentra", "find"),
+    ("borra", "delete"),
+    ("borrar", "delete"),
+    ("elimina", "delete"),
+    ("copia", "copy"),
+    ("mueve", "move"),
+    ("crea", "create"),
+    ("archivo", "file"),
+    ("archivos", "files"),
+    ("directorio", "directory"),
+    ("carpeta", "folder"),
+    ("grandes", "large"),
+    ("pequeños", "small"),
+    ("antiguos", "old"),
+    ("viejos", "old"),
+    ("nuevos", "new"),
+    ("todos", "all"),
+    ("todas", "all"),
+    ("ocultos", "hidden"),
+    ("tamaño", "size"),
+    ("disco", "disk"),
+    ("espacio", "space"),
+    ("memoria", "memory"),
+    ("procesos", "processes"),
+    ("en ejecución", "running"),
+    ("mata", "kill"),
+    ("comprime", "compress"),
+    ("extrae", "extract"),
+    ("cuenta", "count"),
+    ("líneas", "lines"),
+    ("usuario", "user"),
+    ("puerto", "port"),
+    ("ordena", "sort"),
+    ("por", "by"),
+    ("en", "in"),
+    ("más grandes que", "larger than"),
+    ("mayores que", "larger than"),
+    ("modificados", "modified"),
+    ("hoy", "today"),
+    ("días", "days"),
+    ("actual", "current"),
+    ("y", "and"),
+    ("con", "with"),
+    ("de", "of"),
+    ("el", ""),
+    ("la", ""),
+    ("los", ""),
+    ("las", ""),
+    ("un", ""),
+    ("una", ""),
+];
+
+const FR: &[(&str, &str)] = &[
+    ("liste", "list"),
+    ("lister", "list"),
+    ("affiche", "show"),
+    ("afficher", "show"),
+    ("trouve", "find"),
+    ("trouver", "find"),
+    ("cherche", "search"),
+    ("supprime", "delete"),
+    ("supprimer", "delete"),
+    ("copie", "copy"),
+    ("déplace", "move"),
+    ("crée", "create"),
+    ("fichier", "file"),
+    ("fichiers", "files"),
+    ("répertoire", "directory"),
+    ("dossier", "folder"),
+    ("gros", "large"),
+    ("volumineux", "large"),
+    ("petits", "small"),
+    ("anciens", "old"),
+    ("nouveaux", "new"),
+    ("tous", "all"),
+    ("toutes", "all"),
+    ("cachés", "hidden"),
+    ("taille", "size"),
+    ("disque", "disk"),
+    ("espace", "space"),
+    ("mémoire", "memory"),
+    ("processus", "processes"),
+    ("en cours", "running"),
+    ("tue", "kill"),
+    ("compresse", "compress"),
+    ("extrais", "extract"),
+    ("compte", "count"),
+    ("lignes", "lines"),
+    ("utilisateur", "user"),
+    ("port", "port"),
+    ("trie", "sort"),
+    ("par", "by"),
+    ("dans", "in"),
+    ("plus grands que", "larger than"),
+    ("plus de", "more than"),
+    ("modifiés", "modified"),
+    ("aujourd'hui", "today"),
+    ("jours", "days"),
+    ("actuel", "current"),
+    ("et", "and"),
+    ("avec", "with"),
+    ("de", "of"),
+    ("du", "of"),
+    ("le", ""),
+    ("la", ""),
+    ("les", ""),
+    ("un", ""),
+    ("une", ""),
+    ("des", ""),
+];
+
+const IT: &[(&str, &str)] = &[
+    ("elenca", "list"),
+    ("mostra", "show"),
+    ("trova", "find"),
+    ("cerca", "search"),
+    ("elimina", "delete"),
+    ("cancella", "delete"),
+    ("copia", "copy"),
+    ("sposta", "move"),
+    ("crea", "create"),
+    ("file", "files"),
+    ("cartella", "folder"),
+    ("directory", "directory"),
+    ("grandi", "large"),
+    ("piccoli", "small"),
+    ("vecchi", "old"),
+    ("nuovi", "new"),
+    ("tutti", "all"),
+    ("tutte", "all"),
+    ("nascosti", "hidden"),
+    ("dimensione", "size"),
+    ("disco", "disk"),
+    ("spazio", "space"),
+    ("memoria", "memory"),
+    ("processi", "processes"),
+    ("in esecuzione", "running"),
+    ("termina", "kill"),
+    ("comprimi", "compress"),
+    ("estrai", "extract"),
+    ("conta", "count"),
+    ("righe", "lines"),
+    ("utente", "user"),
+    ("porta", "port"),
+    ("ordina", "sort"),
+    ("per", "by"),
+    ("nella", "in"),
+    ("nel", "in"),
+    ("più grandi di", "larger than"),
+    ("modificati", "modified"),
+    ("oggi", "today"),
+    ("giorni", "days"),
+    ("corrente", "current"),
+    ("e", "and"),
+    ("con", "with"),
+    ("di", "of"),
+    ("il", ""),
+    ("lo", ""),
+    ("la", ""),
+    ("i", ""),
+    ("gli", ""),
+    ("le", ""),
+    ("un", ""),
+    ("una", ""),
+];
+
+const NL: &[(&str, &str)] = &[
+    ("toon", "show"),
+    ("vind", "find"),
+    ("zoek", "search"),
+    ("verwijder", "delete"),
+    ("kopieer", "copy"),
+    ("verplaats", "move"),
+    ("maak", "create"),
+    ("bestand", "file"),
+    ("bestanden", "files"),
+    ("map", "folder"),
+    ("grote", "large"),
+    ("kleine", "small"),
+    ("oude", "old"),
+    ("nieuwe", "new"),
+    ("alle", "all"),
+    ("verborgen", "hidden"),
+    ("grootte", "size"),
+    ("schijf", "disk"),
+    ("ruimte", "space"),
+    ("geheugen", "memory"),
+    ("processen", "processes"),
+    ("draaiende", "running"),
+    ("beëindig", "kill"),
+    ("comprimeer", "compress"),
+    ("pak uit", "extract"),
+    ("tel", "count"),
+    ("regels", "lines"),
+    ("gebruiker", "user"),
+    ("poort", "port"),
+    ("sorteer", "sort"),
+    ("op", "by"),
+    ("groter dan", "larger than"),
+    ("dan", "than"),
+    ("gewijzigd", "modified"),
+    ("vandaag", "today"),
+    ("dagen", "days"),
+    ("huidige", "current"),
+    ("en", "and"),
+    ("met", "with"),
+    ("van", "of"),
+    ("de", ""),
+    ("het", ""),
+    ("een", ""),
+];
+
+const PL: &[(&str, &str)] = &[
+    ("pokaż", "show"),
+    ("wyświetl", "list"),
+    ("znajdź", "find"),
+    ("wyszukaj", "search"),
+    ("usuń", "delete"),
+    ("skopiuj", "copy"),
+    ("przenieś", "move"),
+    ("zmień nazwę", "rename"),
+    ("utwórz", "create"),
+    ("plik", "file"),
+    ("pliki", "files"),
+    ("plików", "files"),
+    ("katalog", "directory"),
+    ("katalogu", "directory"),
+    ("duże", "large"),
+    ("małe", "small"),
+    ("stare", "old"),
+    ("nowe", "new"),
+    ("wszystkie", "all"),
+    ("ukryte", "hidden"),
+    ("rozmiar", "size"),
+    ("dysk", "disk"),
+    ("miejsce", "space"),
+    ("pamięć", "memory"),
+    ("procesy", "processes"),
+    ("działające", "running"),
+    ("zabij", "kill"),
+    ("skompresuj", "compress"),
+    ("rozpakuj", "extract"),
+    ("policz", "count"),
+    ("linie", "lines"),
+    ("użytkownik", "user"),
+    ("port", "port"),
+    ("posortuj", "sort"),
+    ("według", "by"),
+    ("w", "in"),
+    ("większe niż", "larger than"),
+    ("niż", "than"),
+    ("zmodyfikowane", "modified"),
+    ("dzisiaj", "today"),
+    ("dni", "days"),
+    ("bieżącym", "current"),
+    ("i", "and"),
+    ("z", "with"),
+];
+
+const PT: &[(&str, &str)] = &[
+    ("listar", "list"),
+    ("liste", "list"),
+    ("mostre", "show"),
+    ("mostrar", "show"),
+    ("encontre", "find"),
+    ("encontrar", "find"),
+    ("procure", "search"),
+    ("apague", "delete"),
+    ("excluir", "delete"),
+    ("copie", "copy"),
+    ("mova", "move"),
+    ("crie", "create"),
+    ("arquivo", "file"),
+    ("arquivos", "files"),
+    ("ficheiros", "files"),
+    ("diretório", "directory"),
+    ("pasta", "folder"),
+    ("grandes", "large"),
+    ("pequenos", "small"),
+    ("antigos", "old"),
+    ("novos", "new"),
+    ("todos", "all"),
+    ("todas", "all"),
+    ("ocultos", "hidden"),
+    ("tamanho", "size"),
+    ("disco", "disk"),
+    ("espaço", "space"),
+    ("memória", "memory"),
+    ("processos", "processes"),
+    ("em execução", "running"),
+    ("mate", "kill"),
+    ("compacte", "compress"),
+    ("extraia", "extract"),
+    ("conte", "count"),
+    ("linhas", "lines"),
+    ("usuário", "user"),
+    ("porta", "port"),
+    ("ordene", "sort"),
+    ("por", "by"),
+    ("em", "in"),
+    ("no", "in"),
+    ("na", "in"),
+    ("maiores que", "larger than"),
+    ("modificados", "modified"),
+    ("hoje", "today"),
+    ("dias", "days"),
+    ("atual", "current"),
+    ("e", "and"),
+    ("com", "with"),
+    ("de", "of"),
+    ("do", "of"),
+    ("da", "of"),
+    ("o", ""),
+    ("a", ""),
+    ("os", ""),
+    ("as", ""),
+    ("um", ""),
+    ("uma", ""),
+];
+
+const RU: &[(&str, &str)] = &[
+    ("покажи", "show"),
+    ("выведи", "list"),
+    ("найди", "find"),
+    ("поиск", "search"),
+    ("удали", "delete"),
+    ("скопируй", "copy"),
+    ("перемести", "move"),
+    ("переименуй", "rename"),
+    ("создай", "create"),
+    ("файл", "file"),
+    ("файлы", "files"),
+    ("файлов", "files"),
+    ("каталог", "directory"),
+    ("каталоге", "directory"),
+    ("папку", "folder"),
+    ("большие", "large"),
+    ("маленькие", "small"),
+    ("старые", "old"),
+    ("новые", "new"),
+    ("все", "all"),
+    ("скрытые", "hidden"),
+    ("размер", "size"),
+    ("размеру", "size"),
+    ("диске", "disk"),
+    ("место", "space"),
+    ("память", "memory"),
+    ("процессы", "processes"),
+    ("запущенные", "running"),
+    ("убей", "kill"),
+    ("сожми", "compress"),
+    ("распакуй", "extract"),
+    ("посчитай", "count"),
+    ("строки", "lines"),
+    ("пользователя", "user"),
+    ("порт", "port"),
+    ("отсортируй", "sort"),
+    ("по", "by"),
+    ("в", "in"),
+    ("больше чем", "larger than"),
+    ("больше", "larger than"),
+    ("чем", "than"),
+    ("изменённые", "modified"),
+    ("сегодня", "today"),
+    ("дней", "days"),
+    ("текущем", "current"),
+    ("и", "and"),
+    ("с", "with"),
+];
+
+const SV: &[(&str, &str)] = &[
+    ("lista", "list"),
+    ("visa", "show"),
+    ("hitta", "find"),
+    ("sök", "search"),
+    ("ta bort", "delete"),
+    ("radera", "delete"),
+    ("kopiera", "copy"),
+    ("flytta", "move"),
+    ("byt namn", "rename"),
+    ("skapa", "create"),
+    ("fil", "file"),
+    ("filer", "files"),
+    ("katalog", "directory"),
+    ("mapp", "folder"),
+    ("stora", "large"),
+    ("små", "small"),
+    ("gamla", "old"),
+    ("nya", "new"),
+    ("alla", "all"),
+    ("dolda", "hidden"),
+    ("storlek", "size"),
+    ("disk", "disk"),
+    ("utrymme", "space"),
+    ("minne", "memory"),
+    ("processer", "processes"),
+    ("körs", "running"),
+    ("döda", "kill"),
+    ("komprimera", "compress"),
+    ("packa upp", "extract"),
+    ("räkna", "count"),
+    ("rader", "lines"),
+    ("användare", "user"),
+    ("port", "port"),
+    ("sortera", "sort"),
+    ("efter", "by"),
+    ("i", "in"),
+    ("större än", "larger than"),
+    ("än", "than"),
+    ("ändrade", "modified"),
+    ("idag", "today"),
+    ("dagar", "days"),
+    ("nuvarande", "current"),
+    ("och", "and"),
+    ("med", "with"),
+    ("av", "of"),
+    ("en", ""),
+    ("ett", ""),
+];
+
+const TR: &[(&str, &str)] = &[
+    ("listele", "list"),
+    ("göster", "show"),
+    ("bul", "find"),
+    ("ara", "search"),
+    ("sil", "delete"),
+    ("kopyala", "copy"),
+    ("taşı", "move"),
+    ("yeniden adlandır", "rename"),
+    ("oluştur", "create"),
+    ("dosya", "file"),
+    ("dosyalar", "files"),
+    ("dosyaları", "files"),
+    ("dizin", "directory"),
+    ("dizindeki", "in directory"),
+    ("klasör", "folder"),
+    ("büyük", "large"),
+    ("küçük", "small"),
+    ("eski", "old"),
+    ("yeni", "new"),
+    ("tüm", "all"),
+    ("gizli", "hidden"),
+    ("boyut", "size"),
+    ("boyuta", "by size"),
+    ("disk", "disk"),
+    ("alan", "space"),
+    ("bellek", "memory"),
+    ("işlemler", "processes"),
+    ("işlemleri", "processes"),
+    ("çalışan", "running"),
+    ("öldür", "kill"),
+    ("sıkıştır", "compress"),
+    ("çıkar", "extract"),
+    ("say", "count"),
+    ("satırları", "lines"),
+    ("kullanıcı", "user"),
+    ("port", "port"),
+    ("sırala", "sort"),
+    ("göre", ""),
+    ("bugün", "today"),
+    ("gün", "days"),
+    ("günden", "days"),
+    ("geçerli", "current"),
+    ("ve", "and"),
+    ("ile", "with"),
+    ("bir", ""),
+];
+
+fn table(lang: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    Some(match lang {
+        "de" => DE,
+        "es" => ES,
+        "fr" => FR,
+        "it" => IT,
+        "nl" => NL,
+        "pl" => PL,
+        "pt" => PT,
+        "ru" => RU,
+        "sv" => SV,
+        "tr" => TR,
+        _ => return None,
+    })
+}
+
+type Phrases = HashMap<&'static str, &'static str>;
+
+/// Phrases into English by language
+static INTO_ENGLISH: Lazy<HashMap<&'static str, Phrases>> = Lazy::new(|| {
+    LANGUAGES
+        .iter()
+        .filter_map(|&lang| Some((lang, table(lang)?.iter().copied().collect())))
+        .collect()
+});
+
+/// English phrases into each language; the first entry of a table wins
+static FROM_ENGLISH: Lazy<HashMap<&'static str, Phrases>> = Lazy::new(|| {
+    LANGUAGES
+        .iter()
+        .filter_map(|&lang| {
+            let mut phrases = Phrases::new();
+            for &(word, english) in table(lang)? {
+                if !english.is_empty() {
+                    phrases.entry(english).or_insert(word);
+                }
+            }
+            Some((lang, phrases))
+        })
+        .collect()
+});
+
+/// Whether the phrasebook translates from `source_lang` to `target_lang`
+pub fn supports(source_lang: &str, target_lang: &str) -> bool {
+    let known = |lang: &str| lang == "en" || LANGUAGES.contains(&base(lang));
+    known(source_lang) && known(target_lang)
+}
+
+/// Translate `text` word by word, through English when neither language is English
+///
+/// Returns `text` unchanged when too few of its words are known.
+pub fn translate(text: &str, source_lang: &str, target_lang: &str) -> Result<String> {
+    if !supports(source_lang, target_lang) {
+        return Err(TranslateError::UnsupportedLanguage(format!(
+            "{} to {} offline (the phrasebook covers en, {})",
+            source_lang,
+            target_lang,
+            LANGUAGES.join(", ")
+        )));
+    }
+    let (source, target) = (base(source_lang), base(target_lang));
+    let english = match INTO_ENGLISH.get(source) {
+        Some(phrases) => substitute(text, phrases),
+        None => (text.to_string(), 1.0),
+    };
+    let translated = match FROM_ENGLISH.get(target) {
+        Some(phrases) if source != target => substitute(&english.0, phrases),
+        _ => english.clone(),
+    };
+    Ok(match english.1.min(translated.1) < MIN_COVERAGE {
+        true => text.to_string(),
+        false => translated.0,
+    })
+}
+
+/// `pt` for `pt-BR`
+fn base(lang: &str) -> &str {
+    lang.split(['-', '_']).next().unwrap_or(lang)
+}
+
+/// Replace the longest known phrase at each word of each line of `text`, with the
+/// share of its words that were known
+fn substitute(text: &str, phrases: &Phrases) -> (String, f64) {
+    let is_word = |word: &str| word.chars().any(char::is_alphabetic) && !word.contains('/');
+    let (mut words_seen, mut known) = (0, 0);
+    let translated = text
+        .lines()
+        .map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let mut out: Vec<String> = Vec::with_capacity(words.len());
+            let mut i = 0;
+            while i < words.len() {
+                let matched = (1..=MAX_PHRASE_WORDS.min(words.len() - i))
+                    .rev()
+                    .find_map(|n| lookup(&words[i..i + n], phrases).map(|t| (n, t)));
+                match matched {
+                    Some((n, translated)) => {
+                        if !translated.is_empty() {
+                            out.push(translated);
+                        }
+                        let phrase_words = words[i..i + n].iter().filter(|w| is_word(w)).count();
+                        words_seen += phrase_words;
+                        known += phrase_words;
+                        i += n;
+                    }
+                    None => {
+                        words_seen += usize::from(is_word(words[i]));
+                        out.push(words[i].to_string());
+                        i += 1;
+                    }
+                }
+            }
+            out.join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let coverage = match words_seen {
+        0 => 1.0,
+        n => known as f64 / n as f64,
+    };
+    (translated, coverage)
+}
+
+/// Translation of the phrase made of `words`, keeping the punctuation around it
+fn lookup(words: &[&str], phrases: &Phrases) -> Option<String> {
+    let is_edge = |c: char| !c.is_alphanumeric() && c != '\'';
+    let first = words.first()?;
+    let last = words.last()?;
+    let lead = &first[..first.len() - first.trim_start_matches(is_edge).len()];
+    let trail = &last[last.trim_end_matches(is_edge).len()..];
+    // Punctuation inside a phrase ends it
+    if words.len() > 1
+        && words[..words.len() - 1]
+            .iter()
+            .any(|w| w.ends_with(is_edge))
+    {
+        return None;
+    }
+    let phrase = words.join(" ").to_lowercase();
+    let phrase = phrase.trim_matches(is_edge);
+    let translated = phrases.get(phrase)?;
+    Some(match translated.is_empty() {
+        true => String::new(),
+        false => format!("{}{}{}", lead, translated, trail),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompts_become_english() {
+        assert_eq!(
+            translate(
+                "Encuentra los archivos más grandes que 100M en /var/log",
+                "es",
+                "en"
+            )
+            .unwrap(),
+            "find files larger than 100M in /var/log"
+        );
+        assert_eq!(
+            translate("Lösche alle Dateien, die älter als 7 Tage sind", "de", "en").unwrap(),
+            "delete all files, älter than 7 days sind"
+        );
+        assert_eq!(
+            translate("liste les fichiers cachés\naujourd'hui", "fr", "en").unwrap(),
+            "list files hidden\ntoday"
+        );
+    }
+
+    #[test]
+    fn test_mostly_unknown_text_is_returned_as_written() {
+        let text = "Wie spät ist es gerade in Tokio?";
+        assert_eq!(translate(text, "de", "en").unwrap(), text);
+        // Paths and numbers are neither known nor unknown words
+        assert_eq!(
+            translate("lösche /tmp/a1 /tmp/b2 42", "de", "en").unwrap(),
+            "delete /tmp/a1 /tmp/b2 42"
+        );
+    }
+
+    #[test]
+    fn test_english_and_other_pairs() {
+        assert_eq!(
+            translate("delete files", "en", "es").unwrap(),
+            "borra archivos"
+        );
+        assert_eq!(
+            translate("Lösche Dateien", "de", "pt-BR").unwrap(),
+            "apague arquivos"
+        );
+        assert!(supports("pt-BR", "en"));
+        assert!(matches!(
+            translate("ファイル", "ja", "en"),
+            Err(TranslateError::UnsupportedLanguage(_))
+        ));
+    }
+}
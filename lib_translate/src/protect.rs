@@ -0,0 +1,249 @@
+// Code, paths and other literals in text to translate
+//
+// Prompts mix prose with things that must reach the other side unchanged: fenced
+// and inline code, file paths and names, URLs, command-line flags and environment
+// variables. Translation services treat them as words and "fix" them: `/var/log`
+// comes back as `/var/registro`, `--force` as `-- force`. Before translating,
+// `protect` swaps each of them for a numbered placeholder the services pass
+// through as is; `Protected::restore` puts the originals back afterwards.
+//
+// Language detection runs on the protected text as well, so a prompt that is
+// mostly a shell snippet is judged by its prose.
+
+/// Marks around the number of a placeholder, e.g. `⟦0⟧`
+const OPEN: char = '⟦';
+const CLOSE: char = '⟧';
+
+/// Punctuation that can follow a path or flag at the end of a sentence
+const TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', ')', '"', '\''];
+
+/// Text with its literals replaced by placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Protected {
+    text: String,
+    spans: Vec<String>,
+}
+
+impl Protected {
+    /// The text to translate, with placeholders
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The literals replaced, in placeholder order
+    pub fn spans(&self) -> &[String] {
+        &self.spans
+    }
+
+    /// `translated` with the placeholders replaced by the original literals
+    ///
+    /// Services occasionally space out a placeholder (`⟦ 0 ⟧`), which is accepted;
+    /// literals whose placeholder was dropped altogether are appended at the end
+    /// rather than lost.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lib_translate::protect::protect;
+    ///
+    /// let protected = protect("borra `*.tmp` en /var/tmp");
+    /// assert_eq!(protected.text(), "borra ⟦0⟧ en ⟦1⟧");
+    /// assert_eq!(
+    ///     protected.restore("delete ⟦0⟧ in ⟦1⟧"),
+    ///     "delete `*.tmp` in /var/tmp"
+    /// );
+    /// ```
+    pub fn restore(&self, translated: &str) -> String {
+        let mut out = String::with_capacity(translated.len());
+        let mut used = vec![false; self.spans.len()];
+        let mut rest = translated;
+        while let Some(open) = rest.find(OPEN) {
+            let after = &rest[open + OPEN.len_utf8()..];
+            let span = after.find(CLOSE).and_then(|close| {
+                let index: usize = after[..close].trim().parse().ok()?;
+                Some((index, close))
+            });
+            match span {
+                Some((index, close)) if index < self.spans.len() => {
+                    out.push_str(&rest[..open]);
+                    out.push_str(&self.spans[index]);
+                    used[index] = true;
+                    rest = &after[close + CLOSE.len_utf8()..];
+                }
+                _ => {
+                    out.push_str(&rest[..open + OPEN.len_utf8()]);
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        for (span, _) in self.spans.iter().zip(used).filter(|(_, used)| !used) {
+            out.push(' ');
+            out.push_str(span);
+        }
+        out
+    }
+
+    fn push_span(&mut self, span: &str) {
+        self.text
+            .push_str(&format!("{}{}{}", OPEN, self.spans.len(), CLOSE));
+        self.spans.push(span.to_string());
+    }
+
+    /// Copy `prose`, protecting the words that are paths, flags and the like
+    fn push_prose(&mut self, prose: &str) {
+        let mut rest = prose;
+        while !rest.is_empty() {
+            let space = rest.len() - rest.trim_start().len();
+            self.text.push_str(&rest[..space]);
+            rest = &rest[space..];
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (word, after) = rest.split_at(end);
+            rest = after;
+
+            let start = word.len() - word.trim_start_matches(['(', '"', '\'']).len();
+            let core = word[start..].trim_end_matches(TRAILING);
+            if core.is_empty() || !is_literal(core) {
+                self.text.push_str(word);
+                continue;
+            }
+            self.text.push_str(&word[..start]);
+            self.push_span(core);
+            self.text.push_str(&word[start + core.len()..]);
+        }
+    }
+}
+
+/// Replace the code, paths and other literals in `text` with placeholders
+pub fn protect(text: &str) -> Protected {
+    let mut protected = Protected {
+        text: String::with_capacity(text.len()),
+        spans: Vec::new(),
+    };
+    let mut rest = text;
+    while let Some(start) = rest.find('`') {
+        let (prose, code) = rest.split_at(start);
+        let end = if let Some(fenced) = code.strip_prefix("```") {
+            // An unclosed fence runs to the end of the text
+            fenced.find("```").map(|i| i + 6).unwrap_or(code.len())
+        } else {
+            match code[1..].find(['`', '\n']) {
+                Some(i) if code[1 + i..].starts_with('`') => i + 2,
+                // A lone backtick is just a character
+                _ => {
+                    protected.push_prose(&rest[..start + 1]);
+                    rest = &rest[start + 1..];
+                    continue;
+                }
+            }
+        };
+        protected.push_prose(prose);
+        protected.push_span(&code[..end]);
+        rest = &code[end..];
+    }
+    protected.push_prose(rest);
+    protected
+}
+
+/// Whether `word` is a path, file name, URL, flag or variable rather than prose
+fn is_literal(word: &str) -> bool {
+    if word.contains("://") {
+        return true;
+    }
+    // /var/log, ~/src, ./build, ../lib, C:\Users
+    if word.starts_with('/')
+        || word.starts_with("~/")
+        || word.starts_with("./")
+        || word.starts_with("../")
+        || word.get(1..3) == Some(":\\")
+    {
+        return true;
+    }
+    // src/main.rs and usr/local/bin, but not and/or
+    if word.contains('/') && (word.contains('.') || word.matches('/').count() > 1) {
+        return true;
+    }
+    // -la, --force
+    if word.len() > 1
+        && word.starts_with('-')
+        && word[1..].starts_with(|c: char| c == '-' || c.is_ascii_alphabetic())
+    {
+        return true;
+    }
+    // $HOME, ${PATH}
+    if word.starts_with('$') && word[1..].starts_with(|c: char| c == '{' || c.is_ascii_alphabetic())
+    {
+        return true;
+    }
+    is_file_name(word)
+}
+
+/// Whether `word` looks like `notes.txt` or `eidos.toml` (not `z.B` or `1.234,5`)
+fn is_file_name(word: &str) -> bool {
+    let Some((name, extension)) = word.rsplit_once('.') else {
+        return false;
+    };
+    name.chars().count() >= 2
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && (1..=5).contains(&extension.len())
+        && extension.starts_with(|c: char| c.is_ascii_alphabetic())
+        && extension.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protects_code_and_paths() {
+        let protected = protect(
+            "Lösche alle Dateien in ~/tmp mit `rm -rf`, außer notes.txt.\n\
+             ```\nfind . -name '*.log'\n```\nSiehe https://example.com/docs und $HOME (--force).",
+        );
+        assert_eq!(
+            protected.text(),
+            "Lösche alle Dateien in ⟦0⟧ mit ⟦1⟧, außer ⟦2⟧.\n⟦3⟧\nSiehe ⟦4⟧ und ⟦5⟧ (⟦6⟧)."
+        );
+        assert_eq!(
+            protected.spans(),
+            [
+                "~/tmp",
+                "`rm -rf`",
+                "notes.txt",
+                "```\nfind . -name '*.log'\n```",
+                "https://example.com/docs",
+                "$HOME",
+                "--force"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prose_numbers_and_dates_are_not_literals() {
+        for text in [
+            "Noch 1.234,5 MB frei seit dem 05.03.2024",
+            "z.B. ein Wort und/oder zwei - oder drei",
+            "a lone ` backtick",
+        ] {
+            let protected = protect(text);
+            assert_eq!(protected.text(), text);
+            assert!(protected.spans().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_restore_tolerates_mangled_placeholders() {
+        let protected = protect("kopiere /etc/hosts nach ./backup");
+        assert_eq!(
+            protected.restore("copy ⟦ 0 ⟧ to ⟦1⟧"),
+            "copy /etc/hosts to ./backup"
+        );
+        // A dropped placeholder is appended rather than lost; unknown ones stay
+        assert_eq!(
+            protected.restore("copy ⟦0⟧ to ⟦7⟧"),
+            "copy /etc/hosts to ⟦7⟧ ./backup"
+        );
+    }
+}
@@ -1,20 +1,43 @@
 // lib_translate/src/translator.rs
 use crate::error::{Result, TranslateError};
+use crate::phrasebook;
+use lib_cassette::Cassette;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::env;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-// Default timeouts (can be overridden via environment variables)
+// Default timeouts, used unless the caller passes its own `HttpTimeouts`
 const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
 const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
 
+/// Timeouts of the HTTP client talking to the translation service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpTimeouts {
+    /// Limit for a whole request, including reading the reply
+    pub request: Duration,
+    /// Limit for establishing the connection
+    pub connect: Duration,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self {
+            request: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            connect: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum TranslatorProvider {
     LibreTranslate {
         url: String,
         api_key: Option<String>,
     },
+    /// The bundled phrasebook, on this machine (see [`phrasebook`])
+    Offline,
     Mock, // For testing without API
 }
 
@@ -40,7 +63,7 @@ impl TranslatorProvider {
 
 #[derive(Debug, Serialize)]
 struct LibreTranslateRequest {
-    q: String,
+    q: Query,
     source: String,
     target: String,
     format: String,
@@ -48,6 +71,14 @@ struct LibreTranslateRequest {
     api_key: Option<String>,
 }
 
+/// Text of a request: one string, or an array translated in one call
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Query {
+    One(String),
+    Many(Vec<String>),
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 enum LibreTranslateResponse {
@@ -55,6 +86,10 @@ enum LibreTranslateResponse {
         #[serde(rename = "translatedText")]
         translated_text: String,
     },
+    Batch {
+        #[serde(rename = "translatedText")]
+        translated_texts: Vec<String>,
+    },
     Error {
         error: String,
     },
@@ -63,29 +98,59 @@ enum LibreTranslateResponse {
 pub struct Translator {
     provider: TranslatorProvider,
     client: Client,
+    request_timeout: Duration,
+    deadline: Option<Instant>,
+    cassette: Option<Arc<Cassette>>,
 }
 
 impl Translator {
     pub fn new(provider: TranslatorProvider) -> Result<Self> {
-        // Get timeout values from environment variables or use defaults
-        let request_timeout = env::var("HTTP_REQUEST_TIMEOUT_SECS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
-
-        let connect_timeout = env::var("HTTP_CONNECT_TIMEOUT_SECS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
-
-        // Create HTTP client with configurable timeouts to prevent hanging requests
+        Self::with_timeouts(provider, HttpTimeouts::default())
+    }
+
+    /// Translator for `provider` whose requests give up after `timeouts`
+    pub fn with_timeouts(provider: TranslatorProvider, timeouts: HttpTimeouts) -> Result<Self> {
+        // Timeouts keep a service that stops answering from hanging the caller
         let client = Client::builder()
-            .timeout(Duration::from_secs(request_timeout))
-            .connect_timeout(Duration::from_secs(connect_timeout))
+            .timeout(timeouts.request)
+            .connect_timeout(timeouts.connect)
             .build()
             .map_err(|e| TranslateError::ApiError(format!("Failed to build HTTP client: {}", e)))?;
+        let cassette = lib_cassette::global().map_err(TranslateError::ConfigError)?;
 
-        Ok(Self { provider, client })
+        Ok(Self {
+            provider,
+            client,
+            request_timeout: timeouts.request,
+            deadline: None,
+            cassette,
+        })
+    }
+
+    /// Record requests into, or replay them from, `cassette` instead of the one
+    /// named by `EIDOS_RECORD` / `EIDOS_REPLAY`
+    pub fn set_cassette(&mut self, cassette: Option<Arc<Cassette>>) {
+        self.cassette = cassette;
+    }
+
+    /// Finish every request by `deadline`, even when the configured HTTP
+    /// timeout would allow longer
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Timeout for the next request, or an error once the deadline has passed
+    fn request_timeout(&self) -> Result<Duration> {
+        match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(TranslateError::DeadlineExceeded);
+                }
+                Ok(remaining.min(self.request_timeout))
+            }
+            None => Ok(self.request_timeout),
+        }
     }
 
     pub fn from_env() -> Result<Self> {
@@ -93,58 +158,102 @@ impl Translator {
         Self::new(provider)
     }
 
+    /// The provider this translator sends requests to
+    pub fn provider(&self) -> &TranslatorProvider {
+        &self.provider
+    }
+
     pub async fn translate(
         &self,
         text: &str,
         source_lang: &str,
         target_lang: &str,
     ) -> Result<String> {
-        match &self.provider {
+        let mut translated = self
+            .translate_batch(&[text.to_string()], source_lang, target_lang)
+            .await?;
+        Ok(translated.remove(0))
+    }
+
+    /// Translate several texts between the same two languages in one request
+    ///
+    /// Returns one translation per text, in order.
+    pub async fn translate_batch(
+        &self,
+        texts: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<String>> {
+        let translated = match &self.provider {
             TranslatorProvider::LibreTranslate { url, api_key } => {
-                self.translate_libretranslate(
-                    url,
-                    api_key.as_deref(),
-                    text,
-                    source_lang,
-                    target_lang,
-                )
-                .await
+                // Single texts go as a string, which every server version accepts
+                let q = match texts {
+                    [text] => Query::One(text.clone()),
+                    texts => Query::Many(texts.to_vec()),
+                };
+                self.translate_libretranslate(url, api_key.as_deref(), q, source_lang, target_lang)
+                    .await?
             }
+            TranslatorProvider::Offline => texts
+                .iter()
+                .map(|text| phrasebook::translate(text, source_lang, target_lang))
+                .collect::<Result<Vec<_>>>()?,
             TranslatorProvider::Mock => {
                 // Mock translator for testing - just returns original text with prefix
-                Ok(format!(
-                    "[Translated from {} to {}] {}",
-                    source_lang, target_lang, text
-                ))
+                texts
+                    .iter()
+                    .map(|text| {
+                        format!(
+                            "[Translated from {} to {}] {}",
+                            source_lang, target_lang, text
+                        )
+                    })
+                    .collect()
             }
+        };
+        if translated.len() != texts.len() {
+            return Err(TranslateError::TranslationFailed(format!(
+                "Expected {} translations, got {}",
+                texts.len(),
+                translated.len()
+            )));
         }
+        Ok(translated)
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "http",
+            skip_all,
+            fields(provider = "libretranslate", op = "translate")
+        )
+    )]
     async fn translate_libretranslate(
         &self,
         base_url: &str,
         api_key: Option<&str>,
-        text: &str,
+        q: Query,
         source_lang: &str,
         target_lang: &str,
-    ) -> Result<String> {
+    ) -> Result<Vec<String>> {
         let url = format!("{}/translate", base_url);
 
         let request_body = LibreTranslateRequest {
-            q: text.to_string(),
+            q,
             source: source_lang.to_string(),
             target: target_lang.to_string(),
             format: "text".to_string(),
             api_key: api_key.map(|s| s.to_string()),
         };
 
-        let response = self
+        let request = self
             .client
             .post(&url)
+            .timeout(self.request_timeout()?)
             .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+            .json(&request_body);
+        let response = lib_cassette::send(self.cassette.as_deref(), request).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -158,7 +267,8 @@ impl Translator {
         let response_data: LibreTranslateResponse = response.json().await?;
 
         match response_data {
-            LibreTranslateResponse::Success { translated_text } => Ok(translated_text),
+            LibreTranslateResponse::Success { translated_text } => Ok(vec![translated_text]),
+            LibreTranslateResponse::Batch { translated_texts } => Ok(translated_texts),
             LibreTranslateResponse::Error { error } => {
                 Err(TranslateError::TranslationFailed(error))
             }
@@ -195,6 +305,50 @@ mod tests {
         assert!(result.contains("es"));
     }
 
+    #[tokio::test]
+    async fn test_offline_translator_uses_the_phrasebook() {
+        let translator = Translator::new(TranslatorProvider::Offline).unwrap();
+        let result = translator
+            .translate("borra archivos", "es", "en")
+            .await
+            .unwrap();
+        assert_eq!(result, "delete files");
+    }
+
+    #[tokio::test]
+    async fn test_batch_keeps_order() {
+        let translator = Translator::new(TranslatorProvider::Offline).unwrap();
+        let texts = vec!["borra archivos".to_string(), "archivos".to_string()];
+        assert_eq!(
+            translator
+                .translate_batch(&texts, "es", "en")
+                .await
+                .unwrap(),
+            vec!["delete files", "files"]
+        );
+    }
+
+    #[test]
+    fn test_batch_request_and_response_format() {
+        let request = LibreTranslateRequest {
+            q: Query::Many(vec!["Hola".to_string(), "Adiós".to_string()]),
+            source: "es".to_string(),
+            target: "en".to_string(),
+            format: "text".to_string(),
+            api_key: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["q"],
+            serde_json::json!(["Hola", "Adiós"])
+        );
+        let response: LibreTranslateResponse =
+            serde_json::from_str(r#"{"translatedText": ["Hello", "Goodbye"]}"#).unwrap();
+        assert!(matches!(
+            response,
+            LibreTranslateResponse::Batch { translated_texts } if translated_texts == ["Hello", "Goodbye"]
+        ));
+    }
+
     #[tokio::test]
     async fn test_translate_to_english_same_language() {
         let translator = Translator::new(TranslatorProvider::Mock).unwrap();
@@ -204,4 +358,18 @@ mod tests {
             .unwrap();
         assert_eq!(result, "Hello");
     }
+
+    #[tokio::test]
+    async fn test_expired_deadline_fails_before_sending() {
+        let mut translator = Translator::new(TranslatorProvider::LibreTranslate {
+            url: "http://127.0.0.1:9".to_string(),
+            api_key: None,
+        })
+        .unwrap();
+        translator.set_deadline(Some(Instant::now()));
+        assert!(matches!(
+            translator.translate("Hola", "es", "en").await,
+            Err(TranslateError::DeadlineExceeded)
+        ));
+    }
 }
@@ -0,0 +1,69 @@
+//! File and clock helpers shared by the Eidos crates
+//!
+//! The CLI's history and metrics, lib_core's command cache and lib_chat's sessions
+//! all keep state in files that other invocations may read at the same moment, and
+//! stamp it with Unix times. Those two primitives live here so every crate replaces
+//! files and reads the clock the same way.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Replace `path` with `contents` in one step, creating its directory if needed
+///
+/// The contents go to a hidden temporary file beside `path` first, which is renamed
+/// over it; a reader sees either the old or the new file, never a mix. The
+/// temporary file ends in `.tmp`, so listings by extension skip it if a crash
+/// leaves it behind.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staging = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+    fs::write(&staging, contents)?;
+    fs::rename(&staging, path).map_err(|e| {
+        let _ = fs::remove_file(&staging);
+        e
+    })
+}
+
+/// Current Unix time in seconds; 0 if the clock is set before 1970
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_and_leaves_no_staging_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/state.json");
+        write_atomic(&path, "old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["state.json"]);
+    }
+
+    #[test]
+    fn test_failed_rename_removes_the_staging_file() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory cannot be replaced by a file
+        let path = dir.path().join("taken");
+        fs::create_dir_all(path.join("child")).unwrap();
+
+        assert!(write_atomic(&path, "data").is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
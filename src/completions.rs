@@ -0,0 +1,152 @@
+// src/completions.rs
+// `eidos completions <shell>`: tab completion for bash, zsh, fish, PowerShell and elvish
+//
+// The printed script does not list the commands itself; it asks eidos on every
+// <Tab> by running it with `COMPLETE=<shell>` set, which `main` answers from the
+// clap definition before doing anything else. That keeps completions in step with
+// the installed binary and lets arguments that name something on disk (sessions,
+// notes indexes, downloaded models, configured personas) complete the names that
+// exist right now. Candidates are read without locks or network access, and a
+// store that cannot be read completes nothing rather than failing the shell.
+
+use clap_complete::env::Shells;
+#[cfg(any(feature = "chat", feature = "models"))]
+use clap_complete::CompletionCandidate;
+#[cfg(feature = "chat")]
+use std::path::Path;
+
+/// Environment variable the registration script sets when it calls back into eidos
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Shells `eidos completions` can register with
+pub const SHELLS: [&str; 5] = ["bash", "elvish", "fish", "powershell", "zsh"];
+
+/// The script that makes `shell` complete eidos, calling `completer` for candidates
+pub fn registration(shell: &str, completer: &str) -> Result<String, String> {
+    let shells = Shells::builtins();
+    let shell = shells
+        .completer(shell)
+        .ok_or_else(|| format!("Unsupported shell: {}", shell))?;
+    let mut script = Vec::new();
+    shell
+        .write_registration(COMPLETE_VAR, "eidos", "eidos", completer, &mut script)
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(script).map_err(|e| e.to_string())
+}
+
+/// Where the registration goes for `shell`, shown after the script
+pub fn install_hint(shell: &str) -> &'static str {
+    match shell {
+        "bash" => "source <(eidos completions bash)    # in ~/.bashrc",
+        "zsh" => "source <(eidos completions zsh)     # in ~/.zshrc",
+        "fish" => "eidos completions fish | source    # in ~/.config/fish/config.fish",
+        "elvish" => "eval (eidos completions elvish | slurp)    # in ~/.config/elvish/rc.elv",
+        _ => "eidos completions powershell | Out-String | Invoke-Expression    # in $PROFILE",
+    }
+}
+
+/// How the registration script should call eidos: by name when it was found on
+/// `PATH`, otherwise by the absolute path it was run from
+pub fn completer() -> String {
+    let Some(arg0) = std::env::args_os().next() else {
+        return "eidos".to_string();
+    };
+    let path = std::path::PathBuf::from(arg0);
+    if path.components().count() > 1 {
+        if let Ok(path) = std::env::current_dir().map(|dir| dir.join(&path)) {
+            return path.to_string_lossy().into_owned();
+        }
+    }
+    path.to_string_lossy().into_owned()
+}
+
+/// Saved chat sessions, with their titles as help
+#[cfg(feature = "chat")]
+pub fn sessions() -> Vec<CompletionCandidate> {
+    let store = lib_chat::session::SessionStore::new(crate::paths::sessions_dir());
+    store
+        .list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|session| CompletionCandidate::new(session.name).help(Some(session.title.into())))
+        .collect()
+}
+
+/// Built-in personas and the `[personas.NAME]` tables of the config
+#[cfg(feature = "chat")]
+pub fn personas() -> Vec<CompletionCandidate> {
+    let Ok(registry) = crate::config::Config::load()
+        .unwrap_or_default()
+        .persona_registry()
+    else {
+        return Vec::new();
+    };
+    registry
+        .names()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Notes indexes built with `eidos index`
+#[cfg(feature = "chat")]
+pub fn indexes() -> Vec<CompletionCandidate> {
+    index_names(&crate::paths::index_dir())
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Models downloaded with `eidos model pull`
+#[cfg(feature = "models")]
+pub fn models() -> Vec<CompletionCandidate> {
+    crate::models::installed(&crate::paths::models_dir())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|model| CompletionCandidate::new(model.name))
+        .collect()
+}
+
+/// Names of the indexes in `dir`, sorted; a missing directory holds none
+#[cfg(feature = "chat")]
+fn index_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_suffix(".json").map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_calls_back_into_eidos() {
+        for shell in SHELLS {
+            let script = registration(shell, "/opt/bin/eidos").unwrap();
+            assert!(script.contains(COMPLETE_VAR), "{}: {}", shell, script);
+            assert!(script.contains("/opt/bin/eidos"), "{}: {}", shell, script);
+        }
+        assert!(registration("tcsh", "eidos").is_err());
+    }
+
+    #[cfg(feature = "chat")]
+    #[test]
+    fn test_index_names_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["work.json", "home.json", "home.json.tmp", "README"] {
+            std::fs::write(dir.path().join(file), "{}").unwrap();
+        }
+
+        assert_eq!(index_names(dir.path()), ["home", "work"]);
+        assert!(index_names(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -1,15 +1,776 @@
 // src/config.rs
+use crate::context::{EnvContext, DEFAULT_ENV_VARS};
+#[cfg(any(feature = "chat", feature = "translate"))]
+use crate::health;
+#[cfg(any(feature = "chat", feature = "translate"))]
+use crate::legacy_env;
+use crate::logging::LogFormat;
+use crate::output::{ManLinks, OutputFormat};
+#[cfg(feature = "core")]
+use crate::paths;
+#[cfg(feature = "chat")]
+use lib_chat::api::{ApiClient, ApiProvider, HttpTimeouts, PROVIDER_NAMES};
+#[cfg(feature = "chat")]
+use lib_chat::persona::PersonaRegistry;
+#[cfg(feature = "chat")]
+use lib_chat::{ChatError, Verbosity};
+#[cfg(feature = "core")]
+use lib_core::registry::{FileStamp, DEFAULT_MEMORY_BUDGET};
+#[cfg(feature = "core")]
+use lib_core::{
+    BackendKind, CommandCache, CommandGrammar, DeviceSpec, ModelPreset, PresetRegistry,
+    SafetyLevel, SafetyPolicy, TargetShell,
+};
+#[cfg(feature = "translate")]
+use lib_translate::localize::{LocaleFormat, Localizer};
+#[cfg(feature = "translate")]
+use lib_translate::translator::{Translator, TranslatorProvider};
+#[cfg(feature = "translate")]
+use lib_translate::Translate;
+#[cfg(any(feature = "chat", feature = "translate"))]
+use log::debug;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+#[cfg(all(unix, feature = "serve"))]
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// HTTP timeouts used when neither eidos.toml nor the environment sets them
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+/// How long an unreachable provider is remembered when nothing else is set
+const DEFAULT_HEALTH_TTL_SECS: u64 = 60;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    /// Path to the ONNX model file
+    /// Path to the model file (ONNX or GGUF)
     pub model_path: PathBuf,
     /// Path to the tokenizer JSON file
     pub tokenizer_path: PathBuf,
+    /// Inference backend: `onnx` or `gguf`; inferred from the model file extension
+    /// when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Device for GGUF inference: `cpu` (default), `auto`, `cuda[:N]` or `metal[:N]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Subcommand used when none is given (e.g. `eidos "list files"`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_command: Option<String>,
+    /// Command generation settings (`[core]` section)
+    #[serde(default)]
+    pub core: CoreConfig,
+    /// Command safety policy (`[safety]` section)
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    /// Chat settings (`[chat]` section)
+    #[serde(default)]
+    pub chat: ChatConfig,
+    /// Environment capture (`[context]` section)
+    #[serde(default)]
+    pub context: ContextConfig,
+    /// Translation settings (`[translate]` section)
+    #[serde(default)]
+    pub translate: TranslateConfig,
+    /// Reuse of generated commands (`[cache]` section)
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Terminal output (`[ui]` section)
+    #[serde(default)]
+    pub ui: UiConfig,
+    /// Pipeline behaviour (`[ci]` section, `--ci`)
+    #[serde(default)]
+    pub ci: CiConfig,
+    /// Timeouts for chat, embedding and translation requests (`[http]` section)
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Diagnostic logging (`[log]` section)
+    #[serde(default)]
+    pub log: LogConfig,
+    /// Local usage metrics (`[metrics]` section)
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Per-model generation settings (`[[presets]]` entries)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub presets: Vec<PresetConfig>,
+    /// Named chat system prompts (`[personas.NAME]` tables)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub personas: BTreeMap<String, PersonaConfig>,
+    /// Settings taken from deprecated environment variables by `load`
+    #[cfg(any(feature = "chat", feature = "translate"))]
+    #[serde(skip)]
+    pub legacy: Vec<legacy_env::Setting>,
+}
+
+/// The sections of a config file that are edited in place, which can be checked
+/// before the file has model paths
+#[cfg(any(feature = "models", feature = "chat", feature = "translate"))]
+#[derive(Debug, Default, Deserialize)]
+struct EditableSections {
+    #[serde(default)]
+    chat: ChatConfig,
+    #[serde(default)]
+    translate: TranslateConfig,
+    #[serde(default)]
+    http: HttpConfig,
+}
+
+/// Everything needed to load the configured model
+///
+/// Also the key of the CLI's model registry: a change to any field loads the model
+/// again, as does a change to the files themselves (see [`ModelSettings::files`]).
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSettings {
+    pub backend: BackendKind,
+    pub model_path: String,
+    pub tokenizer_path: String,
+    pub system_prompt: Option<String>,
+    pub preset: Option<ModelPreset>,
+    pub device: DeviceSpec,
+    /// Sampling temperature, replacing the preset's (GGUF only)
+    pub temperature: Option<f64>,
+    /// Sampling seed, replacing the preset's (GGUF only)
+    pub seed: Option<u64>,
+    /// Token budget for a generated command (GGUF only)
+    pub max_tokens: Option<usize>,
+    /// Copies of the model loaded to generate for that many requests at once
+    /// (GGUF only)
+    pub instances: usize,
+    /// Rules from the safety policy that generated commands follow while they
+    /// are decoded (GGUF only)
+    pub grammar: Option<CommandGrammar>,
+}
+
+#[cfg(feature = "core")]
+impl ModelSettings {
+    /// The model and tokenizer files loading reads
+    ///
+    /// The registry compares them on every access to the cached model, so a file
+    /// replaced by a new fine-tune is picked up without restarting the daemon.
+    pub fn files(&self) -> Vec<PathBuf> {
+        vec![
+            PathBuf::from(&self.model_path),
+            PathBuf::from(&self.tokenizer_path),
+        ]
+    }
+}
+
+/// `[core]` section: settings for command generation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoreConfig {
+    /// Text framing every generation request, e.g. target distro or flag style
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Sampling temperature for GGUF models; `0.0` is deterministic (default
+    /// from the model's preset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Sampling seed for GGUF models, so the same request samples the same
+    /// commands (default from the model's preset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Maximum number of tokens in a generated command for GGUF models (default 96)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    /// MiB of model files `eidos daemon` and `eidos serve` keep loaded before
+    /// dropping the least recently used model (default 8192)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_memory_mb: Option<u64>,
+    /// Copies of a GGUF model `eidos daemon` loads to generate for that many
+    /// requests at once (default 1); each copy takes the model's memory again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instances: Option<usize>,
+    /// Hold GGUF models to the `[safety]` whitelist and metacharacter rules
+    /// while they generate, instead of rejecting commands afterwards
+    #[serde(default)]
+    pub constrained: bool,
+    /// Shell commands are written for: `bash` (default), `zsh`, `powershell` or
+    /// `cmd`; switches the prompt and the safety whitelist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_shell: Option<String>,
+}
+
+/// `[chat]` section: settings for chat replies
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatConfig {
+    /// Default answer length: `concise`, `normal` (default) or `detailed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<String>,
+    /// Provider to use: `openai`, `anthropic`, `gemini`, `ollama` or `custom`; by
+    /// default the first one whose API key or host is set in the environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Model name, replacing the provider's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Endpoint of the `ollama` or `custom` provider named by `provider`, e.g.
+    /// `http://localhost:11434`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Persona framing every conversation, e.g. `sysadmin` (none by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+}
+
+impl ChatConfig {
+    /// Use `provider` and `model` from the command line instead of the configured ones
+    ///
+    /// The configured `url` and `model` belong to the configured provider, so they
+    /// are dropped when `provider` names another one.
+    pub fn select(&mut self, provider: Option<&str>, model: Option<&str>) {
+        if let Some(provider) = provider {
+            if self.provider.as_deref() != Some(provider) {
+                self.url = None;
+                self.model = None;
+            }
+            self.provider = Some(provider.to_string());
+        }
+        if let Some(model) = model {
+            self.model = Some(model.to_string());
+        }
+    }
+}
+
+/// `[personas.NAME]` table: a named system prompt for `eidos chat --persona NAME`
+///
+/// A table named like a built-in persona (`sysadmin`, `teacher`) replaces it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PersonaConfig {
+    pub system_prompt: String,
+}
+
+/// `[context]` section: which parts of the environment requests may see
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// Add a snapshot of `env_vars` to command generation requests (off by default)
+    #[serde(default)]
+    pub capture_env: bool,
+    /// Variables to capture; defaults to SHELL, LANG, PWD and VIRTUAL_ENV
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_vars: Option<Vec<String>>,
+    /// Also send the snapshot to chat providers, which may be remote services
+    #[serde(default)]
+    pub allow_remote: bool,
+    /// Let `--summarize-output` send command output to a chat provider that is
+    /// not on this machine (off by default)
+    #[serde(default)]
+    pub allow_remote_output: bool,
+    /// Add facts about the working environment (directory name, shell, OS, git
+    /// repository, project type, top-level entries) to command generation
+    /// requests (off by default)
+    #[serde(default)]
+    pub facts: bool,
+}
+
+impl ContextConfig {
+    /// Apply `--context` (`Some(true)`: add the facts) or `--no-context`
+    /// (`Some(false)`: send nothing about the environment)
+    pub fn override_with(&mut self, flag: Option<bool>) {
+        match flag {
+            Some(true) => self.facts = true,
+            Some(false) => {
+                self.facts = false;
+                self.capture_env = false;
+            }
+            None => {}
+        }
+    }
+}
+
+/// `[cache]` section: reuse of generated commands
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Reuse the command generated earlier for the same prompt, context and model
+    /// (default true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Days a cached command is reused (default 30)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_days: Option<u64>,
+    /// Cached commands kept before the oldest are dropped (default 1000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_entries: Option<usize>,
+}
+
+impl CacheConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// `[translate]` section: post-processing of translations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TranslateConfig {
+    /// Rewrite numbers and dates in translations for the target language, e.g.
+    /// `1.234,5` -> `1,234.5` from German to English (off by default)
+    #[serde(default)]
+    pub localize: bool,
+    /// Language `eidos translate` translates into (default `en`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_lang: Option<String>,
+    /// Confidence (0.0 to 1.0) the detected source language needs before text is
+    /// translated (default 0.3); below it `eidos translate` asks for `--from`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_confidence: Option<f64>,
+    /// Requests sent at once when translating many texts, e.g. a chat session
+    /// with `eidos sessions translate` (default 4)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<usize>,
+    /// LibreTranslate server, e.g. `http://localhost:5000`; the API key is only read
+    /// from `LIBRETRANSLATE_API_KEY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Number and date formats by language code, replacing or adding to the
+    /// built-in ones (`[translate.locales.de-CH]`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub locales: BTreeMap<String, LocaleConfig>,
+}
+
+/// `[translate.locales.<lang>]` entry; unset fields keep the built-in format of
+/// the language
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocaleConfig {
+    /// Decimal separator, e.g. `","`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimal: Option<String>,
+    /// Separator between groups of three digits, e.g. `"'"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// `dmy`, `mdy` or `ymd`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_order: Option<String>,
+    /// Separator between date fields, e.g. `"."`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_separator: Option<String>,
+}
+
+#[cfg(feature = "translate")]
+impl LocaleConfig {
+    /// Format for `lang`, starting from its built-in one
+    fn format(&self, lang: &str) -> Result<LocaleFormat, String> {
+        let fields = [
+            &self.decimal,
+            &self.group,
+            &self.date_order,
+            &self.date_separator,
+        ];
+        let mut format = match LocaleFormat::for_language(lang) {
+            Some(format) => format,
+            None if fields.iter().all(|f| f.is_some()) => LocaleFormat::for_language("en")
+                .ok_or("no built-in English format")?,
+            None => {
+                return Err(format!(
+                    "locale '{}': no built-in format, so set decimal, group, date_order and date_separator",
+                    lang
+                ))
+            }
+        };
+
+        let separator = |field: &str, value: &Option<String>| -> Result<Option<char>, String> {
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if !c.is_ascii_digit() => Ok(Some(c)),
+                _ => Err(format!(
+                    "locale '{}': {} must be a single non-digit character, got '{}'",
+                    lang, field, value
+                )),
+            }
+        };
+        if let Some(c) = separator("decimal", &self.decimal)? {
+            format.decimal = c;
+        }
+        if let Some(c) = separator("group", &self.group)? {
+            format.group = c;
+        }
+        if let Some(c) = separator("date_separator", &self.date_separator)? {
+            format.date_separator = c;
+        }
+        if let Some(order) = &self.date_order {
+            format.date_order = order
+                .parse()
+                .map_err(|e| format!("locale '{}': {}", lang, e))?;
+        }
+        if format.decimal == format.group {
+            return Err(format!(
+                "locale '{}': decimal and group separators must differ",
+                lang
+            ));
+        }
+        Ok(format)
+    }
+}
+
+/// `[ui]` section: how much decoration human-readable output gets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiConfig {
+    /// Print configuration tips and suggestions after errors (default true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tips: Option<bool>,
+    /// Prefix errors and warnings with an emoji (default true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<bool>,
+    /// Colour results on a terminal, e.g. the diff between alternatives (default true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<bool>,
+    /// Link man and tldr pages mentioned in results (`tar(1)`, `man 5 crontab`);
+    /// detected from the terminal when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hyperlinks: Option<bool>,
+    /// Where man page links go: `web` (manpages.debian.org, default) or `man`
+    /// (`man:` URIs for the desktop's help viewer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub man_links: Option<String>,
+    /// Output format when `--output` is not given: `text` (default), `json` or `raw`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Language explanations and tips are shown in, e.g. `tr`; taken from the
+    /// locale (`LC_ALL`, `LC_MESSAGES`, `LANG`) when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+impl UiConfig {
+    pub fn tips(&self) -> bool {
+        self.tips.unwrap_or(true)
+    }
+
+    pub fn emoji(&self) -> bool {
+        self.emoji.unwrap_or(true)
+    }
+
+    pub fn color(&self) -> bool {
+        self.color.unwrap_or(true)
+    }
+
+    /// Default output format
+    pub fn output_format(&self) -> Result<OutputFormat, String> {
+        match self.output.as_deref() {
+            Some(format) => format.parse(),
+            None => Ok(OutputFormat::default()),
+        }
+    }
+
+    /// Where man page links go
+    pub fn man_links(&self) -> Result<ManLinks, String> {
+        match self.man_links.as_deref() {
+            Some(links) => links.parse(),
+            None => Ok(ManLinks::default()),
+        }
+    }
+
+    /// Display language: the configured one, otherwise the locale's; `None` for
+    /// English
+    pub fn lang(&self) -> Option<String> {
+        let locale = || {
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .filter_map(|var| env::var(var).ok())
+                .find(|value| !value.is_empty())
+        };
+        self.lang
+            .clone()
+            .or_else(locale)
+            .and_then(|lang| locale_lang(&lang))
+    }
+}
+
+/// Language code of a locale name or language tag: `tr` for `tr_TR.UTF-8`,
+/// `pt-BR` or `de@euro`; `None` for English and the `C`/`POSIX` locales
+fn locale_lang(locale: &str) -> Option<String> {
+    let lang = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    match lang.as_str() {
+        "" | "c" | "posix" | "en" => None,
+        _ => Some(lang),
+    }
+}
+
+/// Verdict printed in CI mode when a generated command is blocked
+pub const DEFAULT_BLOCKED_MESSAGE: &str = "blocked: {command} ({rule}, {severity})";
+
+/// `[ci]` section: how `--ci` runs report blocked commands
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CiConfig {
+    /// Run every invocation as with `--ci` (default false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Verdict line for a blocked command, with `{command}`, `{rule}`, `{matched}`,
+    /// `{severity}` and `{level}` placeholders (default [`DEFAULT_BLOCKED_MESSAGE`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_message: Option<String>,
+    /// Exit status when a command is blocked (default 3)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_exit_code: Option<u8>,
+}
+
+impl CiConfig {
+    /// The single-line verdict for `report`, which holds at least one violation
+    #[cfg(feature = "core")]
+    pub fn blocked_verdict(&self, report: &lib_core::SafetyReport, level: SafetyLevel) -> String {
+        let template = self
+            .blocked_message
+            .as_deref()
+            .unwrap_or(DEFAULT_BLOCKED_MESSAGE);
+        let (rule, matched, severity) = match report.primary() {
+            Some(v) => (
+                v.rule.to_string(),
+                v.matched.clone(),
+                v.severity.to_string(),
+            ),
+            None => Default::default(),
+        };
+        let verdict = template
+            .replace("{command}", &report.command)
+            .replace("{rule}", &rule)
+            .replace("{matched}", &matched)
+            .replace("{severity}", &severity)
+            .replace("{level}", &level.to_string());
+        verdict.lines().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// `[log]` section: format and per-module filters of the diagnostic log;
+/// `EIDOS_LOG_FORMAT` and `RUST_LOG` take precedence when set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// `text` (default) or `json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// Filter directives, e.g. `"lib_chat=debug,eidos=info"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+}
+
+impl LogConfig {
+    pub fn format(&self) -> Result<LogFormat, String> {
+        match env::var("EIDOS_LOG_FORMAT")
+            .ok()
+            .or_else(|| self.format.clone())
+        {
+            Some(format) => format.parse(),
+            None => Ok(LogFormat::default()),
+        }
+    }
+
+    pub fn filter(&self) -> Option<String> {
+        env::var("RUST_LOG").ok().or_else(|| self.filter.clone())
+    }
+}
+
+/// `[metrics]` section: counters and latencies kept in
+/// ~/.local/share/eidos/metrics.json (see `metrics`); off unless enabled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Loopback address where `eidos daemon` serves them to Prometheus, e.g.
+    /// `"127.0.0.1:9464"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+}
+
+impl MetricsConfig {
+    /// Address for the Prometheus endpoint, if metrics are enabled and one is set
+    ///
+    /// Only loopback addresses are accepted, so the metrics never leave the machine.
+    #[cfg(all(unix, feature = "serve"))]
+    pub fn listen_addr(&self) -> Result<Option<SocketAddr>, String> {
+        let Some(listen) = self.listen.as_deref().filter(|_| self.enabled) else {
+            return Ok(None);
+        };
+        let addr: SocketAddr = listen.trim().parse().map_err(|_| {
+            format!(
+                "[metrics] listen must be an address like 127.0.0.1:9464, got '{}'",
+                listen
+            )
+        })?;
+        if !addr.ip().is_loopback() {
+            return Err(format!(
+                "[metrics] listen must be a loopback address (127.0.0.1 or [::1]), got '{}'",
+                listen
+            ));
+        }
+        Ok(Some(addr))
+    }
+}
+
+/// `[http]` section: timeouts for requests to chat, embedding and translation
+/// services; `HTTP_REQUEST_TIMEOUT_SECS` / `HTTP_CONNECT_TIMEOUT_SECS` /
+/// `HTTP_HEALTH_TTL_SECS` apply when unset
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Limit for a whole request, including reading the reply (default 30)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_secs: Option<u64>,
+    /// Limit for establishing a connection (default 10)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+    /// How long a provider that could not be reached is skipped or tried with a
+    /// short connect timeout (default 60, 0 to always try normally)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_ttl_secs: Option<u64>,
+}
+
+impl HttpConfig {
+    pub fn request_timeout(&self) -> Result<Duration, String> {
+        Self::timeout(
+            self.request_timeout_secs,
+            "request_timeout_secs",
+            "HTTP_REQUEST_TIMEOUT_SECS",
+            DEFAULT_REQUEST_TIMEOUT_SECS,
+        )
+    }
+
+    pub fn connect_timeout(&self) -> Result<Duration, String> {
+        Self::timeout(
+            self.connect_timeout_secs,
+            "connect_timeout_secs",
+            "HTTP_CONNECT_TIMEOUT_SECS",
+            DEFAULT_CONNECT_TIMEOUT_SECS,
+        )
+    }
+
+    pub fn health_ttl(&self) -> Result<Duration, String> {
+        Self::secs(
+            self.health_ttl_secs,
+            "HTTP_HEALTH_TTL_SECS",
+            DEFAULT_HEALTH_TTL_SECS,
+        )
+        .map(Duration::from_secs)
+    }
+
+    fn secs(value: Option<u64>, var: &str, default: u64) -> Result<u64, String> {
+        match value {
+            Some(secs) => Ok(secs),
+            None => match env::var(var) {
+                Ok(secs) => secs
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{} must be a number of seconds, got '{}'", var, secs)),
+                Err(_) => Ok(default),
+            },
+        }
+    }
+
+    fn timeout(value: Option<u64>, key: &str, var: &str, default: u64) -> Result<Duration, String> {
+        let secs = Self::secs(value, var, default)?;
+        if secs == 0 {
+            return Err(format!("[http] {} must be at least 1 second", key));
+        }
+        Ok(Duration::from_secs(secs))
+    }
+}
+
+/// `[[presets]]` entry: settings for models whose file name contains `model`
+///
+/// An entry named like a built-in preset (`codellama`, `llama2`, `mistral`)
+/// overrides only the fields it sets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresetConfig {
+    /// Matched against the model file name, ignoring case and punctuation
+    pub model: String,
+    /// Prompt format: `plain` or `llama2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Generation stops at the first of these
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Stop when the output repeats the same tokens this many times in a row
+    /// (0 turns loop detection off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_repeats: Option<usize>,
+    /// End-of-sequence tokens, e.g. `<|im_end|>`, besides the ones the GGUF
+    /// file declares
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eos: Option<Vec<String>>,
+    /// Whether prompts start with the BOS token (defaults to the GGUF metadata)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add_bos: Option<bool>,
+}
+
+#[cfg(feature = "core")]
+impl PresetConfig {
+    /// `base` with the fields set in this entry replaced
+    fn apply(&self, base: ModelPreset) -> Result<ModelPreset, String> {
+        let mut preset = base;
+        if let Some(template) = &self.template {
+            preset.template = template.parse()?;
+        }
+        if let Some(eos) = &self.eos {
+            if eos.iter().any(|t| t.is_empty()) {
+                return Err(format!(
+                    "preset '{}': eos tokens must not be empty",
+                    self.model
+                ));
+            }
+            preset.eos_tokens = eos.clone();
+        }
+        if let Some(add_bos) = self.add_bos {
+            preset.add_bos = Some(add_bos);
+        }
+
+        let generation = &mut preset.generation;
+        if let Some(temperature) = self.temperature {
+            generation.temperature = temperature;
+        }
+        if let Some(top_p) = self.top_p {
+            generation.top_p = Some(top_p);
+        }
+        if let Some(top_k) = self.top_k {
+            generation.top_k = Some(top_k);
+        }
+        if let Some(penalty) = self.repeat_penalty {
+            generation.repeat_penalty = penalty;
+        }
+        if let Some(seed) = self.seed {
+            generation.seed = seed;
+        }
+        if let Some(stop) = &self.stop {
+            generation.stop = stop.clone();
+        }
+        if let Some(repeats) = self.loop_repeats {
+            generation.loop_repeats = repeats;
+        }
+        generation
+            .validate()
+            .map_err(|e| format!("preset '{}': {}", self.model, e))?;
+        Ok(preset)
+    }
+}
+
+/// `[safety]` section: validation level plus extra allow/deny entries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    /// `strict`, `standard` (default), `review` or `permissive`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Commands or command prefixes to allow, e.g. `"git status"`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Commands or command prefixes to reject even if otherwise allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
 }
 
 impl Config {
@@ -20,21 +781,40 @@ impl Config {
     /// 2. Local config file (./eidos.toml)
     /// 3. User config file (~/.config/eidos/eidos.toml)
     /// 4. Built-in defaults
+    ///
+    /// Deprecated environment variables fill in the keys they stand for (see
+    /// `legacy_env`), recorded in `legacy`.
     pub fn load() -> Result<Self, String> {
-        // Priority 1: Environment variables (highest priority)
-        if let Ok(config) = Self::from_env() {
-            return Ok(config);
+        #[allow(unused_mut)]
+        let mut config = Self::load_sources()?;
+        #[cfg(any(feature = "chat", feature = "translate"))]
+        {
+            let plan = legacy_env::plan(&config, |var| env::var(var).ok());
+            legacy_env::apply(&mut config, &plan.settings);
+            config.legacy = plan.settings;
         }
+        Ok(config)
+    }
 
-        // Priority 2: Local config file
-        if let Ok(config) = Self::from_file("eidos.toml") {
-            return Ok(config);
+    fn load_sources() -> Result<Self, String> {
+        // Priority 1: Environment variables (highest priority); once both paths
+        // are set, a malformed EIDOS_* value is an error rather than a fallback
+        if env::var_os("EIDOS_MODEL_PATH").is_some()
+            && env::var_os("EIDOS_TOKENIZER_PATH").is_some()
+        {
+            return Self::from_env();
+        }
+
+        // Priority 2: Local config file; one that exists but does not parse is an
+        // error, not a reason to fall back to the defaults
+        if Path::new("eidos.toml").is_file() {
+            return Self::from_file("eidos.toml");
         }
 
         // Priority 3: User config file
         if let Some(user_config_path) = Self::get_user_config_path() {
-            if let Ok(config) = Self::from_file(&user_config_path.to_string_lossy()) {
-                return Ok(config);
+            if user_config_path.is_file() {
+                return Self::from_file(&user_config_path.to_string_lossy());
             }
         }
 
@@ -48,6 +828,105 @@ impl Config {
         Some(PathBuf::from(home).join(".config/eidos/eidos.toml"))
     }
 
+    /// Config file `eidos model pull` and `eidos config migrate` update: ./eidos.toml
+    /// when there is one, since it takes precedence, otherwise the user config file
+    #[cfg(any(feature = "models", feature = "chat", feature = "translate"))]
+    pub fn editable_path() -> Option<PathBuf> {
+        let local = Path::new("eidos.toml");
+        if local.is_file() {
+            return Some(fs::canonicalize(local).unwrap_or_else(|_| local.to_path_buf()));
+        }
+        Self::get_user_config_path()
+    }
+
+    /// Point the config file at `path` at a model, creating the file if needed
+    ///
+    /// Only `model_path`, `tokenizer_path` and an existing `backend` key change;
+    /// other settings, comments and formatting are kept.
+    #[cfg(feature = "models")]
+    pub fn set_model_paths(
+        path: &Path,
+        model: &Path,
+        tokenizer: &Path,
+        backend: BackendKind,
+    ) -> Result<(), String> {
+        let utf8 = |path: &Path, kind: &str| {
+            path.to_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("Invalid {} path encoding", kind))
+        };
+        let (model, tokenizer) = (utf8(model, "model")?, utf8(tokenizer, "tokenizer")?);
+        Self::edit_file(path, |document| {
+            document["model_path"] = toml_edit::value(model);
+            document["tokenizer_path"] = toml_edit::value(tokenizer);
+            if document.contains_key("backend") {
+                document["backend"] = toml_edit::value(backend.as_str());
+            }
+        })
+    }
+
+    /// Write the settings that deprecated environment variables stand for into the
+    /// config file at `path`, unless it sets them already; with `dry_run` only work
+    /// out what they are
+    #[cfg(any(feature = "chat", feature = "translate"))]
+    pub fn migrate_legacy_env(path: &Path, dry_run: bool) -> Result<legacy_env::Plan, String> {
+        let sections: EditableSections = match fs::read_to_string(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => EditableSections::default(),
+            result => {
+                let contents =
+                    result.map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+                toml::from_str(&contents).map_err(|e| {
+                    format!("Failed to parse config file '{}': {}", path.display(), e)
+                })?
+            }
+        };
+        let current = Config {
+            chat: sections.chat,
+            translate: sections.translate,
+            http: sections.http,
+            ..Config::default()
+        };
+        let plan = legacy_env::plan(&current, |var| env::var(var).ok());
+        if dry_run || plan.settings.is_empty() {
+            return Ok(plan);
+        }
+        Self::edit_file(path, |document| {
+            for setting in &plan.settings {
+                let table = document[setting.section].or_insert(toml_edit::table());
+                table[setting.key] = match &setting.value {
+                    legacy_env::Value::Text(text) => toml_edit::value(text.as_str()),
+                    legacy_env::Value::Secs(secs) => toml_edit::value(*secs as i64),
+                };
+            }
+        })?;
+        Ok(plan)
+    }
+
+    /// Change the TOML file at `path` with `edit`, keeping its comments and
+    /// formatting; a missing file starts out empty
+    ///
+    /// The result is checked before it replaces the file.
+    #[cfg(any(feature = "models", feature = "chat", feature = "translate"))]
+    fn edit_file(
+        path: &Path,
+        edit: impl FnOnce(&mut toml_edit::DocumentMut),
+    ) -> Result<(), String> {
+        let contents = match fs::read_to_string(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            result => result.map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?,
+        };
+        let mut document: toml_edit::DocumentMut = contents
+            .parse()
+            .map_err(|e| format!("Failed to parse config file '{}': {}", path.display(), e))?;
+        edit(&mut document);
+
+        let contents = document.to_string();
+        toml::from_str::<EditableSections>(&contents)
+            .map_err(|e| format!("Invalid config file '{}': {}", path.display(), e))?;
+        lib_util::write_atomic(path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
     /// Load config from a TOML file
     pub fn from_file(path: &str) -> Result<Self, String> {
         let contents = fs::read_to_string(path)
@@ -66,13 +945,384 @@ impl Config {
         Ok(Self {
             model_path: PathBuf::from(model_path),
             tokenizer_path: PathBuf::from(tokenizer_path),
+            backend: env::var("EIDOS_BACKEND").ok(),
+            device: env::var("EIDOS_DEVICE").ok(),
+            default_command: env::var("EIDOS_DEFAULT_COMMAND").ok(),
+            core: CoreConfig {
+                system_prompt: env::var("EIDOS_SYSTEM_PROMPT").ok(),
+                temperature: parse_env("EIDOS_TEMPERATURE")?,
+                seed: parse_env("EIDOS_SEED")?,
+                max_tokens: parse_env("EIDOS_MAX_TOKENS")?,
+                model_memory_mb: parse_env("EIDOS_MODEL_MEMORY_MB")?,
+                instances: parse_env("EIDOS_MODEL_INSTANCES")?,
+                constrained: env::var("EIDOS_CONSTRAINED").is_ok_and(|v| is_truthy(&v)),
+                target_shell: env::var("EIDOS_TARGET_SHELL").ok(),
+            },
+            safety: SafetyConfig {
+                level: env::var("EIDOS_SAFETY_LEVEL").ok(),
+                ..SafetyConfig::default()
+            },
+            chat: ChatConfig {
+                verbosity: env::var("EIDOS_CHAT_VERBOSITY").ok(),
+                provider: env::var("EIDOS_CHAT_PROVIDER").ok(),
+                model: env::var("EIDOS_CHAT_MODEL").ok(),
+                persona: env::var("EIDOS_CHAT_PERSONA").ok(),
+                url: env::var("EIDOS_CHAT_URL").ok(),
+            },
+            context: ContextConfig {
+                capture_env: env::var("EIDOS_CAPTURE_ENV").is_ok_and(|v| is_truthy(&v)),
+                facts: env::var("EIDOS_CONTEXT").is_ok_and(|v| is_truthy(&v)),
+                ..ContextConfig::default()
+            },
+            translate: TranslateConfig {
+                localize: env::var("EIDOS_LOCALIZE").is_ok_and(|v| is_truthy(&v)),
+                target_lang: env::var("EIDOS_TRANSLATE_TARGET").ok(),
+                min_confidence: env::var("EIDOS_TRANSLATE_MIN_CONFIDENCE")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                url: env::var("EIDOS_TRANSLATE_URL").ok(),
+                ..TranslateConfig::default()
+            },
+            cache: CacheConfig {
+                enabled: env::var("EIDOS_NO_CACHE").ok().map(|v| !is_truthy(&v)),
+                ..CacheConfig::default()
+            },
+            ui: UiConfig {
+                tips: env::var("EIDOS_NO_TIPS").ok().map(|v| !is_truthy(&v)),
+                emoji: env::var("EIDOS_NO_EMOJI").ok().map(|v| !is_truthy(&v)),
+                // NO_COLOR is honoured by the CLI whatever the config says
+                color: None,
+                hyperlinks: env::var("EIDOS_NO_HYPERLINKS").ok().map(|v| !is_truthy(&v)),
+                man_links: env::var("EIDOS_MAN_LINKS").ok(),
+                output: env::var("EIDOS_OUTPUT").ok(),
+                lang: env::var("EIDOS_LANG").ok(),
+            },
+            ci: CiConfig {
+                enabled: env::var("EIDOS_CI").is_ok_and(|v| is_truthy(&v)),
+                ..CiConfig::default()
+            },
+            // HTTP_*_TIMEOUT_SECS are read by `HttpConfig` whatever the source
+            http: HttpConfig::default(),
+            // So are EIDOS_LOG_FORMAT and RUST_LOG by `LogConfig`
+            log: LogConfig::default(),
+            metrics: MetricsConfig::default(),
+            presets: Vec::new(),
+            personas: BTreeMap::new(),
+            #[cfg(any(feature = "chat", feature = "translate"))]
+            legacy: Vec::new(),
         })
     }
 
+    /// Build the command safety policy described by the `[safety]` section
+    #[cfg(feature = "core")]
+    pub fn safety_policy(&self) -> Result<SafetyPolicy, String> {
+        let level = match self.safety.level.as_deref() {
+            Some(level) => level.parse::<SafetyLevel>()?,
+            None => SafetyLevel::default(),
+        };
+
+        Ok(SafetyPolicy::new(level)
+            .with_target(self.target_shell()?)
+            .with_allow(&self.safety.allow)
+            .with_deny(&self.safety.deny))
+    }
+
+    /// Shell generated commands are for (`[core] target_shell`)
+    #[cfg(feature = "core")]
+    pub fn target_shell(&self) -> Result<TargetShell, String> {
+        match self.core.target_shell.as_deref() {
+            Some(shell) => shell.parse(),
+            None => Ok(TargetShell::default()),
+        }
+    }
+
+    /// Backend that runs the configured model
+    #[cfg(feature = "core")]
+    pub fn backend_kind(&self) -> Result<BackendKind, String> {
+        match self.backend.as_deref() {
+            Some(backend) => backend.parse(),
+            None => Ok(BackendKind::from_path(&self.model_path)),
+        }
+    }
+
+    /// Device requested for GGUF inference
+    #[cfg(feature = "core")]
+    pub fn device_spec(&self) -> Result<DeviceSpec, String> {
+        match self.device.as_deref() {
+            Some(device) => device.parse(),
+            None => Ok(DeviceSpec::default()),
+        }
+    }
+
+    /// Settings for loading the configured model, checking every model-related key
+    #[cfg(feature = "core")]
+    pub fn model_settings(&self) -> Result<ModelSettings, String> {
+        let utf8 = |path: &Path, kind: &str| {
+            path.to_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("Invalid {} path encoding", kind))
+        };
+        Ok(ModelSettings {
+            backend: self.backend_kind()?,
+            model_path: utf8(&self.model_path, "model")?,
+            tokenizer_path: utf8(&self.tokenizer_path, "tokenizer")?,
+            system_prompt: self
+                .target_shell()?
+                .system_prompt(self.core.system_prompt.as_deref()),
+            preset: self.model_preset()?,
+            device: self.device_spec()?,
+            temperature: match self.core.temperature {
+                Some(t) if !t.is_finite() || t < 0.0 => {
+                    return Err(format!(
+                        "[core] temperature must be 0 or greater, got {}",
+                        t
+                    ))
+                }
+                t => t,
+            },
+            seed: self.core.seed,
+            max_tokens: match self.core.max_tokens {
+                Some(0) => return Err("[core] max_tokens must be at least 1".to_string()),
+                n => n,
+            },
+            instances: match self.core.instances {
+                Some(0) => return Err("[core] instances must be at least 1".to_string()),
+                n => n.unwrap_or(1),
+            },
+            grammar: match self.core.constrained {
+                true => Some(self.safety_policy()?.grammar()),
+                false => None,
+            },
+        })
+    }
+
+    /// Bytes of model files kept loaded at once (`[core] model_memory_mb`)
+    #[cfg(feature = "core")]
+    pub fn model_memory_budget(&self) -> u64 {
+        self.core
+            .model_memory_mb
+            .map_or(DEFAULT_MEMORY_BUDGET, |mb| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Built-in presets plus the `[[presets]]` entries
+    #[cfg(feature = "core")]
+    pub fn preset_registry(&self) -> Result<PresetRegistry, String> {
+        let mut registry = PresetRegistry::builtin();
+        for entry in &self.presets {
+            if entry.model.trim().is_empty() {
+                return Err("preset entries need a non-empty `model`".to_string());
+            }
+            let base = registry
+                .get(&entry.model)
+                .cloned()
+                .unwrap_or_else(|| ModelPreset::new(&entry.model));
+            registry.insert(entry.apply(base)?);
+        }
+        Ok(registry)
+    }
+
+    /// Preset for the configured model, if its file name matches one
+    #[cfg(feature = "core")]
+    pub fn model_preset(&self) -> Result<Option<ModelPreset>, String> {
+        Ok(self.preset_registry()?.find(&self.model_path).cloned())
+    }
+
+    /// Default chat verbosity from the `[chat]` section
+    #[cfg(feature = "chat")]
+    pub fn chat_verbosity(&self) -> Result<Verbosity, String> {
+        match self.chat.verbosity.as_deref() {
+            Some(verbosity) => verbosity.parse(),
+            None => Ok(Verbosity::default()),
+        }
+    }
+
+    /// Built-in personas plus the `[personas.NAME]` tables
+    #[cfg(feature = "chat")]
+    pub fn persona_registry(&self) -> Result<PersonaRegistry, String> {
+        let mut registry = PersonaRegistry::builtin();
+        for (name, persona) in &self.personas {
+            if persona.system_prompt.trim().is_empty() {
+                return Err(format!(
+                    "[personas.{}] needs a non-empty system_prompt",
+                    name
+                ));
+            }
+            registry.insert(name.as_str(), persona.system_prompt.trim());
+        }
+        Ok(registry)
+    }
+
+    /// Client for the chat provider selected by `[chat] provider` (at `[chat] url`
+    /// for `ollama` and `custom`, or the first one configured in the environment),
+    /// with the `[http]` timeouts
+    ///
+    /// `None` when the provider's API key or host is not set.
+    #[cfg(feature = "chat")]
+    pub fn chat_client(&self) -> Result<Option<ApiClient>, String> {
+        let health = health::load();
+        let ttl = self.http.health_ttl()?;
+        let now = lib_util::unix_now();
+        let provider = match (self.chat.provider.as_deref(), &self.chat.url) {
+            (Some(name), Some(url)) => {
+                Some(ApiProvider::at_url(name, url).map_err(|e| e.to_string())?)
+            }
+            (None, Some(_)) => {
+                return Err("[chat] url needs provider = \"ollama\" or \"custom\"".to_string())
+            }
+            (Some(name), None) => match ApiProvider::from_env_named(name) {
+                Ok(provider) => Some(provider),
+                Err(ChatError::NoProviderError) => None,
+                Err(e) => return Err(e.to_string()),
+            },
+            // The first configured provider that was not found unreachable lately
+            (None, None) => {
+                let mut configured: Vec<ApiProvider> = PROVIDER_NAMES
+                    .iter()
+                    .filter_map(|name| ApiProvider::from_env_named(name).ok())
+                    .collect();
+                let names: Vec<&str> = configured.iter().map(ApiProvider::name).collect();
+                let elected = health.elect(&names, ttl, now);
+                if elected != names.first().copied() {
+                    debug!(
+                        "Skipping unreachable chat provider {}; using {}",
+                        names[0],
+                        elected.unwrap_or_default()
+                    );
+                }
+                let index = names.iter().position(|name| Some(*name) == elected);
+                index.map(|i| configured.swap_remove(i))
+            }
+        };
+        let Some(mut provider) = provider else {
+            return Ok(None);
+        };
+        if let Some(model) = &self.chat.model {
+            provider = provider.with_model(model);
+        }
+        let mut timeouts = HttpTimeouts {
+            request: self.http.request_timeout()?,
+            connect: self.http.connect_timeout()?,
+        };
+        if health.is_down(provider.name(), ttl, now) {
+            debug!(
+                "Chat provider {} was unreachable lately; trying with a short connect timeout",
+                provider.name()
+            );
+            timeouts.connect = timeouts.connect.min(health::DOWN_CONNECT_TIMEOUT);
+        }
+        ApiClient::with_timeouts(provider, timeouts)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Translation service from `[translate] url` with
+    /// the `[http]` timeouts, `min_confidence` and `max_concurrent_requests`,
+    /// falling back to offline translation
+    #[cfg(feature = "translate")]
+    pub fn translator(&self) -> Result<Translate, String> {
+        let provider = match &self.translate.url {
+            Some(url) => TranslatorProvider::LibreTranslate {
+                url: url.clone(),
+                api_key: env::var("LIBRETRANSLATE_API_KEY").ok(),
+            },
+            None => TranslatorProvider::from_env().unwrap_or(TranslatorProvider::Offline),
+        };
+        let mut timeouts = lib_translate::translator::HttpTimeouts {
+            request: self.http.request_timeout()?,
+            connect: self.http.connect_timeout()?,
+        };
+        let ttl = self.http.health_ttl()?;
+        if matches!(provider, TranslatorProvider::LibreTranslate { .. })
+            && health::load().is_down(health::LIBRETRANSLATE, ttl, lib_util::unix_now())
+        {
+            debug!("LibreTranslate was unreachable lately; trying with a short connect timeout");
+            timeouts.connect = timeouts.connect.min(health::DOWN_CONNECT_TIMEOUT);
+        }
+        let mut translate = Translator::with_timeouts(provider, timeouts)
+            .map(Translate::from_translator)
+            .map_err(|e| e.to_string())?;
+        if let Some(max) = self.translate.max_concurrent_requests {
+            translate = translate.with_max_concurrent_requests(max);
+        }
+        Ok(match self.translate.min_confidence {
+            Some(min) if !(0.0..=1.0).contains(&min) => {
+                return Err(format!(
+                    "[translate] min_confidence must be between 0.0 and 1.0, got {}",
+                    min
+                ))
+            }
+            Some(min) => translate.with_min_confidence(min),
+            None => translate,
+        })
+    }
+
+    /// Number and date localizer with the `[translate.locales]` formats
+    #[cfg(feature = "translate")]
+    pub fn localizer(&self) -> Result<Localizer, String> {
+        self.translate
+            .locales
+            .iter()
+            .try_fold(Localizer::new(), |localizer, (lang, locale)| {
+                Ok(localizer.with_format(lang, locale.format(lang)?))
+            })
+    }
+
+    /// Cache of commands generated by the configured model; `None` when `[cache]`
+    /// is disabled
+    ///
+    /// The model is identified by its settings and the size and modification time
+    /// of its files, so a new fine-tune or a changed preset starts afresh.
+    #[cfg(feature = "core")]
+    pub fn command_cache(&self) -> Option<CommandCache> {
+        if !self.cache.enabled() {
+            return None;
+        }
+        let settings = self.model_settings().ok()?;
+        let model = format!("{:?} {:?}", settings, FileStamp::of(&settings.files()));
+        let mut cache = CommandCache::new(paths::command_cache_dir(), model);
+        if let Some(days) = self.cache.ttl_days {
+            cache = cache.with_ttl(Duration::from_secs(days * 24 * 60 * 60));
+        }
+        if let Some(max_entries) = self.cache.max_entries {
+            cache = cache.with_max_entries(max_entries);
+        }
+        Some(cache)
+    }
+
+    /// Environment snapshot for local command generation; empty unless
+    /// `[context] capture_env` or `facts` is on
+    pub fn env_context(&self) -> EnvContext {
+        let context = match &self.context.env_vars {
+            _ if !self.context.capture_env => EnvContext::default(),
+            Some(names) => EnvContext::capture(names),
+            None => EnvContext::capture(DEFAULT_ENV_VARS),
+        };
+        if self.context.facts {
+            context.with_facts()
+        } else {
+            context
+        }
+    }
+
+    /// Environment snapshot for requests to chat providers; empty unless
+    /// `[context] allow_remote` is also on
+    pub fn remote_env_context(&self) -> EnvContext {
+        if self.context.allow_remote {
+            self.env_context()
+        } else {
+            EnvContext::default()
+        }
+    }
+
     /// Validate that the configured paths exist and are safe to use
     pub fn validate(&self) -> Result<(), String> {
-        // Validate model path
-        Self::validate_file_path(&self.model_path, "Model", 2 * 1024 * 1024 * 1024)?; // 2GB max
+        // Validate model path; quantized GGUF models of 7B+ parameters run to several GB
+        let max_model_size = if self.model_path.extension().is_some_and(|e| e == "gguf") {
+            32 * 1024 * 1024 * 1024 // 32GB max
+        } else {
+            2 * 1024 * 1024 * 1024 // 2GB max
+        };
+        Self::validate_file_path(&self.model_path, "Model", max_model_size)?;
 
         // Validate tokenizer path
         Self::validate_file_path(&self.tokenizer_path, "Tokenizer", 100 * 1024 * 1024)?; // 100MB max
@@ -81,7 +1331,7 @@ impl Config {
     }
 
     /// Validate a file path for security and safety
-    fn validate_file_path(path: &PathBuf, file_type: &str, max_size: u64) -> Result<(), String> {
+    fn validate_file_path(path: &Path, file_type: &str, max_size: u64) -> Result<(), String> {
         // Check if file exists
         if !path.exists() {
             return Err(format!("{} file not found: {}", file_type, path.display()));
@@ -108,12 +1358,8 @@ impl Config {
         }
 
         // Get file metadata
-        let metadata = fs::metadata(&canonical_path).map_err(|e| {
-            format!(
-                "Failed to read {} file metadata: {}",
-                file_type, e
-            )
-        })?;
+        let metadata = fs::metadata(&canonical_path)
+            .map_err(|e| format!("Failed to read {} file metadata: {}", file_type, e))?;
 
         // Check if it's a regular file (not directory or other special file)
         if !metadata.is_file() {
@@ -155,11 +1401,11 @@ impl Config {
 
             // Warn if file is world-readable with write permissions
             if mode & 0o002 != 0 {
-                eprintln!(
-                    "⚠️  Warning: {} file is world-writable: {}",
+                crate::output::warning(format!(
+                    "Warning: {} file is world-writable: {}",
                     file_type,
                     path.display()
-                );
+                ));
             }
         }
 
@@ -172,10 +1418,47 @@ impl Default for Config {
         Self {
             model_path: PathBuf::from("model.onnx"),
             tokenizer_path: PathBuf::from("tokenizer.json"),
+            backend: None,
+            device: None,
+            default_command: None,
+            core: CoreConfig::default(),
+            safety: SafetyConfig::default(),
+            chat: ChatConfig::default(),
+            context: ContextConfig::default(),
+            translate: TranslateConfig::default(),
+            cache: CacheConfig::default(),
+            ui: UiConfig::default(),
+            ci: CiConfig::default(),
+            http: HttpConfig::default(),
+            log: LogConfig::default(),
+            metrics: MetricsConfig::default(),
+            presets: Vec::new(),
+            personas: BTreeMap::new(),
+            #[cfg(any(feature = "chat", feature = "translate"))]
+            legacy: Vec::new(),
         }
     }
 }
 
+/// Value of the environment variable `var`, parsed; `None` when it is not set
+fn parse_env<T: std::str::FromStr>(var: &str) -> Result<Option<T>, String> {
+    match env::var(var) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid value for {}: '{}'", var, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,6 +1469,583 @@ mod tests {
         let config = Config::default();
         assert_eq!(config.model_path, PathBuf::from("model.onnx"));
         assert_eq!(config.tokenizer_path, PathBuf::from("tokenizer.json"));
+        assert!(config.default_command.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "translate")]
+    fn test_config_locales() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [translate]
+            localize = true
+
+            [translate.locales.de-CH]
+            decimal = "."
+            group = "'"
+            "#,
+        )
+        .unwrap();
+        assert!(config.translate.localize);
+        let localizer = config.localizer().unwrap();
+        assert_eq!(
+            localizer.localize("1.234,5 am 05.03.2024", "de", "de-CH"),
+            "1'234.5 am 05.03.2024"
+        );
+
+        let invalid = |locale: &str| {
+            let config: Config = toml::from_str(&format!(
+                "model_path = \"m\"\ntokenizer_path = \"t\"\n[translate.locales.xx]\n{}",
+                locale
+            ))
+            .unwrap();
+            config.localizer().unwrap_err()
+        };
+        assert!(invalid("decimal = \",\"").contains("no built-in format"));
+        assert!(invalid(
+            "decimal = \",\"\ngroup = \",\"\ndate_order = \"dmy\"\ndate_separator = \".\""
+        )
+        .contains("must differ"));
+        assert!(invalid(
+            "decimal = \"ab\"\ngroup = \".\"\ndate_order = \"dmy\"\ndate_separator = \".\""
+        )
+        .contains("single"));
+    }
+
+    #[test]
+    fn test_config_default_command_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+            default_command = "core"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.default_command.as_deref(), Some("core"));
+
+        // The key is optional
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+            "#,
+        )
+        .unwrap();
+        assert!(config.default_command.is_none());
+    }
+
+    #[test]
+    fn test_config_core_section() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [core]
+            system_prompt = "Target distro: Debian 12. Prefer long flags."
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.core.system_prompt.as_deref(),
+            Some("Target distro: Debian 12. Prefer long flags.")
+        );
+        assert!(Config::default().core.system_prompt.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_config_generation_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.gguf"
+            tokenizer_path = "tokenizer.json"
+
+            [core]
+            temperature = 0.4
+            seed = 7
+            max_tokens = 128
+            "#,
+        )
+        .unwrap();
+        let settings = config.model_settings().unwrap();
+        assert_eq!(settings.temperature, Some(0.4));
+        assert_eq!(settings.seed, Some(7));
+        assert_eq!(settings.max_tokens, Some(128));
+
+        let mut config = Config::default();
+        config.core.temperature = Some(-1.0);
+        assert!(config.model_settings().unwrap_err().contains("temperature"));
+        config.core.temperature = None;
+        config.core.max_tokens = Some(0);
+        assert!(config.model_settings().unwrap_err().contains("max_tokens"));
+        config.core.max_tokens = None;
+        assert_eq!(config.model_settings().unwrap().instances, 1);
+        config.core.instances = Some(0);
+        assert!(config.model_settings().unwrap_err().contains("instances"));
+        config.core.instances = None;
+        config.core.constrained = true;
+        config.safety.level = Some("strict".to_string());
+        let grammar = config.model_settings().unwrap().grammar.unwrap();
+        assert!(grammar.accepts("ls -la"));
+        assert!(!grammar.allows_prefix("find ."));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_config_target_shell() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.gguf"
+            tokenizer_path = "tokenizer.json"
+
+            [core]
+            system_prompt = "Prefer long flags."
+            target_shell = "powershell"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.target_shell(), Ok(TargetShell::PowerShell));
+        let policy = config.safety_policy().unwrap();
+        assert!(policy.is_safe("Get-ChildItem -Force"));
+        assert!(!policy.is_safe("Remove-Item notes.txt"));
+        let system_prompt = config.model_settings().unwrap().system_prompt.unwrap();
+        assert!(system_prompt.contains("PowerShell on Windows"));
+        assert!(system_prompt.ends_with("Prefer long flags."));
+
+        assert_eq!(Config::default().target_shell(), Ok(TargetShell::Bash));
+        let mut config = Config::default();
+        config.core.target_shell = Some("fish".to_string());
+        assert!(config.safety_policy().unwrap_err().contains("target shell"));
+        assert!(config.model_settings().is_err());
+    }
+
+    #[test]
+    fn test_display_lang_from_locale() {
+        assert_eq!(locale_lang("tr_TR.UTF-8").as_deref(), Some("tr"));
+        assert_eq!(locale_lang("pt-BR").as_deref(), Some("pt"));
+        assert_eq!(locale_lang("de@euro").as_deref(), Some("de"));
+        assert_eq!(locale_lang("en_US.UTF-8"), None);
+        assert_eq!(locale_lang("C.UTF-8"), None);
+        assert_eq!(locale_lang("POSIX"), None);
+
+        let ui = UiConfig {
+            lang: Some("TR".to_string()),
+            ..UiConfig::default()
+        };
+        assert_eq!(ui.lang().as_deref(), Some("tr"));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_file_stamp_changes_with_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            model_path: dir.path().join("model.onnx"),
+            tokenizer_path: dir.path().join("tokenizer.json"),
+            ..Config::default()
+        };
+        let settings = config.model_settings().unwrap();
+        let stamp = || FileStamp::of(&settings.files());
+
+        let missing = stamp();
+        fs::write(&config.model_path, "weights").unwrap();
+        fs::write(&config.tokenizer_path, "{}").unwrap();
+        let written = stamp();
+        assert_ne!(written, missing);
+        assert_eq!(stamp(), written);
+
+        // A new fine-tune copied over the old file
+        fs::write(&config.model_path, "new weights").unwrap();
+        assert_ne!(stamp(), written);
+        assert_eq!(stamp().bytes(), 13);
+    }
+
+    #[test]
+    fn test_chat_select_overrides_provider_and_model() {
+        let configured = ChatConfig {
+            provider: Some("ollama".to_string()),
+            model: Some("llama3".to_string()),
+            url: Some("http://gpu-box:11434".to_string()),
+            ..Default::default()
+        };
+
+        let mut chat = configured.clone();
+        chat.select(Some("ollama"), Some("qwen2.5"));
+        assert_eq!(chat.url.as_deref(), Some("http://gpu-box:11434"));
+        assert_eq!(chat.model.as_deref(), Some("qwen2.5"));
+
+        // Another provider does not inherit the endpoint or model of the configured one
+        let mut chat = configured.clone();
+        chat.select(Some("openai"), None);
+        assert_eq!(chat.provider.as_deref(), Some("openai"));
+        assert_eq!(chat.url, None);
+        assert_eq!(chat.model, None);
+
+        let mut chat = configured;
+        chat.select(None, None);
+        assert_eq!(chat.model.as_deref(), Some("llama3"));
+    }
+
+    #[test]
+    #[cfg(feature = "chat")]
+    fn test_config_chat_section() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [chat]
+            verbosity = "concise"
+            persona = "reviewer"
+
+            [personas.reviewer]
+            system_prompt = "Review shell scripts for portability."
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.chat_verbosity(), Ok(Verbosity::Concise));
+        assert_eq!(config.chat.persona.as_deref(), Some("reviewer"));
+        let personas = config.persona_registry().unwrap();
+        assert_eq!(personas.names(), ["reviewer", "sysadmin", "teacher"]);
+
+        let mut config = Config::default();
+        config
+            .personas
+            .insert("empty".to_string(), PersonaConfig::default());
+        assert!(config.persona_registry().is_err());
+        assert_eq!(Config::default().chat_verbosity(), Ok(Verbosity::Normal));
+
+        let mut config = Config::default();
+        config.chat.verbosity = Some("chatty".to_string());
+        assert!(config.chat_verbosity().is_err());
+
+        let mut config = Config::default();
+        config.chat.provider = Some("bard".to_string());
+        match config.chat_client() {
+            Err(e) => assert!(e.contains("Unknown chat provider")),
+            Ok(_) => panic!("expected an unknown provider error"),
+        }
+    }
+
+    #[test]
+    fn test_config_context_section() {
+        env::set_var("EIDOS_TEST_CONFIG_CONTEXT", "/opt/venv");
+
+        // Off unless asked for
+        assert!(Config::default().env_context().is_empty());
+
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [context]
+            capture_env = true
+            env_vars = ["EIDOS_TEST_CONFIG_CONTEXT"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.env_context().render().as_deref(),
+            Some("Environment:\nEIDOS_TEST_CONFIG_CONTEXT=/opt/venv")
+        );
+        // Chat providers only see it with allow_remote
+        assert!(config.remote_env_context().is_empty());
+
+        let mut config = config;
+        config.context.allow_remote = true;
+        assert!(!config.remote_env_context().is_empty());
+
+        // --context adds the facts, --no-context drops everything
+        config.context.override_with(Some(true));
+        assert!(!config.env_context().facts().is_empty());
+        config.context.override_with(Some(false));
+        assert!(config.env_context().is_empty());
+
+        env::remove_var("EIDOS_TEST_CONFIG_CONTEXT");
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_config_presets() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "models/mistral-7b-instruct.Q4_K_M.gguf"
+            tokenizer_path = "tokenizer.json"
+
+            [[presets]]
+            model = "mistral"
+            temperature = 0.1
+
+            [[presets]]
+            model = "my-finetune"
+            template = "llama2"
+            stop = ["---"]
+            loop_repeats = 0
+            eos = ["<|im_end|>"]
+            add_bos = false
+            "#,
+        )
+        .unwrap();
+
+        // Overriding a built-in keeps the fields the entry does not set
+        let preset = config.model_preset().unwrap().unwrap();
+        assert_eq!(preset.generation.temperature, 0.1);
+        assert_eq!(preset.generation.top_p, Some(0.9));
+
+        let registry = config.preset_registry().unwrap();
+        let custom = registry.find("my_finetune-v2.gguf").unwrap();
+        assert_eq!(custom.template, lib_core::PromptTemplate::Llama2);
+        assert_eq!(custom.generation.stop, vec!["---".to_string()]);
+        assert_eq!(custom.generation.loop_repeats, 0);
+        assert_eq!(custom.eos_tokens, vec!["<|im_end|>".to_string()]);
+        assert_eq!(custom.add_bos, Some(false));
+
+        let invalid: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [[presets]]
+            model = "mistral"
+            top_p = 2.0
+            "#,
+        )
+        .unwrap();
+        assert!(invalid.model_preset().is_err());
+        assert_eq!(Config::default().model_preset(), Ok(None));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_config_backend() {
+        assert_eq!(Config::default().backend_kind(), Ok(BackendKind::Onnx));
+
+        let mut config = Config {
+            model_path: PathBuf::from("models/codellama-7b.Q4_K_M.gguf"),
+            ..Config::default()
+        };
+        assert_eq!(config.backend_kind(), Ok(BackendKind::Gguf));
+
+        // An explicit setting wins over the extension
+        config.backend = Some("onnx".to_string());
+        assert_eq!(config.backend_kind(), Ok(BackendKind::Onnx));
+        config.backend = Some("torch".to_string());
+        assert!(config.backend_kind().is_err());
+
+        let mut config = Config::default();
+        assert_eq!(config.model_settings().unwrap().device, DeviceSpec::Cpu);
+        config.device = Some("cuda:1".to_string());
+        assert_eq!(config.model_settings().unwrap().device, DeviceSpec::Cuda(1));
+        config.device = Some("gpu".to_string());
+        assert!(config.model_settings().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_config_safety_section() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [safety]
+            level = "permissive"
+            allow = ["make test"]
+            deny = ["docker ps"]
+            "#,
+        )
+        .unwrap();
+        let policy = config.safety_policy().unwrap();
+        assert_eq!(policy.level(), SafetyLevel::Permissive);
+        assert!(policy.is_safe("git status"));
+        assert!(policy.is_safe("make test"));
+        assert!(!policy.is_safe("docker ps"));
+
+        // Missing section means the standard policy
+        let policy = Config::default().safety_policy().unwrap();
+        assert_eq!(policy, SafetyPolicy::default());
+
+        let mut config = Config::default();
+        config.safety.level = Some("yolo".to_string());
+        assert!(config.safety_policy().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_ci_blocked_verdict() {
+        let policy = SafetyPolicy::new(SafetyLevel::Standard);
+        let report = policy.validate("rm -rf /");
+        let verdict = CiConfig::default().blocked_verdict(&report, SafetyLevel::Standard);
+        assert!(verdict.starts_with("blocked: rm -rf / ("));
+
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [ci]
+            blocked_message = "::error::{command} rejected at {level} level"
+            blocked_exit_code = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.ci.blocked_verdict(&report, SafetyLevel::Standard),
+            "::error::rm -rf / rejected at standard level"
+        );
+        assert_eq!(config.ci.blocked_exit_code, Some(1));
+        assert!(!config.ci.enabled);
+    }
+
+    #[test]
+    fn test_config_ui_section() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [ui]
+            tips = false
+            color = false
+            "#,
+        )
+        .unwrap();
+        assert!(!config.ui.tips());
+        assert!(config.ui.emoji());
+        assert!(!config.ui.color());
+        assert!(Config::default().ui.tips());
+        assert_eq!(config.ui.output_format(), Ok(OutputFormat::Text));
+
+        let mut ui = UiConfig {
+            output: Some("json".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ui.output_format(), Ok(OutputFormat::Json));
+        ui.output = Some("yaml".to_string());
+        assert!(ui.output_format().is_err());
+
+        assert_eq!(ui.hyperlinks, None);
+        assert_eq!(ui.man_links(), Ok(ManLinks::Web));
+        ui.man_links = Some("man".to_string());
+        assert_eq!(ui.man_links(), Ok(ManLinks::Man));
+        ui.man_links = Some("info".to_string());
+        assert!(ui.man_links().is_err());
+    }
+
+    #[test]
+    fn test_config_http_section() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [http]
+            request_timeout_secs = 120
+            connect_timeout_secs = 5
+            health_ttl_secs = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.http.request_timeout(), Ok(Duration::from_secs(120)));
+        assert_eq!(config.http.connect_timeout(), Ok(Duration::from_secs(5)));
+        // Unlike the timeouts, 0 is valid: it turns the health cache off
+        assert_eq!(config.http.health_ttl(), Ok(Duration::ZERO));
+
+        let http = HttpConfig {
+            request_timeout_secs: Some(0),
+            ..HttpConfig::default()
+        };
+        assert!(http.request_timeout().is_err());
+    }
+
+    #[test]
+    fn test_config_metrics_section() {
+        let config: Config = toml::from_str(
+            r#"
+            model_path = "model.onnx"
+            tokenizer_path = "tokenizer.json"
+
+            [metrics]
+            enabled = true
+            listen = "127.0.0.1:9464"
+            "#,
+        )
+        .unwrap();
+        assert!(config.metrics.enabled);
+        // Off by default
+        assert!(!Config::default().metrics.enabled);
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "serve"))]
+    fn test_metrics_listen_only_on_loopback() {
+        let config = MetricsConfig {
+            enabled: true,
+            listen: Some("127.0.0.1:9464".to_string()),
+        };
+        assert_eq!(
+            config.listen_addr(),
+            Ok(Some("127.0.0.1:9464".parse().unwrap()))
+        );
+        // No endpoint while metrics are off
+        let metrics = MetricsConfig {
+            enabled: false,
+            ..config.clone()
+        };
+        assert_eq!(metrics.listen_addr(), Ok(None));
+
+        for listen in ["0.0.0.0:9464", "192.168.1.5:9464", "localhost"] {
+            let metrics = MetricsConfig {
+                enabled: true,
+                listen: Some(listen.to_string()),
+            };
+            assert!(metrics.listen_addr().is_err(), "{}", listen);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "models")]
+    fn test_set_model_paths_keeps_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eidos.toml");
+        fs::write(
+            &path,
+            "# My settings\nmodel_path = \"old.onnx\"\ntokenizer_path = \"old.json\"\n\
+             backend = \"onnx\"\n\n[ui]\ntips = false # quiet\n",
+        )
+        .unwrap();
+        Config::set_model_paths(
+            &path,
+            Path::new("/models/m/m.gguf"),
+            Path::new("/models/m/tokenizer.json"),
+            BackendKind::Gguf,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# My settings\nmodel_path = \"/models/m/m.gguf\"\n\
+             tokenizer_path = \"/models/m/tokenizer.json\"\nbackend = \"gguf\"\n\n\
+             [ui]\ntips = false # quiet\n"
+        );
+
+        // A missing file is created with just the paths
+        let path = dir.path().join("new/eidos.toml");
+        Config::set_model_paths(
+            &path,
+            Path::new("m.onnx"),
+            Path::new("t.json"),
+            BackendKind::Onnx,
+        )
+        .unwrap();
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(config.model_path, PathBuf::from("m.onnx"));
+        assert_eq!(config.backend, None);
     }
 
     #[test]
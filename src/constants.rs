@@ -5,3 +5,4 @@
 pub const MAX_CHAT_INPUT_LENGTH: usize = 10_000;
 pub const MAX_CORE_PROMPT_LENGTH: usize = 1_000;
 pub const MAX_TRANSLATE_INPUT_LENGTH: usize = 5_000;
+pub const MAX_SUMMARIZE_INPUT_LENGTH: usize = 500_000;
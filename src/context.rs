@@ -0,0 +1,295 @@
+// src/context.rs
+// Opt-in snapshot of the user's shell environment for generation requests
+//
+// Knowing the shell, locale, working directory or active virtualenv makes generated
+// commands more relevant. Only whitelisted variables are read (`[context] env_vars`),
+// and the snapshot travels with the request as `env.<NAME>` payload options so a
+// daemon sees the client's environment rather than its own.
+//
+// `[context] facts` (or `--context`) adds a few derived facts that are not secret:
+// the name (not the path) of the working directory, the shell's name, the OS or
+// distribution and whether the directory is inside a git repository. With command
+// generation built in, the directory's project type (from manifests such as
+// `Cargo.toml` or `package.json`) and the names of its first top-level entries
+// are added too. They travel as `fact.<name>` options, so "list my branches"
+// reaches the model knowing there is a repository to list them from, and "build
+// this project" knowing whether that takes `cargo build` or `npm run build`.
+
+use lib_bridge::Payload;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Variables captured when `[context] env_vars` is not set
+pub const DEFAULT_ENV_VARS: &[&str] = &["SHELL", "LANG", "PWD", "VIRTUAL_ENV"];
+
+/// Payload option prefix carrying captured variables
+const OPTION_PREFIX: &str = "env.";
+
+/// Payload option prefix carrying collected facts
+const FACT_PREFIX: &str = "fact.";
+
+/// Facts collected by [`EnvContext::with_facts`], in prompt order
+pub const FACTS: &[&str] = &["directory", "shell", "os", "git_repo", "project", "entries"];
+
+/// Top-level entries named in the `entries` fact
+#[cfg(feature = "core")]
+const MAX_ENTRIES: usize = 20;
+
+/// Longest value kept; anything beyond is noise for the model
+const MAX_VALUE_CHARS: usize = 256;
+
+/// Whitelisted environment variables and facts captured for one request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvContext {
+    vars: Vec<(String, String)>,
+    facts: Vec<(String, String)>,
+}
+
+impl EnvContext {
+    /// Read `names` from the process environment, skipping unset or empty ones
+    pub fn capture<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut context = Self::default();
+        for name in names {
+            let name = name.as_ref();
+            if let Ok(value) = env::var(name) {
+                insert(&mut context.vars, name, &value);
+            }
+        }
+        context
+    }
+
+    /// Add the facts in [`FACTS`] about the current directory and system
+    pub fn with_facts(mut self) -> Self {
+        let cwd = env::current_dir().ok();
+        if let Some(name) = cwd.as_deref().and_then(Path::file_name) {
+            insert(&mut self.facts, "directory", &name.to_string_lossy());
+        }
+        if let Some(shell) = env::var_os("SHELL") {
+            if let Some(name) = Path::new(&shell).file_name() {
+                insert(&mut self.facts, "shell", &name.to_string_lossy());
+            }
+        }
+        insert(&mut self.facts, "os", &os_name());
+        if let Some(cwd) = &cwd {
+            let in_repo = cwd.ancestors().any(|dir| dir.join(".git").exists());
+            insert(
+                &mut self.facts,
+                "git_repo",
+                if in_repo { "yes" } else { "no" },
+            );
+            #[cfg(feature = "core")]
+            self.add_directory_facts(cwd);
+        }
+        self
+    }
+
+    /// Add the project type and top-level entries of `dir`
+    #[cfg(feature = "core")]
+    fn add_directory_facts(&mut self, dir: &Path) {
+        use lib_core::fscontext::{detect_projects, list_entries};
+
+        let projects: Vec<String> = detect_projects(dir).iter().map(|p| p.to_string()).collect();
+        if !projects.is_empty() {
+            insert(&mut self.facts, "project", &projects.join("; "));
+        }
+        if let Ok(listing) = list_entries(dir, MAX_ENTRIES) {
+            insert(&mut self.facts, "entries", &listing.to_string());
+        }
+    }
+
+    /// Recover the variables and facts a client attached to `payload`
+    pub fn from_payload(payload: &Payload) -> Self {
+        let mut context = Self::default();
+        for (key, value) in &payload.options {
+            if let Some(name) = key.strip_prefix(OPTION_PREFIX) {
+                insert(&mut context.vars, name, value);
+            } else if let Some(name) = key.strip_prefix(FACT_PREFIX) {
+                if FACTS.contains(&name) {
+                    insert(&mut context.facts, name, value);
+                }
+            }
+        }
+        // Payload options are unordered; keep prompts stable between runs
+        context.vars.sort();
+        context
+            .facts
+            .sort_by_key(|(name, _)| FACTS.iter().position(|f| f == name));
+        context
+    }
+
+    /// Attach the variables and facts to `payload` as `env.<NAME>` and
+    /// `fact.<name>` options
+    pub fn attach(&self, mut payload: Payload) -> Payload {
+        for (name, value) in &self.vars {
+            payload = payload.with_option(format!("{}{}", OPTION_PREFIX, name), value);
+        }
+        for (name, value) in &self.facts {
+            payload = payload.with_option(format!("{}{}", FACT_PREFIX, name), value);
+        }
+        payload
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty() && self.facts.is_empty()
+    }
+
+    pub fn vars(&self) -> &[(String, String)] {
+        &self.vars
+    }
+
+    pub fn facts(&self) -> &[(String, String)] {
+        &self.facts
+    }
+
+    /// Text block handed to the model, or `None` when nothing was captured
+    pub fn render(&self) -> Option<String> {
+        let mut blocks = Vec::new();
+        if !self.facts.is_empty() {
+            let lines: Vec<String> = self
+                .facts
+                .iter()
+                .map(|(name, value)| format!("{}: {}", fact_label(name), value))
+                .collect();
+            blocks.push(format!("Context:\n{}", lines.join("\n")));
+        }
+        if !self.vars.is_empty() {
+            let lines: Vec<String> = self
+                .vars
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            blocks.push(format!("Environment:\n{}", lines.join("\n")));
+        }
+        (!blocks.is_empty()).then(|| blocks.join("\n"))
+    }
+}
+
+fn insert(entries: &mut Vec<(String, String)>, name: &str, value: &str) {
+    // Values end up on a single prompt line
+    let value: String = value
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_VALUE_CHARS)
+        .collect();
+    if name.is_empty() || value.is_empty() || entries.iter().any(|(n, _)| n == name) {
+        return;
+    }
+    entries.push((name.to_string(), value));
+}
+
+/// How a fact is introduced in the prompt
+fn fact_label(name: &str) -> &str {
+    match name {
+        "directory" => "Current directory",
+        "shell" => "Shell",
+        "os" => "Operating system",
+        "git_repo" => "Inside a git repository",
+        "project" => "Project",
+        "entries" => "Top-level entries",
+        other => other,
+    }
+}
+
+/// The distribution's name on Linux (`PRETTY_NAME` in /etc/os-release), else the OS
+fn os_name() -> String {
+    fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|release| pretty_name(&release))
+        .unwrap_or_else(|| env::consts::OS.to_string())
+}
+
+fn pretty_name(os_release: &str) -> Option<String> {
+    os_release.lines().find_map(|line| {
+        let value = line.strip_prefix("PRETTY_NAME=")?;
+        let value = value.trim().trim_matches('"');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_whitelist() {
+        env::set_var("EIDOS_TEST_CONTEXT_SHELL", "/bin/zsh");
+        env::set_var("EIDOS_TEST_CONTEXT_EMPTY", "  ");
+        env::remove_var("EIDOS_TEST_CONTEXT_UNSET");
+
+        let context = EnvContext::capture(&[
+            "EIDOS_TEST_CONTEXT_SHELL",
+            "EIDOS_TEST_CONTEXT_EMPTY",
+            "EIDOS_TEST_CONTEXT_UNSET",
+        ]);
+        assert_eq!(
+            context.vars(),
+            &[(
+                "EIDOS_TEST_CONTEXT_SHELL".to_string(),
+                "/bin/zsh".to_string()
+            )]
+        );
+        assert_eq!(
+            context.render().as_deref(),
+            Some("Environment:\nEIDOS_TEST_CONTEXT_SHELL=/bin/zsh")
+        );
+        assert_eq!(EnvContext::default().render(), None);
+
+        env::remove_var("EIDOS_TEST_CONTEXT_SHELL");
+        env::remove_var("EIDOS_TEST_CONTEXT_EMPTY");
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let mut context = EnvContext::default();
+        insert(&mut context.vars, "SHELL", "/bin/bash");
+        insert(&mut context.vars, "PWD", "/home/user/my\nproject");
+
+        let payload = context.attach(Payload::new("list files").with_option("explain", "true"));
+        assert_eq!(payload.option("env.SHELL"), Some("/bin/bash"));
+
+        let restored = EnvContext::from_payload(&payload);
+        assert_eq!(
+            restored.vars(),
+            &[
+                ("PWD".to_string(), "/home/user/my project".to_string()),
+                ("SHELL".to_string(), "/bin/bash".to_string()),
+            ]
+        );
+        assert!(EnvContext::from_payload(&Payload::new("hi")).is_empty());
+    }
+
+    #[test]
+    fn test_facts_round_trip_and_render() {
+        let context = EnvContext::default().with_facts();
+        let names: Vec<&str> = context.facts().iter().map(|(n, _)| n.as_str()).collect();
+        // Present whatever the environment, unlike the directory and shell
+        assert!(names.contains(&"os") && names.contains(&"git_repo"));
+        // Tests run in the crate's directory
+        #[cfg(feature = "core")]
+        assert!(context.facts().iter().any(|(name, value)| name == "project"
+            && value.starts_with("Rust (Cargo.toml, built with `cargo build`)")));
+
+        let payload = Payload::new("list my branches")
+            .with_option("fact.git_repo", "yes")
+            .with_option("fact.directory", "eidos")
+            .with_option("fact.home", "/home/user")
+            .with_option("env.SHELL", "/bin/zsh");
+        let restored = EnvContext::from_payload(&payload);
+        assert_eq!(
+            restored.render().as_deref(),
+            Some(
+                "Context:\nCurrent directory: eidos\nInside a git repository: yes\n\
+                 Environment:\nSHELL=/bin/zsh"
+            )
+        );
+    }
+
+    #[test]
+    fn test_pretty_name() {
+        let release = "NAME=\"Ubuntu\"\nPRETTY_NAME=\"Ubuntu 24.04.1 LTS\"\nID=ubuntu\n";
+        assert_eq!(pretty_name(release).as_deref(), Some("Ubuntu 24.04.1 LTS"));
+        assert_eq!(pretty_name("ID=arch\n"), None);
+    }
+}
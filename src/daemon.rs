@@ -0,0 +1,411 @@
+// src/daemon.rs
+// `eidos daemon`: keeps the model resident and serves requests over a Unix socket
+//
+// The protocol is JSON lines. Each line a client writes is a `DaemonRequest`, and
+// the daemon answers every request with one `DaemonReply` line, in order:
+//
+//   → {"request":"core","input":"list files","options":{"explain":"true"}}
+//   ← {"ok":{"command_generated":{"prompt":"list files","command":"ls -la","explanation":null}}}
+//   ← {"error":"Generated command failed safety validation","code":"unsafe","retryable":false}
+//
+// Errors carry the code and retryability of the handler's error, so the client
+// exits with the status a local run would have.
+// The socket is created with mode 0600, so only the owning user can connect.
+// With `[metrics] listen` set, the daemon also answers `GET /metrics` on that
+// loopback address with the usage metrics in the Prometheus text format.
+// A request carrying a time budget (`timeout_ms` option) is answered with an error
+// once it runs out; HTTP calls and generation are also bounded by it, but a
+// synchronous inference pass already underway finishes before the reply is sent.
+
+use crate::{metrics, paths};
+use lib_bridge::{Bridge, Error, ErrorCode, Payload, Request, Response};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// One request line sent to the daemon
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonRequest {
+    pub request: Request,
+    #[serde(flatten)]
+    pub payload: Payload,
+}
+
+/// One reply line sent back by the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DaemonReply {
+    Ok { ok: Response },
+    Error(Error),
+}
+
+impl From<Result<Response, Error>> for DaemonReply {
+    fn from(result: Result<Response, Error>) -> Self {
+        match result {
+            Ok(response) => DaemonReply::Ok { ok: response },
+            Err(e) => DaemonReply::Error(e),
+        }
+    }
+}
+
+/// Serve requests on `socket` until SIGINT or SIGTERM, and the metrics on
+/// `metrics_addr` if given
+///
+/// Connections are handled concurrently on the current thread; handlers that
+/// run inference still block it, so requests are effectively processed one at
+/// a time, each against the already loaded model.
+pub async fn serve(
+    bridge: Bridge,
+    socket: &Path,
+    metrics_addr: Option<SocketAddr>,
+) -> io::Result<()> {
+    let listener = bind(socket)?;
+    info!("Daemon listening on {}", socket.display());
+    let scrapes = match metrics_addr {
+        Some(addr) => {
+            let scrapes = TcpListener::bind(addr).await?;
+            info!("Serving metrics on http://{}/metrics", addr);
+            Some(scrapes)
+        }
+        None => None,
+    };
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let bridge = Rc::new(bridge);
+    let local = tokio::task::LocalSet::new();
+
+    let result = local
+        .run_until(async {
+            if let Some(scrapes) = scrapes {
+                tokio::task::spawn_local(serve_metrics(scrapes, paths::metrics_path()));
+            }
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => {
+                        let (stream, _) = accepted?;
+                        let bridge = Rc::clone(&bridge);
+                        tokio::task::spawn_local(async move {
+                            if let Err(e) = handle_connection(&bridge, stream).await {
+                                warn!("Daemon connection failed: {}", e);
+                            }
+                        });
+                    }
+                    _ = interrupt.recv() => break,
+                    _ = terminate.recv() => break,
+                }
+            }
+            Ok(())
+        })
+        .await;
+
+    info!("Daemon shutting down");
+    let _ = fs::remove_file(socket);
+    result
+}
+
+/// Bind the socket, replacing a stale one left behind by a crashed daemon
+fn bind(socket: &Path) -> io::Result<UnixListener> {
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    if socket.exists() {
+        // A live daemon still accepts connections; a stale socket file does not
+        if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already listening on {}", socket.display()),
+            ));
+        }
+        debug!("Removing stale socket {}", socket.display());
+        fs::remove_file(socket)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Answer every request line on one connection until the client hangs up
+async fn handle_connection(bridge: &Bridge, stream: UnixStream) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str::<DaemonRequest>(&line) {
+            Ok(request) => {
+                debug!("Daemon request: {}", request.request);
+                let kind = request.request;
+                let timeout = request.payload.timeout();
+                let routed = bridge.route_async(request.request, request.payload);
+                let reply: DaemonReply = match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, routed)
+                        .await
+                        .unwrap_or_else(|_| Err(timed_out(timeout)))
+                        .into(),
+                    None => routed.await.into(),
+                };
+                metrics::record_daemon_request(kind, matches!(reply, DaemonReply::Ok { .. }));
+                metrics::flush(&paths::metrics_path());
+                reply
+            }
+            Err(e) => DaemonReply::Error(Error::new(
+                ErrorCode::InvalidInput,
+                format!("Invalid request: {}", e),
+            )),
+        };
+
+        let mut json = serde_json::to_string(&reply)?;
+        json.push('\n');
+        writer.write_all(json.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Answer metrics scrapes until the daemon stops
+async fn serve_metrics(listener: TcpListener, path: PathBuf) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let path = path.clone();
+                tokio::task::spawn_local(async move {
+                    if let Err(e) = answer_scrape(stream, &path).await {
+                        debug!("Metrics scrape failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept a metrics scrape: {}", e),
+        }
+    }
+}
+
+/// Answer one HTTP request: the totals in `path` for `GET /metrics`, 404 otherwise
+async fn answer_scrape(stream: TcpStream, path: &Path) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let request = lines.next_line().await?.unwrap_or_default();
+    // Headers are not needed, but the client expects them to be read
+    while let Some(header) = lines.next_line().await? {
+        if header.is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics::snapshot(path).to_prometheus()),
+        _ => (
+            "404 Not Found",
+            "Not found; metrics are at /metrics\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Error reported for a request that ran past its time budget
+pub fn timed_out(timeout: Duration) -> Error {
+    Error::new(
+        ErrorCode::Timeout,
+        format!("Request timed out after {:?}", timeout),
+    )
+}
+
+/// Send one request to a running daemon
+///
+/// Returns `None` when nothing is listening on `socket`, so the caller can
+/// handle the request in-process instead.
+pub async fn try_request(
+    socket: &Path,
+    request: &DaemonRequest,
+) -> Option<io::Result<DaemonReply>> {
+    match UnixStream::connect(socket).await {
+        Ok(stream) => Some(exchange(stream, request).await),
+        Err(e) => {
+            debug!("No daemon at {}: {}", socket.display(), e);
+            None
+        }
+    }
+}
+
+async fn exchange(stream: UnixStream, request: &DaemonRequest) -> io::Result<DaemonReply> {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+
+    let mut reply = String::new();
+    BufReader::new(reader).read_line(&mut reply).await?;
+    if reply.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "daemon closed the connection without replying",
+        ));
+    }
+    Ok(serde_json::from_str(&reply)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_format() {
+        let request: DaemonRequest = serde_json::from_str(
+            r#"{"request":"core","input":"list files","options":{"explain":"true"}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.request, Request::Core);
+        assert!(request.payload.flag("explain"));
+
+        // Options are optional
+        let request: DaemonRequest =
+            serde_json::from_str(r#"{"request":"chat","input":"hi"}"#).unwrap();
+        assert!(request.payload.options.is_empty());
+
+        let reply = DaemonReply::Ok {
+            ok: Response::Text("ls".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_string(&reply).unwrap(),
+            r#"{"ok":{"text":"ls"}}"#
+        );
+
+        // Errors keep their code, and replies from older daemons still parse
+        let reply = DaemonReply::Error(Error::new(ErrorCode::Unsafe, "rejected"));
+        let json = serde_json::to_string(&reply).unwrap();
+        assert_eq!(
+            json,
+            r#"{"error":"rejected","code":"unsafe","retryable":false}"#
+        );
+        assert_eq!(serde_json::from_str::<DaemonReply>(&json).unwrap(), reply);
+        let old: DaemonReply = serde_json::from_str(r#"{"error":"rejected"}"#).unwrap();
+        assert_eq!(old, DaemonReply::Error(Error::from("rejected")));
+    }
+
+    #[tokio::test]
+    async fn test_connection_round_trip() {
+        let mut bridge = Bridge::new();
+        bridge.register(
+            Request::Core,
+            Box::new(|payload: &Payload| Ok(Response::Text(format!("echo {}", payload.input)))),
+        );
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let request = DaemonRequest {
+            request: Request::Core,
+            payload: Payload::new("hello"),
+        };
+
+        // The client hangs up after its reply, which ends the server loop
+        let (reply, served) = tokio::join!(
+            exchange(client, &request),
+            handle_connection(&bridge, server)
+        );
+        assert_eq!(
+            reply.unwrap(),
+            DaemonReply::Ok {
+                ok: Response::Text("echo hello".to_string())
+            }
+        );
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_scrape() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.json");
+        let totals = metrics::Metrics {
+            safety_rejections: 3,
+            ..metrics::Metrics::default()
+        };
+        totals.save(&path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let scrape = |request: &'static str| {
+            let path = path.clone();
+            let listener = &listener;
+            async move {
+                let (mut client, (server, _)) =
+                    tokio::join!(async { TcpStream::connect(addr).await.unwrap() }, async {
+                        listener.accept().await.unwrap()
+                    });
+                let (response, answered) = tokio::join!(
+                    async {
+                        client.write_all(request.as_bytes()).await.unwrap();
+                        let mut response = String::new();
+                        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut response)
+                            .await
+                            .unwrap();
+                        response
+                    },
+                    answer_scrape(server, &path)
+                );
+                answered.unwrap();
+                response
+            }
+        };
+
+        let response = scrape("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("\r\n\r\n# HELP eidos_commands_total"));
+        assert!(response.contains("eidos_safety_rejections_total 3\n"));
+
+        let response = scrape("GET / HTTP/1.1\r\n\r\n").await;
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            response
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let mut bridge = Bridge::new();
+        bridge.register_async(Request::Chat, |_payload: Payload| async {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            Ok(Response::Text("too late".to_string()))
+        });
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let request = DaemonRequest {
+            request: Request::Chat,
+            payload: Payload::new("hi").with_timeout(Duration::from_millis(20)),
+        };
+
+        let (reply, served) = tokio::join!(
+            exchange(client, &request),
+            handle_connection(&bridge, server)
+        );
+        assert_eq!(
+            reply.unwrap(),
+            DaemonReply::Error(Error::new(
+                ErrorCode::Timeout,
+                "Request timed out after 20ms"
+            ))
+        );
+        served.unwrap();
+    }
+}
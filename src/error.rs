@@ -1,4 +1,5 @@
 // src/error.rs
+use lib_errors::{EidosError, ErrorCode};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,14 +7,67 @@ pub enum AppError {
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
-    #[error("Network request error: {0}")]
-    Network(#[from] reqwest::Error),
-
     #[error("JSON parsing error: {0}")]
     Serde(#[from] serde_json::Error),
 
     #[error("Invalid user input: {0}")]
     InvalidInput(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("Model error: {0}")]
+    Model(String),
+
+    #[error("{0}")]
+    Unsafe(String),
+
+    #[cfg(feature = "chat")]
+    #[error(transparent)]
+    Chat(#[from] lib_chat::ChatError),
+
+    #[cfg(feature = "translate")]
+    #[error(transparent)]
+    Translate(#[from] lib_translate::TranslateError),
+
+    /// A failure routed through the bridge or the daemon, with the code of the
+    /// crate it came from
+    #[error("{0}")]
+    Routed(lib_errors::Error),
+}
+
+impl From<lib_errors::Error> for AppError {
+    fn from(e: lib_errors::Error) -> Self {
+        AppError::Routed(e)
+    }
+}
+
+impl EidosError for AppError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AppError::Io(_) => ErrorCode::Io,
+            AppError::Serde(_) | AppError::InvalidInput(_) => ErrorCode::InvalidInput,
+            AppError::Config(_) => ErrorCode::Config,
+            AppError::Model(_) => ErrorCode::Model,
+            AppError::Unsafe(_) => ErrorCode::Unsafe,
+            #[cfg(feature = "chat")]
+            AppError::Chat(e) => e.code(),
+            #[cfg(feature = "translate")]
+            AppError::Translate(e) => e.code(),
+            AppError::Routed(e) => e.code(),
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(feature = "chat")]
+            AppError::Chat(e) => e.is_retryable(),
+            #[cfg(feature = "translate")]
+            AppError::Translate(e) => e.is_retryable(),
+            AppError::Routed(e) => e.is_retryable(),
+            _ => self.code().is_retryable(),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, AppError>;
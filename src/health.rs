@@ -0,0 +1,216 @@
+// src/health.rs
+// Availability of chat and translation services, remembered between invocations
+//
+// A provider that could not be reached (connection refused, connect timeout) is
+// recorded in ~/.cache/eidos/providers.json. While the record is fresh (`[http]
+// health_ttl_secs`, default 60), provider election skips it in favour of the next
+// configured provider, and when there is no other choice it is tried with a short
+// connect timeout. A stopped Ollama or LibreTranslate server then costs about a
+// second per invocation instead of the full connect timeout, and the election sticks
+// until the record expires. The cache is advisory: a missing or unreadable file
+// means every provider is assumed up. Concurrent invocations update it under a lock
+// (see `lockfile`), so one outage recorded does not overwrite another.
+
+use crate::lockfile::with_exclusive;
+use crate::metrics;
+use crate::paths;
+#[cfg(feature = "chat")]
+use lib_chat::{Chat, ChatError};
+#[cfg(feature = "translate")]
+use lib_translate::TranslateError;
+use lib_util::write_atomic;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Record name of the translation service
+pub const LIBRETRANSLATE: &str = "libretranslate";
+
+/// Connect timeout for a provider recently found unreachable
+pub const DOWN_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Why and when a provider was last found unreachable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outage {
+    /// Unix time of the failed request, in seconds
+    pub since: u64,
+    pub error: String,
+}
+
+/// Providers found unreachable, by name (`ollama`, `libretranslate`, ...)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderHealth {
+    #[serde(default)]
+    down: BTreeMap<String, Outage>,
+}
+
+impl ProviderHealth {
+    /// The records in `path`; empty when the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, serde_json::to_string(self)?.as_bytes())
+    }
+
+    /// The outage of `name` if it was recorded less than `ttl` before `now`
+    pub fn outage(&self, name: &str, ttl: Duration, now: u64) -> Option<&Outage> {
+        self.down
+            .get(name)
+            .filter(|outage| now.saturating_sub(outage.since) < ttl.as_secs())
+    }
+
+    pub fn is_down(&self, name: &str, ttl: Duration, now: u64) -> bool {
+        self.outage(name, ttl, now).is_some()
+    }
+
+    /// Record that `name` could not be reached at `now`
+    pub fn mark_down(&mut self, name: &str, error: impl Into<String>, now: u64) {
+        self.down.insert(
+            name.to_string(),
+            Outage {
+                since: now,
+                error: error.into(),
+            },
+        );
+    }
+
+    /// The first of `candidates` that is not down, or the first one when all are
+    pub fn elect<'a>(&self, candidates: &[&'a str], ttl: Duration, now: u64) -> Option<&'a str> {
+        candidates
+            .iter()
+            .find(|name| !self.is_down(name, ttl, now))
+            .or_else(|| candidates.first())
+            .copied()
+    }
+}
+
+/// The records of earlier invocations
+pub fn load() -> ProviderHealth {
+    ProviderHealth::load(&paths::health_path())
+}
+
+/// Remember that `name` could not be reached, for the next invocations
+pub fn record_unreachable(name: &str, error: impl Into<String>) {
+    let path = paths::health_path();
+    let saved = with_exclusive(&path, || {
+        let mut health = ProviderHealth::load(&path);
+        health.mark_down(name, error, lib_util::unix_now());
+        health.save(&path)
+    });
+    if let Err(e) = saved {
+        debug!(
+            "Could not save provider health to {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Record the provider of `chat` if `e` shows it could not be reached, and count
+/// the failure in the metrics when the provider caused it
+#[cfg(feature = "chat")]
+pub fn note_chat_error(chat: &Chat, e: &ChatError) {
+    let Some(provider) = chat.provider() else {
+        return;
+    };
+    if matches!(
+        e,
+        ChatError::RequestError(_)
+            | ChatError::JsonError(_)
+            | ChatError::ApiError(_)
+            | ChatError::AuthenticationError
+            | ChatError::RateLimitError
+            | ChatError::InvalidResponse(_)
+            | ChatError::DeadlineExceeded
+    ) {
+        metrics::record_provider_error(provider.name());
+    }
+    if e.is_unreachable() {
+        record_unreachable(provider.name(), e.to_string());
+    }
+}
+
+/// Record the translation service if `e` shows it could not be reached, and
+/// count the failure in the metrics when the service caused it
+#[cfg(feature = "translate")]
+pub fn note_translate_error(e: &TranslateError) {
+    // Only requests to the service fail this way; offline translation never does
+    if matches!(
+        e,
+        TranslateError::RequestError(_)
+            | TranslateError::JsonError(_)
+            | TranslateError::ApiError(_)
+            | TranslateError::DeadlineExceeded
+    ) {
+        metrics::record_provider_error(LIBRETRANSLATE);
+    }
+    if e.is_unreachable() {
+        record_unreachable(LIBRETRANSLATE, e.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outages_expire_after_ttl() {
+        let ttl = Duration::from_secs(60);
+        let mut health = ProviderHealth::default();
+        health.mark_down("ollama", "connection refused", 1_000);
+
+        assert!(health.is_down("ollama", ttl, 1_059));
+        assert!(!health.is_down("ollama", ttl, 1_060));
+        assert!(!health.is_down("ollama", Duration::ZERO, 1_000));
+        assert!(!health.is_down("openai", ttl, 1_000));
+    }
+
+    #[test]
+    fn test_election_skips_providers_that_are_down() {
+        let ttl = Duration::from_secs(60);
+        let mut health = ProviderHealth::default();
+        health.mark_down("openai", "timeout", 1_000);
+
+        assert_eq!(
+            health.elect(&["openai", "ollama"], ttl, 1_010),
+            Some("ollama")
+        );
+        health.mark_down("ollama", "connection refused", 1_010);
+        assert_eq!(
+            health.elect(&["openai", "ollama"], ttl, 1_020),
+            Some("openai")
+        );
+        assert_eq!(health.elect(&[], ttl, 1_020), None);
+    }
+
+    #[test]
+    fn test_records_survive_a_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache/providers.json");
+        assert!(ProviderHealth::load(&path).down.is_empty());
+
+        let mut health = ProviderHealth::default();
+        health.mark_down("libretranslate", "connection refused", 42);
+        health.save(&path).unwrap();
+        let loaded = ProviderHealth::load(&path);
+        assert_eq!(
+            loaded.outage("libretranslate", Duration::from_secs(60), 50),
+            Some(&Outage {
+                since: 42,
+                error: "connection refused".to_string()
+            })
+        );
+
+        fs::write(&path, "not json").unwrap();
+        assert!(ProviderHealth::load(&path).down.is_empty());
+    }
+}
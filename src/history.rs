@@ -0,0 +1,441 @@
+// src/history.rs
+// Log of generated commands (~/.local/share/eidos/history.jsonl)
+//
+// Every command `eidos core` shows is appended as one JSON line together with its
+// undo suggestion, so `eidos history undo <id>` can still offer a way back after
+// the command has been run. Entries are append-only; ids increase monotonically.
+//
+// New entries go to the plain log. Once it outgrows `ROLL_BYTES` it is folded into
+// a zstd-compressed archive beside it (history.jsonl.zst), which readers decompress
+// transparently; `eidos maintenance compact` does the same and can also drop old
+// entries.
+//
+// Concurrent invocations (the shell widget and a script, say) append under the
+// history's lock (see `lockfile`), so ids stay unique and compaction never drops an
+// entry written while it ran.
+
+use crate::lockfile::{with_exclusive, with_shared};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Size at which the plain log is folded into the compressed archive
+const ROLL_BYTES: u64 = 64 * 1024;
+
+/// zstd level for the archive
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// One generated command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub prompt: String,
+    pub command: String,
+    /// Command reversing `command`, when one is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undo: Option<String>,
+    /// How long generating the command took, explanation included, in
+    /// milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4}  {}", self.id, self.command)?;
+        if let Some(undo) = &self.undo {
+            write!(f, "  (undo: {})", undo)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which entries `compact` keeps; unset limits keep everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    /// Drop entries older than this
+    pub max_age: Option<Duration>,
+    /// Drop the oldest entries until the archive is at most this many bytes
+    pub max_bytes: Option<u64>,
+}
+
+/// Outcome of [`CommandHistory::compact`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// Entries dropped by the retention limits
+    pub pruned: usize,
+}
+
+/// Append-only command history file
+pub struct CommandHistory {
+    path: PathBuf,
+}
+
+impl CommandHistory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Compressed archive of older entries
+    fn archive_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".zst");
+        path.into()
+    }
+
+    /// All entries, oldest first; a missing file is an empty history
+    ///
+    /// Lines that fail to parse (e.g. a write cut short) are skipped.
+    pub fn entries(&self) -> io::Result<Vec<HistoryEntry>> {
+        with_shared(&self.path, || self.read_entries())
+    }
+
+    fn read_entries(&self) -> io::Result<Vec<HistoryEntry>> {
+        let mut entries = parse(&self.read_archive()?);
+        // Entries already archived may linger in the log if compaction was
+        // interrupted before clearing it
+        let archived = entries.last().map_or(0, |entry| entry.id);
+        entries.extend(
+            parse(&self.read()?)
+                .into_iter()
+                .filter(|entry| entry.id > archived),
+        );
+        Ok(entries)
+    }
+
+    /// Look up an entry by id
+    pub fn get(&self, id: u64) -> io::Result<Option<HistoryEntry>> {
+        Ok(self.entries()?.into_iter().find(|entry| entry.id == id))
+    }
+
+    /// Record a generated command and return the stored entry
+    pub fn append(
+        &self,
+        prompt: &str,
+        command: &str,
+        undo: Option<String>,
+        latency: Option<Duration>,
+    ) -> io::Result<HistoryEntry> {
+        with_exclusive(&self.path, || {
+            self.append_locked(prompt, command, undo, latency)
+        })
+    }
+
+    fn append_locked(
+        &self,
+        prompt: &str,
+        command: &str,
+        undo: Option<String>,
+        latency: Option<Duration>,
+    ) -> io::Result<HistoryEntry> {
+        let contents = self.read()?;
+        let last = match parse(&contents).last() {
+            Some(entry) => Some(entry.id),
+            None => parse(&self.read_archive()?).last().map(|entry| entry.id),
+        };
+        let id = last.map_or(1, |id| id + 1);
+        let entry = HistoryEntry {
+            id,
+            timestamp: lib_util::unix_now(),
+            prompt: prompt.to_string(),
+            command: command.to_string(),
+            undo,
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+        };
+
+        // Start on a fresh line if the last write was cut short
+        let mut line = String::new();
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            line.push('\n');
+        }
+        line.push_str(&serde_json::to_string(&entry)?);
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+
+        if (contents.len() + line.len()) as u64 > ROLL_BYTES {
+            self.compact_locked(Retention::default())?;
+        }
+        Ok(entry)
+    }
+
+    /// Fold the log into the compressed archive, dropping entries outside
+    /// `retention`
+    ///
+    /// The newest entry is always kept so ids keep increasing.
+    pub fn compact(&self, retention: Retention) -> io::Result<Compaction> {
+        with_exclusive(&self.path, || self.compact_locked(retention))
+    }
+
+    fn compact_locked(&self, retention: Retention) -> io::Result<Compaction> {
+        let bytes_before = file_size(&self.path)? + file_size(&self.archive_path())?;
+        let mut entries = self.read_entries()?;
+        let total = entries.len();
+
+        if let Some(max_age) = retention.max_age {
+            let cutoff = lib_util::unix_now().saturating_sub(max_age.as_secs());
+            let newest = entries.len().saturating_sub(1);
+            let expired = entries[..newest]
+                .iter()
+                .take_while(|entry| entry.timestamp < cutoff)
+                .count();
+            entries.drain(..expired);
+        }
+
+        let mut archive = encode(&entries)?;
+        if let Some(max_bytes) = retention.max_bytes {
+            while archive.len() as u64 > max_bytes && entries.len() > 1 {
+                // Drop a tenth at a time rather than re-compressing per entry
+                let excess = (entries.len() / 10).clamp(1, entries.len() - 1);
+                entries.drain(..excess);
+                archive = encode(&entries)?;
+            }
+        }
+
+        let archive_path = self.archive_path();
+        if entries.is_empty() {
+            remove_if_present(&archive_path)?;
+        } else {
+            // Replace the archive atomically before clearing the log, so a crash
+            // in between leaves duplicates (skipped on read) rather than a gap
+            lib_util::write_atomic(&archive_path, &archive)?;
+        }
+        remove_if_present(&self.path)?;
+
+        Ok(Compaction {
+            bytes_before,
+            bytes_after: file_size(&archive_path)?,
+            pruned: total - entries.len(),
+        })
+    }
+
+    fn read(&self) -> io::Result<String> {
+        match fs::read_to_string(&self.path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            result => result,
+        }
+    }
+
+    fn read_archive(&self) -> io::Result<String> {
+        match fs::read(self.archive_path()) {
+            Ok(bytes) => {
+                let bytes = zstd::decode_all(bytes.as_slice())?;
+                String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn encode(entries: &[HistoryEntry]) -> io::Result<Vec<u8>> {
+    let mut jsonl = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut jsonl, entry)?;
+        jsonl.push(b'\n');
+    }
+    zstd::encode_all(jsonl.as_slice(), COMPRESSION_LEVEL)
+}
+
+fn file_size(path: &Path) -> io::Result<u64> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_if_present(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn parse(contents: &str) -> Vec<HistoryEntry> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = CommandHistory::new(dir.path().join("nested/history.jsonl"));
+        assert!(history.entries().unwrap().is_empty());
+
+        let first = history
+            .append(
+                "rename a to b",
+                "mv a b",
+                Some("mv b a".to_string()),
+                Some(Duration::from_millis(1200)),
+            )
+            .unwrap();
+        let second = history.append("list files", "ls -la", None, None).unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+
+        assert_eq!(history.get(1).unwrap(), Some(first));
+        assert_eq!(history.get(1).unwrap().unwrap().latency_ms, Some(1200));
+        assert_eq!(history.get(2).unwrap().unwrap().undo, None);
+        assert_eq!(history.get(3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_corrupt_lines_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        fs::write(
+            &path,
+            "{\"id\":7,\"timestamp\":0,\"prompt\":\"p\",\"command\":\"git stash\"}\n{\"id\":8,\"time",
+        )
+        .unwrap();
+
+        let history = CommandHistory::new(&path);
+        assert_eq!(history.entries().unwrap().len(), 1);
+        // Ids continue after the last readable entry
+        assert_eq!(history.append("p", "ls", None, None).unwrap().id, 8);
+        assert_eq!(history.entries().unwrap().len(), 2);
+    }
+
+    fn write_entries(path: &Path, timestamps: &[u64]) {
+        let lines: Vec<String> = timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, timestamp)| {
+                serde_json::to_string(&HistoryEntry {
+                    id: i as u64 + 1,
+                    timestamp: *timestamp,
+                    prompt: "list files".to_string(),
+                    command: format!("ls -la dir{}", i),
+                    undo: None,
+                    latency_ms: None,
+                })
+                .unwrap()
+            })
+            .collect();
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn test_compact_archives_transparently() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        write_entries(&path, &[lib_util::unix_now(); 50]);
+        let history = CommandHistory::new(&path);
+
+        let compaction = history.compact(Retention::default()).unwrap();
+        assert_eq!(compaction.pruned, 0);
+        assert!(compaction.bytes_after < compaction.bytes_before);
+        assert!(!path.exists());
+        assert_eq!(history.entries().unwrap().len(), 50);
+
+        // New entries continue the ids and are read after the archive
+        assert_eq!(history.append("p", "pwd", None, None).unwrap().id, 51);
+        assert_eq!(history.get(51).unwrap().unwrap().command, "pwd");
+        assert_eq!(history.get(3).unwrap().unwrap().command, "ls -la dir2");
+    }
+
+    #[test]
+    fn test_interrupted_compaction_leaves_no_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        write_entries(&path, &[lib_util::unix_now(); 3]);
+        let history = CommandHistory::new(&path);
+        let log = fs::read(&path).unwrap();
+        history.compact(Retention::default()).unwrap();
+        fs::write(&path, log).unwrap();
+
+        assert_eq!(history.entries().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_compact_prunes_by_age_and_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let day = 24 * 60 * 60;
+        write_entries(
+            &path,
+            &[0, 0, lib_util::unix_now() - 2 * day, lib_util::unix_now()],
+        );
+        let history = CommandHistory::new(&path);
+
+        let retention = Retention {
+            max_age: Some(Duration::from_secs(7 * day)),
+            max_bytes: None,
+        };
+        assert_eq!(history.compact(retention).unwrap().pruned, 2);
+        let ids: Vec<u64> = history.entries().unwrap().iter().map(|e| e.id).collect();
+        assert_eq!(ids, [3, 4]);
+
+        // The newest entry survives any limit
+        let retention = Retention {
+            max_age: Some(Duration::ZERO),
+            max_bytes: Some(0),
+        };
+        history.compact(retention).unwrap();
+        assert_eq!(history.entries().unwrap().len(), 1);
+        assert_eq!(history.append("p", "ls", None, None).unwrap().id, 5);
+    }
+
+    #[test]
+    fn test_large_log_rolls_into_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let history = CommandHistory::new(&path);
+        let prompt = "x".repeat(1024);
+        for _ in 0..70 {
+            history.append(&prompt, "ls", None, None).unwrap();
+        }
+
+        assert!(history.archive_path().exists());
+        assert!(file_size(&path).unwrap() < ROLL_BYTES);
+        assert_eq!(history.entries().unwrap().len(), 70);
+    }
+
+    #[test]
+    fn test_concurrent_appends_get_unique_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        // Long prompts so the log also rolls over while others append
+        let prompt = "x".repeat(2048);
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let (path, prompt) = (path.clone(), prompt.clone());
+                std::thread::spawn(move || {
+                    let history = CommandHistory::new(path);
+                    for _ in 0..15 {
+                        history.append(&prompt, "ls", None, None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let ids: Vec<u64> = CommandHistory::new(&path)
+            .entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, (1..=60).collect::<Vec<_>>());
+    }
+}
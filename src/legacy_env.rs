@@ -0,0 +1,381 @@
+// src/legacy_env.rs
+// Environment variables replaced by eidos.toml settings
+//
+// Chat and translation services were first configured with the variables of their
+// own tools (OLLAMA_HOST, OPENAI_MODEL, LIBRETRANSLATE_URL, ...) and with generic
+// names like HTTP_REQUEST_TIMEOUT_SECS. Their settings now live in the [chat],
+// [translate] and [http] sections. The old variables keep working: `plan` maps each
+// one onto the key it stands for, unless the configuration sets that key already,
+// and `Config::load` applies the result. Every variable still in use is reported
+// once per user (remembered in the cache directory) with the key replacing it, and
+// `eidos config migrate` writes the same settings to eidos.toml.
+//
+// API keys are not deprecated: they are secrets, and stay out of config files.
+
+use crate::config::Config;
+use crate::paths;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::fs;
+
+/// Chat providers in the order the first configured one is picked, with the
+/// variable that announces each and the one naming its model
+const PROVIDER_VARS: &[(&str, &str, &str)] = &[
+    ("openai", "OPENAI_API_KEY", "OPENAI_MODEL"),
+    ("anthropic", "ANTHROPIC_API_KEY", "ANTHROPIC_MODEL"),
+    ("gemini", "GEMINI_API_KEY", "GEMINI_MODEL"),
+    ("ollama", "OLLAMA_HOST", "OLLAMA_MODEL"),
+    ("custom", "LLM_API_URL", "LLM_MODEL"),
+];
+
+/// `[http]` keys and the variables they replace
+const HTTP_VARS: &[(&str, &str)] = &[
+    ("HTTP_REQUEST_TIMEOUT_SECS", "request_timeout_secs"),
+    ("HTTP_CONNECT_TIMEOUT_SECS", "connect_timeout_secs"),
+    ("HTTP_HEALTH_TTL_SECS", "health_ttl_secs"),
+];
+
+/// Value of a setting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Text(String),
+    Secs(u64),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Text(text) => write!(f, "{}", toml_edit::Value::from(text.as_str())),
+            Value::Secs(secs) => write!(f, "{}", secs),
+        }
+    }
+}
+
+/// A config key set from a deprecated variable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Setting {
+    /// The variable it comes from
+    pub var: &'static str,
+    pub section: &'static str,
+    pub key: &'static str,
+    pub value: Value,
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} = {}", self.section, self.key, self.value)
+    }
+}
+
+/// A deprecated variable that is set but maps onto nothing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Skipped {
+    pub var: &'static str,
+    pub reason: String,
+}
+
+/// Settings the deprecated variables stand for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    pub settings: Vec<Setting>,
+    pub skipped: Vec<Skipped>,
+}
+
+impl Plan {
+    fn set(&mut self, var: &'static str, section: &'static str, key: &'static str, value: Value) {
+        self.settings.push(Setting {
+            var,
+            section,
+            key,
+            value,
+        });
+    }
+
+    fn skip(&mut self, var: &'static str, reason: impl Into<String>) {
+        self.skipped.push(Skipped {
+            var,
+            reason: reason.into(),
+        });
+    }
+}
+
+/// Settings for the deprecated variables `var` returns, given what `config`
+/// already sets
+///
+/// Provider URLs and models only count for the provider in use: `[chat] provider`,
+/// or else the first one configured in the environment.
+pub fn plan(config: &Config, var: impl Fn(&str) -> Option<String>) -> Plan {
+    let mut plan = Plan::default();
+    let provider = match &config.chat.provider {
+        Some(name) => Some(name.trim().to_lowercase()),
+        None => PROVIDER_VARS
+            .iter()
+            .find(|(_, announce, _)| var(announce).is_some())
+            .map(|(name, ..)| name.to_string()),
+    };
+    let unused = format!(
+        "not used, the chat provider is {}",
+        provider.as_deref().unwrap_or("unset")
+    );
+
+    for &(name, announce, model_var) in PROVIDER_VARS {
+        let in_use = provider.as_deref() == Some(name);
+        if let Some(url) = var(announce).filter(|_| matches!(name, "ollama" | "custom")) {
+            if !in_use {
+                plan.skip(announce, unused.clone());
+            } else if config.chat.url.is_some() {
+                plan.skip(announce, "ignored, [chat] url is set");
+            } else {
+                if config.chat.provider.is_none() {
+                    plan.set(announce, "chat", "provider", Value::Text(name.to_string()));
+                }
+                plan.set(announce, "chat", "url", Value::Text(url));
+            }
+        }
+        if let Some(model) = var(model_var) {
+            if !in_use {
+                plan.skip(model_var, unused.clone());
+            } else if config.chat.model.is_some() {
+                plan.skip(model_var, "ignored, [chat] model is set");
+            } else {
+                plan.set(model_var, "chat", "model", Value::Text(model));
+            }
+        }
+    }
+
+    if let Some(url) = var("LIBRETRANSLATE_URL") {
+        if config.translate.url.is_some() {
+            plan.skip("LIBRETRANSLATE_URL", "ignored, [translate] url is set");
+        } else {
+            plan.set("LIBRETRANSLATE_URL", "translate", "url", Value::Text(url));
+        }
+    }
+
+    for &(http_var, key) in HTTP_VARS {
+        let Some(value) = var(http_var) else {
+            continue;
+        };
+        let configured = match key {
+            "request_timeout_secs" => config.http.request_timeout_secs,
+            "connect_timeout_secs" => config.http.connect_timeout_secs,
+            _ => config.http.health_ttl_secs,
+        };
+        match value.trim().parse() {
+            _ if configured.is_some() => {
+                plan.skip(http_var, format!("ignored, [http] {} is set", key))
+            }
+            Ok(secs) => plan.set(http_var, "http", key, Value::Secs(secs)),
+            Err(_) => plan.skip(http_var, format!("'{}' is not a number of seconds", value)),
+        }
+    }
+    plan
+}
+
+/// Apply `settings` to `config`
+///
+/// Without a configured chat provider the chat variables also decide which one
+/// is picked, skipping providers that were unreachable lately; lib_chat reads
+/// them itself then, and naming one provider here would end that.
+pub fn apply(config: &mut Config, settings: &[Setting]) {
+    let from_env = config.chat.provider.is_none();
+    for setting in settings {
+        match (setting.section, setting.key, &setting.value) {
+            ("chat", _, _) if from_env => {}
+            ("chat", "provider", Value::Text(name)) => config.chat.provider = Some(name.clone()),
+            ("chat", "url", Value::Text(url)) => config.chat.url = Some(url.clone()),
+            ("chat", "model", Value::Text(model)) => config.chat.model = Some(model.clone()),
+            ("translate", "url", Value::Text(url)) => config.translate.url = Some(url.clone()),
+            ("http", "request_timeout_secs", Value::Secs(secs)) => {
+                config.http.request_timeout_secs = Some(*secs)
+            }
+            ("http", "connect_timeout_secs", Value::Secs(secs)) => {
+                config.http.connect_timeout_secs = Some(*secs)
+            }
+            ("http", "health_ttl_secs", Value::Secs(secs)) => {
+                config.http.health_ttl_secs = Some(*secs)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Warning about a deprecated variable in use
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Deprecation {
+    /// The variable
+    pub deprecated: &'static str,
+    /// Keys replacing it, e.g. `[chat] provider` and `[chat] url`
+    pub replacement: Vec<String>,
+}
+
+impl Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is deprecated; set {} in eidos.toml instead (`eidos config migrate` writes it)",
+            self.deprecated,
+            self.replacement.join(" and ")
+        )
+    }
+}
+
+/// One warning per variable in `settings`, in order
+pub fn deprecations(settings: &[Setting]) -> Vec<Deprecation> {
+    let mut deprecations: Vec<Deprecation> = Vec::new();
+    for setting in settings {
+        let key = format!("[{}] {}", setting.section, setting.key);
+        match deprecations
+            .iter_mut()
+            .find(|d| d.deprecated == setting.var)
+        {
+            Some(deprecation) => deprecation.replacement.push(key),
+            None => deprecations.push(Deprecation {
+                deprecated: setting.var,
+                replacement: vec![key],
+            }),
+        }
+    }
+    deprecations
+}
+
+/// Warn about the variables in `settings` not reported before
+///
+/// Reported variables are remembered in the cache directory; clearing it brings
+/// the warnings back. Failing to remember them only means warning again.
+pub fn warn_once(settings: &[Setting]) {
+    let path = paths::deprecations_path();
+    let mut warned: BTreeSet<String> = fs::read_to_string(&path)
+        .map(|contents| contents.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    let new: Vec<Deprecation> = deprecations(settings)
+        .into_iter()
+        .filter(|d| !warned.contains(d.deprecated))
+        .collect();
+    if new.is_empty() {
+        return;
+    }
+    for deprecation in &new {
+        crate::output::structured_warning(deprecation);
+        warned.insert(deprecation.deprecated.to_string());
+    }
+    let contents: Vec<String> = warned.into_iter().collect();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(&path, contents.join("\n") + "\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpConfig;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_plan_maps_variables_of_the_provider_in_use() {
+        let vars = [
+            ("OLLAMA_HOST", "http://localhost:11434"),
+            ("OLLAMA_MODEL", "codellama"),
+            ("LLM_API_URL", "http://localhost:8080/v1"),
+            ("LIBRETRANSLATE_URL", "http://localhost:5000"),
+            ("HTTP_CONNECT_TIMEOUT_SECS", "5"),
+            ("HTTP_HEALTH_TTL_SECS", "soon"),
+        ];
+        let plan = plan(&Config::default(), env(&vars));
+        let settings: Vec<String> = plan.settings.iter().map(Setting::to_string).collect();
+        assert_eq!(
+            settings,
+            [
+                "[chat] provider = \"ollama\"",
+                "[chat] url = \"http://localhost:11434\"",
+                "[chat] model = \"codellama\"",
+                "[translate] url = \"http://localhost:5000\"",
+                "[http] connect_timeout_secs = 5",
+            ]
+        );
+        assert_eq!(
+            plan.skipped,
+            [
+                Skipped {
+                    var: "LLM_API_URL",
+                    reason: "not used, the chat provider is ollama".to_string()
+                },
+                Skipped {
+                    var: "HTTP_HEALTH_TTL_SECS",
+                    reason: "'soon' is not a number of seconds".to_string()
+                },
+            ]
+        );
+
+        let deprecations = deprecations(&plan.settings);
+        assert_eq!(
+            deprecations[0].to_string(),
+            "OLLAMA_HOST is deprecated; set [chat] provider and [chat] url in eidos.toml \
+             instead (`eidos config migrate` writes it)"
+        );
+        assert_eq!(deprecations.len(), 4);
+    }
+
+    #[test]
+    fn test_configured_keys_win() {
+        let mut config = Config::default();
+        config.chat.provider = Some("openai".to_string());
+        config.translate.url = Some("https://translate.example.com".to_string());
+        config.http = HttpConfig {
+            request_timeout_secs: Some(60),
+            ..HttpConfig::default()
+        };
+        let vars = [
+            ("OPENAI_API_KEY", "sk-test"),
+            ("OPENAI_MODEL", "gpt-4o-mini"),
+            ("OLLAMA_HOST", "http://localhost:11434"),
+            ("LIBRETRANSLATE_URL", "http://localhost:5000"),
+            ("HTTP_REQUEST_TIMEOUT_SECS", "10"),
+        ];
+        let plan = plan(&config, env(&vars));
+        assert_eq!(plan.settings.len(), 1);
+        assert_eq!(
+            plan.settings[0].to_string(),
+            "[chat] model = \"gpt-4o-mini\""
+        );
+        let skipped: Vec<&str> = plan.skipped.iter().map(|s| s.var).collect();
+        assert_eq!(
+            skipped,
+            [
+                "OLLAMA_HOST",
+                "LIBRETRANSLATE_URL",
+                "HTTP_REQUEST_TIMEOUT_SECS"
+            ]
+        );
+
+        apply(&mut config, &plan.settings);
+        assert_eq!(config.chat.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(config.http.request_timeout_secs, Some(60));
+    }
+
+    #[test]
+    fn test_apply_leaves_provider_choice_to_the_environment() {
+        let vars = [
+            ("OLLAMA_HOST", "http://localhost:11434"),
+            ("LIBRETRANSLATE_URL", "http://localhost:5000"),
+        ];
+        let mut config = Config::default();
+        let plan = plan(&config, env(&vars));
+        apply(&mut config, &plan.settings);
+        assert_eq!(config.chat.provider, None);
+        assert_eq!(config.chat.url, None);
+        assert_eq!(
+            config.translate.url.as_deref(),
+            Some("http://localhost:5000")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Eidos as a library
+//!
+//! A stable facade over the internal workspace crates (`lib_bridge`, `lib_chat`,
+//! `lib_core`, `lib_translate`), whose own APIs may change between releases. Only the
+//! items re-exported here follow semantic versioning.
+//!
+//! Each subsystem sits behind a Cargo feature, all enabled by default:
+//!
+//! | Feature     | Module          | Provides                                   |
+//! |-------------|-----------------|--------------------------------------------|
+//! | *(always)*  | [`bridge`]      | Request routing                            |
+//! | *(always)*  | [`errors`]      | Error codes and exit statuses              |
+//! | `chat`      | [`chat`]        | LLM chat, summaries, notes retrieval       |
+//! | `core`      | [`command`]     | Safety validation, `InferenceBackend`,     |
+//! |             |                 | `MockBackend` for tests                    |
+//! | `onnx`      | [`command`]     | `Core`: command generation (ONNX models)   |
+//! | `gguf`      | [`command`]     | `QuantizedLlm`: quantized GGUF models      |
+//! | `translate` | [`translate`]   | Language detection and translation         |
+//!
+//! `onnx` and `gguf` imply `core`.
+//!
+//! To embed only translation:
+//!
+//! ```toml
+//! [dependencies]
+//! eidos = { version = "0.2", default-features = false, features = ["translate"] }
+//! ```
+//!
+//! # Examples
+//!
+//! ```
+//! use eidos::prelude::*;
+//!
+//! let mut bridge = Bridge::new();
+//! bridge.register(Request::Chat, Box::new(|payload: &Payload| {
+//!     Ok(Response::Text(payload.input.to_uppercase()))
+//! }));
+//! let response = bridge.route(Request::Chat, "hello").unwrap();
+//! assert_eq!(response, Response::Text("HELLO".to_string()));
+//! ```
+
+/// Request routing between subsystems
+pub mod bridge {
+    pub use lib_bridge::{
+        AsyncHandler, Bridge, Handler, HandlerFuture, Middleware, MiddlewareFuture, Next, Payload,
+        Request, Response,
+    };
+}
+
+/// Error codes shared by every subsystem's error type
+pub mod errors {
+    pub use lib_errors::{EidosError, Error, ErrorCode, ErrorReport};
+}
+
+/// Chat with LLM providers, document summaries and notes retrieval
+#[cfg(feature = "chat")]
+pub mod chat {
+    pub use lib_chat::api::{ApiProvider, Feature};
+    pub use lib_chat::history::{
+        ConversationHistory, HistoryCompaction, Message, Role, TokenCounter,
+    };
+    pub use lib_chat::import::read_chatgpt_export;
+    pub use lib_chat::retrieval::Index;
+    pub use lib_chat::session::{Session, SessionStore};
+    pub use lib_chat::{build_index, Chat, ChatError, ChatOptions, SummaryLength, Verbosity};
+}
+
+/// Natural language to shell command generation and command safety validation
+#[cfg(feature = "core")]
+pub mod command {
+    pub use lib_core::compat::VocabMismatch;
+    #[cfg(feature = "onnx")]
+    pub use lib_core::Core;
+    #[cfg(any(feature = "onnx", feature = "gguf"))]
+    pub use lib_core::TokenizerWarning;
+    pub use lib_core::{
+        classify_command, is_safe_command, validate_command, Accelerator, BackendKind,
+        CommandGenerator, CommandSchema, DeviceSpec, GenerationConfig, InferenceBackend,
+        MockBackend, MockGenerator, ModelInfo, ModelPreset, PresetRegistry, PromptBuilder,
+        PromptTemplate, RefinementContext, RefinementSession, Risk, SafetyLevel, SafetyPolicy,
+        SafetyReport, SafetyRule, Severity,
+    };
+    #[cfg(feature = "gguf")]
+    pub use lib_core::{QuantizedLlm, QuantizedLlmError};
+}
+
+/// Language detection and translation
+#[cfg(feature = "translate")]
+pub mod translate {
+    pub use lib_translate::languages::{display_name, language_name, LanguageName};
+    pub use lib_translate::localize::{LocaleFormat, Localizer};
+    pub use lib_translate::{Translate, TranslateError, TranslationResult};
+}
+
+/// The most commonly used types, for glob import
+pub mod prelude {
+    pub use crate::bridge::{Bridge, Payload, Request, Response};
+    #[cfg(feature = "chat")]
+    pub use crate::chat::{Chat, ChatError};
+    #[cfg(feature = "onnx")]
+    pub use crate::command::Core;
+    #[cfg(feature = "core")]
+    pub use crate::command::{InferenceBackend, SafetyPolicy, SafetyReport};
+    pub use crate::errors::{EidosError, ErrorCode};
+    #[cfg(feature = "translate")]
+    pub use crate::translate::{Translate, TranslateError};
+}
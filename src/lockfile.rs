@@ -0,0 +1,106 @@
+// src/lockfile.rs
+// Consistent updates of files shared by concurrent invocations
+//
+// The shell widget, a script and an interactive session can all run `eidos` at the
+// same time against one command history and provider cache. Updates that read a
+// file and write it back hold an exclusive advisory lock on a `<file>.lock` beside
+// it, and readers that look at more than one file take a shared lock. The lock
+// lives in its own file because data files are replaced by renaming a new copy
+// over them, which would leave a lock on the old copy behind. Replacing by rename
+// (`lib_util::write_atomic`) also means readers never see half of a write.
+
+use fd_lock::RwLock;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Lock file guarding `path`
+fn lock_path(path: &Path) -> PathBuf {
+    let mut lock = path.to_path_buf().into_os_string();
+    lock.push(".lock");
+    lock.into()
+}
+
+fn open_lock(path: &Path) -> io::Result<RwLock<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(path))?;
+    Ok(RwLock::new(file))
+}
+
+/// Run `update` while holding the exclusive lock of `path`, creating its
+/// directory if needed
+///
+/// Blocks until other invocations have released the lock.
+pub fn with_exclusive<T>(path: &Path, update: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut lock = open_lock(path)?;
+    let _guard = lock.write()?;
+    update()
+}
+
+/// Run `read` while holding a shared lock of `path`, so no update is half done
+///
+/// When the lock file cannot be opened (no directory for `path` yet, or a
+/// read-only one) nobody can be updating it, and `read` runs without the lock.
+pub fn with_shared<T>(path: &Path, read: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    let Ok(lock) = open_lock(path) else {
+        return read();
+    };
+    let _guard = lock.read()?;
+    read()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn test_exclusive_updates_do_not_interleave() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/counter");
+        let threads = 8;
+        let barrier = Arc::new(Barrier::new(threads));
+
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    // Each thread opens the lock file itself, like another process
+                    with_exclusive(&path, || {
+                        let count: u32 =
+                            fs::read_to_string(&path).map_or(0, |count| count.parse().unwrap());
+                        thread::yield_now();
+                        lib_util::write_atomic(&path, (count + 1).to_string().as_bytes())
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), threads.to_string());
+        let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2, "no staging files left: {:?}", names);
+    }
+
+    #[test]
+    fn test_shared_read_without_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing/history.jsonl");
+        assert_eq!(with_shared(&path, || Ok(1)).unwrap(), 1);
+        assert!(!path.parent().unwrap().exists());
+    }
+}
@@ -0,0 +1,165 @@
+// src/logging.rs
+// Diagnostic logging for the CLI
+//
+// Logs are for operators, not for the user: they go to stderr, separate from the
+// results and messages of `output`. The crates log through `log` macros, which are
+// forwarded to a `tracing` subscriber, so one filter and one format cover
+// everything. Model loading, inference and requests to chat and translation
+// services run inside spans (`model_load`, `inference`, `http`); from the info
+// level on, each span logs its duration when it closes.
+//
+// `--log-format json` (or `[log] format`, `EIDOS_LOG_FORMAT`) writes one JSON
+// object per line for log pipelines. `RUST_LOG` (or `[log] filter`, `EIDOS_LOG`)
+// takes per-module directives such as `warn,lib_chat=debug`, applied on top of the
+// level chosen with `-v`/`-d`.
+
+#[cfg(feature = "core")]
+use lib_core::{GenerationConfig, InferenceBackend, ModelInfo};
+use std::str::FromStr;
+#[cfg(feature = "core")]
+use std::sync::Arc;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Rendering of log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `2026-01-05T10:00:00.000Z  INFO eidos: message`
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `target`, the message
+    /// and the current span
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "Unknown log format '{}' (expected text or json)",
+                other
+            )),
+        }
+    }
+}
+
+/// Filter directives: `level` for everything, then the per-module `directives`
+pub fn filter_directives(level: &str, directives: Option<&str>) -> String {
+    match directives.map(str::trim).filter(|d| !d.is_empty()) {
+        Some(directives) => format!("{},{}", level, directives),
+        None => level.to_string(),
+    }
+}
+
+/// Install the global subscriber; an invalid directive is skipped with a warning.
+/// `ansi` colours the level of text lines
+pub fn init(level: &str, directives: Option<&str>, format: LogFormat, ansi: bool) {
+    let directives = filter_directives(level, directives);
+    let filter = EnvFilter::builder().parse_lossy(&directives);
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+    let installed = match format {
+        LogFormat::Text => builder.with_ansi(ansi).try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+    if let Err(e) = installed {
+        crate::output::warning(format!("Logging could not be initialized: {}", e));
+    }
+    log::debug!("Logging initialized with filter '{}'", directives);
+}
+
+/// A backend whose calls each run in an `inference` span, timed for the metrics
+#[cfg(feature = "core")]
+pub struct Traced(pub Arc<dyn InferenceBackend>);
+
+#[cfg(feature = "core")]
+impl InferenceBackend for Traced {
+    fn generate(&self, request: &str, context: Option<&str>) -> anyhow::Result<String> {
+        let _span = tracing::info_span!("inference", op = "generate").entered();
+        timed("generate", || self.0.generate(request, context))
+    }
+
+    fn generate_with_config(
+        &self,
+        request: &str,
+        context: Option<&str>,
+        config: &GenerationConfig,
+    ) -> anyhow::Result<String> {
+        let _span = tracing::info_span!("inference", op = "generate").entered();
+        timed("generate", || {
+            self.0.generate_with_config(request, context, config)
+        })
+    }
+
+    fn explain(&self, command: &str) -> anyhow::Result<String> {
+        let _span = tracing::info_span!("inference", op = "explain").entered();
+        timed("explain", || self.0.explain(command))
+    }
+
+    fn explain_all(&self, commands: &[String]) -> Vec<anyhow::Result<String>> {
+        let _span =
+            tracing::info_span!("inference", op = "explain", commands = commands.len()).entered();
+        timed("explain", || self.0.explain_all(commands))
+    }
+
+    fn parallelism(&self) -> usize {
+        self.0.parallelism()
+    }
+
+    fn sampling(&self) -> Option<GenerationConfig> {
+        self.0.sampling()
+    }
+
+    fn alternatives(
+        &self,
+        request: &str,
+        count: usize,
+        context: Option<&str>,
+    ) -> anyhow::Result<Vec<String>> {
+        let _span = tracing::info_span!("inference", op = "alternatives", count).entered();
+        timed("alternatives", || {
+            self.0.alternatives(request, count, context)
+        })
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        self.0.model_info()
+    }
+}
+
+/// Run one inference pass of kind `op`, timing it for the metrics
+#[cfg(feature = "core")]
+fn timed<T>(op: &str, pass: impl FnOnce() -> T) -> T {
+    let started = std::time::Instant::now();
+    let result = pass();
+    crate::metrics::record_inference(op, started.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directives_refine_the_level() {
+        assert_eq!(filter_directives("warn", None), "warn");
+        assert_eq!(filter_directives("info", Some("  ")), "info");
+        assert_eq!(
+            filter_directives("off", Some("lib_chat=debug")),
+            "off,lib_chat=debug"
+        );
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
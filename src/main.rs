@@ -1,36 +1,145 @@
+// Slim builds (see [features] in Cargo.toml) leave some shared helpers unused
+#![cfg_attr(
+    not(all(feature = "chat", feature = "core", feature = "translate")),
+    allow(dead_code)
+)]
+
+mod completions;
 mod config;
 mod constants;
+mod context;
+#[cfg(all(unix, feature = "serve"))]
+mod daemon;
 mod error;
+#[cfg(any(feature = "chat", feature = "translate"))]
+mod health;
+#[cfg(feature = "core")]
+mod history;
+#[cfg(any(feature = "chat", feature = "translate"))]
+mod legacy_env;
+#[cfg(any(feature = "chat", feature = "translate", feature = "core"))]
+mod lockfile;
+mod logging;
+#[cfg(any(feature = "chat", feature = "translate", feature = "core"))]
+mod metrics;
+#[cfg(feature = "models")]
+mod models;
+mod output;
+mod paths;
+#[cfg(feature = "core")]
+mod run_safe;
+#[cfg(feature = "core")]
+mod shell_integration;
+#[cfg(feature = "core")]
+mod stats;
+mod terminal;
 
-use crate::config::Config;
+#[cfg(feature = "core")]
+use crate::config::ModelSettings;
+use crate::config::{Config, LogConfig};
 use crate::constants::*;
+#[cfg(any(feature = "chat", feature = "core"))]
+use crate::context::EnvContext;
 use crate::error::Result;
-use clap::{Parser, Subcommand};
+#[cfg(feature = "core")]
+use crate::history::CommandHistory;
+use crate::logging::LogFormat;
+#[cfg(any(feature = "chat", feature = "translate"))]
+use crate::output::ConfigMigrateResult;
+use crate::output::Hints;
+#[cfg(all(feature = "core", feature = "translate"))]
+use crate::output::PromptTranslation;
+#[cfg(all(feature = "chat", feature = "translate"))]
+use crate::output::SessionTranslationResult;
+#[cfg(feature = "translate")]
+use crate::output::TranslationResultOutput;
+#[cfg(feature = "core")]
+use crate::output::{
+    AuditResult, BatchEntry, BatchResult, CacheStatsResult, CommandResult, DoctorResult,
+    EvalResult, ExplainResult, GeneratedCommand, HistoryResult, MetricsResult, UndoResult,
+};
+#[cfg(any(feature = "core", feature = "chat", feature = "translate"))]
+use crate::output::{BenchMeasurement, BenchResult};
+#[cfg(feature = "chat")]
+use crate::output::{
+    ChatResult, IndexResult, SessionImportResult, SessionSummary, SessionsResult, SummaryResult,
+};
+#[cfg(any(feature = "chat", feature = "core"))]
+use crate::output::{CompactResult, CompactedFile};
+use crate::output::{ManLinks, OutputFormat, TextResult};
+#[cfg(feature = "models")]
+use crate::output::{
+    ModelFileInfo, ModelInfoResult, ModelListResult, ModelPullResult, ModelSummary,
+};
+#[cfg(feature = "core")]
+use crate::shell_integration::Shell;
+#[cfg(feature = "core")]
+use crate::stats::{Dataset, ExportFormat, Table};
+use crate::terminal::ColorChoice;
+#[cfg(feature = "chat")]
+use crate::terminal::MarkdownStream;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+#[cfg(any(feature = "chat", feature = "models"))]
+use clap_complete::ArgValueCandidates;
+use clap_complete::CompleteEnv;
+#[cfg(feature = "core")]
 use lazy_static::lazy_static;
-use lib_bridge::{Bridge, Request};
-use lib_chat::Chat;
-use lib_core::Core;
-use lib_translate::Translate;
+#[cfg(all(feature = "core", feature = "translate"))]
+use lib_bridge::pipeline::Pipeline;
+#[cfg(feature = "core")]
+use lib_bridge::pipeline::{REPLY_LANG_OPTION, SPECULATIVE_OPTION};
+#[cfg(feature = "translate")]
+use lib_bridge::pipeline::{SOURCE_LANG_OPTION, TARGET_LANG_OPTION};
+use lib_bridge::{Bridge, Payload, Request, Response};
+#[cfg(feature = "chat")]
+use lib_chat::export::TranscriptFormat;
+#[cfg(all(feature = "chat", feature = "translate"))]
+use lib_chat::history::ConversationHistory;
+#[cfg(feature = "vision")]
+use lib_chat::image::Image;
+#[cfg(feature = "chat")]
+use lib_chat::retrieval::{parse_citations, Embedder, Index};
+#[cfg(all(feature = "chat", feature = "translate"))]
+use lib_chat::session::Session;
+#[cfg(feature = "chat")]
+use lib_chat::session::SessionStore;
+#[cfg(feature = "chat")]
+use lib_chat::{Chat, ChatOptions, SummaryLength, Verbosity};
+#[cfg(feature = "core")]
+use lib_core::registry::{Loaded, ModelRegistry};
+#[cfg(any(feature = "onnx", feature = "gguf", feature = "models"))]
+use lib_core::BackendKind;
+#[cfg(feature = "core")]
+use lib_core::{
+    CancelFlag, HistoryContext, InferenceBackend, RefinementSession, Risk, SafetyLevel,
+    SafetyPolicy, SafetyReport, TemplateVars,
+};
+use lib_errors::{EidosError, ErrorCode};
+#[cfg(feature = "translate")]
+use lib_translate::languages::display_name;
 use log::{debug, error, info, warn};
-use parking_lot::RwLock;
+use std::io::IsTerminal;
+#[cfg(feature = "chat")]
+use std::io::Read;
+#[cfg(any(
+    feature = "chat",
+    feature = "core",
+    feature = "serve",
+    feature = "translate"
+))]
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-/// Cached model instance to avoid reloading from disk on every request
-struct ModelCache {
-    core: Option<Arc<Core>>,
-    model_path: String,
-    tokenizer_path: String,
-}
-
+#[cfg(feature = "core")]
 lazy_static! {
-    static ref MODEL_CACHE: RwLock<ModelCache> = RwLock::new(ModelCache {
-        core: None,
-        model_path: String::new(),
-        tokenizer_path: String::new(),
-    });
+    /// Models loaded by this process, keyed by the settings they were loaded with
+    static ref MODEL_REGISTRY: ModelRegistry<ModelSettings> = ModelRegistry::new();
 }
 
-/// Get or load the Core model from cache
+/// Get or load the inference backend from the registry
 ///
 /// This function implements model caching to avoid the performance penalty
 /// of loading 200MB+ model files from disk on every request.
@@ -40,52 +149,170 @@ lazy_static! {
 /// - Subsequent calls: Returns cached instance (~1-10ms)
 ///
 /// # Thread Safety
-/// Uses RwLock to allow multiple concurrent reads while ensuring
-/// exclusive access during model loading.
+/// The registry loads under its lock, so concurrent requests for a model that
+/// is not loaded yet wait for one load instead of each starting their own.
+#[cfg(feature = "core")]
 fn get_or_load_model(
-    model_path: &str,
-    tokenizer_path: &str,
-) -> std::result::Result<Arc<Core>, String> {
-    // Fast path: Check if model is already cached with read lock
-    {
-        let cache = MODEL_CACHE.read();
-        if let Some(ref core) = cache.core {
-            if cache.model_path == model_path && cache.tokenizer_path == tokenizer_path {
-                debug!("Returning cached model instance (fast path)");
-                return Ok(Arc::clone(core));
-            }
-        }
+    settings: &ModelSettings,
+) -> std::result::Result<Arc<dyn InferenceBackend>, String> {
+    let (backend, loaded) = MODEL_REGISTRY.get_or_load(settings, &settings.files(), || {
+        info!("Loading model from disk (first request, config or files changed)");
+        debug!("Backend: {}", settings.backend);
+        debug!("Model path: {}", settings.model_path);
+        debug!("Tokenizer path: {}", settings.tokenizer_path);
+
+        let start = std::time::Instant::now();
+        let backend = {
+            let _span = tracing::info_span!("model_load", backend = %settings.backend).entered();
+            load_backend(settings)?
+        };
+        info!(
+            "Model loaded successfully in {:.2}s",
+            start.elapsed().as_secs_f64()
+        );
+        Ok::<_, String>(Arc::new(logging::Traced(backend)) as Arc<dyn InferenceBackend>)
+    })?;
+    match loaded {
+        Loaded::Cached => debug!("Returning cached model instance"),
+        Loaded::Loaded => {}
+        Loaded::Reloaded => info!("Model files changed on disk; reloaded"),
+        // A file still being copied into place fails to load; keep serving the
+        // previous model and try again on the next request
+        Loaded::Stale(e) => output::warning(format!(
+            "Reloading the changed model failed ({}); still using the one loaded before",
+            e
+        )),
     }
+    Ok(backend)
+}
 
-    // Slow path: Load model with write lock
-    let mut cache = MODEL_CACHE.write();
+/// Load the configured model with the selected backend
+#[cfg(feature = "core")]
+fn load_backend(
+    settings: &ModelSettings,
+) -> std::result::Result<Arc<dyn InferenceBackend>, String> {
+    let system_prompt = settings.system_prompt.as_deref();
+    if let Some(prompt) = system_prompt {
+        debug!("Using system prompt: {}", sanitize_for_logging(prompt, 50));
+    }
+    let preset = settings.preset.as_ref();
+    if let Some(preset) = preset {
+        debug!("Using generation preset '{}'", preset.name);
+    }
 
-    // Double-check in case another thread loaded it while we waited for write lock
-    if let Some(ref core) = cache.core {
-        if cache.model_path == model_path && cache.tokenizer_path == tokenizer_path {
-            debug!("Model loaded by another thread (double-check)");
-            return Ok(Arc::clone(core));
+    match settings.backend {
+        #[cfg(feature = "onnx")]
+        BackendKind::Onnx => {
+            if !matches!(
+                settings.device,
+                lib_core::DeviceSpec::Cpu | lib_core::DeviceSpec::Auto
+            ) {
+                output::warning(format!(
+                    "The onnx backend runs on the CPU; ignoring device = \"{}\"",
+                    settings.device
+                ));
+            }
+            if settings.temperature.is_some()
+                || settings.seed.is_some()
+                || settings.max_tokens.is_some()
+            {
+                debug!("The onnx backend decodes in a single pass; ignoring temperature, seed and max_tokens");
+            }
+            if settings.instances > 1 {
+                debug!(
+                    "The onnx backend serves concurrent requests from one copy; ignoring instances"
+                );
+            }
+            if settings.grammar.is_some() {
+                debug!("The onnx backend decodes in a single pass; ignoring constrained");
+            }
+            let mut core = lib_core::Core::new(&settings.model_path, &settings.tokenizer_path)
+                .map_err(|e| format!("Failed to load model: {}", e))?;
+            report_tokenizer_warnings(core.tokenizer_warnings());
+            if let Some(prompt) = system_prompt {
+                core = core.with_system_prompt(prompt);
+            }
+            if let Some(preset) = preset {
+                core = core.with_preset(preset);
+            }
+            Ok(Arc::new(core))
+        }
+        #[cfg(feature = "gguf")]
+        BackendKind::Gguf => {
+            let (device, fallback) = lib_core::device::select_device(settings.device);
+            if let Some(reason) = fallback {
+                output::warning(reason);
+            }
+            debug!("Device: {}", lib_core::device::device_name(&device));
+            let mut first = true;
+            let mut load = || -> std::result::Result<Arc<dyn InferenceBackend>, String> {
+                let mut llm = lib_core::QuantizedLlm::new_with_device(
+                    &settings.model_path,
+                    &settings.tokenizer_path,
+                    device.clone(),
+                )
+                .map_err(|e| format!("Failed to load model: {}", e))?;
+                let first_copy = std::mem::take(&mut first);
+                if first_copy {
+                    report_tokenizer_warnings(llm.tokenizer_warnings());
+                }
+                if let Some(prompt) = system_prompt {
+                    llm = llm.with_system_prompt(prompt);
+                }
+                if let Some(preset) = preset {
+                    llm = llm.with_preset(preset);
+                }
+                if let Some(temperature) = settings.temperature {
+                    let generation = llm
+                        .generation_config()
+                        .clone()
+                        .with_temperature(temperature);
+                    llm = llm.with_generation_config(generation);
+                }
+                if let Some(seed) = settings.seed {
+                    let generation = llm.generation_config().clone().with_seed(seed);
+                    llm = llm.with_generation_config(generation);
+                }
+                if first_copy {
+                    // Reported so a run can be repeated with `--seed`
+                    info!("Sampling seed: {}", llm.generation_config().seed);
+                }
+                if let Some(max_tokens) = settings.max_tokens {
+                    llm = llm.with_max_tokens(max_tokens);
+                }
+                if let Some(grammar) = &settings.grammar {
+                    llm = llm.with_grammar(grammar.clone());
+                }
+                Ok(Arc::new(llm))
+            };
+            // Each copy decodes one request at a time, so concurrent daemon
+            // requests need several to run in parallel
+            match settings.instances {
+                1 => load(),
+                instances => {
+                    debug!("Loading {} copies of the model", instances);
+                    Ok(Arc::new(lib_core::GeneratorPool::load(instances, load)?))
+                }
+            }
         }
+        #[allow(unreachable_patterns)]
+        kind => Err(format!(
+            "The {} backend is not available: eidos was built without the `{}` feature",
+            kind, kind
+        )),
     }
+}
 
-    info!("Loading model from disk (first request or config changed)");
-    debug!("Model path: {}", model_path);
-    debug!("Tokenizer path: {}", tokenizer_path);
-
-    let start = std::time::Instant::now();
-
-    let core = Core::new(model_path, tokenizer_path)
-        .map_err(|e| format!("Failed to load model: {}", e))?;
-
-    let elapsed = start.elapsed();
-    info!("Model loaded successfully in {:.2}s", elapsed.as_secs_f64());
-
-    let core_arc = Arc::new(core);
-    cache.core = Some(Arc::clone(&core_arc));
-    cache.model_path = model_path.to_string();
-    cache.tokenizer_path = tokenizer_path.to_string();
-
-    Ok(core_arc)
+/// Tell the user which tokenizer components were replaced with defaults
+#[cfg(any(feature = "onnx", feature = "gguf"))]
+fn report_tokenizer_warnings(warnings: &[lib_core::TokenizerWarning]) {
+    for warning in warnings {
+        if warning.is_routine() {
+            debug!("{}", warning);
+        } else {
+            output::warning(warning.to_string());
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -103,33 +330,724 @@ struct Cli {
 
     #[clap(short, long, global = true, help = "Enable debug logging")]
     debug: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        help = "Output format: text, json or raw (default: [ui] output, or text)"
+    )]
+    output: Option<OutputFormat>,
+
+    #[clap(
+        long,
+        global = true,
+        conflicts_with = "output",
+        help = "Print only the bare result, e.g. the command; same as --output raw"
+    )]
+    raw: bool,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        help = "Print only results and errors: no tips, progress notes, emoji or log warnings"
+    )]
+    quiet: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "WHEN",
+        help = "Colour output: auto (terminals, unless NO_COLOR is set), always or never"
+    )]
+    color: Option<ColorChoice>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "LANG",
+        help = "Show explanations and tips in this language, e.g. tr (default: [ui] lang, or the locale)"
+    )]
+    lang: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        help = "Log format: text or json (default: [log] format, or text)"
+    )]
+    log_format: Option<LogFormat>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Give up on requests that take longer than this many seconds"
+    )]
+    timeout: Option<u64>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Don't print configuration tips after errors (also [ui] tips = false)"
+    )]
+    no_tips: bool,
+
+    #[clap(
+        long,
+        global = true,
+        overrides_with = "no_context",
+        help = "Generate commands knowing the directory name and contents, project type, shell, OS and whether this is a git repository (also [context] facts = true)"
+    )]
+    context: bool,
+
+    #[clap(
+        long,
+        global = true,
+        overrides_with = "context",
+        help = "Generate commands without any facts or variables from the environment, overriding [context]"
+    )]
+    no_context: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Pipeline mode: single-line verdicts, no colour, emoji or tips, never prompts (also [ci] enabled = true)"
+    )]
+    ci: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    #[clap(about = "Chat with the AI model")]
+    #[cfg(feature = "chat")]
+    #[clap(
+        about = "Chat with the AI model",
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
     Chat {
-        #[clap(help = "The input text for the chat")]
-        text: String,
+        #[clap(subcommand)]
+        action: Option<ChatAction>,
+
+        #[clap(
+            required = true,
+            help = "The input text for the chat; put `--` before a message that is just `export`"
+        )]
+        text: Option<String>,
+
+        #[clap(
+            long,
+            value_name = "NAME",
+            help = "Ground the answer in a notes index built with `eidos index`",
+            add = ArgValueCandidates::new(completions::indexes)
+        )]
+        with_index: Option<String>,
+
+        #[clap(long, default_value = "4", help = "Number of index chunks to include")]
+        top_k: usize,
+
+        #[clap(
+            long,
+            conflicts_with = "detailed",
+            help = "Short answers: a sentence or two, or just the command"
+        )]
+        concise: bool,
+
+        #[clap(long, help = "Thorough answers with reasoning and examples")]
+        detailed: bool,
+
+        #[clap(
+            long,
+            value_name = "NAME",
+            help = "Answer as a persona: sysadmin, teacher or a [personas.NAME] table of the config",
+            add = ArgValueCandidates::new(completions::personas)
+        )]
+        persona: Option<String>,
+
+        #[clap(
+            long,
+            value_parser = lib_chat::api::PROVIDER_NAMES,
+            help = "Provider to ask instead of the configured one: openai, anthropic, gemini, ollama or custom"
+        )]
+        provider: Option<String>,
+
+        #[clap(
+            long,
+            value_name = "NAME",
+            help = "Model to use instead of the provider's default"
+        )]
+        model: Option<String>,
+
+        #[clap(
+            long,
+            value_parser = parse_temperature,
+            help = "Sampling temperature, 0 to 2 [default: 0.7]"
+        )]
+        temperature: Option<f32>,
+
+        #[clap(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Longest reply in tokens [default: 1000, 300 with --concise, 2000 with --detailed]"
+        )]
+        max_tokens: Option<u32>,
+
+        #[cfg(feature = "vision")]
+        #[clap(
+            long,
+            value_name = "FILE|URL",
+            help = "Show the model an image (PNG, JPEG, GIF or WebP) with the question; repeatable"
+        )]
+        image: Vec<String>,
     },
+    #[cfg(feature = "core")]
     #[clap(about = "Generate shell command from natural language prompt")]
     Core {
-        #[clap(help = "The natural language prompt describing desired command")]
-        prompt: String,
+        #[clap(
+            required_unless_present = "batch",
+            help = "The natural language prompt describing desired command"
+        )]
+        prompt: Option<String>,
+
+        #[clap(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["prompt", "alternatives", "run_safe", "preview", "refine", "vars"],
+            help = "Generate and safety-check a command for each line of FILE (- for stdin); nothing is run, cached or recorded"
+        )]
+        batch: Option<PathBuf>,
+
+        #[clap(
+            long,
+            value_name = "N",
+            default_value = "1",
+            value_parser = clap::value_parser!(u16).range(1..),
+            requires = "batch",
+            conflicts_with = "prompt",
+            help = "Prompts generated at once in --batch mode; GGUF models are loaded that many times"
+        )]
+        jobs: u16,
 
-        #[clap(short = 'n', long, default_value = "1", help = "Number of alternative commands to generate")]
+        #[clap(
+            long,
+            requires = "batch",
+            conflicts_with = "prompt",
+            help = "Print each --batch result as a JSON line as soon as it is ready, instead of one array at the end"
+        )]
+        ndjson: bool,
+
+        #[clap(
+            short = 'n',
+            long,
+            default_value = "1",
+            help = "Number of alternative commands to generate"
+        )]
         alternatives: usize,
 
-        #[clap(short = 'e', long, help = "Include explanation of what the command does")]
+        #[clap(
+            short = 'e',
+            long,
+            help = "Include explanation of what the command does"
+        )]
+        explain: bool,
+
+        #[clap(
+            long,
+            conflicts_with = "alternatives",
+            help = "Run the command and show its output if it is read-only (e.g. df -h)"
+        )]
+        run_safe: bool,
+
+        #[clap(
+            long,
+            conflicts_with_all = ["alternatives", "refine"],
+            help = "Show the paths the command would touch, whether it recurses and roughly how much it prints, without running it"
+        )]
+        preview: bool,
+
+        #[cfg(feature = "chat")]
+        #[clap(
+            long,
+            value_name = "LENGTH",
+            num_args = 0..=1,
+            default_missing_value = "short",
+            requires = "run_safe",
+            help = "Answer the prompt from the --run-safe output via the chat provider: short or long. The output can hold the contents of any file the command reads, so a provider that is not on this machine needs [context] allow_remote_output = true"
+        )]
+        summarize_output: Option<SummaryLength>,
+
+        #[clap(
+            long,
+            conflicts_with_all = ["alternatives", "run_safe"],
+            help = "Refine the command with follow-up instructions (e.g. \"sort by size\") before accepting it"
+        )]
+        refine: bool,
+
+        #[clap(
+            long = "var",
+            value_name = "NAME=VALUE",
+            value_parser = parse_template_var,
+            conflicts_with = "refine",
+            help = "Value for a {NAME} placeholder in the prompt; the command is generated as a template and the values are filled in"
+        )]
+        vars: Vec<(String, String)>,
+
+        #[clap(
+            long,
+            help = "Never ask a clarifying question (e.g. \"Which directory?\") about a vague prompt"
+        )]
+        no_ask: bool,
+
+        #[clap(
+            long,
+            help = "Generate afresh instead of reusing the command cached for this prompt (the new one replaces it)"
+        )]
+        no_cache: bool,
+
+        #[clap(
+            long,
+            value_name = "N",
+            value_parser = clap::value_parser!(u8).range(1..=lib_core::prompt::MAX_HISTORY_TURNS as i64),
+            conflicts_with = "batch",
+            help = "Show the model the last N prompts and commands from the history, for prompts like \"same as before but sorted by size\""
+        )]
+        context_history: Option<u8>,
+
+        #[clap(
+            long,
+            value_name = "SEED",
+            help = "Sampling seed for GGUF models, to reproduce a command or its alternatives exactly; skips the daemon and the command cache"
+        )]
+        seed: Option<u64>,
+    },
+    #[cfg(all(feature = "core", feature = "translate"))]
+    #[clap(about = "Generate a shell command from a prompt in any language")]
+    Ask {
+        #[clap(help = "The prompt, in any language; it is translated to English first")]
+        prompt: String,
+
+        #[clap(
+            short = 'e',
+            long,
+            help = "Include an explanation, translated into the prompt's language"
+        )]
         explain: bool,
     },
+    #[cfg(feature = "core")]
+    #[clap(about = "Explain what a shell command does, option by option")]
+    Explain {
+        #[clap(help = "The command to explain, quoted (e.g. \"tar -xzvf file.tar.gz\")")]
+        command: String,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "Check every command of a shell script against the safety rules")]
+    AuditScript {
+        #[clap(help = "The script to audit")]
+        file: PathBuf,
+
+        #[clap(long, help = "Print the findings as a SARIF 2.1.0 log")]
+        sarif: bool,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "Measure the model against a dataset of prompts and expected commands")]
+    Eval {
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "JSONL file of {\"prompt\": ..., \"command\": ...} pairs, as used for training"
+        )]
+        dataset: PathBuf,
+
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Also write the full report, every case included, as JSON"
+        )]
+        report: Option<PathBuf>,
+
+        #[clap(long, value_name = "N", help = "Only evaluate the first N cases")]
+        limit: Option<usize>,
+
+        #[clap(
+            long,
+            value_name = "SEED",
+            help = "Sampling seed for GGUF models, to repeat a run exactly"
+        )]
+        seed: Option<u64>,
+    },
+    #[cfg(any(feature = "core", feature = "chat", feature = "translate"))]
+    #[clap(
+        about = "Time model loading, generation, validation and provider round trips on this machine"
+    )]
+    Bench {
+        #[clap(
+            long,
+            value_name = "N",
+            default_value_t = 3,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Generate each benchmark prompt N times"
+        )]
+        runs: u16,
+
+        #[clap(long, help = "Skip loading the model and generating")]
+        no_model: bool,
+
+        #[clap(long, help = "Skip the chat and translation round trips")]
+        no_providers: bool,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "List generated commands, or show how to undo one")]
+    History {
+        #[clap(subcommand)]
+        action: Option<HistoryAction>,
+
+        #[clap(
+            short = 'n',
+            long,
+            default_value = "20",
+            help = "Number of recent entries to list"
+        )]
+        limit: usize,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "Export usage and history data for analysis in other tools")]
+    Stats {
+        #[clap(subcommand)]
+        action: StatsAction,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "Show or clear the cache of generated commands (~/.cache/eidos/commands)")]
+    Cache {
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+    #[cfg(feature = "models")]
+    #[clap(about = "Download, list and remove models (~/.local/share/eidos/models)")]
+    Model {
+        #[clap(subcommand)]
+        action: ModelAction,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "Print a Ctrl-G keybinding that puts generated commands on your prompt line")]
+    ShellInit {
+        #[clap(help = "Shell to integrate with: bash, zsh or fish")]
+        shell: Shell,
+    },
+    #[clap(about = "Print a script that enables tab completion for eidos in your shell")]
+    Completions {
+        #[clap(
+            value_parser = completions::SHELLS,
+            help = "Shell to complete in: bash, elvish, fish, powershell or zsh"
+        )]
+        shell: String,
+    },
+    #[cfg(feature = "core")]
+    #[clap(about = "Check the model configuration and list the accelerators eidos can use")]
+    Doctor,
+    #[cfg(feature = "chat")]
+    #[clap(about = "Build an embedding index over text/markdown notes for chat retrieval")]
+    Index {
+        #[clap(help = "Directory containing the notes to index")]
+        dir: PathBuf,
+
+        #[clap(long, help = "Index name (defaults to the directory name)")]
+        name: Option<String>,
+    },
+    #[cfg(feature = "chat")]
+    #[clap(about = "Summarize a file or text piped on stdin")]
+    Summarize {
+        #[clap(short, long, help = "File to summarize (reads stdin when omitted)")]
+        file: Option<PathBuf>,
+
+        #[clap(
+            short,
+            long,
+            default_value = "short",
+            help = "Summary length: short or long"
+        )]
+        length: SummaryLength,
+    },
+    #[cfg(feature = "translate")]
     #[clap(about = "Translate text")]
     Translate {
         #[clap(help = "The text to translate")]
         text: String,
+
+        #[clap(
+            long,
+            value_name = "LANG",
+            help = "Language of the text, skipping detection (e.g. es)"
+        )]
+        from: Option<String>,
+
+        #[clap(
+            long,
+            value_name = "LANG",
+            help = "Language to translate into (default: [translate] target_lang, or en)"
+        )]
+        to: Option<String>,
+
+        #[clap(
+            long,
+            help = "Rewrite numbers and dates for the target language (e.g. 1.234,5 -> 1,234.5)"
+        )]
+        localize: bool,
+    },
+    #[cfg(feature = "chat")]
+    #[clap(about = "List saved chat sessions or import conversations from other apps")]
+    Sessions {
+        #[clap(subcommand)]
+        action: Option<SessionsAction>,
+    },
+    #[cfg(any(feature = "chat", feature = "translate"))]
+    #[clap(about = "Move settings from deprecated environment variables into eidos.toml")]
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+    #[cfg(any(feature = "chat", feature = "core"))]
+    #[clap(about = "Tidy the data directory (~/.local/share/eidos)")]
+    Maintenance {
+        #[clap(subcommand)]
+        action: MaintenanceAction,
+    },
+    #[cfg(all(unix, feature = "serve"))]
+    #[clap(about = "Keep the model loaded and serve requests over a Unix socket")]
+    Daemon {
+        #[clap(
+            long,
+            value_name = "PATH",
+            help = "Socket path (default: $XDG_RUNTIME_DIR/eidos/daemon.sock or $EIDOS_SOCKET)"
+        )]
+        socket: Option<PathBuf>,
+    },
+    /// Bare input routed to `default_command` from eidos.toml
+    #[clap(external_subcommand)]
+    External(Vec<String>),
+}
+
+#[cfg(feature = "core")]
+#[derive(Subcommand, Debug)]
+enum HistoryAction {
+    #[clap(about = "Print the command that reverses history entry <ID>")]
+    Undo {
+        #[clap(help = "Entry id, as shown by `eidos history`")]
+        id: u64,
+    },
+}
+
+#[cfg(feature = "core")]
+#[derive(Subcommand, Debug)]
+enum StatsAction {
+    #[clap(about = "Write the command history or the daily usage as CSV or Parquet")]
+    Export {
+        #[clap(long, value_enum, default_value = "csv", help = "File format")]
+        format: ExportFormat,
+
+        #[clap(
+            long,
+            value_enum,
+            default_value = "history",
+            help = "history: one row per generated command; usage: one row per day"
+        )]
+        data: Dataset,
+
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Write to FILE instead of stdout (required for parquet)"
+        )]
+        out: Option<PathBuf>,
+    },
+    #[clap(
+        about = "Show the local usage metrics ([metrics] enabled); --output raw prints the Prometheus format"
+    )]
+    Metrics {
+        #[clap(long, help = "Delete the recorded metrics")]
+        reset: bool,
+    },
+}
+
+#[cfg(feature = "core")]
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    #[clap(about = "Number, size and use of the cached commands")]
+    Stats,
+    #[clap(about = "Remove every cached command and explanation")]
+    Clear,
+}
+
+#[cfg(feature = "models")]
+#[derive(Subcommand, Debug)]
+enum ModelAction {
+    #[clap(about = "Download a model and its tokenizer and use it from now on")]
+    Pull {
+        #[clap(help = "Hugging Face file (owner/repo[@revision]/file.gguf) or an HTTP(S) URL")]
+        source: String,
+
+        #[clap(
+            long,
+            value_name = "SOURCE",
+            help = "Tokenizer to download (default: tokenizer.json beside the model)"
+        )]
+        tokenizer: Option<String>,
+
+        #[clap(
+            long,
+            value_name = "HEX",
+            help = "Expected SHA-256 of the model file (Hub files are checked without it)"
+        )]
+        sha256: Option<String>,
+
+        #[clap(long, help = "Name to keep the model under (default: the file name)")]
+        name: Option<String>,
+
+        #[clap(long, help = "Download again even if the model is already there")]
+        force: bool,
+
+        #[clap(long, help = "Do not point eidos.toml at the model")]
+        no_activate: bool,
+    },
+    #[clap(about = "List downloaded models; * marks the one in use")]
+    List,
+    #[clap(about = "Delete a downloaded model")]
+    Remove {
+        #[clap(
+            help = "Model name, as listed by `eidos model list`",
+            add = ArgValueCandidates::new(completions::models)
+        )]
+        name: String,
+
+        #[clap(long, help = "Delete it even if it is the configured model")]
+        force: bool,
+    },
+    #[clap(about = "Show where a model came from, its files and checksums")]
+    Info {
+        #[clap(
+            help = "Model name, as listed by `eidos model list`",
+            add = ArgValueCandidates::new(completions::models)
+        )]
+        name: String,
+    },
+}
+
+#[cfg(feature = "chat")]
+#[derive(Subcommand, Debug)]
+enum ChatAction {
+    #[clap(
+        about = "Write a saved session as Markdown, JSON or HTML (same as `eidos sessions export`)"
+    )]
+    Export {
+        #[clap(
+            long,
+            help = "Session name, as listed by `eidos sessions`",
+            add = ArgValueCandidates::new(completions::sessions)
+        )]
+        session: String,
+
+        #[clap(
+            long,
+            default_value = "markdown",
+            help = "Document format: markdown, json or html"
+        )]
+        format: TranscriptFormat,
+    },
+}
+
+#[cfg(feature = "chat")]
+#[derive(Subcommand, Debug)]
+enum SessionsAction {
+    #[clap(about = "Import the conversations of a ChatGPT data export")]
+    Import {
+        #[clap(help = "The export zip, or the conversations.json extracted from it")]
+        file: PathBuf,
+    },
+    #[clap(about = "Write a saved session as Markdown, JSON or HTML, with roles and timestamps")]
+    Export {
+        #[clap(
+            help = "Session name, as listed by `eidos sessions`",
+            add = ArgValueCandidates::new(completions::sessions)
+        )]
+        name: String,
+
+        #[clap(
+            long,
+            default_value = "markdown",
+            help = "Document format: markdown, json or html"
+        )]
+        format: TranscriptFormat,
+    },
+    #[cfg(feature = "translate")]
+    #[clap(about = "Translate a saved session into another language, kept as a copy")]
+    Translate {
+        #[clap(
+            help = "Session name, as listed by `eidos sessions`",
+            add = ArgValueCandidates::new(completions::sessions)
+        )]
+        name: String,
+
+        #[clap(
+            long,
+            value_name = "LANG",
+            help = "Language to translate into (e.g. de)"
+        )]
+        to: String,
+    },
+}
+
+#[cfg(any(feature = "chat", feature = "translate"))]
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    #[clap(
+        about = "Write the settings that OLLAMA_HOST, LIBRETRANSLATE_URL and other deprecated variables stand for"
+    )]
+    Migrate {
+        #[clap(long, help = "Show the settings without writing them")]
+        dry_run: bool,
+
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Config file to write (default: ./eidos.toml if present, else ~/.config/eidos/eidos.toml)"
+        )]
+        file: Option<PathBuf>,
+    },
+}
+
+#[cfg(any(feature = "chat", feature = "core"))]
+#[derive(Subcommand, Debug)]
+enum MaintenanceAction {
+    #[clap(
+        about = "Compress the command history and notes indexes, optionally dropping old history"
+    )]
+    Compact {
+        #[clap(
+            long,
+            value_name = "DAYS",
+            help = "Drop history entries older than this many days"
+        )]
+        max_age: Option<u64>,
+
+        #[clap(
+            long,
+            value_name = "MB",
+            help = "Drop the oldest history entries until the history fits in this many megabytes"
+        )]
+        max_size: Option<u64>,
     },
 }
 
+/// Maximum accepted input length for a request type
+fn max_input_length(request: Request) -> usize {
+    match request {
+        Request::Chat => MAX_CHAT_INPUT_LENGTH,
+        Request::Core => MAX_CORE_PROMPT_LENGTH,
+        Request::Translate => MAX_TRANSLATE_INPUT_LENGTH,
+    }
+}
+
 /// Sanitize sensitive text for logging by truncating and masking
 ///
 /// This prevents sensitive information from being exposed in debug logs.
@@ -137,7 +1055,11 @@ enum Commands {
 fn sanitize_for_logging(text: &str, max_chars: usize) -> String {
     let char_count = text.chars().count();
     if char_count <= max_chars {
-        format!("{}... ({} chars)", text.chars().take(max_chars).collect::<String>(), char_count)
+        format!(
+            "{}... ({} chars)",
+            text.chars().take(max_chars).collect::<String>(),
+            char_count
+        )
     } else {
         format!(
             "{}... [TRUNCATED] ({} chars total)",
@@ -176,361 +1098,3369 @@ fn validate_input(text: &str, max_length: usize) -> std::result::Result<(), Stri
 }
 
 /// Initialize logging based on verbosity level
-fn init_logging(verbose: bool, debug_mode: bool) {
+fn init_logging(
+    verbose: bool,
+    debug_mode: bool,
+    ci: bool,
+    quiet: bool,
+    log: &LogConfig,
+    format: LogFormat,
+    ansi: bool,
+) {
     let log_level = if debug_mode {
         "debug"
     } else if verbose {
         "info"
+    } else if ci {
+        // Verdicts and errors are the only stderr lines a pipeline should parse
+        "off"
+    } else if quiet {
+        "error"
     } else {
         "warn"
     };
 
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level))
-        .format_timestamp_millis()
-        .format_module_path(true)
-        .init();
+    logging::init(log_level, log.filter().as_deref(), format, ansi);
+}
 
-    debug!("Logging initialized at {} level", log_level);
+/// Notes index and number of chunks to ground a chat request in
+#[cfg(feature = "chat")]
+struct ChatGrounding<'a> {
+    index: &'a Index,
+    top_k: usize,
 }
 
-/// Set up the Bridge with all request handlers
-fn setup_bridge() -> Bridge {
-    let mut bridge = Bridge::new();
+/// Persona, provider and sampling settings given on the chat command line,
+/// each replacing the configured one
+#[cfg(feature = "chat")]
+#[derive(Default)]
+struct ChatFlags<'a> {
+    persona: Option<&'a str>,
+    provider: Option<&'a str>,
+    model: Option<&'a str>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    /// Image files or URLs shown with the question
+    #[cfg(feature = "vision")]
+    images: &'a [String],
+}
 
-    // Register Chat handler
-    bridge.register(
-        Request::Chat,
-        Box::new(|text: &str| {
-            info!("Processing chat request");
-            debug!("Chat input: {}", sanitize_for_logging(text, 50));
+/// Parse a `--temperature` argument
+#[cfg(feature = "chat")]
+fn parse_temperature(value: &str) -> std::result::Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(temperature) if (0.0..=2.0).contains(&temperature) => Ok(temperature),
+        Ok(_) => Err("must be between 0 and 2".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
-            let mut chat = Chat::new();
-            match chat.run(text) {
-                Ok(response) => {
-                    println!("Assistant: {}", response);
-                    debug!("Chat request completed successfully");
-                    Ok(())
-                }
-                Err(e) => {
-                    error!("Chat request failed: {}", e);
-                    eprintln!("❌ Chat Error: {}", e);
-                    eprintln!();
-                    eprintln!("Tip: Configure an API provider:");
-                    eprintln!("  - OpenAI: export OPENAI_API_KEY=your-key");
-                    eprintln!("  - Ollama: export OLLAMA_HOST=http://localhost:11434");
-                    eprintln!("  - Custom: export LLM_API_URL=http://your-api");
-                    Err(e.to_string())
-                }
-            }
-        }),
-    );
+/// Chat verbosity from `--concise`/`--detailed`, falling back to `[chat] verbosity`
+#[cfg(feature = "chat")]
+fn chat_verbosity(
+    config: &Config,
+    concise: bool,
+    detailed: bool,
+) -> std::result::Result<Verbosity, String> {
+    if concise {
+        return Ok(Verbosity::Concise);
+    }
+    if detailed {
+        return Ok(Verbosity::Detailed);
+    }
+    config.chat_verbosity().map_err(|e| {
+        error!("Invalid chat configuration: {}", e);
+        output::error(format!("Configuration Error: {}", e));
+        e
+    })
+}
 
-    // Register Core handler
-    bridge.register(
-        Request::Core,
-        Box::new(|prompt: &str| {
-            info!("Processing core command generation request");
-            debug!("Prompt: {}", sanitize_for_logging(prompt, 50));
+/// Chat request settings: the verbosity preset plus whatever environment the
+/// `[context]` section allows sending to the provider
+#[cfg(feature = "chat")]
+fn chat_options(verbosity: Verbosity, context: &EnvContext) -> ChatOptions {
+    let mut options = verbosity.options();
+    if let Some(env) = context.render() {
+        options.system_prompt = Some(match options.system_prompt.take() {
+            Some(prompt) => format!("{}\n\n{}", prompt, env),
+            None => env,
+        });
+    }
+    options
+}
 
-            // Load configuration
-            debug!("Loading configuration");
-            let config = Config::load().map_err(|e| {
-                error!("Configuration loading failed: {}", e);
-                format!("Config error: {}", e)
-            })?;
+/// Chat for the provider selected in the configuration, with its HTTP timeouts
+#[cfg(feature = "chat")]
+fn configured_chat(config: &Config) -> std::result::Result<Chat, String> {
+    config.chat_client().map(Chat::from_client).map_err(|e| {
+        error!("Invalid chat configuration: {}", e);
+        output::error(format!("Configuration Error: {}", e));
+        e
+    })
+}
 
-            // Validate configuration
-            config.validate().map_err(|e| {
-                error!("Configuration validation failed: {}", e);
-                eprintln!("❌ Configuration Error: {}", e);
-                eprintln!();
-                eprintln!("To configure Eidos, choose one of:");
-                eprintln!("  1. Environment variables:");
-                eprintln!("     export EIDOS_MODEL_PATH=/path/to/model.onnx");
-                eprintln!("     export EIDOS_TOKENIZER_PATH=/path/to/tokenizer.json");
-                eprintln!();
-                eprintln!("  2. Config file (./eidos.toml or ~/.config/eidos/eidos.toml):");
-                eprintln!("     model_path = \"/path/to/model.onnx\"");
-                eprintln!("     tokenizer_path = \"/path/to/tokenizer.json\"");
-                eprintln!();
-                eprintln!("  3. See docs/MODEL_GUIDE.md for training your own model");
-                e.to_string()
-            })?;
+/// Send a chat message and stream the reply to stdout
+#[cfg(feature = "chat")]
+async fn run_chat(
+    config: &Config,
+    text: &str,
+    verbosity: Verbosity,
+    grounding: Option<ChatGrounding<'_>>,
+    flags: &ChatFlags<'_>,
+    timeout: Option<Duration>,
+) -> std::result::Result<(), lib_errors::Error> {
+    info!("Processing chat request");
+    debug!("Chat input: {}", sanitize_for_logging(text, 50));
+    debug!("Chat verbosity: {}", verbosity);
 
-            debug!("Configuration valid, loading model");
+    let mut config = config.clone();
+    config.chat.select(flags.provider, flags.model);
+    let context = config.remote_env_context();
+    if !context.is_empty() {
+        debug!(
+            "Sending {} environment variables to the provider",
+            context.vars().len()
+        );
+    }
 
-            // Get Core instance from cache (or load if not cached)
-            let model_path_str = config
-                .model_path
-                .to_str()
-                .ok_or_else(|| "Invalid model path encoding".to_string())?;
-            let tokenizer_path_str = config
-                .tokenizer_path
-                .to_str()
-                .ok_or_else(|| "Invalid tokenizer path encoding".to_string())?;
+    let mut options = chat_options(verbosity, &context);
+    if let Some(timeout) = timeout {
+        options = options.with_timeout(timeout);
+    }
+    if let Some(temperature) = flags.temperature {
+        options = options.with_temperature(temperature);
+    }
+    if let Some(max_tokens) = flags.max_tokens {
+        options = options.with_max_tokens(max_tokens);
+    }
+    let mut chat = configured_chat(&config)
+        .map_err(config_error)?
+        .with_options(options);
+    if let Some(name) = flags.persona.or(config.chat.persona.as_deref()) {
+        let personas = config.persona_registry().map_err(|e| {
+            output::error(format!("Configuration Error: {}", e));
+            config_error(e)
+        })?;
+        chat = chat
+            .with_personas(personas)
+            .with_persona(name)
+            .map_err(|e| {
+                output::error(e.to_string());
+                e
+            })?;
+        debug!("Chat persona: {}", name);
+    }
+    #[cfg(feature = "vision")]
+    if !flags.images.is_empty() {
+        let images = flags
+            .images
+            .iter()
+            .map(|arg| Image::load(arg))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| {
+                output::error(e.to_string());
+                e
+            })?;
+        debug!("Attaching {} images", images.len());
+        chat.set_images(images);
+    }
+    if !chat.is_configured() {
+        output::warning(
+            "No API provider configured. Set OPENAI_API_KEY, ANTHROPIC_API_KEY, GEMINI_API_KEY, or [chat] provider and url in eidos.toml",
+        );
+    }
 
-            let core = get_or_load_model(model_path_str, tokenizer_path_str).map_err(|e| {
-                error!("Model loading failed: {}", e);
+    let mut sources = Vec::new();
+    if let Some(grounding) = grounding {
+        sources = chat
+            .attach_index_async(grounding.index, text, grounding.top_k)
+            .await
+            .map_err(|e| {
+                error!("Retrieval failed: {}", e);
+                output::error(format!("Retrieval Error: {}", e));
                 e
             })?;
+        info!(
+            "Attached {} chunks from index '{}'",
+            sources.len(),
+            grounding.index.name
+        );
+        for source in &sources {
+            debug!("Context source: {}", source);
+        }
+    }
 
-            // Generate command (validation happens in Core)
-            match core.generate_command(prompt) {
-                Ok(command) => {
-                    // Validate that generated command is safe
-                    if core.is_safe_command(&command) {
-                        info!("Command generated and validated successfully");
-                        debug!("Generated command: {}", command);
-                        println!("{}", command);
-                        Ok(())
-                    } else {
-                        error!("Generated command failed safety validation");
-                        eprintln!("❌ Safety Error: Generated command is not safe to execute");
-                        eprintln!("Generated: {}", command);
-                        eprintln!();
-                        eprintln!(
-                            "The model generated a command that contains dangerous patterns."
-                        );
-                        eprintln!("This is a safety feature to prevent harmful commands.");
-                        Err("Generated command failed safety validation".to_string())
-                    }
-                }
-                Err(e) => {
-                    error!("Inference failed: {}", e);
-                    eprintln!("❌ Error: {}", e);
-                    eprintln!();
-                    eprintln!("This could be due to:");
-                    eprintln!("  - Invalid or corrupted model file");
-                    eprintln!("  - Incompatible model format");
-                    eprintln!("  - Prompt too long or malformed");
-                    Err(e.to_string())
-                }
-            }
-        }),
-    );
+    // Scripts get a single JSON object (or raw reply) once the reply is complete
+    if output::format() != OutputFormat::Text {
+        let reply = chat
+            .send_async(text)
+            .await
+            .map_err(|e| report_chat_error(e, &chat))?;
+        let citations = parse_citations(&reply, &sources);
+        output::emit(&ChatResult {
+            reply,
+            sources,
+            citations,
+        });
+        return Ok(());
+    }
 
-    // Register Translate handler
-    bridge.register(
-        Request::Translate,
-        Box::new(|text: &str| {
-            info!("Processing translation request");
-            debug!("Translation input: {}", sanitize_for_logging(text, 50));
-
-            let translate = Translate::new();
-            match translate.run(text) {
-                Ok(result) => {
-                    println!("Detected language: {}", result.source_lang);
-                    if result.was_translated {
-                        println!("Original ({}): {}", result.source_lang, result.original);
-                        println!("Translated ({}): {}", result.target_lang, result.translated);
-                    } else {
-                        println!("Text is already in {}", result.target_lang);
-                        println!("Text: {}", result.original);
-                    }
-                    debug!("Translation request completed successfully");
-                    Ok(())
-                }
-                Err(e) => {
-                    error!("Translation request failed: {}", e);
-                    eprintln!("❌ Translation Error: {}", e);
-                    eprintln!();
-                    eprintln!("Tip: Set LIBRETRANSLATE_URL for translation API");
-                    Err(e.to_string())
-                }
+    // Print the reply as it streams in; the prefix is only written once
+    // the provider has actually started answering. With colours, Markdown is
+    // rendered a line at a time
+    let mut started = false;
+    let mut markdown = output::color().then(MarkdownStream::default);
+    let streamed = chat
+        .send_stream_async(text, |token| {
+            if !started {
+                output::fragment("Assistant: ");
+                started = true;
             }
-        }),
-    );
+            match markdown.as_mut() {
+                Some(markdown) => output::fragment(markdown.push(token)),
+                None => output::fragment(token),
+            }
+        })
+        .await;
 
-    debug!("Bridge setup complete with {} handlers", 3);
-    bridge
+    let reply = streamed.map_err(|e| report_chat_error(e, &chat))?;
+    let citations = parse_citations(&reply, &sources);
+    if started {
+        if let Some(markdown) = markdown.as_mut() {
+            output::fragment(markdown.finish());
+        }
+        output::result("");
+    } else {
+        output::emit(&ChatResult {
+            reply,
+            sources,
+            citations: citations.clone(),
+        });
+    }
+    if !citations.is_empty() {
+        output::note("");
+        output::note("Cited:");
+        for citation in &citations {
+            output::note(format!("  [{}] {}", citation.number, citation.source));
+        }
+    }
+    debug!("Chat request completed successfully");
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    // Parse CLI arguments
-    let cli = Cli::parse();
+/// Explain a failed chat request on stderr
+#[cfg(feature = "chat")]
+fn report_chat_error(e: lib_chat::ChatError, chat: &Chat) -> lib_errors::Error {
+    error!("Chat request failed: {}", e);
+    health::note_chat_error(chat, &e);
+    output::error(format!("Chat Error: {}", e));
+    Hints::new()
+        .line("Tip: Configure an API provider:")
+        .line("  - OpenAI: export OPENAI_API_KEY=your-key")
+        .line("  - Anthropic: export ANTHROPIC_API_KEY=your-key")
+        .line("  - Gemini: export GEMINI_API_KEY=your-key")
+        .line("  - Ollama or an OpenAI-compatible server: in eidos.toml,")
+        .line("      [chat]")
+        .line("      provider = \"ollama\"  # or \"custom\"")
+        .line("      url = \"http://localhost:11434\"")
+        .emit();
+    e.into()
+}
 
-    // Initialize logging
-    init_logging(cli.verbose, cli.debug);
+/// How to point eidos at a model, shown when the configuration is incomplete
+#[cfg(feature = "core")]
+fn model_config_hints() -> Hints {
+    Hints::new()
+        .line("To configure Eidos, choose one of:")
+        .line("  1. Environment variables:")
+        .code("     export EIDOS_MODEL_PATH=/path/to/model.onnx")
+        .code("     export EIDOS_TOKENIZER_PATH=/path/to/tokenizer.json")
+        .line("")
+        .line("  2. Config file (./eidos.toml or ~/.config/eidos/eidos.toml):")
+        .code("     model_path = \"/path/to/model.onnx\"")
+        .code("     tokenizer_path = \"/path/to/tokenizer.json\"")
+        .line("")
+        .line("  3. See docs/MODEL_GUIDE.md for training your own model")
+}
 
-    info!("Eidos v0.2.0-beta starting");
-    debug!("Command: {:?}", cli.command);
+/// Likely causes of a failed inference
+#[cfg(feature = "core")]
+fn inference_hints() -> Hints {
+    Hints::new()
+        .line("This could be due to:")
+        .line("  - Invalid or corrupted model file")
+        .line("  - Incompatible model format")
+        .line("  - Prompt too long or malformed")
+}
 
-    // Initialize the bridge with all handlers
-    let bridge = setup_bridge();
+/// `context` followed by the last `limit` prompts and commands of the history
+/// (`eidos core --context-history`); an unreadable history only costs a warning
+#[cfg(feature = "core")]
+fn with_history_context(limit: usize, context: Option<String>) -> Option<String> {
+    let entries = match CommandHistory::new(paths::history_path()).entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            output::warning(format!("Not using the command history: {}", e));
+            return context;
+        }
+    };
+    let turns = entries
+        .into_iter()
+        .map(|entry| (entry.prompt, entry.command));
+    let history = HistoryContext::from_turns(turns, limit);
+    debug!(
+        "Showing the model {} earlier commands",
+        history.turns().len()
+    );
+    history.with_context(context.as_deref())
+}
 
-    // Route commands through the bridge with input validation
-    let result = match cli.command {
-        Commands::Chat { ref text } => {
-            // Validate input (max 10000 chars for chat)
-            if let Err(e) = validate_input(text, MAX_CHAT_INPUT_LENGTH) {
-                error!("Input validation failed: {}", e);
-                eprintln!("❌ Invalid input: {}", e);
-                return Err(crate::error::AppError::InvalidInput(e));
-            }
+/// Explain on stderr why a generated command was rejected
+///
+/// In CI mode the explanation is the single `[ci] blocked_message` verdict line.
+/// In JSON mode the report is also emitted on stdout so scripts can see it.
+#[cfg(feature = "core")]
+fn report_unsafe_command(
+    config: &Config,
+    prompt: &str,
+    report: SafetyReport,
+    level: SafetyLevel,
+    reply_lang: Option<&str>,
+) {
+    if output::ci() {
+        output::verdict(config.ci.blocked_verdict(&report, level));
+    } else {
+        let mut lines = vec![
+            "Safety Error: Generated command is not safe to execute".to_string(),
+            "Rejected because of:".to_string(),
+            "This is a safety feature to prevent harmful commands.".to_string(),
+        ];
+        lines.extend(report.violations.iter().map(|v| v.to_string()));
+        // Explanations follow the language of a translated `eidos ask` prompt
+        if let Some(lang) = reply_lang {
+            lines = translate_lines(config, lines, lang);
+        }
 
-            debug!("Routing to chat handler");
-            bridge.route(Request::Chat, text).map_err(|e| {
-                error!("Chat routing failed: {}", e);
-                crate::error::AppError::InvalidInput(e)
-            })
+        output::error(&lines[0]);
+        output::note(format!("Generated: {}", report.command));
+        output::note("");
+        output::note(&lines[1]);
+        for violation in &lines[3..] {
+            output::note(format!("  - {}", violation));
         }
-        Commands::Core {
+        Hints::new()
+            .line(&lines[2])
+            .line(format!(
+                "Safety level: {} (adjust [safety] in eidos.toml)",
+                level
+            ))
+            .emit();
+    }
+
+    if output::format() == OutputFormat::Json {
+        output::emit(&CommandResult {
+            prompt: prompt.to_string(),
+            requested: 1,
+            commands: Vec::new(),
+            rejected: vec![report],
+            translation: None,
+            latency: None,
+        });
+    }
+}
+
+/// Translate messages written in English into `lang`, leaving them in English when
+/// the translation service fails
+///
+/// Called from handlers on the blocking pool, which wait for the translation.
+#[cfg(feature = "core")]
+fn translate_lines(config: &Config, lines: Vec<String>, lang: &str) -> Vec<String> {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => runtime.block_on(translate_lines_async(config, lines, lang)),
+        Err(_) => lines,
+    }
+}
+
+/// [`translate_lines`] for async code
+#[cfg(feature = "core")]
+#[cfg_attr(not(feature = "translate"), allow(unused_variables))]
+async fn translate_lines_async(config: &Config, lines: Vec<String>, lang: &str) -> Vec<String> {
+    #[cfg(feature = "translate")]
+    if lang != "en" && !lines.is_empty() {
+        // Offline translation is made for prompts; messages stay in English
+        let translate = config
+            .translator()
+            .map(|translate| (!translate.is_offline()).then_some(translate));
+        if let Ok(Some(translate)) = translate {
+            match translate.translate_lines_async(&lines, "en", lang).await {
+                Ok(translated) => return translated,
+                Err(e) => {
+                    warn!("Failed to translate messages into {}: {}", lang, e);
+                    health::note_translate_error(&e);
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// `hints` with their prose in `lang` (see [`translate_lines`])
+#[cfg(feature = "core")]
+fn localize_hints(config: &Config, hints: Hints, lang: Option<&str>) -> Hints {
+    match lang {
+        Some(lang) => {
+            let prose = translate_lines(config, hints.prose(), lang);
+            hints.with_prose(prose)
+        }
+        None => hints,
+    }
+}
+
+/// `hints` with their prose in the display language (`--lang`), for async code
+#[cfg(feature = "core")]
+async fn localize_hints_async(config: &Config, hints: Hints) -> Hints {
+    match output::lang() {
+        Some(lang) => {
+            let prose = translate_lines_async(config, hints.prose(), &lang).await;
+            hints.with_prose(prose)
+        }
+        None => hints,
+    }
+}
+
+/// `texts`, each of one or more lines, in the display language (`--lang`)
+#[cfg(feature = "core")]
+async fn localize_texts(config: &Config, texts: Vec<String>) -> Vec<String> {
+    let Some(lang) = output::lang() else {
+        return texts;
+    };
+    let counts: Vec<usize> = texts.iter().map(|text| text.lines().count()).collect();
+    let lines = texts
+        .iter()
+        .flat_map(|text| text.lines().map(str::to_string));
+    let mut translated = translate_lines_async(config, lines.collect(), &lang)
+        .await
+        .into_iter();
+    counts
+        .into_iter()
+        .map(|count| {
+            translated
+                .by_ref()
+                .take(count)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect()
+}
+
+/// The explanations in `result` in the display language (`--lang`)
+///
+/// Done after caching, so the cache keeps the model's English.
+#[cfg(feature = "core")]
+async fn localize_explanations(config: &Config, result: &mut CommandResult) {
+    if output::lang().is_none() {
+        return;
+    }
+    let explanations = result
+        .commands
+        .iter()
+        .filter_map(|generated| generated.explanation.clone())
+        .collect();
+    let mut translated = localize_texts(config, explanations).await.into_iter();
+    for generated in &mut result.commands {
+        if generated.explanation.is_some() {
+            generated.explanation = translated.next();
+        }
+    }
+}
+
+/// Describe `command` for `eidos explain`, option by option
+///
+/// The prose explanation comes from the local model, or from the chat provider
+/// when no model is configured; without either only the options are listed.
+#[cfg(feature = "core")]
+#[cfg_attr(not(feature = "chat"), allow(unused_variables))]
+async fn explain_command(
+    config: &Config,
+    command: &str,
+    timeout: Option<Duration>,
+) -> ExplainResult {
+    let mut result = ExplainResult {
+        command: command.to_string(),
+        explanation: None,
+        source: None,
+        parts: lib_core::breakdown_command(command),
+    };
+
+    match explain_with_model(config, command) {
+        Ok(explanation) => {
+            result.explanation = Some(explanation);
+            result.source = Some("model".to_string());
+            return result;
+        }
+        Err(e) => debug!("Local model cannot explain the command: {}", e),
+    }
+
+    #[cfg(feature = "chat")]
+    {
+        let mut options = ChatOptions::default();
+        if let Some(timeout) = timeout {
+            options = options.with_timeout(timeout);
+        }
+        let mut chat = match config.chat_client() {
+            Ok(client) => Chat::from_client(client).with_options(options),
+            Err(e) => {
+                warn!("Invalid chat configuration: {}", e);
+                Chat::from_client(None)
+            }
+        };
+        if chat.is_configured() {
+            let question = format!(
+                "Explain in one or two sentences what the following Linux shell command does:\n\n{}",
+                command
+            );
+            match chat.send_async(&question).await {
+                Ok(explanation) => {
+                    result.explanation = Some(explanation.trim().to_string());
+                    result.source = Some("chat".to_string());
+                    return result;
+                }
+                Err(e) => {
+                    warn!("Chat provider failed to explain the command: {}", e);
+                    health::note_chat_error(&chat, &e);
+                }
+            }
+        }
+    }
+
+    output::warning("No model or chat provider available; listing the options only");
+    localize_hints_async(config, model_config_hints())
+        .await
+        .emit();
+    result
+}
+
+/// Explanation of `command` from the configured local model
+#[cfg(feature = "core")]
+fn explain_with_model(config: &Config, command: &str) -> std::result::Result<String, String> {
+    config.validate().map_err(|e| e.to_string())?;
+    let settings = config.model_settings()?;
+    let backend = get_or_load_model(&settings)?;
+    backend.explain(command).map_err(|e| e.to_string())
+}
+
+/// Read the text to summarize from `file`, or from stdin when it is piped
+#[cfg(feature = "chat")]
+fn read_summarize_input(file: Option<&PathBuf>) -> std::result::Result<(String, String), String> {
+    match file {
+        Some(path) => std::fs::read_to_string(path)
+            .map(|text| (text, path.display().to_string()))
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e)),
+        None => {
+            let mut stdin = std::io::stdin();
+            if stdin.is_terminal() {
+                return Err("No input: pass --file <path> or pipe text on stdin".to_string());
+            }
+            let mut text = String::new();
+            stdin
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?;
+            Ok((text, "stdin".to_string()))
+        }
+    }
+}
+
+/// `eidos core --batch`: generate a command for every prompt in `file`
+///
+/// Prompts are independent, so a failure is reported in its entry and the batch
+/// goes on. Results come out in input order even when `jobs` generate at once.
+#[cfg(feature = "core")]
+fn run_batch(
+    config: &Config,
+    file: &std::path::Path,
+    jobs: usize,
+    explain: bool,
+    ndjson: bool,
+    context_flag: Option<bool>,
+    seed: Option<u64>,
+) -> Result<()> {
+    let prompts = read_batch(file).map_err(|e| {
+        output::error(&e);
+        crate::error::AppError::InvalidInput(e)
+    })?;
+
+    let mut config = config.clone();
+    config.context.override_with(context_flag);
+    config.core.seed = seed.or(config.core.seed);
+    // A GGUF instance decodes one prompt at a time, so parallel jobs need a copy each
+    let (policy, backend) = local_generator(&config, jobs)?;
+    let context = config.env_context().render();
+
+    info!(
+        "Generating commands for {} prompts, {} at a time",
+        prompts.len(),
+        jobs
+    );
+    let mut entries = Vec::with_capacity(prompts.len());
+    generate_batch(
+        backend.as_ref(),
+        &policy,
+        &prompts,
+        context.as_deref(),
+        explain,
+        jobs,
+        |entry| {
+            if ndjson {
+                match serde_json::to_string(&entry) {
+                    Ok(line) => output::result(line),
+                    Err(e) => warn!("Failed to encode batch entry: {}", e),
+                }
+            }
+            entries.push(entry);
+        },
+    );
+
+    if ndjson {
+        let safe = entries.iter().filter(|e| e.command().is_some()).count();
+        output::note(format!(
+            "{} of {} prompts produced a safe command",
+            safe,
+            entries.len()
+        ));
+    } else {
+        output::emit(&BatchResult { entries });
+    }
+    Ok(())
+}
+
+/// The safety policy and a model loaded in this process, for commands that
+/// generate many times and bypass the daemon
+///
+/// `instances` raises the number of GGUF copies (`[core] instances`) to allow that
+/// many generations at once.
+#[cfg(feature = "core")]
+fn local_generator(
+    config: &Config,
+    instances: usize,
+) -> Result<(SafetyPolicy, Arc<dyn InferenceBackend>)> {
+    config.validate().map_err(|e| {
+        error!("Configuration validation failed: {}", e);
+        output::error(format!("Configuration Error: {}", e));
+        model_config_hints().emit();
+        crate::error::AppError::Config(e.to_string())
+    })?;
+    let policy = config.safety_policy().map_err(|e| {
+        output::error(format!("Configuration Error: {}", e));
+        crate::error::AppError::Config(e)
+    })?;
+    let mut settings = config.model_settings().map_err(|e| {
+        output::error(format!("Configuration Error: {}", e));
+        crate::error::AppError::Config(e)
+    })?;
+    settings.instances = settings.instances.max(instances);
+    let backend = get_or_load_model(&settings).map_err(|e| {
+        error!("Model loading failed: {}", e);
+        crate::error::AppError::Model(e)
+    })?;
+    Ok((policy, backend))
+}
+
+/// Prompts for `--batch`, one per non-empty line of `file` (`-` reads stdin)
+#[cfg(feature = "core")]
+fn read_batch(file: &std::path::Path) -> std::result::Result<Vec<String>, String> {
+    let text = if file.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin())
+            .map_err(|e| format!("Failed to read stdin: {}", e))?
+    } else {
+        std::fs::read_to_string(file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?
+    };
+    let prompts: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if prompts.is_empty() {
+        return Err(format!("No prompts in {}", file.display()));
+    }
+    Ok(prompts)
+}
+
+/// Run `jobs` workers over `prompts`, passing each entry to `emit` in input order
+#[cfg(feature = "core")]
+fn generate_batch(
+    backend: &dyn InferenceBackend,
+    policy: &SafetyPolicy,
+    prompts: &[String],
+    context: Option<&str>,
+    explain: bool,
+    jobs: usize,
+    mut emit: impl FnMut(BatchEntry),
+) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(prompts.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(prompt) = prompts.get(i) else {
+                    break;
+                };
+                let entry = batch_entry(backend, policy, prompt, context, explain);
+                if sender.send((i, entry)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Entries finish out of order; hold them until the ones before are out
+        let mut pending: Vec<Option<BatchEntry>> = vec![None; prompts.len()];
+        let mut emitted = 0;
+        for (i, entry) in receiver {
+            pending[i] = Some(entry);
+            while let Some(entry) = pending.get_mut(emitted).and_then(Option::take) {
+                emit(entry);
+                emitted += 1;
+            }
+        }
+    });
+}
+
+/// Generate, check and optionally explain the command for one batch prompt
+#[cfg(feature = "core")]
+fn batch_entry(
+    backend: &dyn InferenceBackend,
+    policy: &SafetyPolicy,
+    prompt: &str,
+    context: Option<&str>,
+    explain: bool,
+) -> BatchEntry {
+    if let Err(e) = validate_input(prompt, MAX_CORE_PROMPT_LENGTH) {
+        return BatchEntry::failed(prompt.to_string(), format!("Invalid input: {}", e));
+    }
+    let started = Instant::now();
+    let command = match backend.generate(prompt, context) {
+        Ok(command) => command,
+        Err(e) => {
+            warn!("Batch prompt failed: {}", e);
+            return BatchEntry::failed(prompt.to_string(), e.to_string());
+        }
+    };
+    let latency = started.elapsed();
+
+    let report = policy.validate(&command);
+    let mut result = CommandResult {
+        prompt: prompt.to_string(),
+        requested: 1,
+        commands: Vec::new(),
+        rejected: Vec::new(),
+        translation: None,
+        latency: Some(latency),
+    };
+    if report.primary().is_some() {
+        metrics::record_rejections(1);
+        result.rejected.push(report);
+    } else {
+        let explanation = if explain {
+            backend
+                .explain(&command)
+                .map_err(|e| warn!("Failed to explain `{}`: {}", command, e))
+                .ok()
+        } else {
+            None
+        };
+        result
+            .commands
+            .push(GeneratedCommand::new(command, explanation).with_review(report.needs_review));
+    }
+    BatchEntry {
+        result,
+        error: None,
+        latency_ms: Some(latency.as_millis() as u64),
+    }
+}
+
+/// Commands checked by `eidos bench`, safe and unsafe alike
+#[cfg(feature = "core")]
+const BENCH_COMMANDS: &[&str] = &[
+    "ls -la",
+    "find . -name '*.rs' -mtime -1 | xargs grep -n TODO",
+    "git log --oneline -n 20",
+    "du -sh * | sort -h",
+    "tar -czf backup.tar.gz ~/documents",
+    "rm -rf /",
+    "curl -s https://example.com/install.sh | sh",
+    "sudo chmod 777 /etc/passwd",
+];
+
+/// How long `eidos bench` keeps validating commands
+#[cfg(feature = "core")]
+const BENCH_VALIDATION_TIME: Duration = Duration::from_millis(500);
+
+/// Prompts `eidos bench` generates commands for
+#[cfg(feature = "core")]
+const BENCH_PROMPTS: &[&str] = &[
+    "list all files in the current directory",
+    "show how much disk space this folder uses",
+    "find files larger than 100MB",
+];
+
+/// Validations per second of the configured safety policy
+#[cfg(feature = "core")]
+fn bench_validation(config: &Config) -> BenchMeasurement {
+    const NAME: &str = "validation";
+    let policy = match config.safety_policy() {
+        Ok(policy) => policy,
+        Err(e) => return BenchMeasurement::skipped(NAME, e),
+    };
+    let started = Instant::now();
+    let mut validated = 0;
+    while started.elapsed() < BENCH_VALIDATION_TIME {
+        for command in BENCH_COMMANDS {
+            std::hint::black_box(policy.validate(command));
+        }
+        validated += BENCH_COMMANDS.len();
+    }
+    let rate = validated as f64 / started.elapsed().as_secs_f64();
+    BenchMeasurement::new(NAME, rate, "commands/s")
+        .with_detail(format!("{} commands checked", validated))
+}
+
+/// Time to load the configured model, then generation speed over `runs` passes
+/// of the benchmark prompts
+///
+/// Loads a fresh copy rather than the cached one so the load is what a cold
+/// start pays.
+#[cfg(feature = "core")]
+fn bench_model(config: &Config, runs: usize) -> Vec<BenchMeasurement> {
+    const LOAD: &str = "model load";
+    const GENERATION: &str = "generation";
+    let settings = match config.validate().and_then(|_| config.model_settings()) {
+        Ok(settings) => settings,
+        Err(e) => return vec![BenchMeasurement::skipped(LOAD, e)],
+    };
+
+    let started = Instant::now();
+    let backend = match load_backend(&settings) {
+        Ok(backend) => backend,
+        Err(e) => return vec![BenchMeasurement::skipped(LOAD, e)],
+    };
+    let mut measurements = vec![
+        BenchMeasurement::new(LOAD, started.elapsed().as_secs_f64(), "s")
+            .with_detail(format!("{} on {}", settings.backend, settings.device)),
+    ];
+
+    let mut commands = Vec::with_capacity(runs * BENCH_PROMPTS.len());
+    let mut generating = Duration::ZERO;
+    for prompt in BENCH_PROMPTS.iter().cycle().take(commands.capacity()) {
+        let started = Instant::now();
+        match backend.generate(prompt, None) {
+            Ok(command) => commands.push(command),
+            Err(e) => {
+                measurements.push(BenchMeasurement::skipped(GENERATION, e.to_string()));
+                return measurements;
+            }
+        }
+        generating += started.elapsed();
+    }
+    let seconds = generating.as_secs_f64();
+    measurements.push(
+        BenchMeasurement::new(
+            GENERATION,
+            seconds * 1000.0 / commands.len() as f64,
+            "ms/command",
+        )
+        .with_detail(format!("{} commands", commands.len())),
+    );
+
+    #[cfg(any(feature = "onnx", feature = "gguf"))]
+    measurements.push(
+        match lib_core::TokenCounter::from_file(&settings.tokenizer_path) {
+            Ok(counter) => {
+                let tokens: usize = commands.iter().map(|c| counter.count(c)).sum();
+                BenchMeasurement::new("tokens", tokens as f64 / seconds, "tokens/s")
+                    .with_detail(format!("{} tokens generated", tokens))
+            }
+            Err(e) => {
+                BenchMeasurement::skipped("tokens", format!("Failed to load tokenizer: {}", e))
+            }
+        },
+    );
+    measurements
+}
+
+/// Message sent by `eidos bench` to time a chat round trip
+#[cfg(feature = "chat")]
+const BENCH_CHAT_MESSAGE: &str = "Reply with the single word OK.";
+
+/// Time one short chat message to the configured provider
+#[cfg(feature = "chat")]
+async fn bench_chat(config: &Config, timeout: Option<Duration>) -> BenchMeasurement {
+    const NAME: &str = "chat round trip";
+    let client = match config.chat_client() {
+        Ok(Some(client)) => client,
+        Ok(None) => return BenchMeasurement::skipped(NAME, "no provider configured"),
+        Err(e) => return BenchMeasurement::skipped(NAME, e),
+    };
+    let provider = client.provider().name();
+    let mut chat = Chat::from_client(Some(client));
+    if let Some(timeout) = timeout {
+        chat = chat.with_options(ChatOptions::default().with_deadline(Instant::now() + timeout));
+    }
+
+    let started = Instant::now();
+    match chat.send_async(BENCH_CHAT_MESSAGE).await {
+        Ok(_) => BenchMeasurement::new(NAME, started.elapsed().as_secs_f64() * 1000.0, "ms")
+            .with_detail(provider),
+        Err(e) => BenchMeasurement::skipped(NAME, format!("{}: {}", provider, e)),
+    }
+}
+
+/// Time one short translation with the configured translator
+#[cfg(feature = "translate")]
+async fn bench_translate(config: &Config, timeout: Option<Duration>) -> BenchMeasurement {
+    const NAME: &str = "translate round trip";
+    let mut translator = match config.translator() {
+        Ok(translator) => translator,
+        Err(e) => return BenchMeasurement::skipped(NAME, e),
+    };
+    if let Some(timeout) = timeout {
+        translator = translator.with_deadline(Instant::now() + timeout);
+    }
+    let provider = if translator.is_offline() {
+        "offline"
+    } else {
+        "LibreTranslate"
+    };
+
+    let started = Instant::now();
+    match translator
+        .translate_async("Bonjour tout le monde", "fr", "en")
+        .await
+    {
+        Ok(_) => BenchMeasurement::new(NAME, started.elapsed().as_secs_f64() * 1000.0, "ms")
+            .with_detail(provider),
+        Err(e) => BenchMeasurement::skipped(NAME, format!("{}: {}", provider, e)),
+    }
+}
+
+/// Hand a request to a running `eidos daemon`, if there is one
+///
+/// Returns `None` when no daemon is reachable (or `EIDOS_NO_DAEMON` is set), in
+/// which case the caller handles the request in-process.
+#[cfg(all(unix, feature = "serve"))]
+async fn route_via_daemon(
+    request: Request,
+    payload: Payload,
+) -> Option<std::result::Result<Response, lib_errors::Error>> {
+    // CI runs are one-shot and need the verdict and exit status of a local run
+    if std::env::var_os("EIDOS_NO_DAEMON").is_some() || output::ci() {
+        return None;
+    }
+
+    let timeout = payload.timeout();
+    let socket = paths::socket_path();
+    let request = daemon::DaemonRequest { request, payload };
+    let exchange = daemon::try_request(&socket, &request);
+    let reply = match timeout {
+        // The daemon enforces the budget too; this covers one that stopped answering
+        Some(timeout) => match tokio::time::timeout(timeout, exchange).await {
+            Ok(reply) => reply?,
+            Err(_) => {
+                let e = daemon::timed_out(timeout);
+                error!("Daemon request failed: {}", e);
+                output::error(format!("Daemon Error: {}", e));
+                return Some(Err(e));
+            }
+        },
+        None => exchange.await?,
+    };
+    match reply {
+        Ok(daemon::DaemonReply::Ok { ok: response }) => {
+            info!("Request served by daemon at {}", socket.display());
+            Some(Ok(response))
+        }
+        Ok(daemon::DaemonReply::Error(e)) => {
+            error!("Daemon request failed: {}", e);
+            output::error(format!("Daemon Error: {}", e));
+            Some(Err(e))
+        }
+        Err(e) => {
+            warn!(
+                "Daemon at {} did not answer ({}); handling the request locally",
+                socket.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(all(unix, feature = "serve")))]
+async fn route_via_daemon(
+    _request: Request,
+    _payload: Payload,
+) -> Option<std::result::Result<Response, lib_errors::Error>> {
+    None
+}
+
+/// `payload` carrying the `--timeout` budget, if one was given
+fn with_timeout(payload: Payload, timeout: Option<Duration>) -> Payload {
+    match timeout {
+        Some(timeout) => payload.with_timeout(timeout),
+        None => payload,
+    }
+}
+
+/// A bridge error for an invalid configuration value
+fn config_error(e: String) -> lib_errors::Error {
+    lib_errors::Error::new(ErrorCode::Config, e)
+}
+
+/// Whether `deadline` has passed, in which case the optional `step` is skipped
+#[cfg(feature = "core")]
+fn past_deadline(deadline: Option<Instant>, step: &str) -> bool {
+    let passed = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    if passed {
+        warn!("Request deadline passed; skipping {}", step);
+    }
+    passed
+}
+
+/// Cargo feature that provides the handler for `request`
+fn feature_for(request: Request) -> &'static str {
+    match request {
+        Request::Chat => "chat",
+        Request::Core => "core",
+        Request::Translate => "translate",
+    }
+}
+
+/// Set up the Bridge with a handler for every subsystem compiled into this binary
+#[cfg_attr(
+    not(any(feature = "chat", feature = "core", feature = "translate")),
+    allow(unused_variables)
+)]
+fn setup_bridge(config: &Arc<Config>) -> Bridge {
+    let mut bridge = Bridge::new();
+
+    // Register Chat handler (the `chat` subcommand streams through `run_chat` instead)
+    #[cfg(feature = "chat")]
+    let shared = Arc::clone(config);
+    #[cfg(feature = "chat")]
+    bridge.register_async(Request::Chat, move |payload: Payload| {
+        let config = Arc::clone(&shared);
+        async move {
+            info!("Processing chat request");
+
+            let verbosity = match payload.option("verbosity") {
+                Some(verbosity) => verbosity.parse()?,
+                None => Verbosity::default(),
+            };
+            let context = EnvContext::from_payload(&payload);
+            let mut options = chat_options(verbosity, &context);
+            if let Some(deadline) = payload.deadline() {
+                options = options.with_deadline(deadline);
+            }
+            let mut chat = configured_chat(&config)
+                .map_err(config_error)?
+                .with_options(options);
+            let reply = chat
+                .send_async(&payload.input)
+                .await
+                .map_err(|e| report_chat_error(e, &chat))?;
+            Ok(Response::ChatReply {
+                reply,
+                sources: Vec::new(),
+            })
+        }
+    });
+
+    // Register Core handler (inference is CPU-bound, so it runs on the blocking
+    // pool, leaving the runtime free for translation running alongside it).
+    // Dropping the request, as the ask pipeline does with a speculative one it no
+    // longer needs, stops generation at the next token and frees the model.
+    #[cfg(feature = "core")]
+    let shared = Arc::clone(config);
+    #[cfg(feature = "core")]
+    bridge.register_async(Request::Core, move |payload: Payload| {
+        let config = Arc::clone(&shared);
+        async move {
+            let cancel = CancelFlag::new();
+            let _guard = cancel.cancel_on_drop();
+            tokio::task::spawn_blocking(move || generate_command(&config, &payload, &cancel))
+                .await
+                .map_err(|e| format!("Command generation panicked: {}", e))?
+        }
+    });
+
+    // Register Translate handler
+    #[cfg(feature = "translate")]
+    let shared = Arc::clone(config);
+    #[cfg(feature = "translate")]
+    bridge.register_async(Request::Translate, move |payload: Payload| {
+        let config = Arc::clone(&shared);
+        async move {
+        let text = payload.input.as_str();
+        info!("Processing translation request");
+
+        let mut translate = config.translator().map_err(|e| {
+            output::error(format!("Configuration Error: {}", e));
+            config_error(e)
+        })?;
+        if let Some(deadline) = payload.deadline() {
+            translate = translate.with_deadline(deadline);
+        }
+        let offline = translate.is_offline();
+        if payload.flag("localize") || config.translate.localize {
+            let localizer = config.localizer().map_err(|e| {
+                output::error(format!("Configuration Error: {}", e));
+                config_error(e)
+            })?;
+            translate = translate.with_localizer(localizer);
+        }
+        // The ask pipeline translates explanations back with explicit languages;
+        // `eidos translate --to` only names the target
+        let target = payload.option(TARGET_LANG_OPTION).unwrap_or("en");
+        let result = match payload.option(SOURCE_LANG_OPTION) {
+            Some(source) => translate.translate_async(text, source, target).await,
+            None => translate.detect_and_translate_async(text, target).await,
+        };
+        match result {
+            Ok(result) => {
+                debug!("Translation request completed successfully");
+                // Text already in the target language never needed the service
+                if offline && result.source_lang != result.target_lang {
+                    output::warning(
+                        "No translation service configured; translating offline. Set [translate] url in eidos.toml for full translation",
+                    );
+                }
+                Ok(Response::Translation {
+                    source_lang: result.source_lang,
+                    target_lang: result.target_lang,
+                    original: result.original,
+                    translated: result.translated,
+                    was_translated: result.was_translated,
+                    confidence: result.confidence,
+                })
+            }
+            Err(e) => {
+                error!("Translation request failed: {}", e);
+                health::note_translate_error(&e);
+                output::error(format!("Translation Error: {}", e));
+                match e {
+                    lib_translate::TranslateError::LowConfidence { .. } => Hints::new().line(
+                        "Tip: Name the language with `eidos translate --from LANG`, or lower [translate] min_confidence",
+                    ),
+                    _ => Hints::new().line("Tip: Set [translate] url in eidos.toml for translation API"),
+                }
+                .emit();
+                Err(e.into())
+            }
+        }
+        }
+    });
+
+    // Every request is logged once here, redacted, instead of in each handler
+    bridge.use_middleware(|request, payload, next| {
+        Box::pin(async move {
+            debug!(
+                "{} input: {}",
+                request,
+                sanitize_for_logging(&payload.input, 50)
+            );
+            let started = Instant::now();
+            let result = next.run(payload).await;
+            debug!(
+                "{} handled in {:.2}s ({})",
+                request,
+                started.elapsed().as_secs_f64(),
+                if result.is_ok() { "ok" } else { "failed" }
+            );
+            result
+        })
+    });
+
+    for plugin in lib_bridge::plugin::discover(&paths::plugins_dir()) {
+        let path = plugin.path().display().to_string();
+        match bridge.register_plugin(plugin) {
+            Ok(()) => debug!("Registered plugin {}", path),
+            Err(e) => warn!("Ignoring {}: {}", path, e),
+        }
+    }
+
+    debug!(
+        "Bridge setup complete with {} handlers and {} plugins",
+        bridge.handler_count(),
+        bridge.plugin_verbs().len()
+    );
+    bridge
+}
+
+/// Core handler: generate a command for `payload` and validate it
+///
+/// Speculative requests (see [`lib_bridge::pipeline`]) may be discarded, so their
+/// failures are returned without being reported to the user. Sampling backends
+/// stop once `cancel` is cancelled.
+#[cfg(feature = "core")]
+fn generate_command(
+    config: &Config,
+    payload: &Payload,
+    cancel: &CancelFlag,
+) -> std::result::Result<Response, lib_errors::Error> {
+    let quiet = payload.flag(SPECULATIVE_OPTION);
+    let deadline = payload.deadline();
+    let prompt = payload.input.as_str();
+    let reply_lang = payload.option(REPLY_LANG_OPTION);
+    info!("Processing core command generation request");
+
+    // Validate configuration
+    config.validate().map_err(|e| {
+        error!("Configuration validation failed: {}", e);
+        if !quiet {
+            output::error(format!("Configuration Error: {}", e));
+            localize_hints(config, model_config_hints(), reply_lang).emit();
+        }
+        config_error(e)
+    })?;
+
+    let policy = config.safety_policy().map_err(|e| {
+        error!("Invalid safety configuration: {}", e);
+        if !quiet {
+            output::error(format!("Configuration Error: {}", e));
+        }
+        config_error(e)
+    })?;
+    let settings = config.model_settings().map_err(|e| {
+        error!("Invalid model configuration: {}", e);
+        if !quiet {
+            output::error(format!("Configuration Error: {}", e));
+        }
+        config_error(e)
+    })?;
+
+    debug!("Configuration valid, loading model");
+
+    // Get the backend from cache (or load if not cached)
+    let backend = get_or_load_model(&settings).map_err(|e| {
+        error!("Model loading failed: {}", e);
+        lib_errors::Error::new(ErrorCode::Model, e)
+    })?;
+
+    // Generate command, with the environment the client captured, if any
+    let context = EnvContext::from_payload(payload).render();
+    let generated = match backend.sampling() {
+        Some(sampling) => backend.generate_with_config(
+            prompt,
+            context.as_deref(),
+            &sampling.with_cancel(cancel.clone()),
+        ),
+        None => backend.generate(prompt, context.as_deref()),
+    };
+    if cancel.is_cancelled() {
+        // Nobody waits for the command any more; there is nothing to report
+        debug!("Command generation cancelled");
+        return Err(lib_errors::Error::new(
+            ErrorCode::Model,
+            "Generation cancelled",
+        ));
+    }
+    match generated {
+        Ok(command) => {
+            // Validate that generated command is safe
+            let report = policy.validate(&command);
+            if report.is_safe() {
+                info!("Command generated and validated successfully");
+                debug!("Generated command: {}", command);
+                let explanation =
+                    if payload.flag("explain") && !past_deadline(deadline, "explanation") {
+                        backend
+                            .explain(&command)
+                            .map_err(|e| warn!("Failed to generate explanation: {}", e))
+                            .ok()
+                    } else {
+                        None
+                    };
+                Ok(Response::CommandGenerated {
+                    prompt: prompt.to_string(),
+                    command,
+                    explanation,
+                })
+            } else {
+                error!("Generated command failed safety validation");
+                metrics::record_rejections(1);
+                if !quiet {
+                    report_unsafe_command(config, prompt, report, policy.level(), reply_lang);
+                }
+                Err(lib_errors::Error::new(
+                    ErrorCode::Unsafe,
+                    "Generated command failed safety validation",
+                ))
+            }
+        }
+        Err(e) => {
+            error!("Inference failed: {}", e);
+            if !quiet {
+                output::error(format!("Error: {}", e));
+                localize_hints(config, inference_hints(), reply_lang).emit();
+            }
+            Err(lib_errors::Error::new(ErrorCode::Model, e.to_string()))
+        }
+    }
+}
+
+/// Remember `command`, and its explanation if there is one, for `prompt`
+///
+/// The cache is an optimisation; failing to write it only costs the next run time.
+#[cfg(feature = "core")]
+fn store_in_cache(
+    cache: &lib_core::CommandCache,
+    prompt: &str,
+    context: Option<&str>,
+    command: &str,
+    explanation: Option<&str>,
+) {
+    let stored = cache
+        .put_command(prompt, context, command)
+        .and_then(|()| match explanation {
+            Some(explanation) => cache.put_explanation(command, explanation),
+            None => Ok(()),
+        });
+    if let Err(e) = stored {
+        debug!("Could not cache the generated command: {}", e);
+    }
+}
+
+/// Fold the command history into its compressed archive, pruning it by age and
+/// size, and compress notes indexes saved before indexes were compressed
+#[cfg(any(feature = "chat", feature = "core"))]
+#[cfg_attr(not(feature = "core"), allow(unused_variables))]
+fn compact_data_dir(
+    max_age_days: Option<u64>,
+    max_size_mb: Option<u64>,
+) -> std::result::Result<CompactResult, String> {
+    let data_dir = paths::data_dir();
+    let display = |path: &std::path::Path| {
+        path.strip_prefix(&data_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let mut files = Vec::new();
+
+    #[cfg(feature = "core")]
+    {
+        let path = paths::history_path();
+        let history = CommandHistory::new(&path);
+        let retention = history::Retention {
+            max_age: max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            max_bytes: max_size_mb.map(|mb| mb * 1024 * 1024),
+        };
+        let compaction = history.compact(retention).map_err(|e| e.to_string())?;
+        if compaction.bytes_before > 0 {
+            files.push(CompactedFile {
+                path: display(&path),
+                bytes_before: compaction.bytes_before,
+                bytes_after: compaction.bytes_after,
+                pruned: Some(compaction.pruned),
+            });
+        }
+    }
+
+    #[cfg(feature = "chat")]
+    for path in index_files().map_err(|e| e.to_string())? {
+        let bytes_before = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        let head = std::fs::read(&path).map_err(|e| e.to_string())?;
+        if !Index::is_compressed(&head) {
+            Index::load(&path)
+                .and_then(|index| index.save(&path))
+                .map_err(|e| e.to_string())?;
+        }
+        files.push(CompactedFile {
+            path: display(&path),
+            bytes_before,
+            bytes_after: std::fs::metadata(&path).map_err(|e| e.to_string())?.len(),
+            pruned: None,
+        });
+    }
+
+    Ok(CompactResult { files })
+}
+
+/// Saved notes indexes, sorted by name
+#[cfg(feature = "chat")]
+fn index_files() -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = match std::fs::read_dir(paths::index_dir()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    files.sort();
+    Ok(files)
+}
+
+/// `eidos sessions export` and `eidos chat export`: print a saved session as a
+/// transcript
+#[cfg(feature = "chat")]
+fn export_session(name: &str, format: TranscriptFormat) -> Result<()> {
+    let store = SessionStore::new(paths::sessions_dir());
+    let session = store.load(name).map_err(|e| {
+        error!("Session operation failed: {}", e);
+        output::error(format!("Session Error: {}", e));
+        Hints::new()
+            .line("Tip: list saved sessions with `eidos sessions`")
+            .emit();
+        crate::error::AppError::from(e)
+    })?;
+    output::result(lib_chat::export::export(&session, format).trim_end());
+    Ok(())
+}
+
+/// `eidos model`: download, list, inspect and remove models
+#[cfg(feature = "models")]
+async fn manage_models(config: &Config, action: ModelAction) -> Result<()> {
+    use crate::models::{Downloader, InstalledModel, Source};
+
+    let dir = paths::models_dir();
+    let model_error = |e: String| {
+        output::error(format!("Model Error: {}", e));
+        crate::error::AppError::InvalidInput(e)
+    };
+    let is_active = |model: &InstalledModel| {
+        let path = model.model_path(&dir);
+        match (config.model_path.canonicalize(), path.canonicalize()) {
+            (Ok(configured), Ok(path)) => configured == path,
+            _ => config.model_path == path,
+        }
+    };
+    let file_info = |model: &InstalledModel, file: &models::ModelFile| ModelFileInfo {
+        path: dir.join(&model.name).join(&file.file).display().to_string(),
+        url: file.url.clone(),
+        bytes: file.bytes,
+        sha256: file.sha256.clone(),
+        verified: file.verified,
+    };
+
+    match action {
+        ModelAction::Pull {
+            source,
+            tokenizer,
+            sha256,
+            name,
+            force,
+            no_activate,
+        } => {
+            let source = Source::parse(&source).map_err(model_error)?;
+            let name = name.unwrap_or_else(|| models::default_name(&source));
+            models::validate_name(&name).map_err(model_error)?;
+
+            let (model, cached) = match models::find(&dir, &name).map_err(model_error)? {
+                Some(model) if !force => (model, true),
+                _ => {
+                    let tokenizer_source = match &tokenizer {
+                        Some(spec) => Source::parse(spec).map_err(model_error)?,
+                        None => source.sibling(models::DEFAULT_TOKENIZER),
+                    };
+                    let connect_timeout = config.http.connect_timeout().map_err(model_error)?;
+                    let downloader = Downloader::new(connect_timeout).map_err(model_error)?;
+                    models::remove(&dir, &name).map_err(model_error)?;
+                    let target = dir.join(&name);
+
+                    // The tokenizer is small; fetching it first means a missing one
+                    // fails before gigabytes of weights are downloaded
+                    let downloaded = async {
+                        output::note(format!("Downloading {} ...", tokenizer_source.url()));
+                        let tokenizer_file = downloader
+                            .fetch(&tokenizer_source, &target, None)
+                            .await
+                            .map_err(|e| match tokenizer {
+                                Some(_) => e,
+                                None => format!("{}; name one with --tokenizer", e),
+                            })?;
+                        output::note(format!("Downloading {} ...", source.url()));
+                        let model_file = downloader
+                            .fetch(&source, &target, sha256.as_deref())
+                            .await?;
+                        let model = InstalledModel::new(&name, model_file, tokenizer_file);
+                        models::save(&dir, &model)
+                            .map_err(|e| format!("Cannot save {}: {}", name, e))?;
+                        Ok(model)
+                    }
+                    .await;
+                    // Nothing is left of a failed download
+                    let model = downloaded.map_err(|e| {
+                        let _ = models::remove(&dir, &name);
+                        model_error(e)
+                    })?;
+                    (model, false)
+                }
+            };
+
+            let model_path = model.model_path(&dir);
+            let tokenizer_path = model.tokenizer_path(&dir);
+            let config_file = if no_activate {
+                None
+            } else {
+                let path = Config::editable_path().ok_or_else(|| {
+                    model_error("No config file to update: HOME is not set".into())
+                })?;
+                Config::set_model_paths(&path, &model_path, &tokenizer_path, model.backend())
+                    .map_err(model_error)?;
+                if std::env::var_os("EIDOS_MODEL_PATH").is_some() {
+                    output::warning("EIDOS_MODEL_PATH is set and takes precedence over eidos.toml");
+                }
+                Some(path.display().to_string())
+            };
+            let runnable = match model.backend() {
+                BackendKind::Onnx => cfg!(feature = "onnx"),
+                BackendKind::Gguf => cfg!(feature = "gguf"),
+            };
+            if !runnable {
+                output::warning(format!(
+                    "eidos was built without the `{}` feature and cannot run this model",
+                    model.backend()
+                ));
+            }
+            output::emit(&ModelPullResult {
+                name: model.name.clone(),
+                model_path: model_path.display().to_string(),
+                tokenizer_path: tokenizer_path.display().to_string(),
+                bytes: model.bytes(),
+                verified: model.model.verified,
+                cached,
+                config: config_file,
+            });
+        }
+        ModelAction::List => {
+            let installed = models::installed(&dir)
+                .map_err(|e| model_error(format!("Cannot read {}: {}", dir.display(), e)))?;
+            if installed.is_empty() && output::format() == OutputFormat::Text {
+                output::note(
+                    "No models downloaded; get one with `eidos model pull owner/repo/file`",
+                );
+                return Ok(());
+            }
+            output::emit(&ModelListResult {
+                dir: dir.display().to_string(),
+                models: installed
+                    .iter()
+                    .map(|model| ModelSummary {
+                        name: model.name.clone(),
+                        backend: model.backend().to_string(),
+                        bytes: model.bytes(),
+                        active: is_active(model),
+                        source: model.model.url.clone(),
+                    })
+                    .collect(),
+            });
+        }
+        ModelAction::Remove { name, force } => {
+            let model = models::find(&dir, &name)
+                .map_err(model_error)?
+                .ok_or_else(|| model_error(format!("No model named '{}'", name)))?;
+            if is_active(&model) && !force {
+                return Err(model_error(format!(
+                    "{} is the configured model; pull or configure another one first, or pass --force",
+                    name
+                )));
+            }
+            models::remove(&dir, &name).map_err(model_error)?;
+            output::emit(&TextResult {
+                text: format!("Removed {}", name),
+            });
+        }
+        ModelAction::Info { name } => {
+            let model = models::find(&dir, &name)
+                .map_err(model_error)?
+                .ok_or_else(|| model_error(format!("No model named '{}'", name)))?;
+            let preset = config
+                .preset_registry()
+                .ok()
+                .and_then(|presets| presets.find(&model.model.file).map(|p| p.name.clone()));
+            output::emit(&ModelInfoResult {
+                name: model.name.clone(),
+                backend: model.backend().to_string(),
+                active: is_active(&model),
+                preset,
+                downloaded: model.downloaded,
+                model: file_info(&model, &model.model),
+                tokenizer: file_info(&model, &model.tokenizer),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// State of the model configuration and of the accelerators in this build
+#[cfg(feature = "core")]
+fn doctor_report(config: &Config) -> DoctorResult {
+    let requested = config.device_spec();
+    let mut report = DoctorResult {
+        backend: match config.backend_kind() {
+            Ok(kind) => kind.to_string(),
+            Err(e) => e,
+        },
+        model_path: config.model_path.display().to_string(),
+        model_error: config.validate().err(),
+        device: match &requested {
+            Ok(spec) => spec.to_string(),
+            Err(e) => e.clone(),
+        },
+        active_device: "cpu".to_string(),
+        device_fallback: None,
+        accelerators: Vec::new(),
+    };
+
+    #[cfg(feature = "gguf")]
+    {
+        let (device, fallback) = lib_core::device::select_device(requested.unwrap_or_default());
+        report.active_device = lib_core::device::device_name(&device);
+        report.device_fallback = fallback;
+        report.accelerators = lib_core::device::accelerators();
+    }
+    #[cfg(not(feature = "gguf"))]
+    {
+        report.device_fallback =
+            Some("eidos was built without the `gguf` feature; running on the CPU".to_string());
+    }
+
+    report
+}
+
+/// Attach undo suggestions, record the commands in the history and emit them
+///
+/// With `run_safe`, read-only commands are run and their output shown beneath.
+#[cfg(feature = "core")]
+fn emit_commands(mut result: CommandResult, run_safe: bool) {
+    let refused = record_commands(&mut result, run_safe);
+    emit_recorded(&result, refused);
+}
+
+/// Write `table` for `eidos stats export`: CSV to stdout or `out`, Parquet to `out`
+#[cfg(feature = "core")]
+fn export_table(table: &Table, format: ExportFormat, out: Option<&std::path::Path>) -> Result<()> {
+    let write_error = |e: std::io::Error| {
+        output::error(format!("Export Error: {}", e));
+        crate::error::AppError::from(e)
+    };
+    let path = match (format, out) {
+        (ExportFormat::Csv, None) => {
+            output::fragment(table.to_csv());
+            return Ok(());
+        }
+        (ExportFormat::Csv, Some(path)) => {
+            std::fs::write(path, table.to_csv()).map_err(write_error)?;
+            path
+        }
+        (ExportFormat::Parquet, None) => {
+            let e = "Parquet is a binary format; write it to a file with --out FILE".to_string();
+            output::error(&e);
+            return Err(crate::error::AppError::InvalidInput(e));
+        }
+        #[cfg(feature = "parquet")]
+        (ExportFormat::Parquet, Some(path)) => {
+            let file = std::fs::File::create(path).map_err(write_error)?;
+            table.write_parquet(file).map_err(|e| {
+                output::error(format!("Export Error: {}", e));
+                crate::error::AppError::InvalidInput(e)
+            })?;
+            path
+        }
+        #[cfg(not(feature = "parquet"))]
+        (ExportFormat::Parquet, Some(_)) => {
+            let e = "eidos was built without the `parquet` feature; export as csv".to_string();
+            output::error(&e);
+            return Err(crate::error::AppError::InvalidInput(e));
+        }
+    };
+    output::note(format!(
+        "Exported {} {} rows to {}",
+        table.rows(),
+        table.name,
+        path.display()
+    ));
+    Ok(())
+}
+
+/// Parse a `--var NAME=VALUE` argument
+#[cfg(feature = "core")]
+fn parse_template_var(var: &str) -> std::result::Result<(String, String), String> {
+    lib_core::template::parse_var(var).map_err(|e| e.to_string())
+}
+
+/// The generated command with the `--var` values filled in, and the template it
+/// was generated as
+#[cfg(feature = "core")]
+fn fill_template(template: Option<&TemplateVars>, generated: String) -> (String, Option<String>) {
+    let Some(template) = template else {
+        return (generated, None);
+    };
+    let missing = template.missing_in(&generated);
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(|name| format!("{{{}}}", name)).collect();
+        output::warning(format!(
+            "The model did not keep {} in `{}`; check that the command uses your values",
+            missing.join(", "),
+            generated
+        ));
+    }
+    (template.fill(&generated), Some(generated))
+}
+
+/// Attach undo suggestions and record the commands in the history; with
+/// `run_safe`, also run the read-only ones
+///
+/// Returns why commands were not run, for [`emit_recorded`] to report.
+#[cfg(feature = "core")]
+fn record_commands(result: &mut CommandResult, run_safe: bool) -> Vec<String> {
+    let history = CommandHistory::new(paths::history_path());
+    for generated in &mut result.commands {
+        generated.undo = lib_core::undo_command(&generated.command);
+        match history.append(
+            &result.prompt,
+            &generated.command,
+            generated.undo.clone(),
+            result.latency,
+        ) {
+            Ok(entry) => generated.history_id = Some(entry.id),
+            Err(e) => warn!("Failed to record command history: {}", e),
+        }
+    }
+    if run_safe {
+        result
+            .commands
+            .iter_mut()
+            .filter_map(run_if_read_only)
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Work out what each command would touch for `--preview`, from the working
+/// directory
+#[cfg(feature = "core")]
+fn preview_commands(result: &mut CommandResult) {
+    let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    for generated in &mut result.commands {
+        generated.preview = Some(lib_core::preview_command(&generated.command, &dir));
+    }
+}
+
+/// Emit commands prepared by [`record_commands`], then why some were not run
+#[cfg(feature = "core")]
+fn emit_recorded(result: &CommandResult, refused: Vec<String>) {
+    output::emit(result);
+
+    // Alternatives list their undo inline; a single command stays alone on
+    // stdout so `$(eidos core ...)` keeps working
+    if result.requested <= 1 && output::format() == OutputFormat::Text {
+        if let Some(generated) = result.commands.first() {
+            if let Some(template) = &generated.template {
+                output::note(format!("Template: {}  (fill in with --var)", template));
+            }
+            if let Some(preview) = &generated.preview {
+                output::note(format!("Preview (nothing was run):\n{}", preview));
+            }
+            if let (Some(undo), Some(id)) = (&generated.undo, generated.history_id) {
+                output::note(format!(
+                    "Undo: {}  (later: eidos history undo {})",
+                    undo, id
+                ));
+            }
+        }
+    }
+    for reason in refused {
+        output::warning(reason);
+    }
+    if output::ci() {
+        for generated in &result.commands {
+            output::verdict(format!("allowed: {}", generated.command));
+        }
+    }
+}
+
+/// Answer the prompt from the output of each command run by `--run-safe`
+/// (`--summarize-output`)
+///
+/// The output is sent to the chat provider; without one, or when the request
+/// fails, the raw output is shown as usual. It can hold the contents of any file
+/// `cat` reads, so a remote provider only gets it with `[context]
+/// allow_remote_output`.
+#[cfg(all(feature = "core", feature = "chat"))]
+async fn answer_from_runs(
+    config: &Config,
+    result: &mut CommandResult,
+    length: SummaryLength,
+    deadline: Option<Instant>,
+) {
+    if result.commands.iter().all(|c| c.run.is_none()) || past_deadline(deadline, "summary") {
+        return;
+    }
+    let Ok(mut chat) = configured_chat(config) else {
+        return;
+    };
+    if let Some(deadline) = deadline {
+        chat.set_options(ChatOptions::default().with_deadline(deadline));
+    }
+    if !chat.is_configured() {
+        output::warning(
+            "Not summarizing the output: no API provider configured. Set OPENAI_API_KEY, ANTHROPIC_API_KEY, GEMINI_API_KEY, or [chat] provider and url in eidos.toml",
+        );
+        return;
+    }
+    if let Some(provider) = chat.provider().filter(|provider| !provider.is_local()) {
+        if !config.context.allow_remote_output {
+            output::warning(format!(
+                "Not summarizing the output: the {} provider is not on this machine. Set [context] allow_remote_output = true in eidos.toml to send command output to it",
+                provider.name()
+            ));
+            return;
+        }
+    }
+
+    for generated in &mut result.commands {
+        let Some(run) = &generated.run else {
+            continue;
+        };
+        let output = match run.to_string() {
+            output if output.is_empty() => "(no output)".to_string(),
+            output => output,
+        };
+        info!("Summarizing {} bytes of output", output.len());
+        match chat
+            .answer_from_output_async(&result.prompt, &generated.command, &output, length)
+            .await
+        {
+            Ok(answer) => generated.answer = Some(answer),
+            Err(e) => {
+                warn!("Failed to summarize command output: {}", e);
+                health::note_chat_error(&chat, &e);
+                output::warning(format!("Could not summarize the output: {}", e));
+            }
+        }
+    }
+}
+
+/// `eidos core --refine`: show the command, then regenerate it from follow-up
+/// instructions read on stdin until an empty line accepts it
+///
+/// Only the accepted command goes to stdout and the history; Ctrl-D cancels.
+#[cfg(feature = "core")]
+fn refine_command(
+    backend: &dyn InferenceBackend,
+    policy: &SafetyPolicy,
+    prompt: &str,
+    context: Option<String>,
+    explain: bool,
+) -> Result<()> {
+    let mut session = RefinementSession::new(backend).with_context(context);
+    let mut request = Some(prompt.to_string());
+    loop {
+        if let Some(request) = request.take() {
+            let command = session.generate(&request).map_err(|e| {
+                error!("Inference failed: {}", e);
+                output::error(format!("Error: {}", e));
+                crate::error::AppError::Model(e.to_string())
+            })?;
+            let report = policy.validate(&command);
+            match report.primary() {
+                None if report.needs_review => output::note(format!(
+                    "\n  {}\n  ⚠ needs review: {}\n",
+                    command,
+                    output::NEEDS_REVIEW
+                )),
+                None => output::note(format!("\n  {}\n", command)),
+                Some(violation) => {
+                    warn!(
+                        "Refined command failed safety check ({}): {}",
+                        violation, command
+                    );
+                    output::warning(format!("Rejected '{}': {}", command, violation));
+                    metrics::record_rejections(1);
+                    session.reject_last();
+                }
+            }
+        }
+
+        output::note(match session.command() {
+            Some(_) => "Refine it (e.g. \"sort by size\"), Enter to accept, Ctrl-D to cancel:",
+            None => "Describe the command another way, or Ctrl-D to cancel:",
+        });
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            output::note("Cancelled");
+            return Ok(());
+        }
+        match line.trim() {
+            "" if session.command().is_some() => break,
+            "" => continue,
+            follow_up => {
+                validate_input(follow_up, MAX_CORE_PROMPT_LENGTH)
+                    .map_err(crate::error::AppError::InvalidInput)?;
+                request = Some(follow_up.to_string());
+            }
+        }
+    }
+
+    let requests: Vec<&str> = session.turns().iter().map(|(r, _)| r.as_str()).collect();
+    let command = session.command().unwrap_or_default().to_string();
+    let explanation = if explain {
+        backend
+            .explain(&command)
+            .map_err(|e| warn!("Failed to generate explanation: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    emit_commands(
+        CommandResult::single(requests.join("; "), command, explanation),
+        false,
+    );
+    Ok(())
+}
+
+/// `prompt` with the answer to a clarifying question, if it leaves something open
+#[cfg(feature = "core")]
+fn clarify_prompt(prompt: &str) -> crate::error::Result<String> {
+    let Some(clarification) = lib_core::clarify::clarifying_question(prompt) else {
+        return Ok(prompt.to_string());
+    };
+    debug!("Asking about the {}", clarification.label);
+    output::note(format!("{} (Enter to skip)", clarification.question));
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    let answer = line.trim();
+    if answer.is_empty() {
+        return Ok(prompt.to_string());
+    }
+    let clarified = clarification.apply(prompt, answer);
+    validate_input(&clarified, MAX_CORE_PROMPT_LENGTH).map_err(|e| {
+        output::error(format!("Invalid input: {}", e));
+        crate::error::AppError::InvalidInput(e)
+    })?;
+    Ok(clarified)
+}
+
+/// Run `generated` for `--run-safe` if it is read-only, else say why it was not run
+#[cfg(feature = "core")]
+fn run_if_read_only(generated: &mut GeneratedCommand) -> Option<String> {
+    let risk = lib_core::classify_command(&generated.command);
+    if risk != Risk::ReadOnly {
+        info!("Not running {} command: {}", risk, generated.command);
+        return Some(format!(
+            "Not run: '{}' is {}; --run-safe only runs read-only commands",
+            generated.command, risk
+        ));
+    }
+
+    info!("Running read-only command: {}", generated.command);
+    match run_safe::run(&generated.command, run_safe::RUN_TIMEOUT) {
+        Ok(run) => {
+            generated.run = Some(run);
+            None
+        }
+        Err(e) => {
+            warn!("Failed to run command: {}", e);
+            Some(format!("Could not run '{}': {}", generated.command, e))
+        }
+    }
+}
+
+/// Render a bridge response through the output sink
+fn render(response: Response) {
+    match response {
+        #[cfg(feature = "core")]
+        Response::CommandGenerated {
+            prompt,
+            command,
+            explanation,
+        } => emit_commands(CommandResult::single(prompt, command, explanation), false),
+        #[cfg(feature = "chat")]
+        Response::ChatReply { reply, sources } => output::emit(&ChatResult {
+            reply,
+            sources,
+            citations: Vec::new(),
+        }),
+        #[cfg(feature = "translate")]
+        Response::Translation {
+            source_lang,
+            target_lang,
+            original,
+            translated,
+            was_translated,
+            confidence,
+        } => output::emit(&TranslationResultOutput {
+            source_lang_name: display_name(&source_lang),
+            source_lang,
+            target_lang_name: display_name(&target_lang),
+            target_lang,
+            original,
+            translated,
+            was_translated,
+            confidence,
+        }),
+        Response::Text(text) => output::emit(&TextResult { text }),
+        // Only handlers compiled into this binary are registered, so responses
+        // belonging to a disabled subsystem never reach this point
+        #[allow(unreachable_patterns)]
+        other => debug!("Ignoring response from a disabled subsystem: {:?}", other),
+    }
+}
+
+// A single runtime drives every async subsystem; the blocking wrappers in
+// lib_chat/lib_translate (and their hidden runtimes) are not used by the CLI
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    // A <Tab> press from the script of `eidos completions`: answer and exit
+    CompleteEnv::with_factory(Cli::command)
+        .var(completions::COMPLETE_VAR)
+        .complete();
+    let config = Config::load();
+    let result = run(config.as_ref()).await;
+    #[cfg(any(feature = "chat", feature = "translate", feature = "core"))]
+    metrics::finish(result.is_ok(), &paths::metrics_path());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let mut report = e.report();
+            if output::ci() && report.code == ErrorCode::Unsafe {
+                // The blocked command's verdict is already printed
+                if let Some(code) = config.ok().and_then(|config| config.ci.blocked_exit_code) {
+                    report.exit_code = code.into();
+                }
+            } else if !output::error_shown() {
+                // Handlers print their failures with context; only the rest is printed here
+                output::error(&report);
+            }
+            if output::format() == OutputFormat::Json {
+                output::emit(&report);
+            }
+            ExitCode::from(report.exit_code as u8)
+        }
+    }
+}
+
+/// Run the command line; `loaded` is the configuration read once for the whole run
+async fn run(loaded: std::result::Result<&Config, &String>) -> Result<()> {
+    // Parse CLI arguments
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Bare input is counted as one kind, whatever its first word
+    #[cfg(any(feature = "chat", feature = "translate", feature = "core"))]
+    metrics::start_command(match cli.command {
+        Commands::External(_) => "external",
+        _ => matches.subcommand_name().unwrap_or_default(),
+    });
+
+    // Output is set up even when loading failed, so the error is shown as configured
+    let fallback = Config::default();
+    let config = loaded.unwrap_or(&fallback);
+    #[cfg(any(feature = "chat", feature = "translate", feature = "core"))]
+    metrics::set_enabled(config.metrics.enabled);
+    #[cfg(any(feature = "chat", feature = "translate"))]
+    let legacy = config.legacy.clone();
+    #[cfg(feature = "core")]
+    MODEL_REGISTRY.set_memory_budget(config.model_memory_budget());
+    let Config { ui, ci, log, .. } = config;
+    let ci = cli.ci || ci.enabled;
+
+    // Initialize logging
+    let log_format = match cli.log_format {
+        Some(format) => format,
+        None => log.format().unwrap_or_else(|e| {
+            output::warning(format!("Ignoring [log] format: {}", e));
+            LogFormat::default()
+        }),
+    };
+    let color = cli.color.unwrap_or_default();
+    let no_color = std::env::var_os("NO_COLOR").is_some();
+    let stderr_color = color.enabled(ui.color(), no_color, std::io::stderr().is_terminal());
+    init_logging(
+        cli.verbose,
+        cli.debug,
+        ci,
+        cli.quiet,
+        log,
+        log_format,
+        stderr_color,
+    );
+    output::set_format(match cli.output {
+        Some(format) => format,
+        None if cli.raw => OutputFormat::Raw,
+        None => ui.output_format().unwrap_or_else(|e| {
+            output::warning(format!("Ignoring [ui] output: {}", e));
+            OutputFormat::default()
+        }),
+    });
+    output::set_tips(ui.tips() && !cli.no_tips);
+    output::set_emoji(ui.emoji());
+    output::set_color(color.enabled(ui.color(), no_color, std::io::stdout().is_terminal()));
+    output::set_stderr_color(stderr_color);
+    let hyperlinks = ui
+        .hyperlinks
+        .unwrap_or_else(|| output::terminal_supports_hyperlinks(|name| std::env::var(name).ok()));
+    output::set_links((hyperlinks && std::io::stdout().is_terminal()).then(|| {
+        ui.man_links().unwrap_or_else(|e| {
+            output::warning(format!("Ignoring [ui] man_links: {}", e));
+            ManLinks::default()
+        })
+    }));
+    output::set_ci(ci);
+    output::set_quiet(cli.quiet);
+    output::set_lang(match &cli.lang {
+        Some(lang) => Some(lang.trim().to_lowercase()),
+        None => ui.lang(),
+    });
+    #[cfg(any(feature = "chat", feature = "translate"))]
+    legacy_env::warn_once(&legacy);
+    let timeout = cli.timeout.map(Duration::from_secs);
+    let context_flag = match (cli.context, cli.no_context) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    };
+
+    // Every command runs with the loaded settings, so a broken config file stops
+    // them all rather than being replaced by the defaults
+    if let Err(e) = loaded {
+        error!("Configuration loading failed: {}", e);
+        output::error(format!("Configuration Error: {}", e));
+        return Err(crate::error::AppError::Config(e.clone()));
+    }
+
+    info!("Eidos v0.2.0-beta starting");
+    debug!("Command: {:?}", cli.command);
+
+    // Initialize the bridge with all handlers
+    let mut bridge = setup_bridge(&Arc::new(config.clone()));
+
+    // Route commands through the bridge with input validation
+    let result = match cli.command {
+        #[cfg(feature = "chat")]
+        Commands::Chat {
+            action:
+                Some(ChatAction::Export {
+                    ref session,
+                    format,
+                }),
+            ..
+        } => export_session(session, format),
+        #[cfg(feature = "chat")]
+        Commands::Chat {
+            action: None,
+            ref text,
+            ref with_index,
+            top_k,
+            concise,
+            detailed,
+            ref persona,
+            ref provider,
+            ref model,
+            temperature,
+            max_tokens,
+            #[cfg(feature = "vision")]
+            ref image,
+        } => {
+            let Some(text) = text else {
+                unreachable!("clap requires the chat text without a subcommand");
+            };
+            // Validate input (max 10000 chars for chat)
+            if let Err(e) = validate_input(text, MAX_CHAT_INPUT_LENGTH) {
+                error!("Input validation failed: {}", e);
+                output::error(format!("Invalid input: {}", e));
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+
+            let verbosity = chat_verbosity(config, concise, detailed)
+                .map_err(crate::error::AppError::InvalidInput)?;
+            let flags = ChatFlags {
+                persona: persona.as_deref(),
+                provider: provider.as_deref(),
+                model: model.as_deref(),
+                temperature,
+                max_tokens,
+                #[cfg(feature = "vision")]
+                images: image,
+            };
+
+            match with_index {
+                Some(name) => {
+                    let index = Index::load(&paths::index_path(name)).map_err(|e| {
+                        error!("Failed to load index '{}': {}", name, e);
+                        output::error(format!("Index Error: {}", e));
+                        Hints::new()
+                            .line(format!(
+                                "Tip: build it first with `eidos index <dir> --name {}`",
+                                name
+                            ))
+                            .emit();
+                        crate::error::AppError::from(e)
+                    })?;
+                    run_chat(
+                        config,
+                        text,
+                        verbosity,
+                        Some(ChatGrounding {
+                            index: &index,
+                            top_k,
+                        }),
+                        &flags,
+                        timeout,
+                    )
+                    .await
+                    .map_err(crate::error::AppError::from)
+                }
+                // Rendered here rather than through the bridge so the reply can stream
+                None => run_chat(config, text, verbosity, None, &flags, timeout)
+                    .await
+                    .map_err(crate::error::AppError::from),
+            }
+        }
+        #[cfg(feature = "core")]
+        Commands::History { ref action, limit } => {
+            let history = CommandHistory::new(paths::history_path());
+            let read_error = |e: std::io::Error| {
+                error!("Failed to read history: {}", e);
+                output::error(format!("History Error: {}", e));
+                crate::error::AppError::from(e)
+            };
+
+            match action {
+                None => {
+                    let entries = history.entries().map_err(read_error)?;
+                    if entries.is_empty() && output::format() == OutputFormat::Text {
+                        output::note("No commands generated yet");
+                        return Ok(());
+                    }
+                    let skip = entries.len().saturating_sub(limit);
+                    output::emit(&HistoryResult {
+                        entries: entries.into_iter().skip(skip).collect(),
+                    });
+                    Ok(())
+                }
+                Some(HistoryAction::Undo { id }) => {
+                    let Some(entry) = history.get(*id).map_err(read_error)? else {
+                        let e = format!("No history entry {}", id);
+                        output::error(&e);
+                        Hints::new()
+                            .line("Tip: list entries with `eidos history`")
+                            .emit();
+                        return Err(crate::error::AppError::InvalidInput(e));
+                    };
+                    match entry.undo {
+                        Some(undo) => {
+                            output::emit(&UndoResult {
+                                id: entry.id,
+                                command: entry.command,
+                                undo,
+                            });
+                            Ok(())
+                        }
+                        None => {
+                            let e = format!("No undo is known for `{}`", entry.command);
+                            output::error(&e);
+                            Err(crate::error::AppError::InvalidInput(e))
+                        }
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "core")]
+        Commands::Stats {
+            action:
+                StatsAction::Export {
+                    format,
+                    data,
+                    ref out,
+                },
+        } => {
+            let entries = CommandHistory::new(paths::history_path())
+                .entries()
+                .map_err(|e| {
+                    error!("Failed to read history: {}", e);
+                    output::error(format!("History Error: {}", e));
+                    crate::error::AppError::from(e)
+                })?;
+            export_table(&Table::of(data, &entries), format, out.as_deref())
+        }
+        #[cfg(feature = "core")]
+        Commands::Stats {
+            action: StatsAction::Metrics { reset },
+        } => {
+            let path = paths::metrics_path();
+            if reset {
+                let removed = metrics::reset(&path).map_err(|e| {
+                    output::error(format!("Failed to reset metrics: {}", e));
+                    crate::error::AppError::Io(e)
+                })?;
+                output::emit(&TextResult {
+                    text: if removed {
+                        format!("Removed {}", path.display())
+                    } else {
+                        "No metrics recorded".to_string()
+                    },
+                });
+            } else {
+                output::emit(&MetricsResult {
+                    enabled: metrics::enabled(),
+                    path: path.display().to_string(),
+                    metrics: metrics::snapshot(&path),
+                });
+            }
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::ShellInit { shell } => {
+            output::result(shell.init_script().trim_end());
+            // Only when run by hand; `eval "$(eidos shell-init ...)"` stays quiet
+            if std::io::stdout().is_terminal() {
+                Hints::new()
+                    .line("To enable it, add to your shell config:")
+                    .line(format!("  {}", shell.install_hint()))
+                    .emit();
+            }
+            Ok(())
+        }
+        Commands::Completions { ref shell } => {
+            let script = completions::registration(shell, &completions::completer())
+                .map_err(crate::error::AppError::InvalidInput)?;
+            output::result(script.trim_end());
+            if std::io::stdout().is_terminal() {
+                Hints::new()
+                    .line("To enable it, add to your shell config:")
+                    .line(format!("  {}", completions::install_hint(shell)))
+                    .emit();
+            }
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::Doctor => {
+            output::emit(&doctor_report(config));
+            Ok(())
+        }
+        #[cfg(feature = "chat")]
+        Commands::Index { ref dir, ref name } => {
+            let name = match name {
+                Some(name) => name.clone(),
+                None => dir
+                    .canonicalize()
+                    .ok()
+                    .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "notes".to_string()),
+            };
+            if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+                output::error(format!("Invalid index name: {}", name));
+                return Err(crate::error::AppError::InvalidInput(format!(
+                    "Invalid index name: {}",
+                    name
+                )));
+            }
+
+            let client = config.chat_client().map_err(|e| {
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            })?;
+            output::note(format!("Indexing {} ...", dir.display()));
+            let embedder = Embedder::from_client(client);
+            let index = Index::build(&name, dir, &embedder).await.map_err(|e| {
+                error!("Indexing failed: {}", e);
+                output::error(format!("Index Error: {}", e));
+                crate::error::AppError::from(e)
+            })?;
+
+            let path = paths::index_path(&name);
+            index.save(&path).map_err(|e| {
+                output::error(format!("Index Error: {}", e));
+                crate::error::AppError::from(e)
+            })?;
+
+            output::note(format!(
+                "Indexed {} chunks from {} files with {}",
+                index.chunks.len(),
+                index.source_count(),
+                index.embedder
+            ));
+            output::emit(&IndexResult {
+                name,
+                path: path.display().to_string(),
+                chunks: index.chunks.len(),
+                sources: index.source_count(),
+                embedder: index.embedder.clone(),
+            });
+            Ok(())
+        }
+        #[cfg(feature = "chat")]
+        Commands::Sessions { ref action } => {
+            let store = SessionStore::new(paths::sessions_dir());
+            let session_error = |e: lib_chat::ChatError| {
+                error!("Session operation failed: {}", e);
+                output::error(format!("Session Error: {}", e));
+                crate::error::AppError::from(e)
+            };
+
+            match action {
+                None => {
+                    let sessions = store.list().map_err(session_error)?;
+                    if sessions.is_empty() && output::format() == OutputFormat::Text {
+                        output::note("No saved sessions");
+                        Hints::new()
+                            .line("Tip: import ChatGPT conversations with `eidos sessions import <export.zip>`")
+                            .emit();
+                        return Ok(());
+                    }
+                    output::emit(&SessionsResult {
+                        sessions: sessions.iter().map(SessionSummary::from).collect(),
+                    });
+                }
+                Some(SessionsAction::Import { file }) => {
+                    let sessions =
+                        lib_chat::import::read_chatgpt_export(file).map_err(session_error)?;
+                    let found = sessions.len();
+                    let report = store.import(sessions).map_err(session_error)?;
+                    output::note(format!(
+                        "Imported {} of {} conversations ({} already imported)",
+                        report.imported.len(),
+                        found,
+                        report.skipped
+                    ));
+                    output::emit(&SessionImportResult {
+                        imported: report.imported,
+                        skipped: report.skipped,
+                    });
+                }
+                Some(SessionsAction::Export { name, format }) => export_session(name, *format)?,
+                #[cfg(feature = "translate")]
+                Some(SessionsAction::Translate { name, to }) => {
+                    let target = to.trim().to_lowercase();
+                    let session = store.load(name).map_err(session_error)?;
+                    let mut translate = config.translator().map_err(|e| {
+                        output::error(format!("Configuration Error: {}", e));
+                        crate::error::AppError::Config(e)
+                    })?;
+                    if let Some(timeout) = timeout {
+                        translate = translate.with_timeout(timeout);
+                    }
+                    if translate.is_offline() {
+                        output::warning(
+                            "No translation service configured; translating offline. Set [translate] url in eidos.toml for full translation",
+                        );
+                    }
+
+                    let mut history = ConversationHistory::new(session.messages.len().max(1));
+                    for message in &session.messages {
+                        history
+                            .add_message(message.clone())
+                            .map_err(|e| session_error(lib_chat::ChatError::InvalidInput(e)))?;
+                    }
+                    let mut chat = Chat::from_client(None)
+                        .with_history(history)
+                        .with_translate(translate);
+                    let translated = chat
+                        .switch_language_async(&target)
+                        .await
+                        .map_err(session_error)?;
+
+                    let copy = Session {
+                        name: format!("{}-{}", session.name, target),
+                        title: format!("{} ({})", session.title, display_name(&target)),
+                        created: lib_util::unix_now(),
+                        origin: None,
+                        model: session.model.clone(),
+                        messages: chat.history().to_vec(),
+                    };
+                    store.save(&copy).map_err(session_error)?;
+                    output::emit(&SessionTranslationResult {
+                        name: copy.name,
+                        source: session.name,
+                        target_lang_name: display_name(&target),
+                        target_lang: target,
+                        translated,
+                        messages: copy.messages.len(),
+                    });
+                }
+            }
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::Cache { action } => {
+            let dir = paths::command_cache_dir();
+            let cache_error = |e: std::io::Error| {
+                let e = format!("Cannot read {}: {}", dir.display(), e);
+                output::error(format!("Cache Error: {}", e));
+                crate::error::AppError::InvalidInput(e)
+            };
+            match action {
+                CacheAction::Stats => {
+                    let stats = lib_core::cache::stats(&dir).map_err(cache_error)?;
+                    output::emit(&CacheStatsResult {
+                        dir: dir.display().to_string(),
+                        entries: stats.entries,
+                        bytes: stats.bytes,
+                        hits: stats.hits,
+                        oldest_days: stats.oldest.map(|age| age.as_secs() / (24 * 60 * 60)),
+                    });
+                }
+                CacheAction::Clear => {
+                    let removed = lib_core::cache::clear(&dir).map_err(cache_error)?;
+                    output::emit(&TextResult {
+                        text: format!("Removed {} cached entries", removed),
+                    });
+                }
+            }
+            Ok(())
+        }
+        #[cfg(feature = "models")]
+        Commands::Model { action } => manage_models(config, action).await,
+        #[cfg(any(feature = "chat", feature = "translate"))]
+        Commands::Config {
+            action: ConfigAction::Migrate { dry_run, file },
+        } => {
+            let config_error = |e: String| {
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            };
+            let path = match file {
+                Some(path) => path,
+                None => Config::editable_path().ok_or_else(|| {
+                    config_error("No config file to write: HOME is not set".to_string())
+                })?,
+            };
+            let plan = Config::migrate_legacy_env(&path, dry_run).map_err(config_error)?;
+            let written = !dry_run && !plan.settings.is_empty();
+            let mut migrated: Vec<&str> = plan.settings.iter().map(|s| s.var).collect();
+            migrated.dedup();
+            output::emit(&ConfigMigrateResult {
+                path: path.display().to_string(),
+                written,
+                settings: plan.settings.clone(),
+                skipped: plan.skipped,
+            });
+            if written {
+                output::note(format!(
+                    "Unset {} once eidos works with the new settings",
+                    migrated.join(", ")
+                ));
+            }
+            Ok(())
+        }
+        #[cfg(any(feature = "chat", feature = "core"))]
+        Commands::Maintenance {
+            action: MaintenanceAction::Compact { max_age, max_size },
+        } => {
+            let result = compact_data_dir(max_age, max_size).map_err(|e| {
+                error!("Compaction failed: {}", e);
+                output::error(format!("Maintenance Error: {}", e));
+                crate::error::AppError::InvalidInput(e)
+            })?;
+            if result.files.is_empty() && output::format() == OutputFormat::Text {
+                output::note("Nothing to compact");
+                return Ok(());
+            }
+            output::emit(&result);
+            Ok(())
+        }
+        #[cfg(all(feature = "core", feature = "translate"))]
+        Commands::Ask {
+            ref prompt,
+            explain,
+        } => {
+            if let Err(e) = validate_input(prompt, MAX_CORE_PROMPT_LENGTH) {
+                error!("Input validation failed: {}", e);
+                output::error(format!("Invalid input: {}", e));
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+
+            let mut config = config.clone();
+            config.context.override_with(context_flag);
+            let mut payload = with_timeout(Payload::new(prompt.as_str()), timeout);
+            if explain {
+                payload = payload.with_option("explain", "true");
+            }
+            let payload = config.env_context().attach(payload);
+
+            let started = Instant::now();
+            let answer = Pipeline::new(&bridge).ask(payload).await.map_err(|e| {
+                error!("Ask pipeline failed: {}", e);
+                crate::error::AppError::from(e)
+            })?;
+            let translation = answer.was_translated().then(|| PromptTranslation {
+                source_lang: answer.source_lang.clone(),
+                source_lang_name: display_name(&answer.source_lang),
+                english_prompt: answer.english_prompt.clone(),
+            });
+            if let Some(translation) = &translation {
+                output::note(format!(
+                    "Translated from {}: {}",
+                    translation.source_lang_name, translation.english_prompt
+                ));
+            }
+
+            let mut result =
+                CommandResult::single(prompt.clone(), answer.command, answer.explanation);
+            result.translation = translation;
+            result.latency = Some(started.elapsed());
+            emit_commands(result, false);
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::Explain { ref command } => {
+            if let Err(e) = validate_input(command, MAX_CORE_PROMPT_LENGTH) {
+                error!("Input validation failed: {}", e);
+                output::error(format!("Invalid input: {}", e));
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+            info!("Processing explain request");
+            debug!("Command: {}", sanitize_for_logging(command, 50));
+            let mut result = explain_command(config, command, timeout).await;
+            if let Some(explanation) = result.explanation.take() {
+                result.explanation = localize_texts(config, vec![explanation]).await.pop();
+            }
+            output::emit(&result);
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::AuditScript { ref file, sarif } => {
+            info!("Processing audit-script request");
+            let script = std::fs::read_to_string(file).map_err(|e| {
+                output::error(format!("Failed to read {}: {}", file.display(), e));
+                crate::error::AppError::Io(e)
+            })?;
+            let policy = config.safety_policy().map_err(|e| {
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            })?;
+
+            let result = AuditResult {
+                file: file.display().to_string(),
+                commands: lib_core::audit::script_commands(&script).len(),
+                findings: lib_core::audit_script(&script, &policy),
+            };
+            debug!(
+                "Audited {} commands, {} findings",
+                result.commands,
+                result.findings.len()
+            );
+            if sarif {
+                output::result(result.sarif());
+            } else {
+                output::emit(&result);
+            }
+
+            if result.findings.is_empty() {
+                Ok(())
+            } else {
+                Err(crate::error::AppError::Unsafe(format!(
+                    "Risky commands in {}: {}",
+                    result.file,
+                    result.findings.len()
+                )))
+            }
+        }
+        #[cfg(any(feature = "core", feature = "chat", feature = "translate"))]
+        Commands::Bench {
+            runs,
+            no_model,
+            no_providers,
+        } => {
+            info!("Processing bench request");
+            let mut measurements = Vec::new();
+            #[cfg(feature = "core")]
+            {
+                measurements.push(bench_validation(config));
+                if !no_model {
+                    measurements.extend(bench_model(config, runs as usize));
+                }
+            }
+            #[cfg(not(feature = "core"))]
+            let _ = (runs, no_model);
+            #[cfg(feature = "chat")]
+            if !no_providers {
+                measurements.push(bench_chat(config, timeout).await);
+            }
+            #[cfg(feature = "translate")]
+            if !no_providers {
+                measurements.push(bench_translate(config, timeout).await);
+            }
+            #[cfg(not(any(feature = "chat", feature = "translate")))]
+            let _ = no_providers;
+            output::emit(&BenchResult { measurements });
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::Eval {
+            ref dataset,
+            ref report,
+            limit,
+            seed,
+        } => {
+            info!("Processing eval request");
+            let text = std::fs::read_to_string(dataset).map_err(|e| {
+                output::error(format!("Failed to read {}: {}", dataset.display(), e));
+                crate::error::AppError::Io(e)
+            })?;
+            let mut cases = lib_core::eval::parse_dataset(&text).map_err(|e| {
+                let e = format!("Invalid dataset {}: {}", dataset.display(), e);
+                output::error(&e);
+                crate::error::AppError::InvalidInput(e)
+            })?;
+            if let Some(limit) = limit {
+                cases.truncate(limit);
+            }
+
+            let mut config = config.clone();
+            config.core.seed = seed.or(config.core.seed);
+            let (policy, backend) = local_generator(&config, 1)?;
+
+            let total = cases.len();
+            let evaluation =
+                lib_core::evaluate(backend.as_ref(), &policy, &cases, |done, record| {
+                    debug!(
+                        "[{}/{}] {} -> {}",
+                        done,
+                        total,
+                        record.prompt,
+                        record.generated.as_deref().unwrap_or("(failed)")
+                    );
+                });
+
+            if let Some(path) = report {
+                let json = serde_json::to_string_pretty(&evaluation)
+                    .map_err(|e| crate::error::AppError::InvalidInput(e.to_string()))?;
+                std::fs::write(path, json + "\n").map_err(|e| {
+                    output::error(format!("Failed to write {}: {}", path.display(), e));
+                    crate::error::AppError::Io(e)
+                })?;
+            }
+            output::emit(&EvalResult {
+                dataset: dataset.display().to_string(),
+                report: report.as_ref().map(|path| path.display().to_string()),
+                evaluation,
+            });
+            Ok(())
+        }
+        #[cfg(feature = "core")]
+        Commands::Core {
             ref prompt,
+            ref batch,
+            jobs,
+            ndjson,
             alternatives,
             explain,
+            run_safe,
+            preview,
+            #[cfg(feature = "chat")]
+            summarize_output,
+            refine,
+            ref vars,
+            no_ask,
+            no_cache,
+            context_history,
+            seed,
         } => {
+            if let Some(file) = batch {
+                return run_batch(
+                    config,
+                    file,
+                    usize::from(jobs),
+                    explain,
+                    ndjson,
+                    context_flag,
+                    seed,
+                );
+            }
+            let Some(prompt) = prompt else {
+                unreachable!("clap requires a prompt without --batch");
+            };
+            let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+            if refine && (output::ci() || !std::io::stdin().is_terminal()) {
+                let e = "--refine reads follow-ups from a terminal; it is not available with --ci or piped input".to_string();
+                output::error(&e);
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+
             // Validate input (max 1000 chars for prompts)
             if let Err(e) = validate_input(prompt, MAX_CORE_PROMPT_LENGTH) {
                 error!("Input validation failed: {}", e);
-                eprintln!("❌ Invalid input: {}", e);
+                output::error(format!("Invalid input: {}", e));
                 return Err(crate::error::AppError::InvalidInput(e));
             }
 
+            let template = TemplateVars::for_prompt(prompt, vars.clone()).map_err(|e| {
+                output::error(e.to_string());
+                crate::error::AppError::InvalidInput(e.to_string())
+            })?;
+
+            // One question about a vague prompt beats a guessed directory; only asked
+            // at a terminal, and templates already name their parameters
+            let clarified;
+            let prompt =
+                if !no_ask && template.is_none() && !output::ci() && std::io::stdin().is_terminal()
+                {
+                    clarified = clarify_prompt(prompt)?;
+                    &clarified
+                } else {
+                    prompt
+                };
+
+            let mut config = config.clone();
+            config.context.override_with(context_flag);
+            config.core.seed = seed.or(config.core.seed);
+
+            // Captured here so a daemon sees this shell's environment, not its own
+            let env_context = config.env_context();
+            if !env_context.is_empty() {
+                debug!(
+                    "Captured {} environment variables and {} facts",
+                    env_context.vars().len(),
+                    env_context.facts().len()
+                );
+            }
+
+            let context = env_context.render();
+            let context = match &template {
+                Some(template) => Some(match context {
+                    Some(context) => format!("{}\n\n{}", context, template.instruction()),
+                    None => template.instruction(),
+                }),
+                None => context,
+            };
+            let context = match context_history {
+                Some(limit) => with_history_context(usize::from(limit), context),
+                None => context,
+            };
+
+            // A command generated earlier for the same prompt, context and model
+            // needs neither the daemon nor the model; a seed asks for a fresh one
+            let cache = if alternatives == 1 && !refine && seed.is_none() {
+                config.command_cache()
+            } else {
+                None
+            };
+            let cached = cache
+                .as_ref()
+                .filter(|_| !no_cache)
+                .and_then(|cache| cache.command(prompt, context.as_deref()));
+            if cached.is_some() {
+                info!("Using the cached command for this prompt");
+            }
+
+            // A running daemon already has the model loaded; alternatives,
+            // refinement dialogues, templates, requests with earlier commands and
+            // seeded requests are always generated locally
+            if alternatives == 1
+                && !refine
+                && template.is_none()
+                && context_history.is_none()
+                && seed.is_none()
+                && cached.is_none()
+            {
+                let mut payload = with_timeout(Payload::new(prompt.as_str()), timeout);
+                if explain {
+                    payload = payload.with_option("explain", "true");
+                }
+                // Failures are reported in the display language; explanations are
+                // translated here, after caching
+                if let Some(lang) = output::lang() {
+                    payload = payload.with_option(REPLY_LANG_OPTION, lang);
+                }
+                let payload = env_context.attach(payload);
+                let started = Instant::now();
+                if let Some(result) = route_via_daemon(Request::Core, payload).await {
+                    let response = result.map_err(crate::error::AppError::from)?;
+                    let Response::CommandGenerated {
+                        prompt,
+                        command,
+                        explanation,
+                    } = response
+                    else {
+                        render(response);
+                        return Ok(());
+                    };
+                    if let Some(cache) = &cache {
+                        store_in_cache(
+                            cache,
+                            &prompt,
+                            context.as_deref(),
+                            &command,
+                            explanation.as_deref(),
+                        );
+                    }
+                    let mut result = CommandResult::single(prompt, command, explanation);
+                    result.latency = Some(started.elapsed());
+                    localize_explanations(&config, &mut result).await;
+                    if preview {
+                        preview_commands(&mut result);
+                    }
+                    let refused = record_commands(&mut result, run_safe);
+                    #[cfg(feature = "chat")]
+                    if let Some(length) = summarize_output {
+                        answer_from_runs(&config, &mut result, length, deadline).await;
+                    }
+                    emit_recorded(&result, refused);
+                    return Ok(());
+                }
+            }
+
             // Handle Core command generation with alternatives and explain support
             info!("Processing core command generation request");
             debug!("Prompt: {}", sanitize_for_logging(prompt, 50));
             debug!("Alternatives: {}, Explain: {}", alternatives, explain);
 
-            // Load configuration
-            debug!("Loading configuration");
-            let config = Config::load().map_err(|e| {
-                error!("Configuration loading failed: {}", e);
-                crate::error::AppError::InvalidInput(format!("Config error: {}", e))
-            })?;
-
             // Validate configuration
-            config.validate().map_err(|e| {
+            if let Err(e) = config.validate() {
                 error!("Configuration validation failed: {}", e);
-                eprintln!("❌ Configuration Error: {}", e);
-                eprintln!();
-                eprintln!("To configure Eidos, choose one of:");
-                eprintln!("  1. Environment variables:");
-                eprintln!("     export EIDOS_MODEL_PATH=/path/to/model.onnx");
-                eprintln!("     export EIDOS_TOKENIZER_PATH=/path/to/tokenizer.json");
-                eprintln!();
-                eprintln!("  2. Config file (./eidos.toml or ~/.config/eidos/eidos.toml):");
-                eprintln!("     model_path = \"/path/to/model.onnx\"");
-                eprintln!("     tokenizer_path = \"/path/to/tokenizer.json\"");
-                eprintln!();
-                eprintln!("  3. See docs/MODEL_GUIDE.md for training your own model");
-                crate::error::AppError::InvalidInput(e.to_string())
-            })?;
+                output::error(format!("Configuration Error: {}", e));
+                localize_hints_async(&config, model_config_hints())
+                    .await
+                    .emit();
+                return Err(crate::error::AppError::Config(e.to_string()));
+            }
 
-            debug!("Configuration valid, loading model");
+            let policy = config.safety_policy().map_err(|e| {
+                error!("Invalid safety configuration: {}", e);
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            })?;
+            let settings = config.model_settings().map_err(|e| {
+                error!("Invalid model configuration: {}", e);
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            })?;
 
-            // Get Core instance from cache (or load if not cached)
-            let model_path_str = config
-                .model_path
-                .to_str()
-                .ok_or_else(|| {
-                    crate::error::AppError::InvalidInput(
-                        "Invalid model path encoding".to_string(),
-                    )
-                })?;
-            let tokenizer_path_str = config
-                .tokenizer_path
-                .to_str()
-                .ok_or_else(|| {
-                    crate::error::AppError::InvalidInput(
-                        "Invalid tokenizer path encoding".to_string(),
-                    )
-                })?;
+            // Get the backend from cache (or load if not cached); not needed for a
+            // cached command
+            let load_backend = || {
+                debug!("Configuration valid, loading model");
+                get_or_load_model(&settings).map_err(|e| {
+                    error!("Model loading failed: {}", e);
+                    crate::error::AppError::Model(e)
+                })
+            };
 
-            let core = get_or_load_model(model_path_str, tokenizer_path_str).map_err(|e| {
-                error!("Model loading failed: {}", e);
-                crate::error::AppError::InvalidInput(e)
-            })?;
+            if refine {
+                let backend = load_backend()?;
+                return refine_command(backend.as_ref(), &policy, prompt, context, explain);
+            }
 
             // Generate alternatives if requested
             if alternatives > 1 {
+                let backend = load_backend()?;
                 info!("Generating {} alternative commands", alternatives);
-                match core.generate_alternatives(prompt, alternatives) {
+                let started = Instant::now();
+                match backend.alternatives(prompt, alternatives, context.as_deref()) {
                     Ok(commands) => {
-                        println!("Generated {} alternatives:", commands.len());
-                        for (i, cmd) in commands.iter().enumerate() {
-                            if core.is_safe_command(cmd) {
-                                println!("  {}. {}", i + 1, cmd);
-                                if explain {
-                                    if let Ok(explanation) = core.explain_command(cmd) {
-                                        println!("     → {}", explanation);
-                                    }
+                        if commands.len() < alternatives {
+                            info!(
+                                "Only {} distinct commands for {} requested alternatives",
+                                commands.len(),
+                                alternatives
+                            );
+                        }
+                        let (commands, command_templates): (Vec<_>, Vec<_>) = commands
+                            .into_iter()
+                            .map(|cmd| fill_template(template.as_ref(), cmd))
+                            .unzip();
+                        let reports = policy.validate_all(&commands);
+                        let mut safe = Vec::new();
+                        let mut rejected = Vec::new();
+                        let mut templates = Vec::new();
+                        for (i, ((cmd, command_template), report)) in commands
+                            .into_iter()
+                            .zip(command_templates)
+                            .zip(reports)
+                            .enumerate()
+                        {
+                            match report.primary() {
+                                Some(violation) => {
+                                    warn!(
+                                        "Alternative {} failed safety check ({}): {}",
+                                        i + 1,
+                                        violation,
+                                        cmd
+                                    );
+                                    rejected.push(report);
+                                }
+                                None => {
+                                    safe.push(cmd);
+                                    templates.push((command_template, report.needs_review));
                                 }
-                            } else {
-                                warn!("Alternative {} failed safety check: {}", i + 1, cmd);
                             }
                         }
+                        metrics::record_rejections(rejected.len());
+
+                        // Explain all safe alternatives in one batch instead of one by one
+                        let mut explanations =
+                            if explain && !past_deadline(deadline, "explanations") {
+                                backend.explain_all(&safe).into_iter()
+                            } else {
+                                Vec::new().into_iter()
+                            };
+
+                        let commands = safe
+                            .into_iter()
+                            .zip(templates)
+                            .enumerate()
+                            .map(|(i, (command, (template, needs_review)))| {
+                                let explanation = match explanations.next() {
+                                    Some(Ok(explanation)) => Some(explanation),
+                                    Some(Err(e)) => {
+                                        warn!("Failed to explain alternative {}: {}", i + 1, e);
+                                        None
+                                    }
+                                    None => None,
+                                };
+                                GeneratedCommand::new(command, explanation)
+                                    .with_template(template)
+                                    .with_review(needs_review)
+                            })
+                            .collect();
+
+                        let mut result = CommandResult {
+                            prompt: prompt.clone(),
+                            requested: alternatives,
+                            commands,
+                            rejected,
+                            translation: None,
+                            latency: Some(started.elapsed()),
+                        };
+                        localize_explanations(&config, &mut result).await;
+                        emit_commands(result, false);
                         info!("Alternatives generated successfully");
                         Ok(())
                     }
                     Err(e) => {
                         error!("Alternative generation failed: {}", e);
-                        eprintln!("❌ Error: {}", e);
-                        Err(crate::error::AppError::InvalidInput(e.to_string()))
+                        output::error(format!("Error: {}", e));
+                        Err(crate::error::AppError::Model(e.to_string()))
                     }
                 }
             } else {
                 // Generate single command
-                match core.generate_command(prompt) {
+                let started = Instant::now();
+                let generated = match cached {
+                    Some(command) => Ok(command),
+                    None => load_backend()?
+                        .generate(prompt, context.as_deref())
+                        .map(|command| {
+                            if let Some(cache) = &cache {
+                                store_in_cache(cache, prompt, context.as_deref(), &command, None);
+                            }
+                            command
+                        }),
+                };
+                match generated {
                     Ok(command) => {
+                        let (command, command_template) = fill_template(template.as_ref(), command);
                         // Validate that generated command is safe
-                        if core.is_safe_command(&command) {
+                        let report = policy.validate(&command);
+                        if report.is_safe() {
                             info!("Command generated and validated successfully");
                             debug!("Generated command: {}", command);
-                            println!("{}", command);
 
                             // Add explanation if requested
-                            if explain {
-                                match core.explain_command(&command) {
-                                    Ok(explanation) => {
-                                        println!("\nExplanation: {}", explanation);
-                                    }
-                                    Err(e) => {
-                                        warn!("Failed to generate explanation: {}", e);
+                            let explanation = if !explain {
+                                None
+                            } else if let Some(explanation) =
+                                cache.as_ref().and_then(|cache| cache.explanation(&command))
+                            {
+                                Some(explanation)
+                            } else if !past_deadline(deadline, "explanation") {
+                                let explanation = load_backend()?
+                                    .explain(&command)
+                                    .map_err(|e| warn!("Failed to generate explanation: {}", e))
+                                    .ok();
+                                if let (Some(cache), Some(explanation)) = (&cache, &explanation) {
+                                    if let Err(e) = cache.put_explanation(&command, explanation) {
+                                        debug!("Could not cache the explanation: {}", e);
                                     }
                                 }
-                            }
+                                explanation
+                            } else {
+                                None
+                            };
 
+                            let mut result =
+                                CommandResult::single(prompt.clone(), command, explanation);
+                            result.commands[0].template = command_template;
+                            if report.needs_review {
+                                output::warning(format!(
+                                    "Read before running: {}",
+                                    output::NEEDS_REVIEW
+                                ));
+                                result.commands[0].needs_review = true;
+                            }
+                            result.latency = Some(started.elapsed());
+                            localize_explanations(&config, &mut result).await;
+                            if preview {
+                                preview_commands(&mut result);
+                            }
+                            let refused = record_commands(&mut result, run_safe);
+                            #[cfg(feature = "chat")]
+                            if let Some(length) = summarize_output {
+                                answer_from_runs(&config, &mut result, length, deadline).await;
+                            }
+                            emit_recorded(&result, refused);
                             Ok(())
                         } else {
                             error!("Generated command failed safety validation");
-                            eprintln!("❌ Safety Error: Generated command is not safe to execute");
-                            eprintln!("Generated: {}", command);
-                            eprintln!();
-                            eprintln!(
-                                "The model generated a command that contains dangerous patterns."
-                            );
-                            eprintln!("This is a safety feature to prevent harmful commands.");
-                            Err(crate::error::AppError::InvalidInput(
+                            metrics::record_rejections(1);
+                            // On the blocking pool, which can wait for a translation
+                            let prompt = prompt.clone();
+                            let (level, lang) = (policy.level(), output::lang());
+                            tokio::task::spawn_blocking(move || {
+                                report_unsafe_command(
+                                    &config,
+                                    &prompt,
+                                    report,
+                                    level,
+                                    lang.as_deref(),
+                                )
+                            })
+                            .await
+                            .unwrap_or_else(|e| {
+                                warn!("Failed to report the unsafe command: {}", e)
+                            });
+                            Err(crate::error::AppError::Unsafe(
                                 "Generated command failed safety validation".to_string(),
                             ))
                         }
                     }
                     Err(e) => {
                         error!("Inference failed: {}", e);
-                        eprintln!("❌ Error: {}", e);
-                        eprintln!();
-                        eprintln!("This could be due to:");
-                        eprintln!("  - Invalid or corrupted model file");
-                        eprintln!("  - Incompatible model format");
-                        eprintln!("  - Prompt too long or malformed");
-                        Err(crate::error::AppError::InvalidInput(e.to_string()))
+                        output::error(format!("Error: {}", e));
+                        localize_hints_async(&config, inference_hints())
+                            .await
+                            .emit();
+                        Err(crate::error::AppError::Model(e.to_string()))
                     }
                 }
             }
         }
-        Commands::Translate { ref text } => {
+        #[cfg(feature = "chat")]
+        Commands::Summarize { ref file, length } => {
+            info!("Processing summarize request");
+            let (text, source) = read_summarize_input(file.as_ref()).map_err(|e| {
+                error!("Failed to read summarize input: {}", e);
+                output::error(&e);
+                crate::error::AppError::InvalidInput(e)
+            })?;
+
+            if let Err(e) = validate_input(&text, MAX_SUMMARIZE_INPUT_LENGTH) {
+                error!("Input validation failed: {}", e);
+                output::error(format!("Invalid input: {}", e));
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+            debug!(
+                "Summarizing {}: {}",
+                source,
+                sanitize_for_logging(&text, 50)
+            );
+
+            let mut chat = configured_chat(config).map_err(crate::error::AppError::Config)?;
+            if let Some(timeout) = timeout {
+                chat.set_options(ChatOptions::default().with_timeout(timeout));
+            }
+            if !chat.is_configured() {
+                output::warning(
+                    "No API provider configured. Set OPENAI_API_KEY, ANTHROPIC_API_KEY, GEMINI_API_KEY, or [chat] provider and url in eidos.toml",
+                );
+            }
+
+            match chat.summarize_async(&text, length).await {
+                Ok(summary) => {
+                    output::emit(&SummaryResult {
+                        source,
+                        length: length.to_string(),
+                        summary,
+                    });
+                    debug!("Summarize request completed successfully");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("Summarize request failed: {}", e);
+                    health::note_chat_error(&chat, &e);
+                    output::error(format!("Summarize Error: {}", e));
+                    Err(crate::error::AppError::from(e))
+                }
+            }
+        }
+        #[cfg(feature = "translate")]
+        Commands::Translate {
+            ref text,
+            ref from,
+            ref to,
+            localize,
+        } => {
             // Validate input (max 5000 chars for translation)
             if let Err(e) = validate_input(text, MAX_TRANSLATE_INPUT_LENGTH) {
                 error!("Input validation failed: {}", e);
-                eprintln!("❌ Invalid input: {}", e);
+                output::error(format!("Invalid input: {}", e));
                 return Err(crate::error::AppError::InvalidInput(e));
             }
 
             debug!("Routing to translate handler");
-            bridge.route(Request::Translate, text).map_err(|e| {
-                error!("Translate routing failed: {}", e);
-                crate::error::AppError::InvalidInput(e)
-            })
+            let mut payload = Payload::new(text.as_str());
+            if localize {
+                payload = payload.with_option("localize", "true");
+            }
+            let target = to.clone().or_else(|| config.translate.target_lang.clone());
+            if let Some(target) = target {
+                payload = payload.with_option(TARGET_LANG_OPTION, target.trim().to_lowercase());
+            }
+            if let Some(source) = from {
+                payload = payload.with_option(SOURCE_LANG_OPTION, source.trim().to_lowercase());
+            }
+            bridge
+                .route_async(Request::Translate, with_timeout(payload, timeout))
+                .await
+                .map(render)
+                .map_err(|e| {
+                    error!("Translate routing failed: {}", e);
+                    crate::error::AppError::from(e)
+                })
+        }
+        Commands::External(ref args) => {
+            // Verbs added by executables in the plugins directory come first
+            if let Some((verb, rest)) = args.split_first() {
+                if bridge.plugin(verb).is_some() {
+                    let input = rest.join(" ");
+                    debug!("Routing to plugin '{}'", verb);
+                    let payload = with_timeout(Payload::new(input), timeout);
+                    return bridge.route_plugin(verb, payload).map(render).map_err(|e| {
+                        error!("Plugin failed: {}", e);
+                        crate::error::AppError::from(e)
+                    });
+                }
+            }
+
+            let input = args.join(" ");
+
+            // Without a configured default this is just an unknown subcommand
+            let mut config = config.clone();
+            config.context.override_with(context_flag);
+            let env_context = config.env_context();
+            let Some(default_command) = config.default_command.clone() else {
+                Cli::command()
+                    .error(
+                        ErrorKind::InvalidSubcommand,
+                        format!(
+                            "unrecognized subcommand '{}'\n\n\
+                             tip: set default_command = \"core\" in eidos.toml to run bare prompts,\n\
+                             \x20    or add an executable named after it to {}",
+                            args.first().map(String::as_str).unwrap_or_default(),
+                            paths::plugins_dir().display()
+                        ),
+                    )
+                    .exit();
+            };
+
+            let request: Request = default_command.parse().map_err(|e: String| {
+                error!("Invalid default_command in config: {}", e);
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            })?;
+
+            if let Err(e) = validate_input(&input, max_input_length(request)) {
+                error!("Input validation failed: {}", e);
+                output::error(format!("Invalid input: {}", e));
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+
+            if !bridge.is_registered(request) {
+                let e = format!(
+                    "default_command = \"{}\" is not available: eidos was built without the `{}` feature",
+                    request,
+                    feature_for(request)
+                );
+                error!("{}", e);
+                output::error(format!("Configuration Error: {}", e));
+                return Err(crate::error::AppError::InvalidInput(e));
+            }
+
+            // Chat replies stream, so they are rendered here rather than by the bridge
+            #[cfg(feature = "chat")]
+            if request == Request::Chat {
+                let verbosity = chat_verbosity(&config, false, false)
+                    .map_err(crate::error::AppError::InvalidInput)?;
+                return run_chat(
+                    &config,
+                    &input,
+                    verbosity,
+                    None,
+                    &ChatFlags::default(),
+                    timeout,
+                )
+                .await
+                .map_err(crate::error::AppError::from);
+            }
+
+            let mut payload = with_timeout(Payload::new(input), timeout);
+            if request == Request::Core {
+                payload = env_context.attach(payload);
+                if let Some(result) = route_via_daemon(request, payload.clone()).await {
+                    return result.map(render).map_err(crate::error::AppError::from);
+                }
+            }
+
+            bridge.set_default(request);
+
+            debug!("Routing to default handler ({})", request);
+            bridge
+                .route_default_async(payload)
+                .await
+                .map(render)
+                .map_err(|e| {
+                    error!("Default routing failed: {}", e);
+                    crate::error::AppError::from(e)
+                })
+        }
+        #[cfg(all(unix, feature = "serve"))]
+        Commands::Daemon { ref socket } => {
+            let socket = socket.clone().unwrap_or_else(paths::socket_path);
+
+            // Load the model up front so the first request is fast too
+            let metrics_addr = config.metrics.listen_addr().map_err(|e| {
+                output::error(format!("Configuration Error: {}", e));
+                crate::error::AppError::Config(e)
+            })?;
+            match config.validate().and_then(|()| config.model_settings()) {
+                Ok(settings) => {
+                    if let Err(e) = get_or_load_model(&settings) {
+                        output::warning(format!("Model not loaded: {}", e));
+                    }
+                }
+                Err(e) => output::warning(format!(
+                    "Model not configured ({}); core requests will fail until it is",
+                    e
+                )),
+            }
+
+            output::note(format!(
+                "Listening on {} (Ctrl-C to stop)",
+                socket.display()
+            ));
+            if let Some(addr) = metrics_addr {
+                output::note(format!("Metrics at http://{}/metrics", addr));
+            }
+            daemon::serve(bridge, &socket, metrics_addr)
+                .await
+                .map_err(|e| {
+                    error!("Daemon failed: {}", e);
+                    output::error(format!("Daemon Error: {}", e));
+                    crate::error::AppError::from(e)
+                })
         }
     };
 
@@ -0,0 +1,463 @@
+// src/metrics.rs
+// Opt-in usage metrics, kept on this machine
+//
+// With `[metrics] enabled = true`, an invocation counts what it did in memory
+// (commands run, requests the daemon served, inference latency, commands rejected
+// by the safety policy, provider errors) and adds it to
+// ~/.local/share/eidos/metrics.json before it exits. Concurrent invocations update
+// the file under a lock (see `lockfile`), so no count is lost. `eidos stats metrics`
+// prints the totals, and `eidos daemon` can serve them to a Prometheus scraper on a
+// loopback address (`[metrics] listen`). Nothing is ever sent anywhere; with metrics
+// off (the default) nothing is recorded and the file is never written.
+
+use crate::lockfile::with_exclusive;
+use lazy_static::lazy_static;
+use lib_util::write_atomic;
+use log::debug;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the inference latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Recorded by this process and not yet added to the metrics file
+    static ref PENDING: Mutex<Metrics> = Mutex::new(Metrics::default());
+    /// Subcommand of this invocation, counted by `finish`
+    static ref COMMAND: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Successful and failed runs of one kind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outcomes {
+    pub ok: u64,
+    pub error: u64,
+}
+
+impl Outcomes {
+    pub fn total(&self) -> u64 {
+        self.ok + self.error
+    }
+}
+
+/// Latencies counted into [`LATENCY_BUCKETS`], plus one bucket for slower ones
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Observations per bucket, not cumulative
+    counts: Vec<u64>,
+    sum_secs: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts.resize(LATENCY_BUCKETS.len() + 1, 0);
+        self.counts[bucket] += 1;
+        self.sum_secs += secs;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_secs_f64(self.sum_secs / count as f64))
+    }
+
+    /// Upper bound of the bucket holding the `quantile` (0.0 to 1.0) observation;
+    /// `None` when it is slower than every bound or nothing was observed
+    pub fn quantile_bound(&self, quantile: f64) -> Option<Duration> {
+        let rank = (self.count() as f64 * quantile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (count, bound) in self.counts.iter().zip(LATENCY_BUCKETS) {
+            seen += count;
+            if seen >= rank {
+                return Some(Duration::from_secs_f64(bound));
+            }
+        }
+        None
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        self.counts
+            .resize(self.counts.len().max(other.counts.len()), 0);
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.sum_secs += other.sum_secs;
+    }
+}
+
+/// Counters and latencies, as recorded by one process or totalled in the file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    /// Unix time of the first recorded value, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// Invocations by subcommand (`core`, `chat`, ...)
+    pub commands: BTreeMap<String, Outcomes>,
+    /// Requests answered by `eidos daemon`, by request type
+    pub daemon_requests: BTreeMap<String, Outcomes>,
+    /// Inference latency by operation (`generate`, `explain`, `alternatives`)
+    pub inference: BTreeMap<String, Histogram>,
+    /// Generated commands refused by the safety policy
+    pub safety_rejections: u64,
+    /// Failed chat and translation requests, by provider
+    pub provider_errors: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    /// The totals in `path`; empty when the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, serde_json::to_string(self)?.as_bytes())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Metrics::default()
+    }
+
+    /// Add the counts of `other` to these
+    pub fn merge(&mut self, other: &Metrics) {
+        self.since = match (self.since, other.since) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        for (name, outcomes) in &other.commands {
+            let total = self.commands.entry(name.clone()).or_default();
+            total.ok += outcomes.ok;
+            total.error += outcomes.error;
+        }
+        for (name, outcomes) in &other.daemon_requests {
+            let total = self.daemon_requests.entry(name.clone()).or_default();
+            total.ok += outcomes.ok;
+            total.error += outcomes.error;
+        }
+        for (op, histogram) in &other.inference {
+            self.inference
+                .entry(op.clone())
+                .or_default()
+                .merge(histogram);
+        }
+        self.safety_rejections += other.safety_rejections;
+        for (provider, errors) in &other.provider_errors {
+            *self.provider_errors.entry(provider.clone()).or_default() += errors;
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let outcomes =
+            |out: &mut String, name: &str, label: &str, values: &BTreeMap<String, Outcomes>| {
+                for (key, outcomes) in values {
+                    for (outcome, count) in [("ok", outcomes.ok), ("error", outcomes.error)] {
+                        let _ = writeln!(
+                            out,
+                            "{}{{{}=\"{}\",outcome=\"{}\"}} {}",
+                            name,
+                            label,
+                            escape(key),
+                            outcome,
+                            count
+                        );
+                    }
+                }
+            };
+
+        out.push_str("# HELP eidos_commands_total Invocations of eidos, by subcommand\n");
+        out.push_str("# TYPE eidos_commands_total counter\n");
+        outcomes(&mut out, "eidos_commands_total", "command", &self.commands);
+
+        out.push_str("# HELP eidos_daemon_requests_total Requests answered by eidos daemon\n");
+        out.push_str("# TYPE eidos_daemon_requests_total counter\n");
+        outcomes(
+            &mut out,
+            "eidos_daemon_requests_total",
+            "request",
+            &self.daemon_requests,
+        );
+
+        out.push_str("# HELP eidos_inference_seconds Time spent in model inference\n");
+        out.push_str("# TYPE eidos_inference_seconds histogram\n");
+        for (op, histogram) in &self.inference {
+            let op = escape(op);
+            let mut cumulative = 0;
+            for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+                cumulative += histogram.counts.get(i).copied().unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "eidos_inference_seconds_bucket{{op=\"{}\",le=\"{}\"}} {}",
+                    op, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "eidos_inference_seconds_bucket{{op=\"{}\",le=\"+Inf\"}} {}",
+                op,
+                histogram.count()
+            );
+            let _ = writeln!(
+                out,
+                "eidos_inference_seconds_sum{{op=\"{}\"}} {}",
+                op, histogram.sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "eidos_inference_seconds_count{{op=\"{}\"}} {}",
+                op,
+                histogram.count()
+            );
+        }
+
+        out.push_str(
+            "# HELP eidos_safety_rejections_total Generated commands refused by the safety policy\n",
+        );
+        out.push_str("# TYPE eidos_safety_rejections_total counter\n");
+        let _ = writeln!(
+            out,
+            "eidos_safety_rejections_total {}",
+            self.safety_rejections
+        );
+
+        out.push_str("# HELP eidos_provider_errors_total Failed chat and translation requests\n");
+        out.push_str("# TYPE eidos_provider_errors_total counter\n");
+        for (provider, errors) in &self.provider_errors {
+            let _ = writeln!(
+                out,
+                "eidos_provider_errors_total{{provider=\"{}\"}} {}",
+                escape(provider),
+                errors
+            );
+        }
+        out
+    }
+}
+
+/// Label value with the characters the exposition format reserves escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Record from now on (`[metrics] enabled`); off by default
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Add to this process's metrics, if they are enabled
+fn record(update: impl FnOnce(&mut Metrics)) {
+    if !enabled() {
+        return;
+    }
+    let mut pending = PENDING.lock();
+    pending.since.get_or_insert_with(lib_util::unix_now);
+    update(&mut pending);
+}
+
+/// Name the subcommand this invocation runs, to be counted by [`finish`]
+pub fn start_command(command: &str) {
+    *COMMAND.lock() = Some(command.to_string());
+}
+
+/// Count the invocation started with [`start_command`] and add what this process
+/// recorded to the totals in `path`
+pub fn finish(ok: bool, path: &Path) {
+    if let Some(command) = COMMAND.lock().take() {
+        record(|m| count(m.commands.entry(command).or_default(), ok));
+    }
+    flush(path);
+}
+
+/// Count one request answered by the daemon
+#[cfg(all(unix, feature = "serve"))]
+pub fn record_daemon_request(request: lib_bridge::Request, ok: bool) {
+    record(|m| {
+        count(
+            m.daemon_requests.entry(request.to_string()).or_default(),
+            ok,
+        )
+    });
+}
+
+/// Time one inference pass of kind `op`
+#[cfg(feature = "core")]
+pub fn record_inference(op: &str, elapsed: Duration) {
+    record(|m| {
+        m.inference
+            .entry(op.to_string())
+            .or_default()
+            .observe(elapsed)
+    });
+}
+
+/// Count generated commands refused by the safety policy
+#[cfg(feature = "core")]
+pub fn record_rejections(rejected: usize) {
+    record(|m| m.safety_rejections += rejected as u64);
+}
+
+/// Count one failed request to `provider` (`openai`, `libretranslate`, ...)
+#[cfg(any(feature = "chat", feature = "translate"))]
+pub fn record_provider_error(provider: &str) {
+    record(|m| *m.provider_errors.entry(provider.to_string()).or_default() += 1);
+}
+
+fn count(outcomes: &mut Outcomes, ok: bool) {
+    if ok {
+        outcomes.ok += 1;
+    } else {
+        outcomes.error += 1;
+    }
+}
+
+/// Add what this process recorded to the totals in `path`
+///
+/// Does nothing when nothing was recorded, so with metrics off the file is never
+/// created.
+pub fn flush(path: &Path) {
+    let pending = std::mem::take(&mut *PENDING.lock());
+    if pending.is_empty() {
+        return;
+    }
+    let saved = with_exclusive(path, || {
+        let mut totals = Metrics::load(path);
+        totals.merge(&pending);
+        totals.save(path)
+    });
+    if let Err(e) = saved {
+        debug!("Could not save metrics to {}: {}", path.display(), e);
+    }
+}
+
+/// The totals in `path` together with what this process has not flushed yet
+#[cfg(feature = "core")]
+pub fn snapshot(path: &Path) -> Metrics {
+    let mut totals =
+        crate::lockfile::with_shared(path, || Ok(Metrics::load(path))).unwrap_or_default();
+    totals.merge(&PENDING.lock());
+    totals
+}
+
+/// Forget every recorded value, and stop recording in this process so the file
+/// stays gone; true when there was a file to remove
+#[cfg(feature = "core")]
+pub fn reset(path: &Path) -> io::Result<bool> {
+    set_enabled(false);
+    *PENDING.lock() = Metrics::default();
+    with_exclusive(path, || match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Metrics {
+        let mut metrics = Metrics {
+            since: Some(1_000),
+            safety_rejections: 2,
+            ..Metrics::default()
+        };
+        count(
+            metrics.commands.entry("core".to_string()).or_default(),
+            true,
+        );
+        count(
+            metrics.commands.entry("core".to_string()).or_default(),
+            false,
+        );
+        let histogram = metrics.inference.entry("generate".to_string()).or_default();
+        histogram.observe(Duration::from_millis(80));
+        histogram.observe(Duration::from_millis(300));
+        histogram.observe(Duration::from_secs(20));
+        metrics.provider_errors.insert("ollama".to_string(), 1);
+        metrics
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let metrics = sample();
+        let histogram = &metrics.inference["generate"];
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(
+            histogram.quantile_bound(0.5),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(histogram.quantile_bound(0.95), None);
+        assert_eq!(Histogram::default().quantile_bound(0.5), None);
+        assert_eq!(Histogram::default().mean(), None);
+    }
+
+    #[test]
+    fn test_merge_adds_counts() {
+        let mut totals = Metrics::default();
+        totals.merge(&sample());
+        let mut later = sample();
+        later.since = Some(2_000);
+        totals.merge(&later);
+
+        assert_eq!(totals.since, Some(1_000));
+        assert_eq!(totals.commands["core"], Outcomes { ok: 2, error: 2 });
+        assert_eq!(totals.inference["generate"].count(), 6);
+        assert_eq!(totals.safety_rejections, 4);
+        assert_eq!(totals.provider_errors["ollama"], 2);
+    }
+
+    #[test]
+    fn test_prometheus_exposition() {
+        let text = sample().to_prometheus();
+        assert!(text.contains("# TYPE eidos_commands_total counter\n"));
+        assert!(text.contains("eidos_commands_total{command=\"core\",outcome=\"ok\"} 1\n"));
+        assert!(text.contains("eidos_inference_seconds_bucket{op=\"generate\",le=\"0.1\"} 1\n"));
+        assert!(text.contains("eidos_inference_seconds_bucket{op=\"generate\",le=\"10\"} 2\n"));
+        assert!(text.contains("eidos_inference_seconds_bucket{op=\"generate\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("eidos_inference_seconds_count{op=\"generate\"} 3\n"));
+        assert!(text.contains("eidos_safety_rejections_total 2\n"));
+        assert!(text.contains("eidos_provider_errors_total{provider=\"ollama\"} 1\n"));
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn test_totals_survive_a_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data/metrics.json");
+        assert!(Metrics::load(&path).is_empty());
+
+        sample().save(&path).unwrap();
+        assert_eq!(Metrics::load(&path), sample());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(Metrics::load(&path).is_empty());
+    }
+}
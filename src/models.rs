@@ -0,0 +1,523 @@
+// src/models.rs
+// Models downloaded by `eidos model pull`
+//
+// Each model gets a directory under ~/.local/share/eidos/models named after it,
+// holding the model file (ONNX or GGUF), its tokenizer and a `model.json` manifest
+// recording where both came from and their SHA-256 checksums. Files come from the
+// Hugging Face Hub (`owner/repo/file.gguf`, resolved against `$HF_ENDPOINT` when
+// set) or from any HTTP(S) URL.
+//
+// Downloads go to a `.part` file that is hashed while it is written and only
+// renamed into place once the checksum is right. The expected checksum is the one
+// passed on the command line, or for files the Hub stores in LFS (all model
+// weights) the SHA-256 it reports in the `X-Linked-Etag` header. Small files
+// without either are recorded with the checksum they arrived with.
+
+use lib_core::BackendKind;
+use reqwest::{header::HeaderMap, redirect, Client, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+/// Hugging Face Hub used when `HF_ENDPOINT` is not set
+pub const DEFAULT_HUB: &str = "https://huggingface.co";
+
+/// Manifest file in each model directory
+const MANIFEST: &str = "model.json";
+
+/// Tokenizer fetched from beside the model when none is named
+pub const DEFAULT_TOKENIZER: &str = "tokenizer.json";
+
+/// Where a file is downloaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A file in a Hugging Face Hub repository
+    Hub {
+        repo: String,
+        revision: String,
+        file: String,
+    },
+    /// Any other HTTP(S) URL
+    Url(String),
+}
+
+impl Source {
+    /// Parse `owner/repo[@revision]/path/file`, `hf:` followed by the same, or a URL
+    ///
+    /// Hub web links (`https://huggingface.co/owner/repo/blob/main/file`) are
+    /// read as Hub files, so their checksums are verified too.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if let Some(rest) = spec
+            .strip_prefix(DEFAULT_HUB)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            let rest = rest.split(['?', '#']).next().unwrap_or_default();
+            let parts: Vec<&str> = rest.splitn(5, '/').collect();
+            if let [owner, repo, "resolve" | "blob", revision, file] = parts[..] {
+                return Self::hub(&format!("{}/{}", owner, repo), revision, file);
+            }
+        }
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let source = Source::Url(spec.to_string());
+            return match source.file_name() {
+                "" => Err(format!("URL does not name a file: {}", spec)),
+                _ => Ok(source),
+            };
+        }
+        if spec.contains("://") {
+            return Err(format!("Only HTTP(S) URLs can be downloaded: {}", spec));
+        }
+
+        let spec = spec.strip_prefix("hf:").unwrap_or(spec);
+        let parts: Vec<&str> = spec.splitn(3, '/').collect();
+        match parts[..] {
+            [owner, repo, file] if !owner.is_empty() && !file.is_empty() => {
+                let (repo, revision) = repo.split_once('@').unwrap_or((repo, "main"));
+                Self::hub(&format!("{}/{}", owner, repo), revision, file)
+            }
+            [_, _] => Err(format!(
+                "Name the file to download from {}, e.g. {}/model.gguf",
+                spec, spec
+            )),
+            _ => Err(format!("Expected owner/repo/file or a URL, got '{}'", spec)),
+        }
+    }
+
+    fn hub(repo: &str, revision: &str, file: &str) -> Result<Self, String> {
+        let valid = |part: &str| !part.is_empty() && part != "." && part != "..";
+        if !repo.split('/').all(valid) || !valid(revision) || !file.split('/').all(valid) {
+            return Err(format!("Invalid Hub file: {}/{}", repo, file));
+        }
+        Ok(Source::Hub {
+            repo: repo.to_string(),
+            revision: revision.to_string(),
+            file: file.to_string(),
+        })
+    }
+
+    /// URL the file is fetched from
+    pub fn url(&self) -> String {
+        match self {
+            Source::Hub {
+                repo,
+                revision,
+                file,
+            } => format!("{}/{}/resolve/{}/{}", hub_endpoint(), repo, revision, file),
+            Source::Url(url) => url.clone(),
+        }
+    }
+
+    /// Name of the file, e.g. `codellama-7b.Q4_K_M.gguf`
+    pub fn file_name(&self) -> &str {
+        let path = match self {
+            Source::Hub { file, .. } => file.as_str(),
+            Source::Url(url) => {
+                let url = url.split(['?', '#']).next().unwrap_or_default();
+                let host_and_path = url.split_once("://").map_or(url, |(_, rest)| rest);
+                host_and_path.split_once('/').map_or("", |(_, path)| path)
+            }
+        };
+        match path.rsplit('/').next().unwrap_or_default() {
+            "." | ".." => "",
+            name => name,
+        }
+    }
+
+    /// The file called `name` in the same directory, e.g. the tokenizer beside a model
+    pub fn sibling(&self, name: &str) -> Source {
+        match self {
+            Source::Hub {
+                repo,
+                revision,
+                file,
+            } => Source::Hub {
+                repo: repo.clone(),
+                revision: revision.clone(),
+                file: match file.rsplit_once('/') {
+                    Some((dir, _)) => format!("{}/{}", dir, name),
+                    None => name.to_string(),
+                },
+            },
+            Source::Url(url) => {
+                let base = url.split(['?', '#']).next().unwrap_or_default();
+                let dir = base.rsplit_once('/').map_or(base, |(dir, _)| dir);
+                Source::Url(format!("{}/{}", dir, name))
+            }
+        }
+    }
+}
+
+/// Base URL of the Hugging Face Hub (`HF_ENDPOINT`, e.g. a mirror)
+fn hub_endpoint() -> String {
+    env::var("HF_ENDPOINT")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_HUB.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// A downloaded file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelFile {
+    /// File name inside the model directory
+    pub file: String,
+    /// URL it was downloaded from
+    pub url: String,
+    pub bytes: u64,
+    pub sha256: String,
+    /// Whether `sha256` was checked against a published or given checksum
+    pub verified: bool,
+}
+
+/// Manifest of a downloaded model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstalledModel {
+    pub name: String,
+    pub model: ModelFile,
+    pub tokenizer: ModelFile,
+    /// Seconds since the Unix epoch
+    pub downloaded: u64,
+}
+
+impl InstalledModel {
+    pub fn new(name: &str, model: ModelFile, tokenizer: ModelFile) -> Self {
+        Self {
+            name: name.to_string(),
+            model,
+            tokenizer,
+            downloaded: lib_util::unix_now(),
+        }
+    }
+
+    pub fn model_path(&self, models_dir: &Path) -> PathBuf {
+        models_dir.join(&self.name).join(&self.model.file)
+    }
+
+    pub fn tokenizer_path(&self, models_dir: &Path) -> PathBuf {
+        models_dir.join(&self.name).join(&self.tokenizer.file)
+    }
+
+    pub fn backend(&self) -> BackendKind {
+        BackendKind::from_path(&self.model.file)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.model.bytes + self.tokenizer.bytes
+    }
+}
+
+/// Check a model name, which becomes a directory name
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("Invalid model name: '{}'", name));
+    }
+    Ok(())
+}
+
+/// Name of a model directory for `source`: its file name without the extension
+pub fn default_name(source: &Source) -> String {
+    let file = source.file_name();
+    Path::new(file)
+        .file_stem()
+        .map_or(file, |stem| stem.to_str().unwrap_or(file))
+        .to_string()
+}
+
+/// The manifest of the model called `name`, if it is downloaded
+pub fn find(models_dir: &Path, name: &str) -> Result<Option<InstalledModel>, String> {
+    validate_name(name)?;
+    let path = models_dir.join(name).join(MANIFEST);
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Cannot read {}: {}", path.display(), e)),
+    }
+}
+
+/// Every downloaded model, by name; directories without a readable manifest
+/// (an interrupted first download) are skipped
+pub fn installed(models_dir: &Path) -> io::Result<Vec<InstalledModel>> {
+    let read_dir = match fs::read_dir(models_dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        result => result?,
+    };
+    let mut models = Vec::new();
+    for entry in read_dir {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Ok(Some(model)) = find(models_dir, &name) {
+            models.push(model);
+        }
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+/// Record `model` as downloaded
+pub fn save(models_dir: &Path, model: &InstalledModel) -> io::Result<()> {
+    let json = serde_json::to_vec_pretty(model)?;
+    lib_util::write_atomic(&models_dir.join(&model.name).join(MANIFEST), &json)
+}
+
+/// Delete the model called `name` with its files, returning whether it existed
+pub fn remove(models_dir: &Path, name: &str) -> Result<bool, String> {
+    validate_name(name)?;
+    let dir = models_dir.join(name);
+    match fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!("Cannot remove {}: {}", dir.display(), e)),
+    }
+}
+
+/// Fetches files, checking their checksums
+pub struct Downloader {
+    client: Client,
+    /// Reads Hub metadata from the first response, before any redirect to the
+    /// storage servers
+    metadata: Client,
+}
+
+impl Downloader {
+    /// Downloads give up when a connection takes longer than `connect_timeout`;
+    /// reading a multi-gigabyte file has no time limit
+    pub fn new(connect_timeout: Duration) -> Result<Self, String> {
+        let build = |policy| {
+            Client::builder()
+                .connect_timeout(connect_timeout)
+                .redirect(policy)
+                .user_agent(concat!("eidos/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .map_err(|e| format!("Failed to build HTTP client: {}", e))
+        };
+        Ok(Self {
+            client: build(redirect::Policy::default())?,
+            metadata: build(redirect::Policy::none())?,
+        })
+    }
+
+    /// Download `source` into `dir`, checking it against `sha256` or the Hub's
+    /// checksum; a file that does not match is deleted
+    pub async fn fetch(
+        &self,
+        source: &Source,
+        dir: &Path,
+        sha256: Option<&str>,
+    ) -> Result<ModelFile, String> {
+        let url = source.url();
+        let expected = match sha256 {
+            Some(sha256) => Some(parse_sha256(sha256)?),
+            None => self.published_sha256(source).await,
+        };
+
+        let mut response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .map_err(|e| format!("Download of {} failed: {}", url, e))?;
+        let file = source.file_name().to_string();
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        let part = dir.join(format!("{}.{}.part", file, process::id()));
+        let written = async {
+            let mut out = File::create(&part)?;
+            let mut hasher = Sha256::new();
+            let mut bytes = 0;
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+            {
+                hasher.update(&chunk);
+                out.write_all(&chunk)?;
+                bytes += chunk.len() as u64;
+            }
+            out.sync_all()?;
+            Ok::<_, io::Error>((bytes, hex(&hasher.finalize())))
+        }
+        .await;
+        let (bytes, actual) = written.map_err(|e| {
+            let _ = fs::remove_file(&part);
+            format!("Download of {} failed: {}", url, e)
+        })?;
+
+        if let Some(expected) = &expected {
+            if *expected != actual {
+                let _ = fs::remove_file(&part);
+                return Err(format!(
+                    "Checksum mismatch for {}: expected {}, got {}",
+                    file, expected, actual
+                ));
+            }
+        }
+        fs::rename(&part, dir.join(&file)).map_err(|e| {
+            let _ = fs::remove_file(&part);
+            format!("Cannot save {}: {}", file, e)
+        })?;
+        Ok(ModelFile {
+            file,
+            url,
+            bytes,
+            sha256: actual,
+            verified: expected.is_some(),
+        })
+    }
+
+    /// SHA-256 the Hub publishes for an LFS file; `None` for other sources and
+    /// for small files kept in git, whose ETag is a git hash
+    async fn published_sha256(&self, source: &Source) -> Option<String> {
+        if !matches!(source, Source::Hub { .. }) {
+            return None;
+        }
+        let response = self.metadata.head(source.url()).send().await.ok()?;
+        linked_sha256(response.headers())
+    }
+}
+
+fn linked_sha256(headers: &HeaderMap) -> Option<String> {
+    let etag = headers
+        .get("x-linked-etag")
+        .or_else(|| headers.get("etag"))?
+        .to_str()
+        .ok()?;
+    parse_sha256(etag.trim_start_matches("W/").trim_matches('"')).ok()
+}
+
+/// A SHA-256 in lowercase hex
+fn parse_sha256(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.len() == 64 && text.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(text.to_ascii_lowercase())
+    } else {
+        Err(format!("Not a SHA-256 checksum: '{}'", text))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_parse_sources() {
+        let source = Source::parse("TheBloke/CodeLlama-7B-GGUF/codellama-7b.Q4_K_M.gguf").unwrap();
+        assert_eq!(
+            source,
+            Source::Hub {
+                repo: "TheBloke/CodeLlama-7B-GGUF".to_string(),
+                revision: "main".to_string(),
+                file: "codellama-7b.Q4_K_M.gguf".to_string(),
+            }
+        );
+        assert_eq!(default_name(&source), "codellama-7b.Q4_K_M");
+        assert_eq!(
+            source.sibling("tokenizer.json").url(),
+            "https://huggingface.co/TheBloke/CodeLlama-7B-GGUF/resolve/main/tokenizer.json"
+        );
+
+        let source = Source::parse("hf:org/model@v1.0/onnx/model.onnx").unwrap();
+        assert_eq!(
+            source.url(),
+            "https://huggingface.co/org/model/resolve/v1.0/onnx/model.onnx"
+        );
+        assert_eq!(source.file_name(), "model.onnx");
+        assert_eq!(
+            source.sibling("tokenizer.json").url(),
+            "https://huggingface.co/org/model/resolve/v1.0/onnx/tokenizer.json"
+        );
+
+        // Web links to Hub files are Hub files
+        assert_eq!(
+            Source::parse("https://huggingface.co/org/model/blob/main/model.gguf").unwrap(),
+            Source::parse("org/model/model.gguf").unwrap()
+        );
+
+        let source = Source::parse("https://example.com/files/model.onnx?download=1").unwrap();
+        assert_eq!(source.file_name(), "model.onnx");
+        assert_eq!(
+            source.sibling("tokenizer.json"),
+            Source::Url("https://example.com/files/tokenizer.json".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_sources() {
+        for spec in [
+            "org/model",
+            "model.gguf",
+            "org/model/../secrets",
+            "ftp://example.com/model.gguf",
+            "https://example.com/",
+            "https://example.com",
+        ] {
+            assert!(Source::parse(spec).is_err(), "{}", spec);
+        }
+        assert!(Source::parse("org/model")
+            .unwrap_err()
+            .contains("org/model/model.gguf"));
+    }
+
+    #[test]
+    fn test_linked_sha256() {
+        let sha = "a".repeat(64);
+        let mut headers = HeaderMap::new();
+        // Files kept in git have a git hash as their ETag
+        headers.insert("etag", HeaderValue::from_static("\"0123456789abcdef\""));
+        assert_eq!(linked_sha256(&headers), None);
+        headers.insert(
+            "x-linked-etag",
+            HeaderValue::from_str(&format!("\"{}\"", sha.to_uppercase())).unwrap(),
+        );
+        assert_eq!(linked_sha256(&headers), Some(sha));
+        assert!(parse_sha256("abc").is_err());
+    }
+
+    #[test]
+    fn test_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(installed(&dir.path().join("missing")).unwrap().is_empty());
+
+        let file = |file: &str, bytes| ModelFile {
+            file: file.to_string(),
+            url: format!("https://example.com/{}", file),
+            bytes,
+            sha256: "0".repeat(64),
+            verified: true,
+        };
+        let model = InstalledModel::new(
+            "mistral",
+            file("mistral.Q4_K_M.gguf", 4000),
+            file("tokenizer.json", 100),
+        );
+        save(dir.path(), &model).unwrap();
+        // An interrupted first download has no manifest
+        fs::create_dir(dir.path().join("partial")).unwrap();
+
+        assert_eq!(installed(dir.path()).unwrap(), vec![model.clone()]);
+        assert_eq!(find(dir.path(), "mistral").unwrap(), Some(model.clone()));
+        assert_eq!(model.backend(), BackendKind::Gguf);
+        assert_eq!(model.bytes(), 4100);
+        assert_eq!(
+            model.model_path(dir.path()),
+            dir.path().join("mistral/mistral.Q4_K_M.gguf")
+        );
+        assert!(find(dir.path(), "../mistral").is_err());
+
+        assert!(remove(dir.path(), "mistral").unwrap());
+        assert!(!remove(dir.path(), "mistral").unwrap());
+        assert_eq!(find(dir.path(), "mistral").unwrap(), None);
+    }
+}
@@ -0,0 +1,2896 @@
+// src/output.rs
+// Single output sink for the CLI
+//
+// Machine-consumable output (generated commands, JSON) goes to stdout so it can be
+// captured with `$(eidos core ...)` or piped into other tools. Everything meant for
+// a human reader (errors, warnings, tips, progress) goes to stderr. No other module
+// should call println!/eprintln! directly.
+//
+// Handlers describe their results with the structs below and hand them to `emit`,
+// which renders them as text, JSON or raw values depending on the global `--output`
+// flag. Advice for the user after a failure goes through `Hints`, so `--no-tips`
+// (or `[ui] tips = false`) can silence it in one place.
+
+#[cfg(feature = "core")]
+use crate::history::HistoryEntry;
+#[cfg(any(feature = "chat", feature = "translate"))]
+use crate::legacy_env::{Setting, Skipped};
+#[cfg(feature = "core")]
+use crate::metrics::{Metrics, Outcomes};
+#[cfg(feature = "core")]
+use crate::run_safe::CommandRun;
+#[cfg(feature = "chat")]
+use crate::terminal::render_markdown;
+use crate::terminal::Style;
+use lazy_static::lazy_static;
+#[cfg(feature = "chat")]
+use lib_chat::retrieval::Citation;
+#[cfg(feature = "chat")]
+use lib_chat::session::Session;
+#[cfg(feature = "core")]
+use lib_core::{
+    Accelerator, AuditFinding, CommandPart, CommandPreview, EvalReport, SafetyReport, Severity,
+};
+use lib_errors::ErrorReport;
+use parking_lot::Mutex;
+use serde::Serialize;
+#[cfg(feature = "core")]
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::str::FromStr;
+#[cfg(feature = "core")]
+use std::time::Duration;
+
+/// Rendering of a command's primary result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A single JSON object on stdout
+    Json,
+    /// Just the bare value (e.g. the command), for shell widgets and scripts
+    Raw,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "raw" => Ok(OutputFormat::Raw),
+            other => Err(format!(
+                "Unknown output format '{}' (expected text, json or raw)",
+                other
+            )),
+        }
+    }
+}
+
+/// A result that can be emitted in every output format
+pub trait Emit: Serialize + Display {
+    /// The bare value printed for `--output raw`; the text rendering by default
+    fn raw(&self) -> String {
+        self.to_string()
+    }
+
+    /// Text rendering with ANSI colours, used on a terminal; plain text by default
+    fn colored(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Where references to man pages (`tar(1)`, `man 5 crontab`) in text results
+/// link to on terminals with hyperlinks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ManLinks {
+    /// manpages.debian.org, opened in the browser
+    #[default]
+    Web,
+    /// `man:` URIs, opened by the desktop's help viewer
+    Man,
+}
+
+impl FromStr for ManLinks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "web" => Ok(ManLinks::Web),
+            "man" => Ok(ManLinks::Man),
+            other => Err(format!(
+                "Unknown man page links '{}' (expected web or man)",
+                other
+            )),
+        }
+    }
+}
+
+/// A man page or tldr page mentioned in text
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Reference {
+    page: String,
+    section: Option<String>,
+    tldr: bool,
+}
+
+impl Reference {
+    fn url(&self, links: ManLinks) -> String {
+        match (self.tldr, links, &self.section) {
+            (true, _, _) => format!("https://tldr.inbrowser.app/pages/common/{}", self.page),
+            (false, ManLinks::Web, Some(section)) => {
+                format!("https://manpages.debian.org/{}.{}", self.page, section)
+            }
+            (false, ManLinks::Web, None) => format!("https://manpages.debian.org/{}", self.page),
+            (false, ManLinks::Man, Some(section)) => format!("man:{}({})", self.page, section),
+            (false, ManLinks::Man, None) => format!("man:{}", self.page),
+        }
+    }
+}
+
+/// `text` with references to man pages (`tar(1)`, `man tar`, `man 5 crontab`) and
+/// tldr pages (`tldr tar`) turned into OSC 8 hyperlinks
+///
+/// Terminals without hyperlinks would print the escape sequences, so the sink only
+/// does this when told the terminal has them (see [`terminal_supports_hyperlinks`]).
+pub fn hyperlink_references(text: &str, links: ManLinks) -> String {
+    let words: Vec<(usize, &str)> = text
+        .split_whitespace()
+        .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+        .collect();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < words.len() {
+        let Some((start, end, reference, used)) = reference_at(&words[i..]) else {
+            i += 1;
+            continue;
+        };
+        out.push_str(&text[copied..start]);
+        let url = reference.url(links);
+        out.push_str(&format!(
+            "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\",
+            url,
+            &text[start..end]
+        ));
+        copied = end;
+        i += used;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// The reference starting at the first of `words` (offset, word): its byte range
+/// in the text and the number of words it spans
+fn reference_at(words: &[(usize, &str)]) -> Option<(usize, usize, Reference, usize)> {
+    let (offset, word) = words[0];
+    let leading = word.len() - word.trim_start_matches(['(', '"', '\'', '`']).len();
+    let core = word[leading..].trim_end_matches(['.', ',', ';', ':', '!', '?', '"', '\'', '`']);
+    let start = offset + leading;
+
+    // tar(1), crontab(5)
+    if let Some((page, rest)) = core.split_once('(') {
+        let section = &rest[..rest.find(')')?];
+        if !is_page(page) || !is_section(section) {
+            return None;
+        }
+        let end = start + page.len() + section.len() + 2;
+        let reference = Reference {
+            page: page.to_string(),
+            section: Some(section.to_string()),
+            tldr: false,
+        };
+        return Some((start, end, reference, 1));
+    }
+
+    // man tar, man 5 crontab, tldr tar
+    let core = core.trim_end_matches(')');
+    if core != "man" && core != "tldr" {
+        return None;
+    }
+    let tldr = core == "tldr";
+    let trim = |word: &'_ str| -> String {
+        word.trim_end_matches(['.', ',', ';', ':', '!', '?', '"', '\'', '`', ')'])
+            .to_string()
+    };
+    let (section, page_index) = match words.get(1).map(|(_, w)| trim(w)) {
+        Some(section) if !tldr && is_section(&section) => (Some(section), 2),
+        _ => (None, 1),
+    };
+    let (page_offset, page_word) = *words.get(page_index)?;
+    let page = trim(page_word);
+    // "the man page for tar" is prose, not a reference
+    if !is_page(&page) || NOT_PAGES.contains(&page.as_str()) {
+        return None;
+    }
+    let reference = Reference {
+        page: page.clone(),
+        section,
+        tldr,
+    };
+    Some((start, page_offset + page.len(), reference, page_index + 1))
+}
+
+/// Words after "man" or "tldr" in prose that are not page names
+const NOT_PAGES: &[&str] = &["page", "pages", "entry", "for", "and", "or", "of", "the"];
+
+/// Whether `name` can be a man page name (`tar`, `git-log`, `systemd.unit`)
+fn is_page(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'))
+}
+
+/// Whether `section` is a man page section (`1`, `3p`, `8`)
+fn is_section(section: &str) -> bool {
+    section.len() <= 4
+        && section.starts_with(|c: char| c.is_ascii_digit())
+        && section[1..].chars().all(|c| c.is_ascii_lowercase())
+}
+
+/// Whether the terminal described by the environment (`var` looks up a variable)
+/// renders OSC 8 hyperlinks
+///
+/// `FORCE_HYPERLINK=1` or `=0` overrides the guess, which is based on the variables
+/// the terminals known to support hyperlinks set.
+pub fn terminal_supports_hyperlinks(var: impl Fn(&str) -> Option<String>) -> bool {
+    if let Some(force) = var("FORCE_HYPERLINK") {
+        return !matches!(force.trim(), "0" | "false" | "");
+    }
+    if [
+        "WT_SESSION",
+        "KITTY_WINDOW_ID",
+        "KONSOLE_VERSION",
+        "DOMTERM",
+    ]
+    .iter()
+    .any(|name| var(name).is_some())
+    {
+        return true;
+    }
+    // GNOME Terminal, Tilix and other VTE terminals since 0.50
+    if var("VTE_VERSION")
+        .and_then(|v| v.parse::<u32>().ok())
+        .is_some_and(|v| v >= 5000)
+    {
+        return true;
+    }
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    let term = var("TERM").unwrap_or_default();
+    matches!(
+        program.as_str(),
+        "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper"
+    ) || matches!(
+        term.as_str(),
+        "xterm-kitty" | "alacritty" | "foot" | "wezterm" | "xterm-ghostty"
+    )
+}
+
+/// How one whitespace-separated token differs between two commands
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenDiff<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// Token-level diff turning `from` into `to`, in the order of `to`
+///
+/// Based on the longest common subsequence of tokens; a removed token is listed
+/// before the token added in its place.
+#[cfg(feature = "core")]
+pub fn token_diff<'a>(from: &'a str, to: &'a str) -> Vec<TokenDiff<'a>> {
+    let a: Vec<&str> = from.split_whitespace().collect();
+    let b: Vec<&str> = to.split_whitespace().collect();
+
+    // lcs[i][j]: length of the common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::with_capacity(a.len().max(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            diff.push(TokenDiff::Same(b[j]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(TokenDiff::Removed(a[i]));
+            i += 1;
+        } else {
+            diff.push(TokenDiff::Added(b[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// A generated shell command, optionally with an explanation
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedCommand {
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    /// Command reversing this one, when one is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undo: Option<String>,
+    /// Id in the command history (`eidos history`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_id: Option<u64>,
+    /// Output of running the command with `--run-safe`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<CommandRun>,
+    /// Answer to the prompt summarized from `run` (`--summarize-output`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    /// The command with its `{name}` placeholders, before the `--var` values
+    /// were filled in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// The command passed the review safety level as a pipeline (quotes, globs
+    /// or `|`) and should be read before it is run
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub needs_review: bool,
+    /// What the command would touch (`--preview`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<CommandPreview>,
+}
+
+#[cfg(feature = "core")]
+impl GeneratedCommand {
+    pub fn new(command: impl Into<String>, explanation: Option<String>) -> Self {
+        Self {
+            command: command.into(),
+            explanation,
+            undo: None,
+            history_id: None,
+            run: None,
+            answer: None,
+            template: None,
+            needs_review: false,
+            preview: None,
+        }
+    }
+
+    pub fn with_template(mut self, template: Option<String>) -> Self {
+        self.template = template;
+        self
+    }
+
+    pub fn with_review(mut self, needs_review: bool) -> Self {
+        self.needs_review = needs_review;
+        self
+    }
+}
+
+/// Why a command passed only at the review safety level
+#[cfg(feature = "core")]
+pub const NEEDS_REVIEW: &str = "quotes, globs or pipes were checked stage by stage";
+
+/// Result of `eidos core`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandResult {
+    pub prompt: String,
+    /// Number of alternatives requested (1 for a single command)
+    pub requested: usize,
+    /// Commands that passed safety validation
+    pub commands: Vec<GeneratedCommand>,
+    /// Safety reports for generated commands that were withheld
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<SafetyReport>,
+    /// How a non-English prompt was translated (`eidos ask`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<PromptTranslation>,
+    /// How long generation took, recorded in the history
+    #[serde(skip)]
+    pub latency: Option<Duration>,
+}
+
+/// A prompt translated to English before generating from it
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTranslation {
+    /// Language detected in the prompt
+    pub source_lang: String,
+    /// Its display name, e.g. `Turkish / Türkçe`
+    pub source_lang_name: String,
+    /// The English prompt the command was generated from
+    pub english_prompt: String,
+}
+
+#[cfg(feature = "core")]
+impl CommandResult {
+    /// Result holding one generated command
+    pub fn single(prompt: String, command: String, explanation: Option<String>) -> Self {
+        Self {
+            prompt,
+            requested: 1,
+            commands: vec![GeneratedCommand::new(command, explanation)],
+            rejected: Vec::new(),
+            translation: None,
+            latency: None,
+        }
+    }
+}
+
+#[cfg(feature = "core")]
+impl CommandResult {
+    fn write_text(&self, f: &mut impl fmt::Write, color: bool) -> fmt::Result {
+        if self.requested <= 1 {
+            if let Some(generated) = self.commands.first() {
+                write!(f, "{}", Style::Command.apply(&generated.command, color))?;
+                if let Some(explanation) = &generated.explanation {
+                    write!(f, "\n\nExplanation: {}", explanation)?;
+                }
+                // The answer replaces the output it was summarized from;
+                // JSON keeps both
+                match (&generated.answer, &generated.run) {
+                    (Some(answer), Some(run)) => {
+                        let lines = run.stdout.lines().count();
+                        write!(
+                            f,
+                            "\n\n{}\n\n[summarized from {} lines of output]",
+                            answer, lines
+                        )?;
+                    }
+                    (_, Some(run)) => write!(f, "\n\n{}", run)?,
+                    _ => {}
+                }
+            }
+            return Ok(());
+        }
+
+        // Duplicates and commands failing validation leave fewer than requested
+        match self.commands.len() {
+            n if n < self.requested => {
+                write!(f, "Generated {} of {} alternatives:", n, self.requested)?
+            }
+            n => write!(f, "Generated {} alternatives:", n)?,
+        }
+        let primary = self.commands.first().map(|c| c.command.as_str());
+        for (i, generated) in self.commands.iter().enumerate() {
+            match primary.filter(|p| i > 0 && same_program(p, &generated.command)) {
+                Some(primary) => {
+                    let diff = token_diff(primary, &generated.command);
+                    write!(f, "\n  {}. {}", i + 1, highlight_added(&diff, color))?;
+                    if let Some(changes) = describe_changes(&diff, color) {
+                        write!(f, "\n     ± vs 1: {}", changes)?;
+                    }
+                }
+                None => write!(f, "\n  {}. {}", i + 1, generated.command)?,
+            }
+            if let Some(explanation) = &generated.explanation {
+                write!(f, "\n     → {}", explanation)?;
+            }
+            if let Some(template) = &generated.template {
+                write!(f, "\n     template: {}", template)?;
+            }
+            if generated.needs_review {
+                let review = format!("⚠ needs review: {}", NEEDS_REVIEW);
+                write!(f, "\n     {}", Style::Warning.apply(&review, color))?;
+            }
+            if let Some(undo) = &generated.undo {
+                write!(f, "\n     ↩ undo: {}", undo)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "core")]
+impl Display for CommandResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_text(f, false)
+    }
+}
+
+/// Whether two commands run the same program, so a token diff is meaningful
+#[cfg(feature = "core")]
+fn same_program(a: &str, b: &str) -> bool {
+    let program = |cmd: &str| cmd.split_whitespace().next().map(str::to_string);
+    program(a).is_some() && program(a) == program(b)
+}
+
+/// The new command, with the tokens not in the primary one highlighted
+#[cfg(feature = "core")]
+fn highlight_added(diff: &[TokenDiff], color: bool) -> String {
+    let tokens: Vec<String> = diff
+        .iter()
+        .filter_map(|token| match *token {
+            TokenDiff::Same(t) => Some(t.to_string()),
+            TokenDiff::Added(t) => Some(Style::Added.apply(t, color)),
+            TokenDiff::Removed(_) => None,
+        })
+        .collect();
+    tokens.join(" ")
+}
+
+/// `adds -a, drops -l`, or `None` when the commands are identical
+#[cfg(feature = "core")]
+fn describe_changes(diff: &[TokenDiff], color: bool) -> Option<String> {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for token in diff {
+        match *token {
+            TokenDiff::Added(t) => added.push(t),
+            TokenDiff::Removed(t) => removed.push(t),
+            TokenDiff::Same(_) => {}
+        }
+    }
+
+    let mut changes = Vec::new();
+    if !added.is_empty() {
+        changes.push(format!(
+            "adds {}",
+            Style::Added.apply(&added.join(" "), color)
+        ));
+    }
+    if !removed.is_empty() {
+        changes.push(format!(
+            "drops {}",
+            Style::Removed.apply(&removed.join(" "), color)
+        ));
+    }
+    (!changes.is_empty()).then(|| changes.join(", "))
+}
+
+#[cfg(feature = "core")]
+impl Emit for CommandResult {
+    /// One command per line, without explanations
+    fn raw(&self) -> String {
+        let commands: Vec<&str> = self.commands.iter().map(|c| c.command.as_str()).collect();
+        commands.join("\n")
+    }
+
+    /// Alternatives with the tokens that differ from the first one highlighted
+    fn colored(&self) -> String {
+        let mut text = String::new();
+        let _ = self.write_text(&mut text, true);
+        text
+    }
+}
+
+/// One prompt of `eidos core --batch`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchEntry {
+    #[serde(flatten)]
+    pub result: CommandResult,
+    /// Why no command was generated (invalid prompt, inference failure)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Generation time, for comparing models on the same prompts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+#[cfg(feature = "core")]
+impl BatchEntry {
+    /// Entry for a prompt that produced no command
+    pub fn failed(prompt: String, error: String) -> Self {
+        Self {
+            result: CommandResult {
+                prompt,
+                requested: 1,
+                commands: Vec::new(),
+                rejected: Vec::new(),
+                translation: None,
+                latency: None,
+            },
+            error: Some(error),
+            latency_ms: None,
+        }
+    }
+
+    /// The command that passed the safety checks, if any
+    pub fn command(&self) -> Option<&str> {
+        self.result.commands.first().map(|c| c.command.as_str())
+    }
+}
+
+/// Result of `eidos core --batch`: one entry per prompt, in input order
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct BatchResult {
+    pub entries: Vec<BatchEntry>,
+}
+
+#[cfg(feature = "core")]
+impl Display for BatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            writeln!(f, "{}. {}", i + 1, entry.result.prompt)?;
+            match (entry.command(), entry.result.rejected.first(), &entry.error) {
+                (Some(command), _, _) => {
+                    writeln!(f, "   {}", command)?;
+                    if let Some(explanation) = &entry.result.commands[0].explanation {
+                        writeln!(f, "   → {}", explanation)?;
+                    }
+                    if entry.result.commands[0].needs_review {
+                        writeln!(f, "   ⚠ needs review: {}", NEEDS_REVIEW)?;
+                    }
+                }
+                (None, Some(report), _) => {
+                    let reason = report
+                        .primary()
+                        .map(|violation| violation.to_string())
+                        .unwrap_or_default();
+                    writeln!(f, "   ✗ rejected: {} ({})", report.command, reason)?;
+                }
+                (None, None, error) => {
+                    writeln!(f, "   ✗ {}", error.as_deref().unwrap_or("no command"))?;
+                }
+            }
+        }
+        let safe = self
+            .entries
+            .iter()
+            .filter(|entry| entry.command().is_some())
+            .count();
+        write!(
+            f,
+            "{} of {} prompts produced a safe command",
+            safe,
+            self.entries.len()
+        )
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for BatchResult {
+    /// One line per prompt: the command, or an empty line when there is none
+    fn raw(&self) -> String {
+        let commands: Vec<&str> = self
+            .entries
+            .iter()
+            .map(|entry| entry.command().unwrap_or_default())
+            .collect();
+        commands.join("\n")
+    }
+}
+
+/// Result of `eidos eval`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalResult {
+    pub dataset: String,
+    /// Where the full report was written (`--report`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    #[serde(flatten)]
+    pub evaluation: EvalReport,
+}
+
+/// Misses listed by the text rendering of [`EvalResult`]
+#[cfg(feature = "core")]
+const EVAL_MISSES_SHOWN: usize = 10;
+
+#[cfg(feature = "core")]
+impl Display for EvalResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = &self.evaluation.summary;
+        let percent = |share: f64| format!("{:.1}%", share * 100.0);
+        writeln!(f, "Evaluated {} cases from {}", summary.cases, self.dataset)?;
+        writeln!(f, "  Exact match:       {}", percent(summary.exact))?;
+        writeln!(f, "  Normalized match:  {}", percent(summary.normalized))?;
+        writeln!(f, "  BLEU:              {:.3}", summary.bleu)?;
+        writeln!(
+            f,
+            "  Rejected:          {} of generated commands",
+            percent(summary.rejected)
+        )?;
+        writeln!(f, "  Errors:            {}", summary.errors)?;
+        write!(f, "  Mean latency:      {} ms", summary.mean_latency_ms)?;
+
+        let misses = self.evaluation.misses();
+        if !misses.is_empty() {
+            write!(f, "\n\nMisses, worst first:")?;
+        }
+        for record in misses.iter().take(EVAL_MISSES_SHOWN) {
+            write!(f, "\n  {}", record.prompt)?;
+            write!(f, "\n    expected: {}", record.expected)?;
+            match (&record.generated, &record.error) {
+                (Some(generated), _) => {
+                    write!(f, "\n    got:      {}", generated)?;
+                    write!(f, "  (BLEU {:.2})", record.scores.bleu)?;
+                }
+                (None, error) => {
+                    write!(
+                        f,
+                        "\n    failed:   {}",
+                        error.as_deref().unwrap_or_default()
+                    )?;
+                }
+            }
+            if let Some(violation) = &record.rejected {
+                write!(f, "\n    rejected: {}", violation)?;
+            }
+        }
+        if misses.len() > EVAL_MISSES_SHOWN {
+            write!(f, "\n  ...and {} more", misses.len() - EVAL_MISSES_SHOWN)?;
+        }
+        if let Some(report) = &self.report {
+            write!(f, "\n\nFull report: {}", report)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for EvalResult {}
+
+/// One row of `eidos bench`
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchMeasurement {
+    pub name: String,
+    /// `None` when the measurement was skipped
+    pub value: Option<f64>,
+    pub unit: String,
+    /// What was measured, or why it was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl BenchMeasurement {
+    pub fn new(name: impl Into<String>, value: f64, unit: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: Some(value),
+            unit: unit.into(),
+            detail: None,
+        }
+    }
+
+    /// A measurement that could not be taken, and why
+    pub fn skipped(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: None,
+            unit: String::new(),
+            detail: Some(reason.into()),
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// The value with its unit, to a precision that suits its size
+    fn reading(&self) -> String {
+        match self.value {
+            Some(value) if value >= 100.0 => format!("{:.0} {}", value, self.unit),
+            Some(value) if value >= 10.0 => format!("{:.1} {}", value, self.unit),
+            Some(value) => format!("{:.2} {}", value, self.unit),
+            None => "-".to_string(),
+        }
+    }
+}
+
+/// Result of `eidos bench`
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub measurements: Vec<BenchMeasurement>,
+}
+
+impl Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.measurements.is_empty() {
+            return write!(f, "Nothing to measure");
+        }
+        let readings: Vec<String> = self.measurements.iter().map(|m| m.reading()).collect();
+        let name_width = self.measurements.iter().map(|m| m.name.len()).max();
+        let name_width = name_width.unwrap_or_default().max("Measurement".len());
+        let value_width = readings.iter().map(String::len).max();
+        let value_width = value_width.unwrap_or_default().max("Result".len());
+
+        write!(
+            f,
+            "{:<name_width$}  {:<value_width$}  Detail",
+            "Measurement", "Result"
+        )?;
+        for (measurement, reading) in self.measurements.iter().zip(&readings) {
+            let line = format!(
+                "{:<name_width$}  {:<value_width$}  {}",
+                measurement.name,
+                reading,
+                measurement.detail.as_deref().unwrap_or_default()
+            );
+            write!(f, "\n{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl Emit for BenchResult {}
+
+/// Result of `eidos doctor`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorResult {
+    /// Configured backend, or why the setting is invalid
+    pub backend: String,
+    pub model_path: String,
+    /// Why the model or tokenizer cannot be loaded, if so
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_error: Option<String>,
+    /// Device requested in the configuration
+    pub device: String,
+    /// Device GGUF models would run on
+    pub active_device: String,
+    /// Why the requested device is not used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_fallback: Option<String>,
+    /// Accelerators supported by this build (GGUF backend only)
+    pub accelerators: Vec<Accelerator>,
+}
+
+#[cfg(feature = "core")]
+impl Display for DoctorResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Backend:  {}", self.backend)?;
+        match &self.model_error {
+            Some(e) => writeln!(f, "Model:    {} ({})", self.model_path, e)?,
+            None => writeln!(f, "Model:    {} (ok)", self.model_path)?,
+        }
+        write!(f, "Device:   {} -> {}", self.device, self.active_device)?;
+        if let Some(fallback) = &self.device_fallback {
+            write!(f, "\n          {}", fallback)?;
+        }
+        if !self.accelerators.is_empty() {
+            write!(f, "\nAccelerators:")?;
+        }
+        for accelerator in &self.accelerators {
+            let status = match (accelerator.compiled, &accelerator.error) {
+                (false, _) => format!("not built (enable the `{}` feature)", accelerator.name),
+                (true, Some(e)) => format!("not available: {}", e),
+                (true, None) => "available".to_string(),
+            };
+            write!(f, "\n  {:<6} {}", accelerator.name, status)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for DoctorResult {}
+
+/// Result of `eidos explain`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainResult {
+    pub command: String,
+    /// Prose explanation, when a model or chat provider was available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    /// Where the explanation came from: `model` or `chat`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Each program in the command with its options
+    pub parts: Vec<CommandPart>,
+}
+
+#[cfg(feature = "core")]
+impl ExplainResult {
+    /// Each program with its options, one block per program; programs without
+    /// options are left out unless `all`
+    fn breakdown(&self, all: bool) -> String {
+        let blocks: Vec<String> = self
+            .parts
+            .iter()
+            .filter(|part| all || !part.flags.is_empty())
+            .map(|part| {
+                let sudo = if part.sudo { "sudo " } else { "" };
+                let mut block = format!("{}{}", sudo, part.program);
+                for flag in &part.flags {
+                    let name = match &flag.value {
+                        Some(value) => format!("{} {}", flag.flag, value),
+                        None => flag.flag.clone(),
+                    };
+                    match &flag.description {
+                        Some(description) => {
+                            block.push_str(&format!("\n  {:<16} {}", name, description))
+                        }
+                        None => block.push_str(&format!("\n  {}", name)),
+                    }
+                }
+                block
+            })
+            .collect();
+        blocks.join("\n\n")
+    }
+}
+
+#[cfg(feature = "core")]
+impl Display for ExplainResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)?;
+        if let Some(explanation) = &self.explanation {
+            write!(f, "\n\n{}", explanation)?;
+        }
+        let breakdown = self.breakdown(false);
+        if !breakdown.is_empty() {
+            write!(f, "\n\n{}", breakdown)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for ExplainResult {
+    /// The prose explanation, or the breakdown of every program when there is none
+    fn raw(&self) -> String {
+        match &self.explanation {
+            Some(explanation) => explanation.clone(),
+            None => self.breakdown(true),
+        }
+    }
+}
+
+/// Result of `eidos history`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryResult {
+    pub entries: Vec<HistoryEntry>,
+}
+
+#[cfg(feature = "core")]
+impl Display for HistoryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.entries.iter().map(ToString::to_string).collect();
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for HistoryResult {}
+
+/// Result of `eidos history undo <id>`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoResult {
+    pub id: u64,
+    pub command: String,
+    pub undo: String,
+}
+
+#[cfg(feature = "core")]
+impl Display for UndoResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.undo)
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for UndoResult {}
+
+/// Result of `eidos audit-script`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditResult {
+    pub file: String,
+    /// Number of commands found in the script
+    pub commands: usize,
+    pub findings: Vec<AuditFinding>,
+}
+
+#[cfg(feature = "core")]
+impl AuditResult {
+    /// The findings as a SARIF 2.1.0 log, for code scanning dashboards
+    pub fn sarif(&self) -> serde_json::Value {
+        let results: Vec<serde_json::Value> = self
+            .findings
+            .iter()
+            .flat_map(|finding| {
+                finding.violations.iter().map(move |violation| {
+                    let level = match violation.severity {
+                        Severity::Critical => "error",
+                        Severity::High => "warning",
+                        _ => "note",
+                    };
+                    serde_json::json!({
+                        "ruleId": serde_json::to_value(violation.rule).unwrap_or_default(),
+                        "level": level,
+                        "message": {
+                            "text": format!(
+                                "{}: {}",
+                                violation,
+                                lib_core::audit::advice(violation.rule)
+                            ),
+                        },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": self.file },
+                                "region": {
+                                    "startLine": finding.line,
+                                    "snippet": { "text": finding.command },
+                                },
+                            },
+                        }],
+                    })
+                })
+            })
+            .collect();
+        let rules: Vec<serde_json::Value> = lib_core::audit::AUDIT_RULES
+            .iter()
+            .map(|rule| {
+                serde_json::json!({
+                    "id": serde_json::to_value(rule).unwrap_or_default(),
+                    "shortDescription": { "text": rule.description() },
+                    "fullDescription": { "text": lib_core::audit::advice(*rule) },
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "eidos",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        })
+    }
+}
+
+#[cfg(feature = "core")]
+impl AuditResult {
+    /// Findings with critical and high violations in red, the others in yellow,
+    /// and a clean script in green when `color` is on
+    fn write_text(&self, f: &mut impl fmt::Write, color: bool) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{}:{}: {}", self.file, finding.line, finding.command)?;
+            for violation in &finding.violations {
+                let style = match violation.severity {
+                    Severity::Critical | Severity::High => Style::Danger,
+                    _ => Style::Warning,
+                };
+                writeln!(
+                    f,
+                    "  {}: {}",
+                    style.apply(&violation.to_string(), color),
+                    lib_core::audit::advice(violation.rule)
+                )?;
+            }
+        }
+        if self.findings.is_empty() {
+            let clean = format!(
+                "No risky commands in {} ({} checked)",
+                self.file, self.commands
+            );
+            write!(f, "{}", Style::Safe.apply(&clean, color))
+        } else {
+            write!(
+                f,
+                "{} risky of {} commands in {}",
+                self.findings.len(),
+                self.commands,
+                self.file
+            )
+        }
+    }
+}
+
+#[cfg(feature = "core")]
+impl Display for AuditResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_text(f, false)
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for AuditResult {
+    fn colored(&self) -> String {
+        let mut text = String::new();
+        let _ = self.write_text(&mut text, true);
+        text
+    }
+}
+
+/// Result of `eidos chat`
+#[cfg(feature = "chat")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatResult {
+    pub reply: String,
+    /// Notes the reply was grounded in (`--with-index`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    /// Excerpts of `sources` the reply cites
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+}
+
+#[cfg(feature = "chat")]
+impl Display for ChatResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Assistant: {}", self.reply)
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Emit for ChatResult {
+    fn raw(&self) -> String {
+        self.reply.clone()
+    }
+
+    /// The reply with its Markdown bold, code and headings rendered
+    fn colored(&self) -> String {
+        format!("Assistant: {}", render_markdown(&self.reply))
+    }
+}
+
+/// Result of `eidos translate`
+#[cfg(feature = "translate")]
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationResultOutput {
+    pub source_lang: String,
+    /// Display name of the source language, e.g. `Turkish / Türkçe`
+    pub source_lang_name: String,
+    pub target_lang: String,
+    pub target_lang_name: String,
+    pub original: String,
+    pub translated: String,
+    pub was_translated: bool,
+    /// Confidence in the detected source language (0.0 to 1.0); absent with
+    /// `--from`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+#[cfg(feature = "translate")]
+impl Display for TranslationResultOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.confidence {
+            Some(confidence) => writeln!(
+                f,
+                "Detected language: {} ({}), {:.0}% confidence",
+                self.source_lang_name,
+                self.source_lang,
+                confidence * 100.0
+            )?,
+            None => writeln!(
+                f,
+                "Source language: {} ({})",
+                self.source_lang_name, self.source_lang
+            )?,
+        }
+        if self.was_translated {
+            writeln!(f, "Original ({}): {}", self.source_lang_name, self.original)?;
+            write!(
+                f,
+                "Translated ({}): {}",
+                self.target_lang_name, self.translated
+            )
+        } else {
+            writeln!(f, "Text is already in {}", self.target_lang_name)?;
+            write!(f, "Text: {}", self.original)
+        }
+    }
+}
+
+#[cfg(feature = "translate")]
+impl Emit for TranslationResultOutput {
+    fn raw(&self) -> String {
+        if self.was_translated {
+            self.translated.clone()
+        } else {
+            self.original.clone()
+        }
+    }
+}
+
+/// Result of `eidos summarize`
+#[cfg(feature = "chat")]
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryResult {
+    pub source: String,
+    pub length: String,
+    pub summary: String,
+}
+
+#[cfg(feature = "chat")]
+impl Display for SummaryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.summary)
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Emit for SummaryResult {}
+
+/// Result of `eidos index`
+#[cfg(feature = "chat")]
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexResult {
+    pub name: String,
+    pub path: String,
+    pub chunks: usize,
+    pub sources: usize,
+    pub embedder: String,
+}
+
+#[cfg(feature = "chat")]
+impl Display for IndexResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Emit for IndexResult {}
+
+/// A saved chat session, as listed by `eidos sessions`
+#[cfg(feature = "chat")]
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub name: String,
+    pub title: String,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    pub messages: usize,
+}
+
+#[cfg(feature = "chat")]
+impl From<&Session> for SessionSummary {
+    fn from(session: &Session) -> Self {
+        Self {
+            name: session.name.clone(),
+            title: session.title.clone(),
+            created: session.created,
+            messages: session.messages.len(),
+        }
+    }
+}
+
+/// Result of `eidos sessions`
+#[cfg(feature = "chat")]
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionsResult {
+    pub sessions: Vec<SessionSummary>,
+}
+
+#[cfg(feature = "chat")]
+impl Display for SessionsResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .sessions
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0);
+        let lines: Vec<String> = self
+            .sessions
+            .iter()
+            .map(|s| {
+                format!(
+                    "{:<width$}  {} ({} messages)",
+                    s.name,
+                    s.title,
+                    s.messages,
+                    width = width
+                )
+            })
+            .collect();
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Emit for SessionsResult {}
+
+/// Failed command: `{"error": ..., "code": ..., "exit_code": ...}` on stdout in
+/// JSON mode, so scripts get a result line either way
+impl Emit for ErrorReport {}
+
+/// Result of `eidos sessions import`
+#[cfg(feature = "chat")]
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionImportResult {
+    /// Names of the new sessions
+    pub imported: Vec<String>,
+    /// Conversations imported before
+    pub skipped: usize,
+}
+
+#[cfg(feature = "chat")]
+impl Display for SessionImportResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.imported.join("\n"))
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Emit for SessionImportResult {}
+
+/// Result of `eidos sessions translate`
+#[cfg(all(feature = "chat", feature = "translate"))]
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTranslationResult {
+    /// Name of the translated copy
+    pub name: String,
+    /// Session it was translated from
+    pub source: String,
+    pub target_lang: String,
+    pub target_lang_name: String,
+    /// Messages translated; the others were system prompts or already in the
+    /// target language
+    pub translated: usize,
+    pub messages: usize,
+}
+
+#[cfg(all(feature = "chat", feature = "translate"))]
+impl Display for SessionTranslationResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} messages translated into {} from {}",
+            self.name, self.translated, self.messages, self.target_lang_name, self.source
+        )
+    }
+}
+
+#[cfg(all(feature = "chat", feature = "translate"))]
+impl Emit for SessionTranslationResult {
+    fn raw(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// One file rewritten by `eidos maintenance compact`
+#[cfg(any(feature = "chat", feature = "core"))]
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactedFile {
+    pub path: String,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// History entries dropped by `--max-age` / `--max-size`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pruned: Option<usize>,
+}
+
+/// Result of `eidos maintenance compact`
+#[cfg(any(feature = "chat", feature = "core"))]
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactResult {
+    pub files: Vec<CompactedFile>,
+}
+
+#[cfg(any(feature = "chat", feature = "core"))]
+impl Display for CompactResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .files
+            .iter()
+            .map(|file| {
+                let mut line = format!(
+                    "{}: {} -> {}",
+                    file.path,
+                    format_bytes(file.bytes_before),
+                    format_bytes(file.bytes_after)
+                );
+                if let Some(pruned) = file.pruned.filter(|&n| n > 0) {
+                    line.push_str(&format!(" ({} old entries dropped)", pruned));
+                }
+                line
+            })
+            .collect();
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(any(feature = "chat", feature = "core"))]
+impl Emit for CompactResult {}
+
+/// Result of `eidos cache stats`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStatsResult {
+    pub dir: String,
+    pub entries: usize,
+    pub bytes: u64,
+    /// Times a cached command or explanation was used instead of the model
+    pub hits: u64,
+    /// Age of the oldest entry in days
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oldest_days: Option<u64>,
+}
+
+#[cfg(feature = "core")]
+impl Display for CacheStatsResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} {}, {}, used {} times",
+            self.dir,
+            self.entries,
+            if self.entries == 1 {
+                "entry"
+            } else {
+                "entries"
+            },
+            format_bytes(self.bytes),
+            self.hits
+        )?;
+        match self.oldest_days {
+            Some(0) => f.write_str(" (all from today)"),
+            Some(1) => f.write_str(" (oldest 1 day old)"),
+            Some(days) => write!(f, " (oldest {} days old)", days),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for CacheStatsResult {}
+
+/// Result of `eidos stats metrics`
+#[cfg(feature = "core")]
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsResult {
+    /// Whether new values are being recorded (`[metrics] enabled`)
+    pub enabled: bool,
+    pub path: String,
+    #[serde(flatten)]
+    pub metrics: Metrics,
+}
+
+#[cfg(feature = "core")]
+impl Display for MetricsResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = &self.metrics;
+        if metrics.is_empty() {
+            return if self.enabled {
+                write!(f, "No metrics recorded yet")
+            } else {
+                write!(
+                    f,
+                    "Metrics are off; set [metrics] enabled = true in eidos.toml to record them"
+                )
+            };
+        }
+
+        let outcomes =
+            |f: &mut fmt::Formatter<'_>, title: &str, values: &BTreeMap<String, Outcomes>| {
+                if values.is_empty() {
+                    return Ok(());
+                }
+                write!(f, "{}:", title)?;
+                for (name, outcomes) in values {
+                    write!(f, "\n  {:<14}{:>6}", name, outcomes.total())?;
+                    if outcomes.error > 0 {
+                        write!(f, "  ({} failed)", outcomes.error)?;
+                    }
+                }
+                writeln!(f)
+            };
+        outcomes(f, "Commands", &metrics.commands)?;
+        outcomes(f, "Daemon requests", &metrics.daemon_requests)?;
+        if !metrics.inference.is_empty() {
+            write!(f, "Inference:")?;
+            for (op, histogram) in &metrics.inference {
+                write!(f, "\n  {:<14}{:>6}", op, histogram.count())?;
+                if let Some(mean) = histogram.mean() {
+                    write!(f, "  mean {} ms", mean.as_millis())?;
+                }
+                match histogram.quantile_bound(0.95) {
+                    Some(bound) => write!(f, ", p95 under {} ms", bound.as_millis())?,
+                    None => write!(f, ", p95 over 10 s")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "Safety rejections: {}", metrics.safety_rejections)?;
+        if !metrics.provider_errors.is_empty() {
+            write!(f, "Provider errors:")?;
+            for (provider, errors) in &metrics.provider_errors {
+                write!(f, "\n  {:<14}{:>6}", provider, errors)?;
+            }
+            writeln!(f)?;
+        }
+        if !self.enabled {
+            writeln!(f, "Recording is off; these totals are from before.")?;
+        }
+        write!(f, "Totals in {}", self.path)
+    }
+}
+
+#[cfg(feature = "core")]
+impl Emit for MetricsResult {
+    /// The Prometheus text exposition format
+    fn raw(&self) -> String {
+        self.metrics.to_prometheus()
+    }
+}
+
+/// Result of `eidos model pull`
+#[cfg(feature = "models")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelPullResult {
+    pub name: String,
+    pub model_path: String,
+    pub tokenizer_path: String,
+    pub bytes: u64,
+    /// Whether the model file matched a published or given checksum
+    pub verified: bool,
+    /// Whether the files were already there
+    pub cached: bool,
+    /// Config file pointed at the model, unless `--no-activate`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<String>,
+}
+
+#[cfg(feature = "models")]
+impl Display for ModelPullResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}{}): {}",
+            if self.cached { "Found" } else { "Downloaded" },
+            self.name,
+            format_bytes(self.bytes),
+            if self.verified {
+                ", checksum verified"
+            } else {
+                ""
+            },
+            self.model_path
+        )?;
+        if let Some(config) = &self.config {
+            write!(f, "\nUsing it from now on (model_path in {})", config)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "models")]
+impl Emit for ModelPullResult {}
+
+/// A model in `eidos model list`
+#[cfg(feature = "models")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelSummary {
+    pub name: String,
+    pub backend: String,
+    pub bytes: u64,
+    /// Whether the configuration points at it
+    pub active: bool,
+    /// URL of the model file
+    pub source: String,
+}
+
+/// Result of `eidos model list`
+#[cfg(feature = "models")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelListResult {
+    pub dir: String,
+    pub models: Vec<ModelSummary>,
+}
+
+#[cfg(feature = "models")]
+impl Display for ModelListResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.models.iter().map(|m| m.name.len()).max().unwrap_or(0);
+        let lines: Vec<String> = self
+            .models
+            .iter()
+            .map(|m| {
+                format!(
+                    "{} {:<width$}  {:<4}  {:>9}  {}",
+                    if m.active { "*" } else { " " },
+                    m.name,
+                    m.backend,
+                    format_bytes(m.bytes),
+                    m.source,
+                    width = width
+                )
+            })
+            .collect();
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(feature = "models")]
+impl Emit for ModelListResult {}
+
+/// A file of a model in `eidos model info`
+#[cfg(feature = "models")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelFileInfo {
+    pub path: String,
+    pub url: String,
+    pub bytes: u64,
+    pub sha256: String,
+    pub verified: bool,
+}
+
+#[cfg(feature = "models")]
+impl Display for ModelFileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({})\n    from {}\n    sha256 {}{}",
+            self.path,
+            format_bytes(self.bytes),
+            self.url,
+            self.sha256,
+            if self.verified { " (verified)" } else { "" }
+        )
+    }
+}
+
+/// Result of `eidos model info`
+#[cfg(feature = "models")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfoResult {
+    pub name: String,
+    pub backend: String,
+    pub active: bool,
+    /// Preset matching the model file name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Seconds since the Unix epoch
+    pub downloaded: u64,
+    pub model: ModelFileInfo,
+    pub tokenizer: ModelFileInfo,
+}
+
+#[cfg(feature = "models")]
+impl Display for ModelInfoResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({} backend{})",
+            self.name,
+            self.backend,
+            if self.active { ", in use" } else { "" }
+        )?;
+        if let Some(preset) = &self.preset {
+            writeln!(f, "  preset: {}", preset)?;
+        }
+        writeln!(f, "  model: {}", self.model)?;
+        write!(f, "  tokenizer: {}", self.tokenizer)
+    }
+}
+
+#[cfg(feature = "models")]
+impl Emit for ModelInfoResult {}
+
+/// Result of `eidos config migrate`
+#[cfg(any(feature = "chat", feature = "translate"))]
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigMigrateResult {
+    /// Config file written, or that would be with `--dry-run`
+    pub path: String,
+    pub written: bool,
+    pub settings: Vec<Setting>,
+    /// Deprecated variables that are set but were not migrated
+    pub skipped: Vec<Skipped>,
+}
+
+#[cfg(any(feature = "chat", feature = "translate"))]
+impl Display for ConfigMigrateResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = Vec::new();
+        if self.settings.is_empty() {
+            lines.push("No deprecated environment variables to migrate".to_string());
+        } else {
+            lines.push(format!(
+                "{} {}:",
+                if self.written {
+                    "Wrote to"
+                } else {
+                    "Would write to"
+                },
+                self.path
+            ));
+            for setting in &self.settings {
+                lines.push(format!("  {}  (from {})", setting, setting.var));
+            }
+        }
+        for skipped in &self.skipped {
+            lines.push(format!("Skipped {}: {}", skipped.var, skipped.reason));
+        }
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(any(feature = "chat", feature = "translate"))]
+impl Emit for ConfigMigrateResult {}
+
+#[cfg(any(feature = "chat", feature = "core"))]
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        1_048_576..=1_073_741_823 => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+        _ => format!("{:.1} GiB", bytes as f64 / 1_073_741_824.0),
+    }
+}
+
+/// Free-form text result
+#[derive(Debug, Clone, Serialize)]
+pub struct TextResult {
+    pub text: String,
+}
+
+impl Display for TextResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Emit for TextResult {}
+
+/// Advice shown after an error, such as how to configure a missing provider
+///
+/// Emitted as one block so it can be dropped as a whole when tips are off.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hints {
+    /// Each line, with whether it is prose that may be translated
+    lines: Vec<(String, bool)>,
+}
+
+impl Hints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(mut self, text: impl Display) -> Self {
+        self.lines.push((text.to_string(), true));
+        self
+    }
+
+    /// A command or config snippet, kept as written when the hints are translated
+    pub fn code(mut self, text: impl Display) -> Self {
+        self.lines.push((text.to_string(), false));
+        self
+    }
+
+    /// The non-blank prose lines without their indentation, for translation
+    pub fn prose(&self) -> Vec<String> {
+        self.prose_lines()
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    /// The hints with their prose lines replaced, in order, by `prose`, keeping
+    /// each line's indentation
+    pub fn with_prose(mut self, prose: Vec<String>) -> Self {
+        for (line, text) in self.prose_lines_mut().zip(prose) {
+            let indent = line.len() - line.trim_start().len();
+            *line = format!("{}{}", &line[..indent], text.trim());
+        }
+        self
+    }
+
+    fn prose_lines(&self) -> impl Iterator<Item = &String> {
+        self.lines
+            .iter()
+            .filter(|(line, prose)| *prose && !line.trim().is_empty())
+            .map(|(line, _)| line)
+    }
+
+    fn prose_lines_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.lines
+            .iter_mut()
+            .filter(|(line, prose)| *prose && !line.trim().is_empty())
+            .map(|(line, _)| line)
+    }
+
+    /// Print the hints on stderr unless tips are turned off
+    pub fn emit(self) {
+        SINK.lock().hints(&self);
+    }
+}
+
+/// Destination pair for CLI output
+pub struct OutputSink {
+    stdout: Box<dyn Write + Send>,
+    stderr: Box<dyn Write + Send>,
+    format: OutputFormat,
+    tips: bool,
+    emoji: bool,
+    color: bool,
+    stderr_color: bool,
+    links: Option<ManLinks>,
+    ci: bool,
+    quiet: bool,
+    lang: Option<String>,
+    /// Whether an error has been printed, so the caller need not print it again
+    error_shown: bool,
+}
+
+impl OutputSink {
+    /// Sink writing to the process stdout/stderr
+    pub fn stdio() -> Self {
+        Self::with_writers(Box::new(io::stdout()), Box::new(io::stderr()))
+    }
+
+    /// Sink writing to arbitrary writers (used in tests)
+    pub fn with_writers(stdout: Box<dyn Write + Send>, stderr: Box<dyn Write + Send>) -> Self {
+        Self {
+            stdout,
+            stderr,
+            format: OutputFormat::default(),
+            tips: true,
+            emoji: true,
+            color: false,
+            stderr_color: false,
+            links: None,
+            ci: false,
+            quiet: false,
+            lang: None,
+            error_shown: false,
+        }
+    }
+
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Whether `hints` prints anything
+    pub fn set_tips(&mut self, tips: bool) {
+        self.tips = tips;
+    }
+
+    /// Whether errors and warnings are prefixed with an emoji
+    pub fn set_emoji(&mut self, emoji: bool) {
+        self.emoji = emoji;
+    }
+
+    /// Whether text results are coloured
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> bool {
+        self.color
+    }
+
+    /// Whether errors are red and warnings yellow
+    pub fn set_stderr_color(&mut self, color: bool) {
+        self.stderr_color = color;
+    }
+
+    /// Whether man and tldr page references in text results become hyperlinks,
+    /// and where man pages link to
+    pub fn set_links(&mut self, links: Option<ManLinks>) {
+        self.links = links;
+    }
+
+    /// CI mode (`--ci`): no tips, emoji, colour, links, notes or translation;
+    /// errors, warnings and verdicts are single `error: ` / `warning: ` / verdict
+    /// lines
+    pub fn set_ci(&mut self, ci: bool) {
+        self.ci = ci;
+        if ci {
+            self.tips = false;
+            self.emoji = false;
+            self.color = false;
+            self.stderr_color = false;
+            self.links = None;
+            self.lang = None;
+        }
+    }
+
+    pub fn ci(&self) -> bool {
+        self.ci
+    }
+
+    /// Quiet mode (`--quiet`): no tips, notes or emoji; results, errors and
+    /// warnings are printed as usual
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+        if quiet {
+            self.tips = false;
+            self.emoji = false;
+        }
+    }
+
+    /// Language explanations and tips are translated into; `None` for English
+    pub fn set_lang(&mut self, lang: Option<String>) {
+        self.lang = lang.filter(|lang| !self.ci && lang != "en");
+    }
+
+    pub fn lang(&self) -> Option<String> {
+        self.lang.clone()
+    }
+
+    /// Structured result rendered in the selected format (stdout)
+    pub fn emit<T: Emit>(&mut self, value: &T) {
+        match self.format {
+            OutputFormat::Text => {
+                let text = match self.color {
+                    true => value.colored(),
+                    false => value.to_string(),
+                };
+                match self.links {
+                    Some(links) => self.result(hyperlink_references(&text, links)),
+                    None => self.result(text),
+                }
+            }
+            OutputFormat::Raw => self.result(value.raw()),
+            OutputFormat::Json => match serde_json::to_string(value) {
+                Ok(json) => self.result(json),
+                Err(e) => self.error(format!("Failed to serialize result: {}", e)),
+            },
+        }
+    }
+
+    /// Primary result line (stdout)
+    pub fn result(&mut self, text: impl Display) {
+        // Ignore write errors (e.g. closed pipe); there is nowhere left to report them
+        let _ = writeln!(self.stdout, "{}", text);
+        let _ = self.stdout.flush();
+    }
+
+    /// Partial result without a trailing newline, flushed immediately (stdout)
+    pub fn fragment(&mut self, text: impl Display) {
+        let _ = write!(self.stdout, "{}", text);
+        let _ = self.stdout.flush();
+    }
+
+    /// Error message (stderr)
+    pub fn error(&mut self, text: impl Display) {
+        self.error_shown = true;
+        if self.ci {
+            return self.verdict(format!("error: {}", text));
+        }
+        let prefix = if self.emoji { "❌ " } else { "" };
+        let text = Style::Danger.apply(&text.to_string(), self.stderr_color);
+        let _ = writeln!(self.stderr, "{}{}", prefix, text);
+    }
+
+    /// Whether [`OutputSink::error`] has printed anything
+    pub fn error_shown(&self) -> bool {
+        self.error_shown
+    }
+
+    /// Warning message (stderr)
+    pub fn warning(&mut self, text: impl Display) {
+        if self.ci {
+            return self.verdict(format!("warning: {}", text));
+        }
+        let prefix = if self.emoji { "⚠️  " } else { "" };
+        let text = Style::Warning.apply(&text.to_string(), self.stderr_color);
+        let _ = writeln!(self.stderr, "{}{}", prefix, text);
+    }
+
+    /// Warning with fields for tools to read (stderr): `{"warning": ...}` in JSON
+    /// mode, the text otherwise
+    pub fn structured_warning<T: Serialize + Display>(&mut self, warning: &T) {
+        if self.format != OutputFormat::Json {
+            return self.warning(warning);
+        }
+        let line = serde_json::json!({ "warning": warning });
+        let _ = writeln!(self.stderr, "{}", line);
+    }
+
+    /// Free-form human-readable line such as tips or progress (stderr); dropped
+    /// in CI and quiet mode
+    pub fn note(&mut self, text: impl Display) {
+        if self.ci || self.quiet {
+            return;
+        }
+        let _ = writeln!(self.stderr, "{}", text);
+    }
+
+    /// Outcome of a check on a single line, for pipelines to match on (stderr)
+    pub fn verdict(&mut self, text: impl Display) {
+        let text = text.to_string();
+        let line: Vec<&str> = text.split_whitespace().collect();
+        let _ = writeln!(self.stderr, "{}", line.join(" "));
+    }
+
+    /// Hint block set off from the preceding message by a blank line (stderr)
+    pub fn hints(&mut self, hints: &Hints) {
+        if !self.tips || self.ci || hints.lines.is_empty() {
+            return;
+        }
+        let _ = writeln!(self.stderr);
+        for (line, _) in &hints.lines {
+            let _ = writeln!(self.stderr, "{}", line);
+        }
+    }
+}
+
+lazy_static! {
+    static ref SINK: Mutex<OutputSink> = Mutex::new(OutputSink::stdio());
+}
+
+/// Select how `emit` renders results
+pub fn set_format(format: OutputFormat) {
+    SINK.lock().set_format(format);
+}
+
+/// The output format selected with `--output`
+pub fn format() -> OutputFormat {
+    SINK.lock().format()
+}
+
+/// Print a structured result to stdout as text, JSON or a raw value
+pub fn emit<T: Emit>(value: &T) {
+    SINK.lock().emit(value);
+}
+
+/// Print a primary result to stdout
+pub fn result(text: impl Display) {
+    SINK.lock().result(text);
+}
+
+/// Print part of a streamed result to stdout without a newline
+pub fn fragment(text: impl Display) {
+    SINK.lock().fragment(text);
+}
+
+/// Turn hints on or off (`--no-tips`, `[ui] tips`)
+pub fn set_tips(tips: bool) {
+    SINK.lock().set_tips(tips);
+}
+
+/// Turn the emoji prefix of errors and warnings on or off (`[ui] emoji`)
+pub fn set_emoji(emoji: bool) {
+    SINK.lock().set_emoji(emoji);
+}
+
+/// Turn colours in text results on or off (`--color`, terminal, `NO_COLOR`,
+/// `[ui] color`)
+pub fn set_color(color: bool) {
+    SINK.lock().set_color(color);
+}
+
+/// Whether text results on stdout are coloured
+pub fn color() -> bool {
+    SINK.lock().color()
+}
+
+/// Turn colours in errors and warnings on or off; decided like `set_color`,
+/// but for stderr
+pub fn set_stderr_color(color: bool) {
+    SINK.lock().set_stderr_color(color);
+}
+
+/// Turn hyperlinks to man and tldr pages in text results on or off (terminal,
+/// `[ui] hyperlinks`, `[ui] man_links`)
+pub fn set_links(links: Option<ManLinks>) {
+    SINK.lock().set_links(links);
+}
+
+/// Turn CI mode on or off (`--ci`, `[ci] enabled`)
+pub fn set_ci(ci: bool) {
+    SINK.lock().set_ci(ci);
+}
+
+/// Whether CI mode is on; nothing may prompt for input then
+pub fn ci() -> bool {
+    SINK.lock().ci()
+}
+
+/// Turn quiet mode on or off (`--quiet`)
+pub fn set_quiet(quiet: bool) {
+    SINK.lock().set_quiet(quiet);
+}
+
+/// Select the language explanations and tips are shown in (`--lang`, `[ui] lang`,
+/// the locale)
+pub fn set_lang(lang: Option<String>) {
+    SINK.lock().set_lang(lang);
+}
+
+/// Language to translate explanations and tips into; `None` for English
+pub fn lang() -> Option<String> {
+    SINK.lock().lang()
+}
+
+/// Print a single-line verdict to stderr
+pub fn verdict(text: impl Display) {
+    SINK.lock().verdict(text);
+}
+
+/// Print an error to stderr
+pub fn error(text: impl Display) {
+    SINK.lock().error(text);
+}
+
+/// Whether an error has been printed, e.g. by the handler that failed
+pub fn error_shown() -> bool {
+    SINK.lock().error_shown()
+}
+
+/// Print a warning to stderr
+pub fn warning(text: impl Display) {
+    SINK.lock().warning(text);
+}
+
+/// Print a warning with fields to stderr, as JSON in JSON mode
+pub fn structured_warning<T: Serialize + Display>(warning: &T) {
+    SINK.lock().structured_warning(warning);
+}
+
+/// Print a human-readable note (tips, progress, blank lines) to stderr
+pub fn note(text: impl Display) {
+    SINK.lock().note(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Writer that appends into a shared buffer
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_results_go_to_stdout_only() {
+        let out = SharedBuf::default();
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(out.clone()), Box::new(err.clone()));
+
+        sink.result("ls -la");
+        sink.warning("using mock translator");
+        sink.error("something failed");
+        sink.note("Tip: set OPENAI_API_KEY");
+
+        assert_eq!(out.contents(), "ls -la\n");
+        let stderr = err.contents();
+        assert!(stderr.contains("using mock translator"));
+        assert!(stderr.contains("something failed"));
+        assert!(stderr.contains("Tip: set OPENAI_API_KEY"));
+        assert!(!stderr.contains("ls -la"));
+    }
+
+    #[test]
+    fn test_error_shown_tracks_printed_errors() {
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(io::sink()));
+        sink.warning("using mock translator");
+        assert!(!sink.error_shown());
+        sink.error("something failed");
+        assert!(sink.error_shown());
+    }
+
+    #[test]
+    fn test_structured_warning_is_json_in_json_mode() {
+        #[derive(Serialize)]
+        struct Deprecated {
+            deprecated: &'static str,
+        }
+        impl Display for Deprecated {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{} is deprecated", self.deprecated)
+            }
+        }
+
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(err.clone()));
+        sink.set_emoji(false);
+        sink.structured_warning(&Deprecated {
+            deprecated: "OLLAMA_HOST",
+        });
+        sink.set_format(OutputFormat::Json);
+        sink.structured_warning(&Deprecated {
+            deprecated: "OLLAMA_HOST",
+        });
+        assert_eq!(
+            err.contents(),
+            "OLLAMA_HOST is deprecated\n{\"warning\":{\"deprecated\":\"OLLAMA_HOST\"}}\n"
+        );
+    }
+
+    #[test]
+    fn test_tips_and_emoji_can_be_disabled() {
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(err.clone()));
+        let hints = Hints::new().line("Tip: set OPENAI_API_KEY");
+
+        sink.error("no provider");
+        sink.hints(&hints);
+        assert_eq!(
+            err.contents(),
+            "❌ no provider\n\nTip: set OPENAI_API_KEY\n"
+        );
+
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(err.clone()));
+        sink.set_tips(false);
+        sink.set_emoji(false);
+        sink.error("no provider");
+        sink.warning("mock translator");
+        sink.hints(&hints);
+        assert_eq!(err.contents(), "no provider\nmock translator\n");
+    }
+
+    #[test]
+    fn test_ci_mode_prints_single_lines() {
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(err.clone()));
+        sink.set_ci(true);
+        sink.error("Configuration Error:\n  model not found");
+        sink.note("Generated: rm -rf /");
+        sink.hints(&Hints::new().line("Tip: set OPENAI_API_KEY"));
+        sink.warning("mock translator");
+        sink.verdict("blocked: rm -rf / (recursive delete of root, critical)");
+        assert_eq!(
+            err.contents(),
+            "error: Configuration Error: model not found\nwarning: mock translator\n\
+             blocked: rm -rf / (recursive delete of root, critical)\n"
+        );
+    }
+
+    #[test]
+    fn test_stderr_color_paints_errors_and_warnings() {
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(err.clone()));
+        sink.set_emoji(false);
+        sink.set_stderr_color(true);
+        sink.error("something failed");
+        sink.warning("mock translator");
+        sink.note("Generating command...");
+        assert_eq!(
+            err.contents(),
+            "\x1b[31msomething failed\x1b[0m\n\x1b[33mmock translator\x1b[0m\n\
+             Generating command...\n"
+        );
+    }
+
+    #[test]
+    fn test_quiet_mode_keeps_results_and_errors() {
+        let out = SharedBuf::default();
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(out.clone()), Box::new(err.clone()));
+        sink.set_format(OutputFormat::Raw);
+        sink.set_quiet(true);
+        sink.note("Generating command...");
+        sink.result("ls -la");
+        sink.warning("mock translator");
+        sink.error("something failed");
+        sink.hints(&Hints::new().line("Tip: set OPENAI_API_KEY"));
+        assert_eq!(out.contents(), "ls -la\n");
+        assert_eq!(err.contents(), "mock translator\nsomething failed\n");
+    }
+
+    #[test]
+    fn test_hints_translate_prose_only() {
+        let hints = Hints::new()
+            .line("Set the model path:")
+            .code("  export EIDOS_MODEL_PATH=/path/to/model.onnx")
+            .line("")
+            .line("  - or run `eidos model pull`");
+        assert_eq!(
+            hints.prose(),
+            ["Set the model path:", "- or run `eidos model pull`"]
+        );
+
+        let err = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(io::sink()), Box::new(err.clone()));
+        let translated = vec![
+            "Model yolunu ayarlayın:".to_string(),
+            "- ya da `eidos model pull` çalıştırın".to_string(),
+        ];
+        sink.hints(&hints.with_prose(translated));
+        assert_eq!(
+            err.contents(),
+            "\nModel yolunu ayarlayın:\n  export EIDOS_MODEL_PATH=/path/to/model.onnx\n\n  \
+             - ya da `eidos model pull` çalıştırın\n"
+        );
+
+        sink.set_lang(Some("en".to_string()));
+        assert_eq!(sink.lang(), None);
+        sink.set_lang(Some("tr".to_string()));
+        assert_eq!(sink.lang().as_deref(), Some("tr"));
+        sink.set_ci(true);
+        sink.set_lang(Some("tr".to_string()));
+        assert_eq!(sink.lang(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_emit_text_and_json() {
+        let result = CommandResult {
+            prompt: "list files".to_string(),
+            requested: 1,
+            commands: vec![GeneratedCommand::new(
+                "ls -la",
+                Some("Lists all files".to_string()),
+            )],
+            rejected: Vec::new(),
+            translation: None,
+            latency: None,
+        };
+
+        let out = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(out.clone()), Box::new(io::sink()));
+        sink.emit(&result);
+        assert_eq!(out.contents(), "ls -la\n\nExplanation: Lists all files\n");
+
+        let out = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(out.clone()), Box::new(io::sink()));
+        sink.set_format(OutputFormat::Json);
+        sink.emit(&result);
+        let json: serde_json::Value = serde_json::from_str(&out.contents()).unwrap();
+        assert_eq!(json["commands"][0]["command"], "ls -la");
+        assert_eq!(json["requested"], 1);
+        assert!(json.get("rejected").is_none());
+        assert!(json["commands"][0].get("undo").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_batch_result_keeps_one_entry_per_prompt() {
+        let safe = BatchEntry {
+            result: CommandResult {
+                latency: Some(Duration::from_millis(12)),
+                ..CommandResult::single("list files".to_string(), "ls".to_string(), None)
+            },
+            error: None,
+            latency_ms: Some(12),
+        };
+        let mut rejected = BatchEntry::failed("wipe the disk".to_string(), String::new());
+        rejected.error = None;
+        rejected
+            .result
+            .rejected
+            .push(lib_core::SafetyPolicy::default().validate("rm -rf /"));
+        let failed = BatchEntry::failed("".to_string(), "Invalid input: empty".to_string());
+        let batch = BatchResult {
+            entries: vec![safe, rejected, failed],
+        };
+
+        let text = batch.to_string();
+        assert!(text.starts_with("1. list files\n   ls\n2. wipe the disk\n   ✗ rejected: rm -rf /"));
+        assert!(text.contains("3. \n   ✗ Invalid input: empty\n"));
+        assert!(text.ends_with("1 of 3 prompts produced a safe command"));
+        assert_eq!(batch.raw(), "ls\n\n");
+
+        let json = serde_json::to_value(&batch).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        assert_eq!(json[0]["prompt"], "list files");
+        assert_eq!(json[0]["commands"][0]["command"], "ls");
+        assert_eq!(json[0]["latency_ms"], 12);
+        assert_eq!(json[1]["rejected"][0]["command"], "rm -rf /");
+        assert_eq!(json[2]["error"], "Invalid input: empty");
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_eval_result_lists_worst_misses() {
+        let backend = lib_core::MockBackend::new()
+            .with_command("list files", "ls -l")
+            .with_fallback("pwd");
+        let cases = [("list files", "ls -la"), ("where am i", "pwd")].map(|(prompt, command)| {
+            lib_core::EvalCase {
+                prompt: prompt.to_string(),
+                command: command.to_string(),
+            }
+        });
+        let evaluation = lib_core::evaluate(
+            &backend,
+            &lib_core::SafetyPolicy::default(),
+            &cases,
+            |_, _| {},
+        );
+        let result = EvalResult {
+            dataset: "pairs.jsonl".to_string(),
+            report: None,
+            evaluation,
+        };
+
+        let text = result.to_string();
+        assert!(text.starts_with("Evaluated 2 cases from pairs.jsonl\n  Exact match:       50.0%"));
+        assert!(text.contains("  list files\n    expected: ls -la\n    got:      ls -l"));
+        assert!(!text.contains("where am i"));
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["summary"]["cases"], 2);
+        assert_eq!(json["records"][1]["normalized"], true);
+        assert!(json.get("report").is_none());
+    }
+
+    #[test]
+    fn test_bench_result_aligns_table() {
+        let result = BenchResult {
+            measurements: vec![
+                BenchMeasurement::new("validation", 41230.4, "commands/s"),
+                BenchMeasurement::new("model load", 1.234, "s").with_detail("gguf on cpu"),
+                BenchMeasurement::skipped("chat round trip", "no provider configured"),
+            ],
+        };
+        assert_eq!(
+            result.to_string(),
+            "Measurement      Result            Detail\n\
+             validation       41230 commands/s\n\
+             model load       1.23 s            gguf on cpu\n\
+             chat round trip  -                 no provider configured"
+        );
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["measurements"][1]["value"], 1.234);
+        assert!(json["measurements"][2]["value"].is_null());
+        assert!(json["measurements"][0].get("detail").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_alternatives_text_layout() {
+        let result = CommandResult {
+            prompt: "list files".to_string(),
+            requested: 3,
+            commands: vec![
+                GeneratedCommand::new("ls", None),
+                GeneratedCommand::new("ls -a", Some("Includes hidden files".to_string())),
+                GeneratedCommand {
+                    undo: Some("mv b a".to_string()),
+                    ..GeneratedCommand::new("mv a b", None)
+                },
+            ],
+            rejected: Vec::new(),
+            translation: None,
+            latency: None,
+        };
+        assert_eq!(
+            result.to_string(),
+            "Generated 3 alternatives:\n  1. ls\n  2. ls -a\n     ± vs 1: adds -a\n     \
+             → Includes hidden files\n  3. mv a b\n     ↩ undo: mv b a"
+        );
+
+        let result = CommandResult {
+            requested: 5,
+            commands: result.commands[..2].to_vec(),
+            ..result
+        };
+        assert!(result
+            .to_string()
+            .starts_with("Generated 2 of 5 alternatives:\n  1. ls\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_alternatives_needing_review_are_marked() {
+        let result = CommandResult {
+            prompt: "is nginx running".to_string(),
+            requested: 2,
+            commands: vec![
+                GeneratedCommand::new("pgrep nginx", None),
+                GeneratedCommand::new("ps aux | grep nginx", None).with_review(true),
+            ],
+            rejected: Vec::new(),
+            translation: None,
+            latency: None,
+        };
+        assert_eq!(
+            result.to_string(),
+            "Generated 2 alternatives:\n  1. pgrep nginx\n  2. ps aux | grep nginx\n     \
+             ⚠ needs review: quotes, globs or pipes were checked stage by stage"
+        );
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json["commands"][0].get("needs_review").is_none());
+        assert_eq!(json["commands"][1]["needs_review"], true);
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_summarized_output_replaces_run_in_text() {
+        let mut result =
+            CommandResult::single("biggest process?".to_string(), "ps aux".to_string(), None);
+        result.commands[0].run = Some(CommandRun {
+            exit_code: Some(0),
+            stdout: "USER PID\nroot 1\nme 42\n".to_string(),
+            stderr: String::new(),
+            timed_out: false,
+        });
+        result.commands[0].answer = Some("PID 42".to_string());
+        assert_eq!(
+            result.to_string(),
+            "ps aux\n\nPID 42\n\n[summarized from 3 lines of output]"
+        );
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["commands"][0]["answer"], "PID 42");
+        assert_eq!(json["commands"][0]["run"]["exit_code"], 0);
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_token_diff() {
+        use TokenDiff::*;
+        assert_eq!(
+            token_diff("ls -l /tmp", "ls -la /tmp"),
+            vec![Same("ls"), Removed("-l"), Added("-la"), Same("/tmp")]
+        );
+        assert_eq!(
+            token_diff("du -sh .", "du -h ."),
+            vec![Same("du"), Removed("-sh"), Added("-h"), Same(".")]
+        );
+        assert_eq!(token_diff("df -h", "df -h"), vec![Same("df"), Same("-h")]);
+    }
+
+    #[test]
+    fn test_hyperlink_references() {
+        let link = |url: &str, text: &str| format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text);
+        assert_eq!(
+            hyperlink_references("See tar(1) and man 5 crontab.", ManLinks::Web),
+            format!(
+                "See {} and {}.",
+                link("https://manpages.debian.org/tar.1", "tar(1)"),
+                link("https://manpages.debian.org/crontab.5", "man 5 crontab")
+            )
+        );
+        assert_eq!(
+            hyperlink_references("(see find(1)), or tldr find", ManLinks::Man),
+            format!(
+                "(see {}), or {}",
+                link("man:find(1)", "find(1)"),
+                link("https://tldr.inbrowser.app/pages/common/find", "tldr find")
+            )
+        );
+        assert_eq!(
+            hyperlink_references("Read  man git-log\nfor more", ManLinks::Man),
+            format!("Read  {}\nfor more", link("man:git-log", "man git-log"))
+        );
+
+        // Function calls, options and prose stay as they are
+        for text in [
+            "calls main(argc)",
+            "man -k disk",
+            "the man page for tar",
+            "tldr",
+        ] {
+            assert_eq!(hyperlink_references(text, ManLinks::Web), text);
+        }
+    }
+
+    #[test]
+    fn test_hyperlink_support_from_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert!(terminal_supports_hyperlinks(env(&[(
+            "TERM_PROGRAM",
+            "iTerm.app"
+        )])));
+        assert!(terminal_supports_hyperlinks(env(&[(
+            "VTE_VERSION",
+            "6800"
+        )])));
+        assert!(terminal_supports_hyperlinks(env(&[(
+            "TERM",
+            "xterm-kitty"
+        )])));
+        assert!(!terminal_supports_hyperlinks(env(&[(
+            "VTE_VERSION",
+            "4600"
+        )])));
+        assert!(!terminal_supports_hyperlinks(env(&[(
+            "TERM",
+            "xterm-256color"
+        )])));
+        assert!(terminal_supports_hyperlinks(env(&[(
+            "FORCE_HYPERLINK",
+            "1"
+        )])));
+        assert!(!terminal_supports_hyperlinks(env(&[
+            ("FORCE_HYPERLINK", "0"),
+            ("WT_SESSION", "1")
+        ])));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_explanation_links_only_when_enabled() {
+        let result = ExplainResult {
+            command: "crontab -e".to_string(),
+            explanation: Some("Edits your crontab(5).".to_string()),
+            source: Some("model".to_string()),
+            parts: Vec::new(),
+        };
+
+        let out = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(out.clone()), Box::new(io::sink()));
+        sink.emit(&result);
+        sink.set_links(Some(ManLinks::Man));
+        sink.emit(&result);
+        sink.set_ci(true);
+        sink.emit(&result);
+        assert_eq!(
+            out.contents(),
+            "crontab -e\n\nEdits your crontab(5).\n\
+             crontab -e\n\nEdits your \x1b]8;;man:crontab(5)\x1b\\crontab(5)\x1b]8;;\x1b\\.\n\
+             crontab -e\n\nEdits your crontab(5).\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_alternatives_diff_is_colored_on_terminals() {
+        let result = CommandResult {
+            prompt: "disk usage".to_string(),
+            requested: 2,
+            commands: vec![
+                GeneratedCommand::new("du -sh .", None),
+                GeneratedCommand::new("du -h --max-depth=1 .", None),
+            ],
+            rejected: Vec::new(),
+            translation: None,
+            latency: None,
+        };
+
+        let out = SharedBuf::default();
+        let mut sink = OutputSink::with_writers(Box::new(out.clone()), Box::new(io::sink()));
+        sink.set_color(true);
+        sink.emit(&result);
+        assert_eq!(
+            out.contents(),
+            "Generated 2 alternatives:\n  1. du -sh .\n  \
+             2. du \x1b[1;32m-h\x1b[0m \x1b[1;32m--max-depth=1\x1b[0m .\n     \
+             ± vs 1: adds \x1b[1;32m-h --max-depth=1\x1b[0m, drops \x1b[31m-sh\x1b[0m\n"
+        );
+
+        // Plain text keeps the summary line but no escape codes
+        assert!(!result.to_string().contains('\x1b'));
+        assert!(result
+            .to_string()
+            .contains("± vs 1: adds -h --max-depth=1, drops -sh"));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_explain_result_text() {
+        let result = ExplainResult {
+            command: "tar -xzf a.tgz | wc -l".to_string(),
+            explanation: Some("Extracts a.tgz".to_string()),
+            source: Some("model".to_string()),
+            parts: lib_core::breakdown_command("tar -xzf a.tgz | wc -l"),
+        };
+        assert_eq!(
+            result.to_string(),
+            "tar -xzf a.tgz | wc -l\n\nExtracts a.tgz\n\n\
+             tar\n  -x               extract files from an archive\n  \
+             -z               filter the archive through gzip\n  \
+             -f a.tgz         use the given archive file\n\n\
+             wc\n  -l               count lines"
+        );
+        assert_eq!(result.raw(), "Extracts a.tgz");
+
+        // Without a model, --raw prints the breakdown
+        let result = ExplainResult {
+            explanation: None,
+            source: None,
+            parts: lib_core::breakdown_command("sudo ls -a | sort"),
+            command: "sudo ls -a | sort".to_string(),
+        };
+        assert_eq!(
+            result.raw(),
+            "sudo ls\n  -a               include hidden entries starting with .\n\nsort"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "translate")]
+    fn test_translation_text_names_languages() {
+        let result = TranslationResultOutput {
+            source_lang: "tr".to_string(),
+            source_lang_name: "Turkish / Türkçe".to_string(),
+            target_lang: "en".to_string(),
+            target_lang_name: "English".to_string(),
+            original: "Merhaba".to_string(),
+            translated: "Hello".to_string(),
+            was_translated: true,
+            confidence: Some(0.874),
+        };
+        assert_eq!(
+            result.to_string(),
+            "Detected language: Turkish / Türkçe (tr), 87% confidence\n\
+             Original (Turkish / Türkçe): Merhaba\n\
+             Translated (English): Hello"
+        );
+
+        // Named with --from, nothing was detected
+        let result = TranslationResultOutput {
+            confidence: None,
+            ..result
+        };
+        assert!(result
+            .to_string()
+            .starts_with("Source language: Turkish / Türkçe (tr)\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_audit_result_text_and_sarif() {
+        let script = "cd build\nrm -rf out\n";
+        let result = AuditResult {
+            file: "build.sh".to_string(),
+            commands: 2,
+            findings: lib_core::audit_script(script, &lib_core::SafetyPolicy::default()),
+        };
+        assert_eq!(
+            result.to_string(),
+            "build.sh:2: rm -rf out\n  \
+             dangerous command 'rm' [critical]: deletes data, changes permissions or users, \
+             needs privileges or uses the network\n\
+             1 risky of 2 commands in build.sh"
+        );
+
+        let sarif = result.sarif();
+        assert_eq!(sarif["version"], "2.1.0");
+        let finding = &sarif["runs"][0]["results"][0];
+        assert_eq!(finding["ruleId"], "dangerous_command");
+        assert_eq!(finding["level"], "error");
+        let region = &finding["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 2);
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_cache_stats_text() {
+        let mut stats = CacheStatsResult {
+            dir: "/home/user/.cache/eidos/commands".to_string(),
+            entries: 12,
+            bytes: 4300,
+            hits: 31,
+            oldest_days: Some(3),
+        };
+        assert_eq!(
+            stats.to_string(),
+            "/home/user/.cache/eidos/commands: 12 entries, 4.2 KiB, used 31 times (oldest 3 days old)"
+        );
+        stats.oldest_days = Some(0);
+        assert!(stats
+            .to_string()
+            .ends_with("used 31 times (all from today)"));
+    }
+
+    #[test]
+    #[cfg(feature = "core")]
+    fn test_metrics_result_text() {
+        let mut metrics = Metrics::default();
+        assert!(MetricsResult {
+            enabled: false,
+            path: "metrics.json".to_string(),
+            metrics: metrics.clone(),
+        }
+        .to_string()
+        .starts_with("Metrics are off"));
+
+        metrics
+            .commands
+            .insert("core".to_string(), Outcomes { ok: 11, error: 1 });
+        metrics.safety_rejections = 2;
+        metrics.provider_errors.insert("ollama".to_string(), 3);
+        let result = MetricsResult {
+            enabled: true,
+            path: "metrics.json".to_string(),
+            metrics,
+        };
+        assert_eq!(
+            result.to_string(),
+            "Commands:\n  core              12  (1 failed)\n\
+             Safety rejections: 2\n\
+             Provider errors:\n  ollama             3\n\
+             Totals in metrics.json"
+        );
+        assert!(result
+            .raw()
+            .contains("eidos_commands_total{command=\"core\",outcome=\"error\"} 1\n"));
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["commands"]["core"]["ok"], 11);
+    }
+
+    #[test]
+    #[cfg(feature = "models")]
+    fn test_model_results_text() {
+        let pull = ModelPullResult {
+            name: "codellama-7b.Q4_K_M".to_string(),
+            model_path: "/models/codellama-7b.Q4_K_M/codellama-7b.Q4_K_M.gguf".to_string(),
+            tokenizer_path: "/models/codellama-7b.Q4_K_M/tokenizer.json".to_string(),
+            bytes: 4_081_004_224,
+            verified: true,
+            cached: false,
+            config: Some("/home/user/.config/eidos/eidos.toml".to_string()),
+        };
+        assert_eq!(
+            pull.to_string(),
+            "Downloaded codellama-7b.Q4_K_M (3.8 GiB, checksum verified): \
+             /models/codellama-7b.Q4_K_M/codellama-7b.Q4_K_M.gguf\n\
+             Using it from now on (model_path in /home/user/.config/eidos/eidos.toml)"
+        );
+
+        let list = ModelListResult {
+            dir: "/models".to_string(),
+            models: vec![
+                ModelSummary {
+                    name: "codellama".to_string(),
+                    backend: "gguf".to_string(),
+                    bytes: 4_081_004_224,
+                    active: true,
+                    source: "https://huggingface.co/a/b/resolve/main/codellama.gguf".to_string(),
+                },
+                ModelSummary {
+                    name: "t5".to_string(),
+                    backend: "onnx".to_string(),
+                    bytes: 300_000_000,
+                    active: false,
+                    source: "https://example.com/t5.onnx".to_string(),
+                },
+            ],
+        };
+        assert_eq!(
+            list.to_string(),
+            "* codellama  gguf    3.8 GiB  https://huggingface.co/a/b/resolve/main/codellama.gguf\n  \
+             t5         onnx  286.1 MiB  https://example.com/t5.onnx"
+        );
+    }
+
+    #[test]
+    #[cfg(any(feature = "chat", feature = "core"))]
+    fn test_compact_result_text() {
+        let result = CompactResult {
+            files: vec![
+                CompactedFile {
+                    path: "history.jsonl".to_string(),
+                    bytes_before: 3 * 1024 * 1024,
+                    bytes_after: 307_200,
+                    pruned: Some(12),
+                },
+                CompactedFile {
+                    path: "indexes/notes.json".to_string(),
+                    bytes_before: 900,
+                    bytes_after: 900,
+                    pruned: None,
+                },
+            ],
+        };
+        assert_eq!(
+            result.to_string(),
+            "history.jsonl: 3.0 MiB -> 300.0 KiB (12 old entries dropped)\n\
+             indexes/notes.json: 900 B -> 900 B"
+        );
+    }
+}
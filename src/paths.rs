@@ -0,0 +1,108 @@
+// src/paths.rs
+// Well-known directories for Eidos data, following the XDG base directory spec
+
+use std::env;
+use std::path::PathBuf;
+
+/// Directory for persistent data (~/.local/share/eidos)
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Directory for data that can be rebuilt at any time (~/.cache/eidos)
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Directory for user configuration (~/.config/eidos)
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Executables that add verbs to eidos (`eidos <verb> ...`)
+pub fn plugins_dir() -> PathBuf {
+    config_dir().join("plugins")
+}
+
+/// Providers recently found unreachable (see `health`)
+pub fn health_path() -> PathBuf {
+    cache_dir().join("providers.json")
+}
+
+/// Deprecated environment variables already warned about (see `legacy_env`)
+#[cfg(any(feature = "chat", feature = "translate"))]
+pub fn deprecations_path() -> PathBuf {
+    cache_dir().join("deprecations")
+}
+
+/// Commands cached by `eidos core` (`eidos cache`)
+#[cfg(feature = "core")]
+pub fn command_cache_dir() -> PathBuf {
+    cache_dir().join("commands")
+}
+
+/// Directory for notes indexes built by `eidos index`
+pub fn index_dir() -> PathBuf {
+    data_dir().join("indexes")
+}
+
+/// Path of a named notes index
+pub fn index_path(name: &str) -> PathBuf {
+    index_dir().join(format!("{}.json", name))
+}
+
+/// Directory for saved chat sessions (`eidos sessions`)
+pub fn sessions_dir() -> PathBuf {
+    data_dir().join("sessions")
+}
+
+/// Directory of models downloaded by `eidos model pull`, one directory each
+#[cfg(feature = "models")]
+pub fn models_dir() -> PathBuf {
+    data_dir().join("models")
+}
+
+/// Totals of the opt-in usage metrics (see `metrics`)
+#[cfg(any(feature = "core", feature = "chat", feature = "translate"))]
+pub fn metrics_path() -> PathBuf {
+    data_dir().join("metrics.json")
+}
+
+/// Log of commands generated by `eidos core`
+pub fn history_path() -> PathBuf {
+    data_dir().join("history.jsonl")
+}
+
+/// Directory for sockets and other per-session files
+///
+/// `$XDG_RUNTIME_DIR/eidos` when set (private to the user and cleared on logout),
+/// otherwise the data directory.
+pub fn runtime_dir() -> PathBuf {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("eidos"),
+        _ => data_dir(),
+    }
+}
+
+/// Socket of the `eidos daemon` process (`EIDOS_SOCKET` overrides it)
+pub fn socket_path() -> PathBuf {
+    env::var("EIDOS_SOCKET")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| runtime_dir().join("daemon.sock"))
+}
+
+fn xdg_dir(var: &str, home_fallback: &str) -> PathBuf {
+    let base = env::var(var)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|h| PathBuf::from(h).join(home_fallback))
+        })
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("eidos")
+}
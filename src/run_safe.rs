@@ -0,0 +1,159 @@
+// src/run_safe.rs
+// `eidos core --run-safe`: run a read-only command and capture what it prints
+//
+// Only commands `lib_core::risk` classifies as read-only get here, which rules out
+// pipes, redirects, quoting and substitutions, so the command is split on
+// whitespace and executed directly rather than handed to a shell. Stdin is closed,
+// output is capped, and a command that outlives its time budget is killed.
+
+use serde::Serialize;
+use std::fmt;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a command may run before it is killed
+pub const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes of stdout / stderr kept from a run
+const MAX_OUTPUT_BYTES: u64 = 64 * 1024;
+
+/// What a read-only command printed
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandRun {
+    /// Exit status; absent when the command was killed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    /// Whether the command was killed for running longer than [`RUN_TIMEOUT`]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+}
+
+impl fmt::Display for CommandRun {
+    /// The command's output, followed by how it ended unless it succeeded
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let output: Vec<&str> = [self.stdout.trim_end(), self.stderr.trim_end()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect();
+        write!(f, "{}", output.join("\n"))?;
+        let status = match self.exit_code {
+            _ if self.timed_out => "[killed: took too long]".to_string(),
+            Some(0) => return Ok(()),
+            Some(code) => format!("[exit status {}]", code),
+            None => "[killed by a signal]".to_string(),
+        };
+        if !output.is_empty() {
+            writeln!(f)?;
+        }
+        write!(f, "{}", status)
+    }
+}
+
+/// Run `command`, which must already be classified read-only
+pub fn run(command: &str, timeout: Duration) -> Result<CommandRun, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("empty command")?;
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+
+    // Drained on their own threads so a chatty command cannot fill the pipe and
+    // stall while we wait for it
+    let stdout = capture(child.stdout.take());
+    let stderr = capture(child.stderr.take());
+    let (exit_code, timed_out) = wait(&mut child, timeout)?;
+    // After a timeout its own children may still hold the pipes open, so the
+    // output is abandoned rather than waited for
+    let (stdout, stderr) = if timed_out {
+        (String::new(), String::new())
+    } else {
+        (
+            stdout.join().unwrap_or_default(),
+            stderr.join().unwrap_or_default(),
+        )
+    };
+
+    Ok(CommandRun {
+        exit_code,
+        stdout,
+        stderr,
+        timed_out,
+    })
+}
+
+fn capture(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(pipe) = pipe {
+            let mut pipe = pipe.take(MAX_OUTPUT_BYTES);
+            let _ = pipe.read_to_end(&mut bytes);
+            // Keep draining so the child never blocks on a full pipe
+            let _ = std::io::copy(pipe.get_mut(), &mut std::io::sink());
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+fn wait(child: &mut Child, timeout: Duration) -> Result<(Option<i32>, bool), String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            return Ok((status.code(), false));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok((None, true));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_captures_output() {
+        let run = run("echo hello world", RUN_TIMEOUT).unwrap();
+        assert_eq!(run.exit_code, Some(0));
+        assert_eq!(run.stdout, "hello world\n");
+        assert!(!run.timed_out);
+
+        assert!(super::run("definitely-not-a-command", RUN_TIMEOUT).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_kills_slow_commands() {
+        let run = run("sleep 5", Duration::from_millis(100)).unwrap();
+        assert!(run.timed_out);
+        assert_eq!(run.exit_code, None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_timeout_does_not_wait_for_orphans() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // The background sleep keeps the pipes open after the script is killed
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("linger.sh");
+        std::fs::write(&script, "#!/bin/sh\nsleep 5 &\nsleep 5\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let started = Instant::now();
+        let run = run(script.to_str().unwrap(), Duration::from_millis(200)).unwrap();
+        assert!(run.timed_out);
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+}
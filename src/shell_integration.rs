@@ -0,0 +1,132 @@
+// src/shell_integration.rs
+// `eidos shell-init <shell>`: a keybinding that turns the current line into a command
+//
+// Each snippet binds Ctrl-G to a function that sends whatever is on the prompt line
+// to `eidos --output raw core` and replaces the line with the generated command. The
+// command is only placed in the editing buffer; the user reviews it and presses Enter
+// themselves. Errors are printed by eidos on stderr and leave the line untouched.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Shells `eidos shell-init` can generate a snippet for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        }
+    }
+
+    /// Snippet to evaluate from the shell's startup file
+    pub fn init_script(&self) -> &'static str {
+        match self {
+            Shell::Bash => BASH_INIT,
+            Shell::Zsh => ZSH_INIT,
+            Shell::Fish => FISH_INIT,
+        }
+    }
+
+    /// Line to add to the shell's startup file
+    pub fn install_hint(&self) -> &'static str {
+        match self {
+            Shell::Bash => "eval \"$(eidos shell-init bash)\"   # in ~/.bashrc",
+            Shell::Zsh => "eval \"$(eidos shell-init zsh)\"    # in ~/.zshrc",
+            Shell::Fish => "eidos shell-init fish | source    # in ~/.config/fish/config.fish",
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            other => Err(format!(
+                "Unsupported shell '{}' (expected bash, zsh or fish)",
+                other
+            )),
+        }
+    }
+}
+
+const BASH_INIT: &str = r#"# eidos shell integration: Ctrl-G replaces the current line with a generated command
+__eidos_generate() {
+    [ -n "$READLINE_LINE" ] || return
+    local cmd
+    cmd="$(eidos --output raw core "$READLINE_LINE" </dev/null)" || return
+    [ -n "$cmd" ] || return
+    READLINE_LINE="$cmd"
+    READLINE_POINT=${#READLINE_LINE}
+}
+bind -x '"\C-g": __eidos_generate'
+"#;
+
+const ZSH_INIT: &str = r#"# eidos shell integration: Ctrl-G replaces the current line with a generated command
+__eidos_generate() {
+    [[ -n "$BUFFER" ]] || return
+    local cmd
+    cmd="$(eidos --output raw core "$BUFFER" </dev/null)"
+    if [[ $? -eq 0 && -n "$cmd" ]]; then
+        BUFFER="$cmd"
+        CURSOR=${#BUFFER}
+    fi
+    zle reset-prompt
+}
+zle -N __eidos_generate
+bindkey '^G' __eidos_generate
+"#;
+
+const FISH_INIT: &str = r#"# eidos shell integration: Ctrl-G replaces the current line with a generated command
+function __eidos_generate
+    set -l line (commandline)
+    test -n "$line"; or return
+    set -l cmd (eidos --output raw core "$line" </dev/null)
+    if test $status -eq 0; and test -n "$cmd"
+        commandline --replace -- "$cmd"
+    end
+    commandline --function repaint
+end
+bind \cg __eidos_generate
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shell() {
+        assert_eq!("Zsh".parse::<Shell>(), Ok(Shell::Zsh));
+        assert!("powershell".parse::<Shell>().is_err());
+    }
+
+    #[test]
+    fn test_scripts_only_edit_the_line() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = shell.init_script();
+            assert!(script.contains("eidos --output raw core"), "{}", shell);
+            assert!(script.contains("__eidos_generate"), "{}", shell);
+            // Never run the result: no eval or accept-line in the widget
+            assert!(!script.contains("eval"), "{}", shell);
+            assert!(!script.contains("accept-line"), "{}", shell);
+            assert!(!script.contains("execute"), "{}", shell);
+        }
+    }
+}
@@ -0,0 +1,379 @@
+// src/stats.rs
+// Usage and history data as tables for `eidos stats export`
+//
+// The command history (see `history`) is the record of what Eidos generated and how
+// long it took. Exporting it as CSV or Parquet lets teams look at adoption and model
+// latency in their own BI tools. `Table` is the serialization layer: the history and
+// the daily usage derived from it are built as typed columns once, and each format
+// writes those columns.
+
+use crate::history::HistoryEntry;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+/// File format of `eidos stats export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+/// Data set of `eidos stats export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dataset {
+    /// One row per generated command
+    History,
+    /// One row per day: commands generated and their latency
+    Usage,
+}
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Values of one column
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    Int(Vec<Option<i64>>),
+    Text(Vec<Option<String>>),
+    /// Seconds since the Unix epoch, UTC
+    Time(Vec<u64>),
+    /// Days since the Unix epoch
+    Date(Vec<u64>),
+}
+
+impl Values {
+    fn len(&self) -> usize {
+        match self {
+            Values::Int(values) => values.len(),
+            Values::Text(values) => values.len(),
+            Values::Time(values) | Values::Date(values) => values.len(),
+        }
+    }
+
+    /// The value of `row` as CSV text; empty for a missing value
+    fn csv(&self, row: usize) -> String {
+        match self {
+            Values::Int(values) => values[row].map(|v| v.to_string()).unwrap_or_default(),
+            Values::Text(values) => values[row].as_deref().map(csv_quote).unwrap_or_default(),
+            Values::Time(values) => {
+                let secs = values[row];
+                let time = secs % SECS_PER_DAY;
+                format!(
+                    "{}T{:02}:{:02}:{:02}Z",
+                    date(secs / SECS_PER_DAY),
+                    time / 3600,
+                    time % 3600 / 60,
+                    time % 60
+                )
+            }
+            Values::Date(values) => date(values[row]),
+        }
+    }
+}
+
+/// A named, typed column
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: &'static str,
+    pub values: Values,
+}
+
+/// Columns of equal length
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub name: &'static str,
+    pub columns: Vec<Column>,
+}
+
+impl Table {
+    /// `dataset` of the history `entries`
+    pub fn of(dataset: Dataset, entries: &[HistoryEntry]) -> Self {
+        match dataset {
+            Dataset::History => Self::history(entries),
+            Dataset::Usage => Self::usage(entries),
+        }
+    }
+
+    /// One row per history entry
+    pub fn history(entries: &[HistoryEntry]) -> Self {
+        let int =
+            |f: fn(&HistoryEntry) -> Option<i64>| Values::Int(entries.iter().map(f).collect());
+        let text =
+            |f: fn(&HistoryEntry) -> Option<String>| Values::Text(entries.iter().map(f).collect());
+        Self {
+            name: "history",
+            columns: vec![
+                column("id", int(|e| Some(e.id as i64))),
+                column(
+                    "timestamp",
+                    Values::Time(entries.iter().map(|e| e.timestamp).collect()),
+                ),
+                column("prompt", text(|e| Some(e.prompt.clone()))),
+                column("command", text(|e| Some(e.command.clone()))),
+                column("undo", text(|e| e.undo.clone())),
+                column("latency_ms", int(|e| e.latency_ms.map(|ms| ms as i64))),
+            ],
+        }
+    }
+
+    /// One row per day with commands: how many, how many could be undone, and the
+    /// mean and 95th percentile latency of those with a recorded latency
+    pub fn usage(entries: &[HistoryEntry]) -> Self {
+        let mut days: BTreeMap<u64, Vec<&HistoryEntry>> = BTreeMap::new();
+        for entry in entries {
+            days.entry(entry.timestamp / SECS_PER_DAY)
+                .or_default()
+                .push(entry);
+        }
+        let mut commands = Vec::new();
+        let mut undoable = Vec::new();
+        let mut mean = Vec::new();
+        let mut p95 = Vec::new();
+        for day in days.values() {
+            commands.push(Some(day.len() as i64));
+            undoable.push(Some(day.iter().filter(|e| e.undo.is_some()).count() as i64));
+            let mut latencies: Vec<u64> = day.iter().filter_map(|e| e.latency_ms).collect();
+            latencies.sort_unstable();
+            mean.push(
+                (!latencies.is_empty())
+                    .then(|| (latencies.iter().sum::<u64>() / latencies.len() as u64) as i64),
+            );
+            // Nearest rank
+            p95.push(
+                latencies
+                    .get(((latencies.len() * 95 + 99) / 100).saturating_sub(1))
+                    .map(|&ms| ms as i64),
+            );
+        }
+        Self {
+            name: "usage",
+            columns: vec![
+                column("date", Values::Date(days.keys().copied().collect())),
+                column("commands", Values::Int(commands)),
+                column("undoable", Values::Int(undoable)),
+                column("mean_latency_ms", Values::Int(mean)),
+                column("p95_latency_ms", Values::Int(p95)),
+            ],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.columns.first().map_or(0, |column| column.values.len())
+    }
+
+    /// RFC 4180 CSV with a header line
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let names: Vec<&str> = self.columns.iter().map(|c| c.name).collect();
+        csv.push_str(&names.join(","));
+        csv.push_str("\r\n");
+        for row in 0..self.rows() {
+            let fields: Vec<String> = self.columns.iter().map(|c| c.values.csv(row)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    /// The table as a Parquet file: text as UTF-8 strings, times as UTC
+    /// millisecond timestamps, dates as dates
+    #[cfg(feature = "parquet")]
+    pub fn write_parquet<W: std::io::Write + Send>(&self, writer: W) -> Result<(), String> {
+        parquet_file::write(self, writer).map_err(|e| e.to_string())
+    }
+}
+
+fn column(name: &'static str, values: Values) -> Column {
+    Column { name, values }
+}
+
+/// A CSV field, quoted when it holds a separator, quote or line break
+fn csv_quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// `YYYY-MM-DD` of a day counted from the Unix epoch
+fn date(days: u64) -> String {
+    // Civil-from-days (Howard Hinnant), shifted to start the year in March
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_file {
+    use super::{Table, Values};
+    use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+    use parquet::errors::Result;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    fn schema(table: &Table) -> String {
+        let fields: Vec<String> = table
+            .columns
+            .iter()
+            .map(|column| {
+                let field = match column.values {
+                    Values::Int(_) => "OPTIONAL INT64",
+                    Values::Text(_) => "OPTIONAL BINARY",
+                    Values::Time(_) => "REQUIRED INT64",
+                    Values::Date(_) => "REQUIRED INT32",
+                };
+                let logical = match column.values {
+                    Values::Int(_) => "",
+                    Values::Text(_) => " (STRING)",
+                    Values::Time(_) => " (TIMESTAMP(MILLIS,true))",
+                    Values::Date(_) => " (DATE)",
+                };
+                format!("{} {}{};", field, column.name, logical)
+            })
+            .collect();
+        format!("message {} {{ {} }}", table.name, fields.join(" "))
+    }
+
+    /// Definition levels and present values of an optional column
+    fn present<T: Clone>(values: &[Option<T>]) -> (Vec<i16>, Vec<T>) {
+        let levels = values.iter().map(|v| i16::from(v.is_some())).collect();
+        (levels, values.iter().flatten().cloned().collect())
+    }
+
+    pub fn write<W: Write + Send>(table: &Table, writer: W) -> Result<()> {
+        let schema = Arc::new(parse_message_type(&schema(table))?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        let mut file = SerializedFileWriter::new(writer, schema, properties)?;
+        let mut row_group = file.next_row_group()?;
+        for column in &table.columns {
+            let Some(mut writer) = row_group.next_column()? else {
+                break;
+            };
+            match &column.values {
+                Values::Int(values) => {
+                    let (levels, values) = present(values);
+                    writer
+                        .typed::<Int64Type>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                Values::Text(values) => {
+                    let (levels, values) = present(values);
+                    let values: Vec<ByteArray> = values
+                        .into_iter()
+                        .map(|v| ByteArray::from(v.as_str()))
+                        .collect();
+                    writer
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                Values::Time(values) => {
+                    let millis: Vec<i64> = values.iter().map(|&s| s as i64 * 1000).collect();
+                    writer
+                        .typed::<Int64Type>()
+                        .write_batch(&millis, None, None)?;
+                }
+                Values::Date(values) => {
+                    let days: Vec<i32> = values.iter().map(|&d| d as i32).collect();
+                    writer.typed::<Int32Type>().write_batch(&days, None, None)?;
+                }
+            }
+            writer.close()?;
+        }
+        row_group.close()?;
+        file.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, timestamp: u64, undo: Option<&str>, latency_ms: Option<u64>) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            timestamp,
+            prompt: "list files, \"big\" first".to_string(),
+            command: "ls -S".to_string(),
+            undo: undo.map(str::to_string),
+            latency_ms,
+        }
+    }
+
+    #[test]
+    fn test_history_csv() {
+        // 2024-03-05T12:30:00Z
+        let entries = [
+            entry(1, 1_709_641_800, Some("true"), Some(850)),
+            entry(2, 0, None, None),
+        ];
+        assert_eq!(
+            Table::history(&entries).to_csv(),
+            "id,timestamp,prompt,command,undo,latency_ms\r\n\
+             1,2024-03-05T12:30:00Z,\"list files, \"\"big\"\" first\",ls -S,true,850\r\n\
+             2,1970-01-01T00:00:00Z,\"list files, \"\"big\"\" first\",ls -S,,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_usage_aggregates_by_day() {
+        let day = 19_787 * SECS_PER_DAY;
+        let entries = [
+            entry(1, day + 10, Some("x"), Some(100)),
+            entry(2, day + 20, None, Some(300)),
+            entry(3, day + 30, None, None),
+            entry(4, day + SECS_PER_DAY, None, None),
+        ];
+        let table = Table::usage(&entries);
+        assert_eq!(table.rows(), 2);
+        assert_eq!(
+            table.to_csv(),
+            "date,commands,undoable,mean_latency_ms,p95_latency_ms\r\n\
+             2024-03-05,3,1,200,300\r\n\
+             2024-03-06,1,0,,\r\n"
+        );
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(11_016), "2000-02-29");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_round_trip() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let entries = [
+            entry(1, 1_709_641_800, None, Some(850)),
+            entry(2, 60, None, None),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.parquet");
+        let file = std::fs::File::create(&path).unwrap();
+        Table::history(&entries).write_parquet(file).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 2);
+        let names: Vec<&str> = metadata
+            .file_metadata()
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|c| c.name())
+            .collect();
+        assert_eq!(
+            names,
+            ["id", "timestamp", "prompt", "command", "undo", "latency_ms"]
+        );
+    }
+}
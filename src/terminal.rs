@@ -0,0 +1,224 @@
+// src/terminal.rs
+// Colours and Markdown for output read on a terminal
+//
+// Whether a stream gets ANSI colours is decided once at startup from `--color`,
+// `NO_COLOR`, `[ui] color` and whether the stream is a terminal; stdout and stderr
+// are decided separately, so `eidos core ... | sh` still gets a red error on the
+// terminal. `output` then paints results with `Style` and renders chat replies
+// with `render_markdown`. Text for pipes, files, JSON and `--output raw` is never
+// touched: every function here returns its input unchanged when colour is off.
+
+use std::str::FromStr;
+
+/// When to colour output (`--color`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// On terminals, unless `NO_COLOR` is set or `[ui] color = false`
+    #[default]
+    Auto,
+    /// Always, even when piped
+    Always,
+    /// Never
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether a stream gets colours, given `[ui] color`, whether `NO_COLOR`
+    /// is set and whether the stream is a terminal
+    pub fn enabled(self, configured: bool, no_color: bool, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => configured && !no_color && terminal,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(format!(
+                "Unknown color choice '{}' (expected auto, always or never)",
+                other
+            )),
+        }
+    }
+}
+
+/// How a part of the output is painted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// The generated command
+    Command,
+    /// Tokens an alternative adds to the first command
+    Added,
+    /// Tokens an alternative drops from the first command
+    Removed,
+    /// Passed a check
+    Safe,
+    /// Needs a look before use
+    Warning,
+    /// Rejected or failed
+    Danger,
+    /// Markdown `**bold**` and headings
+    Bold,
+    /// Markdown `code` and fenced blocks
+    Code,
+    /// Code fence lines
+    Dim,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Command | Style::Bold => "1",
+            Style::Added => "1;32",
+            Style::Safe => "32",
+            Style::Removed | Style::Danger => "31",
+            Style::Warning => "33",
+            Style::Code => "36",
+            Style::Dim => "2",
+        }
+    }
+
+    /// `text` wrapped in this style, or unchanged when `color` is off
+    pub fn apply(self, text: &str, color: bool) -> String {
+        if !color || text.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", self.code(), text)
+    }
+}
+
+/// `text` with Markdown bold, inline code, headings and code fences painted;
+/// everything else, including the markers around code, is kept as written
+pub fn render_markdown(text: &str) -> String {
+    let mut stream = MarkdownStream::default();
+    let mut rendered = stream.push(text);
+    rendered.push_str(&stream.finish());
+    rendered
+}
+
+/// `render_markdown` for a reply that arrives in pieces: complete lines are
+/// rendered as soon as their newline arrives
+#[derive(Debug, Default)]
+pub struct MarkdownStream {
+    line: String,
+    in_fence: bool,
+}
+
+impl MarkdownStream {
+    /// The lines completed by `token`, rendered
+    pub fn push(&mut self, token: &str) -> String {
+        self.line.push_str(token);
+        let mut rendered = String::new();
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            rendered.push_str(&self.render_line(&line[..end]));
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    /// The last, unterminated line, rendered
+    pub fn finish(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        self.render_line(&line)
+    }
+
+    fn render_line(&mut self, line: &str) -> String {
+        if line.trim_start().starts_with("```") {
+            self.in_fence = !self.in_fence;
+            return Style::Dim.apply(line, true);
+        }
+        if self.in_fence {
+            return Style::Code.apply(line, true);
+        }
+        let heading = line.trim_start();
+        if heading.starts_with('#') && heading.trim_start_matches('#').starts_with(' ') {
+            return Style::Bold.apply(line, true);
+        }
+        render_inline(line)
+    }
+}
+
+/// `**bold**` and `` `code` `` spans in one line; an unclosed marker is left as is
+fn render_inline(line: &str) -> String {
+    let mut rendered = String::new();
+    let mut rest = line;
+    loop {
+        let bold = rest.find("**");
+        let code = rest.find('`');
+        let (start, marker, style) = match (bold, code) {
+            (Some(b), Some(c)) if c < b => (c, "`", Style::Code),
+            (Some(b), _) => (b, "**", Style::Bold),
+            (None, Some(c)) => (c, "`", Style::Code),
+            (None, None) => break,
+        };
+        let inner = &rest[start + marker.len()..];
+        let Some(end) = inner.find(marker).filter(|&end| end > 0) else {
+            rendered.push_str(&rest[..start + marker.len()]);
+            rest = inner;
+            continue;
+        };
+        rendered.push_str(&rest[..start]);
+        let span = match style {
+            Style::Bold => &inner[..end],
+            _ => &rest[start..start + 2 * marker.len() + end],
+        };
+        rendered.push_str(&style.apply(span, true));
+        rest = &inner[end + marker.len()..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_choice() {
+        assert_eq!("always".parse(), Ok(ColorChoice::Always));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+
+        assert!(ColorChoice::Auto.enabled(true, false, true));
+        assert!(!ColorChoice::Auto.enabled(true, false, false));
+        assert!(!ColorChoice::Auto.enabled(true, true, true));
+        assert!(!ColorChoice::Auto.enabled(false, false, true));
+        assert!(ColorChoice::Always.enabled(false, true, false));
+        assert!(!ColorChoice::Never.enabled(true, false, true));
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        assert_eq!(
+            render_markdown("Use **-h** or `ls -a`, not ** or `"),
+            "Use \x1b[1m-h\x1b[0m or \x1b[36m`ls -a`\x1b[0m, not ** or `"
+        );
+        assert_eq!(
+            render_markdown("## Files\n```bash\nls **/*.rs\n```\ndone"),
+            "\x1b[1m## Files\x1b[0m\n\x1b[2m```bash\x1b[0m\n\x1b[36mls **/*.rs\x1b[0m\n\
+             \x1b[2m```\x1b[0m\ndone"
+        );
+        assert_eq!(Style::Danger.apply("rm -rf /", false), "rm -rf /");
+    }
+
+    #[test]
+    fn test_markdown_stream_renders_whole_lines() {
+        let mut stream = MarkdownStream::default();
+        assert_eq!(stream.push("Run **"), "");
+        assert_eq!(stream.push("ls** now\n```"), "Run \x1b[1mls\x1b[0m now\n");
+        assert_eq!(
+            stream.push("\nls -la\n"),
+            "\x1b[2m```\x1b[0m\n\x1b[36mls -la\x1b[0m\n"
+        );
+        assert_eq!(stream.push("```"), "");
+        assert_eq!(stream.finish(), "\x1b[2m```\x1b[0m");
+    }
+}
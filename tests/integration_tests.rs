@@ -40,6 +40,222 @@ fn test_chat_command() {
     );
 }
 
+/// Answer one Ollama `/api/chat` request with `reply`; the request body is sent on
+/// the returned channel
+fn serve_ollama_once(reply: &'static str) -> (String, std::sync::mpsc::Receiver<String>) {
+    serve_json_once(format!(
+        r#"{{"message":{{"role":"assistant","content":"{}"}}}}"#,
+        reply
+    ))
+}
+
+/// Answer one HTTP request with `json`; the request body is sent on the returned
+/// channel
+fn serve_json_once(json: String) -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stream = listener.incoming().next().unwrap().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut length = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+            line.clear();
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        sender.send(String::from_utf8(body).unwrap()).unwrap();
+
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            json.len(),
+            json
+        );
+    });
+    (url, receiver)
+}
+
+#[test]
+#[cfg(feature = "chat")]
+fn test_chat_flags_override_configured_model_and_sampling() {
+    let work = tempfile::tempdir().unwrap();
+    let (url, request) = serve_ollama_once("Use ls -la");
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        format!(
+            "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n\
+             [chat]\nprovider = \"ollama\"\nurl = \"{}\"\nmodel = \"llama3\"\n",
+            url
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .env("XDG_CACHE_HOME", work.path().join("cache"))
+        .current_dir(work.path())
+        .args(["--output", "json", "chat", "How do I list hidden files?"])
+        .args([
+            "--model",
+            "qwen2.5",
+            "--temperature",
+            "0.2",
+            "--max-tokens",
+            "50",
+        ]);
+    let output = cmd.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let reply: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(reply["reply"], "Use ls -la");
+
+    let body: serde_json::Value = serde_json::from_str(&request.recv().unwrap()).unwrap();
+    assert_eq!(body["model"], "qwen2.5");
+    assert_eq!(body["options"]["num_predict"], 50);
+    assert!((body["options"]["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["chat", "hi", "--temperature", "3"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("must be between 0 and 2"));
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["chat", "hi", "--provider", "mistral"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "possible values: openai, anthropic",
+    ));
+}
+
+#[test]
+#[cfg(feature = "vision")]
+fn test_chat_image_is_sent_inline() {
+    let work = tempfile::tempdir().unwrap();
+    let (url, request) = serve_ollama_once("A permission error");
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        format!(
+            "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n\
+             [chat]\nprovider = \"ollama\"\nurl = \"{}\"\n",
+            url
+        ),
+    )
+    .unwrap();
+    std::fs::write(work.path().join("shot.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+    std::fs::write(work.path().join("notes.txt"), "not an image").unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .env("XDG_CACHE_HOME", work.path().join("cache"))
+        .current_dir(work.path())
+        .args(["--output", "json", "chat", "What does this error mean?"])
+        .args(["--model", "llava", "--image", "shot.png"]);
+    let output = cmd.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let body: serde_json::Value = serde_json::from_str(&request.recv().unwrap()).unwrap();
+    let messages = body["messages"].as_array().unwrap();
+    let question = messages.last().unwrap();
+    assert_eq!(question["content"], "What does this error mean?");
+    assert_eq!(question["images"][0], "iVBORw0KGgo=");
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .current_dir(work.path())
+        .args(["chat", "What is this?", "--image", "notes.txt"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "notes.txt is not a PNG, JPEG, GIF or WebP image",
+    ));
+}
+
+#[test]
+fn test_chat_replays_recorded_cassette_without_network() {
+    let work = tempfile::tempdir().unwrap();
+    let (url, request) = serve_ollama_once("Use ls -la");
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        format!(
+            "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n\
+             [chat]\nprovider = \"ollama\"\nurl = \"{}\"\n",
+            url
+        ),
+    )
+    .unwrap();
+    let chat = |var: &str| {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env_remove("EIDOS_MODEL_PATH")
+            .env("XDG_DATA_HOME", work.path().join("data"))
+            .env("XDG_CACHE_HOME", work.path().join("cache"))
+            .env(var, "cassette.json")
+            .current_dir(work.path())
+            .args(["--output", "json", "chat", "How do I list hidden files?"]);
+        cmd.output().unwrap()
+    };
+
+    let recorded = chat("EIDOS_RECORD");
+    assert!(
+        recorded.status.success(),
+        "{}",
+        String::from_utf8_lossy(&recorded.stderr)
+    );
+    request.recv().unwrap();
+    assert!(work.path().join("cassette.json").exists());
+
+    // The server answered once and is gone; the reply now comes from the cassette
+    let replayed = chat("EIDOS_REPLAY");
+    assert!(
+        replayed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&replayed.stderr)
+    );
+    assert_eq!(replayed.stdout, recorded.stdout);
+    assert!(String::from_utf8_lossy(&replayed.stdout).contains("Use ls -la"));
+}
+
+#[test]
+fn test_routed_errors_keep_their_code() {
+    let work = tempfile::tempdir().unwrap();
+    // Nothing listens on the discard port, so the translation service is unreachable
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n\
+         [translate]\nurl = \"http://127.0.0.1:9\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_CACHE_HOME", work.path().join("cache"))
+        .current_dir(work.path())
+        .args([
+            "--output",
+            "json",
+            "translate",
+            "--from",
+            "fr",
+            "Bonjour le monde",
+        ]);
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(75));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["code"], "network");
+    assert_eq!(report["retryable"], true);
+}
+
 #[test]
 fn test_translate_command() {
     let mut cmd = Command::cargo_bin("eidos").unwrap();
@@ -81,6 +297,369 @@ fn test_core_command_without_config() {
     );
 }
 
+#[test]
+#[cfg(feature = "translate")]
+fn test_ask_reports_configuration_once() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .env_remove("LIBRETRANSLATE_URL")
+        .args(["ask", "list all files in this directory"]);
+
+    // The speculative generation fails quietly; only the real one reports
+    let output = cmd.output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("Configuration Error").count(),
+        1,
+        "Expected one config error, got: {}",
+        stderr
+    );
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_config_error_exit_code_and_json_report() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .args(["--output", "json", "core", "list files"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(report["code"], "config");
+    assert!(report["error"]
+        .as_str()
+        .unwrap()
+        .contains("Model file not found"));
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_handled_error_is_printed_once() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .args(["core", "list files"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.lines().filter(|line| line.starts_with("❌")).count(),
+        1,
+        "Expected the error once, got: {}",
+        stderr
+    );
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_malformed_config_is_reported_once() {
+    let work = tempfile::tempdir().unwrap();
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        "model_path = \"model.onnx\"\n[ci]\nblocked_exit_code = \"two\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .current_dir(work.path())
+        .args(["core", "list files"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let errors: Vec<_> = stderr
+        .lines()
+        .filter(|line| line.starts_with("❌"))
+        .collect();
+    assert_eq!(errors.len(), 1, "Expected the error once, got: {}", stderr);
+    assert!(errors[0].contains("Failed to parse config file 'eidos.toml'"));
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_ci_mode_reports_errors_on_single_lines() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .args(["--ci", "core", "list files"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.is_empty());
+    assert!(stderr.lines().all(|line| line.starts_with("error: ")));
+    assert!(!stderr.contains("❌"));
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_color_flag_overrides_terminal_detection() {
+    let stderr = |color: &str| {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+            .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+            .env_remove("NO_COLOR")
+            .args(["--color", color, "core", "list files"]);
+        let output = cmd.output().unwrap();
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    // Piped, so `auto` stays plain
+    assert!(stderr("always").contains("\x1b[31m"));
+    assert!(!stderr("auto").contains('\x1b'));
+    assert!(!stderr("never").contains('\x1b'));
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_quiet_raw_keeps_stdout_clean() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .args(["core", "list files", "--raw", "--quiet"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Model file not found"), "{}", stderr);
+    assert!(!stderr.contains("❌"));
+    assert!(!stderr.contains("Tip"));
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["--raw", "--output", "json", "core", "list files"]);
+    cmd.assert().failure().code(2);
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_core_context_history_bounds() {
+    for (args, code) in [
+        (vec!["--context-history", "0"], 2),
+        (vec!["--context-history", "21"], 2),
+        (vec!["--context-history", "3"], 78),
+    ] {
+        let data = tempfile::tempdir().unwrap();
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("XDG_DATA_HOME", data.path())
+            .env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+            .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+            .args(["core", "the same but sorted by size"])
+            .args(&args);
+        let output = cmd.output().unwrap();
+        assert_eq!(output.status.code(), Some(code), "{:?}", args);
+    }
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_core_seed_flag() {
+    for (args, code) in [
+        (vec!["--seed", "-1"], 2),
+        (vec!["--seed", "lucky"], 2),
+        (vec!["--seed", "42"], 78),
+    ] {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.gguf")
+            .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+            .args(["core", "list files", "--no-ask"])
+            .args(&args);
+        let output = cmd.output().unwrap();
+        assert_eq!(output.status.code(), Some(code), "{:?}", args);
+    }
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_core_template_requires_every_placeholder() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args([
+        "core",
+        "find files larger than {size} in {dir}",
+        "--var",
+        "size=100M",
+    ]);
+
+    // Rejected before any model is loaded
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No value for {dir}"), "stderr: {}", stderr);
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["core", "list files", "--var", "not a var"]);
+    cmd.assert().failure();
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_core_preview_is_for_a_single_command() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["core", "list files", "--preview", "-n", "3"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--preview"), "stderr: {}", stderr);
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_audit_script_reports_risky_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("deploy.sh");
+    std::fs::write(
+        &script,
+        "#!/bin/sh\n# ship it\nmake dist\nsudo cp dist/app /usr/local/bin\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("audit-script").arg("--sarif").arg(&script);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let sarif: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+        4
+    );
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_json_log_format() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .env_remove("RUST_LOG")
+        .args(["-v", "--log-format", "json", "core", "list files"]);
+
+    let output = cmd.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let logs: Vec<serde_json::Value> = stderr
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(logs.iter().any(|log| log["level"] == "INFO"));
+    assert!(logs.iter().any(|log| log["level"] == "ERROR"));
+}
+
+#[test]
+fn test_explain_lists_options_without_model() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .env_remove("OPENAI_API_KEY")
+        .env_remove("ANTHROPIC_API_KEY")
+        .env_remove("GEMINI_API_KEY")
+        .env_remove("OLLAMA_HOST")
+        .env_remove("LLM_API_URL")
+        .args(["--output", "json", "explain", "tar -xzvf file.tar.gz"]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["parts"][0]["program"], "tar");
+    assert_eq!(json["parts"][0]["flags"][3]["flag"], "-f");
+    assert_eq!(json["parts"][0]["flags"][3]["value"], "file.tar.gz");
+    assert!(json.get("explanation").is_none());
+}
+
+#[test]
+#[cfg(all(feature = "chat", feature = "translate"))]
+fn test_explain_in_display_language() {
+    let work = tempfile::tempdir().unwrap();
+    let (chat_url, _chat) = serve_ollama_once("Lists all files, including hidden ones.");
+    let (translate_url, request) = serve_json_once(
+        r#"{"translatedText":"Gizli olanlar dahil tüm dosyaları listeler."}"#.to_string(),
+    );
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        format!(
+            "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n\
+             [chat]\nprovider = \"ollama\"\nurl = \"{}\"\nmodel = \"llama3\"\n\n\
+             [translate]\nurl = \"{}\"\n",
+            chat_url, translate_url
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .env_remove("LIBRETRANSLATE_URL")
+        .env("XDG_CACHE_HOME", work.path().join("cache"))
+        .env("XDG_DATA_HOME", work.path().join("data"))
+        .current_dir(work.path())
+        .args(["--lang", "tr", "--output", "json", "explain", "ls -la"]);
+    let output = cmd.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json["explanation"],
+        "Gizli olanlar dahil tüm dosyaları listeler."
+    );
+    assert_eq!(json["parts"][0]["program"], "ls");
+
+    let body: serde_json::Value = serde_json::from_str(&request.recv().unwrap()).unwrap();
+    assert_eq!(body["q"], "Lists all files, including hidden ones.");
+    assert_eq!(
+        (&body["source"], &body["target"]),
+        (&"en".into(), &"tr".into())
+    );
+}
+
+#[test]
+fn test_run_safe_only_runs_single_commands() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("core")
+        .arg("--run-safe")
+        .arg("-n")
+        .arg("3")
+        .arg("disk usage");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+#[cfg(feature = "chat")]
+fn test_summarize_output_requires_run_safe() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("core")
+        .arg("--summarize-output")
+        .arg("--")
+        .arg("which process uses the most memory");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "the following required arguments were not provided",
+    ));
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_refine_needs_a_terminal() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["core", "--refine", "list files"]).write_stdin("");
+
+    cmd.assert().code(65).stderr(predicate::str::contains(
+        "--refine reads follow-ups from a terminal",
+    ));
+}
+
 #[test]
 fn test_missing_subcommand() {
     let mut cmd = Command::cargo_bin("eidos").unwrap();
@@ -108,6 +687,61 @@ fn test_chat_command_empty_text() {
     assert!(output.status.success() || !output.stderr.is_empty());
 }
 
+#[test]
+#[cfg(feature = "translate")]
+fn test_translate_localize() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("LIBRETRANSLATE_URL").args([
+        "translate",
+        "--localize",
+        "Auf der Festplatte sind seit dem 05.03.2024 nur noch 1.234,5 MB frei",
+    ]);
+
+    // The offline phrasebook knows too few of the words to translate them; numbers
+    // and dates are localized all the same
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        stdout.contains("seit dem 03/05/2024 nur noch 1,234.5 MB"),
+        "Expected localized number and date, got: {}",
+        stdout
+    );
+}
+
+#[test]
+#[cfg(feature = "translate")]
+fn test_translate_from_skips_detection() {
+    // Too short for detection to settle on a language
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("LIBRETRANSLATE_URL")
+        .args(["translate", "Guten Morgen"]);
+    let output = cmd.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(65), "stderr: {}", stderr);
+    assert!(stderr.contains("--from LANG"), "stderr: {}", stderr);
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("LIBRETRANSLATE_URL")
+        .args(["translate", "--from", "de", "Guten Morgen"]);
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        stdout.contains("Source language: German"),
+        "stdout: {}",
+        stdout
+    );
+}
+
 #[test]
 fn test_translate_command_english_text() {
     let mut cmd = Command::cargo_bin("eidos").unwrap();
@@ -119,8 +753,769 @@ fn test_translate_command_english_text() {
 
     // Should detect English and report it (even if translation API is unavailable)
     assert!(
-        stdout.contains("Detected language: en") || stdout.contains("Text is already in en"),
+        stdout.contains("Detected language: English (en)")
+            || stdout.contains("Text is already in English"),
         "Expected English detection, got: {}",
         stdout
     );
 }
+
+#[test]
+fn test_default_command_routes_bare_prompt() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.onnx")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .env("EIDOS_DEFAULT_COMMAND", "core")
+        .arg("list biggest files");
+
+    // The bare prompt should reach the core handler, which then fails on the missing model
+    let output = cmd.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Configuration Error"),
+        "Expected core handler configuration error, got: {}",
+        stderr
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_plugin_handles_custom_verb() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let config = tempfile::tempdir().unwrap();
+    let plugins = config.path().join("eidos/plugins");
+    std::fs::create_dir_all(&plugins).unwrap();
+    let plugin = plugins.join("shout.sh");
+    std::fs::write(
+        &plugin,
+        "#!/bin/sh\nsed 's/.*\"input\":\"\\([^\"]*\\)\".*/\\1!/' | tr a-z A-Z\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_CONFIG_HOME", config.path())
+        .env_remove("EIDOS_DEFAULT_COMMAND")
+        .args(["shout", "hello", "plugins"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("HELLO PLUGINS!"));
+}
+
+#[test]
+fn test_core_batch_arguments() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["core", "--batch", "/nonexistent/prompts.txt"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Failed to read /nonexistent/prompts.txt",
+    ));
+
+    // --jobs only makes sense for a batch, and a batch takes no prompt
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["core", "--jobs", "2", "list files"]);
+    cmd.assert().failure().code(2);
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["core", "--batch", "-", "list files"]);
+    cmd.assert().failure().code(2);
+}
+
+#[test]
+fn test_eval_rejects_invalid_dataset() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("pairs.jsonl");
+    std::fs::write(
+        &dataset,
+        "{\"prompt\": \"list files\", \"command\": \"ls\"}\n{\"prompt\": \"no command\"}\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("eval").arg("--dataset").arg(&dataset);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid dataset"))
+        .stderr(predicate::str::contains("line 2"));
+}
+
+#[test]
+fn test_bench_without_model_or_providers() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.args(["--output", "json", "bench", "--no-model", "--no-providers"]);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let measurements = result["measurements"].as_array().unwrap();
+    assert_eq!(measurements.len(), 1);
+    assert_eq!(measurements[0]["name"], "validation");
+    assert!(measurements[0]["value"].as_f64().unwrap() > 0.0);
+}
+
+#[test]
+fn test_warnings_never_reach_stdout() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("LIBRETRANSLATE_URL")
+        .arg("translate")
+        .arg("Bonjour le monde, comment allez-vous aujourd'hui?");
+
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    // The offline-translation notice is human chatter and belongs on stderr
+    assert!(
+        !stdout.contains("translating offline"),
+        "stdout: {}",
+        stdout
+    );
+    assert!(stderr.contains("translating offline"), "stderr: {}", stderr);
+}
+
+#[test]
+#[cfg(feature = "translate")]
+fn test_english_input_needs_no_translation_service() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("LIBRETRANSLATE_URL")
+        .arg("translate")
+        .arg("Show me all of the hidden files in the current directory");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !stderr.contains("translating offline"),
+        "stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_index_builds_with_local_embedder() {
+    let notes = tempfile::tempdir().unwrap();
+    let data = tempfile::tempdir().unwrap();
+    std::fs::write(
+        notes.path().join("deploy.md"),
+        "Deploys run with `make release` from the repo root.",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("OPENAI_API_KEY")
+        .env_remove("ANTHROPIC_API_KEY")
+        .env_remove("GEMINI_API_KEY")
+        .env_remove("OLLAMA_HOST")
+        .env_remove("LLM_API_URL")
+        .env("XDG_DATA_HOME", data.path())
+        .arg("index")
+        .arg(notes.path())
+        .arg("--name")
+        .arg("notes");
+
+    // Without a provider the local embedder is used, so indexing works offline
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("notes.json"));
+    assert!(data.path().join("eidos/indexes/notes.json").exists());
+}
+
+#[test]
+#[cfg(feature = "chat")]
+fn test_chat_sends_a_subcommand_name_after_double_dash() {
+    let work = tempfile::tempdir().unwrap();
+    let (url, request) = serve_ollama_once("Export what?");
+    std::fs::write(
+        work.path().join("eidos.toml"),
+        format!(
+            "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n\
+             [chat]\nprovider = \"ollama\"\nurl = \"{}\"\nmodel = \"llama3\"\n",
+            url
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("EIDOS_MODEL_PATH")
+        .env("XDG_CACHE_HOME", work.path().join("cache"))
+        .current_dir(work.path())
+        .args(["chat", "--", "export"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Export what?"));
+
+    let body: serde_json::Value = serde_json::from_str(&request.recv().unwrap()).unwrap();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.last().unwrap()["content"], "export");
+}
+
+#[test]
+#[cfg(feature = "chat")]
+fn test_sessions_import_chatgpt_export() {
+    let data = tempfile::tempdir().unwrap();
+    let export = data.path().join("conversations.json");
+    std::fs::write(
+        &export,
+        r#"[{"id": "c1", "title": "Nginx 502s", "create_time": 1700000000, "current_node": "a",
+            "mapping": {
+              "q": {"message": {"author": {"role": "user"}, "content": {"content_type": "text", "parts": ["why 502"]}}, "parent": null},
+              "a": {"message": {"author": {"role": "assistant"}, "content": {"content_type": "text", "parts": ["check upstream"]}}, "parent": "q"}
+            }}]"#,
+    )
+    .unwrap();
+
+    for expected in [
+        "Imported 1 of 1",
+        "Imported 0 of 1 conversations (1 already",
+    ] {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("XDG_DATA_HOME", data.path())
+            .args(["sessions", "import"])
+            .arg(&export);
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains(expected));
+    }
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path()).arg("sessions");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "nginx-502s  Nginx 502s (2 messages)",
+    ));
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .args(["sessions", "export", "nginx-502s"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("# Nginx 502s\n"))
+        .stdout(predicate::str::contains(
+            "- Created: 2023-11-14 22:13 UTC\n- Imported from: chatgpt:c1",
+        ))
+        .stdout(predicate::str::contains("## Assistant\n\ncheck upstream"));
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path()).args([
+        "sessions",
+        "export",
+        "nginx-502s",
+        "--format",
+        "json",
+    ]);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["messages"][0]["content"], "why 502");
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path()).args([
+        "chat",
+        "export",
+        "--session",
+        "nginx-502s",
+        "--format",
+        "html",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("<h1>Nginx 502s</h1>"));
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .args(["sessions", "export", "missing"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("eidos sessions"));
+}
+
+#[test]
+#[cfg(all(feature = "chat", feature = "translate"))]
+fn test_sessions_translate_keeps_a_copy() {
+    let data = tempfile::tempdir().unwrap();
+    let export = data.path().join("conversations.json");
+    std::fs::write(
+        &export,
+        r#"[{"id": "c1", "title": "Archivos", "create_time": 1700000000, "current_node": "a",
+            "mapping": {
+              "q": {"message": {"author": {"role": "user"}, "content": {"content_type": "text", "parts": ["Muestra los archivos más grandes de mi carpeta"]}}, "parent": null},
+              "a": {"message": {"author": {"role": "assistant"}, "content": {"content_type": "text", "parts": ["Check the downloads folder in your home directory first."]}}, "parent": "q"}
+            }}]"#,
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .args(["sessions", "import"])
+        .arg(&export);
+    cmd.assert().success();
+
+    // Offline: the phrasebook translates the Spanish question, the reply is English
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .env_remove("LIBRETRANSLATE_URL")
+        .args(["sessions", "translate", "archivos", "--to", "en"]);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "archivos-en: 1 of 2 messages translated into English from archivos",
+    ));
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path()).arg("sessions");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "archivos     Archivos (2 messages)",
+        ))
+        .stdout(predicate::str::contains(
+            "archivos-en  Archivos (English) (2 messages)",
+        ));
+}
+
+#[test]
+#[cfg(feature = "core")]
+fn test_maintenance_compact() {
+    let data = tempfile::tempdir().unwrap();
+    let dir = data.path().join("eidos");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("history.jsonl"),
+        "{\"id\":1,\"timestamp\":0,\"prompt\":\"p\",\"command\":\"ls\"}\n\
+         {\"id\":2,\"timestamp\":0,\"prompt\":\"p\",\"command\":\"pwd\"}\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .args(["maintenance", "compact", "--max-age", "30"]);
+    cmd.assert().success().stdout(
+        predicate::str::contains("history.jsonl")
+            .and(predicate::str::contains("1 old entries dropped")),
+    );
+    assert!(dir.join("history.jsonl.zst").exists());
+
+    // The archive is read back transparently
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path()).arg("history");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("pwd").and(predicate::str::contains("ls").not()));
+}
+
+#[test]
+fn test_metrics_are_local_and_opt_in() {
+    let data = tempfile::tempdir().unwrap();
+    let metrics = data.path().join("eidos/metrics.json");
+
+    // Off by default: nothing is recorded
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .current_dir(data.path())
+        .args(["stats", "metrics"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Metrics are off"));
+    assert!(!metrics.exists());
+
+    std::fs::write(
+        data.path().join("eidos.toml"),
+        "model_path = \"model.onnx\"\ntokenizer_path = \"tokenizer.json\"\n\n[metrics]\nenabled = true\n",
+    )
+    .unwrap();
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("XDG_DATA_HOME", data.path())
+            .current_dir(data.path())
+            .args(["stats", "metrics"]);
+        cmd.assert().success();
+    }
+    assert!(metrics.exists());
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .current_dir(data.path())
+        .args(["--output", "raw", "stats", "metrics"]);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "eidos_commands_total{command=\"stats\",outcome=\"ok\"} 2\n",
+    ));
+}
+
+#[test]
+fn test_stats_export_csv() {
+    let data = tempfile::tempdir().unwrap();
+    let dir = data.path().join("eidos");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("history.jsonl"),
+        "{\"id\":1,\"timestamp\":86400,\"prompt\":\"list files, largest first\",\"command\":\"ls -S\",\"latency_ms\":420}\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .args(["stats", "export", "--format", "csv"]);
+    cmd.assert().success().stdout(
+        "id,timestamp,prompt,command,undo,latency_ms\r\n\
+         1,1970-01-02T00:00:00Z,\"list files, largest first\",ls -S,,420\r\n",
+    );
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .args(["stats", "export", "--format", "parquet"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("--out FILE"));
+}
+
+#[test]
+fn test_cache_stats_and_clear() {
+    let cache = tempfile::tempdir().unwrap();
+    let dir = cache.path().join("eidos/commands");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("0123abcd.entry"), "86400 3\ndf -h").unwrap();
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_CACHE_HOME", cache.path())
+        .args(["--output", "json", "cache", "stats"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("\"entries\":1"))
+        .stdout(predicate::str::contains("\"hits\":3"));
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_CACHE_HOME", cache.path())
+        .args(["cache", "clear"]);
+    cmd.assert().success().stdout("Removed 1 cached entries\n");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn test_summarize_missing_file() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("summarize")
+        .arg("--file")
+        .arg("/nonexistent/notes.txt");
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Failed to read"));
+}
+
+#[test]
+fn test_translate_json_output() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env_remove("LIBRETRANSLATE_URL")
+        .arg("--output")
+        .arg("json")
+        .arg("translate")
+        .arg("Affiche tous les fichiers cachés dans le dossier actuel");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("stdout is not JSON ({}): {:?}", e, output.stdout));
+    assert_eq!(json["source_lang"], "fr");
+    assert_eq!(json["source_lang_name"], "French / français");
+    assert_eq!(json["was_translated"], true);
+}
+
+#[test]
+fn test_doctor_reports_device_fallback() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("EIDOS_MODEL_PATH", "/nonexistent/model.gguf")
+        .env("EIDOS_TOKENIZER_PATH", "/nonexistent/tokenizer.json")
+        .env("EIDOS_DEVICE", "cuda:0")
+        .arg("--output")
+        .arg("json")
+        .arg("doctor");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["backend"], "gguf");
+    assert_eq!(json["device"], "cuda:0");
+    assert!(json["model_error"].as_str().unwrap().contains("not found"));
+    if !json["accelerators"][0]["available"]
+        .as_bool()
+        .unwrap_or(false)
+    {
+        assert_eq!(json["active_device"], "cpu");
+        assert!(json["device_fallback"].is_string());
+    }
+}
+
+#[test]
+fn test_shell_init_prints_snippet() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("shell-init").arg("zsh");
+
+    // Piped output is just the snippet, ready for `eval`
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("bindkey '^G' __eidos_generate"))
+        .stdout(predicate::str::contains("eidos --output raw core"))
+        .stderr(predicate::str::is_empty());
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("shell-init").arg("powershell");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("expected bash, zsh or fish"));
+}
+
+#[test]
+fn test_completions_register_and_complete_names() {
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("completions").arg("bash");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("COMPLETE=\"bash\""))
+        .stdout(predicate::str::contains(
+            "complete -o nospace -o bashdefault",
+        ));
+
+    // What the registered script runs on <Tab> after `eidos chat --with-index `
+    let data = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(data.path().join("eidos/indexes")).unwrap();
+    std::fs::write(data.path().join("eidos/indexes/notes.json"), "{}").unwrap();
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.env("XDG_DATA_HOME", data.path())
+        .env("COMPLETE", "bash")
+        .env("_CLAP_COMPLETE_INDEX", "3")
+        .args(["--", "eidos", "chat", "--with-index", ""]);
+    cmd.assert().success().stdout("notes");
+
+    let mut cmd = Command::cargo_bin("eidos").unwrap();
+    cmd.arg("completions").arg("tcsh");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "possible values: bash, elvish, fish",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_daemon_serves_socket_requests() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("daemon.sock");
+    let mut daemon = std::process::Command::new(assert_cmd::cargo::cargo_bin("eidos"))
+        .arg("daemon")
+        .arg("--socket")
+        .arg(&socket)
+        .env_remove("LIBRETRANSLATE_URL")
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let started = Instant::now();
+    let stream = loop {
+        match UnixStream::connect(&socket) {
+            Ok(stream) => break stream,
+            Err(_) if started.elapsed() < Duration::from_secs(10) => {
+                std::thread::sleep(Duration::from_millis(50))
+            }
+            Err(e) => {
+                let _ = daemon.kill();
+                panic!("daemon did not start: {}", e);
+            }
+        }
+    };
+
+    let mut writer = stream.try_clone().unwrap();
+    writer
+        .write_all(
+            b"{\"request\":\"translate\",\"input\":\"Bonjour le monde, comment allez-vous?\"}\n",
+        )
+        .unwrap();
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).unwrap();
+    let _ = daemon.kill();
+    let _ = daemon.wait();
+
+    let json: serde_json::Value = serde_json::from_str(&reply).unwrap();
+    assert_eq!(json["ok"]["translation"]["source_lang"], "fr");
+}
+
+/// Answer HTTP requests for `files` (path, body, ETag) on a local port, like the
+/// Hugging Face Hub does for `resolve` URLs; returns the base URL
+#[cfg(feature = "models")]
+fn serve_files(files: Vec<(&'static str, &'static str, &'static str)>) -> String {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let mut parts = request.split_whitespace();
+            let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+            let reply = match files.iter().find(|(file, _, _)| *file == path) {
+                Some((_, body, etag)) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nX-Linked-Etag: \"{}\"\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    etag,
+                    if method == "HEAD" { "" } else { body }
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            let _ = stream.write_all(reply.as_bytes());
+        }
+    });
+    url
+}
+
+#[test]
+#[cfg(feature = "models")]
+fn test_model_pull_list_and_remove() {
+    let home = tempfile::tempdir().unwrap();
+    let work = home.path().join("work");
+    std::fs::create_dir(&work).unwrap();
+    let hub = serve_files(vec![
+        (
+            "/org/coder-GGUF/resolve/main/coder.Q4.gguf",
+            "GGUF weights",
+            "8a46a62632e21d0c832005a28cb86dfd5921227920a84939ba90bf55f9f3b737",
+        ),
+        (
+            "/org/coder-GGUF/resolve/main/tokenizer.json",
+            "{\"model\":{}}",
+            "0123abcd",
+        ),
+        // Served bytes that do not match the published checksum
+        (
+            "/org/broken/resolve/main/broken.gguf",
+            "truncated",
+            "8a46a62632e21d0c832005a28cb86dfd5921227920a84939ba90bf55f9f3b737",
+        ),
+        ("/org/broken/resolve/main/tokenizer.json", "{}", "0123abcd"),
+    ]);
+    let eidos = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("HOME", home.path())
+            .env("XDG_DATA_HOME", home.path().join("data"))
+            .env("HF_ENDPOINT", &hub)
+            .env_remove("EIDOS_MODEL_PATH")
+            .env_remove("EIDOS_TOKENIZER_PATH")
+            .current_dir(&work)
+            .args(args);
+        cmd
+    };
+
+    eidos(&[
+        "--output",
+        "json",
+        "model",
+        "pull",
+        "org/coder-GGUF/coder.Q4.gguf",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("\"name\":\"coder.Q4\""))
+    .stdout(predicate::str::contains("\"verified\":true"));
+    let model_dir = home.path().join("data/eidos/models/coder.Q4");
+    assert_eq!(
+        std::fs::read_to_string(model_dir.join("coder.Q4.gguf")).unwrap(),
+        "GGUF weights"
+    );
+    let config = std::fs::read_to_string(home.path().join(".config/eidos/eidos.toml")).unwrap();
+    assert!(config.contains(&format!(
+        "model_path = \"{}\"",
+        model_dir.join("coder.Q4.gguf").display()
+    )));
+
+    eidos(&["model", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("* coder.Q4  gguf"));
+    eidos(&["model", "info", "coder.Q4"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "sha256 8a46a62632e21d0c832005a28cb86dfd5921227920a84939ba90bf55f9f3b737 (verified)",
+        ));
+
+    eidos(&["model", "pull", "--no-activate", "org/broken/broken.gguf"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Checksum mismatch for broken.gguf",
+        ));
+    assert!(!home.path().join("data/eidos/models/broken").exists());
+
+    // The configured model is kept unless removal is forced
+    eidos(&["model", "remove", "coder.Q4"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is the configured model"));
+    eidos(&["model", "remove", "--force", "coder.Q4"])
+        .assert()
+        .success()
+        .stdout("Removed coder.Q4\n");
+    assert!(!model_dir.exists());
+}
+
+#[test]
+#[cfg(all(feature = "chat", feature = "translate"))]
+fn test_config_migrate_deprecated_variables() {
+    let home = tempfile::tempdir().unwrap();
+    let work = home.path().join("work");
+    std::fs::create_dir(&work).unwrap();
+    let eidos = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("eidos").unwrap();
+        cmd.env("HOME", home.path())
+            .env("XDG_CACHE_HOME", home.path().join("cache"))
+            .env("OLLAMA_HOST", "http://gpu-box:11434")
+            .env("OLLAMA_MODEL", "llama3")
+            .env("LIBRETRANSLATE_URL", "http://translate.lan")
+            .env("HTTP_CONNECT_TIMEOUT_SECS", "soon")
+            .env_remove("OPENAI_API_KEY")
+            .env_remove("ANTHROPIC_API_KEY")
+            .env_remove("GEMINI_API_KEY")
+            .env_remove("LLM_API_URL")
+            .env_remove("EIDOS_MODEL_PATH")
+            .env_remove("EIDOS_TOKENIZER_PATH")
+            .current_dir(&work)
+            .args(args);
+        cmd
+    };
+    let config_file = home.path().join(".config/eidos/eidos.toml");
+
+    eidos(&["config", "migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would write to"))
+        .stdout(predicate::str::contains(
+            "  [chat] url = \"http://gpu-box:11434\"  (from OLLAMA_HOST)",
+        ))
+        .stdout(predicate::str::contains(
+            "Skipped HTTP_CONNECT_TIMEOUT_SECS: 'soon' is not a number of seconds",
+        ))
+        .stderr(predicate::str::contains("OLLAMA_HOST is deprecated"));
+    assert!(!config_file.exists());
+
+    // Each variable is reported once
+    eidos(&["config", "migrate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote to"))
+        .stderr(predicate::str::contains("is deprecated").not());
+    let config = std::fs::read_to_string(&config_file).unwrap();
+    assert!(config.contains(
+        "[chat]\nprovider = \"ollama\"\nurl = \"http://gpu-box:11434\"\nmodel = \"llama3\""
+    ));
+    assert!(config.contains("[translate]\nurl = \"http://translate.lan\""));
+}